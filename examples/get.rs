@@ -21,28 +21,19 @@ async fn main() {
     assert!(res5.is_ok());
     assert!(res6.is_ok());
 
-    let entry1 = store.get("apple").await.unwrap(); // handle error
-    let entry2 = store.get("google").await.unwrap();
-    let entry3 = store.get("nvidia").await.unwrap();
-    let entry4 = store.get("microsoft").await.unwrap();
-    let entry5 = store.get("meta").await.unwrap();
-    let entry6 = store.get("openai").await.unwrap();
-    let entry7 = store.get("***not_found_key**").await.unwrap();
+    let entry1 = store.get_str("apple").await.unwrap(); // handle error
+    let entry2 = store.get_str("google").await.unwrap();
+    let entry3 = store.get_str("nvidia").await.unwrap();
+    let entry4 = store.get_str("microsoft").await.unwrap();
+    let entry5 = store.get_str("meta").await.unwrap();
+    let entry6 = store.get_str("openai").await.unwrap();
+    let entry7 = store.get_str("***not_found_key**").await.unwrap();
 
-    assert_eq!(std::str::from_utf8(&entry1.unwrap().val).unwrap(), "tim cook");
-    assert_eq!(
-        std::str::from_utf8(&entry2.unwrap().val).unwrap(),
-        "sundar pichai"
-    );
-    assert_eq!(std::str::from_utf8(&entry3.unwrap().val).unwrap(), "jensen huang");
-    assert_eq!(
-        std::str::from_utf8(&entry4.unwrap().val).unwrap(),
-        "satya nadella"
-    );
-    assert_eq!(
-        std::str::from_utf8(&entry5.unwrap().val).unwrap(),
-        "mark zuckerberg"
-    );
-    assert_eq!(std::str::from_utf8(&entry6.unwrap().val).unwrap(), "sam altman");
+    assert_eq!(entry1.as_deref(), Some("tim cook"));
+    assert_eq!(entry2.as_deref(), Some("sundar pichai"));
+    assert_eq!(entry3.as_deref(), Some("jensen huang"));
+    assert_eq!(entry4.as_deref(), Some("satya nadella"));
+    assert_eq!(entry5.as_deref(), Some("mark zuckerberg"));
+    assert_eq!(entry6.as_deref(), Some("sam altman"));
     assert!(entry7.is_none())
 }
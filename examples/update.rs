@@ -10,11 +10,10 @@ async fn main() {
     store.put("apple", "tim cook").await.unwrap(); // handle error
 
     // Update entry
-    let success = store.update("apple", "elon musk").await;
+    let success = store.update_with("apple", |_| "elon musk").await;
     assert!(success.is_ok());
 
     // Entry should now be updated
-    let entry = store.get("apple").await.unwrap(); // handle error
-    assert!(entry.is_some());
-    assert_eq!(std::str::from_utf8(&entry.unwrap().val).unwrap(), "elon musk")
+    let entry = store.get_str("apple").await.unwrap(); // handle error
+    assert_eq!(entry.as_deref(), Some("elon musk"))
 }
@@ -0,0 +1,39 @@
+//! # Benchmarks
+//!
+//! Available with the `bench` feature. Generates workloads and measures how
+//! a [`DataStore`](crate::db::DataStore) handles them, the `velarix-bench`
+//! binary runs the YCSB core workloads and prints throughput and latencies.
+//!
+//! ```rust
+//! use velarixdb::bench::{self, BenchConfig, YcsbWorkload};
+//! use velarixdb::db::DataStore;
+//! # use tempfile::tempdir;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let root = tempdir().unwrap();
+//!     let mut store = DataStore::open("bench", root.path().join("bench")).await.unwrap();
+//!     let config = BenchConfig {
+//!         workload: YcsbWorkload::B,
+//!         record_count: 100,
+//!         operation_count: 100,
+//!         ..Default::default()
+//!     };
+//!     bench::load(&mut store, &config).await.unwrap();
+//!     let report = bench::run(&mut store, &config).await.unwrap();
+//!     println!("{}", report);
+//! }
+//! ```
+// only `Workload` is used by the internal tests when the feature is off
+#![cfg_attr(not(feature = "bench"), allow(dead_code, unused_imports))]
+
+mod workload;
+mod ycsb;
+mod zipfian;
+
+pub use workload::{Entry, Workload};
+pub use ycsb::{
+    load, run, ycsb_key, BenchConfig, BenchReport, KeyDistribution, LatencyStats, OperationKind,
+    OperationMix, YcsbWorkload,
+};
+pub use zipfian::{ZipfianGenerator, ZIPFIAN_CONSTANT};
@@ -0,0 +1,448 @@
+use super::zipfian::ZipfianGenerator;
+use crate::{db::DataStore, err::Error, types::Key, util};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// Standard YCSB core workloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YcsbWorkload {
+    /// Update heavy: 50% reads, 50% updates
+    A,
+
+    /// Read mostly: 95% reads, 5% updates
+    B,
+
+    /// Read only: 100% reads
+    C,
+
+    /// Read latest: 95% reads, 5% inserts, recently inserted keys are the most popular
+    D,
+
+    /// Short ranges: 95% scans, 5% inserts
+    E,
+
+    /// Read-modify-write: 50% reads, 50% read-modify-writes
+    F,
+}
+
+/// Kinds of operations issued by a workload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OperationKind {
+    Read,
+    Update,
+    Insert,
+    Scan,
+    ReadModifyWrite,
+}
+
+/// How keys are picked among the loaded records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyDistribution {
+    /// Every record is equally likely
+    Uniform,
+
+    /// A few records, spread over the key space, receive most requests
+    Zipfian,
+
+    /// Most recently inserted records receive most requests
+    Latest,
+}
+
+/// Share of each operation kind in a workload, shares add up to 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OperationMix {
+    pub read: f64,
+    pub update: f64,
+    pub insert: f64,
+    pub scan: f64,
+    pub read_modify_write: f64,
+}
+
+impl OperationMix {
+    /// Returns operation for `u` drawn uniformly from `[0, 1)`
+    fn pick(&self, u: f64) -> OperationKind {
+        let shares = [
+            (self.read, OperationKind::Read),
+            (self.update, OperationKind::Update),
+            (self.insert, OperationKind::Insert),
+            (self.scan, OperationKind::Scan),
+            (self.read_modify_write, OperationKind::ReadModifyWrite),
+        ];
+        let mut acc = 0.0;
+        let mut last = OperationKind::Read;
+        for (share, kind) in shares {
+            if share <= 0.0 {
+                continue;
+            }
+            acc += share;
+            last = kind;
+            if u < acc {
+                return kind;
+            }
+        }
+        last
+    }
+}
+
+impl YcsbWorkload {
+    /// All core workloads, in order
+    pub const ALL: [YcsbWorkload; 6] = [
+        YcsbWorkload::A,
+        YcsbWorkload::B,
+        YcsbWorkload::C,
+        YcsbWorkload::D,
+        YcsbWorkload::E,
+        YcsbWorkload::F,
+    ];
+
+    /// Returns share of each operation kind
+    pub fn mix(self) -> OperationMix {
+        let none = OperationMix {
+            read: 0.0,
+            update: 0.0,
+            insert: 0.0,
+            scan: 0.0,
+            read_modify_write: 0.0,
+        };
+        match self {
+            YcsbWorkload::A => OperationMix {
+                read: 0.5,
+                update: 0.5,
+                ..none
+            },
+            YcsbWorkload::B => OperationMix {
+                read: 0.95,
+                update: 0.05,
+                ..none
+            },
+            YcsbWorkload::C => OperationMix { read: 1.0, ..none },
+            YcsbWorkload::D => OperationMix {
+                read: 0.95,
+                insert: 0.05,
+                ..none
+            },
+            YcsbWorkload::E => OperationMix {
+                scan: 0.95,
+                insert: 0.05,
+                ..none
+            },
+            YcsbWorkload::F => OperationMix {
+                read: 0.5,
+                read_modify_write: 0.5,
+                ..none
+            },
+        }
+    }
+
+    /// Returns how keys are picked
+    pub fn distribution(self) -> KeyDistribution {
+        match self {
+            YcsbWorkload::D => KeyDistribution::Latest,
+            _ => KeyDistribution::Zipfian,
+        }
+    }
+}
+
+impl fmt::Display for YcsbWorkload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for YcsbWorkload {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "A" => Ok(YcsbWorkload::A),
+            "B" => Ok(YcsbWorkload::B),
+            "C" => Ok(YcsbWorkload::C),
+            "D" => Ok(YcsbWorkload::D),
+            "E" => Ok(YcsbWorkload::E),
+            "F" => Ok(YcsbWorkload::F),
+            _ => Err(format!("unknown workload {:?}, expected one of A-F", s)),
+        }
+    }
+}
+
+/// Options for loading and running a workload
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// Workload to run
+    pub workload: YcsbWorkload,
+
+    /// Number of records inserted before the run
+    pub record_count: u64,
+
+    /// Number of operations issued during the run
+    pub operation_count: u64,
+
+    /// Length of each value
+    pub value_len: usize,
+
+    /// Maximum number of records read by a scan
+    pub max_scan_len: usize,
+
+    /// Seed for key and operation choices, runs with the same seed issue the same operations
+    pub seed: u64,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            workload: YcsbWorkload::A,
+            record_count: 10_000,
+            operation_count: 10_000,
+            value_len: 100,
+            max_scan_len: 100,
+            seed: 0,
+        }
+    }
+}
+
+/// Latency summary of one operation kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub count: usize,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    fn from_samples(mut samples: Vec<Duration>) -> Self {
+        samples.sort_unstable();
+        let count = samples.len();
+        let percentile = |p: usize| samples[(count * p / 100).min(count - 1)];
+        Self {
+            count,
+            mean: samples.iter().sum::<Duration>() / count as u32,
+            p50: percentile(50),
+            p95: percentile(95),
+            p99: percentile(99),
+            max: samples[count - 1],
+        }
+    }
+}
+
+/// Result of loading or running a workload
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    /// Workload that was measured
+    pub workload: YcsbWorkload,
+
+    /// Wall time spent issuing operations
+    pub elapsed: Duration,
+
+    /// Number of operations issued
+    pub operations: u64,
+
+    /// Latencies of each operation kind that was issued
+    pub latencies: BTreeMap<OperationKind, LatencyStats>,
+}
+
+impl BenchReport {
+    /// Returns operations per second
+    pub fn throughput(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            return 0.0;
+        }
+        self.operations as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "workload {}: {} ops in {:.3}s, {:.0} ops/s",
+            self.workload,
+            self.operations,
+            self.elapsed.as_secs_f64(),
+            self.throughput()
+        )?;
+        for (kind, stats) in &self.latencies {
+            writeln!(
+                f,
+                "  {:<16} count={:<8} mean={:?} p50={:?} p95={:?} p99={:?} max={:?}",
+                format!("{:?}", kind),
+                stats.count,
+                stats.mean,
+                stats.p50,
+                stats.p95,
+                stats.p99,
+                stats.max
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns key of record number `n`, keys sort in record order
+pub fn ycsb_key(n: u64) -> String {
+    format!("user{:012}", n)
+}
+
+/// Collects latency samples of each operation kind
+#[derive(Default)]
+struct Recorder {
+    samples: BTreeMap<OperationKind, Vec<Duration>>,
+}
+
+impl Recorder {
+    fn record(&mut self, kind: OperationKind, started: Instant) {
+        self.samples.entry(kind).or_default().push(started.elapsed());
+    }
+
+    fn report(self, workload: YcsbWorkload, elapsed: Duration, operations: u64) -> BenchReport {
+        BenchReport {
+            workload,
+            elapsed,
+            operations,
+            latencies: self
+                .samples
+                .into_iter()
+                .map(|(kind, samples)| (kind, LatencyStats::from_samples(samples)))
+                .collect(),
+        }
+    }
+}
+
+/// Inserts `config.record_count` records, the load phase of a YCSB run
+///
+/// # Errors
+///
+/// Returns error if an insert failed
+pub async fn load(store: &mut DataStore<'static, Key>, config: &BenchConfig) -> Result<BenchReport, Error> {
+    let mut recorder = Recorder::default();
+    let start = Instant::now();
+    for n in 0..config.record_count {
+        let val = util::generate_random_id(config.value_len);
+        let started = Instant::now();
+        store.put(ycsb_key(n), val).await?;
+        recorder.record(OperationKind::Insert, started);
+    }
+    Ok(recorder.report(config.workload, start.elapsed(), config.record_count))
+}
+
+/// Issues `config.operation_count` operations of `config.workload` against records
+/// inserted by [`load`]
+///
+/// Scans are issued as point reads of consecutive records.
+///
+/// # Errors
+///
+/// Returns error if an operation failed
+///
+/// # Panics
+///
+/// Panics if `config.record_count` is zero.
+pub async fn run(store: &mut DataStore<'static, Key>, config: &BenchConfig) -> Result<BenchReport, Error> {
+    let mix = config.workload.mix();
+    let distribution = config.workload.distribution();
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut zipfian = ZipfianGenerator::new(config.record_count);
+    let mut records = config.record_count;
+    let mut recorder = Recorder::default();
+
+    let start = Instant::now();
+    for _ in 0..config.operation_count {
+        let kind = mix.pick(rng.gen());
+        let record = match distribution {
+            KeyDistribution::Uniform => rng.gen_range(0..records),
+            KeyDistribution::Zipfian => zipfian.next_scrambled(&mut rng),
+            KeyDistribution::Latest => records - 1 - zipfian.next(&mut rng),
+        };
+        let started = Instant::now();
+        match kind {
+            OperationKind::Read => {
+                store.get(ycsb_key(record)).await?;
+            }
+            OperationKind::Update => {
+                store
+                    .put(ycsb_key(record), util::generate_random_id(config.value_len))
+                    .await?;
+            }
+            OperationKind::Insert => {
+                store
+                    .put(ycsb_key(records), util::generate_random_id(config.value_len))
+                    .await?;
+                records += 1;
+                zipfian.grow(records);
+            }
+            OperationKind::Scan => {
+                let len = rng.gen_range(1..=config.max_scan_len.max(1)) as u64;
+                for n in record..(record + len).min(records) {
+                    store.get(ycsb_key(n)).await?;
+                }
+            }
+            OperationKind::ReadModifyWrite => {
+                store.get(ycsb_key(record)).await?;
+                store
+                    .put(ycsb_key(record), util::generate_random_id(config.value_len))
+                    .await?;
+            }
+        }
+        recorder.record(kind, started);
+    }
+    Ok(recorder.report(config.workload, start.elapsed(), config.operation_count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_mix_shares_add_up() {
+        for workload in YcsbWorkload::ALL {
+            let mix = workload.mix();
+            let total = mix.read + mix.update + mix.insert + mix.scan + mix.read_modify_write;
+            assert!((total - 1.0).abs() < 1e-9, "workload {}", workload);
+        }
+        // kinds without a share are never picked
+        assert_eq!(YcsbWorkload::E.mix().pick(0.0), OperationKind::Insert);
+        assert_eq!(YcsbWorkload::E.mix().pick(0.5), OperationKind::Scan);
+        assert_eq!(YcsbWorkload::E.mix().pick(0.99), OperationKind::Scan);
+        assert_eq!("f".parse::<YcsbWorkload>(), Ok(YcsbWorkload::F));
+        assert!("g".parse::<YcsbWorkload>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_load_and_run() {
+        let root = tempdir().unwrap();
+        let mut store = DataStore::open_without_background("bench", root.path().join("bench"))
+            .await
+            .unwrap();
+        let config = BenchConfig {
+            workload: YcsbWorkload::D,
+            record_count: 100,
+            operation_count: 200,
+            value_len: 10,
+            ..Default::default()
+        };
+        let loaded = load(&mut store, &config).await.unwrap();
+        assert_eq!(loaded.operations, 100);
+        assert_eq!(loaded.latencies[&OperationKind::Insert].count, 100);
+
+        let report = run(&mut store, &config).await.unwrap();
+        assert_eq!(report.operations, 200);
+        let issued: usize = report.latencies.values().map(|s| s.count).sum();
+        assert_eq!(issued, 200);
+        assert!(report.latencies.contains_key(&OperationKind::Read));
+        assert!(report.to_string().starts_with("workload D: 200 ops"));
+        // every record read back, including inserted ones, is present
+        let inserted = report
+            .latencies
+            .get(&OperationKind::Insert)
+            .map_or(0, |s| s.count) as u64;
+        assert!(store.get(ycsb_key(99 + inserted)).await.unwrap().is_some());
+    }
+}
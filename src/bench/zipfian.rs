@@ -0,0 +1,144 @@
+use rand::Rng;
+
+/// Skew used by YCSB, a few items receive most of the requests
+pub const ZIPFIAN_CONSTANT: f64 = 0.99;
+
+/// Generates item numbers in `0..items` following a zipfian distribution
+///
+/// Item 0 is the most popular, followed by item 1 and so on. Uses the
+/// algorithm from "Quickly Generating Billion-Record Synthetic Databases"
+/// (Gray et al.), the item count can grow without recomputing zeta from scratch.
+#[derive(Clone, Debug)]
+pub struct ZipfianGenerator {
+    items: u64,
+    theta: f64,
+    alpha: f64,
+    zeta2: f64,
+    zetan: f64,
+    eta: f64,
+}
+
+impl ZipfianGenerator {
+    /// Creates new `ZipfianGenerator` over `items` with [`ZIPFIAN_CONSTANT`] skew
+    ///
+    /// # Panics
+    ///
+    /// Panics if `items` is zero.
+    pub fn new(items: u64) -> Self {
+        Self::with_theta(items, ZIPFIAN_CONSTANT)
+    }
+
+    /// Creates new `ZipfianGenerator` over `items` with `theta` skew
+    ///
+    /// # Panics
+    ///
+    /// Panics if `items` is zero or `theta` is not in `(0, 1)`.
+    pub fn with_theta(items: u64, theta: f64) -> Self {
+        assert!(items > 0, "items should be greater than zero");
+        assert!(theta > 0.0 && theta < 1.0, "theta should be between 0 and 1");
+        let zeta2 = Self::zeta(0, 2, theta, 0.0);
+        let zetan = Self::zeta(0, items, theta, 0.0);
+        let mut generator = Self {
+            items,
+            theta,
+            alpha: 1.0 / (1.0 - theta),
+            zeta2,
+            zetan,
+            eta: 0.0,
+        };
+        generator.eta = generator.eta();
+        generator
+    }
+
+    /// Returns number of items
+    pub fn items(&self) -> u64 {
+        self.items
+    }
+
+    /// Grows the item count to `items`, smaller counts are ignored
+    pub fn grow(&mut self, items: u64) {
+        if items <= self.items {
+            return;
+        }
+        self.zetan = Self::zeta(self.items, items, self.theta, self.zetan);
+        self.items = items;
+        self.eta = self.eta();
+    }
+
+    /// Returns next item number
+    pub fn next<R: Rng>(&self, rng: &mut R) -> u64 {
+        let u: f64 = rng.gen();
+        let uz = u * self.zetan;
+        if uz < 1.0 {
+            return 0;
+        }
+        if uz < 1.0 + 0.5f64.powf(self.theta) {
+            return 1.min(self.items - 1);
+        }
+        let item = (self.items as f64 * (self.eta * u - self.eta + 1.0).powf(self.alpha)) as u64;
+        item.min(self.items - 1)
+    }
+
+    /// Returns next item number, with popular items spread over the whole range
+    /// instead of clustered at the start
+    pub fn next_scrambled<R: Rng>(&self, rng: &mut R) -> u64 {
+        fnv_hash(self.next(rng)) % self.items
+    }
+
+    /// Adds terms `from..to` of the zeta function to `sum`
+    fn zeta(from: u64, to: u64, theta: f64, sum: f64) -> f64 {
+        (from..to).fold(sum, |acc, i| acc + 1.0 / ((i + 1) as f64).powf(theta))
+    }
+
+    fn eta(&self) -> f64 {
+        (1.0 - (2.0 / self.items as f64).powf(1.0 - self.theta)) / (1.0 - self.zeta2 / self.zetan)
+    }
+}
+
+/// 64-bit FNV-1a hash of `val`
+fn fnv_hash(val: u64) -> u64 {
+    val.to_le_bytes().iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_zipfian_is_skewed() {
+        let generator = ZipfianGenerator::new(1000);
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut counts = vec![0; 1000];
+        for _ in 0..100_000 {
+            let item = generator.next(&mut rng);
+            assert!(item < 1000);
+            counts[item as usize] += 1;
+        }
+        assert!(counts[0] > counts[1]);
+        assert!(counts[1] > counts[100]);
+        // the 10 most popular items get a big share of all requests
+        assert!(counts[..10].iter().sum::<usize>() > 30_000);
+    }
+
+    #[test]
+    fn test_zipfian_grow_matches_new() {
+        let mut grown = ZipfianGenerator::new(10);
+        grown.grow(500);
+        let fresh = ZipfianGenerator::new(500);
+        assert_eq!(grown.items(), 500);
+        assert!((grown.zetan - fresh.zetan).abs() < 1e-9);
+        assert!((grown.eta - fresh.eta).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_zipfian_scrambled_in_range() {
+        let generator = ZipfianGenerator::new(37);
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..1000 {
+            assert!(generator.next_scrambled(&mut rng) < 37);
+        }
+    }
+}
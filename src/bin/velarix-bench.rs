@@ -0,0 +1,90 @@
+//! Runs YCSB core workloads against a fresh store and prints throughput and latencies
+//!
+//! ```text
+//! velarix-bench [--workload A-F|all] [--records N] [--operations N]
+//!               [--value-size N] [--max-scan N] [--seed N] [--dir PATH]
+//! ```
+use std::{path::PathBuf, process};
+use velarixdb::{
+    bench::{self, BenchConfig, YcsbWorkload},
+    db::DataStore,
+};
+
+const USAGE: &str = "usage: velarix-bench [--workload A-F|all] [--records N] [--operations N] \
+[--value-size N] [--max-scan N] [--seed N] [--dir PATH]";
+
+struct Args {
+    workloads: Vec<YcsbWorkload>,
+    config: BenchConfig,
+    dir: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        workloads: YcsbWorkload::ALL.to_vec(),
+        config: BenchConfig::default(),
+        dir: None,
+    };
+    let mut iter = std::env::args().skip(1);
+    while let Some(flag) = iter.next() {
+        if flag == "-h" || flag == "--help" {
+            return Err(USAGE.to_owned());
+        }
+        let val = iter.next().ok_or_else(|| format!("missing value for {}", flag))?;
+        let number = || {
+            val.parse::<u64>()
+                .map_err(|_| format!("invalid number for {}: {}", flag, val))
+        };
+        match flag.as_str() {
+            "--workload" if val.eq_ignore_ascii_case("all") => args.workloads = YcsbWorkload::ALL.to_vec(),
+            "--workload" => args.workloads = vec![val.parse()?],
+            "--records" => args.config.record_count = number()?,
+            "--operations" => args.config.operation_count = number()?,
+            "--value-size" => args.config.value_len = number()? as usize,
+            "--max-scan" => args.config.max_scan_len = number()? as usize,
+            "--seed" => args.config.seed = number()?,
+            "--dir" => args.dir = Some(PathBuf::from(val)),
+            _ => return Err(format!("unknown flag {}\n{}", flag, USAGE)),
+        }
+    }
+    if args.config.record_count == 0 {
+        return Err("--records should be greater than zero".to_owned());
+    }
+    Ok(args)
+}
+
+#[tokio::main]
+async fn main() {
+    let args = parse_args().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(2);
+    });
+    let root = tempfile::tempdir().expect("failed to create temporary directory");
+    let base = args.dir.unwrap_or_else(|| root.path().to_path_buf());
+
+    for workload in args.workloads {
+        let config = BenchConfig {
+            workload,
+            ..args.config.clone()
+        };
+        // every workload starts from a freshly loaded store
+        let path = base.join(format!("ycsb_{}", workload).to_lowercase());
+        let mut store = DataStore::open("bench", path).await.unwrap_or_else(|err| {
+            eprintln!("failed to open store: {}", err);
+            process::exit(1);
+        });
+        let result = async {
+            let loaded = bench::load(&mut store, &config).await?;
+            println!("load {}", loaded);
+            bench::run(&mut store, &config).await
+        }
+        .await;
+        match result {
+            Ok(report) => println!("run {}", report),
+            Err(err) => {
+                eprintln!("workload {} failed: {}", workload, err);
+                process::exit(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,264 @@
+//! Inspects a closed store: prints the value log entry at an offset and whether
+//! an SSTable still references it, scans live entries of a key range, or
+//! reports buckets and whether compaction would merge them
+//!
+//! ```text
+//! velarix-inspect --dir PATH --offset N
+//! velarix-inspect scan --dir PATH [--start KEY] [--end KEY] [--prefix PREFIX] [--limit N] [--format json|csv|hex]
+//! velarix-inspect buckets --dir PATH
+//! ```
+//!
+//! `scan` writes one entry per line as it reads them. `json` lines and `csv`
+//! print keys and values as UTF-8, replacing invalid bytes, `hex` prints them
+//! hex encoded and separated by a tab.
+use std::{
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    process,
+};
+use velarixdb::db::{DataStore, FetchedEntry};
+
+const USAGE: &str = "usage: velarix-inspect --dir PATH --offset N
+       velarix-inspect scan --dir PATH [--start KEY] [--end KEY] [--prefix PREFIX] [--limit N] [--format json|csv|hex]
+       velarix-inspect buckets --dir PATH";
+
+enum Command {
+    Entry { dir: PathBuf, offset: usize },
+    Scan(ScanArgs),
+    Buckets { dir: PathBuf },
+}
+
+struct ScanArgs {
+    dir: PathBuf,
+
+    /// Smallest key scanned
+    start: Vec<u8>,
+
+    /// Key the scan ends before, `None` to scan to the last key
+    end: Option<Vec<u8>>,
+    prefix: Option<Vec<u8>>,
+    limit: Option<usize>,
+    format: Format,
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Json,
+    Csv,
+    Hex,
+}
+
+fn parse_number(flag: &str, val: &str) -> Result<usize, String> {
+    val.parse::<usize>()
+        .map_err(|_| format!("invalid number for {}: {}", flag, val))
+}
+
+fn parse_args() -> Result<Command, String> {
+    let mut args = std::env::args().skip(1).peekable();
+    let command = args.next_if(|arg| !arg.starts_with('-'));
+    if let Some(command) = command.as_deref().filter(|c| !["scan", "buckets"].contains(c)) {
+        return Err(format!("unknown command {}\n{}", command, USAGE));
+    }
+    let mut dir = None;
+    let mut offset = None;
+    let mut start = Vec::new();
+    let mut end = None;
+    let mut prefix = None;
+    let mut limit = None;
+    let mut format = Format::Json;
+    while let Some(flag) = args.next() {
+        if flag == "-h" || flag == "--help" {
+            return Err(USAGE.to_owned());
+        }
+        let val = args.next().ok_or_else(|| format!("missing value for {}", flag))?;
+        match (command.as_deref(), flag.as_str()) {
+            (_, "--dir") => dir = Some(PathBuf::from(val)),
+            (None, "--offset") => offset = Some(parse_number(&flag, &val)?),
+            (Some("scan"), "--start") => start = val.into_bytes(),
+            (Some("scan"), "--end") => end = Some(val.into_bytes()),
+            (Some("scan"), "--prefix") => prefix = Some(val.into_bytes()),
+            (Some("scan"), "--limit") => limit = Some(parse_number(&flag, &val)?),
+            (Some("scan"), "--format") => {
+                format = match val.as_str() {
+                    "json" => Format::Json,
+                    "csv" => Format::Csv,
+                    "hex" => Format::Hex,
+                    _ => return Err(format!("unknown format {}, expected json, csv or hex", val)),
+                }
+            }
+            _ => return Err(format!("unknown flag {}\n{}", flag, USAGE)),
+        }
+    }
+    match (command.as_deref(), dir, offset) {
+        (None, Some(dir), Some(offset)) => Ok(Command::Entry { dir, offset }),
+        (Some("scan"), Some(dir), _) => Ok(Command::Scan(ScanArgs {
+            dir,
+            start,
+            end,
+            prefix,
+            limit,
+            format,
+        })),
+        (Some("buckets"), Some(dir), _) => Ok(Command::Buckets { dir }),
+        _ => Err(USAGE.to_owned()),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let command = parse_args().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(2);
+    });
+    let dir = match &command {
+        Command::Entry { dir, .. } => dir,
+        Command::Scan(args) => &args.dir,
+        Command::Buckets { dir } => dir,
+    };
+    if !dir.exists() {
+        eprintln!("no store at {:?}", dir);
+        process::exit(1);
+    }
+    // background tasks would flush and collect garbage while the store is inspected
+    let store = DataStore::open_without_background("inspect", dir)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("failed to open store: {}", err);
+            process::exit(1);
+        });
+    match command {
+        Command::Entry { offset, .. } => print_entry(&store, offset).await,
+        Command::Scan(args) => scan(&store, args).await,
+        Command::Buckets { .. } => print_buckets(&store).await,
+    }
+}
+
+async fn print_entry(store: &DataStore<'static, Vec<u8>>, offset: usize) {
+    match store.entry_at(offset).await {
+        Ok(Some((entry, referenced))) => {
+            println!("offset     {}", offset);
+            println!("key        {}", String::from_utf8_lossy(&entry.key));
+            println!("kind       {:?}", entry.kind);
+            println!("tombstone  {}", entry.is_tombstone);
+            println!("created at {}", entry.created_at);
+            println!("value size {}", entry.vsize);
+            println!("checksum   {}", entry.checksum.is_some());
+            println!("referenced {}", referenced);
+        }
+        Ok(None) => {
+            eprintln!("no entry at offset {}", offset);
+            process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("failed to read entry at offset {}: {}", offset, err);
+            process::exit(1);
+        }
+    }
+}
+
+async fn print_buckets(store: &DataStore<'static, Vec<u8>>) {
+    let reports = store.describe_buckets().await.unwrap_or_else(|err| {
+        eprintln!("failed to describe buckets: {}", err);
+        process::exit(1);
+    });
+    if reports.is_empty() {
+        println!("no buckets");
+        return;
+    }
+    for (i, bucket) in reports.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("bucket     {}", bucket.id);
+        println!("dir        {}", bucket.dir.display());
+        println!("avg size   {}", bucket.average_size);
+        println!(
+            "sstables   {} (threshold {})",
+            bucket.sstable_count, bucket.threshold
+        );
+        println!(
+            "hotness    min {} median {} max {}",
+            bucket.hotness.min, bucket.hotness.median, bucket.hotness.max
+        );
+        println!(
+            "compaction {}",
+            if bucket.needs_compaction {
+                "pending"
+            } else {
+                "not needed"
+            }
+        );
+    }
+}
+
+async fn scan(store: &DataStore<'static, Vec<u8>>, args: ScanArgs) {
+    let view = match &args.prefix {
+        Some(prefix) => store.prefix_view(prefix),
+        None => store.view::<&[u8]>(..),
+    };
+    // keys are at most `max_key_size` bytes, so no key sorts after this one
+    let last = vec![u8::MAX; store.config().max_key_size + 1];
+    let end = args.end.unwrap_or(last);
+    let mut iter = view.seek(args.start, end).await.unwrap_or_else(|err| {
+        eprintln!("failed to scan: {}", err);
+        process::exit(1);
+    });
+    let mut out = BufWriter::new(io::stdout().lock());
+    if let Format::Csv = args.format {
+        write_or_exit(writeln!(out, "key,value"));
+    }
+    let mut written = 0;
+    while args.limit.is_none_or(|limit| written < limit) {
+        match iter.next().await {
+            Ok(Some(entry)) => write_or_exit(write_entry(&mut out, args.format, &entry)),
+            Ok(None) => break,
+            Err(err) => {
+                let _ = out.flush();
+                eprintln!("failed to read entry: {}", err);
+                process::exit(1);
+            }
+        }
+        written += 1;
+    }
+    write_or_exit(out.flush());
+}
+
+fn write_entry(out: &mut impl Write, format: Format, entry: &FetchedEntry) -> io::Result<()> {
+    match format {
+        Format::Json => {
+            let line = serde_json::json!({
+                "key": String::from_utf8_lossy(&entry.key),
+                "value": String::from_utf8_lossy(&entry.val),
+            });
+            writeln!(out, "{}", line)
+        }
+        Format::Csv => writeln!(out, "{},{}", csv_field(&entry.key), csv_field(&entry.val)),
+        Format::Hex => writeln!(out, "{}\t{}", hex(&entry.key), hex(&entry.val)),
+    }
+}
+
+/// Quotes `bytes` if they hold a comma, quote or line break, doubling quotes
+fn csv_field(bytes: &[u8]) -> String {
+    let field = String::from_utf8_lossy(bytes);
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.into_owned()
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Exits quietly once the reader of the output is gone, e.g. `| head`
+fn write_or_exit(res: io::Result<()>) {
+    match res {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => process::exit(0),
+        Err(err) => {
+            eprintln!("failed to write output: {}", err);
+            process::exit(1);
+        }
+    }
+}
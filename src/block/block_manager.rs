@@ -69,6 +69,7 @@ use crate::{
     err::{self, Error},
     fs::{FileAsync, FileNode},
     types::ByteSerializedEntry,
+    util,
 };
 type BytesWritten = usize;
 
@@ -84,7 +85,7 @@ pub struct Block {
 }
 
 /// Each entry in the block
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct BlockEntry {
     pub key_prefix: u32,
     pub key: Vec<u8>,
@@ -92,6 +93,34 @@ pub struct BlockEntry {
     pub creation_date: DateTime<Utc>,
     pub is_tombstone: bool,
 }
+
+impl BlockEntry {
+    /// Serializes entry to the byte layout used in data files
+    ///
+    /// The key length is written from `key` itself, creation date
+    /// is stored with millisecond precision
+    #[doc(hidden)]
+    pub fn serialize(&self) -> ByteSerializedEntry {
+        let entry_len = self.key.len() + SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8;
+        let mut entry_vec = Vec::with_capacity(entry_len);
+        entry_vec.extend_from_slice(&(self.key.len() as u32).to_le_bytes());
+
+        entry_vec.extend_from_slice(&self.key);
+
+        entry_vec.extend_from_slice(&self.value_offset.to_le_bytes());
+
+        entry_vec.extend_from_slice(&self.creation_date.timestamp_millis().to_le_bytes());
+
+        entry_vec.push(self.is_tombstone as u8);
+        entry_vec
+    }
+}
+impl Default for Block {
+    fn default() -> Self {
+        Block::new()
+    }
+}
+
 impl Block {
     /// Creates a new empty Block.
     pub fn new() -> Self {
@@ -117,8 +146,7 @@ impl Block {
         creation_date: DateTime<Utc>,
         is_tombstone: bool,
     ) -> Result<(), Error> {
-        // Key + Key Prefix + Value Offset +  Creation Date + Tombstone Marker
-        let entry_size = key.as_ref().len() + SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8;
+        let entry_size = Block::entry_size(key.as_ref());
 
         if self.is_full(entry_size) {
             return Err(Error::BlockIsFull);
@@ -155,11 +183,22 @@ impl Block {
         Ok(bytes_written)
     }
 
+    /// Returns size of an entry with `key` in the data file
+    pub(crate) fn entry_size(key: &[u8]) -> usize {
+        // Key + Key Prefix + Value Offset +  Creation Date + Tombstone Marker
+        key.len() + SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8
+    }
+
     /// Checks if the Block is full
     pub fn is_full(&self, entry_size: usize) -> bool {
         self.size + entry_size > BLOCK_SIZE
     }
 
+    /// Checks if every entry of the block is a tombstone
+    pub(crate) fn has_only_tombstones(&self) -> bool {
+        !self.entries.is_empty() && self.entries.iter().all(|e| e.is_tombstone)
+    }
+
     pub fn get_last_entry(&self) -> BlockEntry {
         self.entries.last().unwrap().to_owned()
     }
@@ -174,28 +213,56 @@ impl Block {
     ///
     /// Returns `Ok(entry_vec)` or Error if serialization failed
     pub(crate) fn serialize(&self, entry: &BlockEntry) -> Result<ByteSerializedEntry, Error> {
-        let entry_len = entry.key.len() + SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8;
-        let mut entry_vec = Vec::with_capacity(entry_len);
-        entry_vec.extend_from_slice(&(entry.key_prefix).to_le_bytes());
-
-        entry_vec.extend_from_slice(&entry.key);
-
-        entry_vec.extend_from_slice(&entry.value_offset.to_le_bytes());
-
-        entry_vec.extend_from_slice(&entry.creation_date.timestamp_millis().to_le_bytes());
-
-        entry_vec.push(entry.is_tombstone as u8);
-        if entry_len != entry_vec.len() {
+        if entry.key_prefix as usize != entry.key.len() {
             return Err(Serialization("Invalid input"));
         }
+        Ok(entry.serialize())
+    }
 
-        Ok(entry_vec)
+    /// Serializes every entry in the block, in order
+    #[doc(hidden)]
+    pub fn encode(&self) -> ByteSerializedEntry {
+        self.entries.iter().flat_map(|entry| entry.serialize()).collect()
+    }
+
+    /// Decodes block entries from bytes read from the data file
+    ///
+    /// Decoding stops at the first incomplete entry, so `buf` can be
+    /// a window that ends in the middle of the next block
+    #[doc(hidden)]
+    pub fn decode_entries(buf: &[u8]) -> Vec<BlockEntry> {
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        let fixed_size = SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8;
+        while offset + SIZE_OF_U32 <= buf.len() {
+            let key_prefix = u32::from_le_bytes(buf[offset..offset + SIZE_OF_U32].try_into().unwrap());
+            let entry_len = key_prefix as usize + fixed_size;
+            if offset + entry_len > buf.len() {
+                break;
+            }
+            let mut pos = offset + SIZE_OF_U32;
+            let key = buf[pos..pos + key_prefix as usize].to_vec();
+            pos += key_prefix as usize;
+            let value_offset = u32::from_le_bytes(buf[pos..pos + SIZE_OF_U32].try_into().unwrap());
+            pos += SIZE_OF_U32;
+            let created_at = u64::from_le_bytes(buf[pos..pos + SIZE_OF_U64].try_into().unwrap());
+            pos += SIZE_OF_U64;
+            let is_tombstone = buf[pos] == 1;
+            entries.push(BlockEntry {
+                key_prefix,
+                key,
+                value_offset,
+                creation_date: util::milliseconds_to_datetime(created_at),
+                is_tombstone,
+            });
+            offset += entry_len;
+        }
+        entries
     }
 
     /// Constructs BlockEntry from file
     ///
     /// Returns `Some(&BlockEntry)` entry was constructed, `None` otherwise.
-    /// Method will be used when we implement the block cache
     #[allow(dead_code)]
     pub(crate) fn get_entry(&self, key: impl AsRef<[u8]>) -> Option<&BlockEntry> {
         self.entries.iter().find(|entry| *entry.key == *key.as_ref())
@@ -353,4 +420,31 @@ mod tests {
             BLOCK_SIZE / (key.len() + SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8)
         );
     }
+
+    #[test]
+    fn test_decode_entries() {
+        let mut block = Block::new();
+        let creation_date = util::milliseconds_to_datetime(Utc::now().timestamp_millis() as u64);
+        block
+            .set_entry(3, vec![1, 2, 3], 1000, creation_date, false)
+            .unwrap();
+        block.set_entry(2, vec![4, 5], 2000, creation_date, true).unwrap();
+        let mut buf = Vec::new();
+        for entry in &block.entries {
+            buf.extend(block.serialize(entry).unwrap());
+        }
+        // trailing bytes of an incomplete entry are ignored
+        buf.extend_from_slice(&10_u32.to_le_bytes());
+        buf.push(7);
+
+        let entries = Block::decode_entries(&buf);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].key, vec![1, 2, 3]);
+        assert_eq!(entries[0].value_offset, 1000);
+        assert_eq!(entries[0].creation_date, creation_date);
+        assert!(!entries[0].is_tombstone);
+        assert_eq!(entries[1].key, vec![4, 5]);
+        assert_eq!(entries[1].value_offset, 2000);
+        assert!(entries[1].is_tombstone);
+    }
 }
@@ -1 +1,95 @@
-// TODO: Implement block cache
\ No newline at end of file
+use super::block_manager::BlockEntry;
+use crate::{
+    cache::{CacheStats, LruCache},
+    consts::DEFAULT_BLOCK_CACHE_CAPACITY,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Alias for a data block identified by data file path and block offset
+type BlockHandle = (PathBuf, u32);
+
+/// Alias for decoded block entries
+pub(crate) type CachedBlock = Arc<Vec<BlockEntry>>;
+
+/// Cache for decoded SSTable data blocks
+///
+/// Cloning a `BlockCache` is cheap, clones share the same entries and
+/// memory budget, so one instance can be passed to the [`Config`](crate::db::Config)
+/// of several stores.
+#[derive(Clone, Debug)]
+pub struct BlockCache {
+    inner: Arc<LruCache<BlockHandle, CachedBlock>>,
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        BlockCache::new(DEFAULT_BLOCK_CACHE_CAPACITY)
+    }
+}
+
+impl BlockCache {
+    /// Creates new `BlockCache` that holds at most `capacity` bytes
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Returns cached block at `offset` in data file
+    pub(crate) fn get(&self, data_file_path: impl AsRef<Path>, offset: u32) -> Option<CachedBlock> {
+        self.inner.get(&(data_file_path.as_ref().to_path_buf(), offset))
+    }
+
+    /// Caches block at `offset` in data file
+    pub(crate) fn insert(
+        &self,
+        data_file_path: impl AsRef<Path>,
+        offset: u32,
+        block: CachedBlock,
+        charge: usize,
+    ) {
+        self.inner
+            .insert((data_file_path.as_ref().to_path_buf(), offset), block, charge);
+    }
+
+    /// Drops cached blocks of the data file, its sstable was removed
+    pub(crate) fn remove_sstable(&self, data_file_path: impl AsRef<Path>) {
+        let data_file_path = data_file_path.as_ref();
+        self.inner.remove_by(|(path, _)| path == data_file_path);
+    }
+
+    /// Returns bytes currently held by the cache
+    pub fn usage(&self) -> usize {
+        self.inner.usage()
+    }
+
+    /// Returns maximum bytes the cache can hold
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    /// Changes maximum bytes the cache can hold, evicting least recently used blocks that no longer fit
+    pub fn set_capacity(&self, capacity: usize) {
+        self.inner.set_capacity(capacity)
+    }
+
+    /// Returns hit, miss and eviction counts and size of the cache
+    pub fn stats(&self) -> CacheStats {
+        self.inner.stats()
+    }
+
+    /// Returns bytes of cached blocks of each SSTable, keyed by path of its data file
+    pub fn residency(&self) -> HashMap<PathBuf, usize> {
+        self.inner
+            .usage_by(|(data_file_path, _)| data_file_path.to_owned())
+    }
+
+    /// Returns true if both handles share the same cache
+    pub fn same_as(&self, other: &BlockCache) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
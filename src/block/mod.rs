@@ -1,3 +1,7 @@
 mod block_manager;
+mod cache;
 
 pub use block_manager::Block;
+pub use block_manager::BlockEntry;
+pub use cache::BlockCache;
+pub(crate) use cache::CachedBlock;
@@ -1,12 +1,13 @@
+use super::manifest::{BucketManifest, BucketRecord, FileNumber, SSTableRecord};
+use super::FilePins;
 use crate::consts::{
     BUCKET_DIRECTORY_PREFIX, BUCKET_HIGH, BUCKET_LOW, MAX_TRESHOLD, MIN_SSTABLE_SIZE, MIN_TRESHOLD,
 };
 use crate::err::Error;
 use crate::filter::BloomFilter;
 use crate::fs::{FileAsync, FileNode};
-use crate::sst::Table;
+use crate::sst::{RangeTombstone, Table};
 use crate::types::{Bool, Key, SkipMapEntries};
-use chrono::Utc;
 use indexmap::IndexMap;
 use std::fmt::Debug;
 use std::path::Path;
@@ -16,8 +17,6 @@ use tokio::sync::RwLock;
 use uuid::Uuid;
 use Error::*;
 
-static SST_PREFIX: &str = "sstable";
-
 /// Alias for SSTables to remove from each bucket
 pub type SSTablesToRemove = Vec<(BucketID, Vec<Table>)>;
 
@@ -35,6 +34,15 @@ pub type AvgSize = usize;
 pub struct BucketMap {
     pub dir: PathBuf,
     pub buckets: IndexMap<BucketID, Bucket>,
+
+    /// Pins deferring deletion of obsolete sstables
+    pub(crate) pins: FilePins,
+
+    /// Encode sstable blocks on the blocking thread pool
+    pub(crate) offload_cpu_work: bool,
+
+    /// File number the next sstable is written with, persisted in the bucket manifest
+    pub(crate) next_file_number: FileNumber,
 }
 
 /// Enum to signify to create new bucket or use exisiting one
@@ -44,6 +52,20 @@ pub(crate) enum InsertionType {
     Exisiting,
 }
 
+/// Bucket and directory picked for an sstable before it is written
+///
+/// Returned by [`BucketMap::plan_insert`], so the directory can be recorded
+/// before any file of the sstable exists
+pub(crate) struct PlannedTable {
+    bucket: Bucket,
+    insert_type: InsertionType,
+    parent_dir: PathBuf,
+    file_number: FileNumber,
+
+    /// Directory the sstable is written to
+    pub(crate) dir: PathBuf,
+}
+
 /// Groups SSTables of approximately equal sizes together
 #[derive(Debug, Clone)]
 pub struct Bucket {
@@ -59,6 +81,7 @@ pub trait InsertableToBucket: Debug + Send + Sync {
     fn get_entries(&self) -> SkipMapEntries<Key>;
     fn size(&self) -> usize;
     fn get_filter(&self) -> BloomFilter;
+    fn get_range_tombstones(&self) -> Vec<RangeTombstone>;
 }
 
 impl Bucket {
@@ -113,14 +136,20 @@ impl Bucket {
             return Ok(0);
         }
         let mut size = 0;
-        let fetch_files_meta = ssts
-            .iter()
-            .map(|s| tokio::spawn(fs::metadata(s.data_file.path.clone())));
-        for meta_task in fetch_files_meta {
+        let fetch_files_meta = ssts.iter().map(|s| {
+            (
+                s.data_file.path.clone(),
+                tokio::spawn(fs::metadata(s.data_file.path.clone())),
+            )
+        });
+        for (path, meta_task) in fetch_files_meta {
             let meta_data = meta_task
                 .await
-                .map_err(|err| GetFileMetaData(err.into()))?
-                .unwrap();
+                .map_err(|err| GetFileMetaData {
+                    path: path.to_owned(),
+                    error: err.into(),
+                })?
+                .map_err(|error| GetFileMetaData { path, error })?;
             size += meta_data.len() as usize;
         }
         Ok(size / ssts.len() as u64 as usize)
@@ -134,10 +163,41 @@ impl Bucket {
     ///
     /// Returns `true` if table fits or `false` if it doesn't
     ///
+    #[allow(dead_code)]
     pub(crate) fn fits_into_bucket<T: InsertableToBucket + ?Sized>(&self, table: Arc<Box<T>>) -> Bool {
-        (self.avarage_size as f64 * BUCKET_LOW < table.size() as f64)
-            && (table.size() < (self.avarage_size as f64 * BUCKET_HIGH) as usize)
-            || (table.size() < MIN_SSTABLE_SIZE && self.avarage_size < MIN_SSTABLE_SIZE)
+        Bucket::fits_average(self.avarage_size, table.size())
+    }
+
+    /// Checks if a table of `size` fits into a bucket of `avarage_size`, see [`Bucket::fits_into_bucket`]
+    fn fits_average(avarage_size: AvgSize, size: usize) -> Bool {
+        (avarage_size as f64 * BUCKET_LOW < size as f64)
+            && (size < (avarage_size as f64 * BUCKET_HIGH) as usize)
+            || (size < MIN_SSTABLE_SIZE && avarage_size < MIN_SSTABLE_SIZE)
+    }
+
+    /// Returns average size of the sstables that remain in `Bucket` once `replaced` are removed
+    ///
+    /// Returns `None` if no sstable remains
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    async fn average_without(&self, replaced: &SSTablesToRemove) -> Result<Option<AvgSize>, Error> {
+        let Some((_, ssts)) = replaced.iter().find(|(id, _)| *id == self.id) else {
+            return Ok(Some(self.avarage_size));
+        };
+        let remaining: Vec<Table> = self
+            .sstables
+            .read()
+            .await
+            .iter()
+            .filter(|sst| !ssts.iter().any(|replaced| replaced.dir == sst.dir))
+            .cloned()
+            .collect();
+        if remaining.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Bucket::cal_average_size(remaining).await?))
     }
 
     /// Returns SSTables that needs to be compacted in a [`Bucket`]
@@ -185,9 +245,25 @@ impl BucketMap {
         Ok(Self {
             dir: dir.to_path_buf(),
             buckets: IndexMap::new(),
+            pins: FilePins::default(),
+            offload_cpu_work: true,
+            next_file_number: 1,
         })
     }
 
+    /// Sets whether sstable blocks are encoded on the blocking thread pool
+    pub(crate) fn with_cpu_offload(mut self, offload_cpu_work: bool) -> Self {
+        self.offload_cpu_work = offload_cpu_work;
+        self
+    }
+
+    /// Returns file number for the next sstable, numbers are never handed out twice
+    fn take_file_number(&mut self) -> FileNumber {
+        let file_number = self.next_file_number;
+        self.next_file_number += 1;
+        file_number
+    }
+
     /// Inserts merged sstable or memtable to a bucket
     ///
     /// Tables to be inserted to bucket must have the `InsertableToBucket` trait
@@ -201,63 +277,173 @@ impl BucketMap {
         &mut self,
         table: Arc<Box<T>>,
     ) -> Result<Table, Error> {
+        self.insert_to_appropriate_bucket_in(table, None, &Vec::new())
+            .await
+    }
+
+    /// Same as [`BucketMap::insert_to_appropriate_bucket`], but writes the
+    /// sstable files under `cold_dir` if provided
+    ///
+    /// Cold sstables still belong to a bucket in `BucketMap::dir`, their files are
+    /// stored in a bucket directory of the same name under `cold_dir`
+    ///
+    /// Sstables in `replaced` are about to be removed by compaction, the average size
+    /// of each bucket is recomputed without them, so a merged sstable goes to the
+    /// bucket its own size matches rather than back to the bucket it was merged from.
+    /// Of the buckets the table fits, the one with the closest average is used.
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there was an IO error or any kind of Error
+    pub(crate) async fn insert_to_appropriate_bucket_in<T: InsertableToBucket + ?Sized>(
+        &mut self,
+        table: Arc<Box<T>>,
+        cold_dir: Option<&Path>,
+        replaced: &SSTablesToRemove,
+    ) -> Result<Table, Error> {
+        let planned = self.plan_insert(table.size(), cold_dir, replaced).await?;
+        self.insert_planned(planned, table).await
+    }
+
+    /// Picks bucket and directory of an sstable of `size` bytes, see
+    /// [`BucketMap::insert_to_appropriate_bucket_in`]
+    ///
+    /// A new bucket directory is created if no bucket fits, the sstable
+    /// directory is not.
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there was an IO error
+    pub(crate) async fn plan_insert(
+        &mut self,
+        size: usize,
+        cold_dir: Option<&Path>,
+        replaced: &SSTablesToRemove,
+    ) -> Result<PlannedTable, Error> {
+        let mut closest: Option<(usize, &Bucket)> = None;
         for (_, bucket) in self.buckets.iter() {
-            if bucket.fits_into_bucket(table.clone()) {
-                return self
-                    .insert_to_bucket(bucket.to_owned(), table, InsertionType::Exisiting)
-                    .await;
+            let Some(avarage_size) = bucket.average_without(replaced).await? else {
+                continue;
+            };
+            if !Bucket::fits_average(avarage_size, size) {
+                continue;
+            }
+            let distance = avarage_size.abs_diff(size);
+            if closest.is_none_or(|(closest_distance, _)| distance < closest_distance) {
+                closest = Some((distance, bucket));
             }
         }
-
-        let bucket = Bucket::new(self.dir.clone()).await?;
-        self.insert_to_bucket(bucket, table, InsertionType::New).await
+        let (bucket, insert_type) = match closest {
+            Some((_, bucket)) => (bucket.to_owned(), InsertionType::Exisiting),
+            None => (Bucket::new(self.dir.clone()).await?, InsertionType::New),
+        };
+        let parent_dir = match cold_dir {
+            Some(cold_dir) => {
+                let dir = cold_dir.join(bucket.dir.file_name().unwrap_or_default());
+                FileNode::create_dir_all(dir.to_owned()).await?;
+                dir
+            }
+            None => bucket.dir.to_owned(),
+        };
+        // a number is used once even if writing the sstable fails, it is persisted with the next edit
+        let mut file_number = self.take_file_number();
+        let mut dir = parent_dir.join(SSTableRecord::dir_name(file_number));
+        // a directory of a manifest that was lost may hold the name
+        while fs::metadata(&dir).await.is_ok() {
+            file_number = self.take_file_number();
+            dir = parent_dir.join(SSTableRecord::dir_name(file_number));
+        }
+        Ok(PlannedTable {
+            bucket,
+            insert_type,
+            parent_dir,
+            file_number,
+            dir,
+        })
     }
 
-    /// Determines which bucket to insert merged sstable or memtable based on `InsertionType`
+    /// Writes `table` to the bucket and directory of `planned`
     ///
     /// Returns Result `Table` or `Err`
     ///
     /// # Errors
     ///
     /// Returns error in case there in IO error or any kind of Error
-    async fn insert_to_bucket<T: InsertableToBucket + ?Sized>(
+    pub(crate) async fn insert_planned<T: InsertableToBucket + ?Sized>(
         &mut self,
-        mut bucket: Bucket,
+        planned: PlannedTable,
         table: Arc<Box<T>>,
-        insert_type: InsertionType,
     ) -> Result<Table, Error> {
-        let created_at = Utc::now();
-        let sst_dir = bucket
-            .dir
-            .join(format!("{}_{}", SST_PREFIX, created_at.timestamp_millis()));
+        let PlannedTable {
+            mut bucket,
+            insert_type,
+            parent_dir,
+            file_number,
+            dir: sst_dir,
+        } = planned;
+        let cold = parent_dir != bucket.dir;
         let mut sst = Table::new(sst_dir).await?;
+        sst.file_number = file_number;
 
         sst.set_entries(table.get_entries());
         sst.filter = Some(table.get_filter());
-        sst.write_to_file().await?;
-        bucket.sstables.write().await.push(sst.to_owned());
-
-        match insert_type {
-            InsertionType::New => {
-                bucket.avarage_size = fs::metadata(sst.clone().data_file.path)
-                    .await
-                    .map_err(GetFileMetaData)?
-                    .len() as usize;
-                self.buckets.insert(bucket.id, bucket);
+        sst.range_tombstones = table.get_range_tombstones();
+        sst.write_to_file(self.offload_cpu_work).await?;
+        // make the new sstable directory entries durable, up to a newly created bucket
+        FileNode::sync_dir(&sst.dir).await?;
+        FileNode::sync_dir(&parent_dir).await?;
+        if matches!(insert_type, InsertionType::New) || cold {
+            if let Some(buckets_dir) = parent_dir.parent() {
+                FileNode::sync_dir(buckets_dir).await?;
             }
-            InsertionType::Exisiting => {
-                bucket
-                    .sstables
-                    .write()
-                    .await
-                    .iter_mut()
-                    .for_each(|s| s.increase_hotness());
-                bucket.avarage_size = Bucket::cal_average_size(bucket.sstables.read().await.to_vec()).await?;
-                bucket.size = bucket.avarage_size * bucket.sstables.read().await.len();
-                self.buckets.insert(bucket.id, bucket);
+        }
+        // filter bits are kept in the filter cache of key range, not in buckets
+        let mut bucket_sst = sst.to_owned();
+        bucket_sst.filter = sst.filter.as_ref().map(|filter| filter.without_bits());
+        let mut sstables = bucket.sstables.read().await.to_vec();
+        sstables.push(bucket_sst.to_owned());
+        let avarage_size = match insert_type {
+            InsertionType::New => sst.size(),
+            InsertionType::Exisiting => Bucket::cal_average_size(sstables.to_vec()).await?,
+        };
+
+        // the sstable is listed with its key range and filter in one edit before it
+        // is added in memory, an sstable that could not be listed is removed
+        let mut manifest = BucketManifest::from_map(self).await;
+        manifest.set_bucket(BucketRecord {
+            id: bucket.id,
+            avarage_size,
+            sstables: sstables.iter().map(SSTableRecord::of).collect(),
+        });
+        if let Err(err) = manifest.write(&self.dir).await {
+            if let Err(error) = self.pins.remove_dir_all(&sst.dir).await {
+                log::error!(
+                    "{}",
+                    DirDelete {
+                        path: sst.dir.to_owned(),
+                        error
+                    }
+                );
+            }
+            if matches!(insert_type, InsertionType::New) {
+                self.pins.remove_empty_dir(&parent_dir).await;
+                self.pins.remove_empty_dir(&bucket.dir).await;
             }
+            return Err(err);
         }
 
+        bucket.sstables.write().await.push(bucket_sst);
+        bucket.avarage_size = avarage_size;
+        if matches!(insert_type, InsertionType::Exisiting) {
+            bucket
+                .sstables
+                .write()
+                .await
+                .iter_mut()
+                .for_each(|s| s.increase_hotness());
+            bucket.size = bucket.avarage_size * bucket.sstables.read().await.len();
+        }
+        self.buckets.insert(bucket.id, bucket);
         Ok(sst)
     }
 
@@ -313,7 +499,11 @@ impl BucketMap {
             if let Some(bucket) = self.buckets.get_mut(bucket_id) {
                 let bucket_clone = bucket.clone();
                 let b = bucket_clone.sstables.read().await;
-                let ssts_remaining = b.get(ssts.len()..).unwrap_or_default();
+                let ssts_remaining: Vec<Table> = b
+                    .iter()
+                    .filter(|sst| !ssts.iter().any(|merged| merged.dir == sst.dir))
+                    .cloned()
+                    .collect();
                 if !ssts_remaining.is_empty() {
                     let new_average = Bucket::cal_average_size(ssts_remaining.to_vec()).await?;
                     *bucket = Bucket {
@@ -325,17 +515,36 @@ impl BucketMap {
                     };
                 } else {
                     buckets_to_delete.push(bucket_id);
-                    if let Err(err) = fs::remove_dir_all(&bucket.dir).await {
-                        log::error!("{}", DirDelete(err));
+                    if let Err(error) = self.pins.remove_dir_all(&bucket.dir).await {
+                        log::error!(
+                            "{}",
+                            DirDelete {
+                                path: bucket.dir.to_owned(),
+                                error
+                            }
+                        );
                     }
                 }
             }
 
             for sst in ssts {
                 if fs::metadata(&sst.dir).await.is_ok() {
-                    if let Err(err) = fs::remove_dir_all(&sst.dir).await {
+                    if let Err(error) = self.pins.remove_dir_all(&sst.dir).await {
                         all_ssts_deleted = false;
-                        log::error!("{}", DirDelete(err));
+                        log::error!(
+                            "{}",
+                            DirDelete {
+                                path: sst.dir.to_owned(),
+                                error
+                            }
+                        );
+                    }
+                }
+                // sstables in cold storage live outside `self.dir`, their bucket
+                // directory is removed once empty
+                if let Some(parent) = sst.dir.parent() {
+                    if !parent.starts_with(&self.dir) {
+                        self.pins.remove_empty_dir(parent).await;
                     }
                 }
             }
@@ -346,19 +555,38 @@ impl BucketMap {
                 self.buckets.shift_remove(bucket_id);
             });
         }
+        self.write_manifest().await?;
         Ok(all_ssts_deleted)
     }
 
+    /// Writes bucket manifest listing current buckets and their sstables
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case of IO error
+    pub(crate) async fn write_manifest(&self) -> Result<(), Error> {
+        BucketManifest::from_map(self).await.write(&self.dir).await
+    }
+
     /// CAUTION: This removes all sstables and buckets and should only be used for total cleanup
     #[allow(dead_code)]
     pub async fn clear_all(&mut self) {
         for (_, bucket) in &self.buckets {
             if fs::metadata(&bucket.dir).await.is_ok() {
-                if let Err(err) = fs::remove_dir_all(&bucket.dir).await {
-                    log::error!("{}", FileDelete(err));
+                if let Err(error) = fs::remove_dir_all(&bucket.dir).await {
+                    log::error!(
+                        "{}",
+                        DirDelete {
+                            path: bucket.dir.to_owned(),
+                            error
+                        }
+                    );
                 }
             }
         }
         self.buckets = IndexMap::new();
+        if let Err(err) = self.write_manifest().await {
+            log::error!("{}", err);
+        }
     }
 }
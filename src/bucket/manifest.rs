@@ -0,0 +1,257 @@
+//! # Bucket Manifest
+//!
+//! Buckets of a store with the sstables each one holds, kept in the store root.
+//!
+//! The manifest is rewritten whenever an sstable is added to or removed from
+//! a bucket. An sstable is listed with its key range and filter file in the
+//! same edit, and only once that edit is durable is the sstable added to its
+//! bucket and key range in memory. At next open the directories found on disk
+//! are checked against it: sstables it does not list are still loaded, since a
+//! crash may land between writing an sstable and the manifest, but they are
+//! reported along with listed sstables that are missing and files that belong
+//! to no sstable.
+//!
+//! SSTable directories are named by a file number taken from a counter the
+//! manifest persists, so no two sstables of a store share a name. Older
+//! versions named them by creation time, those keep their names and are
+//! mapped to file numbers in order of creation at the first open.
+
+use super::{BucketID, BucketMap};
+use crate::{
+    consts::BUCKET_MANIFEST_FILE_NAME,
+    err::Error::{self, *},
+    fs::FileNode,
+    sst::Table,
+    types::Key,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+static SST_PREFIX: &str = "sstable";
+
+/// File or directory found at open that does not match the bucket manifest
+///
+/// Returned by [`DataStore::layout_issues`](crate::db::DataStore::layout_issues),
+/// nothing is deleted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutIssue {
+    /// File or directory that belongs to no bucket or sstable, left in place and ignored
+    UnknownFile(PathBuf),
+
+    /// SSTable directory the manifest does not list, left in place and not loaded
+    UnlistedSSTable(PathBuf),
+
+    /// SSTable directory listed in the manifest that was not found
+    MissingSSTable(PathBuf),
+}
+
+/// Number of an sstable, unique within a store and increasing in the order sstables are written
+pub(crate) type FileNumber = u64;
+
+/// Bucket as written in the manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct BucketRecord {
+    pub(crate) id: BucketID,
+
+    /// Average size of sstables in the bucket
+    pub(crate) avarage_size: usize,
+
+    /// SSTables in the bucket
+    pub(crate) sstables: Vec<SSTableRecord>,
+}
+
+impl BucketRecord {
+    /// Returns record of sstable with directory name `name`, if listed
+    pub(crate) fn sstable(&self, name: &str) -> Option<&SSTableRecord> {
+        self.sstables.iter().find(|sst| sst.name == name)
+    }
+
+    /// Returns true if sstable with directory name `name` is listed
+    pub(crate) fn lists(&self, name: &str) -> bool {
+        self.sstable(name).is_some()
+    }
+}
+
+/// SSTable as written in the manifest
+///
+/// Manifests written before key ranges were recorded list directory names only,
+/// those are read with no key range and filter.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "ListedSSTable")]
+pub(crate) struct SSTableRecord {
+    /// Directory name of the sstable
+    pub(crate) name: String,
+
+    /// Smallest and biggest key of the sstable
+    pub(crate) key_range: Option<(Key, Key)>,
+
+    /// File name of the filter of the sstable
+    pub(crate) filter: Option<String>,
+
+    /// File number of the sstable, not listed by manifests of older versions
+    pub(crate) number: Option<FileNumber>,
+}
+
+/// SSTable in either manifest format
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ListedSSTable {
+    Name(String),
+    Record {
+        name: String,
+        key_range: Option<(Key, Key)>,
+        filter: Option<String>,
+        number: Option<FileNumber>,
+    },
+}
+
+impl From<ListedSSTable> for SSTableRecord {
+    fn from(listed: ListedSSTable) -> Self {
+        match listed {
+            ListedSSTable::Name(name) => Self::named(name),
+            ListedSSTable::Record {
+                name,
+                key_range,
+                filter,
+                number,
+            } => Self {
+                name,
+                key_range,
+                filter,
+                number,
+            },
+        }
+    }
+}
+
+impl SSTableRecord {
+    /// Creates record of sstable with directory name `name`, with no key range and filter
+    pub(crate) fn named(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            key_range: None,
+            filter: None,
+            number: None,
+        }
+    }
+
+    /// Returns directory name of the sstable with file number `number`
+    pub(crate) fn dir_name(number: FileNumber) -> String {
+        format!("{}_{:06}", SST_PREFIX, number)
+    }
+
+    /// Returns the number a directory name holds, creation time in names of older versions
+    pub(crate) fn parse_number(name: &str) -> Option<FileNumber> {
+        name.strip_prefix(SST_PREFIX)?.strip_prefix('_')?.parse().ok()
+    }
+
+    /// Creates record of `sst`, with key range from its summary and its filter file
+    pub(crate) fn of(sst: &Table) -> Self {
+        let name = sst
+            .dir
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        Self {
+            name,
+            key_range: sst
+                .summary
+                .as_ref()
+                .map(|summary| (summary.smallest_key.to_owned(), summary.biggest_key.to_owned())),
+            filter: sst
+                .filter
+                .as_ref()
+                .and_then(|filter| filter.file_path.as_ref())
+                .and_then(|path| path.file_name())
+                .map(|name| name.to_string_lossy().to_string()),
+            number: Some(sst.file_number),
+        }
+    }
+}
+
+/// Buckets of a store and their sstables
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct BucketManifest {
+    pub(crate) buckets: Vec<BucketRecord>,
+
+    /// File number the next sstable is written with, 0 in manifests of older versions
+    #[serde(default)]
+    pub(crate) next_file_number: FileNumber,
+}
+
+impl BucketManifest {
+    /// Creates manifest of buckets in `bucket_map`
+    pub(crate) async fn from_map(bucket_map: &BucketMap) -> Self {
+        let mut buckets = Vec::with_capacity(bucket_map.buckets.len());
+        for bucket in bucket_map.buckets.values() {
+            let sstables = bucket
+                .sstables
+                .read()
+                .await
+                .iter()
+                .filter(|sst| sst.dir.file_name().is_some())
+                .map(SSTableRecord::of)
+                .collect();
+            buckets.push(BucketRecord {
+                id: bucket.id,
+                avarage_size: bucket.avarage_size,
+                sstables,
+            });
+        }
+        Self {
+            buckets,
+            next_file_number: bucket_map.next_file_number,
+        }
+    }
+
+    /// Returns record of bucket `id`, if listed
+    pub(crate) fn bucket(&self, id: &BucketID) -> Option<&BucketRecord> {
+        self.buckets.iter().find(|b| &b.id == id)
+    }
+
+    /// Replaces record of bucket `record.id`, or adds it if not listed
+    pub(crate) fn set_bucket(&mut self, record: BucketRecord) {
+        match self.buckets.iter_mut().find(|b| b.id == record.id) {
+            Some(listed) => *listed = record,
+            None => self.buckets.push(record),
+        }
+    }
+
+    /// Returns path of the manifest of the store whose buckets are in `buckets_dir`
+    pub(crate) fn path(buckets_dir: impl AsRef<Path>) -> PathBuf {
+        let buckets_dir = buckets_dir.as_ref();
+        buckets_dir
+            .parent()
+            .unwrap_or(buckets_dir)
+            .join(BUCKET_MANIFEST_FILE_NAME)
+    }
+
+    /// Reads manifest of the store whose buckets are in `buckets_dir`, `None` if there is none
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the manifest could not be read or decoded
+    pub(crate) async fn read(buckets_dir: impl AsRef<Path>) -> Result<Option<Self>, Error> {
+        let path = Self::path(buckets_dir);
+        let buf = match fs::read(&path).await {
+            Ok(buf) => buf,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(FileOpen { path, error: err }),
+        };
+        serde_json::from_slice(&buf)
+            .map(Some)
+            .map_err(|err| BucketManifestCorrupt { path, error: err })
+    }
+
+    /// Writes manifest of the store whose buckets are in `buckets_dir`, replacing the previous one
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case of IO error
+    pub(crate) async fn write(&self, buckets_dir: impl AsRef<Path>) -> Result<(), Error> {
+        let buf = serde_json::to_vec(self).map_err(|_| Serialization("bucket manifest"))?;
+        FileNode::write_atomic(Self::path(buckets_dir), &buf).await
+    }
+}
@@ -1,7 +1,12 @@
 pub(crate) mod bucket_manager;
+pub(crate) mod manifest;
+mod pin;
 pub use bucket_manager::Bucket;
 pub use bucket_manager::BucketID;
 pub use bucket_manager::BucketMap;
 pub use bucket_manager::ImbalancedBuckets;
 pub use bucket_manager::InsertableToBucket;
 pub use bucket_manager::SSTablesToRemove;
+pub use manifest::LayoutIssue;
+pub use pin::FilePin;
+pub(crate) use pin::FilePins;
@@ -0,0 +1,173 @@
+use crate::err::Error::DirDelete;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Removal deferred until every [`FilePin`] is dropped
+#[derive(Debug)]
+enum Deferred {
+    /// Directory and everything in it
+    Dir(PathBuf),
+
+    /// Directory, only if nothing is left in it
+    EmptyDir(PathBuf),
+}
+
+#[derive(Debug, Default)]
+struct PinState {
+    pins: usize,
+    deferred: Vec<Deferred>,
+}
+
+/// Tracks pins on sstable and value log files shared by clones of a `BucketMap` and by GC
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FilePins {
+    state: Arc<Mutex<PinState>>,
+}
+
+impl FilePins {
+    /// Pins files, removals requested while pinned are deferred
+    pub(crate) fn pin(&self) -> FilePin {
+        self.state.lock().unwrap().pins += 1;
+        FilePin { pins: self.clone() }
+    }
+
+    /// Returns true if any pin is held
+    pub(crate) fn is_pinned(&self) -> bool {
+        self.state.lock().unwrap().pins > 0
+    }
+
+    /// Removes `dir` with its content, or defers it if pinned
+    pub(crate) async fn remove_dir_all(&self, dir: &Path) -> Result<(), std::io::Error> {
+        if self.defer(Deferred::Dir(dir.to_path_buf())) {
+            return Ok(());
+        }
+        tokio::fs::remove_dir_all(dir).await
+    }
+
+    /// Removes `dir` if empty, or defers it if pinned
+    pub(crate) async fn remove_empty_dir(&self, dir: &Path) {
+        if !self.defer(Deferred::EmptyDir(dir.to_path_buf())) {
+            let _ = tokio::fs::remove_dir(dir).await;
+        }
+    }
+
+    fn defer(&self, removal: Deferred) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.pins == 0 {
+            return false;
+        }
+        state.deferred.push(removal);
+        true
+    }
+}
+
+/// Defers physical deletion of sstable files and value log space while held
+///
+/// Files that compaction makes obsolete are removed once the last
+/// pin of the store is dropped, in the background when it is dropped
+/// inside a tokio runtime. Value log space freed by garbage
+/// collection is punched on the first GC sync after that.
+#[derive(Debug)]
+pub struct FilePin {
+    pins: FilePins,
+}
+
+impl Drop for FilePin {
+    fn drop(&mut self) {
+        let deferred = {
+            let mut state = self.pins.state.lock().unwrap();
+            state.pins -= 1;
+            if state.pins > 0 {
+                return;
+            }
+            std::mem::take(&mut state.deferred)
+        };
+        if deferred.is_empty() {
+            return;
+        }
+        // pins are dropped in async tasks, removal must not block their worker thread,
+        // outside a runtime it runs in place
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn_blocking(move || Deferred::remove_all(deferred));
+            }
+            Err(_) => Deferred::remove_all(deferred),
+        }
+    }
+}
+
+impl Deferred {
+    /// Removes deferred files in order, directories before the parents they may empty
+    fn remove_all(deferred: Vec<Deferred>) {
+        for removal in deferred {
+            match removal {
+                Deferred::Dir(dir) => {
+                    if dir.exists() {
+                        if let Err(error) = std::fs::remove_dir_all(&dir) {
+                            log::error!("{}", DirDelete { path: dir, error });
+                        }
+                    }
+                }
+                Deferred::EmptyDir(dir) => {
+                    let _ = std::fs::remove_dir(dir);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_removal_deferred_until_last_pin_dropped() {
+        let root = tempdir().unwrap();
+        let dir = root.path().join("sstable");
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        let pins = FilePins::default();
+
+        let first = pins.pin();
+        let second = pins.pin();
+        pins.remove_dir_all(&dir).await.unwrap();
+        pins.remove_empty_dir(root.path()).await;
+        assert!(dir.exists());
+
+        drop(first);
+        assert!(dir.exists());
+        drop(second);
+        assert_eq!(pins.state.lock().unwrap().pins, 0);
+        // removal runs on the blocking pool
+        for _ in 0..100 {
+            if !root.path().exists() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(!dir.exists());
+        assert!(!root.path().exists());
+    }
+
+    #[test]
+    fn test_removal_outside_runtime_on_last_pin_dropped() {
+        let root = tempdir().unwrap();
+        let dir = root.path().join("sstable");
+        std::fs::create_dir_all(&dir).unwrap();
+        let pins = FilePins::default();
+        let pin = pins.pin();
+        pins.defer(Deferred::Dir(dir.to_owned()));
+        assert!(dir.exists());
+        drop(pin);
+        assert!(!dir.exists());
+    }
+
+    #[tokio::test]
+    async fn test_removal_without_pin() {
+        let root = tempdir().unwrap();
+        let dir = root.path().join("sstable");
+        std::fs::create_dir_all(&dir).unwrap();
+        FilePins::default().remove_dir_all(&dir).await.unwrap();
+        assert!(!dir.exists());
+    }
+}
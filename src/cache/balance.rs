@@ -0,0 +1,116 @@
+use super::CacheStats;
+use crate::block::BlockCache;
+use crate::consts::{CACHE_REBALANCE_MIN_SHARE, CACHE_REBALANCE_STEP};
+use crate::env::Timer;
+use crate::filter::FilterCache;
+
+/// Moves capacity between a block cache and a filter cache, keeping their total fixed
+///
+/// After each interval the cache that missed more, and had to evict entries to
+/// make room, gets `CACHE_REBALANCE_STEP` of the total capacity from the other
+/// one. Neither cache is shrunk below `CACHE_REBALANCE_MIN_SHARE` of the total.
+#[derive(Debug)]
+pub(crate) struct CacheBalancer {
+    block_cache: BlockCache,
+    filter_cache: FilterCache,
+
+    /// Stats of block and filter cache at the previous rebalance
+    last: (CacheStats, CacheStats),
+}
+
+impl CacheBalancer {
+    /// Creates new `CacheBalancer` of `block_cache` and `filter_cache`
+    pub(crate) fn new(block_cache: BlockCache, filter_cache: FilterCache) -> Self {
+        let last = (block_cache.stats(), filter_cache.stats());
+        Self {
+            block_cache,
+            filter_cache,
+            last,
+        }
+    }
+
+    /// Rebalances the caches on every tick of `timer`
+    pub(crate) fn spawn(mut self, timer: Timer) {
+        tokio::spawn(async move {
+            loop {
+                timer.tick().await;
+                self.rebalance();
+            }
+        });
+    }
+
+    /// Moves capacity to the cache that missed more since the previous call
+    pub(crate) fn rebalance(&mut self) {
+        let (block, filter) = (self.block_cache.stats(), self.filter_cache.stats());
+        let (last_block, last_filter) = std::mem::replace(&mut self.last, (block, filter));
+        let pressure = |now: &CacheStats, last: &CacheStats| {
+            if now.evictions > last.evictions {
+                now.misses.saturating_sub(last.misses)
+            } else {
+                0
+            }
+        };
+        let (block_pressure, filter_pressure) =
+            (pressure(&block, &last_block), pressure(&filter, &last_filter));
+        if block_pressure == filter_pressure {
+            return;
+        }
+
+        let total = block.capacity + filter.capacity;
+        let min = (total as f64 * CACHE_REBALANCE_MIN_SHARE) as usize;
+        let step = (total as f64 * CACHE_REBALANCE_STEP) as usize;
+        if block_pressure > filter_pressure {
+            let moved = step.min(filter.capacity.saturating_sub(min));
+            self.filter_cache.set_capacity(filter.capacity - moved);
+            self.block_cache.set_capacity(block.capacity + moved);
+        } else {
+            let moved = step.min(block.capacity.saturating_sub(min));
+            self.block_cache.set_capacity(block.capacity - moved);
+            self.filter_cache.set_capacity(filter.capacity + moved);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_rebalance_grows_cache_under_pressure() {
+        let block_cache = BlockCache::new(1000);
+        let filter_cache = FilterCache::new(1000);
+        let mut balancer = CacheBalancer::new(block_cache.clone(), filter_cache.clone());
+
+        // no misses, nothing moves
+        balancer.rebalance();
+        assert_eq!((block_cache.capacity(), filter_cache.capacity()), (1000, 1000));
+
+        // block cache misses and evicts
+        for offset in 0..5 {
+            assert!(block_cache.get("data.db", offset).is_none());
+            block_cache.insert("data.db", offset, Arc::new(Vec::new()), 400);
+        }
+        balancer.rebalance();
+        assert!(block_cache.capacity() > 1000);
+        assert_eq!(block_cache.capacity() + filter_cache.capacity(), 2000);
+    }
+
+    #[test]
+    fn test_rebalance_keeps_min_share() {
+        let block_cache = BlockCache::new(1000);
+        let filter_cache = FilterCache::new(1000);
+        let mut balancer = CacheBalancer::new(block_cache.clone(), filter_cache.clone());
+        for round in 0..100 {
+            for offset in 0..5 {
+                let offset = round * 5 + offset;
+                assert!(block_cache.get("data.db", offset).is_none());
+                block_cache.insert("data.db", offset, Arc::new(Vec::new()), 400);
+            }
+            balancer.rebalance();
+        }
+        let min = (2000.0 * CACHE_REBALANCE_MIN_SHARE) as usize;
+        assert_eq!(filter_cache.capacity(), min);
+        assert_eq!(block_cache.capacity(), 2000 - min);
+    }
+}
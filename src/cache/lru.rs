@@ -0,0 +1,337 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    hash::Hash,
+    sync::Mutex,
+};
+
+/// Counters and size of a cache, returned by `stats` of [`BlockCache`](crate::db::BlockCache)
+/// and [`FilterCache`](crate::db::FilterCache)
+///
+/// Counters are cumulative since the cache was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups that found their entry
+    pub hits: u64,
+
+    /// Lookups that did not find their entry
+    pub misses: u64,
+
+    /// Entries evicted to make room for new ones or after the capacity was lowered
+    pub evictions: u64,
+
+    /// Bytes currently held
+    pub usage: usize,
+
+    /// Maximum bytes that can be held
+    pub capacity: usize,
+}
+
+impl CacheStats {
+    /// Returns fraction of lookups that found their entry, `0.0` if there were none
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / lookups as f64
+    }
+}
+
+/// Thread-safe least recently used cache bounded by a byte budget
+///
+/// Every entry is inserted with a `charge` (its approximate size in bytes),
+/// entries that were used least recently are evicted once the sum of charges
+/// exceeds `capacity`
+#[derive(Debug)]
+pub(crate) struct LruCache<K, V> {
+    inner: Mutex<LruInner<K, V>>,
+}
+
+#[derive(Debug)]
+struct LruInner<K, V> {
+    /// Maps key to value, charge and last access tick
+    entries: HashMap<K, (V, usize, u64)>,
+
+    /// Maps last access tick to key, first entry is the least recently used
+    order: BTreeMap<u64, K>,
+
+    /// Sum of charges of all entries
+    usage: usize,
+
+    /// Maximum sum of charges
+    capacity: usize,
+
+    /// Increases on every access
+    tick: u64,
+
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl<K, V> LruInner<K, V>
+where
+    K: Hash + Eq,
+{
+    /// Evicts least recently used entries until `charge` more bytes fit
+    fn make_room(&mut self, charge: usize) {
+        while self.usage + charge > self.capacity {
+            match self.order.pop_first() {
+                Some((_, evicted)) => {
+                    if let Some((_, evicted_charge, _)) = self.entries.remove(&evicted) {
+                        self.usage -= evicted_charge;
+                        self.evictions += 1;
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+impl<K, V> LruCache<K, V>
+where
+    K: Hash + Eq + Clone + Debug,
+    V: Clone + Debug,
+{
+    /// Creates new `LruCache` that holds at most `capacity` bytes
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(LruInner {
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                usage: 0,
+                capacity,
+                tick: 0,
+                hits: 0,
+                misses: 0,
+                evictions: 0,
+            }),
+        }
+    }
+
+    /// Returns value for `key` and marks it as most recently used
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut inner = self.inner.lock().expect("Failed to lock cache");
+        inner.tick += 1;
+        let tick = inner.tick;
+        let (val, old_tick) = match inner.entries.get_mut(key) {
+            Some(entry) => {
+                let old_tick = entry.2;
+                entry.2 = tick;
+                (entry.0.clone(), old_tick)
+            }
+            None => {
+                inner.misses += 1;
+                return None;
+            }
+        };
+        inner.hits += 1;
+        inner.order.remove(&old_tick);
+        inner.order.insert(tick, key.to_owned());
+        Some(val)
+    }
+
+    /// Inserts `val` for `key`, evicting least recently used entries if needed
+    ///
+    /// Entries bigger than the cache capacity are not inserted
+    pub fn insert(&self, key: K, val: V, charge: usize) {
+        let mut inner = self.inner.lock().expect("Failed to lock cache");
+        if charge > inner.capacity {
+            return;
+        }
+        inner.tick += 1;
+        let tick = inner.tick;
+        if let Some((_, old_charge, old_tick)) = inner.entries.remove(&key) {
+            inner.order.remove(&old_tick);
+            inner.usage -= old_charge;
+        }
+        inner.make_room(charge);
+        inner.order.insert(tick, key.to_owned());
+        inner.entries.insert(key, (val, charge, tick));
+        inner.usage += charge;
+    }
+
+    /// Removes entry for `key` and returns its value
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let mut inner = self.inner.lock().expect("Failed to lock cache");
+        let (val, charge, tick) = inner.entries.remove(key)?;
+        inner.order.remove(&tick);
+        inner.usage -= charge;
+        Some(val)
+    }
+
+    /// Removes entries whose key matches `matches`
+    pub fn remove_by(&self, matches: impl Fn(&K) -> bool) {
+        let mut inner = self.inner.lock().expect("Failed to lock cache");
+        let keys: Vec<K> = inner.entries.keys().filter(|key| matches(key)).cloned().collect();
+        for key in keys {
+            if let Some((_, charge, tick)) = inner.entries.remove(&key) {
+                inner.order.remove(&tick);
+                inner.usage -= charge;
+            }
+        }
+    }
+
+    /// Returns sum of charges of cached entries
+    pub fn usage(&self) -> usize {
+        self.inner.lock().expect("Failed to lock cache").usage
+    }
+
+    /// Returns maximum sum of charges
+    pub fn capacity(&self) -> usize {
+        self.inner.lock().expect("Failed to lock cache").capacity
+    }
+
+    /// Changes maximum sum of charges, evicting least recently used entries that no longer fit
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut inner = self.inner.lock().expect("Failed to lock cache");
+        inner.capacity = capacity;
+        inner.make_room(0);
+    }
+
+    /// Returns counters and size of the cache
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().expect("Failed to lock cache");
+        CacheStats {
+            hits: inner.hits,
+            misses: inner.misses,
+            evictions: inner.evictions,
+            usage: inner.usage,
+            capacity: inner.capacity,
+        }
+    }
+
+    /// Returns sum of charges of cached entries grouped by `group` of their key
+    pub fn usage_by<G: Hash + Eq>(&self, group: impl Fn(&K) -> G) -> HashMap<G, usize> {
+        let inner = self.inner.lock().expect("Failed to lock cache");
+        let mut usage = HashMap::new();
+        for (key, (_, charge, _)) in inner.entries.iter() {
+            *usage.entry(group(key)).or_insert(0) += charge;
+        }
+        usage
+    }
+
+    /// Returns number of cached entries
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.inner.lock().expect("Failed to lock cache").entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_get() {
+        let cache: LruCache<u32, &str> = LruCache::new(10);
+        cache.insert(1, "one", 3);
+        cache.insert(2, "two", 3);
+        assert_eq!(cache.get(&1), Some("one"));
+        assert_eq!(cache.get(&2), Some("two"));
+        assert_eq!(cache.get(&3), None);
+        assert_eq!(cache.usage(), 6);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache: LruCache<u32, &str> = LruCache::new(9);
+        cache.insert(1, "one", 3);
+        cache.insert(2, "two", 3);
+        cache.insert(3, "three", 3);
+        // touch 1 so 2 becomes least recently used
+        assert!(cache.get(&1).is_some());
+        cache.insert(4, "four", 3);
+        assert!(cache.get(&2).is_none());
+        assert!(cache.get(&1).is_some());
+        assert!(cache.get(&3).is_some());
+        assert!(cache.get(&4).is_some());
+        assert_eq!(cache.usage(), 9);
+    }
+
+    #[test]
+    fn test_replace_updates_charge() {
+        let cache: LruCache<u32, &str> = LruCache::new(10);
+        cache.insert(1, "one", 3);
+        cache.insert(1, "uno", 5);
+        assert_eq!(cache.get(&1), Some("uno"));
+        assert_eq!(cache.usage(), 5);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_remove_releases_charge() {
+        let cache: LruCache<u32, &str> = LruCache::new(10);
+        cache.insert(1, "one", 3);
+        cache.insert(2, "two", 3);
+        assert_eq!(cache.remove(&1), Some("one"));
+        assert_eq!(cache.remove(&1), None);
+        assert!(cache.get(&1).is_none());
+        assert_eq!(cache.usage(), 3);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_remove_by_releases_charge() {
+        let cache: LruCache<u32, &str> = LruCache::new(10);
+        cache.insert(1, "one", 3);
+        cache.insert(2, "two", 3);
+        cache.insert(3, "three", 3);
+        cache.remove_by(|key| key % 2 == 1);
+        assert!(cache.get(&1).is_none());
+        assert!(cache.get(&3).is_none());
+        assert_eq!(cache.get(&2), Some("two"));
+        assert_eq!(cache.usage(), 3);
+        assert_eq!(cache.stats().evictions, 0);
+    }
+
+    #[test]
+    fn test_oversized_entry_is_skipped() {
+        let cache: LruCache<u32, &str> = LruCache::new(4);
+        cache.insert(1, "one", 5);
+        assert!(cache.get(&1).is_none());
+        assert_eq!(cache.usage(), 0);
+    }
+
+    #[test]
+    fn test_stats_count_hits_misses_and_evictions() {
+        let cache: LruCache<u32, &str> = LruCache::new(6);
+        cache.insert(1, "one", 3);
+        cache.insert(2, "two", 3);
+        assert!(cache.get(&1).is_some());
+        assert!(cache.get(&3).is_none());
+        cache.insert(3, "three", 3);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 1, 1));
+        assert_eq!((stats.usage, stats.capacity), (6, 6));
+        assert_eq!(stats.hit_rate(), 0.5);
+        assert_eq!(CacheStats::default().hit_rate(), 0.0);
+    }
+
+    #[test]
+    fn test_set_capacity_evicts_entries_that_no_longer_fit() {
+        let cache: LruCache<u32, &str> = LruCache::new(9);
+        cache.insert(1, "one", 3);
+        cache.insert(2, "two", 3);
+        cache.insert(3, "three", 3);
+        cache.set_capacity(4);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get(&3).is_some());
+        assert_eq!(cache.stats().evictions, 2);
+        assert_eq!(cache.capacity(), 4);
+    }
+
+    #[test]
+    fn test_usage_by_groups_charges() {
+        let cache: LruCache<(char, u32), &str> = LruCache::new(20);
+        cache.insert(('a', 1), "one", 3);
+        cache.insert(('a', 2), "two", 4);
+        cache.insert(('b', 1), "three", 5);
+        let usage = cache.usage_by(|(group, _)| *group);
+        assert_eq!(usage[&'a'], 7);
+        assert_eq!(usage[&'b'], 5);
+    }
+}
@@ -0,0 +1,5 @@
+mod balance;
+mod lru;
+pub(crate) use balance::CacheBalancer;
+pub use lru::CacheStats;
+pub(crate) use lru::LruCache;
@@ -1,17 +1,28 @@
+use super::{OnProgress, OpenProgress};
+use crate::{
+    block::BlockCache,
+    db::{DataStore, SizeUnit},
+    env::Env,
+    filter::FilterCache,
+    fs::{ReadPool, RetryPolicy},
+    memtable::Val,
+    types::{CreatedAt, Key},
+};
 use crate::{
     compactors,
     consts::{
-        DEFAULT_ALLOW_PREFETCH, DEFAULT_COMPACTION_FLUSH_LISTNER_INTERVAL, DEFAULT_COMPACTION_INTERVAL,
-        DEFAULT_ENABLE_TTL, DEFAULT_FALSE_POSITIVE_RATE, DEFAULT_MAX_WRITE_BUFFER_NUMBER,
-        DEFAULT_ONLINE_GC_INTERVAL, DEFAULT_PREFETCH_SIZE, DEFAULT_TOMBSTONE_COMPACTION_INTERVAL,
-        DEFAULT_TOMBSTONE_TTL, ENTRY_TTL, GC_CHUNK_SIZE, WRITE_BUFFER_SIZE,
+        DEFAULT_ALLOW_PREFETCH, DEFAULT_BACKGROUND_JITTER, DEFAULT_COLD_STORAGE_MAX_HOTNESS,
+        DEFAULT_COLD_STORAGE_MIN_AGE, DEFAULT_COMPACTION_FLUSH_LISTNER_INTERVAL, DEFAULT_COMPACTION_INTERVAL,
+        DEFAULT_ENABLE_TTL, DEFAULT_EXPIRY_BATCH_SIZE, DEFAULT_EXPIRY_INTERVAL, DEFAULT_FALSE_POSITIVE_RATE,
+        DEFAULT_GC_PRESSURE_MAX_CHUNK_SIZE, DEFAULT_LOCK_TIMEOUT, DEFAULT_MAX_KEY_SIZE,
+        DEFAULT_MAX_VALUE_SIZE, DEFAULT_MAX_WRITE_BUFFER_NUMBER, DEFAULT_MIN_FREE_DISK_SPACE,
+        DEFAULT_ONLINE_GC_INTERVAL, DEFAULT_PREFETCH_SIZE, DEFAULT_RETAINED_MEMTABLES,
+        DEFAULT_STATS_PERSIST_INTERVAL, DEFAULT_TOMBSTONE_COMPACTION_INTERVAL, DEFAULT_TOMBSTONE_TTL,
+        ENTRY_TTL, GC_CHUNK_SIZE, MAX_ENCODED_KEY_SIZE, MAX_VALUE_SIZE, WRITE_BUFFER_SIZE,
     },
 };
-use crate::{
-    db::{DataStore, SizeUnit},
-    types::Key,
-};
-use std::time::Duration;
+use chrono::Utc;
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
 
 #[derive(Clone, Debug)]
 /// Configuration for  data store.
@@ -20,6 +31,13 @@ pub struct Config {
     /// but it incurs extra cost on the CPU for more accuracy.
     pub false_positive_rate: f64,
 
+    /// False positive rates of keyspaces that should not use `false_positive_rate`,
+    /// so one `Config` can be shared between stores with different needs
+    pub keyspace_false_positive_rates: HashMap<String, f64>,
+
+    /// Limits on what each keyspace may hold, keyspaces not listed are unlimited
+    pub keyspace_quotas: HashMap<String, KeyspaceQuota>,
+
     /// Should we prefetch values in case of range queries?
     pub allow_prefetch: bool,
 
@@ -32,15 +50,33 @@ pub struct Config {
     /// How many memtables should we have
     pub max_buffer_write_number: usize,
 
+    /// How many flushed memtables to keep in memory once their SSTable is durable,
+    /// reads of recent data are then served without touching SSTables. None are kept by default
+    pub retained_memtables: usize,
+
     /// Should we delete entries that have exceeded their time to live (TTL)?
     pub enable_ttl: bool,
 
-    /// Time for an entry to exist before it is removed automatically.
+    /// Time for an entry to exist before it is removed automatically. Compaction
+    /// drops the entry once `tombstone_ttl` has passed after its expiry too, as it
+    /// does for entries written with [`DataStore::put_with_ttl`]
     pub entry_ttl: std::time::Duration,
 
-    /// Time for a tombstone to exist before it is removed automatically
+    /// Time for a tombstone to exist before it is removed automatically,
+    /// also how long a soft deleted entry can be restored
     pub tombstone_ttl: std::time::Duration,
 
+    /// Interval at which the expiry job deletes keys whose TTL has passed,
+    /// see [`DataStore::spawn_expiry_job`]
+    pub expiry_interval: std::time::Duration,
+
+    /// Most keys the expiry job deletes per interval, the store is locked for writes meanwhile
+    pub expiry_batch_size: usize,
+
+    /// Time a transaction waits for a lock held by another transaction before
+    /// failing with `Error::LockTimeout`
+    pub lock_timeout: std::time::Duration,
+
     /// Interval at which compaction checks if a flush has been made
     pub compactor_flush_listener_interval: std::time::Duration,
 
@@ -53,14 +89,258 @@ pub struct Config {
     /// Which compaction strategy is used STCS, LCS, TCS or UCS
     pub compaction_strategy: compactors::Strategy,
 
+    /// Picks sstables to merge instead of `compaction_strategy`, if set
+    pub compaction_policy: Option<Arc<dyn compactors::CompactionPolicy>>,
+
     /// Interval at which tombstone compaction is triggered
     pub online_gc_interval: std::time::Duration,
 
+    /// Fraction of its interval, between 0 and 1, by which each wait of a
+    /// background compaction or garbage collection loop is randomly moved.
+    /// Keeps stores opened together from running their loops in lockstep
+    pub background_jitter: f64,
+
     /// How many bytes should be checked in value log for garbage collection in kilobytes
     pub gc_chunk_size: usize,
 
+    /// Grows `gc_chunk_size` when free space on the value log file system runs low,
+    /// so garbage collection reclaims more per pass before writes fail. Disabled by default
+    pub gc_disk_pressure: Option<GcDiskPressure>,
+
     /// Maximum number of files that can be opened at once
     pub open_files_limit: usize,
+
+    /// Scheduler that limits concurrent background jobs, share one `Env`
+    /// between stores to apply the limits process-wide
+    pub env: Env,
+
+    /// Directory for the value log, defaults to a `v_log` directory under the
+    /// store root. Setting it allows keeping the append-heavy value log on a
+    /// different disk than SSTables. Each store needs its own directory and
+    /// it must not change between restarts
+    pub vlog_dir: Option<PathBuf>,
+
+    /// Reserve value log disk space in extents of this many bytes (fallocate on Linux),
+    /// so appends don't repeatedly extend the file. Disabled by default
+    pub vlog_preallocation_extent: Option<usize>,
+
+    /// Rotate the active memtable once this many bytes were appended to the value log
+    /// since the last rotation, even if the memtable is not full. Bounds the value log
+    /// replayed by recovery for workloads with small keys and large values. Disabled by default
+    pub max_unflushed_vlog_size: Option<usize>,
+
+    /// Store values of at least this many bytes only once in the value log, writes of an
+    /// identical value append a reference to it instead. Suits workloads with heavily
+    /// duplicated values, like document snapshots. Disabled by default. Once values were
+    /// deduplicated it must stay enabled, references can't be read without it.
+    /// Must be at least 64 bytes
+    pub dedup_min_value_size: Option<usize>,
+
+    /// Write values of at most this many bytes to a small write-ahead log instead
+    /// of the value log. Each write is synced before it returns, batches are
+    /// synced once. Entries move to the value log when the memtable is rotated, so
+    /// tiny-value workloads don't inflate the value log garbage collection scans.
+    /// Disabled by default
+    pub wal_max_value_size: Option<usize>,
+
+    /// Retries of value log appends and SSTable reads failing with transient IO
+    /// errors, such as interrupted or timed-out calls on network file systems.
+    /// Errors are returned once retries are exhausted, syncs are never retried
+    pub io_retry: RetryPolicy,
+
+    /// Threads for value log and SSTable reads of the store, kept apart from the
+    /// threads flushes and compactions write on, so reads don't queue behind them
+    /// under heavy writes. Can be shared between stores. Disabled by default,
+    /// reads then run on the runtime of the caller
+    pub read_pool: Option<ReadPool>,
+
+    /// Free bytes below which [`DataStore::health`] reports the store read-only,
+    /// checked on the file systems of the store and of the value log.
+    /// `None` disables the check
+    pub min_free_disk_space: Option<u64>,
+
+    /// Cache for SSTable data blocks, share one `BlockCache` between
+    /// stores to keep them within a single memory budget
+    pub block_cache: BlockCache,
+
+    /// Cache for SSTable bloom filters, filters of cold tables are evicted
+    /// to stay within its budget. Can be shared between stores like `block_cache`
+    pub filter_cache: FilterCache,
+
+    /// Interval at which capacity is moved between `block_cache` and `filter_cache`,
+    /// towards the one that missed more. Their combined capacity stays the same.
+    /// When the caches are shared, set it on one store only. Disabled by default
+    pub cache_rebalance_interval: Option<std::time::Duration>,
+
+    /// Interval at which cumulative statistics are written to the stats file of
+    /// the store, see [`DataStore::stats`]. They are written at open regardless
+    pub stats_persist_interval: Option<std::time::Duration>,
+
+    /// Secondary directory for cold SSTables, disabled by default
+    pub cold_storage: Option<ColdStorage>,
+
+    /// Bytes of bloom filter memory shared by all SSTables. When set, each flushed
+    /// or merged SSTable gets a false positive rate proportional to its number of
+    /// entries (Monkey), so small tables get more bits per key than big ones.
+    /// Disabled by default, `false_positive_rate` is then used for every filter
+    pub filter_memory_budget: Option<usize>,
+
+    /// Run CPU heavy work of flush and compaction (filter construction, block
+    /// encoding) on tokio's blocking thread pool, keeping the executor responsive.
+    /// Enabled by default
+    pub offload_cpu_work: bool,
+
+    /// Called with progress of each recovery phase while the store is opened
+    pub on_progress: Option<OnProgress>,
+
+    /// Time recovery may spend checking SSTable files. SSTables reached after it
+    /// ran out are opened lazily, their index and filter are read on first lookup.
+    /// Unlimited by default
+    pub open_time_budget: Option<Duration>,
+
+    /// Paranoid mode, stores a checksum of key and value with every entry
+    /// and verifies it on every read, catching corruption anywhere between
+    /// the value log, SSTables and caches at the cost of extra CPU
+    pub verify_reads: bool,
+
+    /// Largest key in bytes accepted by writes, bigger keys are rejected
+    /// with `Error::KeyTooLarge`. Can not exceed `u32::MAX`
+    pub max_key_size: usize,
+
+    /// Largest value in bytes accepted by writes, bigger values are rejected
+    /// with `Error::ValueTooLarge`. Can not exceed `u32::MAX`
+    pub max_value_size: usize,
+}
+
+/// Placement of rarely written SSTables in a secondary directory
+///
+/// During compaction, a merged SSTable whose source bucket had a hotness of at most
+/// `max_hotness` and whose newest source SSTable is at least `min_age` old is written
+/// under `dir` instead of the store root. Reads are served from either directory.
+/// Like `vlog_dir`, `dir` must not change between restarts
+#[derive(Clone, Debug)]
+pub struct ColdStorage {
+    /// Directory for cold SSTables, e.g. on a slower but cheaper disk
+    pub dir: PathBuf,
+
+    /// Highest bucket hotness that is still considered cold
+    pub max_hotness: u64,
+
+    /// Minimum time since the newest merged SSTable was written
+    pub min_age: Duration,
+}
+
+/// Scaling of `gc_chunk_size` with free space on the value log file system
+///
+/// Free bytes are read before every garbage collection pass. Below
+/// `low_free_space` the chunk grows with the missing space, linearly from
+/// `gc_chunk_size` up to `max_chunk_size` at `critical_free_space`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GcDiskPressure {
+    /// Free bytes below which chunks grow
+    pub low_free_space: u64,
+
+    /// Free bytes at which chunks reach `max_chunk_size`
+    pub critical_free_space: u64,
+
+    /// Largest chunk in bytes
+    pub max_chunk_size: usize,
+}
+
+/// Limits on what a keyspace may hold, see `Config::keyspace_quotas`
+///
+/// Writes that would take [`KeyspaceStats`](crate::db::KeyspaceStats) of the
+/// keyspace past a limit fail with `Error::QuotaExceeded`. Overwriting a live
+/// key adds no key, and deletes are always accepted, so a keyspace over its
+/// quota can shrink
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyspaceQuota {
+    /// Maximum bytes of value log entries
+    pub max_bytes: Option<usize>,
+
+    /// Maximum number of keys
+    pub max_keys: Option<usize>,
+}
+
+impl Config {
+    /// Sets `on_progress` to `callback`
+    pub fn on_progress(mut self, callback: impl Fn(OpenProgress) + Send + Sync + 'static) -> Self {
+        self.on_progress = Some(OnProgress::new(callback));
+        self
+    }
+
+    /// Sets `compaction_policy` to `policy`
+    pub fn compaction_policy(mut self, policy: impl compactors::CompactionPolicy + 'static) -> Self {
+        self.compaction_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Returns false positive rate used by `keyspace`
+    pub fn false_positive_rate_for(&self, keyspace: &str) -> f64 {
+        self.keyspace_false_positive_rates
+            .get(keyspace)
+            .copied()
+            .unwrap_or(self.false_positive_rate)
+    }
+
+    /// Returns quota of `keyspace`, if it has one
+    pub fn quota_for(&self, keyspace: &str) -> Option<&KeyspaceQuota> {
+        self.keyspace_quotas.get(keyspace)
+    }
+}
+
+impl ColdStorage {
+    /// Creates `ColdStorage` in `dir` with default thresholds
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_hotness: DEFAULT_COLD_STORAGE_MAX_HOTNESS,
+            min_age: DEFAULT_COLD_STORAGE_MIN_AGE,
+        }
+    }
+
+    /// Returns true if a table with `hotness` whose newest data was written at
+    /// `created_at` belongs in cold storage
+    pub(crate) fn is_cold(&self, hotness: u64, created_at: CreatedAt) -> bool {
+        let age = Utc::now()
+            .signed_duration_since(created_at)
+            .to_std()
+            .unwrap_or_default();
+        hotness <= self.max_hotness && age >= self.min_age
+    }
+}
+
+impl GcDiskPressure {
+    /// Creates `GcDiskPressure` growing chunks below `low_free_space` free bytes,
+    /// up to the default maximum at `critical_free_space`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `critical_free_space` is not below `low_free_space`
+    pub fn new(low_free_space: u64, critical_free_space: u64) -> Self {
+        assert!(
+            critical_free_space < low_free_space,
+            "critical_free_space should be below low_free_space"
+        );
+        Self {
+            low_free_space,
+            critical_free_space,
+            max_chunk_size: DEFAULT_GC_PRESSURE_MAX_CHUNK_SIZE,
+        }
+    }
+
+    /// Returns chunk size for `available` free bytes, `chunk_size` if space is not low
+    pub(crate) fn scale(&self, chunk_size: usize, available: u64) -> usize {
+        if available >= self.low_free_space || self.max_chunk_size <= chunk_size {
+            return chunk_size;
+        }
+        if available <= self.critical_free_space {
+            return self.max_chunk_size;
+        }
+        let missing = (self.low_free_space - available) as f64;
+        let span = (self.low_free_space - self.critical_free_space) as f64;
+        chunk_size + ((self.max_chunk_size - chunk_size) as f64 * missing / span) as usize
+    }
 }
 
 fn get_open_file_limit() -> usize {
@@ -78,30 +358,91 @@ impl Default for Config {
     fn default() -> Self {
         Config {
             false_positive_rate: DEFAULT_FALSE_POSITIVE_RATE,
+            keyspace_false_positive_rates: HashMap::new(),
+            keyspace_quotas: HashMap::new(),
             enable_ttl: DEFAULT_ENABLE_TTL,
             entry_ttl: ENTRY_TTL,
             allow_prefetch: DEFAULT_ALLOW_PREFETCH,
             prefetch_size: DEFAULT_PREFETCH_SIZE,
             max_buffer_write_number: DEFAULT_MAX_WRITE_BUFFER_NUMBER,
+            retained_memtables: DEFAULT_RETAINED_MEMTABLES,
             write_buffer_size: WRITE_BUFFER_SIZE,
             compactor_flush_listener_interval: DEFAULT_COMPACTION_FLUSH_LISTNER_INTERVAL,
             background_compaction_interval: DEFAULT_COMPACTION_INTERVAL,
             tombstone_ttl: DEFAULT_TOMBSTONE_TTL,
+            expiry_interval: DEFAULT_EXPIRY_INTERVAL,
+            expiry_batch_size: DEFAULT_EXPIRY_BATCH_SIZE,
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
             tombstone_compaction_interval: DEFAULT_TOMBSTONE_COMPACTION_INTERVAL,
             compaction_strategy: compactors::Strategy::STCS,
+            compaction_policy: None,
             online_gc_interval: DEFAULT_ONLINE_GC_INTERVAL,
+            background_jitter: DEFAULT_BACKGROUND_JITTER,
             gc_chunk_size: GC_CHUNK_SIZE,
+            gc_disk_pressure: None,
             open_files_limit: get_open_file_limit(),
+            env: Env::default(),
+            vlog_dir: None,
+            vlog_preallocation_extent: None,
+            max_unflushed_vlog_size: None,
+            dedup_min_value_size: None,
+            wal_max_value_size: None,
+            io_retry: RetryPolicy::default(),
+            read_pool: None,
+            min_free_disk_space: Some(DEFAULT_MIN_FREE_DISK_SPACE),
+            block_cache: BlockCache::default(),
+            filter_cache: FilterCache::default(),
+            cache_rebalance_interval: None,
+            stats_persist_interval: Some(DEFAULT_STATS_PERSIST_INTERVAL),
+            cold_storage: None,
+            filter_memory_budget: None,
+            offload_cpu_work: true,
+            on_progress: None,
+            open_time_budget: None,
+            verify_reads: false,
+            max_key_size: DEFAULT_MAX_KEY_SIZE,
+            max_value_size: DEFAULT_MAX_VALUE_SIZE,
         }
     }
 }
 
-impl DataStore<'static, Key> {
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Returns configuration the store runs with
+    ///
+    /// Holds defaults, the config the store was opened with and changes made by
+    /// the `with_*` methods since, e.g. to verify what a running instance uses.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let store = DataStore::open("big_tech", root.path().join("store"))
+    ///         .await
+    ///         .unwrap()
+    ///         .with_online_gc_interval(Duration::from_secs(2 * 60 * 60));
+    ///
+    ///     assert_eq!(store.config().online_gc_interval, Duration::from_secs(2 * 60 * 60));
+    ///     assert!(store.config().allow_prefetch);
+    /// }
+    /// ```
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
     /// Sets the false positive rate for the DataStore.
     /// The rate must be greater than 0.0.
+    /// Applies to memtables created and sstables merged from now on.
     pub fn with_false_positive_rate(mut self, rate: f64) -> Self {
         assert!(rate > 0.0, "false_positive_rate must be greater than 0.0");
         self.config.false_positive_rate = rate;
+        self.active_memtable.config.false_pos_rate = rate;
+        self.compactor.config.filter_false_positive = rate;
         self
     }
 
@@ -141,6 +482,14 @@ impl DataStore<'static, Key> {
         self
     }
 
+    /// Sets how many flushed memtables are kept in memory as a read cache.
+    /// Zero keeps none.
+    pub fn with_retained_memtables(mut self, number: usize) -> Self {
+        self.config.retained_memtables = number;
+        self.flusher.retained_limit = number;
+        self
+    }
+
     /// Enables or disables TTL (Time-To-Live) for entries.
     pub fn with_enable_ttl(mut self, enable: bool) -> Self {
         self.config.enable_ttl = enable;
@@ -166,10 +515,34 @@ impl DataStore<'static, Key> {
             "tombstone_ttl should not be less than 10 days to prevent resurrecting entries marked deleted"
         );
         self.config.tombstone_ttl = ttl;
+        self.gc.config.tombstone_ttl = ttl;
         self
     }
 
-    /// Sets the interval for compactor flush listener.
+    /// Sets the interval of the expiry job, also for a running job, see [`DataStore::spawn_expiry_job`]
+    pub fn with_expiry_interval(mut self, interval: std::time::Duration) -> Self {
+        self.config.expiry_interval = interval;
+        self
+    }
+
+    /// Sets the most keys the expiry job deletes per interval
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero
+    pub fn with_expiry_batch_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "expiry_batch_size should be at least 1");
+        self.config.expiry_batch_size = size;
+        self
+    }
+
+    /// Sets how long transactions begun from now on wait for a lock, see [`DataStore::begin_transaction`]
+    pub fn with_lock_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.config.lock_timeout = timeout;
+        self
+    }
+
+    /// Sets the interval for compactor flush listener, also for a running listener.
     /// The interval must be at least 2 minutes to prevent overloading the system.
     pub fn with_compactor_flush_listener_interval(mut self, interval: std::time::Duration) -> Self {
         assert!(
@@ -177,10 +550,14 @@ impl DataStore<'static, Key> {
             "compactor_flush_listener_interval should not be less than 2 minutes, to prevent overloading the system"
         );
         self.config.compactor_flush_listener_interval = interval;
+        self.compactor
+            .config
+            .flush_listener_interval
+            .set_interval(interval);
         self
     }
 
-    /// Sets the interval for background compaction.
+    /// Sets the interval for background compaction, also for a running compaction worker.
     /// The interval must be at least 5 minutes to prevent overloads.
     pub fn with_background_compaction_interval(mut self, interval: std::time::Duration) -> Self {
         assert!(
@@ -188,6 +565,7 @@ impl DataStore<'static, Key> {
             "background_compaction_interval should not be less than 5 minutes to prevent overloads"
         );
         self.config.background_compaction_interval = interval;
+        self.compactor.config.background_interval.set_interval(interval);
         self
     }
 
@@ -199,6 +577,10 @@ impl DataStore<'static, Key> {
             "tombstone_compaction_interval should not be less than 10 days"
         );
         self.config.tombstone_compaction_interval = interval;
+        self.compactor
+            .config
+            .tombstone_compaction_interval
+            .set_interval(interval);
         self
     }
 
@@ -208,7 +590,7 @@ impl DataStore<'static, Key> {
         self
     }
 
-    /// Sets the interval for online garbage collection.
+    /// Sets the interval for online garbage collection, also for a running garbage collector.
     /// The interval must be at least 1 hour.
     pub fn with_online_gc_interval(mut self, interval: std::time::Duration) -> Self {
         assert!(
@@ -216,6 +598,7 @@ impl DataStore<'static, Key> {
             "online_gc_interval should not be less than 1 hour"
         );
         self.config.online_gc_interval = interval;
+        self.gc.config.online_gc_interval.set_interval(interval);
         self
     }
 
@@ -227,6 +610,46 @@ impl DataStore<'static, Key> {
         self.config.gc_chunk_size = SizeUnit::Kilobytes.as_bytes(size);
         self
     }
+
+    /// Sets scaling of the garbage collection chunk with free disk space, also for a running garbage collector
+    pub fn with_gc_disk_pressure(mut self, pressure: Option<GcDiskPressure>) -> Self {
+        self.config.gc_disk_pressure = pressure.clone();
+        self.gc.set_disk_pressure(pressure);
+        self
+    }
+
+    /// Sets the value log growth in kilobytes after which the active memtable is rotated.
+    /// The size must be at least 50 kilobytes.
+    pub fn with_max_unflushed_vlog_size(mut self, size: usize) -> Self {
+        assert!(
+            size >= 50,
+            "max_unflushed_vlog_size should not be less than 50 Kilobytes"
+        );
+        self.config.max_unflushed_vlog_size = Some(SizeUnit::Kilobytes.as_bytes(size));
+        self
+    }
+
+    /// Sets the largest key size in bytes accepted by writes.
+    /// The size must be greater than 0 and not exceed `u32::MAX`.
+    pub fn with_max_key_size(mut self, size: usize) -> Self {
+        assert!(
+            size > 0 && size <= MAX_ENCODED_KEY_SIZE,
+            "max_key_size should be between 1 and u32::MAX"
+        );
+        self.config.max_key_size = size;
+        self
+    }
+
+    /// Sets the largest value size in bytes accepted by writes.
+    /// The size must be greater than 0 and not exceed `u32::MAX`.
+    pub fn with_max_value_size(mut self, size: usize) -> Self {
+        assert!(
+            size > 0 && size <= MAX_VALUE_SIZE,
+            "max_value_size should be between 1 and u32::MAX"
+        );
+        self.config.max_value_size = size;
+        self
+    }
 }
 
 #[cfg(test)]
@@ -245,25 +668,76 @@ mod tests {
         // Initialize with default or dummy values
         let config = Config {
             false_positive_rate: 0.01,
+            keyspace_false_positive_rates: HashMap::new(),
+            keyspace_quotas: HashMap::new(),
             allow_prefetch: false,
             prefetch_size: 0,
             write_buffer_size: 51200,
             max_buffer_write_number: 1,
+            retained_memtables: 0,
             enable_ttl: false,
             entry_ttl: Duration::from_secs(0),
             tombstone_ttl: Duration::from_secs(0),
+            expiry_interval: Duration::from_secs(0),
+            expiry_batch_size: 0,
+            lock_timeout: Duration::from_secs(0),
             compactor_flush_listener_interval: Duration::from_secs(0),
             background_compaction_interval: Duration::from_secs(0),
             tombstone_compaction_interval: Duration::from_secs(0),
             compaction_strategy: compactors::Strategy::STCS,
+            compaction_policy: None,
             online_gc_interval: Duration::from_secs(0),
+            background_jitter: 0.0,
             gc_chunk_size: 51200,
+            gc_disk_pressure: None,
             open_files_limit: 150,
+            env: Env::default(),
+            vlog_dir: None,
+            vlog_preallocation_extent: None,
+            max_unflushed_vlog_size: None,
+            dedup_min_value_size: None,
+            wal_max_value_size: None,
+            io_retry: RetryPolicy::default(),
+            read_pool: None,
+            min_free_disk_space: None,
+            block_cache: BlockCache::default(),
+            filter_cache: FilterCache::default(),
+            cache_rebalance_interval: None,
+            stats_persist_interval: None,
+            cold_storage: None,
+            filter_memory_budget: None,
+            offload_cpu_work: true,
+            on_progress: None,
+            open_time_budget: None,
+            verify_reads: false,
+            max_key_size: 65536,
+            max_value_size: 1024,
         };
         store.config = config;
         store
     }
 
+    #[tokio::test]
+    async fn test_config_reflects_changes() {
+        let root = tempdir().unwrap();
+        let config = Config {
+            max_key_size: 1024,
+            ..Default::default()
+        };
+        let store: DataStore<'static, Key> =
+            DataStore::open_with_config("test", root.path().join("store"), config)
+                .await
+                .unwrap()
+                .with_retained_memtables(4)
+                .with_max_buffer_write_number(3);
+
+        // overrides and later changes replace defaults
+        assert_eq!(store.config().max_key_size, 1024);
+        assert_eq!(store.config().retained_memtables, 4);
+        assert_eq!(store.config().max_buffer_write_number, 3);
+        assert_eq!(store.config().max_value_size, DEFAULT_MAX_VALUE_SIZE);
+    }
+
     #[tokio::test]
     #[should_panic(expected = "false_positive_rate must be greater than 0.0")]
     async fn test_with_false_positive_rate_invalid() {
@@ -276,6 +750,35 @@ mod tests {
         let ds = create_datastore().await;
         let ds = ds.with_false_positive_rate(0.05);
         assert_eq!(ds.config.false_positive_rate, 0.05);
+        assert_eq!(ds.active_memtable.false_positive_rate(), 0.05);
+        assert_eq!(ds.compactor.config.filter_false_positive, 0.05);
+    }
+
+    #[test]
+    fn test_false_positive_rate_for_keyspace() {
+        let mut config = Config {
+            false_positive_rate: 0.01,
+            ..Default::default()
+        };
+        config
+            .keyspace_false_positive_rates
+            .insert("logs".to_string(), 0.1);
+        assert_eq!(config.false_positive_rate_for("logs"), 0.1);
+        assert_eq!(config.false_positive_rate_for("users"), 0.01);
+    }
+
+    #[test]
+    fn test_quota_for_keyspace() {
+        let mut config = Config::default();
+        let quota = KeyspaceQuota {
+            max_keys: Some(10),
+            ..Default::default()
+        };
+        config
+            .keyspace_quotas
+            .insert("tenant_a".to_string(), quota.clone());
+        assert_eq!(config.quota_for("tenant_a"), Some(&quota));
+        assert_eq!(config.quota_for("tenant_b"), None);
     }
 
     #[tokio::test]
@@ -327,6 +830,14 @@ mod tests {
         assert_eq!(ds.config.max_buffer_write_number, 5);
     }
 
+    #[tokio::test]
+    async fn test_with_retained_memtables() {
+        let ds = create_datastore().await;
+        let ds = ds.with_retained_memtables(3);
+        assert_eq!(ds.config.retained_memtables, 3);
+        assert_eq!(ds.flusher.retained_limit, 3);
+    }
+
     #[tokio::test]
     async fn test_with_enable_ttl() {
         let ds = create_datastore().await;
@@ -402,6 +913,10 @@ mod tests {
             ds.config.background_compaction_interval,
             Duration::from_secs(6 * 60)
         );
+        assert_eq!(
+            ds.compactor.config.background_interval.interval(),
+            Duration::from_secs(6 * 60)
+        );
     }
 
     #[tokio::test]
@@ -442,6 +957,41 @@ mod tests {
         let ds = create_datastore().await;
         let ds = ds.with_online_gc_interval(Duration::from_secs(2 * 60 * 60)); // 2 hours
         assert_eq!(ds.config.online_gc_interval, Duration::from_secs(2 * 60 * 60));
+        assert_eq!(
+            ds.gc.config.online_gc_interval.interval(),
+            Duration::from_secs(2 * 60 * 60)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_with_gc_disk_pressure() {
+        let ds = create_datastore().await;
+        let pressure = GcDiskPressure::new(1000, 100);
+        let ds = ds.with_gc_disk_pressure(Some(pressure.clone()));
+        assert_eq!(ds.config.gc_disk_pressure, Some(pressure.clone()));
+        assert_eq!(*ds.gc.config.disk_pressure.read().unwrap(), Some(pressure));
+    }
+
+    #[test]
+    #[should_panic(expected = "critical_free_space should be below low_free_space")]
+    fn test_gc_disk_pressure_invalid() {
+        GcDiskPressure::new(100, 100);
+    }
+
+    #[test]
+    fn test_gc_disk_pressure_scale() {
+        let pressure = GcDiskPressure {
+            low_free_space: 1000,
+            critical_free_space: 200,
+            max_chunk_size: 10_000,
+        };
+        assert_eq!(pressure.scale(2000, 5000), 2000);
+        assert_eq!(pressure.scale(2000, 1000), 2000);
+        assert_eq!(pressure.scale(2000, 600), 6000);
+        assert_eq!(pressure.scale(2000, 200), 10_000);
+        assert_eq!(pressure.scale(2000, 0), 10_000);
+        // chunks never shrink under pressure
+        assert_eq!(pressure.scale(20_000, 0), 20_000);
     }
 
     #[tokio::test]
@@ -457,4 +1007,49 @@ mod tests {
         let ds = ds.with_gc_chunk_size(100);
         assert_eq!(ds.config.gc_chunk_size, SizeUnit::Kilobytes.as_bytes(100));
     }
+
+    #[tokio::test]
+    #[should_panic(expected = "max_key_size should be between 1 and u32::MAX")]
+    async fn test_with_max_key_size_invalid() {
+        let ds = create_datastore().await;
+        ds.with_max_key_size(0);
+    }
+
+    #[tokio::test]
+    async fn test_with_max_key_size() {
+        let ds = create_datastore().await;
+        let ds = ds.with_max_key_size(128);
+        assert_eq!(ds.config.max_key_size, 128);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "max_value_size should be between 1 and u32::MAX")]
+    async fn test_with_max_value_size_invalid() {
+        let ds = create_datastore().await;
+        ds.with_max_value_size(MAX_VALUE_SIZE + 1);
+    }
+
+    #[tokio::test]
+    async fn test_with_max_value_size() {
+        let ds = create_datastore().await;
+        let ds = ds.with_max_value_size(4096);
+        assert_eq!(ds.config.max_value_size, 4096);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "max_unflushed_vlog_size should not be less than 50 Kilobytes")]
+    async fn test_with_max_unflushed_vlog_size_invalid() {
+        let ds = create_datastore().await;
+        ds.with_max_unflushed_vlog_size(49);
+    }
+
+    #[tokio::test]
+    async fn test_with_max_unflushed_vlog_size() {
+        let ds = create_datastore().await;
+        let ds = ds.with_max_unflushed_vlog_size(1024);
+        assert_eq!(
+            ds.config.max_unflushed_vlog_size,
+            Some(SizeUnit::Kilobytes.as_bytes(1024))
+        );
+    }
 }
@@ -1,2 +1,4 @@
 mod config;
-pub use config::Config;
+mod progress;
+pub use config::{ColdStorage, Config, GcDiskPressure, KeyspaceQuota};
+pub use progress::{OnProgress, OpenPhase, OpenProgress};
@@ -0,0 +1,65 @@
+use std::{fmt, sync::Arc};
+
+/// Phase of [`DataStore`](crate::db::DataStore) recovery
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpenPhase {
+    /// Reading summaries and indexes of SSTables
+    SSTableLoad,
+
+    /// Checking filter files, rebuilding those that are missing or corrupt
+    FilterBuild,
+
+    /// Replaying value log entries that were not flushed into memtables
+    VLogReplay,
+}
+
+/// Progress of a recovery phase, reported to [`OnProgress`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OpenProgress {
+    /// Current phase
+    pub phase: OpenPhase,
+
+    /// Units of work (SSTables or value log entries) done in this phase
+    pub completed: usize,
+
+    /// Units of work in this phase
+    pub total: usize,
+}
+
+impl OpenProgress {
+    /// Returns percentage of the phase that is done, 100 for an empty phase
+    pub fn percent(&self) -> f64 {
+        if self.total == 0 {
+            return 100.0;
+        }
+        self.completed as f64 * 100.0 / self.total as f64
+    }
+}
+
+/// Callback invoked with recovery progress while a store is opened
+///
+/// Cloning an `OnProgress` is cheap, clones call the same function
+#[derive(Clone)]
+pub struct OnProgress(Arc<dyn Fn(OpenProgress) + Send + Sync>);
+
+impl OnProgress {
+    /// Creates new `OnProgress` that calls `callback`
+    pub fn new(callback: impl Fn(OpenProgress) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(callback))
+    }
+
+    /// Reports that `completed` out of `total` units of `phase` are done
+    pub(crate) fn report(&self, phase: OpenPhase, completed: usize, total: usize) {
+        (self.0)(OpenProgress {
+            phase,
+            completed,
+            total,
+        })
+    }
+}
+
+impl fmt::Debug for OnProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnProgress").finish_non_exhaustive()
+    }
+}
@@ -1,7 +1,16 @@
+use super::{BucketInfo, CompactionPolicy};
 use crate::bucket::InsertableToBucket;
-use crate::types::{Bool, BucketMapHandle, FlushReceiver, KeyRangeHandle};
+use crate::cfg::ColdStorage;
+use crate::consts::BACKGROUND_JOB_POLL_INTERVAL;
+use crate::db::{ExpiryIndex, StatsRecorder};
+use crate::env::{supervise, BackgroundJob, Env, Timer};
+use crate::flush::FlushEvent;
+use crate::sst::RangeTombstones;
+use crate::types::{Bool, BucketMapHandle, CreatedAt, FlushReceiver, KeyRangeHandle};
 use crate::{err::Error, filter::BloomFilter};
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{atomic::AtomicU64, Arc};
 use std::time;
 use tokio::sync::Mutex;
 use tokio::time::sleep;
@@ -27,6 +36,9 @@ pub struct Compactor {
 
     /// Is compaction active or sleeping
     pub is_active: Arc<Mutex<CompState>>,
+
+    /// Scheduler shared with other stores to limit concurrent compactions
+    pub env: Env,
 }
 
 /// Compactor configuration
@@ -42,18 +54,39 @@ pub struct Config {
     pub(crate) tombstone_ttl: std::time::Duration,
 
     /// interval to listen for flush event
-    pub(crate) flush_listener_interval: std::time::Duration,
+    pub(crate) flush_listener_interval: Timer,
 
     /// interval to trigger background compaction
-    pub(crate) background_interval: std::time::Duration,
+    pub(crate) background_interval: Timer,
 
     /// interval to trigger background tombstone compaction
-    pub(crate) tombstone_compaction_interval: std::time::Duration,
+    pub(crate) tombstone_compaction_interval: Timer,
 
     /// compaction strategy
     pub(crate) strategy: Strategy,
 
     pub(crate) filter_false_positive: f64,
+
+    /// filter memory shared by all sstables, fixed false positive rate is used if not set
+    pub(crate) filter_memory_budget: Option<usize>,
+
+    /// build filters of merged sstables on the blocking thread pool
+    pub(crate) offload_cpu_work: bool,
+
+    /// where to place cold merged sstables, if anywhere
+    pub(crate) cold_storage: Option<ColdStorage>,
+
+    /// policy used instead of `strategy`, if any
+    pub(crate) policy: Option<Arc<dyn CompactionPolicy>>,
+
+    /// keys written with a TTL, their expired versions are dropped
+    pub(crate) expiry: Option<ExpiryIndex>,
+
+    /// Bytes of merged sstables written, counted for the stats of the store
+    pub(crate) bytes_written: Arc<AtomicU64>,
+
+    /// Range tombstones of the store, keys they cover are dropped
+    pub(crate) range_tombstones: RangeTombstones,
 }
 
 /// Groups TTL params
@@ -89,6 +122,38 @@ pub enum CompState {
     Active,
 }
 
+/// Puts compactor back to sleep if a background compaction panics
+///
+/// Without it the state would stay `Active` and restarted workers would
+/// never compact again
+struct ActiveCompaction {
+    state: Arc<Mutex<CompState>>,
+    finished: bool,
+}
+
+impl ActiveCompaction {
+    fn new(state: Arc<Mutex<CompState>>) -> Self {
+        Self {
+            state,
+            finished: false,
+        }
+    }
+
+    /// Marks compaction as finished, state is then reset by the worker itself
+    fn finish(mut self) {
+        self.finished = true;
+    }
+}
+
+impl Drop for ActiveCompaction {
+    fn drop(&mut self) {
+        if !self.finished {
+            let state = Arc::clone(&self.state);
+            tokio::spawn(async move { *state.lock().await = CompState::Sleep });
+        }
+    }
+}
+
 /// Reasons for compaction
 #[derive(Debug, Clone, PartialEq)]
 pub enum CompactionReason {
@@ -139,28 +204,38 @@ pub struct MergedSSTable {
     pub sstable: Box<dyn InsertableToBucket>,
     pub hotness: u64,
     pub filter: BloomFilter,
+
+    /// Creation date of the newest sstable that was merged
+    pub created_at: CreatedAt,
 }
 
 impl Clone for MergedSSTable {
     fn clone(&self) -> Self {
         Self {
-            sstable: Box::new(super::TableInsertor::from(
-                self.sstable.get_entries(),
-                &self.filter,
-            )),
+            sstable: Box::new(
+                super::TableInsertor::from(self.sstable.get_entries(), &self.filter)
+                    .with_range_tombstones(self.sstable.get_range_tombstones()),
+            ),
             hotness: self.hotness,
             filter: self.filter.clone(),
+            created_at: self.created_at,
         }
     }
 }
 
 impl MergedSSTable {
     /// Creates new `MergedSSTable`
-    pub fn new(sstable: Box<dyn InsertableToBucket>, filter: BloomFilter, hotness: u64) -> Self {
+    pub fn new(
+        sstable: Box<dyn InsertableToBucket>,
+        filter: BloomFilter,
+        hotness: u64,
+        created_at: CreatedAt,
+    ) -> Self {
         Self {
             sstable,
             hotness,
             filter,
+            created_at,
         }
     }
 }
@@ -177,11 +252,30 @@ impl Config {
             use_ttl,
             entry_ttl: ttl.entry_ttl,
             tombstone_ttl: ttl.tombstone_ttl,
-            flush_listener_interval: intervals.flush_listener_interval,
-            background_interval: intervals.background_interval,
-            tombstone_compaction_interval: intervals.tombstone_compaction_interval,
+            flush_listener_interval: Timer::new(intervals.flush_listener_interval),
+            background_interval: Timer::new(intervals.background_interval),
+            tombstone_compaction_interval: Timer::new(intervals.tombstone_compaction_interval),
             strategy,
             filter_false_positive,
+            filter_memory_budget: None,
+            offload_cpu_work: true,
+            cold_storage: None,
+            policy: None,
+            expiry: None,
+            bytes_written: Arc::default(),
+            range_tombstones: RangeTombstones::default(),
+        }
+    }
+
+    /// Checks if a bucket needs compaction under the configured policy or strategy
+    pub(crate) async fn needs_compaction(&self, buckets: &BucketMapHandle) -> bool {
+        let buckets = buckets.read().await;
+        match &self.policy {
+            Some(policy) => !policy
+                .pick_buckets(&BucketInfo::from_map(&buckets).await)
+                .await
+                .is_empty(),
+            None => !buckets.is_balanced().await,
         }
     }
 }
@@ -195,13 +289,66 @@ impl Compactor {
         strategy: Strategy,
         reason: CompactionReason,
         filter_false_positive: f64,
+        env: Env,
     ) -> Self {
         Self {
             is_active: Arc::new(Mutex::new(CompState::Sleep)),
             reason,
+            env,
             config: Config::new(use_ttl, ttl, intervals, strategy, filter_false_positive),
         }
     }
+
+    /// Sizes filters of merged sstables within `filter_memory_budget`
+    pub(crate) fn with_filter_memory_budget(mut self, filter_memory_budget: Option<usize>) -> Self {
+        self.config.filter_memory_budget = filter_memory_budget;
+        self
+    }
+
+    /// Sets whether filters of merged sstables are built on the blocking thread pool
+    pub(crate) fn with_cpu_offload(mut self, offload_cpu_work: bool) -> Self {
+        self.config.offload_cpu_work = offload_cpu_work;
+        self
+    }
+
+    /// Drops versions of keys written with a TTL once it and the tombstone grace period passed
+    pub(crate) fn with_expiry(mut self, expiry: ExpiryIndex) -> Self {
+        self.config.expiry = Some(expiry);
+        self
+    }
+
+    /// Drops versions of keys deleted by `range_tombstones` of the store
+    pub(crate) fn with_range_tombstones(mut self, range_tombstones: RangeTombstones) -> Self {
+        self.config.range_tombstones = range_tombstones;
+        self
+    }
+
+    /// Counts bytes written by compaction in `stats`
+    pub(crate) fn with_stats(mut self, stats: &StatsRecorder) -> Self {
+        self.config.bytes_written = stats.compaction_bytes();
+        self
+    }
+
+    /// Places cold merged sstables in `cold_storage`
+    pub(crate) fn with_cold_storage(mut self, cold_storage: Option<ColdStorage>) -> Self {
+        self.config.cold_storage = cold_storage;
+        self
+    }
+
+    /// Moves delays of background loops by up to `jitter` times their interval
+    pub(crate) fn with_jitter(mut self, jitter: f64) -> Self {
+        let cfg = &mut self.config;
+        cfg.flush_listener_interval = cfg.flush_listener_interval.clone().with_jitter(jitter);
+        cfg.background_interval = cfg.background_interval.clone().with_jitter(jitter);
+        cfg.tombstone_compaction_interval = cfg.tombstone_compaction_interval.clone().with_jitter(jitter);
+        self
+    }
+
+    /// Picks sstables to merge with `policy` instead of the configured strategy
+    pub(crate) fn with_policy(mut self, policy: Option<Arc<dyn CompactionPolicy>>) -> Self {
+        self.config.policy = policy;
+        self
+    }
     /// FUTURE: Explicitly trigger tombstone compaction to remove expired tombstones, although this is handled during
     /// normal compaction
     #[allow(unused_variables, dead_code)]
@@ -213,7 +360,7 @@ impl Compactor {
         let cfg = self.config.to_owned();
         tokio::spawn(async move {
             loop {
-                Compactor::sleep_compaction(cfg.tombstone_compaction_interval).await;
+                cfg.tombstone_compaction_interval.tick().await;
             }
         });
     }
@@ -227,39 +374,65 @@ impl Compactor {
         bucket_map: BucketMapHandle,
         key_range: KeyRangeHandle,
     ) {
-        let mut rx = flush_rx.clone();
         let comp_state = Arc::clone(&self.is_active);
         let cfg = self.config.to_owned();
-        tokio::spawn(async move {
-            loop {
-                Compactor::sleep_compaction(cfg.flush_listener_interval).await;
-                let signal = rx.try_recv();
-                let mut state = comp_state.lock().await;
-                if let CompState::Sleep = *state {
-                    if let Err(err) = signal {
-                        drop(state);
-                        match err {
-                            async_broadcast::TryRecvError::Overflowed(_) => {
-                                log::error!("{}", FlushSignalChannelOverflow)
-                            }
-                            async_broadcast::TryRecvError::Closed => {
-                                log::error!("{}", FlushSignalChannelClosed)
+        let env = self.env.clone();
+        supervise(BackgroundJob::Compaction, self.env.errors.clone(), move || {
+            let mut rx = flush_rx.clone();
+            let comp_state = Arc::clone(&comp_state);
+            let cfg = cfg.to_owned();
+            let env = env.clone();
+            let bucket_map = Arc::clone(&bucket_map);
+            let key_range = Arc::clone(&key_range);
+            async move {
+                loop {
+                    cfg.flush_listener_interval.tick().await;
+                    let signal = rx.try_recv();
+                    let mut state = comp_state.lock().await;
+                    if let CompState::Sleep = *state {
+                        match signal {
+                            // nothing new on disk to compact
+                            Ok(FlushEvent::Failed { .. }) => {
+                                drop(state);
+                                continue;
                             }
-                            async_broadcast::TryRecvError::Empty => {}
+                            Ok(FlushEvent::Flushed { .. }) => {}
+                            Err(err) => match err {
+                                // older signals were dropped, flushes still happened
+                                async_broadcast::TryRecvError::Overflowed(_) => {
+                                    log::warn!("{}", FlushSignalChannelOverflow)
+                                }
+                                async_broadcast::TryRecvError::Closed => {
+                                    drop(state);
+                                    log::error!("{}", FlushSignalChannelClosed);
+                                    continue;
+                                }
+                                async_broadcast::TryRecvError::Empty => {
+                                    drop(state);
+                                    continue;
+                                }
+                            },
+                        }
+                        *state = CompState::Active;
+                        drop(state);
+                        let active = ActiveCompaction::new(Arc::clone(&comp_state));
+                        let permit = env.acquire(BackgroundJob::Compaction).await;
+                        let res = Compactor::handle_compaction(
+                            Arc::clone(&bucket_map),
+                            Arc::clone(&key_range),
+                            &cfg,
+                        )
+                        .await;
+                        drop(permit);
+                        active.finish();
+                        // a failed compaction must not keep the store from compacting again
+                        let mut state = comp_state.lock().await;
+                        *state = CompState::Sleep;
+                        drop(state);
+                        if let Err(err) = res {
+                            log::info!("{}", Error::CompactionFailed(Box::new(err)));
                         }
-                        continue;
-                    }
-                    *state = CompState::Active;
-                    drop(state);
-                    if let Err(err) =
-                        Compactor::handle_compaction(Arc::clone(&bucket_map), Arc::clone(&key_range), &cfg)
-                            .await
-                    {
-                        log::info!("{}", Error::CompactionFailed(Box::new(err)));
-                        continue;
                     }
-                    let mut state = comp_state.lock().await;
-                    *state = CompState::Sleep;
                 }
             }
         });
@@ -270,25 +443,90 @@ impl Compactor {
     pub fn spawn_compaction_worker(&self, buckets: BucketMapHandle, key_range: KeyRangeHandle) {
         let cfg = self.config.to_owned();
         let comp_state = Arc::clone(&self.is_active);
-        tokio::spawn(async move {
-            loop {
-                Compactor::sleep_compaction(cfg.background_interval).await;
-                let mut state = comp_state.lock().await;
-                if let CompState::Sleep = *state {
-                    *state = CompState::Active;
-                    drop(state);
-                    if let Err(err) =
-                        Compactor::handle_compaction(Arc::clone(&buckets), Arc::clone(&key_range), &cfg).await
-                    {
-                        log::info!("{}", Error::CompactionFailed(Box::new(err)))
-                    }
+        let env = self.env.clone();
+        supervise(BackgroundJob::Compaction, self.env.errors.clone(), move || {
+            let cfg = cfg.to_owned();
+            let comp_state = Arc::clone(&comp_state);
+            let env = env.clone();
+            let buckets = Arc::clone(&buckets);
+            let key_range = Arc::clone(&key_range);
+            async move {
+                loop {
+                    cfg.background_interval.tick().await;
                     let mut state = comp_state.lock().await;
-                    *state = CompState::Sleep;
+                    if let CompState::Sleep = *state {
+                        *state = CompState::Active;
+                        drop(state);
+                        let active = ActiveCompaction::new(Arc::clone(&comp_state));
+                        let _permit = env.acquire(BackgroundJob::Compaction).await;
+                        if let Err(err) =
+                            Compactor::handle_compaction(Arc::clone(&buckets), Arc::clone(&key_range), &cfg)
+                                .await
+                        {
+                            log::info!("{}", Error::CompactionFailed(Box::new(err)))
+                        }
+                        active.finish();
+                        let mut state = comp_state.lock().await;
+                        *state = CompState::Sleep;
+                    }
                 }
             }
         });
     }
 
+    /// Waits for a running compaction to finish, then runs pending ones until none is left
+    ///
+    /// Compaction is pending when a bucket holds more sstables than the
+    /// threshold, merged sstables can fill another bucket past it, so
+    /// buckets are checked again after every pass. Background workers
+    /// skip their turn while this runs.
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if pending compaction failed
+    pub(crate) async fn wait_for_idle(
+        &self,
+        buckets: BucketMapHandle,
+        key_range: KeyRangeHandle,
+    ) -> Result<(), Error> {
+        loop {
+            let mut state = self.is_active.lock().await;
+            if let CompState::Sleep = *state {
+                if !self.config.needs_compaction(&buckets).await {
+                    return Ok(());
+                }
+                *state = CompState::Active;
+                drop(state);
+                let before: HashSet<PathBuf> = key_range.key_ranges.read().await.keys().cloned().collect();
+                // caller can drop this future mid-compaction, state is reset then too
+                let active = ActiveCompaction::new(Arc::clone(&self.is_active));
+                let permit = self.env.acquire(BackgroundJob::Compaction).await;
+                let res =
+                    Compactor::handle_compaction(Arc::clone(&buckets), Arc::clone(&key_range), &self.config)
+                        .await;
+                drop(permit);
+                let mut state = self.is_active.lock().await;
+                active.finish();
+                *state = CompState::Sleep;
+                drop(state);
+                res?;
+                // a policy can keep picking a bucket it finds no inputs to merge in
+                if key_range
+                    .key_ranges
+                    .read()
+                    .await
+                    .keys()
+                    .all(|dir| before.contains(dir))
+                {
+                    return Ok(());
+                }
+                continue;
+            }
+            drop(state);
+            Compactor::sleep_compaction(BACKGROUND_JOB_POLL_INTERVAL).await;
+        }
+    }
+
     pub async fn handle_compaction(
         buckets: BucketMapHandle,
         key_range: KeyRangeHandle,
@@ -336,21 +574,22 @@ mod tests {
             strategy,
             reason.to_owned(),
             filter_false_positive,
+            Env::default(),
         );
 
         assert_eq!(compactor.config.use_ttl, use_ttl);
         assert_eq!(compactor.config.entry_ttl, ttl.entry_ttl);
         assert_eq!(compactor.config.tombstone_ttl, ttl.tombstone_ttl);
         assert_eq!(
-            compactor.config.background_interval,
+            compactor.config.background_interval.interval(),
             intervals.background_interval
         );
         assert_eq!(
-            compactor.config.flush_listener_interval,
+            compactor.config.flush_listener_interval.interval(),
             intervals.flush_listener_interval
         );
         assert_eq!(
-            compactor.config.tombstone_compaction_interval,
+            compactor.config.tombstone_compaction_interval.interval(),
             intervals.tombstone_compaction_interval
         );
         assert_eq!(compactor.config.strategy, strategy);
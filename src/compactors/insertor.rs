@@ -1,5 +1,6 @@
 use crate::consts::{SIZE_OF_U64, SIZE_OF_U8, SIZE_OF_USIZE};
 use crate::filter::BloomFilter;
+use crate::sst::RangeTombstone;
 use crate::{bucket::InsertableToBucket, types::*};
 use crossbeam_skiplist::SkipMap;
 use std::sync::Arc;
@@ -9,6 +10,7 @@ pub struct TableInsertor {
     pub(crate) entries: SkipMapEntries<Key>,
     pub(crate) size: usize,
     pub(crate) filter: BloomFilter,
+    pub(crate) range_tombstones: Vec<RangeTombstone>,
 }
 
 impl InsertableToBucket for TableInsertor {
@@ -21,6 +23,9 @@ impl InsertableToBucket for TableInsertor {
     fn size(&self) -> usize {
         self.size
     }
+    fn get_range_tombstones(&self) -> Vec<RangeTombstone> {
+        self.range_tombstones.clone()
+    }
 }
 
 impl TableInsertor {
@@ -33,9 +38,16 @@ impl TableInsertor {
             entries,
             size,
             filter: filter.to_owned(),
+            range_tombstones: Vec::new(),
         }
     }
 
+    /// Stores `range_tombstones` in the summary of the sstable written from the entries
+    pub(crate) fn with_range_tombstones(mut self, range_tombstones: Vec<RangeTombstone>) -> Self {
+        self.range_tombstones = range_tombstones;
+        self
+    }
+
     pub(crate) fn set_entries(&mut self, entries: SkipMapEntries<Key>) {
         self.entries = entries;
         self.set_sst_size_from_entries();
@@ -54,6 +66,7 @@ impl Default for TableInsertor {
             entries: Arc::new(SkipMap::new()),
             size: 0,
             filter: BloomFilter::default(),
+            range_tombstones: Vec::new(),
         }
     }
 }
@@ -0,0 +1,141 @@
+//! # Compaction Journal
+//!
+//! Inputs and outputs of a running compaction, kept in the store root.
+//!
+//! Inputs are journaled before merged sstables are written and the directory of
+//! every output is added before it is written. The journal is marked complete before inputs are deleted
+//! and removed afterwards. At next open an incomplete journal is rolled back by
+//! deleting its outputs, a complete one is rolled forward by deleting its inputs,
+//! so no key is left both in a merged sstable and in one it replaced.
+
+use crate::{
+    consts::COMPACTION_JOURNAL_FILE_NAME,
+    err::Error::{self, *},
+    fs::FileNode,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// Compaction written before its inputs are deleted
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CompactionJournal {
+    /// Directories of sstables being merged
+    pub(crate) inputs: Vec<PathBuf>,
+
+    /// Directories of merged sstables written so far
+    pub(crate) outputs: Vec<PathBuf>,
+
+    /// Are all merged sstables written?
+    pub(crate) complete: bool,
+}
+
+impl CompactionJournal {
+    /// Creates journal of a compaction merging sstables in `inputs`
+    pub(crate) fn new(inputs: Vec<PathBuf>) -> Self {
+        Self {
+            inputs,
+            ..Default::default()
+        }
+    }
+
+    /// Returns path of the journal of the store whose buckets are in `buckets_dir`
+    pub(crate) fn path(buckets_dir: impl AsRef<Path>) -> PathBuf {
+        let buckets_dir = buckets_dir.as_ref();
+        buckets_dir
+            .parent()
+            .unwrap_or(buckets_dir)
+            .join(COMPACTION_JOURNAL_FILE_NAME)
+    }
+
+    /// Reads journal of the store whose buckets are in `buckets_dir`, `None` if there is none
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the journal could not be read or decoded
+    pub(crate) async fn read(buckets_dir: impl AsRef<Path>) -> Result<Option<Self>, Error> {
+        let path = Self::path(buckets_dir);
+        let buf = match fs::read(&path).await {
+            Ok(buf) => buf,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(FileOpen { path, error: err }),
+        };
+        serde_json::from_slice(&buf)
+            .map(Some)
+            .map_err(|err| CompactionJournalCorrupt { path, error: err })
+    }
+
+    /// Writes journal of the store whose buckets are in `buckets_dir`, replacing the previous one
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case of IO error
+    pub(crate) async fn write(&self, buckets_dir: impl AsRef<Path>) -> Result<(), Error> {
+        let buf = serde_json::to_vec(self).map_err(|_| Serialization("compaction journal"))?;
+        FileNode::write_atomic(Self::path(buckets_dir), &buf).await
+    }
+
+    /// Removes journal of the store whose buckets are in `buckets_dir`
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case of IO error
+    pub(crate) async fn remove(buckets_dir: impl AsRef<Path>) -> Result<(), Error> {
+        let path = Self::path(buckets_dir);
+        match fs::remove_file(&path).await {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(FileDelete { path, error }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Undoes a compaction that failed before any of its outputs was listed
+    ///
+    /// Outputs are deleted and the journal is removed, the inputs stay in place.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an output or the journal could not be deleted
+    pub(crate) async fn roll_back(&self, buckets_dir: impl AsRef<Path>) -> Result<(), Error> {
+        Self::remove_dirs(&self.outputs).await?;
+        Self::remove(buckets_dir).await
+    }
+
+    /// Finishes or undoes a compaction interrupted by a crash, before sstables are loaded
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the journal could not be read or an sstable could not be deleted
+    pub(crate) async fn recover(buckets_dir: impl AsRef<Path>) -> Result<(), Error> {
+        let journal = match Self::read(buckets_dir.as_ref()).await? {
+            Some(journal) => journal,
+            None => return Ok(()),
+        };
+        let obsolete = if journal.complete {
+            &journal.inputs
+        } else {
+            &journal.outputs
+        };
+        Self::remove_dirs(obsolete).await?;
+        Self::remove(buckets_dir).await
+    }
+
+    /// Deletes sstable directories in `dirs`, and their bucket directories once empty
+    async fn remove_dirs(dirs: &[PathBuf]) -> Result<(), Error> {
+        for dir in dirs {
+            match fs::remove_dir_all(dir).await {
+                Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(DirDelete {
+                        path: dir.to_owned(),
+                        error,
+                    })
+                }
+                _ => {}
+            }
+            // bucket directory goes along with its last sstable
+            if let Some(bucket_dir) = dir.parent() {
+                let _ = fs::remove_dir(bucket_dir).await;
+            }
+        }
+        Ok(())
+    }
+}
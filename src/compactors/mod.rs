@@ -1,7 +1,10 @@
 mod compact;
 mod insertor;
+pub(crate) mod journal;
+mod policy;
 mod sized;
 
+pub use crate::bucket::BucketID;
 pub use compact::CompState;
 pub use compact::CompactionReason;
 pub use compact::Compactor;
@@ -11,4 +14,5 @@ pub use compact::MergedSSTable;
 pub use compact::Strategy;
 pub use compact::TtlParams;
 pub use insertor::TableInsertor;
+pub use policy::{BucketInfo, CompactionPolicy, SSTableInfo};
 pub use sized::SizedTierRunner;
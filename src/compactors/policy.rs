@@ -0,0 +1,158 @@
+use crate::{
+    bucket::{Bucket, BucketID, BucketMap},
+    sst::Table,
+    types::CreatedAt,
+};
+use async_trait::async_trait;
+use std::{fmt::Debug, path::PathBuf};
+
+/// Decides which sstables are merged together and how merged entries are split
+///
+/// Replaces the sized tier strategy when set with `Config::compaction_policy`,
+/// merging, tombstone handling, journaling and placement of merged sstables in
+/// buckets are unchanged. Compaction runs until [`CompactionPolicy::pick_buckets`]
+/// returns no bucket with at least two inputs, so a policy must converge.
+///
+/// # Examples
+///
+/// ```rust
+/// use velarixdb::compactors::{BucketID, BucketInfo, CompactionPolicy, SSTableInfo};
+/// use velarixdb::db::{Config, DataStore};
+/// use std::path::PathBuf;
+/// # use tempfile::tempdir;
+///
+/// /// Keeps keys of each tenant, prefixed with `tenant:`, in their own sstables
+/// #[derive(Debug)]
+/// struct TenantPolicy;
+///
+/// fn tenant(key: &[u8]) -> &[u8] {
+///     key.split(|&b| b == b':').next().unwrap_or_default()
+/// }
+///
+/// fn is_shared(sst: &SSTableInfo) -> bool {
+///     tenant(&sst.smallest_key) != tenant(&sst.biggest_key)
+/// }
+///
+/// #[async_trait::async_trait]
+/// impl CompactionPolicy for TenantPolicy {
+///     async fn pick_buckets(&self, buckets: &[BucketInfo]) -> Vec<BucketID> {
+///         buckets
+///             .iter()
+///             .filter(|b| b.sstables.iter().filter(|sst| is_shared(sst)).count() >= 2)
+///             .map(|b| b.id)
+///             .collect()
+///     }
+///
+///     async fn choose_inputs(&self, bucket: &BucketInfo) -> Vec<PathBuf> {
+///         bucket.sstables.iter().filter(|sst| is_shared(sst)).map(|sst| sst.dir.to_owned()).collect()
+///     }
+///
+///     fn split_between(&self, previous: &[u8], key: &[u8]) -> bool {
+///         tenant(previous) != tenant(key)
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let root = tempdir().unwrap();
+///     let config = Config::default().compaction_policy(TenantPolicy);
+///     let store = DataStore::open_with_config("big_tech", root.path().join("store"), config).await;
+///     assert!(store.is_ok());
+/// }
+/// ```
+#[async_trait]
+pub trait CompactionPolicy: Debug + Send + Sync {
+    /// Returns ids of buckets to compact
+    async fn pick_buckets(&self, buckets: &[BucketInfo]) -> Vec<BucketID>;
+
+    /// Returns directories of sstables in `bucket` to merge into one, all of them by default
+    async fn choose_inputs(&self, bucket: &BucketInfo) -> Vec<PathBuf> {
+        bucket.sstables.iter().map(|sst| sst.dir.to_owned()).collect()
+    }
+
+    /// Checks if merged output starts a new sstable at `key`, which follows `previous`
+    ///
+    /// Called for every pair of adjacent merged keys, merged entries are written
+    /// to a single sstable by default
+    fn split_between(&self, _previous: &[u8], _key: &[u8]) -> bool {
+        false
+    }
+}
+
+/// Bucket as seen by a [`CompactionPolicy`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BucketInfo {
+    /// Bucket id
+    pub id: BucketID,
+
+    /// Average size of sstables in the bucket
+    pub avarage_size: usize,
+
+    /// SSTables in the bucket, oldest first
+    pub sstables: Vec<SSTableInfo>,
+}
+
+/// SSTable as seen by a [`CompactionPolicy`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SSTableInfo {
+    /// SSTable directory
+    pub dir: PathBuf,
+
+    /// Size of data file
+    pub size: usize,
+
+    /// Hotness of the sstable, raised whenever its bucket receives an sstable
+    pub hotness: u64,
+
+    /// Creation date
+    pub created_at: CreatedAt,
+
+    /// Smallest key in the sstable
+    pub smallest_key: Vec<u8>,
+
+    /// Biggest key in the sstable
+    pub biggest_key: Vec<u8>,
+}
+
+impl BucketInfo {
+    /// Creates `BucketInfo` of every bucket in `bucket_map`
+    pub(crate) async fn from_map(bucket_map: &BucketMap) -> Vec<Self> {
+        let mut buckets = Vec::with_capacity(bucket_map.buckets.len());
+        for bucket in bucket_map.buckets.values() {
+            buckets.push(Self::from_bucket(bucket).await);
+        }
+        buckets
+    }
+
+    async fn from_bucket(bucket: &Bucket) -> Self {
+        Self {
+            id: bucket.id,
+            avarage_size: bucket.avarage_size,
+            sstables: bucket
+                .sstables
+                .read()
+                .await
+                .iter()
+                .map(SSTableInfo::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<&Table> for SSTableInfo {
+    fn from(table: &Table) -> Self {
+        let (smallest_key, biggest_key) = table
+            .summary
+            .as_ref()
+            .map(|summary| (summary.smallest_key.to_owned(), summary.biggest_key.to_owned()))
+            .unwrap_or_default();
+        Self {
+            dir: table.dir.to_owned(),
+            size: table.size(),
+            hotness: table.hotness,
+            created_at: table.created_at,
+            smallest_key,
+            biggest_key,
+        }
+    }
+}
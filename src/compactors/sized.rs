@@ -1,24 +1,34 @@
-use std::{cmp, collections::HashMap, sync::Arc};
+use std::{
+    cmp,
+    collections::HashMap,
+    sync::{atomic::Ordering, Arc},
+};
 
+use chrono::Utc;
 use crossbeam_skiplist::SkipMap;
 
 use super::{
     compact::{Config, MergePointer, WriteTracker},
-    MergedSSTable, TableInsertor,
+    journal::CompactionJournal,
+    BucketInfo, CompactionPolicy, MergedSSTable, TableInsertor,
 };
 use crate::{
-    bucket::{Bucket, ImbalancedBuckets, InsertableToBucket, SSTablesToRemove},
+    bucket::{Bucket, BucketMap, ImbalancedBuckets, InsertableToBucket, SSTablesToRemove},
+    db::Expiry,
     err::Error,
-    filter::BloomFilter,
+    filter::{monkey, BloomFilter},
     memtable::Entry,
-    types::{BucketMapHandle, CreatedAt, Key, KeyRangeHandle, ValOffset},
+    sst::Table,
+    types::{BucketMapHandle, CreatedAt, Key, KeyRangeHandle, SkipMapEntries, ValOffset},
 };
 use crate::{err::Error::*, memtable::SkipMapValue};
 
 /// Sized Tier Compaction Runner (STCS)
 ///
 /// Responsible for merging sstables of almost similar sizes to form a bigger one,
-/// obsolete entries are removed from the sstables and expired tombstones are removed
+/// obsolete entries are removed from the sstables and expired tombstones are removed.
+/// Entries past their TTL are removed once the tombstone grace period passed too,
+/// older versions of their keys in other buckets are merged away by then
 ///
 #[derive(Debug, Clone)]
 pub struct SizedTierRunner<'a> {
@@ -34,6 +44,9 @@ pub struct SizedTierRunner<'a> {
     /// Keeps track of tombstones encountered during compaction
     /// to predict validity of subseqeunt entries
     pub(crate) tombstones: HashMap<Key, CreatedAt>,
+
+    /// Records of keys written with a TTL whose versions can be removed
+    pub(crate) expired: HashMap<Key, Expiry>,
 }
 
 impl<'a> SizedTierRunner<'a> {
@@ -45,6 +58,7 @@ impl<'a> SizedTierRunner<'a> {
     ) -> SizedTierRunner<'a> {
         Self {
             tombstones: HashMap::new(),
+            expired: HashMap::new(),
             bucket_map,
             key_range,
             config,
@@ -56,20 +70,72 @@ impl<'a> SizedTierRunner<'a> {
         bucket_map.read().await.extract_imbalanced_buckets().await
     }
 
+    /// Returns sstables to merge next, picked by the compaction policy of `config` if one is set
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case an error occurs while calculating average
+    pub(crate) async fn select_inputs(bucket_map: &BucketMap, config: &Config) -> ImbalancedBuckets {
+        let policy = match &config.policy {
+            Some(policy) => policy,
+            None => return bucket_map.extract_imbalanced_buckets().await,
+        };
+        let infos = BucketInfo::from_map(bucket_map).await;
+        let mut imbalanced_buckets: Vec<Bucket> = Vec::new();
+        let mut ssts_to_remove: SSTablesToRemove = Vec::new();
+        for bucket_id in policy.pick_buckets(&infos).await {
+            if ssts_to_remove.iter().any(|(id, _)| *id == bucket_id) {
+                continue;
+            }
+            let (bucket, info) = match (
+                bucket_map.buckets.get(&bucket_id),
+                infos.iter().find(|info| info.id == bucket_id),
+            ) {
+                (Some(bucket), Some(info)) => (bucket, info),
+                _ => continue,
+            };
+            let inputs = policy.choose_inputs(info).await;
+            let ssts: Vec<Table> = bucket
+                .sstables
+                .read()
+                .await
+                .iter()
+                .filter(|sst| inputs.contains(&sst.dir))
+                .cloned()
+                .collect();
+            // merging a single sstable makes no progress
+            if ssts.len() < 2 {
+                continue;
+            }
+            let avg = Bucket::cal_average_size(ssts.clone()).await?;
+            ssts_to_remove.push((bucket_id, ssts.clone()));
+            imbalanced_buckets.push(Bucket::from(bucket.dir.to_owned(), bucket_id, ssts, avg).await?);
+        }
+        Ok((imbalanced_buckets, ssts_to_remove))
+    }
+
     /// Main compaction runner
     pub async fn run_compaction(&mut self) -> Result<(), Error> {
-        if self.bucket_map.read().await.is_balanced().await {
+        if !self.config.needs_compaction(&self.bucket_map).await {
             return Ok(());
         }
+        self.expired = self.expired_versions().await;
         // The compaction loop will keep running until there
         // are no more buckets with more than minimum treshold size
         // TODO: Handle this with multiple threads
         loop {
             let buckets: BucketMapHandle = Arc::clone(&self.bucket_map);
             let key_range = Arc::clone(&self.key_range);
+            let buckets_dir = buckets.read().await.dir.to_owned();
+            // a new journal would replace the only record of which sstables are obsolete
+            if CompactionJournal::read(&buckets_dir).await?.is_some() {
+                return Err(CompactionJournalPending {
+                    path: CompactionJournal::path(&buckets_dir),
+                });
+            }
             // Step 1: Extract imbalanced buckets
             let (imbalanced_buckets, ssts_to_remove) =
-                SizedTierRunner::fetch_imbalanced_buckets(buckets.clone()).await?;
+                SizedTierRunner::select_inputs(&*buckets.read().await, self.config).await?;
             if imbalanced_buckets.is_empty() {
                 self.tombstones.clear();
                 return Ok(());
@@ -79,53 +145,44 @@ impl<'a> SizedTierRunner<'a> {
             match self.merge_ssts_in_buckets(&imbalanced_buckets.to_owned()).await {
                 Ok(merged_sstables) => {
                     let mut tracker = WriteTracker::new(merged_sstables.len());
+                    let inputs = ssts_to_remove
+                        .iter()
+                        .flat_map(|(_, ssts)| ssts.iter().map(|s| s.dir.to_owned()))
+                        .collect();
+                    let mut journal = CompactionJournal::new(inputs);
+                    journal.write(&buckets_dir).await?;
                     // Step 3: Insert Merged SSTs to appropriate buckets
-                    for merged_sst in merged_sstables.into_iter() {
-                        let mut bucket = buckets.write().await;
-                        let table = merged_sst.clone().sstable;
-                        let insert_res = bucket.insert_to_appropriate_bucket(Arc::new(table)).await;
-                        drop(bucket);
-                        match insert_res {
-                            Ok(sst) => {
-                                if sst.summary.is_none() {
-                                    return Err(TableSummaryIsNone);
-                                }
-                                if sst.filter.is_none() {
-                                    return Err(FilterNotProvidedForFlush);
-                                }
-                                // IMPORTANT: Don't keep sst entries in memory
-                                sst.entries.clear();
-                                let summary = sst.summary.clone().unwrap();
-                                // Step 5 Store sst key range
-                                key_range
-                                    .set(sst.dir.to_owned(), summary.smallest_key, summary.biggest_key, sst)
-                                    .await;
-                                tracker.actual += 1;
-                            }
-                            Err(err) => {
-                                return Err(CompactionFailed(Box::new(err)));
-                            }
+                    let inserted = self
+                        .insert_merged_ssts(merged_sstables, &ssts_to_remove, &mut journal, &mut tracker)
+                        .await;
+                    if let Err(err) = inserted {
+                        // no merged sstable is listed yet, so none can be left next to its inputs
+                        if tracker.actual == 0 {
+                            journal.roll_back(&buckets_dir).await?;
                         }
+                        return Err(err);
                     }
 
-                    if tracker.expected == tracker.actual {
-                        // Step 6:  Delete the sstables that we already merged from their previous buckets
-                        let clean_up_successful = self
-                            .clean_up_after_compaction(buckets, &ssts_to_remove.clone(), key_range)
-                            .await;
-                        match clean_up_successful {
-                            Ok(None) => {
-                                return Err(Error::CompactionPartiallyFailed(Box::new(
-                                    CompactionCleanupPartial,
-                                )));
-                            }
-                            Err(err) => {
-                                return Err(Error::CompactionCleanup(Box::new(err)));
-                            }
-                            _ => {}
+                    if tracker.expected != tracker.actual {
+                        return Err(CannotRemoveObsoleteSST);
+                    }
+                    // Step 6:  Delete the sstables that we already merged from their previous buckets,
+                    // a crash from here on deletes the rest of them at next open
+                    journal.complete = true;
+                    journal.write(&buckets_dir).await?;
+                    let clean_up_successful = self
+                        .clean_up_after_compaction(buckets, &ssts_to_remove.clone(), key_range)
+                        .await;
+                    match clean_up_successful {
+                        Ok(None) => {
+                            return Err(Error::CompactionPartiallyFailed(Box::new(
+                                CompactionCleanupPartial,
+                            )));
                         }
-                    } else {
-                        log::error!("{}", Error::CannotRemoveObsoleteSST)
+                        Err(err) => {
+                            return Err(Error::CompactionCleanup(Box::new(err)));
+                        }
+                        _ => CompactionJournal::remove(&buckets_dir).await?,
                     }
                 }
                 Err(err) => return Err(CompactionFailed(Box::new(err))),
@@ -133,6 +190,58 @@ impl<'a> SizedTierRunner<'a> {
         }
     }
 
+    /// Writes merged sstables to their buckets and adds them to the key range
+    ///
+    /// Directory of every sstable is added to `journal` before the sstable is
+    /// written, so a crash while writing it rolls it back at next open.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an sstable or the journal could not be written
+    async fn insert_merged_ssts(
+        &self,
+        merged_sstables: Vec<MergedSSTable>,
+        ssts_to_remove: &SSTablesToRemove,
+        journal: &mut CompactionJournal,
+        tracker: &mut WriteTracker,
+    ) -> Result<(), Error> {
+        for merged_sst in merged_sstables.into_iter() {
+            let mut bucket = self.bucket_map.write().await;
+            let cold_dir = self
+                .config
+                .cold_storage
+                .as_ref()
+                .filter(|cold| cold.is_cold(merged_sst.hotness, merged_sst.created_at))
+                .map(|cold| cold.dir.as_path());
+            let table = merged_sst.sstable;
+            let planned = bucket.plan_insert(table.size(), cold_dir, ssts_to_remove).await?;
+            journal.outputs.push(planned.dir.to_owned());
+            journal.write(&bucket.dir).await?;
+            let insert_res = bucket.insert_planned(planned, Arc::new(table)).await;
+            drop(bucket);
+            let sst = insert_res.map_err(|err| CompactionFailed(Box::new(err)))?;
+            // the sstable is listed from here on
+            tracker.actual += 1;
+            if sst.summary.is_none() {
+                return Err(TableSummaryIsNone);
+            }
+            if sst.filter.is_none() {
+                return Err(FilterNotProvidedForFlush);
+            }
+            self.config
+                .bytes_written
+                .fetch_add(sst.size as u64, Ordering::Relaxed);
+            // IMPORTANT: Don't keep sst entries in memory
+            sst.entries.clear();
+            let summary = sst.summary.clone().unwrap();
+            // Step 5 Store sst key range
+            self.key_range
+                .set(sst.dir.to_owned(), summary.smallest_key, summary.biggest_key, sst)
+                .await;
+        }
+        Ok(())
+    }
+
     /// Removes sstables that are already merged to form larger table(s)
     ///
     /// NOTE: This should only be called if merged sstables have been written to disk
@@ -172,8 +281,15 @@ impl<'a> SizedTierRunner<'a> {
         for bucket in buckets.iter() {
             let mut hotness: u64 = Default::default();
             let tables = &bucket.sstables.read().await;
+            let created_at = tables.iter().map(|s| s.created_at).max().unwrap();
 
-            let mut merged_sst: Box<dyn InsertableToBucket> = Box::new(tables.first().unwrap().to_owned());
+            // entries of flushed sstables are not kept in memory, so they are loaded for every table
+            let mut first_sst = tables.first().unwrap().to_owned();
+            first_sst
+                .load_entries_from_file()
+                .await
+                .map_err(|err| CompactionFailed(Box::new(err)))?;
+            let mut merged_sst: Box<dyn InsertableToBucket> = Box::new(first_sst);
             for sst in tables[1..].iter() {
                 let mut insertable_sst = sst.to_owned();
                 hotness += insertable_sst.hotness;
@@ -188,10 +304,36 @@ impl<'a> SizedTierRunner<'a> {
                 // });
                 merged_sst = self.merge_sstables(merged_sst, Box::new(insertable_sst));
             }
-            let entries = &merged_sst.get_entries();
-            let mut filter = BloomFilter::new(self.config.filter_false_positive, entries.len());
-            filter.build_filter_from_entries(entries);
-            merged_ssts.push(MergedSSTable::new(merged_sst, filter, hotness));
+            let merged_entries = merged_sst.get_entries();
+            let outputs = match &self.config.policy {
+                Some(policy) => split_entries(&merged_entries, policy.as_ref()),
+                None => vec![merged_entries],
+            };
+            // merged keys are dropped by now, tombstones are kept for keys of sstables not merged yet
+            let (mut range_tombstones, expired): (Vec<_>, Vec<_>) = tables
+                .iter()
+                .flat_map(|s| s.range_tombstones.iter().cloned())
+                .partition(|t| !t.has_expired(self.config.tombstone_ttl));
+            self.config.range_tombstones.remove(&expired);
+            for entries in outputs {
+                let false_positive_rate = match self.config.filter_memory_budget {
+                    Some(budget) => {
+                        let merged_dirs: Vec<_> = tables.iter().map(|s| s.dir.to_owned()).collect();
+                        let other_tables = self.key_range.entry_counts(&merged_dirs).await;
+                        monkey::false_positive_rate(&other_tables, entries.len(), budget)
+                    }
+                    None => self.config.filter_false_positive,
+                };
+                let filter = BloomFilter::new(false_positive_rate, entries.len())
+                    .build_from_entries(entries.clone(), self.config.offload_cpu_work)
+                    .await
+                    .map_err(|err| CompactionFailed(Box::new(err)))?;
+                let output: Box<dyn InsertableToBucket> = Box::new(
+                    TableInsertor::from(entries, &filter)
+                        .with_range_tombstones(std::mem::take(&mut range_tombstones)),
+                );
+                merged_ssts.push(MergedSSTable::new(output, filter, hotness, created_at));
+            }
         }
         if merged_ssts.is_empty() {
             return Err(CompactionFailed(Box::new(MergeSSTContainsZeroEntries)));
@@ -281,6 +423,32 @@ impl<'a> SizedTierRunner<'a> {
         Box::new(new_sst)
     }
 
+    /// Returns records of the expiry index whose TTL and the tombstone grace period after it passed
+    async fn expired_versions(&self) -> HashMap<Key, Expiry> {
+        let Some(expiry) = &self.config.expiry else {
+            return HashMap::new();
+        };
+        let cutoff = (Utc::now().timestamp_millis() as u64)
+            .saturating_sub(self.config.tombstone_ttl.as_millis() as u64);
+        expiry
+            .due(cutoff)
+            .await
+            .into_iter()
+            .map(|expiry| (expiry.key.to_owned(), expiry))
+            .collect()
+    }
+
+    /// Returns true if live `entry` expired, by `entry_ttl` or its own TTL, and
+    /// the tombstone grace period after it passed
+    fn has_expired(&self, entry: &Entry<Key, usize>) -> bool {
+        let grace = self.config.tombstone_ttl;
+        self.config.use_ttl && entry.has_expired(self.config.entry_ttl.saturating_add(grace))
+            || self
+                .expired
+                .get(&entry.key)
+                .is_some_and(|expiry| expiry.is_version(entry.created_at))
+    }
+
     /// Checks if an entry has been deleted or not
     ///
     /// Deleted entries are discoverd using the tombstones hashmap
@@ -292,6 +460,10 @@ impl<'a> SizedTierRunner<'a> {
         entry: &Entry<Key, usize>,
         merged_entries: &mut Vec<Entry<Key, usize>>,
     ) {
+        // versions deleted by a range are dropped whether newer versions were merged or not
+        if self.config.range_tombstones.covers(&entry.key, entry.created_at) {
+            return;
+        }
         let mut should_insert = false;
         if self.tombstones.contains_key(&entry.key) {
             let tomb_insert_time = *self.tombstones.get(&entry.key).unwrap();
@@ -299,22 +471,37 @@ impl<'a> SizedTierRunner<'a> {
                 if entry.is_tombstone {
                     self.tombstones.insert(entry.key.to_owned(), entry.created_at);
                     should_insert = !entry.to_owned().has_expired(self.config.tombstone_ttl);
-                } else if self.config.use_ttl {
-                    should_insert = !entry.has_expired(self.config.entry_ttl);
                 } else {
-                    should_insert = true
+                    should_insert = !self.has_expired(entry);
                 }
             }
         } else if entry.is_tombstone {
             self.tombstones.insert(entry.key.to_owned(), entry.created_at);
             should_insert = !entry.has_expired(self.config.tombstone_ttl);
-        } else if self.config.use_ttl {
-            should_insert = !entry.has_expired(self.config.entry_ttl);
         } else {
-            should_insert = true
+            should_insert = !self.has_expired(entry);
         }
         if should_insert {
             merged_entries.push(entry.clone())
         }
     }
 }
+
+/// Splits merged `entries` into the sstables to write, wherever `policy` starts a new one
+fn split_entries(entries: &SkipMapEntries<Key>, policy: &dyn CompactionPolicy) -> Vec<SkipMapEntries<Key>> {
+    let mut outputs = Vec::new();
+    let mut output: SkipMapEntries<Key> = Arc::new(SkipMap::new());
+    let mut previous: Option<Key> = None;
+    for entry in entries.iter() {
+        if previous
+            .as_ref()
+            .is_some_and(|previous| policy.split_between(previous, entry.key()))
+        {
+            outputs.push(std::mem::replace(&mut output, Arc::new(SkipMap::new())));
+        }
+        output.insert(entry.key().to_owned(), entry.value().to_owned());
+        previous = Some(entry.key().to_owned());
+    }
+    outputs.push(output);
+    outputs
+}
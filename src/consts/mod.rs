@@ -4,18 +4,45 @@ use crate::db::SizeUnit;
 
 pub const KB: usize = 1024;
 
+/// Largest key accepted unless `Config::max_key_size` raises it
 pub const MAX_KEY_SIZE: usize = 65536;
 
+/// Largest key the on-disk formats can hold, lengths are stored as u32
+pub const MAX_ENCODED_KEY_SIZE: usize = u32::MAX as usize;
+
+pub const DEFAULT_MAX_KEY_SIZE: usize = MAX_KEY_SIZE;
+
 pub const MAX_KEY_SPACE_SIZE: usize = 255;
 
-pub const MAX_VALUE_SIZE: usize = (1u64 << 32) as usize; // 2^32
+/// Largest value the value log can hold, value lengths are encoded as u32
+pub const MAX_VALUE_SIZE: usize = u32::MAX as usize;
+
+pub const DEFAULT_MAX_VALUE_SIZE: usize = MAX_VALUE_SIZE;
 
-pub const DEFAULT_FLUSH_SIGNAL_CHANNEL_SIZE: usize = 1;
+pub const DEFAULT_FLUSH_SIGNAL_CHANNEL_SIZE: usize = 64;
+
+/// Garbage collection passes buffered for each subscriber
+pub const DEFAULT_GC_EVENT_CHANNEL_SIZE: usize = 64;
 
 pub const DEFAULT_MAX_WRITE_BUFFER_NUMBER: usize = 2;
 
+/// Flushed memtables kept in memory by default, none
+pub const DEFAULT_RETAINED_MEMTABLES: usize = 0;
+
+pub const DEFAULT_MAX_BACKGROUND_FLUSHES: usize = 4;
+
+pub const DEFAULT_MAX_BACKGROUND_COMPACTIONS: usize = 2;
+
+pub const DEFAULT_MAX_BACKGROUND_GC: usize = 1;
+
 pub const DEFAULT_FALSE_POSITIVE_RATE: f64 = 1e-4;
 
+/// Lowest false positive rate given to a filter within a filter memory budget
+pub const MIN_MONKEY_FALSE_POSITIVE_RATE: f64 = 1e-9;
+
+/// Highest false positive rate given to a filter within a filter memory budget
+pub const MAX_MONKEY_FALSE_POSITIVE_RATE: f64 = 0.5;
+
 pub const VALUE_LOG_DIRECTORY_NAME: &str = "v_log";
 
 pub const BUCKETS_DIRECTORY_NAME: &str = "buckets";
@@ -24,6 +51,8 @@ pub const BUCKET_DIRECTORY_PREFIX: &str = "bucket";
 
 pub const VLOG_FILE_NAME: &str = "val_log.bin";
 
+pub const WAL_FILE_NAME: &str = "wal.bin";
+
 pub const FILTER_FILE_NAME: &str = "filter";
 
 pub const DATA_FILE_NAME: &str = "data";
@@ -38,12 +67,117 @@ pub const DEFAULT_DB_NAME: &str = "velarix";
 
 pub const META_DIRECTORY_NAME: &str = "meta";
 
+pub const STORE_INFO_FILE_NAME: &str = "VERSION";
+
+pub const MIGRATION_FILE_NAME: &str = "MIGRATION";
+
+pub const GC_JOURNAL_FILE_NAME: &str = "GC_JOURNAL";
+
+pub const GC_PUNCH_MARKER_FILE_NAME: &str = "GC_PUNCH_MARKER";
+
+pub const COMPACTION_JOURNAL_FILE_NAME: &str = "COMPACTION";
+
+pub const EXPORT_MANIFEST_FILE_NAME: &str = "MANIFEST";
+
+pub const BUCKET_MANIFEST_FILE_NAME: &str = "BUCKETS";
+
+pub const EXPIRY_INDEX_FILE_NAME: &str = "EXPIRY_INDEX";
+
+pub const STATS_FILE_NAME: &str = "STATS";
+
+/// Request ids a memtable keeps for flush failure logs
+pub const MAX_MEMTABLE_CONTEXTS: usize = 16;
+
+/// Panics of background tasks kept by `Env`
+pub const MAX_BACKGROUND_ERRORS: usize = 64;
+
+pub const SUPERVISOR_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+pub const SUPERVISOR_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// 256MB
+pub const DEFAULT_MIN_FREE_DISK_SPACE: u64 = SizeUnit::Megabytes.as_bytes(256) as u64;
+
+/// Background task panics older than this don't degrade health
+pub const HEALTH_BACKGROUND_ERROR_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Entries relocated by garbage collection, not yet synced with the store, above which health is degraded
+pub const HEALTH_MAX_PENDING_GC_ENTRIES: usize = 100_000;
+
+/// On-disk format version written by this build
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Format version of stores created before the store-info file existed
+pub const LEGACY_FORMAT_VERSION: u32 = 1;
+
 pub const TOMB_STONE_MARKER: &str = "*";
 
+/// Bit in the flags byte of a value log entry that marks a deleted entry
+pub const VLOG_TOMBSTONE_FLAG: u8 = 0b01;
+
+/// Bit in the flags byte of a value log entry that marks a checksum after the value
+pub const VLOG_CHECKSUM_FLAG: u8 = 0b10;
+
+/// Bit in the flags byte of a value log entry that marks application metadata after the value
+pub const VLOG_METADATA_FLAG: u8 = 0b100;
+
+/// Bit in the flags byte of a value log entry that marks a deduplicated value shared by
+/// references, its key is the content hash of the value
+pub const VLOG_BLOB_FLAG: u8 = 0b1000;
+
+/// Bit in the flags byte of a value log entry whose value is the content hash of a blob
+pub const VLOG_REFERENCE_FLAG: u8 = 0b1_0000;
+
+/// Bit in the flags byte of a value log entry that deletes keys from its key up to its value
+pub const VLOG_RANGE_TOMBSTONE_FLAG: u8 = 0b10_0000;
+
+/// Bit set in offsets of write-ahead log entries, value log offsets never reach it
+pub const WAL_OFFSET_FLAG: usize = 1 << 62;
+
+/// Bits of a write-ahead log offset below it hold the position in the log, the ones
+/// above up to `WAL_OFFSET_FLAG` the number of times the log was cleared
+pub const WAL_GENERATION_SHIFT: u32 = 36;
+
+/// Smallest value worth deduplicating, a reference entry holds an 8 byte hash
+pub const MIN_DEDUP_VALUE_SIZE: usize = 64;
+
+/// Bytes of value log read at once while the deduplication index is rebuilt
+pub const DEDUP_REBUILD_CHUNK_SIZE: usize = SizeUnit::Megabytes.as_bytes(1);
+
+/// Largest application metadata stored with a value, its length is stored as u8
+pub const MAX_METADATA_SIZE: usize = u8::MAX as usize;
+
+/// Marks entry count and `created_at` range after the keys of a summary file
+pub const SUMMARY_STATS_MAGIC: u32 = 0x5354_4154;
+
+/// Marks the prefix sketch after the stats of a summary file
+pub const PREFIX_SKETCH_MAGIC: u32 = 0x5846_5250;
+
+/// Marks the range tombstones after the prefix sketch of a summary file
+pub const RANGE_TOMBSTONES_MAGIC: u32 = 0x5447_4e52;
+
+/// Counters per row of the prefix sketch of an sstable
+pub const PREFIX_SKETCH_WIDTH: usize = 2048;
+
+/// Rows of the prefix sketch of an sstable, each addressed by a different hash
+pub const PREFIX_SKETCH_DEPTH: usize = 4;
+
+/// Longest key prefix counted by the prefix sketch, longer prefixes are truncated
+pub const MAX_SKETCH_PREFIX_LEN: usize = 16;
+
+/// Marks the checksum at the end of an index file
+pub const INDEX_CHECKSUM_MAGIC: u32 = 0x5844_4e49;
+
+/// Marks the tombstone bitmap and checksum at the end of an index file
+pub const INDEX_TOMBSTONE_MAGIC: u32 = 0x424d_4f54;
+
 /// TODO: Many lightweight computations here, benchmark with Lazy initialization
 /// 1KB
 pub static GC_CHUNK_SIZE: usize = SizeUnit::Kilobytes.as_bytes(1);
 
+/// 16MB, largest garbage collection chunk under disk pressure by default
+pub const DEFAULT_GC_PRESSURE_MAX_CHUNK_SIZE: usize = SizeUnit::Megabytes.as_bytes(16);
+
 /// 50KB
 pub const WRITE_BUFFER_SIZE: usize = SizeUnit::Kilobytes.as_bytes(50);
 
@@ -56,6 +190,15 @@ pub const DEFAULT_COMPACTION_INTERVAL: Duration = Duration::from_millis(1000 * 6
 /// 5 Min
 pub const DEFAULT_COMPACTION_FLUSH_LISTNER_INTERVAL: Duration = Duration::from_millis(1000 * 60 * 5);
 
+/// 10 Milliseconds
+pub const BACKGROUND_JOB_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Longest a background job waits for high priority reads before it starts
+pub const MAX_PRIORITY_READ_WAIT: Duration = Duration::from_millis(100);
+
+/// Background loops wait their interval give or take 10%
+pub const DEFAULT_BACKGROUND_JITTER: f64 = 0.1;
+
 /// 10 hours
 pub const DEFAULT_ONLINE_GC_INTERVAL: Duration = Duration::from_millis(10 * 1000 * 60 * 60);
 
@@ -68,6 +211,27 @@ pub const DEFAULT_TOMBSTONE_TTL: Duration = Duration::from_millis(120 * 86400000
 
 pub const DEFAULT_ENABLE_TTL: bool = false;
 
+/// 1 minute
+pub const DEFAULT_EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Stripes of the lock table of transactions, keys are spread over them by hash
+pub const LOCK_TABLE_STRIPES: usize = 64;
+
+/// 5 seconds
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Keys written with a TTL deleted by one pass of the expiry job
+pub const DEFAULT_EXPIRY_BATCH_SIZE: usize = 1000;
+
+/// 1 minute
+pub const DEFAULT_STATS_PERSIST_INTERVAL: Duration = Duration::from_secs(60);
+
+/// SSTables merged from buckets with hotness up to this value can be moved to cold storage
+pub const DEFAULT_COLD_STORAGE_MAX_HOTNESS: u64 = 8;
+
+/// 7 days
+pub const DEFAULT_COLD_STORAGE_MIN_AGE: Duration = Duration::from_millis(7 * 86400000);
+
 pub const BUCKET_LOW: f64 = 0.5;
 
 pub const BUCKET_HIGH: f64 = 1.5;
@@ -102,8 +266,22 @@ pub const SIZE_OF_U64: usize = std::mem::size_of::<u64>();
 
 pub const SIZE_OF_U8: usize = std::mem::size_of::<u8>();
 
-pub const FLUSH_SIGNAL: u8 = 1;
-
 pub const BLOCK_SIZE: usize = 4 * 1024; // 4KB
 
+/// Filter metadata (hash functions, elements, false positive rate) and its checksum,
+/// the bit vector is stored after it
+pub const FILTER_META_SIZE: usize = SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U32;
+
+/// 8MB
+pub const DEFAULT_BLOCK_CACHE_CAPACITY: usize = SizeUnit::Megabytes.as_bytes(8);
+
+/// 4MB
+pub const DEFAULT_FILTER_CACHE_CAPACITY: usize = SizeUnit::Megabytes.as_bytes(4);
+
+/// Fraction of the combined block and filter cache capacity moved by one rebalance
+pub const CACHE_REBALANCE_STEP: f64 = 0.05;
+
+/// Fraction of the combined block and filter cache capacity each cache keeps when rebalanced
+pub const CACHE_REBALANCE_MIN_SHARE: f64 = 0.1;
+
 pub const VLOG_START_OFFSET: usize = 0;
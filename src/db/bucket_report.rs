@@ -0,0 +1,114 @@
+use crate::{
+    bucket::{Bucket, BucketID},
+    compactors::SizedTierRunner,
+    consts::MIN_TRESHOLD,
+    db::DataStore,
+    err::Error,
+    memtable::Val,
+    types::Key,
+};
+use std::path::PathBuf;
+
+/// State of one bucket as compaction sees it, returned by [`DataStore::describe_buckets`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BucketReport {
+    /// Bucket id
+    pub id: BucketID,
+
+    /// Bucket directory
+    pub dir: PathBuf,
+
+    /// Average size of data files of the sstables in the bucket
+    pub average_size: usize,
+
+    /// SSTables in the bucket
+    pub sstable_count: usize,
+
+    /// SSTables a bucket needs before the size-tiered strategy merges them
+    pub threshold: usize,
+
+    /// Hotness of the sstables in the bucket
+    pub hotness: HotnessDistribution,
+
+    /// Whether the next compaction merges sstables of the bucket, picked by
+    /// `threshold` or by `Config::compaction_policy` if one is set
+    pub needs_compaction: bool,
+}
+
+/// Smallest, median and biggest hotness of sstables in a bucket, all zero for an empty bucket
+///
+/// Hotness of an sstable is raised whenever its bucket receives an sstable,
+/// cold storage moves merged sstables whose hotness stays low.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HotnessDistribution {
+    pub min: u64,
+    pub median: u64,
+    pub max: u64,
+}
+
+impl HotnessDistribution {
+    fn from_hotness(mut hotness: Vec<u64>) -> Self {
+        if hotness.is_empty() {
+            return Self::default();
+        }
+        hotness.sort_unstable();
+        Self {
+            min: hotness[0],
+            median: hotness[hotness.len() / 2],
+            max: hotness[hotness.len() - 1],
+        }
+    }
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Returns report of every bucket, to diagnose why compaction does or doesn't run
+    ///
+    /// Buckets needing compaction are the ones [`DataStore::plan_compaction`]
+    /// plans merges for. Only file sizes are read.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+    ///
+    ///     for bucket in store.describe_buckets().await.unwrap() {
+    ///         println!(
+    ///             "bucket {}: {}/{} sstables, compaction {}",
+    ///             bucket.id,
+    ///             bucket.sstable_count,
+    ///             bucket.threshold,
+    ///             if bucket.needs_compaction { "pending" } else { "not needed" }
+    ///         );
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if file metadata could not be read
+    pub async fn describe_buckets(&self) -> Result<Vec<BucketReport>, Error> {
+        // read lock keeps compaction from deleting files while they are measured
+        let bucket_map = self.buckets.read().await;
+        let (_, selected) = SizedTierRunner::select_inputs(&bucket_map, &self.compactor.config).await?;
+        let mut reports = Vec::with_capacity(bucket_map.buckets.len());
+        for (id, bucket) in bucket_map.buckets.iter() {
+            let sstables = bucket.sstables.read().await.clone();
+            reports.push(BucketReport {
+                id: *id,
+                dir: bucket.dir.to_owned(),
+                sstable_count: sstables.len(),
+                threshold: MIN_TRESHOLD,
+                hotness: HotnessDistribution::from_hotness(sstables.iter().map(|sst| sst.hotness).collect()),
+                needs_compaction: selected.iter().any(|(selected, _)| selected == id),
+                average_size: Bucket::cal_average_size(sstables).await?,
+            });
+        }
+        Ok(reports)
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{
+    bucket::BucketID,
+    compactors::SizedTierRunner,
+    db::{DataStore, SSTableUsage},
+    err::Error,
+    memtable::Val,
+    types::Key,
+};
+
+/// Merges [`DataStore::run_compaction`] would start with
+///
+/// Returned by [`DataStore::plan_compaction`]. Only the first round is
+/// planned, sstables it writes may be merged again by later rounds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompactionPlan {
+    /// SSTables merged together, per bucket
+    pub merges: Vec<PlannedMerge>,
+}
+
+/// SSTables of one bucket that would be merged together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedMerge {
+    /// Bucket holding the sstables
+    pub bucket: BucketID,
+
+    /// Usage of every sstable that would be merged
+    pub sstables: Vec<SSTableUsage>,
+
+    /// Bytes of merged sstable files, an upper bound since overwritten
+    /// entries and expired tombstones are dropped
+    pub expected_output: u64,
+}
+
+impl CompactionPlan {
+    /// Checks if compaction has nothing to do
+    pub fn is_empty(&self) -> bool {
+        self.merges.is_empty()
+    }
+
+    /// Returns bytes read by all merges
+    pub fn estimated_read_bytes(&self) -> u64 {
+        self.merges.iter().map(PlannedMerge::read_bytes).sum()
+    }
+
+    /// Returns bytes written by all merges, at most
+    pub fn estimated_write_bytes(&self) -> u64 {
+        self.merges.iter().map(|merge| merge.expected_output).sum()
+    }
+}
+
+impl PlannedMerge {
+    /// Returns bytes read by the merge, data files are read whole
+    pub fn read_bytes(&self) -> u64 {
+        self.sstables.iter().map(|sst| sst.data).sum()
+    }
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Returns sstables the next compaction would merge, without merging them
+    ///
+    /// SSTables are picked the way [`DataStore::run_compaction`] picks them,
+    /// by the compaction strategy or `Config::compaction_policy`. Only file
+    /// sizes are read.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let mut store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+    ///
+    ///     let plan = store.plan_compaction().await.unwrap();
+    ///     for merge in &plan.merges {
+    ///         println!("bucket {}: {} sstables", merge.bucket, merge.sstables.len());
+    ///     }
+    ///     println!(
+    ///         "reads {} bytes, writes up to {} bytes",
+    ///         plan.estimated_read_bytes(),
+    ///         plan.estimated_write_bytes()
+    ///     );
+    ///     if !plan.is_empty() {
+    ///         store.run_compaction().await.unwrap();
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if file metadata could not be read
+    pub async fn plan_compaction(&self) -> Result<CompactionPlan, Error> {
+        // read lock keeps compaction from deleting files while they are measured
+        let bucket_map = self.buckets.read().await;
+        let (_, ssts_to_merge) = SizedTierRunner::select_inputs(&bucket_map, &self.compactor.config).await?;
+        let mut merges = Vec::with_capacity(ssts_to_merge.len());
+        for (bucket, ssts) in ssts_to_merge {
+            let mut sstables = Vec::with_capacity(ssts.len());
+            for sst in ssts.iter() {
+                let (smallest_key, biggest_key) = sst
+                    .summary
+                    .as_ref()
+                    .map(|s| (s.smallest_key.to_owned(), s.biggest_key.to_owned()))
+                    .unwrap_or_default();
+                sstables.push(
+                    SSTableUsage::read(
+                        sst.dir.to_owned(),
+                        &sst.data_file.path,
+                        &sst.index_file.path,
+                        smallest_key,
+                        biggest_key,
+                    )
+                    .await?,
+                );
+            }
+            merges.push(PlannedMerge {
+                bucket,
+                expected_output: sstables.iter().map(SSTableUsage::total).sum(),
+                sstables,
+            });
+        }
+        Ok(CompactionPlan { merges })
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{
+    consts::{HEAD_ENTRY_KEY, TAIL_ENTRY_KEY},
+    db::DataStore,
+    err::Error,
+    fs::DataFs,
+    index::Index,
+    memtable::Val,
+    types::{CreatedAt, Key, ValOffset},
+};
+use rand::Rng;
+use std::path::PathBuf;
+
+/// Result of [`DataStore::verify_consistency`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsistencyReport {
+    /// SSTables whose entries were read
+    pub sstables: usize,
+
+    /// SSTable entries checked against the value log
+    pub checked: usize,
+
+    /// Checked entries pointing to a value log region reclaimed by garbage
+    /// collection, that have a newer version and need no value
+    pub superseded: usize,
+
+    /// Entries whose offset does not lead to their value
+    pub inconsistencies: Vec<Inconsistency>,
+}
+
+impl ConsistencyReport {
+    /// Checks if no inconsistency was found
+    pub fn is_consistent(&self) -> bool {
+        self.inconsistencies.is_empty()
+    }
+}
+
+/// SSTable entry whose offset does not lead to its value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inconsistency {
+    /// SSTable directory
+    pub sstable: PathBuf,
+
+    /// Key of the entry
+    pub key: Vec<u8>,
+
+    /// Value log offset stored with the entry
+    pub offset: ValOffset,
+
+    /// What was found at `offset`
+    pub kind: InconsistencyKind,
+}
+
+/// What an inconsistent SSTable entry points to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InconsistencyKind {
+    /// Offset is past the end of the value log
+    Missing,
+
+    /// Newest version of the key points to a region reclaimed by garbage collection
+    Reclaimed,
+
+    /// Value log entry at the offset belongs to another key
+    KeyMismatch {
+        /// Key of the value log entry
+        found: Vec<u8>,
+    },
+
+    /// Value log entry at the offset can not be decoded or does not match its checksum
+    Corrupt,
+}
+
+/// SSTable entry picked for checking
+struct Candidate {
+    sstable: PathBuf,
+    key: Key,
+    offset: ValOffset,
+    created_at: CreatedAt,
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Checks that SSTable entries point to value log entries of their own key
+    ///
+    /// With `sample` of `None` every entry of every SSTable is checked, otherwise
+    /// `sample` entries picked at random are. Entries pointing below the value log
+    /// tail are only reported if they are the newest version of their key, older
+    /// versions are expected to lose their value to garbage collection.
+    ///
+    /// Meant to catch value log rewrites that were not reflected in SSTables.
+    /// Garbage collection running during the check can make entries look
+    /// inconsistent, so results are only exact while it is idle.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+    ///
+    ///     let report = store.verify_consistency(Some(1000)).await.unwrap();
+    ///     assert!(report.is_consistent());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if a file could not be read
+    pub async fn verify_consistency(&self, sample: Option<usize>) -> Result<ConsistencyReport, Error> {
+        let (_pin, tables) = {
+            let buckets = self.buckets.read().await;
+            let mut tables = Vec::new();
+            for bucket in buckets.buckets.values() {
+                tables.extend(bucket.sstables.read().await.iter().cloned());
+            }
+            (buckets.pins.pin(), tables)
+        };
+        let mut report = ConsistencyReport {
+            sstables: tables.len(),
+            ..Default::default()
+        };
+
+        // reservoir sampling keeps every entry equally likely to be checked
+        let mut rng = rand::thread_rng();
+        let mut seen = 0;
+        let mut candidates = Vec::new();
+        for table in tables.iter() {
+            let (entries, _) = table.data_file.file.load_entries().await?;
+            for e in entries.iter() {
+                if e.key().as_slice() == HEAD_ENTRY_KEY || e.key().as_slice() == TAIL_ENTRY_KEY {
+                    continue;
+                }
+                let candidate = Candidate {
+                    sstable: table.dir.to_owned(),
+                    key: e.key().to_owned(),
+                    offset: e.value().val_offset,
+                    created_at: e.value().created_at,
+                };
+                seen += 1;
+                match sample {
+                    None => self.check_candidate(candidate, &mut report).await?,
+                    Some(size) if candidates.len() < size => candidates.push(candidate),
+                    Some(_) => {
+                        let idx = rng.gen_range(0..seen);
+                        if idx < candidates.len() {
+                            candidates[idx] = candidate;
+                        }
+                    }
+                }
+            }
+        }
+        for candidate in candidates {
+            self.check_candidate(candidate, &mut report).await?;
+        }
+        Ok(report)
+    }
+
+    /// Checks value log entry `candidate` points to and adds the result to `report`
+    async fn check_candidate(
+        &self,
+        candidate: Candidate,
+        report: &mut ConsistencyReport,
+    ) -> Result<(), Error> {
+        report.checked += 1;
+        let kind = if candidate.offset < self.val_log.tail_offset {
+            if self.has_newer_version(&candidate).await? {
+                report.superseded += 1;
+                return Ok(());
+            }
+            InconsistencyKind::Reclaimed
+        } else {
+            match self.val_log.entry_at(candidate.offset).await {
+                Ok(Some(entry)) if entry.key == candidate.key => return Ok(()),
+                Ok(Some(entry)) => InconsistencyKind::KeyMismatch { found: entry.key },
+                Ok(None) => InconsistencyKind::Missing,
+                Err(Error::ChecksumMismatch { .. } | Error::UnexpectedEOF(_)) => InconsistencyKind::Corrupt,
+                Err(err) => return Err(err),
+            }
+        };
+        report.inconsistencies.push(Inconsistency {
+            sstable: candidate.sstable,
+            key: candidate.key,
+            offset: candidate.offset,
+            kind,
+        });
+        Ok(())
+    }
+
+    /// Checks if another version of the key of `candidate` is at least as new
+    ///
+    /// SSTables store `created_at` in milliseconds, so versions are compared at
+    /// that precision and a version of the same millisecond counts as newer
+    async fn has_newer_version(&self, candidate: &Candidate) -> Result<bool, Error> {
+        let key = candidate.key.as_slice();
+        let is_newer = |offset: ValOffset, created_at: CreatedAt| {
+            offset != candidate.offset
+                && created_at.timestamp_millis() >= candidate.created_at.timestamp_millis()
+        };
+        let in_memory = self
+            .active_memtable
+            .get(key)
+            .into_iter()
+            .chain(self.read_only_memtables.iter().filter_map(|m| m.value().get(key)))
+            .chain(
+                self.gc_updated_entries
+                    .read()
+                    .await
+                    .get(key)
+                    .map(|e| e.value().to_owned()),
+            )
+            .any(|v| is_newer(v.val_offset, v.created_at));
+        if in_memory {
+            return Ok(true);
+        }
+        for sst in self.key_range.filter_sstables_by_key_range(key).await? {
+            let index = Index::new(sst.index_file.path.to_owned(), sst.index_file.file.to_owned());
+            if let Some(block_handle) = index.get(key).await? {
+                let sst_res = sst
+                    .get(
+                        block_handle,
+                        key,
+                        &self.config.block_cache,
+                        &self.config.io_retry,
+                        self.config.read_pool.as_ref(),
+                        true,
+                    )
+                    .await?;
+                if matches!(sst_res, Some((offset, created_at, _)) if is_newer(offset, created_at)) {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{
+    db::DataStore,
+    err::Error,
+    memtable::{UserEntry, Val},
+    range::RangeIterator,
+    types::{Bool, Key},
+};
+use std::fmt;
+
+/// Identifies the request an operation belongs to in logs
+///
+/// Passed to [`DataStore::put_with_context`](crate::db::DataStore::put_with_context)
+/// and similar methods. Failures of the operation are logged with its request id,
+/// and a memtable remembers ids of writes it holds, so a background flush that
+/// fails logs which requests wrote the data.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OpContext {
+    request_id: String,
+}
+
+impl OpContext {
+    /// Creates context of request `request_id`
+    pub fn new(request_id: impl Into<String>) -> Self {
+        Self {
+            request_id: request_id.into(),
+        }
+    }
+
+    /// Returns request id
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+}
+
+impl fmt::Display for OpContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "request_id={}", self.request_id)
+    }
+}
+
+/// Formats `contexts` for a log line, empty if there are none
+pub(crate) fn describe(contexts: &[OpContext]) -> String {
+    if contexts.is_empty() {
+        return String::new();
+    }
+    let ids: Vec<&str> = contexts.iter().map(OpContext::request_id).collect();
+    format!(" (request_ids={})", ids.join(","))
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Same as [`DataStore::put`], but logs failure of the write with `context`
+    ///
+    /// The memtable holding the entry remembers `context`, so a failed background
+    /// flush of it is logged with the request id too.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::{DataStore, OpContext};
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let mut store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+    ///
+    ///     let context = OpContext::new("req-42");
+    ///     store.put_with_context("apple", "tim cook", &context).await.unwrap();
+    ///     let entry = store.get_with_context("apple", &context).await.unwrap();
+    ///     assert_eq!(entry.unwrap().val, b"tim cook".to_vec());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or key or value size is invalid.
+    pub async fn put_with_context(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        val: impl AsRef<[u8]>,
+        context: &OpContext,
+    ) -> Result<Bool, Error> {
+        let res = self.put(key, val).await;
+        match &res {
+            Ok(_) => self.active_memtable.record_context(context),
+            Err(err) => log::error!("Write failed: {} ({})", err, context),
+        }
+        res
+    }
+
+    /// Same as [`DataStore::get`], but logs failure of the read with `context`
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn get_with_context<T: AsRef<[u8]>>(
+        &self,
+        key: T,
+        context: &OpContext,
+    ) -> Result<Option<UserEntry<V>>, Error> {
+        self.get(key)
+            .await
+            .inspect_err(|err| log::error!("Read failed: {} ({})", err, context))
+    }
+
+    /// Same as [`DataStore::seek`], but logs failure to create the iterator with `context`
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an sstable could not be read
+    pub async fn seek_with_context<T: AsRef<[u8]>>(
+        &self,
+        start: T,
+        end: T,
+        context: &OpContext,
+    ) -> Result<RangeIterator, Error> {
+        self.seek(start, end)
+            .await
+            .inspect_err(|err| log::error!("Range scan failed: {} ({})", err, context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_contexts() {
+        assert_eq!(describe(&[]), "");
+        let contexts = [OpContext::new("req-1"), OpContext::new("req-2")];
+        assert_eq!(describe(&contexts), " (request_ids=req-1,req-2)");
+        assert_eq!(contexts[0].to_string(), "request_id=req-1");
+    }
+}
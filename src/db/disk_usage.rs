@@ -0,0 +1,159 @@
+use crate::bucket::BucketID;
+use crate::consts::{FILTER_FILE_NAME, SUMMARY_FILE_NAME};
+use crate::err::Error;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// Bytes on disk used by a [`DataStore`](crate::db::DataStore)
+///
+/// Returned by [`DataStore::disk_usage`](crate::db::DataStore::disk_usage).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskUsage {
+    /// Usage of every bucket
+    pub buckets: Vec<BucketUsage>,
+
+    /// Usage of value log
+    pub vlog: VlogUsage,
+}
+
+/// Bytes used by sstables of one bucket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BucketUsage {
+    /// Bucket id
+    pub id: BucketID,
+
+    /// Bucket directory
+    pub dir: PathBuf,
+
+    /// Usage of every sstable in the bucket
+    pub sstables: Vec<SSTableUsage>,
+}
+
+/// Bytes used by files of one sstable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SSTableUsage {
+    /// SSTable directory
+    pub dir: PathBuf,
+
+    /// Smallest key in the sstable
+    pub smallest_key: Vec<u8>,
+
+    /// Biggest key in the sstable
+    pub biggest_key: Vec<u8>,
+
+    /// Size of data file
+    pub data: u64,
+
+    /// Size of index file
+    pub index: u64,
+
+    /// Size of bloom filter file
+    pub filter: u64,
+
+    /// Size of summary file
+    pub summary: u64,
+}
+
+/// Bytes used by value log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VlogUsage {
+    /// Value log file
+    pub path: PathBuf,
+
+    /// Bytes from the tail to the end of value log
+    pub live: u64,
+
+    /// Bytes before the tail, already reclaimed by garbage collection
+    pub punched: u64,
+}
+
+impl DiskUsage {
+    /// Returns bytes used by all sstables
+    pub fn sstables(&self) -> u64 {
+        self.buckets.iter().map(BucketUsage::total).sum()
+    }
+
+    /// Returns bytes used by sstables and live part of value log
+    pub fn total(&self) -> u64 {
+        self.sstables() + self.vlog.live
+    }
+
+    /// Returns sstables whose key range overlaps `start..=end`
+    pub fn in_range<'a>(
+        &'a self,
+        start: &'a [u8],
+        end: &'a [u8],
+    ) -> impl Iterator<Item = &'a SSTableUsage> + 'a {
+        self.buckets
+            .iter()
+            .flat_map(|bucket| bucket.sstables.iter())
+            .filter(move |sst| sst.smallest_key.as_slice() <= end && sst.biggest_key.as_slice() >= start)
+    }
+}
+
+impl BucketUsage {
+    /// Returns bytes used by all sstables in the bucket
+    pub fn total(&self) -> u64 {
+        self.sstables.iter().map(SSTableUsage::total).sum()
+    }
+}
+
+impl SSTableUsage {
+    /// Reads sizes of sstable files in `dir`
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if file metadata could not be read
+    pub(crate) async fn read(
+        dir: PathBuf,
+        data_file: &Path,
+        index_file: &Path,
+        smallest_key: Vec<u8>,
+        biggest_key: Vec<u8>,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            data: file_size(data_file).await?,
+            index: file_size(index_file).await?,
+            filter: file_size(dir.join(format!("{}.db", FILTER_FILE_NAME))).await?,
+            summary: file_size(dir.join(format!("{}.db", SUMMARY_FILE_NAME))).await?,
+            smallest_key,
+            biggest_key,
+            dir,
+        })
+    }
+
+    /// Returns bytes used by all files of the sstable
+    pub fn total(&self) -> u64 {
+        self.data + self.index + self.filter + self.summary
+    }
+}
+
+impl VlogUsage {
+    /// Reads value log size, bytes before `tail_offset` count as punched
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if file metadata could not be read
+    pub(crate) async fn read(path: PathBuf, tail_offset: usize) -> Result<Self, Error> {
+        let size = file_size(&path).await?;
+        let punched = (tail_offset as u64).min(size);
+        Ok(Self {
+            path,
+            live: size - punched,
+            punched,
+        })
+    }
+}
+
+/// Returns size of file, zero if it does not exist
+async fn file_size(path: impl AsRef<Path>) -> Result<u64, Error> {
+    let path = path.as_ref();
+    match fs::metadata(path).await {
+        Ok(meta) => Ok(meta.len()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(error) => Err(Error::GetFileMetaData {
+            path: path.to_path_buf(),
+            error,
+        }),
+    }
+}
@@ -0,0 +1,471 @@
+use crate::{
+    consts::{EXPIRY_INDEX_FILE_NAME, SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8, TOMB_STONE_MARKER},
+    db::DataStore,
+    env::Timer,
+    err::Error::{self, *},
+    fs::FileNode,
+    memtable::Val,
+    types::{Bool, CreatedAt, Key},
+};
+use chrono::Utc;
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::{Mutex, RwLock},
+    task::JoinHandle,
+    time::sleep,
+};
+
+const INSERT: u8 = 0;
+const REMOVE: u8 = 1;
+
+/// Operation, expiry time, version timestamp and key length of an index record
+const RECORD_HEADER_SIZE: usize = SIZE_OF_U8 + SIZE_OF_U64 + SIZE_OF_U64 + SIZE_OF_U32;
+
+/// Key written with a TTL, ordered by the time it expires
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Expiry {
+    /// Milliseconds since the epoch after which the key expires
+    pub(crate) expires_at: u64,
+
+    pub(crate) key: Key,
+
+    /// Nanoseconds since the epoch of the version written with the TTL
+    pub(crate) created_at: u64,
+}
+
+impl Expiry {
+    /// Returns true if `created_at` is the version this record was written for
+    ///
+    /// Versions read from sstables lost their sub-millisecond part, they are
+    /// compared by millisecond
+    pub(crate) fn is_version(&self, created_at: CreatedAt) -> bool {
+        nanos(created_at) == self.created_at
+            || created_at.timestamp_subsec_nanos().is_multiple_of(1_000_000)
+                && millis(created_at) == self.created_at / 1_000_000
+    }
+}
+
+/// Secondary index from expiry time to key, kept in the store directory
+///
+/// Keys written with a TTL are recorded with the time they expire, so keys
+/// due for deletion are found without scanning the store. A key has at most
+/// one record, for its latest write with a TTL, a later write of the key drops
+/// it. The index is an append-only log of inserts and removals, compacted
+/// when the store is opened.
+#[derive(Debug, Clone)]
+pub(crate) struct ExpiryIndex {
+    path: PathBuf,
+    inner: Arc<Mutex<IndexState>>,
+}
+
+#[derive(Debug, Default)]
+struct IndexState {
+    /// Records ordered by expiry
+    entries: BTreeSet<Expiry>,
+
+    /// Record of each key
+    by_key: HashMap<Key, Expiry>,
+
+    /// Records dropped by writes, removed from the index file with the next append
+    forgotten: Vec<Expiry>,
+
+    /// Index file opened for appends on first write
+    file: Option<File>,
+}
+
+impl IndexState {
+    fn insert(&mut self, expiry: Expiry) {
+        if let Some(previous) = self.by_key.insert(expiry.key.to_owned(), expiry.to_owned()) {
+            self.entries.remove(&previous);
+        }
+        self.entries.insert(expiry);
+    }
+
+    fn remove(&mut self, expiry: &Expiry) -> bool {
+        if self.by_key.get(&expiry.key) != Some(expiry) {
+            return false;
+        }
+        self.by_key.remove(&expiry.key);
+        self.entries.remove(expiry)
+    }
+}
+
+impl ExpiryIndex {
+    /// Reads index from store directory `root`, empty if there is none
+    ///
+    /// A record cut short by a crash is dropped
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case of IO error
+    pub(crate) async fn open(root: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = root.as_ref().join(EXPIRY_INDEX_FILE_NAME);
+        let buf = match fs::read(&path).await {
+            Ok(buf) => buf,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(FileRead { path, error: err }),
+        };
+        let (state, records, read) = Self::replay(&buf);
+        if records > state.entries.len() || read < buf.len() {
+            if state.entries.is_empty() {
+                fs::remove_file(&path).await.map_err(|err| FileDelete {
+                    path: path.to_owned(),
+                    error: err,
+                })?;
+            } else {
+                let buf: Vec<u8> = state
+                    .entries
+                    .iter()
+                    .flat_map(|expiry| Self::encode(INSERT, expiry))
+                    .collect();
+                FileNode::write_atomic(&path, &buf).await?;
+            }
+        }
+        Ok(Self {
+            path,
+            inner: Arc::new(Mutex::new(state)),
+        })
+    }
+
+    /// Applies records of `buf`, returns index state, number of records and bytes read
+    fn replay(buf: &[u8]) -> (IndexState, usize, usize) {
+        let mut state = IndexState::default();
+        let mut records = 0;
+        let mut offset = 0;
+        while offset + RECORD_HEADER_SIZE <= buf.len() {
+            let op = buf[offset];
+            let mut field = offset + SIZE_OF_U8;
+            let expires_at = u64::from_le_bytes(buf[field..field + SIZE_OF_U64].try_into().unwrap());
+            field += SIZE_OF_U64;
+            let created_at = u64::from_le_bytes(buf[field..field + SIZE_OF_U64].try_into().unwrap());
+            field += SIZE_OF_U64;
+            let key_len = u32::from_le_bytes(buf[field..field + SIZE_OF_U32].try_into().unwrap()) as usize;
+            field += SIZE_OF_U32;
+            if field + key_len > buf.len() || op > REMOVE {
+                log::warn!("Expiry index ends with an incomplete record, dropping it");
+                break;
+            }
+            let expiry = Expiry {
+                expires_at,
+                key: buf[field..field + key_len].to_vec(),
+                created_at,
+            };
+            if op == INSERT {
+                state.insert(expiry);
+            } else {
+                state.remove(&expiry);
+            }
+            records += 1;
+            offset = field + key_len;
+        }
+        (state, records, offset)
+    }
+
+    fn encode(op: u8, expiry: &Expiry) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(RECORD_HEADER_SIZE + expiry.key.len());
+        buf.push(op);
+        buf.extend_from_slice(&expiry.expires_at.to_le_bytes());
+        buf.extend_from_slice(&expiry.created_at.to_le_bytes());
+        buf.extend_from_slice(&(expiry.key.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&expiry.key);
+        buf
+    }
+
+    /// Appends `records` to the index file, after removals of forgotten records
+    async fn append(&self, state: &mut IndexState, records: Vec<u8>) -> Result<(), Error> {
+        let mut buf: Vec<u8> = state
+            .forgotten
+            .iter()
+            .flat_map(|expiry| Self::encode(REMOVE, expiry))
+            .collect();
+        buf.extend(records);
+        if state.file.is_none() {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .await
+                .map_err(|err| FileOpen {
+                    path: self.path.to_owned(),
+                    error: err,
+                })?;
+            state.file = Some(file);
+        }
+        let file = state.file.as_mut().unwrap();
+        file.write_all(&buf).await.map_err(|err| FileWrite {
+            path: self.path.to_owned(),
+            error: err,
+        })?;
+        file.flush().await.map_err(|err| FileWrite {
+            path: self.path.to_owned(),
+            error: err,
+        })?;
+        state.forgotten.clear();
+        Ok(())
+    }
+
+    /// Records that `expiry.key` written at `expiry.created_at` expires at `expiry.expires_at`
+    ///
+    /// Replaces the previous record of the key
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case of IO error
+    pub(crate) async fn insert(&self, expiry: Expiry) -> Result<(), Error> {
+        let mut state = self.inner.lock().await;
+        self.append(&mut state, Self::encode(INSERT, &expiry)).await?;
+        state.insert(expiry);
+        Ok(())
+    }
+
+    /// Removes `expired` from the index, records replaced since are kept
+    ///
+    /// Records forgotten since the last append are removed from the index file too
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case of IO error
+    pub(crate) async fn remove(&self, expired: &[Expiry]) -> Result<(), Error> {
+        let mut state = self.inner.lock().await;
+        if expired.is_empty() && state.forgotten.is_empty() {
+            return Ok(());
+        }
+        let records: Vec<u8> = expired
+            .iter()
+            .flat_map(|expiry| Self::encode(REMOVE, expiry))
+            .collect();
+        self.append(&mut state, records).await?;
+        for expiry in expired {
+            state.remove(expiry);
+        }
+        Ok(())
+    }
+
+    /// Drops record of `key` unless it is for the version written at `created_at`
+    ///
+    /// Called for every write, the index file is left as is until the next append
+    pub(crate) async fn forget(&self, key: &[u8], created_at: CreatedAt) {
+        let mut state = self.inner.lock().await;
+        match state.by_key.get(key) {
+            Some(expiry) if expiry.created_at != nanos(created_at) => {
+                let expiry = expiry.to_owned();
+                state.remove(&expiry);
+                state.forgotten.push(expiry);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns entries expired at `now`, in milliseconds since the epoch, earliest first
+    pub(crate) async fn due(&self, now: u64) -> Vec<Expiry> {
+        self.inner
+            .lock()
+            .await
+            .entries
+            .iter()
+            .take_while(|expiry| expiry.expires_at <= now)
+            .cloned()
+            .collect()
+    }
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Same as [`DataStore::put`], but the entry expires `ttl` after it is written
+    ///
+    /// The key and the time it expires are recorded in the expiry index of the
+    /// store, from which [`DataStore::expired_keys`] lists keys due for deletion
+    /// without scanning the store. The entry stays readable until it is deleted,
+    /// by [`DataStore::expire`] or the job [`DataStore::spawn_expiry_job`] starts.
+    /// A later write of the key replaces the TTL, a write without TTL removes it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use std::time::Duration;
+    /// use velarixdb::db::DataStore;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     store.put_with_ttl("session", "token", Duration::from_millis(10)).await.unwrap();
+    ///     assert!(store.expired_keys(10).await.unwrap().is_empty());
+    ///
+    ///     tokio::time::sleep(Duration::from_millis(20)).await;
+    ///     assert_eq!(store.expired_keys(10).await.unwrap(), vec![b"session".to_vec()]);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidTtl` if `ttl` is zero or too large, or the errors of [`DataStore::put`]
+    pub async fn put_with_ttl(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        val: impl AsRef<[u8]>,
+        ttl: Duration,
+    ) -> Result<Bool, Error> {
+        if val.as_ref() == TOMB_STONE_MARKER.as_bytes() {
+            return self.put(key, val).await;
+        }
+        self.validate_size(key.as_ref(), Some(val.as_ref()))?;
+        let created_at = Utc::now();
+        let expires_at = chrono::Duration::from_std(ttl)
+            .ok()
+            .filter(|_| !ttl.is_zero())
+            .and_then(|ttl| created_at.checked_add_signed(ttl))
+            .ok_or(InvalidTtl(ttl))?;
+        // recorded first, a crash before the write leaves a record checked against the latest version
+        self.expiry
+            .insert(Expiry {
+                expires_at: expires_at.timestamp_millis() as u64,
+                key: key.as_ref().to_vec(),
+                created_at: nanos(created_at),
+            })
+            .await?;
+        self.put_at(key, val, created_at).await
+    }
+
+    /// Returns up to `limit` keys whose TTL has passed, earliest expiry first
+    ///
+    /// Only keys whose latest version was written by [`DataStore::put_with_ttl`]
+    /// are returned, writes and deletes of a key drop it from the expiry index.
+    /// Returned keys stay in the index until they are written again or deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    pub async fn expired_keys(&self, limit: usize) -> Result<Vec<Key>, Error> {
+        Ok(self
+            .expired(limit)
+            .await?
+            .into_iter()
+            .map(|expiry| expiry.key)
+            .collect())
+    }
+
+    /// Returns up to `limit` expired entries of the expiry index, dropping stale ones
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    pub(crate) async fn expired(&self, limit: usize) -> Result<Vec<Expiry>, Error> {
+        let mut expired = Vec::new();
+        let mut stale = Vec::new();
+        for expiry in self.expiry.due(millis(Utc::now())).await {
+            if expired.len() == limit {
+                break;
+            }
+            match self.latest_version(&expiry.key).await? {
+                Some(version) if !version.is_tombstone && expiry.is_version(version.created_at) => {
+                    expired.push(expiry)
+                }
+                _ => stale.push(expiry),
+            }
+        }
+        self.expiry.remove(&stale).await?;
+        Ok(expired)
+    }
+
+    /// Deletes up to `Config::expiry_batch_size` keys whose TTL has passed, earliest expiry first
+    ///
+    /// One pass of the job started by [`DataStore::spawn_expiry_job`]. Tombstones
+    /// are written like [`DataStore::delete_many`] does, so flush and compaction
+    /// remove the expired values from disk even if they are never read again.
+    ///
+    /// Returns number of keys deleted
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured, in which case no key was deleted.
+    pub async fn expire(&mut self) -> Result<usize, Error> {
+        let expired = self.expired(self.config.expiry_batch_size).await?;
+        if expired.is_empty() {
+            return Ok(0);
+        }
+        let results = self
+            .delete_many(expired.into_iter().map(|expiry| expiry.key))
+            .await?;
+        // tombstones dropped the records of the keys, removed from the index file here
+        self.expiry.remove(&[]).await?;
+        Ok(results.iter().filter(|res| matches!(res, Ok(true))).count())
+    }
+
+    /// Starts job deleting keys whose TTL has passed every `Config::expiry_interval`
+    ///
+    /// Each pass takes the write lock of `store` and runs [`DataStore::expire`].
+    /// The job holds no reference to the store between passes and stops once
+    /// the store is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use std::{sync::Arc, time::Duration};
+    /// use tokio::sync::RwLock;
+    /// use velarixdb::db::DataStore;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///     let store = Arc::new(RwLock::new(store.with_expiry_interval(Duration::from_millis(10))));
+    ///     DataStore::spawn_expiry_job(&store);
+    ///
+    ///     let ttl = Duration::from_millis(10);
+    ///     store.write().await.put_with_ttl("session", "token", ttl).await.unwrap();
+    ///     tokio::time::sleep(Duration::from_millis(200)).await;
+    ///     assert!(store.read().await.get("session").await.unwrap().is_none());
+    /// }
+    /// ```
+    pub fn spawn_expiry_job(store: &Arc<RwLock<Self>>) -> JoinHandle<()>
+    where
+        V: 'static,
+    {
+        let store = Arc::downgrade(store);
+        tokio::spawn(async move {
+            loop {
+                let delay = match store.upgrade() {
+                    Some(store) => {
+                        let config = &store.read().await.config;
+                        Timer::new(config.expiry_interval)
+                            .with_jitter(config.background_jitter)
+                            .next_delay()
+                    }
+                    None => return,
+                };
+                sleep(delay).await;
+                let Some(store) = store.upgrade() else {
+                    return;
+                };
+                let res = store.write().await.expire().await;
+                match res {
+                    Ok(0) => {}
+                    Ok(deleted) => log::info!("Expiry deleted {} keys", deleted),
+                    Err(err) => log::error!("Expiry Error {}", err),
+                }
+            }
+        })
+    }
+}
+
+/// Milliseconds since the epoch, the precision timestamps are stored with in sstables
+fn millis(timestamp: CreatedAt) -> u64 {
+    timestamp.timestamp_millis() as u64
+}
+
+/// Nanoseconds since the epoch, the precision timestamps are kept with in memtables
+fn nanos(timestamp: CreatedAt) -> u64 {
+    timestamp.timestamp_nanos_opt().unwrap_or(i64::MAX) as u64
+}
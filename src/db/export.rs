@@ -0,0 +1,481 @@
+use crate::{
+    block::Block,
+    bucket::FilePin,
+    consts::{EXPORT_MANIFEST_FILE_NAME, FORMAT_VERSION, HEAD_ENTRY_KEY, TAIL_ENTRY_KEY, VLOG_FILE_NAME},
+    db::DataStore,
+    err::Error,
+    filter::BloomFilter,
+    fs::{FileAsync, FileNode, P},
+    memtable::{MemTable, SkipMapValue, Val},
+    sst::{RangeTombstone, Table},
+    types::{CreatedAt, Key, SkipMapEntries, ValOffset, Value},
+    vlog::{is_wal_offset, ValueLogEntry},
+};
+use crossbeam_skiplist::SkipMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::{Bound, RangeBounds},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::fs;
+
+/// Describes a set of SSTables written by [`DataStore::export`]
+///
+/// The manifest is written after every other file of the export,
+/// a directory holding it contains a complete export.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportManifest {
+    /// Version of the crate that wrote the export
+    pub engine_version: String,
+
+    /// On-disk format version of the exported files
+    pub format_version: u32,
+
+    /// SSTable directories relative to the export directory, in key order
+    pub sstables: Vec<PathBuf>,
+
+    /// Value log holding values of the exported entries, relative to the export directory
+    pub vlog: PathBuf,
+
+    /// Number of exported entries
+    pub entry_count: usize,
+
+    /// Smallest exported key, `None` if the range was empty
+    pub smallest_key: Option<Key>,
+
+    /// Biggest exported key, `None` if the range was empty
+    pub biggest_key: Option<Key>,
+}
+
+impl ExportManifest {
+    /// Reads manifest of export at `dir`
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the manifest cannot be read or parsed
+    pub async fn read(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = dir.as_ref().join(EXPORT_MANIFEST_FILE_NAME);
+        let buf = fs::read(&path).await.map_err(|err| Error::FileOpen {
+            path: path.to_owned(),
+            error: err,
+        })?;
+        serde_json::from_slice(&buf).map_err(|err| Error::StoreInfoCorrupt { path, error: err })
+    }
+
+    async fn write(&self, dir: &Path) -> Result<(), Error> {
+        let buf = serde_json::to_vec_pretty(self).map_err(|_| Error::Serialization("export manifest"))?;
+        FileNode::write_atomic(dir.join(EXPORT_MANIFEST_FILE_NAME), &buf).await
+    }
+}
+
+/// Returns true if `key` is within `range`
+pub(crate) fn contains_key<T: AsRef<[u8]>>(range: &impl RangeBounds<T>, key: &[u8]) -> bool {
+    let after_start = match range.start_bound() {
+        Bound::Included(start) => key >= start.as_ref(),
+        Bound::Excluded(start) => key > start.as_ref(),
+        Bound::Unbounded => true,
+    };
+    let before_end = match range.end_bound() {
+        Bound::Included(end) => key <= end.as_ref(),
+        Bound::Excluded(end) => key < end.as_ref(),
+        Bound::Unbounded => true,
+    };
+    after_start && before_end
+}
+
+/// Checks if `key` is in `range` and not one of the keys tracking the value log head and tail
+pub(crate) fn contains_user_key<T: AsRef<[u8]>>(range: &impl RangeBounds<T>, key: &[u8]) -> bool {
+    key != HEAD_ENTRY_KEY && key != TAIL_ENTRY_KEY && contains_key(range, key)
+}
+
+/// Checks if keys between `span` bounds can be in `range`
+pub(crate) fn overlaps<T: AsRef<[u8]>>(
+    range: &impl RangeBounds<T>,
+    span: (Bound<&[u8]>, Bound<&[u8]>),
+) -> bool {
+    let ends_before_start = match (span.1, range.start_bound()) {
+        (Bound::Included(upper), Bound::Included(start)) => upper < start.as_ref(),
+        (Bound::Included(upper), Bound::Excluded(start)) => upper <= start.as_ref(),
+        _ => false,
+    };
+    let starts_after_end = match (span.0, range.end_bound()) {
+        (Bound::Included(lower), Bound::Included(end)) => lower > end.as_ref(),
+        (Bound::Included(lower) | Bound::Excluded(lower), Bound::Included(end) | Bound::Excluded(end)) => {
+            lower >= end.as_ref()
+        }
+        _ => false,
+    };
+    !ends_before_start && !starts_after_end
+}
+
+/// Entries of a key range captured by [`DataStore::snapshot_range`]
+///
+/// SSTable blocks are listed but not read, so a range of any size can be
+/// captured. The pin keeps the SSTables on disk while the snapshot is held.
+#[derive(Debug)]
+pub(crate) struct RangeSnapshot {
+    /// Newest version of each key of the range in memtables and gc updated entries
+    pub(crate) memtable_entries: BTreeMap<Key, SkipMapValue<ValOffset>>,
+
+    /// SSTables overlapping the range with handles of the blocks to read, in key order
+    pub(crate) tables: Vec<(Table, Vec<u32>)>,
+
+    /// Range tombstones at the time the range was captured
+    pub(crate) range_tombstones: Vec<RangeTombstone>,
+
+    /// Values of memtable entries in the write-ahead log, which is cleared while they are read
+    pub(crate) wal_values: HashMap<ValOffset, Value>,
+
+    pub(crate) pin: FilePin,
+}
+
+impl RangeSnapshot {
+    /// Whether the version of `key` created at `created_at` is deleted by a range tombstone
+    pub(crate) fn covers(&self, key: &[u8], created_at: CreatedAt) -> bool {
+        self.range_tombstones.iter().any(|t| t.covers(key, created_at))
+    }
+}
+
+/// Checks if tombstones of `table` with keys in `span` may hide an older version of their key
+///
+/// They may not if every entry with a key in `span` outside of `table` is newer than all
+/// entries of `table`. Tables with summaries of older versions are assumed to overlap.
+fn tombstones_may_shadow(
+    table: &Table,
+    span: (Bound<&[u8]>, Bound<&[u8]>),
+    tables: &[Table],
+    memtables: &[Arc<MemTable<Key>>],
+    active_memtable: &SkipMapEntries<Key>,
+    gc_updated_entries: &[(Key, SkipMapValue<ValOffset>)],
+) -> bool {
+    let newest = match table.summary.as_ref().and_then(|s| s.created_at_range) {
+        Some((_, newest)) => newest.timestamp_millis(),
+        None => return true,
+    };
+    let is_older = |created_at: &CreatedAt| created_at.timestamp_millis() <= newest;
+    let older_table =
+        tables
+            .iter()
+            .filter(|other| other.dir != table.dir)
+            .any(|other| match other.summary.as_ref() {
+                Some(summary) => {
+                    let key_range = (
+                        Bound::Included(summary.smallest_key.as_slice()),
+                        Bound::Included(summary.biggest_key.as_slice()),
+                    );
+                    overlaps::<&[u8]>(&span, key_range)
+                        && summary
+                            .created_at_range
+                            .is_none_or(|(oldest, _)| is_older(&oldest))
+                }
+                None => true,
+            });
+    older_table
+        || memtables
+            .iter()
+            .map(|m| &m.entries)
+            .chain(std::iter::once(active_memtable))
+            .any(|entries| {
+                entries
+                    .range::<[u8], _>(span)
+                    .any(|e| is_older(&e.value().created_at))
+            })
+        || gc_updated_entries
+            .iter()
+            .any(|(key, value)| contains_key::<&[u8]>(&span, key) && is_older(&value.created_at))
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Writes live entries with keys in `range` to `dir` as a self-contained set of SSTables
+    ///
+    /// Only the newest version of each key is exported and deleted keys are
+    /// left out. Values are copied, with their metadata, into a value log of the
+    /// export, so `dir` can be moved to and ingested by another store. SSTables
+    /// of the store are pinned while they are read, compaction can't remove them.
+    /// Returns manifest describing the export, also written to `dir`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let mut store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+    ///     store.put("apple", "tim cook").await.unwrap();
+    ///     store.put("google", "sundar pichai").await.unwrap();
+    ///     store.put("nvidia", "jensen huang").await.unwrap();
+    ///
+    ///     let manifest = store.export("a".."h", root.path().join("export")).await.unwrap();
+    ///     assert_eq!(manifest.entry_count, 2);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ExportDirNotEmpty` if `dir` already has files, or error
+    /// if an entry could not be read or a file could not be written
+    pub async fn export<T: AsRef<[u8]>>(
+        &self,
+        range: impl RangeBounds<T>,
+        dir: impl P,
+    ) -> Result<ExportManifest, Error> {
+        let (manifest, _) = self.export_range(range, dir.as_ref()).await?;
+        Ok(manifest)
+    }
+
+    /// Same as [`DataStore::export`], but also returns the exported keys, in order
+    pub(crate) async fn export_range<T: AsRef<[u8]>>(
+        &self,
+        range: impl RangeBounds<T>,
+        dir: &Path,
+    ) -> Result<(ExportManifest, Vec<Key>), Error> {
+        if let Ok(mut entries) = fs::read_dir(dir).await {
+            if entries.next_entry().await.ok().flatten().is_some() {
+                return Err(Error::ExportDirNotEmpty(dir.to_path_buf()));
+            }
+        }
+        FileNode::create_dir_all(dir).await?;
+
+        let (_pin, live_entries) = self.collect_live_entries(&range).await?;
+
+        // values are copied to the value log of the export, entries point into it
+        let mut vlog_buf = Vec::new();
+        let mut tables: Vec<SkipMapEntries<Key>> = Vec::new();
+        let mut table_entries: SkipMapEntries<Key> = Arc::new(SkipMap::new());
+        let mut table_size = 0;
+        let mut exported_keys: Vec<Key> = Vec::with_capacity(live_entries.len());
+        for (key, value) in live_entries.iter() {
+            let entry = match self.val_log.get_entry(value.val_offset).await? {
+                Some(entry) => entry,
+                None => continue,
+            };
+            if self.config.verify_reads {
+                entry.verify(key, value.val_offset)?;
+            }
+            let mut exported = ValueLogEntry::new(
+                key.len(),
+                entry.value.len(),
+                key.to_owned(),
+                entry.value,
+                value.created_at,
+                false,
+            );
+            if let Some(metadata) = entry.metadata {
+                exported = exported.with_metadata(metadata);
+            }
+            if self.config.verify_reads {
+                exported = exported.with_checksum();
+            }
+            let offset = vlog_buf.len();
+            vlog_buf.extend(exported.serialize());
+
+            // tables are cut at memtable size, like flushed ones
+            if table_size + Block::entry_size(key) > self.config.write_buffer_size
+                && !table_entries.is_empty()
+            {
+                tables.push(std::mem::replace(&mut table_entries, Arc::new(SkipMap::new())));
+                table_size = 0;
+            }
+            table_size += Block::entry_size(key);
+            table_entries.insert(key.to_owned(), SkipMapValue::new(offset, value.created_at, false));
+            exported_keys.push(key.to_owned());
+        }
+        if !table_entries.is_empty() {
+            tables.push(table_entries);
+        }
+
+        let vlog = PathBuf::from(VLOG_FILE_NAME);
+        FileNode::write_atomic(dir.join(&vlog), &vlog_buf).await?;
+        let mut sstables = Vec::with_capacity(tables.len());
+        for (i, entries) in tables.into_iter().enumerate() {
+            let sst_dir = PathBuf::from(format!("sstable_{:06}", i));
+            self.write_export_table(&dir.join(&sst_dir), entries).await?;
+            sstables.push(sst_dir);
+        }
+        FileNode::sync_dir(dir).await?;
+
+        let manifest = ExportManifest {
+            engine_version: env!("CARGO_PKG_VERSION").to_string(),
+            format_version: FORMAT_VERSION,
+            sstables,
+            vlog,
+            entry_count: exported_keys.len(),
+            smallest_key: exported_keys.first().cloned(),
+            biggest_key: exported_keys.last().cloned(),
+        };
+        manifest.write(dir).await?;
+        Ok((manifest, exported_keys))
+    }
+
+    /// Returns newest version of every live key in `range`, deleted keys are left out
+    ///
+    /// Returned pin keeps the SSTables and value log regions the entries point
+    /// to on disk while it is held.
+    pub(crate) async fn collect_live_entries<T: AsRef<[u8]>>(
+        &self,
+        range: &impl RangeBounds<T>,
+    ) -> Result<(FilePin, BTreeMap<Key, SkipMapValue<ValOffset>>), Error> {
+        let snapshot = self.snapshot_range(range).await?;
+        let mut newest: BTreeMap<Key, SkipMapValue<ValOffset>> = BTreeMap::new();
+        for (table, blocks) in snapshot.tables.iter() {
+            for block_handle in blocks {
+                let (block, _) = table
+                    .cached_block(
+                        *block_handle,
+                        &self.config.block_cache,
+                        &self.config.io_retry,
+                        self.config.read_pool.as_ref(),
+                    )
+                    .await?;
+                for e in block.iter().filter(|e| contains_user_key(range, &e.key)) {
+                    let value = SkipMapValue::new(e.value_offset as usize, e.creation_date, e.is_tombstone);
+                    match newest.get(&e.key) {
+                        Some(existing) if existing.created_at > value.created_at => {}
+                        _ => {
+                            newest.insert(e.key.to_owned(), value);
+                        }
+                    }
+                }
+            }
+        }
+        // memtables hold newer entries than sstables
+        for (key, value) in snapshot.memtable_entries.iter() {
+            match newest.get(key) {
+                Some(existing) if existing.created_at > value.created_at => {}
+                _ => {
+                    newest.insert(key.to_owned(), value.to_owned());
+                }
+            }
+        }
+        newest.retain(|key, value| !value.is_tombstone && !snapshot.covers(key, value.created_at));
+        Ok((snapshot.pin, newest))
+    }
+
+    /// Captures memtable entries and SSTable blocks holding the live entries of `range`
+    ///
+    /// Memtables are captured before the set of SSTables, so entries of a memtable
+    /// flushed in between are seen at least once. Only SSTables and blocks overlapping
+    /// `range` are listed. Blocks holding only tombstones are skipped when no older
+    /// version of their keys can be found elsewhere.
+    pub(crate) async fn snapshot_range<T: AsRef<[u8]>>(
+        &self,
+        range: &impl RangeBounds<T>,
+    ) -> Result<RangeSnapshot, Error> {
+        let read_only_memtables: Vec<_> = self
+            .read_only_memtables
+            .iter()
+            .map(|m| m.value().clone())
+            .collect();
+        let gc_updated_entries: Vec<_> = self
+            .gc_updated_entries
+            .read()
+            .await
+            .iter()
+            .map(|e| (e.key().to_owned(), e.value().to_owned()))
+            .collect();
+        let mut memtable_entries: BTreeMap<Key, SkipMapValue<ValOffset>> = BTreeMap::new();
+        let mut keep_newest = |key: &Key, value: &SkipMapValue<ValOffset>| {
+            if !contains_user_key(range, key) {
+                return;
+            }
+            match memtable_entries.get(key) {
+                Some(existing) if existing.created_at > value.created_at => {}
+                _ => {
+                    memtable_entries.insert(key.to_owned(), value.to_owned());
+                }
+            }
+        };
+        // versions of the same time are taken from the later source, gc updated entries are the newest
+        for memtable in read_only_memtables.iter() {
+            memtable
+                .entries
+                .iter()
+                .for_each(|e| keep_newest(e.key(), e.value()));
+        }
+        self.active_memtable
+            .entries
+            .iter()
+            .for_each(|e| keep_newest(e.key(), e.value()));
+        gc_updated_entries
+            .iter()
+            .for_each(|(key, value)| keep_newest(key, value));
+        let range_tombstones = self.range_tombstones.to_vec();
+        let mut wal_values = HashMap::new();
+        if let Some(wal) = &self.val_log.wal {
+            if memtable_entries
+                .values()
+                .any(|value| is_wal_offset(value.val_offset))
+            {
+                for (offset, entry) in wal.entries().await? {
+                    if memtable_entries
+                        .get(&entry.key)
+                        .is_some_and(|value| value.val_offset == offset)
+                    {
+                        wal_values.insert(offset, entry.value);
+                    }
+                }
+            }
+        }
+
+        let (pin, tables) = {
+            let buckets = self.buckets.read().await;
+            let mut tables = Vec::new();
+            for bucket in buckets.buckets.values() {
+                tables.extend(bucket.sstables.read().await.iter().cloned());
+            }
+            (buckets.pins.pin(), tables)
+        };
+        let disjoint = self.key_range.disjoint_from(range).await;
+        let mut table_blocks = Vec::new();
+        for table in tables.iter().filter(|t| !disjoint.contains(&t.dir)) {
+            let index = table.index_file.file.load().await?;
+            let mut blocks = Vec::new();
+            let mut lower = Bound::Unbounded;
+            for (idx, index_entry) in index.entries().iter().enumerate() {
+                let span = (lower, Bound::Included(index_entry.key.as_slice()));
+                lower = Bound::Excluded(index_entry.key.as_slice());
+                if !overlaps(range, span)
+                    || index.is_tombstone_block(idx)
+                        && !tombstones_may_shadow(
+                            table,
+                            span,
+                            &tables,
+                            &read_only_memtables,
+                            &self.active_memtable.entries,
+                            &gc_updated_entries,
+                        )
+                {
+                    continue;
+                }
+                blocks.push(index_entry.block_handle);
+            }
+            if !blocks.is_empty() {
+                table_blocks.push((table.to_owned(), blocks));
+            }
+        }
+        Ok(RangeSnapshot {
+            memtable_entries,
+            tables: table_blocks,
+            range_tombstones,
+            wal_values,
+            pin,
+        })
+    }
+
+    /// Writes an exported sstable with `entries` to `sst_dir`
+    async fn write_export_table(&self, sst_dir: &Path, entries: SkipMapEntries<Key>) -> Result<(), Error> {
+        let mut table = Table::new(sst_dir).await?;
+        let filter = BloomFilter::new(self.config.false_positive_rate, entries.len())
+            .build_from_entries(entries.clone(), self.config.offload_cpu_work)
+            .await?;
+        table.filter = Some(filter);
+        table.set_entries(entries);
+        table.write_to_file(self.config.offload_cpu_work).await?;
+        FileNode::sync_dir(sst_dir).await
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{
+    consts::{HEALTH_BACKGROUND_ERROR_WINDOW, HEALTH_MAX_PENDING_GC_ENTRIES, MAX_TRESHOLD},
+    db::DataStore,
+    err::Error,
+    fs::FileNode,
+    memtable::Val,
+    types::Key,
+};
+use chrono::Utc;
+
+/// Health of a [`DataStore`], returned by [`DataStore::health`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Health {
+    /// Store serves reads and writes
+    Ok,
+
+    /// Store serves reads and writes, but background work falls behind or failed recently
+    Degraded(String),
+
+    /// Writes are rejected or about to fail, reads are still served
+    ReadOnly(String),
+}
+
+impl Health {
+    /// Returns true unless the store is read-only, as expected by readiness probes of writers
+    pub fn is_ready(&self) -> bool {
+        !matches!(self, Health::ReadOnly(_))
+    }
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Returns health of the store
+    ///
+    /// The store is read-only if free disk space of the store or value log file
+    /// system is below `Config::min_free_disk_space`, or the keyspace quota is
+    /// reached. It is degraded if a background task panicked in the last ten
+    /// minutes, more memtables than `Config::max_buffer_write_number` wait to
+    /// be flushed, a bucket holds more sstables than one compaction merges, or
+    /// too many entries relocated by garbage collection wait to be synced.
+    /// Checks only read in-memory state and file system statistics, so it can
+    /// back frequent readiness probes.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::{Config, DataStore, Health};
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let config = Config {
+    ///         min_free_disk_space: None,
+    ///         ..Default::default()
+    ///     };
+    ///     let store = DataStore::open_with_config("big_tech", root.path().join("store"), config)
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     match store.health().await.unwrap() {
+    ///         Health::Ok => println!("ready"),
+    ///         Health::Degraded(reason) => println!("ready, but {}", reason),
+    ///         Health::ReadOnly(reason) => println!("not ready: {}", reason),
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if file system statistics could not be read
+    pub async fn health(&self) -> Result<Health, Error> {
+        if let Some(min_free) = self.config.min_free_disk_space {
+            let vlog_dir = self.val_log.content.path.parent().unwrap_or(&self.dir.root);
+            for dir in [self.dir.root.as_path(), vlog_dir] {
+                match FileNode::available_space(dir).await? {
+                    Some(available) if available < min_free => {
+                        return Ok(Health::ReadOnly(format!(
+                            "{} bytes free on file system of {:?}, below {} bytes",
+                            available, dir, min_free
+                        )));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if let Err(err @ Error::QuotaExceeded { .. }) = self.check_quota_limits(1, 1).await {
+            return Ok(Health::ReadOnly(err.to_string()));
+        }
+
+        let mut reasons = Vec::new();
+        let since = Utc::now() - HEALTH_BACKGROUND_ERROR_WINDOW;
+        let errors = self.config.env.background_errors();
+        if let Some(error) = errors.iter().rev().find(|e| e.occurred_at > since) {
+            reasons.push(format!("{:?} task panicked: {}", error.job, error.message));
+        }
+        let unflushed = self.read_only_memtables.len();
+        if unflushed > self.config.max_buffer_write_number {
+            reasons.push(format!("{} memtables wait to be flushed", unflushed));
+        }
+        let buckets = self.buckets.read().await;
+        let mut backlogged = 0;
+        for bucket in buckets.buckets.values() {
+            if bucket.sstables.read().await.len() > MAX_TRESHOLD {
+                backlogged += 1;
+            }
+        }
+        if backlogged > 0 {
+            reasons.push(format!(
+                "{} buckets hold more than {} sstables",
+                backlogged, MAX_TRESHOLD
+            ));
+        }
+        let pending = self.gc_updated_entries.read().await.len();
+        if pending > HEALTH_MAX_PENDING_GC_ENTRIES {
+            reasons.push(format!(
+                "{} entries relocated by garbage collection wait to be synced",
+                pending
+            ));
+        }
+        if reasons.is_empty() {
+            return Ok(Health::Ok);
+        }
+        Ok(Health::Degraded(reasons.join(", ")))
+    }
+}
@@ -1,4 +1,11 @@
-use crate::consts::MAX_KEY_SPACE_SIZE;
+use super::export::{contains_key, overlaps};
+use crate::consts::{HEAD_ENTRY_KEY, MAX_KEY_SPACE_SIZE, TAIL_ENTRY_KEY};
+use crate::db::DataStore;
+use crate::err::Error;
+use crate::memtable::Val;
+use crate::types::{Key, SkipMapEntries};
+use std::collections::BTreeMap;
+use std::ops::{Bound, RangeBounds};
 
 const VALID_CHARACTERS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_-";
 
@@ -20,6 +27,278 @@ pub fn is_valid_keyspace_name(s: &str) -> bool {
     s.chars().all(|c| VALID_CHARACTERS.contains(c))
 }
 
+/// Size of a keyspace, returned by [`DataStore::keyspace_stats`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyspaceStats {
+    /// Keyspace name
+    pub keyspace: String,
+
+    /// Number of live keys, deleted keys and older versions of a key are not counted
+    pub keys: usize,
+
+    /// Bytes of value log entries between tail and end of the value log,
+    /// including entries garbage collection has not reclaimed yet
+    pub bytes: usize,
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Returns size of the keyspace of this store
+    ///
+    /// Keys are counted like [`DataStore::count_range`] counts them, from
+    /// memtables and sstable blocks. Bytes are computed from value log offsets.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an sstable could not be read
+    pub async fn keyspace_stats(&self) -> Result<KeyspaceStats, Error> {
+        Ok(KeyspaceStats {
+            keyspace: self.keyspace.to_string(),
+            keys: self.count_range::<&[u8]>(..).await?,
+            bytes: self.val_log.size.saturating_sub(self.val_log.tail_offset),
+        })
+    }
+
+    /// Returns approximate number of live keys starting with `prefix`
+    ///
+    /// Memtables are counted exactly, sstables are estimated from a count-min
+    /// sketch of key prefixes built when they are flushed or compacted. The
+    /// estimate may exceed the actual count but is never below the number of
+    /// keys in sstables. Versions of a key not yet merged by compaction are
+    /// counted once each, and prefixes longer than 16 bytes are estimated from
+    /// their first 16 bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns IO error if an sstable written by an older version could not be read
+    pub async fn prefix_cardinality<T: AsRef<[u8]>>(&self, prefix: T) -> Result<usize, Error> {
+        let prefix = prefix.as_ref();
+        let live_keys = |entries: &SkipMapEntries<Key>| {
+            entries
+                .range(prefix.to_vec()..)
+                .take_while(|e| e.key().starts_with(prefix))
+                .filter(|e| {
+                    !e.value().is_tombstone
+                        && e.key().as_slice() != HEAD_ENTRY_KEY
+                        && e.key().as_slice() != TAIL_ENTRY_KEY
+                })
+                .count()
+        };
+        let memtable_keys = live_keys(&self.active_memtable.entries)
+            + self
+                .read_only_memtables
+                .iter()
+                .map(|m| live_keys(&m.value().entries))
+                .sum::<usize>();
+        Ok(memtable_keys + self.key_range.prefix_cardinality(prefix).await?)
+    }
+
+    /// Returns number of live keys within `range`
+    ///
+    /// Keys are counted from memtables and sstable blocks, values are never
+    /// read from the value log. Blocks holding only tombstones are skipped
+    /// unless they may hide an older version. See [`DataStore::estimate_range_count`]
+    /// for a faster estimate.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let mut store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+    ///     store.put("apple", "tim cook").await.unwrap();
+    ///     store.put("google", "sundar pichai").await.unwrap();
+    ///     store.put("nvidia", "jensen huang").await.unwrap();
+    ///     store.delete("google").await.unwrap();
+    ///
+    ///     assert_eq!(store.count_range("a".."h").await.unwrap(), 1);
+    ///     assert_eq!(store.count_range("a"..="nvidia").await.unwrap(), 2);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an sstable could not be read
+    pub async fn count_range<T: AsRef<[u8]>>(&self, range: impl RangeBounds<T>) -> Result<usize, Error> {
+        let (_, entries) = self.collect_live_entries(&range).await?;
+        Ok(entries.len())
+    }
+
+    /// Returns approximate number of live keys within `range`
+    ///
+    /// Memtables are counted exactly. Sstable blocks entirely within `range`
+    /// are counted as the average number of entries per block of their table,
+    /// only the blocks at the bounds of `range` are read. Blocks holding only
+    /// tombstones count as empty and versions of a key not yet merged by
+    /// compaction are counted once each.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an sstable could not be read
+    pub async fn estimate_range_count<T: AsRef<[u8]>>(
+        &self,
+        range: impl RangeBounds<T>,
+    ) -> Result<usize, Error> {
+        let bounds = (
+            range.start_bound().map(|start| start.as_ref()),
+            range.end_bound().map(|end| end.as_ref()),
+        );
+        let live_keys = |entries: &SkipMapEntries<Key>| {
+            entries
+                .range::<[u8], _>(bounds)
+                .filter(|e| {
+                    !e.value().is_tombstone
+                        && e.key().as_slice() != HEAD_ENTRY_KEY
+                        && e.key().as_slice() != TAIL_ENTRY_KEY
+                })
+                .count()
+        };
+        let mut count = live_keys(&self.active_memtable.entries)
+            + self
+                .read_only_memtables
+                .iter()
+                .map(|m| live_keys(&m.value().entries))
+                .sum::<usize>();
+
+        let (_pin, tables) = {
+            let buckets = self.buckets.read().await;
+            let mut tables = Vec::new();
+            for bucket in buckets.buckets.values() {
+                tables.extend(bucket.sstables.read().await.iter().cloned());
+            }
+            (buckets.pins.pin(), tables)
+        };
+        for table in tables.iter() {
+            let index = table.index_file.file.load().await?;
+            let per_block = table.entry_count() as f64 / index.entries().len().max(1) as f64;
+            let mut estimate = 0.0;
+            let mut lower: Option<&[u8]> = None;
+            for (idx, index_entry) in index.entries().iter().enumerate() {
+                let upper = index_entry.key.as_slice();
+                let span = (
+                    lower.map_or(Bound::Unbounded, Bound::Excluded),
+                    Bound::Included(upper),
+                );
+                let within =
+                    lower.is_some_and(|lower| contains_key(&range, lower)) && contains_key(&range, upper);
+                lower = Some(upper);
+                if !overlaps(&range, span) || index.is_tombstone_block(idx) {
+                    continue;
+                }
+                if within {
+                    estimate += per_block;
+                    continue;
+                }
+                let (block, _) = table
+                    .cached_block(
+                        index_entry.block_handle,
+                        &self.config.block_cache,
+                        &self.config.io_retry,
+                        self.config.read_pool.as_ref(),
+                    )
+                    .await?;
+                estimate += block
+                    .iter()
+                    .filter(|e| {
+                        !e.is_tombstone
+                            && contains_key(&range, &e.key)
+                            && e.key.as_slice() != HEAD_ENTRY_KEY
+                            && e.key.as_slice() != TAIL_ENTRY_KEY
+                    })
+                    .count() as f64;
+            }
+            count += estimate.round() as usize;
+        }
+        Ok(count)
+    }
+
+    /// Checks that `writes` taking `new_bytes` stay within quota of the keyspace
+    ///
+    /// Each write is a key and whether it deletes the key, the last write of a
+    /// key counts. Overwriting a live key adds no key, deleting one frees a key.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::QuotaExceeded` if a limit would be exceeded
+    pub(crate) async fn check_quota<'a>(
+        &self,
+        new_bytes: usize,
+        writes: impl IntoIterator<Item = (&'a [u8], bool)>,
+    ) -> Result<(), Error> {
+        let quota = match self.config.quota_for(self.keyspace) {
+            Some(quota) => quota,
+            None => return Ok(()),
+        };
+        let new_keys = match quota.max_keys {
+            Some(_) => self.live_key_delta(writes).await?,
+            None => 0,
+        };
+        self.check_quota_limits(new_bytes, new_keys).await
+    }
+
+    /// Checks that adding `new_bytes` and `new_keys` live keys stays within quota of the keyspace
+    ///
+    /// Keys are only counted if `new_keys` is positive, so writes that don't
+    /// add keys are accepted by a keyspace at its key limit.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::QuotaExceeded` if a limit would be exceeded
+    pub(crate) async fn check_quota_limits(&self, new_bytes: usize, new_keys: isize) -> Result<(), Error> {
+        let quota = match self.config.quota_for(self.keyspace) {
+            Some(quota) => quota,
+            None => return Ok(()),
+        };
+        let keyspace = self.keyspace.to_string();
+        let used_bytes = self.val_log.size.saturating_sub(self.val_log.tail_offset);
+        if let Some(limit) = quota.max_bytes.filter(|limit| used_bytes + new_bytes > *limit) {
+            return Err(Error::QuotaExceeded {
+                keyspace,
+                limit,
+                unit: "bytes",
+            });
+        }
+        if let (Some(limit), Ok(new_keys @ 1..)) = (quota.max_keys, usize::try_from(new_keys)) {
+            if self.count_range::<&[u8]>(..).await? + new_keys > limit {
+                return Err(Error::QuotaExceeded {
+                    keyspace,
+                    limit,
+                    unit: "keys",
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns change in number of live keys once `writes` are applied
+    ///
+    /// See [`DataStore::check_quota`] for how `writes` are read.
+    ///
+    /// # Errors
+    ///
+    /// Returns IO error if a key could not be looked up
+    async fn live_key_delta<'a>(
+        &self,
+        writes: impl IntoIterator<Item = (&'a [u8], bool)>,
+    ) -> Result<isize, Error> {
+        let mut last: BTreeMap<&[u8], bool> = BTreeMap::new();
+        for (key, is_tombstone) in writes {
+            last.insert(key, is_tombstone);
+        }
+        let mut delta = 0;
+        for (key, is_tombstone) in last {
+            let live = self.latest_version(key).await?.is_some_and(|version| {
+                !version.is_tombstone && !self.range_tombstones.covers(key, version.created_at)
+            });
+            delta += (!is_tombstone) as isize - live as isize;
+        }
+        Ok(delta)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
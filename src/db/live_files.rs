@@ -0,0 +1,61 @@
+use crate::bucket::FilePin;
+use crate::consts::{FILTER_FILE_NAME, SUMMARY_FILE_NAME};
+use std::path::PathBuf;
+
+/// Files making up a [`DataStore`](crate::db::DataStore) at one point in time
+///
+/// Returned by [`DataStore::live_files`](crate::db::DataStore::live_files).
+/// Files listed here are not deleted by compaction, and value log space is not
+/// freed by garbage collection, while this value (or the pin taken from it) is
+/// alive, so they can be copied as a consistent set.
+#[derive(Debug)]
+pub struct LiveFiles {
+    /// Directory of each sstable
+    pub sstables: Vec<PathBuf>,
+
+    /// SSTable data files
+    pub data_files: Vec<PathBuf>,
+
+    /// SSTable index files
+    pub index_files: Vec<PathBuf>,
+
+    /// Value log file
+    pub vlog: PathBuf,
+
+    /// Write-ahead log file, if small values are written to one
+    pub wal: Option<PathBuf>,
+
+    pin: FilePin,
+}
+
+impl LiveFiles {
+    pub(crate) fn new(vlog: PathBuf, pin: FilePin) -> Self {
+        Self {
+            sstables: Vec::new(),
+            data_files: Vec::new(),
+            index_files: Vec::new(),
+            vlog,
+            wal: None,
+            pin,
+        }
+    }
+
+    /// Returns path of every file, including sstable filters and summaries
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let mut paths = Vec::with_capacity(self.sstables.len() * 4 + 2);
+        paths.extend(self.data_files.iter().cloned());
+        paths.extend(self.index_files.iter().cloned());
+        for dir in &self.sstables {
+            paths.push(dir.join(format!("{}.db", FILTER_FILE_NAME)));
+            paths.push(dir.join(format!("{}.db", SUMMARY_FILE_NAME)));
+        }
+        paths.push(self.vlog.to_owned());
+        paths.extend(self.wal.iter().cloned());
+        paths
+    }
+
+    /// Drops the listing but keeps files pinned until returned guard drops
+    pub fn into_pin(self) -> FilePin {
+        self.pin
+    }
+}
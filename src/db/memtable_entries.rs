@@ -0,0 +1,134 @@
+use crate::{
+    consts::{HEAD_ENTRY_KEY, TAIL_ENTRY_KEY},
+    db::DataStore,
+    err::Error,
+    memtable::{SkipMapValue, Val},
+    types::{CreatedAt, Key, ValOffset, Value},
+    vlog::ValueLog,
+};
+use std::collections::VecDeque;
+
+/// Entry of a memtable returned by [`MemTableIterator`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemTableEntry {
+    pub key: Key,
+
+    /// Timestamp versions of the key are ordered by
+    pub created_at: CreatedAt,
+
+    pub is_tombstone: bool,
+
+    /// Offset of the value in the value log, read with [`MemTableIterator::value`]
+    pub val_offset: ValOffset,
+
+    /// True if the entry is in a read-only memtable waiting to be flushed,
+    /// false if it is in the active memtable
+    pub read_only: bool,
+}
+
+/// Iterates over entries of the active and read-only memtables, i.e. writes not yet flushed to SSTables
+///
+/// Entries are returned in key order, newest first for the same key, as they
+/// were when the iterator was created. Values stay in the value log until
+/// [`MemTableIterator::value`] is called, so keys can be indexed without
+/// reading values that are not needed.
+///
+/// # Examples
+///
+/// ```rust
+/// use velarixdb::db::DataStore;
+/// # use tempfile::tempdir;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let root = tempdir().unwrap();
+///     let mut store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+///     store.put("apple", "tim cook").await.unwrap();
+///     store.put("google", "sundar pichai").await.unwrap();
+///
+///     let mut entries = store.memtable_entries();
+///     let entry = entries.next().unwrap();
+///     assert_eq!(entry.key, b"apple".to_vec());
+///     assert_eq!(entries.value(&entry).await.unwrap(), Some(b"tim cook".to_vec()));
+///     assert_eq!(entries.next().unwrap().key, b"google".to_vec());
+///     assert!(entries.next().is_none());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct MemTableIterator {
+    entries: VecDeque<MemTableEntry>,
+    v_log: ValueLog,
+}
+
+impl MemTableIterator {
+    /// Reads value of `entry` from value log
+    ///
+    /// Returns `None` for tombstones and for values whose space garbage
+    /// collection reclaimed since the iterator was created
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an IO error occured
+    pub async fn value(&self, entry: &MemTableEntry) -> Result<Option<Value>, Error> {
+        if entry.is_tombstone {
+            return Ok(None);
+        }
+        match self.v_log.get_entry(entry.val_offset).await {
+            Ok(Some(stored)) if stored.key == entry.key => Ok(Some(stored.value)),
+            Ok(_) | Err(Error::UnexpectedEOF(_) | Error::ChecksumMismatch { .. }) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Returns number of entries not yet returned
+    pub fn remaining(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+impl Iterator for MemTableIterator {
+    type Item = MemTableEntry;
+
+    fn next(&mut self) -> Option<MemTableEntry> {
+        self.entries.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.entries.len(), Some(self.entries.len()))
+    }
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Returns iterator over entries of the memtables, see [`MemTableIterator`]
+    ///
+    /// Lets embedders index writes before they are flushed, e.g. to keep a
+    /// secondary index near real time.
+    pub fn memtable_entries(&self) -> MemTableIterator {
+        let mut entries = Vec::new();
+        let mut keep = |key: &[u8], value: &SkipMapValue<ValOffset>, read_only| {
+            if key == HEAD_ENTRY_KEY || key == TAIL_ENTRY_KEY {
+                return;
+            }
+            entries.push(MemTableEntry {
+                key: key.to_vec(),
+                created_at: value.created_at,
+                is_tombstone: value.is_tombstone,
+                val_offset: value.val_offset,
+                read_only,
+            });
+        };
+        for e in self.active_memtable.entries.iter() {
+            keep(e.key(), e.value(), false);
+        }
+        for table in self.read_only_memtables.iter() {
+            for e in table.value().entries.iter() {
+                keep(e.key(), e.value(), true);
+            }
+        }
+        entries.sort_by(|a, b| a.key.cmp(&b.key).then(b.created_at.cmp(&a.created_at)));
+        MemTableIterator {
+            entries: entries.into(),
+            v_log: self.val_log.clone(),
+        }
+    }
+}
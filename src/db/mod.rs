@@ -1,5 +1,62 @@
+mod bucket_report;
+mod compaction_plan;
+mod consistency;
+pub(crate) mod context;
+mod disk_usage;
+mod expiry;
+mod export;
+mod health;
 mod keyspace;
+mod live_files;
+mod memtable_entries;
+mod range_delete;
+#[cfg(any(test, feature = "raw-versions"))]
+mod raw;
+mod read_options;
 mod recovery;
+mod shard;
+mod soft_delete;
+mod stats;
 mod store;
+mod store_info;
+mod string_store;
+mod transaction;
+mod view;
+mod warm_cache;
+pub use crate::block::BlockCache;
+pub use crate::bucket::{FilePin, LayoutIssue};
+pub use crate::cache::CacheStats;
+pub use crate::cfg::{
+    ColdStorage, Config, GcDiskPressure, KeyspaceQuota, OnProgress, OpenPhase, OpenProgress,
+};
+pub use crate::env::{BackgroundError, BackgroundJob, Env};
+pub use crate::err::{Error, ErrorKind};
+pub use crate::filter::{FilterCache, FilterStats};
+pub use crate::flush::{FlushEvent, FlushOutcome, FlushSubscription};
+pub use crate::fs::{ReadPool, RetryPolicy};
+pub use crate::gc::{GcPass, GcStats, GcSubscription};
+pub use crate::range::{ContinuationToken, FetchedEntry, Page, RangeIterator};
+pub use bucket_report::{BucketReport, HotnessDistribution};
+pub use compaction_plan::{CompactionPlan, PlannedMerge};
+pub use consistency::{ConsistencyReport, Inconsistency, InconsistencyKind};
+pub use context::OpContext;
+pub use disk_usage::{BucketUsage, DiskUsage, SSTableUsage, VlogUsage};
+pub(crate) use expiry::{Expiry, ExpiryIndex};
+pub use export::ExportManifest;
+pub(crate) use export::{contains_key, contains_user_key, overlaps, RangeSnapshot};
+pub use health::Health;
+pub use keyspace::KeyspaceStats;
+pub use live_files::LiveFiles;
+pub use memtable_entries::{MemTableEntry, MemTableIterator};
+#[cfg(any(test, feature = "raw-versions"))]
+pub use raw::{RawVersion, RawVersionIterator, VersionSource};
+pub use read_options::{ReadOptions, ReadPriority};
+pub(crate) use stats::StatsRecorder;
+pub use stats::StoreStats;
 pub use store::DataStore;
 pub use store::SizeUnit;
+pub use store_info::StoreInfo;
+pub use string_store::StringStore;
+pub use transaction::Transaction;
+pub use view::StoreView;
+pub use warm_cache::CacheWarmup;
@@ -0,0 +1,89 @@
+use crate::{
+    db::DataStore,
+    err::Error,
+    memtable::{Entry, Val},
+    sst::RangeTombstone,
+    types::Key,
+    util,
+};
+use chrono::Utc;
+use tokio::time::sleep;
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Removes every key from `start` up to, not including, `end`
+    ///
+    /// A single range tombstone is written to the value log, however many keys
+    /// the range holds. Keys of the range read as deleted right away, versions
+    /// written afterwards are not affected. The tombstone is flushed to the
+    /// summary of an sstable and compaction drops the keys it covers, until
+    /// `Config::tombstone_ttl` passed.
+    ///
+    /// Nothing is deleted if `start` is not smaller than `end`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use velarixdb::db::DataStore;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     store.put("user:1", "tim cook").await.unwrap(); // handle error
+    ///     store.put("user:2", "sundar pichai").await.unwrap();
+    ///     store.put("vendor:1", "nvidia").await.unwrap();
+    ///
+    ///     store.delete_range("user:", "user;").await.unwrap();
+    ///     assert!(store.get("user:1").await.unwrap().is_none());
+    ///     assert!(store.get("user:2").await.unwrap().is_none());
+    ///     assert!(store.get("vendor:1").await.unwrap().is_some());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or a key size is invalid.
+    pub async fn delete_range(
+        &mut self,
+        start: impl AsRef<[u8]>,
+        end: impl AsRef<[u8]>,
+    ) -> Result<(), Error> {
+        let (start, end) = (start.as_ref(), end.as_ref());
+        self.validate_size(start, None::<&[u8]>)?;
+        self.validate_size(end, None::<&[u8]>)?;
+        if start >= end {
+            return Ok(());
+        }
+
+        if !self.gc_updated_entries.read().await.is_empty() {
+            self.sync_gc_update_with_store().await?
+        }
+        // write-ahead log entries carry no range
+        self.seal_wal().await?;
+        // versions on disk keep milliseconds only, so the range is deleted at the next whole
+        // millisecond, which earlier writes are before and writes after this returns are not
+        let now = Utc::now();
+        let created_at = util::milliseconds_to_datetime(now.timestamp_millis() as u64 + 1);
+        sleep((created_at - now).to_std().unwrap_or_default()).await;
+        let v_offset = self
+            .val_log
+            .append_range_tombstone(start, end, created_at)
+            .await?;
+        let tombstone = RangeTombstone::new(start.to_vec(), end.to_vec(), created_at);
+        {
+            // a garbage collection pass moving keys of the range re-inserts them as new versions,
+            // it must see the range before it checks an entry or drop the move afterwards
+            let _gc_table = self.gc_table.write().await;
+            self.gc.config.writes.record_range(start, end);
+            self.range_tombstones.insert(tombstone.to_owned());
+        }
+        // the start key points at the record, so the memtable tracks the head of the log like for any write
+        let entry = Entry::new(start.to_vec(), v_offset, created_at, true);
+        self.insert_to_memtable(entry).await?;
+        self.active_memtable.range_tombstones.push(tombstone);
+        Ok(())
+    }
+}
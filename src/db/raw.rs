@@ -0,0 +1,216 @@
+//! # Raw Versions
+//!
+//! Available with the `raw-versions` feature. Iterates over every version of
+//! a key range still stored in memtables and SSTables, tombstones included,
+//! as needed by replication, change data capture backfills and debugging tools.
+//! Versions compaction already dropped are not returned.
+//!
+//! ```rust
+//! use velarixdb::db::{DataStore, VersionSource};
+//! # use tempfile::tempdir;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let root = tempdir().unwrap();
+//!     let mut store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+//!     store.put("apple", "tim cook").await.unwrap();
+//!     store.delete("apple").await.unwrap();
+//!
+//!     let mut iter = store.raw_versions("a", "b").await.unwrap();
+//!     let version = iter.next().await.unwrap().unwrap();
+//!     assert!(version.is_tombstone);
+//!     assert_eq!(version.source, VersionSource::ActiveMemTable);
+//! }
+//! ```
+
+use super::export::{contains_key, overlaps};
+use crate::{
+    bucket::FilePin,
+    consts::{HEAD_ENTRY_KEY, TAIL_ENTRY_KEY},
+    db::DataStore,
+    err::Error,
+    memtable::Val,
+    types::{CreatedAt, Key, ValOffset, Value},
+    vlog::ValueLog,
+};
+use std::{collections::VecDeque, ops::Bound, path::PathBuf};
+
+/// Where a version is stored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionSource {
+    /// Active memtable
+    ActiveMemTable,
+
+    /// Read-only memtable waiting to be flushed
+    ReadOnlyMemTable,
+
+    /// Entry relocated by garbage collection, not yet synced with the active memtable
+    GarbageCollection,
+
+    /// SSTable in the directory
+    SSTable(PathBuf),
+}
+
+/// Version of a key returned by [`RawVersionIterator`]
+#[derive(Debug, Clone)]
+pub struct RawVersion {
+    pub key: Key,
+
+    /// Value of the version, `None` for tombstones and values whose space
+    /// garbage collection already reclaimed
+    pub val: Option<Value>,
+
+    /// Timestamp versions of the key are ordered by
+    pub created_at: CreatedAt,
+
+    pub is_tombstone: bool,
+
+    /// Offset of the entry in the value log
+    pub val_offset: ValOffset,
+
+    pub source: VersionSource,
+}
+
+/// Iterates over stored versions of a key range as they were when it was created
+///
+/// Versions are returned in key order, newest first for the same key. Like
+/// [`RangeIterator`](crate::db::RangeIterator) SSTables are pinned and values
+/// are read as the iterator advances.
+#[derive(Debug)]
+pub struct RawVersionIterator {
+    versions: VecDeque<RawVersion>,
+    v_log: ValueLog,
+    _pin: FilePin,
+}
+
+impl RawVersionIterator {
+    /// Returns next version, or `None` once the range is exhausted
+    ///
+    /// # Errors
+    ///
+    /// Returns error if a value could not be read from value log
+    pub async fn next(&mut self) -> Result<Option<RawVersion>, Error> {
+        let mut version = match self.versions.pop_front() {
+            Some(version) => version,
+            None => return Ok(None),
+        };
+        if !version.is_tombstone {
+            version.val = match self.v_log.get_entry(version.val_offset).await {
+                Ok(Some(entry)) if entry.key == version.key => Some(entry.value),
+                // space of older versions may have been reclaimed
+                Ok(_) | Err(Error::UnexpectedEOF(_) | Error::ChecksumMismatch { .. }) => None,
+                Err(err) => return Err(err),
+            };
+        }
+        Ok(Some(version))
+    }
+
+    /// Returns number of versions not yet returned
+    pub fn remaining(&self) -> usize {
+        self.versions.len()
+    }
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Returns iterator over every stored version with keys from `start` up to, but excluding, `end`
+    ///
+    /// Unlike [`DataStore::seek`] overwritten versions and tombstones are
+    /// returned too, each with its timestamp and where it is stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an sstable could not be read
+    pub async fn raw_versions<T: AsRef<[u8]>>(&self, start: T, end: T) -> Result<RawVersionIterator, Error> {
+        let range = start.as_ref().to_vec()..end.as_ref().to_vec();
+        let mut versions = Vec::new();
+        let mut keep = |key: &[u8], val_offset, created_at, is_tombstone, source: VersionSource| {
+            if key == HEAD_ENTRY_KEY || key == TAIL_ENTRY_KEY || !contains_key(&range, key) {
+                return;
+            }
+            versions.push(RawVersion {
+                key: key.to_vec(),
+                val: None,
+                created_at,
+                is_tombstone,
+                val_offset,
+                source,
+            });
+        };
+
+        for e in self.active_memtable.entries.iter() {
+            let value = e.value();
+            keep(
+                e.key(),
+                value.val_offset,
+                value.created_at,
+                value.is_tombstone,
+                VersionSource::ActiveMemTable,
+            );
+        }
+        for table in self.read_only_memtables.iter() {
+            for e in table.value().entries.iter() {
+                let value = e.value();
+                keep(
+                    e.key(),
+                    value.val_offset,
+                    value.created_at,
+                    value.is_tombstone,
+                    VersionSource::ReadOnlyMemTable,
+                );
+            }
+        }
+        for e in self.gc_updated_entries.read().await.iter() {
+            let value = e.value();
+            keep(
+                e.key(),
+                value.val_offset,
+                value.created_at,
+                value.is_tombstone,
+                VersionSource::GarbageCollection,
+            );
+        }
+        let (pin, tables) = {
+            let buckets = self.buckets.read().await;
+            let mut tables = Vec::new();
+            for bucket in buckets.buckets.values() {
+                tables.extend(bucket.sstables.read().await.iter().cloned());
+            }
+            (buckets.pins.pin(), tables)
+        };
+        for table in tables.iter() {
+            let index = table.index_file.file.load().await?;
+            let mut lower = Bound::Unbounded;
+            for index_entry in index.entries().iter() {
+                let span = (lower, Bound::Included(index_entry.key.as_slice()));
+                lower = Bound::Excluded(index_entry.key.as_slice());
+                if !overlaps(&range, span) {
+                    continue;
+                }
+                let (block, _) = table
+                    .cached_block(
+                        index_entry.block_handle,
+                        &self.config.block_cache,
+                        &self.config.io_retry,
+                        self.config.read_pool.as_ref(),
+                    )
+                    .await?;
+                block.iter().for_each(|e| {
+                    keep(
+                        &e.key,
+                        e.value_offset as usize,
+                        e.creation_date,
+                        e.is_tombstone,
+                        VersionSource::SSTable(table.dir.to_owned()),
+                    )
+                });
+            }
+        }
+
+        versions.sort_by(|a, b| a.key.cmp(&b.key).then(b.created_at.cmp(&a.created_at)));
+        Ok(RawVersionIterator {
+            versions: versions.into(),
+            v_log: self.val_log.clone(),
+            _pin: pin,
+        })
+    }
+}
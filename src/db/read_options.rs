@@ -0,0 +1,106 @@
+use crate::{
+    db::DataStore,
+    err::Error,
+    memtable::{UserEntry, Val},
+    types::Key,
+};
+
+/// Priority of a read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReadPriority {
+    /// Blocks read from disk are cached, background jobs run alongside
+    #[default]
+    Normal,
+
+    /// For latency-critical reads, e.g. serving a user while batch scans run
+    ///
+    /// Blocks read from disk are not admitted to the block cache, and background
+    /// jobs of every store sharing the [`Env`](crate::db::Env) wait for the read
+    /// to finish before they start.
+    High,
+}
+
+/// Options of a read, passed to [`DataStore::get_with_options`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReadOptions {
+    priority: ReadPriority,
+}
+
+impl ReadOptions {
+    /// Creates options of a normal priority read
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets priority of the read
+    pub fn priority(mut self, priority: ReadPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Returns priority of the read
+    pub fn get_priority(&self) -> ReadPriority {
+        self.priority
+    }
+
+    /// Returns true if blocks read from disk should be cached
+    pub(crate) fn admits_blocks(&self) -> bool {
+        self.priority != ReadPriority::High
+    }
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Same as [`DataStore::get`], but reads according to `opts`
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::{DataStore, ReadOptions, ReadPriority};
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let mut store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+    ///
+    ///     store.put("apple", "tim cook").await.unwrap();
+    ///     let opts = ReadOptions::new().priority(ReadPriority::High);
+    ///     let entry = store.get_with_options("apple", &opts).await.unwrap();
+    ///     assert_eq!(entry.unwrap().val, b"tim cook".to_vec());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    pub async fn get_with_options<T: AsRef<[u8]>>(
+        &self,
+        key: T,
+        opts: &ReadOptions,
+    ) -> Result<Option<UserEntry<V>>, Error> {
+        let _read = match opts.priority {
+            ReadPriority::High => Some(self.config.env.begin_priority_read()),
+            ReadPriority::Normal => None,
+        };
+        Ok(self
+            .get_entry_with_options(key, opts)
+            .await?
+            .map(|(entry, _)| entry.into_val()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_options_priority() {
+        let opts = ReadOptions::new();
+        assert_eq!(opts.get_priority(), ReadPriority::Normal);
+        assert!(opts.admits_blocks());
+
+        let opts = opts.priority(ReadPriority::High);
+        assert_eq!(opts.get_priority(), ReadPriority::High);
+        assert!(!opts.admits_blocks());
+    }
+}
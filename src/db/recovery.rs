@@ -1,32 +1,42 @@
 use std::collections::HashSet;
 
-use super::{store::DirPath, DataStore, SizeUnit};
+use super::{
+    expiry::ExpiryIndex, stats::StatsRecorder, store::DirPath, transaction::LockTable, DataStore, SizeUnit,
+};
 
-use crate::bucket::{Bucket, BucketID, BucketMap};
-use crate::cfg::Config;
-use crate::compactors::{self, Compactor, IntervalParams, TtlParams};
+use crate::bucket::{
+    manifest::{BucketManifest, FileNumber, SSTableRecord},
+    Bucket, BucketID, BucketMap, LayoutIssue,
+};
+use crate::cfg::{Config, OnProgress, OpenPhase};
+use crate::compactors::{self, journal::CompactionJournal, Compactor, IntervalParams, TtlParams};
 use crate::consts::{
-    DEFAULT_DB_NAME, DEFAULT_FLUSH_SIGNAL_CHANNEL_SIZE, HEAD_ENTRY_KEY, HEAD_ENTRY_VALUE, SIZE_OF_U32,
-    SIZE_OF_U64, SIZE_OF_U8, TAIL_ENTRY_KEY, TAIL_ENTRY_VALUE,
+    BUCKET_DIRECTORY_PREFIX, DATA_FILE_NAME, DEFAULT_DB_NAME, DEFAULT_FLUSH_SIGNAL_CHANNEL_SIZE,
+    FILTER_FILE_NAME, HEAD_ENTRY_KEY, HEAD_ENTRY_VALUE, INDEX_FILE_NAME, SUMMARY_FILE_NAME, TAIL_ENTRY_KEY,
+    TAIL_ENTRY_VALUE,
 };
 use crate::err::Error;
 use crate::err::Error::*;
 use crate::filter::BloomFilter;
 use crate::flush::Flusher;
-use crate::fs::{FileAsync, P};
-use crate::gc::garbage_collector::GC;
+use crate::fs::{FileAsync, FileNode, FilterFileNode, FilterFs, P};
+use crate::gc::garbage_collector::{PunchMarker, GC};
+use crate::gc::journal::GcJournal;
 use crate::key_range::KeyRange;
 use crate::memtable::{Entry, MemTable};
 use crate::meta::Meta;
 use crate::open_dir_stream;
-use crate::sst::{Summary, Table};
-use crate::types::{ImmutableMemTablesLockFree, Key};
-use crate::vlog::ValueLog;
+use crate::sst::{RangeTombstone, RangeTombstones, Summary, Table};
+use crate::types::{ImmutableMemTablesLockFree, Key, MemtableFlushStream};
+use crate::vlog::{ValueKind, ValueLog};
 use async_broadcast::broadcast;
 use chrono::Utc;
 use crossbeam_skiplist::SkipMap;
 use indexmap::IndexMap;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::fs::read_dir;
 use tokio::sync::RwLock;
 
@@ -60,137 +70,288 @@ impl DataStore<'static, Key> {
             params.meta,
         );
 
-        let mut recovered_buckets: IndexMap<BucketID, Bucket> = IndexMap::new();
-        // Get bucket diretories streams
-        let mut buckets_stream = open_dir_stream!(buckets_path.as_ref().to_path_buf());
-        // for each bucket directory
-        while let Some(bucket_dir) = buckets_stream.next_entry().await.map_err(|err| DirOpen {
-            path: buckets_path.as_ref().to_path_buf(),
-            error: err,
-        })? {
-            // get read stream for sstable directories stream in the bucket
-            let mut sst_dir_stream = open_dir_stream!(bucket_dir.path());
+        let deadline = config.open_time_budget.map(|budget| Instant::now() + budget);
+        let out_of_time = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        let report = |phase, completed, total| {
+            if let Some(on_progress) = &config.on_progress {
+                on_progress.report(phase, completed, total);
+            }
+        };
+
+        // a crash during compaction leaves no sstable both merged and in place
+        CompactionJournal::recover(buckets_path.as_ref()).await?;
+
+        // directories are checked against the manifest of the last open, if any
+        let manifest = BucketManifest::read(buckets_path.as_ref()).await?;
+        let mut layout_issues = Vec::new();
 
-            // iterate over each sstable directory
-            while let Some(sst_dir) = sst_dir_stream.next_entry().await.map_err(|err| DirOpen {
-                path: buckets_path.as_ref().to_path_buf(),
+        // sstable directories are listed first, so progress can be reported
+        let mut sst_dirs = Vec::new();
+        let mut unlisted_file_number = 0;
+        let mut buckets_roots = vec![buckets_path.as_ref().to_path_buf()];
+        if let Some(cold_storage) = &config.cold_storage {
+            if cold_storage.dir.exists() {
+                buckets_roots.push(cold_storage.dir.to_owned());
+            }
+        }
+        for buckets_root in buckets_roots.iter() {
+            // Get bucket diretories streams
+            let mut buckets_stream = open_dir_stream!(buckets_root.to_owned());
+            // for each bucket directory
+            while let Some(bucket_dir) = buckets_stream.next_entry().await.map_err(|err| DirOpen {
+                path: buckets_root.to_owned(),
                 error: err,
             })? {
-                // get read stream for files in the sstable directory
-                let mut files_stream = open_dir_stream!(sst_dir.path());
-                let mut files = Vec::new();
+                let bucket_id = match Self::parse_bucket_dir_name(&bucket_dir.file_name().to_string_lossy()) {
+                    Some(bucket_id) if bucket_dir.path().is_dir() => bucket_id,
+                    _ => {
+                        layout_issues.push(LayoutIssue::UnknownFile(bucket_dir.path()));
+                        continue;
+                    }
+                };
+                // sstables in cold storage still belong to the bucket under `buckets_path`
+                let hot_bucket_dir = buckets_path.as_ref().join(bucket_dir.file_name());
+                FileNode::create_dir_all(hot_bucket_dir.to_owned()).await?;
+                // get read stream for sstable directories stream in the bucket
+                let mut sst_dir_stream = open_dir_stream!(bucket_dir.path());
 
-                // iterate over each file
-                while let Some(file) = files_stream.next_entry().await.map_err(|err| DirOpen {
-                    path: buckets_path.as_ref().to_path_buf(),
+                // iterate over each sstable directory
+                while let Some(sst_dir) = sst_dir_stream.next_entry().await.map_err(|err| DirOpen {
+                    path: buckets_root.to_owned(),
                     error: err,
                 })? {
-                    let file_path = file.path();
-                    if file_path.is_file() {
-                        files.push(file_path);
+                    if !sst_dir.path().is_dir() {
+                        layout_issues.push(LayoutIssue::UnknownFile(sst_dir.path()));
+                        continue;
                     }
-                }
-                // Sort to make order deterministic
-                files.sort();
-                let bucket_id = Self::get_bucket_id_from_full_bucket_path(sst_dir.path());
-
-                if files.len() < 4 {
-                    return Err(InvalidSSTableDirectory {
-                        input_string: sst_dir.path().to_owned().to_string_lossy().to_string(),
+                    // a directory the manifest does not list was left by an interrupted flush
+                    // or compaction, its entries are still in the value log or in the sstables
+                    // it was merged from
+                    let sst_name = sst_dir.file_name().to_string_lossy().to_string();
+                    let listed = manifest.as_ref().is_none_or(|manifest| {
+                        manifest
+                            .bucket(&bucket_id)
+                            .is_some_and(|bucket| bucket.lists(&sst_name))
                     });
+                    // other files can be regenerated, data file can not
+                    if !sst_dir.path().join(format!("{}.db", DATA_FILE_NAME)).is_file() {
+                        if listed {
+                            return Err(InvalidSSTableDirectory {
+                                input_string: sst_dir.path().to_owned().to_string_lossy().to_string(),
+                            });
+                        }
+                        layout_issues.push(LayoutIssue::UnknownFile(sst_dir.path()));
+                        continue;
+                    }
+                    if !listed {
+                        // left in place, but its number is not handed out again
+                        if let Some(number) = SSTableRecord::parse_number(&sst_name) {
+                            unlisted_file_number = unlisted_file_number.max(number + 1);
+                        }
+                        layout_issues.push(LayoutIssue::UnlistedSSTable(sst_dir.path()));
+                        continue;
+                    }
+                    layout_issues.extend(Self::find_unknown_sstable_files(sst_dir.path()).await?);
+                    sst_dirs.push((bucket_id, hot_bucket_dir.to_owned(), sst_dir.path()));
                 }
+            }
+        }
 
-                let data_file_path = files[0].to_owned();
-                let filter_file_path = files[1].to_owned();
-                let index_file_path = files[2].to_owned();
-                let _summary_file_path = files[3].to_owned();
+        // key range and age come from the summary alone, data file is not scanned
+        // unless a file of the sstable has to be regenerated
+        let mut tables = Vec::with_capacity(sst_dirs.len());
+        let range_tombstones = RangeTombstones::default();
+        for (i, (bucket_id, hot_bucket_dir, sst_dir)) in sst_dirs.into_iter().enumerate() {
+            let mut table = Table::build_from(
+                sst_dir.to_owned(),
+                sst_dir.join(format!("{}.db", DATA_FILE_NAME)),
+                sst_dir.join(format!("{}.db", INDEX_FILE_NAME)),
+            )
+            .await?;
+            let summary = Self::recover_summary(&mut table, out_of_time()).await?;
+            if let Some((_, newest)) = summary.created_at_range {
+                table.created_at = newest;
+            }
+            table.range_tombstones = summary.range_tombstones.clone();
+            range_tombstones.extend(summary.range_tombstones.iter().cloned());
+            table.summary = Some(summary);
+            tables.push((bucket_id, hot_bucket_dir, table));
+            report(OpenPhase::SSTableLoad, i + 1, tables.capacity());
+        }
+        let next_file_number =
+            Self::number_sstables(&mut tables, manifest.as_ref()).max(unlisted_file_number);
+
+        let mut bucket_tables: IndexMap<BucketID, (PathBuf, Vec<Table>)> = IndexMap::new();
+        let table_count = tables.len();
+        for (i, (bucket_id, hot_bucket_dir, mut table)) in tables.into_iter().enumerate() {
+            let filter = Self::recover_filter(
+                &mut table,
+                config.false_positive_rate,
+                config.offload_cpu_work,
+                out_of_time(),
+            )
+            .await?;
+            table.filter = Some(filter);
+            bucket_tables
+                .entry(bucket_id)
+                .or_insert_with(|| (hot_bucket_dir, Vec::new()))
+                .1
+                .push(table.clone());
 
-                let mut table = Table::build_from(
-                    sst_dir.path().to_owned(),
-                    data_file_path.to_owned(),
-                    index_file_path.to_owned(),
+            let summary = table.summary.clone().unwrap();
+            key_range
+                .set(
+                    table.dir.to_owned(),
+                    summary.smallest_key,
+                    summary.biggest_key,
+                    table,
                 )
                 .await;
-                let bucket_uuid = uuid::Uuid::parse_str(&bucket_id).map_err(|err| InvaidUUIDParseString {
-                    input_string: bucket_id,
-                    error: err,
-                })?;
-
-                if let Some(b) = recovered_buckets.get(&bucket_uuid) {
-                    let temp_sstables = b.sstables.clone();
-                    temp_sstables.write().await.push(table.clone());
-                    let updated_bucket = Bucket::from(
-                        bucket_dir.path(),
-                        bucket_uuid,
-                        temp_sstables.read().await.clone(),
-                        0,
-                    )
-                    .await?;
-                    recovered_buckets.insert(bucket_uuid, updated_bucket);
-                } else {
-                    // Create new bucket
-                    let updated_bucket =
-                        Bucket::from(bucket_dir.path(), bucket_uuid, vec![table.clone()], 0).await?;
-                    recovered_buckets.insert(bucket_uuid, updated_bucket);
+            report(OpenPhase::FilterBuild, i + 1, table_count);
+        }
+        let mut buckets_map = BucketMap::new(buckets_path.as_ref())
+            .await?
+            .with_cpu_offload(config.offload_cpu_work);
+        buckets_map.next_file_number = next_file_number;
+        for (bucket_id, (hot_bucket_dir, mut tables)) in bucket_tables {
+            // directories are listed in no particular order
+            tables.sort_by_key(|table| table.file_number);
+            let record = manifest.as_ref().and_then(|manifest| manifest.bucket(&bucket_id));
+            let sst_names: Vec<String> = tables
+                .iter()
+                .map(|table| {
+                    table
+                        .dir
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string()
+                })
+                .collect();
+            // average size is only reused if the bucket holds the sstables it was computed for
+            let mut avarage_size = 0;
+            if let Some(record) = record {
+                if record.sstables.len() == sst_names.len() && sst_names.iter().all(|name| record.lists(name))
+                {
+                    avarage_size = record.avarage_size;
+                }
+            }
+            let bucket = Bucket::from(hot_bucket_dir, bucket_id, tables, avarage_size).await?;
+            buckets_map.buckets.insert(bucket_id, bucket);
+        }
+        if let Some(manifest) = &manifest {
+            for record in manifest.buckets.iter() {
+                let bucket_dir = format!("{}{}", BUCKET_DIRECTORY_PREFIX, record.id);
+                let recovered = buckets_map.buckets.get(&record.id);
+                for name in record.sstables.iter().map(|sst| &sst.name) {
+                    let found = match recovered {
+                        Some(bucket) => bucket
+                            .sstables
+                            .read()
+                            .await
+                            .iter()
+                            .any(|table| table.dir.file_name().is_some_and(|n| n == name.as_str())),
+                        None => false,
+                    };
+                    if !found {
+                        layout_issues.push(LayoutIssue::MissingSSTable(
+                            buckets_path.as_ref().join(&bucket_dir).join(name),
+                        ));
+                    }
                 }
-
-                // recover summary
-                let mut summary = Summary::new(sst_dir.path());
-                summary.recover().await?;
-                table.summary = Some(summary.to_owned());
-
-                // store bloomfilter metadata in table
-                let new_filter = BloomFilter {
-                    file_path: Some(filter_file_path),
-                    ..Default::default()
-                };
-                table.filter = Some(new_filter);
-
-                key_range
-                    .set(sst_dir.path(), summary.smallest_key, summary.biggest_key, table)
-                    .await;
             }
         }
-        let mut buckets_map = BucketMap::new(buckets_path.as_ref()).await?;
-        for (bucket_id, bucket) in recovered_buckets.iter() {
-            buckets_map.buckets.insert(*bucket_id, bucket.clone());
+        for issue in layout_issues.iter() {
+            log::warn!("Store layout does not match bucket manifest: {:?}", issue);
         }
+        buckets_map.write_manifest().await?;
         if meta.file_handle.file.node.size().await > 0 {
             meta.recover().await?;
             vlog.set_head(meta.v_log_head);
             vlog.set_tail(meta.v_log_tail);
         } else {
             // if meta is empty then no flush has happened before crash
-            // therefore read from the beginning of vlog
-            vlog.set_head(
-                SIZE_OF_U32               // tail key length 
-                +SIZE_OF_U32              // tail value length
-                + SIZE_OF_U64             // date Length
-                + SIZE_OF_U8              // tombstone marker
-                + TAIL_ENTRY_KEY.len()    // tail key
-                + TAIL_ENTRY_VALUE.len(), // tail value
-            );
+            // therefore read from the entry after the tail entry at the beginning of vlog
+            // vlog is not empty here so the tail entry exists
+            let tail_entry_len = vlog
+                .get_entry(0)
+                .await?
+                .map(|e| e.encoded_len())
+                .unwrap_or_default();
+            vlog.set_head(tail_entry_len);
             vlog.set_tail(0);
         }
+        // entry at the head is already in an sstable unless the journal moves the head
+        let mut skip_head = true;
+        // garbage collection may have moved the tail past the one in meta
+        let punch_marker = PunchMarker::read(&dir.val_log).await?.unwrap_or_default();
+        let reached_tail = punch_marker.reached_tail();
+        if reached_tail > vlog.tail_offset && reached_tail <= vlog.size {
+            vlog.set_tail(reached_tail);
+            meta.set_tail(reached_tail);
+            if vlog.head_offset < reached_tail {
+                skip_head = false;
+                vlog.set_head(reached_tail);
+                meta.set_head(reached_tail);
+            }
+        }
+        // a crash during garbage collection is rolled back or forward as journaled
+        if let Some(journal) = GcJournal::read(&dir.val_log).await? {
+            let roll_forward = journal.can_roll_forward(&vlog).await?;
+            let (head, tail) = journal.recover(roll_forward, vlog.head_offset, vlog.tail_offset);
+            #[cfg(target_os = "linux")]
+            if roll_forward && vlog.tail_offset < journal.new_tail_offset {
+                let (offset, length) = (journal.tail_offset, journal.new_tail_offset - journal.tail_offset);
+                let vlog_path = vlog.content.file.node.file_path.to_owned();
+                GC::punch_holes(vlog_path, offset as i64, length as i64).await?;
+            }
+            skip_head &= head == vlog.head_offset;
+            vlog.set_head(head);
+            vlog.set_tail(tail);
+            meta.set_head(head);
+            meta.set_tail(tail);
+        }
+        let punch_marker = punch_marker.resume(vlog.tail_offset);
+        vlog.rebuild_dedup_index().await?;
 
-        let recover_res = DataStore::recover_memtable(
+        let recover_res = DataStore::replay_vlog(
             size_unit,
             config.write_buffer_size,
             config.false_positive_rate,
             &dir.val_log,
             vlog.head_offset,
+            skip_head,
+            config.on_progress.as_ref(),
         )
         .await;
-        let (flush_signal_tx, flush_signal_rx) = broadcast(DEFAULT_FLUSH_SIGNAL_CHANNEL_SIZE);
+        let (mut flush_signal_tx, flush_signal_rx) = broadcast(DEFAULT_FLUSH_SIGNAL_CHANNEL_SIZE);
+        // slow subscribers lose old signals instead of blocking new ones
+        flush_signal_tx.set_overflow(true);
         match recover_res {
             Ok((active_memtable, read_only_memtables)) => {
+                range_tombstones.extend(active_memtable.range_tombstones.iter().cloned());
+                for table in read_only_memtables.iter() {
+                    range_tombstones.extend(table.value().range_tombstones.iter().cloned());
+                }
                 let buckets = Arc::new(RwLock::new(buckets_map.to_owned()));
                 let key_range = Arc::new(key_range.to_owned());
                 let read_only_memtables = Arc::new(read_only_memtables);
                 let gc_table = Arc::new(RwLock::new(active_memtable.to_owned()));
                 let gc_log = Arc::new(RwLock::new(vlog.to_owned()));
-                let flusher = Flusher::new(read_only_memtables.clone(), buckets.clone(), key_range.clone());
+                let flush_stream = MemtableFlushStream::default();
+                let flusher = Flusher::new(
+                    read_only_memtables.clone(),
+                    buckets.clone(),
+                    key_range.clone(),
+                    config.env.clone(),
+                )
+                .with_filter_memory_budget(config.filter_memory_budget)
+                .with_cpu_offload(config.offload_cpu_work)
+                .with_retained(Arc::default(), config.retained_memtables)
+                .with_flush_stream(flush_stream.clone());
                 let gc_updated_entries = Arc::new(RwLock::new(SkipMap::new()));
+                let expiry = ExpiryIndex::open(&dir.root).await?;
+                let stats = StatsRecorder::open(&dir.root).await?;
                 Ok(DataStore {
                     keyspace: DEFAULT_DB_NAME,
                     active_memtable: active_memtable.to_owned(),
@@ -214,7 +375,16 @@ impl DataStore<'static, Key> {
                         config.compaction_strategy,
                         compactors::CompactionReason::MaxSize,
                         config.false_positive_rate,
-                    ),
+                        config.env.clone(),
+                    )
+                    .with_cold_storage(config.cold_storage.clone())
+                    .with_policy(config.compaction_policy.clone())
+                    .with_jitter(config.background_jitter)
+                    .with_filter_memory_budget(config.filter_memory_budget)
+                    .with_cpu_offload(config.offload_cpu_work)
+                    .with_expiry(expiry.clone())
+                    .with_range_tombstones(range_tombstones.clone())
+                    .with_stats(&stats),
                     config: config.clone(),
                     gc: GC::new(
                         config.online_gc_interval,
@@ -222,7 +392,17 @@ impl DataStore<'static, Key> {
                         gc_table.clone(),
                         gc_log.clone(),
                         gc_updated_entries.clone(),
-                    ),
+                        config.env.clone(),
+                        config.block_cache.clone(),
+                    )
+                    .with_pins(buckets_map.pins.clone())
+                    .with_punch_marker(punch_marker)
+                    .with_retry(config.io_retry)
+                    .with_tombstone_ttl(config.tombstone_ttl)
+                    .with_range_tombstones(range_tombstones.clone())
+                    .with_disk_pressure(config.gc_disk_pressure.clone())
+                    .with_stats(&stats)
+                    .with_jitter(config.background_jitter),
                     read_only_memtables,
                     range_iterator: None,
                     flush_signal_tx,
@@ -230,7 +410,13 @@ impl DataStore<'static, Key> {
                     gc_log,
                     gc_table,
                     gc_updated_entries,
-                    flush_stream: HashSet::new(),
+                    flush_stream,
+                    layout_issues,
+                    expiry,
+                    locks: LockTable::default(),
+                    stats,
+                    range_tombstones,
+                    value_type: PhantomData,
                 })
             }
             Err(err) => Err(MemTableRecovery(Box::new(err))),
@@ -248,6 +434,29 @@ impl DataStore<'static, Key> {
         false_positive_rate: f64,
         vlog_path: impl P,
         head_offset: usize,
+        on_progress: Option<&OnProgress>,
+    ) -> Result<(MemTable<Key>, ImmutableMemTablesLockFree<Key>), Error> {
+        DataStore::replay_vlog(
+            size_unit,
+            capacity,
+            false_positive_rate,
+            vlog_path,
+            head_offset,
+            true,
+            on_progress,
+        )
+        .await
+    }
+
+    /// Same as [`DataStore::recover_memtable`], replaying the entry at `head_offset` too unless `skip_head`
+    pub(crate) async fn replay_vlog(
+        size_unit: SizeUnit,
+        capacity: usize,
+        false_positive_rate: f64,
+        vlog_path: impl P,
+        head_offset: usize,
+        skip_head: bool,
+        on_progress: Option<&OnProgress>,
     ) -> Result<(MemTable<Key>, ImmutableMemTablesLockFree<Key>), Error> {
         let read_only_memtables: ImmutableMemTablesLockFree<Key> = SkipMap::new();
         let mut active_memtable =
@@ -255,13 +464,24 @@ impl DataStore<'static, Key> {
         let mut vlog = ValueLog::new(vlog_path.as_ref()).await?;
         let mut most_recent_offset = head_offset;
         let entries = vlog.recover(head_offset).await?;
+        let entry_count = entries.len();
+        let mut reported_percent = 0;
 
-        for e in entries {
+        for (i, e) in entries.into_iter().enumerate() {
+            if let Some(on_progress) = on_progress {
+                // report whole percents only, value logs can hold millions of entries
+                let percent = (i + 1) * 100 / entry_count;
+                if percent > reported_percent || i == 0 {
+                    reported_percent = percent;
+                    on_progress.report(OpenPhase::VLogReplay, i + 1, entry_count);
+                }
+            }
             let entry = Entry::new(e.key.to_owned(), most_recent_offset, e.created_at, e.is_tombstone);
             // Since the most recent offset is the offset we start reading entries from in value log
             // and we retrieved this from the sstable, therefore should not re-write the initial entry in
             // memtable since it's already in the sstable
-            if most_recent_offset != head_offset {
+            // blobs only hold values of references, they have no key
+            if (most_recent_offset != head_offset || !skip_head) && e.kind != ValueKind::Blob {
                 if active_memtable.is_full(e.key.len()) {
                     // Make memtable read only
                     active_memtable.read_only = true;
@@ -273,13 +493,12 @@ impl DataStore<'static, Key> {
                         MemTable::with_specified_capacity_and_rate(size_unit, capacity, false_positive_rate);
                 }
                 active_memtable.insert(&entry);
+                if e.kind == ValueKind::RangeTombstone {
+                    let tombstone = RangeTombstone::new(e.key.to_owned(), e.value.to_owned(), e.created_at);
+                    active_memtable.range_tombstones.push(tombstone);
+                }
             }
-            most_recent_offset += SIZE_OF_U32   // Key Size(for fetching key length)
-                        +SIZE_OF_U32            // Value Length(for fetching value length)
-                        + SIZE_OF_U64           // Date Length
-                        + SIZE_OF_U8            // Tombstone marker
-                        + e.key.len()           // Key Length
-                        + e.value.len(); // Value Length
+            most_recent_offset += e.encoded_len();
         }
 
         Ok((active_memtable, read_only_memtables))
@@ -331,16 +550,33 @@ impl DataStore<'static, Key> {
         // insert tail and head to memtable
         active_memtable.insert(&tail_entry.to_owned());
         active_memtable.insert(&head_entry.to_owned());
-        let buckets = BucketMap::new(buckets_path).await?;
-        let (flush_signal_tx, flush_signal_rx) = broadcast(DEFAULT_FLUSH_SIGNAL_CHANNEL_SIZE);
+        let buckets = BucketMap::new(buckets_path)
+            .await?
+            .with_cpu_offload(config.offload_cpu_work);
+        let (mut flush_signal_tx, flush_signal_rx) = broadcast(DEFAULT_FLUSH_SIGNAL_CHANNEL_SIZE);
+        flush_signal_tx.set_overflow(true);
         let read_only_memtables = SkipMap::new();
+        let pins = buckets.pins.clone();
         let buckets = Arc::new(RwLock::new(buckets.to_owned()));
         let key_range = Arc::new(key_range);
         let read_only_memtables = Arc::new(read_only_memtables);
         let gc_table = Arc::new(RwLock::new(active_memtable.to_owned()));
         let gc_log = Arc::new(RwLock::new(vlog.to_owned()));
-        let flusher = Flusher::new(read_only_memtables.clone(), buckets.clone(), key_range.clone());
+        let flush_stream = MemtableFlushStream::default();
+        let flusher = Flusher::new(
+            read_only_memtables.clone(),
+            buckets.clone(),
+            key_range.clone(),
+            config.env.clone(),
+        )
+        .with_filter_memory_budget(config.filter_memory_budget)
+        .with_cpu_offload(config.offload_cpu_work)
+        .with_retained(Arc::default(), config.retained_memtables)
+        .with_flush_stream(flush_stream.clone());
         let gc_updated_entries = Arc::new(RwLock::new(SkipMap::new()));
+        let expiry = ExpiryIndex::open(&dir.root).await?;
+        let stats = StatsRecorder::open(&dir.root).await?;
+        let range_tombstones = RangeTombstones::default();
         Ok(DataStore {
             keyspace: DEFAULT_DB_NAME,
             active_memtable,
@@ -362,7 +598,16 @@ impl DataStore<'static, Key> {
                 config.compaction_strategy,
                 compactors::CompactionReason::MaxSize,
                 config.false_positive_rate,
-            ),
+                config.env.clone(),
+            )
+            .with_cold_storage(config.cold_storage.clone())
+            .with_policy(config.compaction_policy.clone())
+            .with_jitter(config.background_jitter)
+            .with_filter_memory_budget(config.filter_memory_budget)
+            .with_cpu_offload(config.offload_cpu_work)
+            .with_expiry(expiry.clone())
+            .with_range_tombstones(range_tombstones.clone())
+            .with_stats(&stats),
             meta,
             flusher,
             read_only_memtables,
@@ -375,28 +620,206 @@ impl DataStore<'static, Key> {
                 gc_table.clone(),
                 gc_log.clone(),
                 gc_updated_entries.clone(),
-            ),
+                config.env.clone(),
+                config.block_cache.clone(),
+            )
+            .with_pins(pins)
+            .with_retry(config.io_retry)
+            .with_tombstone_ttl(config.tombstone_ttl)
+            .with_range_tombstones(range_tombstones.clone())
+            .with_disk_pressure(config.gc_disk_pressure.clone())
+            .with_stats(&stats)
+            .with_jitter(config.background_jitter),
             gc_log,
             gc_table,
             gc_updated_entries,
-            flush_stream: HashSet::new(),
+            flush_stream,
+            layout_issues: Vec::new(),
+            expiry,
+            locks: LockTable::default(),
+            stats,
+            range_tombstones,
+            value_type: PhantomData,
             config,
         })
     }
 
-    fn get_bucket_id_from_full_bucket_path(full_path: impl P) -> String {
-        let full_path_as_str = full_path.as_ref().to_string_lossy().to_string();
-        let mut bucket_id = String::new();
-        // Find the last occurrence of "bucket" in the file path
-        if let Some(idx) = full_path_as_str.rfind("bucket") {
-            // Extract the substring starting from the index after the last occurrence of "bucket"
-            let uuid_part = &full_path_as_str[idx + "bucket".len()..];
-            if let Some(end_idx) = uuid_part.find('/') {
-                // Extract the UUID
-                let uuid = &uuid_part[..end_idx];
-                bucket_id = uuid.to_string();
+    /// Recovers summary of `table`
+    ///
+    /// Index and summary files that are missing or corrupt are regenerated
+    /// from the data file, instead of failing recovery. If `lazy` is set the
+    /// index is only checked on first lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if the data file could not be read or a file could not be rewritten
+    async fn recover_summary(table: &mut Table, lazy: bool) -> Result<Summary, Error> {
+        // corrupt or missing index is rebuilt while loading
+        if !lazy {
+            table.index_file.file.load().await?;
+        }
+
+        let mut summary = Summary::new(&table.dir);
+        if let Err(err) = summary.recover().await {
+            Self::load_table_entries(table).await?;
+            log::warn!(
+                "Summary file {:?} is unusable ({}), rebuilding it from data file",
+                summary.path,
+                err
+            );
+            Self::remove_file_if_exists(&summary.path).await?;
+            summary.set_from_entries(&table.entries);
+            summary.write_to_file().await?;
+            // Don't keep sst entries in memory
+            table.entries.clear();
+        }
+        Ok(summary)
+    }
+
+    /// Recovers filter metadata of `table`
+    ///
+    /// A filter file that is missing or corrupt is rebuilt from the data file,
+    /// filter bits are otherwise restored on first lookup. If `lazy` is set the
+    /// filter file is only checked on first lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if the data file could not be read or the filter could not be rewritten
+    async fn recover_filter(
+        table: &mut Table,
+        false_positive_rate: f64,
+        offload_cpu_work: bool,
+        lazy: bool,
+    ) -> Result<BloomFilter, Error> {
+        let filter_file_path = table.dir.join(format!("{}.db", FILTER_FILE_NAME));
+        let filter_res = if lazy {
+            Ok(())
+        } else {
+            FilterFileNode::recover(&filter_file_path).await.map(|_| ())
+        };
+        if let Err(err) = filter_res {
+            Self::load_table_entries(table).await?;
+            log::warn!(
+                "Filter file {:?} is unusable ({}), rebuilding it from data file",
+                filter_file_path,
+                err
+            );
+            Self::remove_file_if_exists(&filter_file_path).await?;
+            let mut filter = BloomFilter::new(false_positive_rate, table.entries.len())
+                .build_from_entries(table.entries.clone(), offload_cpu_work)
+                .await?;
+            filter.write(&table.dir).await?;
+            filter.set_sstable_path(&table.data_file.path);
+            // Don't keep sst entries in memory
+            table.entries.clear();
+            return Ok(filter);
+        }
+        Ok(BloomFilter {
+            file_path: Some(filter_file_path),
+            ..Default::default()
+        })
+    }
+
+    /// Loads entries of `table` from its data file to regenerate other files
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if the data file could not be read or has no entries
+    async fn load_table_entries(table: &mut Table) -> Result<(), Error> {
+        table.load_entries_from_file().await?;
+        if table.entries.is_empty() {
+            return Err(InvalidSSTableDirectory {
+                input_string: table.dir.to_string_lossy().to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Removes file so it can be rewritten from scratch
+    async fn remove_file_if_exists(path: &Path) -> Result<(), Error> {
+        match tokio::fs::remove_file(path).await {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(FileDelete {
+                path: path.to_path_buf(),
+                error,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns bucket id from name of a bucket directory, `None` if it is not one
+    fn parse_bucket_dir_name(name: &str) -> Option<BucketID> {
+        name.strip_prefix(BUCKET_DIRECTORY_PREFIX)
+            .and_then(|id| uuid::Uuid::parse_str(id).ok())
+    }
+
+    /// Sets file number of every table in `tables`, returns the number of the next sstable
+    ///
+    /// Numbers are taken from the manifest, or from the directory name of sstables
+    /// written since it. SSTables of older versions, named by creation time, and
+    /// ones whose number is taken already are numbered in order of creation
+    /// after the biggest number in use.
+    fn number_sstables(
+        tables: &mut [(BucketID, PathBuf, Table)],
+        manifest: Option<&BucketManifest>,
+    ) -> FileNumber {
+        // names only hold file numbers once a manifest counting them was written
+        let numbered = manifest.is_some_and(|manifest| manifest.next_file_number > 0);
+        let mut next_file_number = manifest.map_or(1, |manifest| manifest.next_file_number.max(1));
+        let mut used = HashSet::new();
+        let mut unnumbered = Vec::new();
+        for (bucket_id, _, table) in tables.iter_mut() {
+            let name = table
+                .dir
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            let listed = manifest
+                .and_then(|manifest| manifest.bucket(bucket_id))
+                .and_then(|bucket| bucket.sstable(&name))
+                .and_then(|sst| sst.number);
+            let number = listed.or_else(|| SSTableRecord::parse_number(&name).filter(|_| numbered));
+            match number.filter(|number| used.insert(*number)) {
+                Some(number) => {
+                    table.file_number = number;
+                    next_file_number = next_file_number.max(number + 1);
+                }
+                None => unnumbered.push(table),
+            }
+        }
+        unnumbered.sort_by_key(|table| table.created_at);
+        for table in unnumbered {
+            table.file_number = next_file_number;
+            next_file_number += 1;
+        }
+        next_file_number
+    }
+
+    /// Returns files in an sstable directory other than the ones an sstable is made of
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the directory could not be read
+    async fn find_unknown_sstable_files(sst_dir: PathBuf) -> Result<Vec<LayoutIssue>, Error> {
+        let known: Vec<String> = [
+            DATA_FILE_NAME,
+            INDEX_FILE_NAME,
+            FILTER_FILE_NAME,
+            SUMMARY_FILE_NAME,
+        ]
+        .iter()
+        .map(|name| format!("{}.db", name))
+        .collect();
+        let mut unknown = Vec::new();
+        let mut files = open_dir_stream!(sst_dir.to_owned());
+        while let Some(file) = files.next_entry().await.map_err(|err| DirOpen {
+            path: sst_dir.to_owned(),
+            error: err,
+        })? {
+            if !known.iter().any(|name| file.file_name() == name.as_str()) {
+                unknown.push(LayoutIssue::UnknownFile(file.path()));
             }
         }
-        bucket_id
+        Ok(unknown)
     }
 }
@@ -0,0 +1,154 @@
+use crate::{
+    consts::{DATA_FILE_NAME, EOF, FORMAT_VERSION, INDEX_FILE_NAME, TOMB_STONE_MARKER},
+    db::{DataStore, ExportManifest},
+    err::Error,
+    fs::P,
+    memtable::{Entry, Val},
+    sst::Table,
+    types::{Key, Metadata, Value},
+    vlog::ValueLogEntry,
+};
+use chrono::Utc;
+use std::io;
+use tokio::fs;
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Moves entries with keys from `key` onwards out of the store, as an export written to `dir`
+    ///
+    /// Exported keys are deleted with tombstones sharing one timestamp and written
+    /// in a single value log batch, at the cutover point of the split. The store
+    /// is borrowed mutably, no write can land between export and deletion, so
+    /// every key ends up in exactly one of the two stores once `dir` is merged
+    /// into another one with [`DataStore::merge_from`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let mut store = DataStore::open("big_tech", root.path().join("a_to_m")).await.unwrap();
+    ///     store.put("apple", "tim cook").await.unwrap();
+    ///     store.put("nvidia", "jensen huang").await.unwrap();
+    ///
+    ///     let manifest = store.split_at("n", root.path().join("export")).await.unwrap();
+    ///     assert_eq!(manifest.entry_count, 1);
+    ///     assert!(store.get("nvidia").await.unwrap().is_none());
+    ///
+    ///     let mut other = DataStore::open("big_tech", root.path().join("n_to_z")).await.unwrap();
+    ///     other.merge_from(root.path().join("export")).await.unwrap();
+    ///     assert!(other.get("nvidia").await.unwrap().is_some());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if export fails, in which case no key was deleted
+    pub async fn split_at<T: AsRef<[u8]>>(&mut self, key: T, dir: impl P) -> Result<ExportManifest, Error> {
+        let (manifest, keys) = self.export_range(key.as_ref().., dir.as_ref()).await?;
+        if keys.is_empty() {
+            return Ok(manifest);
+        }
+
+        if !self.gc_updated_entries.read().await.is_empty() {
+            self.sync_gc_update_with_store().await?
+        }
+        let cutover = Utc::now();
+        let tombstones: Vec<_> = keys
+            .into_iter()
+            .map(|key| (key, TOMB_STONE_MARKER.as_bytes().to_vec(), cutover, true))
+            .collect();
+        self.seal_wal().await?;
+        let offsets = self.val_log.append_batch(&tombstones).await?;
+        for ((key, _, created_at, is_tombstone), v_offset) in tombstones.into_iter().zip(offsets) {
+            self.insert_to_memtable(Entry::new(key, v_offset, created_at, is_tombstone))
+                .await?;
+        }
+        Ok(manifest)
+    }
+
+    /// Adds entries of an export at `dir`, written by [`DataStore::export`] or [`DataStore::split_at`]
+    ///
+    /// The whole export is read and verified before anything is written, then
+    /// its entries are appended in a single value log batch sharing one timestamp,
+    /// so they replace existing versions of the same keys all at once. Metadata of
+    /// exported values is kept. Returns number of merged entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::IncompatibleFormat` if the export is newer than this build
+    /// supports, `Error::QuotaExceeded` if its entries don't fit the keyspace quota,
+    /// or error if the export is corrupt, in which case nothing was merged
+    pub async fn merge_from(&mut self, dir: impl P) -> Result<usize, Error> {
+        let dir = dir.as_ref();
+        let manifest = ExportManifest::read(dir).await?;
+        if manifest.format_version > FORMAT_VERSION {
+            return Err(Error::IncompatibleFormat {
+                path: dir.to_path_buf(),
+                found: manifest.format_version,
+                supported: FORMAT_VERSION,
+            });
+        }
+
+        let vlog_path = dir.join(&manifest.vlog);
+        let vlog = fs::read(&vlog_path).await.map_err(|err| Error::FileRead {
+            path: vlog_path,
+            error: err,
+        })?;
+        let mut entries: Vec<(Key, Value, Option<Metadata>)> = Vec::with_capacity(manifest.entry_count);
+        for sst_dir in manifest.sstables.iter() {
+            let sst_dir = dir.join(sst_dir);
+            let mut table = Table::build_from(
+                sst_dir.to_owned(),
+                sst_dir.join(format!("{}.db", DATA_FILE_NAME)),
+                sst_dir.join(format!("{}.db", INDEX_FILE_NAME)),
+            )
+            .await?;
+            table.load_entries_from_file().await?;
+            for e in table.entries.iter() {
+                let offset = e.value().val_offset;
+                let entry = match vlog.get(offset..).and_then(ValueLogEntry::deserialize) {
+                    Some((entry, _)) => entry,
+                    None => {
+                        return Err(Error::UnexpectedEOF(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            EOF,
+                        )))
+                    }
+                };
+                entry.verify(e.key(), offset)?;
+                self.validate_size(e.key(), Some(&entry.value))?;
+                entries.push((e.key().to_owned(), entry.value, entry.metadata));
+            }
+        }
+        if entries.is_empty() {
+            return Ok(0);
+        }
+        let bytes = entries
+            .iter()
+            .map(|(key, value, metadata)| key.len() + value.len() + metadata.as_ref().map_or(0, |m| m.len()))
+            .sum();
+        self.check_quota(bytes, entries.iter().map(|(key, _, _)| (key.as_slice(), false)))
+            .await?;
+
+        if !self.gc_updated_entries.read().await.is_empty() {
+            self.sync_gc_update_with_store().await?
+        }
+        let cutover = Utc::now();
+        let batch: Vec<_> = entries
+            .into_iter()
+            .map(|(key, value, metadata)| (key, value, metadata, cutover))
+            .collect();
+        self.seal_wal().await?;
+        let offsets = self.val_log.append_batch_with_metadata(&batch).await?;
+        let merged = batch.len();
+        for ((key, _, _, created_at), v_offset) in batch.into_iter().zip(offsets) {
+            self.insert_to_memtable(Entry::new(key, v_offset, created_at, false))
+                .await?;
+        }
+        Ok(merged)
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{
+    db::DataStore,
+    err::Error,
+    memtable::{Entry, Val},
+    types::Key,
+};
+use chrono::Utc;
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Removes an entry from the store, keeping its value so that [`DataStore::undelete`] can restore it
+    ///
+    /// The key reads as deleted, like after [`DataStore::delete`]. Its value and
+    /// metadata stay in the value log for `Config::tombstone_ttl`, after which
+    /// garbage collection drops them and compaction purges the deletion.
+    ///
+    /// Returns `true` if the entry was deleted, `false` if the key was not found.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use velarixdb::db::DataStore;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     store.put("apple", "tim cook").await.unwrap(); // handle error
+    ///     assert!(store.soft_delete("apple").await.unwrap());
+    ///     assert!(store.get("apple").await.unwrap().is_none());
+    ///
+    ///     assert!(store.undelete("apple").await.unwrap());
+    ///     assert_eq!(store.get("apple").await.unwrap().unwrap().val, b"tim cook");
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    pub async fn soft_delete<T: AsRef<[u8]>>(&mut self, key: T) -> Result<bool, Error> {
+        let (entry, metadata) = match self.get_entry_with_metadata(key.as_ref()).await? {
+            Some(found) => found,
+            None => return Ok(false),
+        };
+
+        if !self.gc_updated_entries.read().await.is_empty() {
+            self.sync_gc_update_with_store().await?
+        }
+        // write-ahead log entries carry no metadata
+        self.seal_wal().await?;
+        let created_at = Utc::now();
+        let v_offset = self
+            .val_log
+            .append_deleted(key.as_ref(), &entry.val, metadata.as_deref(), created_at)
+            .await?;
+        let entry = Entry::new(key.as_ref().to_vec(), v_offset, created_at, true);
+        self.insert_to_memtable(entry).await?;
+        Ok(true)
+    }
+
+    /// Restores an entry removed with [`DataStore::soft_delete`]
+    ///
+    /// Value and metadata are written again as the most recent version of the key.
+    ///
+    /// Returns `false` if the key is not soft deleted, e.g. it was written or deleted
+    /// with [`DataStore::delete`] since, or was deleted longer than `Config::tombstone_ttl` ago.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use velarixdb::db::DataStore;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     store.put("apple", "tim cook").await.unwrap(); // handle error
+    ///     store.delete("apple").await.unwrap();
+    ///
+    ///     // nothing to restore after a permanent deletion
+    ///     assert!(!store.undelete("apple").await.unwrap());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the soft deleted entry is corrupted.
+    pub async fn undelete<T: AsRef<[u8]>>(&mut self, key: T) -> Result<bool, Error> {
+        self.validate_size(key.as_ref(), None::<T>)?;
+        if !self.gc_updated_entries.read().await.is_empty() {
+            self.sync_gc_update_with_store().await?
+        }
+        let offset = match self.latest_version(key.as_ref()).await? {
+            // a range deleted since is not undone
+            Some(version)
+                if version.is_tombstone
+                    && !self.range_tombstones.covers(key.as_ref(), version.created_at) =>
+            {
+                version.val_offset
+            }
+            _ => return Ok(false),
+        };
+        let deleted = match self.val_log.get_entry(offset).await? {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+        deleted.verify(key.as_ref(), offset)?;
+        if !deleted.is_soft_deleted() || deleted.has_expired(self.config.tombstone_ttl) {
+            return Ok(false);
+        }
+        match deleted.metadata {
+            Some(metadata) => self.put_with_metadata(key, deleted.value, metadata).await,
+            None => self.put(key, deleted.value).await,
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use crate::{
+    consts::STATS_FILE_NAME, db::DataStore, env::Timer, err::Error, fs::FileNode, memtable::Val, types::Key,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Weak,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{fs, sync::Mutex, task::JoinHandle, time::sleep};
+
+/// Cumulative statistics of a store, returned by [`DataStore::stats`]
+///
+/// Counters add up over every time the store was opened. They are kept in the
+/// stats file in the root directory of the store, written at open, every
+/// `Config::stats_persist_interval` and by [`DataStore::persist_stats`], so
+/// counts since the last write are lost if the process crashes.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StoreStats {
+    /// Entries written, deletions excluded
+    pub total_puts: u64,
+
+    /// Bytes of SSTables written by compaction
+    pub compaction_bytes: u64,
+
+    /// Bytes of value log garbage collection released to the file system
+    pub gc_reclaimed: u64,
+
+    /// Times the store was opened, including the current one
+    pub open_count: u64,
+
+    /// Time the store has been open since it was last opened
+    #[serde(skip)]
+    pub uptime: Duration,
+
+    /// Time the store has been open over all times it was opened
+    pub total_uptime: Duration,
+}
+
+impl StoreStats {
+    /// Returns path of the stats file of store at `root`
+    pub(crate) fn path(root: impl AsRef<Path>) -> PathBuf {
+        root.as_ref().join(STATS_FILE_NAME)
+    }
+
+    /// Reads stats of store at `root`, zeroed if it has none or they can't be parsed
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the file cannot be read
+    pub(crate) async fn read(root: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = Self::path(root);
+        let buf = match fs::read(&path).await {
+            Ok(buf) => buf,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(Error::FileOpen { path, error: err }),
+        };
+        // statistics are not worth refusing to open the store
+        Ok(serde_json::from_slice(&buf).unwrap_or_else(|err| {
+            log::warn!("Stats file {:?} is corrupt, counting from zero: {}", path, err);
+            Self::default()
+        }))
+    }
+
+    /// Writes stats of store at `root`, replacing any previous ones atomically
+    pub(crate) async fn write(&self, root: impl AsRef<Path>) -> Result<(), Error> {
+        let buf = serde_json::to_vec_pretty(self).map_err(|_| Error::Serialization("store stats"))?;
+        FileNode::write_atomic(Self::path(root), &buf).await
+    }
+}
+
+/// Counts work of the store for [`StoreStats`] on top of the stats it was opened with
+#[derive(Debug, Clone)]
+pub(crate) struct StatsRecorder {
+    inner: Arc<StatsInner>,
+}
+
+#[derive(Debug)]
+struct StatsInner {
+    root: PathBuf,
+
+    /// Stats read at open, with this open counted
+    opened_with: StoreStats,
+    opened_at: Instant,
+    puts: AtomicU64,
+
+    /// Shared with the compactor
+    compaction_bytes: Arc<AtomicU64>,
+
+    /// Shared with the garbage collector
+    gc_reclaimed: Arc<AtomicU64>,
+
+    /// Keeps concurrent writes of the stats file in order
+    write_lock: Mutex<()>,
+}
+
+impl StatsRecorder {
+    /// Reads stats of store at `root` and writes them back with this open counted
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the stats file cannot be read or written
+    pub(crate) async fn open(root: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut opened_with = StoreStats::read(&root).await?;
+        opened_with.open_count += 1;
+        opened_with.write(&root).await?;
+        Ok(Self {
+            inner: Arc::new(StatsInner {
+                root: root.as_ref().to_path_buf(),
+                opened_with,
+                opened_at: Instant::now(),
+                puts: AtomicU64::default(),
+                compaction_bytes: Arc::default(),
+                gc_reclaimed: Arc::default(),
+                write_lock: Mutex::default(),
+            }),
+        })
+    }
+
+    pub(crate) fn record_put(&self) {
+        self.inner.puts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counter of bytes written by compaction
+    pub(crate) fn compaction_bytes(&self) -> Arc<AtomicU64> {
+        self.inner.compaction_bytes.clone()
+    }
+
+    /// Counter of bytes released by garbage collection
+    pub(crate) fn gc_reclaimed(&self) -> Arc<AtomicU64> {
+        self.inner.gc_reclaimed.clone()
+    }
+
+    /// Returns stats the store was opened with plus work counted since
+    pub(crate) fn stats(&self) -> StoreStats {
+        let inner = &self.inner;
+        let uptime = inner.opened_at.elapsed();
+        StoreStats {
+            total_puts: inner.opened_with.total_puts + inner.puts.load(Ordering::Relaxed),
+            compaction_bytes: inner.opened_with.compaction_bytes
+                + inner.compaction_bytes.load(Ordering::Relaxed),
+            gc_reclaimed: inner.opened_with.gc_reclaimed + inner.gc_reclaimed.load(Ordering::Relaxed),
+            open_count: inner.opened_with.open_count,
+            uptime,
+            total_uptime: inner.opened_with.total_uptime + uptime,
+        }
+    }
+
+    /// Writes current stats to the stats file
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the file cannot be written
+    pub(crate) async fn persist(&self) -> Result<(), Error> {
+        let _guard = self.inner.write_lock.lock().await;
+        self.stats().write(&self.inner.root).await
+    }
+
+    /// Starts job writing stats every `interval`, stops once the store is dropped
+    pub(crate) fn spawn_persist_job(&self, interval: Timer) -> JoinHandle<()> {
+        let inner: Weak<StatsInner> = Arc::downgrade(&self.inner);
+        tokio::spawn(async move {
+            loop {
+                sleep(interval.next_delay()).await;
+                let Some(inner) = inner.upgrade() else {
+                    return;
+                };
+                if let Err(err) = (StatsRecorder { inner }).persist().await {
+                    log::error!("Stats Persist Error {}", err);
+                }
+            }
+        })
+    }
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Returns statistics accumulated over every time the store was opened, see [`StoreStats`]
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("store");
+    ///     let mut store = DataStore::open("big_tech", path.to_owned()).await.unwrap();
+    ///     store.put("apple", "tim cook").await.unwrap();
+    ///     store.persist_stats().await.unwrap();
+    ///     drop(store);
+    ///
+    ///     let store = DataStore::open("big_tech", path).await.unwrap();
+    ///     let stats = store.stats();
+    ///     assert_eq!(stats.total_puts, 1);
+    ///     assert_eq!(stats.open_count, 2);
+    /// }
+    /// ```
+    pub fn stats(&self) -> StoreStats {
+        self.stats.stats()
+    }
+
+    /// Writes current statistics to the stats file of the store
+    ///
+    /// Counts since the last write are lost if the store is dropped without
+    /// calling this, e.g. on shutdown.
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    pub async fn persist_stats(&self) -> Result<(), Error> {
+        self.stats.persist().await
+    }
+}
@@ -1,38 +1,51 @@
+use crate::bucket::LayoutIssue;
+use crate::cache::CacheBalancer;
 use crate::cfg::Config;
 use crate::compactors::{CompactionReason, Compactor};
 use crate::consts::{
-    BUCKETS_DIRECTORY_NAME, HEAD_ENTRY_KEY, HEAD_KEY_SIZE, KB, MAX_KEY_SIZE, MAX_VALUE_SIZE,
-    META_DIRECTORY_NAME, TOMB_STONE_MARKER, VALUE_LOG_DIRECTORY_NAME, VLOG_START_OFFSET,
+    BACKGROUND_JOB_POLL_INTERVAL, BLOCK_SIZE, BUCKETS_DIRECTORY_NAME, FORMAT_VERSION, HEAD_ENTRY_KEY,
+    HEAD_KEY_SIZE, KB, MAX_METADATA_SIZE, META_DIRECTORY_NAME, SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8,
+    TAIL_ENTRY_KEY, TOMB_STONE_MARKER, VALUE_LOG_DIRECTORY_NAME, VLOG_START_OFFSET,
 };
 use crate::db::keyspace::is_valid_keyspace_name;
-use crate::flush::Flusher;
+use crate::db::{BucketUsage, DiskUsage, LiveFiles, ReadOptions, SSTableUsage, StoreInfo, VlogUsage};
+use crate::env::{BackgroundJob, Timer};
+use crate::flush::{FlushEvent, FlushOutcome, FlushSubscription, Flusher};
 use crate::fs::P;
 use crate::gc::garbage_collector::GC;
 use crate::index::Index;
 use crate::key_range::KeyRange;
-use crate::memtable::{Entry, MemTable, UserEntry, K};
+use crate::memtable::{Entry, MemTable, SkipMapValue, UserEntry, Val, K};
 use crate::meta::Meta;
 use crate::range::RangeIterator;
-use crate::sst::Table;
+use crate::sst::{RangeTombstones, Table};
 use crate::types::{
-    Bool, BucketMapHandle, CreatedAt, FlushSignal, GCUpdatedEntries, ImmutableMemTables, Key, KeyRangeHandle,
-    MemtableFlushStream,
+    Bool, BucketMapHandle, CreatedAt, GCUpdatedEntries, ImmutableMemTables, Key, KeyRangeHandle,
+    MemtableFlushStream, Metadata, ValOffset, Value,
 };
 use crate::util;
-use crate::vlog::ValueLog;
+use crate::vlog::{is_wal_offset, ValueKind, ValueLog, ValueLogEntry};
 use chrono::Utc;
+use std::collections::HashMap;
+use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::fs::{self};
 use tokio::sync::{Mutex, RwLock};
+use tokio::time::sleep;
 
 use super::recovery::CreateOrRecoverStoreParams;
+use super::{expiry::ExpiryIndex, stats::StatsRecorder, transaction::LockTable};
 
 /// DataStore struct is the main struct for the library crate
 /// i.e user-facing struct
-pub struct DataStore<'a, Key>
+///
+/// Values are returned as `V`, `Vec<u8>` by default. Any type implementing
+/// `AsRef<[u8]> + From<Vec<u8>>` can be used instead, see [`DataStore::with_value_type`].
+pub struct DataStore<'a, Key, V = Value>
 where
     Key: K,
+    V: Val,
 {
     /// Keyspace name
     pub(crate) keyspace: &'a str,
@@ -68,16 +81,16 @@ where
     pub(crate) gc: GC,
 
     /// Handles range queries
-    pub(crate) range_iterator: Option<RangeIterator<'a>>,
+    pub(crate) range_iterator: Option<RangeIterator>,
 
     /// Stores read only memtables yet to be flushed
     pub(crate) read_only_memtables: ImmutableMemTables<Key>,
 
     /// Sends singnal to subscribers whenever a flush happens
-    pub(crate) flush_signal_tx: async_broadcast::Sender<FlushSignal>,
+    pub(crate) flush_signal_tx: async_broadcast::Sender<FlushEvent>,
 
     /// Flush listeners receiver
-    pub(crate) flush_signal_rx: async_broadcast::Receiver<FlushSignal>,
+    pub(crate) flush_signal_rx: async_broadcast::Receiver<FlushEvent>,
 
     /// Stores valid entries gotten from garbage collection but yet to be synced with
     /// memtable
@@ -92,7 +105,24 @@ where
 
     /// keeps track of memtable going through flush
     pub(crate) flush_stream: MemtableFlushStream,
+
+    /// Mismatches between bucket directories and the bucket manifest found at open
+    pub(crate) layout_issues: Vec<LayoutIssue>,
+
+    /// Keys written with a TTL, ordered by the time they expire
+    pub(crate) expiry: ExpiryIndex,
+
+    /// Keys locked by transactions
+    pub(crate) locks: LockTable,
+
+    /// Cumulative statistics, persisted in the store root
+    pub(crate) stats: StatsRecorder,
+
+    /// Ranges deleted by [`DataStore::delete_range`] and not yet dropped by compaction
+    pub(crate) range_tombstones: RangeTombstones,
     // TODO: pub block_cache: BlockCache
+    /// Type values are returned as
+    pub(crate) value_type: PhantomData<fn() -> V>,
 }
 
 #[derive(Clone, Debug)]
@@ -148,7 +178,54 @@ impl DataStore<'static, Key> {
         Ok(store)
     }
 
-    /// Same as [`Datastore::open`], but does not start background tasks.
+    /// Same as [`DataStore::open`], but uses the provided [`Config`].
+    ///
+    /// Options that are needed while the store is being built, such as the
+    /// shared [`Env`](crate::db::Env), must be set here instead of through
+    /// the `with_*` builder methods.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use velarixdb::db::{Config, DataStore, Env};
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     // Both stores share the same background job limits
+    ///     let env = Env::new(2, 1, 1);
+    ///     let config = Config {
+    ///         env: env.clone(),
+    ///         ..Default::default()
+    ///     };
+    ///     let users = DataStore::open_with_config("users", root.path().join("users"), config.clone()).await;
+    ///     let orders = DataStore::open_with_config("orders", root.path().join("orders"), config).await;
+    ///     assert!(users.is_ok());
+    ///     assert!(orders.is_ok());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the keyspace name is invalid.
+    pub async fn open_with_config(
+        keyspace: &'static str,
+        dir: impl P,
+        config: Config,
+    ) -> Result<DataStore<'static, Key>, crate::err::Error> {
+        assert!(is_valid_keyspace_name(keyspace));
+        let mut config = config;
+        config.false_positive_rate = config.false_positive_rate_for(keyspace);
+        let mut store = Self::create_or_recover(DirPath::build(dir), SizeUnit::Bytes, config).await?;
+        store.keyspace = keyspace;
+        store.start_background_tasks();
+        Ok(store)
+    }
+
+    /// Same as [`DataStore::open`], but does not start background tasks.
     ///
     /// Open a keyspace without background tasks for testing.
     ///
@@ -166,6 +243,37 @@ impl DataStore<'static, Key> {
         Ok(store)
     }
 
+    /// Upgrades store at `dir` to the on-disk format of this build
+    ///
+    /// Same as [`tools::migrate`](crate::tools::migrate) with the format
+    /// version of this build. Stores created before the store-info file existed
+    /// get one, recording default layout options since the ones they were created
+    /// with are not known. Must not run while the store is open.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarix");
+    ///     let store = DataStore::open("big_tech", path.to_owned()).await.unwrap();
+    ///     drop(store);
+    ///     let info = DataStore::migrate(path).await.unwrap();
+    ///     assert_eq!(info.format_version, 2);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::IncompatibleFormat` if the store uses a newer format
+    pub async fn migrate(dir: impl P) -> Result<StoreInfo, crate::err::Error> {
+        crate::tools::migrate(dir, FORMAT_VERSION).await
+    }
+
     /// Starts background tasks that maintain the keyspace.
     ///
     /// Should not be called, unless [`DataStore::open`]
@@ -183,6 +291,86 @@ impl DataStore<'static, Key> {
 
         self.gc
             .start_gc_worker(self.key_range.clone(), self.read_only_memtables.clone());
+
+        if let Some(interval) = self.config.stats_persist_interval {
+            self.stats
+                .spawn_persist_job(Timer::new(interval).with_jitter(self.config.background_jitter));
+        }
+
+        if let Some(interval) = self.config.cache_rebalance_interval {
+            CacheBalancer::new(self.config.block_cache.clone(), self.config.filter_cache.clone())
+                .spawn(Timer::new(interval).with_jitter(self.config.background_jitter));
+        }
+    }
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Returns the store with values returned as `T`
+    ///
+    /// `T` can be any type that can be viewed as bytes and built from the bytes
+    /// read back, so values don't need converting at every call site. Writes
+    /// accept any `AsRef<[u8]>` regardless of the value type.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// struct Ceo(Vec<u8>);
+    ///
+    /// impl AsRef<[u8]> for Ceo {
+    ///     fn as_ref(&self) -> &[u8] {
+    ///         &self.0
+    ///     }
+    /// }
+    ///
+    /// impl From<Vec<u8>> for Ceo {
+    ///     fn from(bytes: Vec<u8>) -> Self {
+    ///         Ceo(bytes)
+    ///     }
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///     let mut store = store.with_value_type::<Ceo>();
+    ///
+    ///     store.put("apple", Ceo(b"tim cook".to_vec())).await.unwrap();
+    ///     let ceo: Ceo = store.get("apple").await.unwrap().unwrap().val;
+    ///     assert_eq!(ceo.0, b"tim cook");
+    /// }
+    /// ```
+    pub fn with_value_type<T: Val>(self) -> DataStore<'static, Key, T> {
+        DataStore {
+            keyspace: self.keyspace,
+            dir: self.dir,
+            active_memtable: self.active_memtable,
+            val_log: self.val_log,
+            buckets: self.buckets,
+            key_range: self.key_range,
+            compactor: self.compactor,
+            meta: self.meta,
+            flusher: self.flusher,
+            config: self.config,
+            gc: self.gc,
+            range_iterator: self.range_iterator,
+            read_only_memtables: self.read_only_memtables,
+            flush_signal_tx: self.flush_signal_tx,
+            flush_signal_rx: self.flush_signal_rx,
+            gc_updated_entries: self.gc_updated_entries,
+            gc_table: self.gc_table,
+            gc_log: self.gc_log,
+            flush_stream: self.flush_stream,
+            layout_issues: self.layout_issues,
+            expiry: self.expiry,
+            locks: self.locks,
+            stats: self.stats,
+            range_tombstones: self.range_tombstones,
+            value_type: PhantomData,
+        }
     }
 
     /// Inserts a new entry into the store
@@ -216,30 +404,313 @@ impl DataStore<'static, Key> {
         &mut self,
         key: impl AsRef<[u8]>,
         val: impl AsRef<[u8]>,
+    ) -> Result<Bool, crate::err::Error> {
+        self.put_at(key, val, Utc::now()).await
+    }
+
+    /// Same as [`DataStore::put`], but stores `created_at` as the timestamp of the entry
+    ///
+    /// Meant for replicated or imported data that must keep its original
+    /// timestamp. Versions of a key are resolved by timestamp, so `created_at`
+    /// must be newer than the stored version of the key, including a deletion,
+    /// and must not be in the future, otherwise later writes would be shadowed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use chrono::{Duration, Utc};
+    /// use velarixdb::db::DataStore;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     let created_at = Utc::now() - Duration::days(1);
+    ///     store.put_with_timestamp("apple", "tim cook", created_at).await.unwrap();
+    ///     assert_eq!(store.get("apple").await.unwrap().unwrap().created_at, created_at);
+    ///
+    ///     // an older timestamp would lose against the stored version
+    ///     let older = created_at - Duration::days(1);
+    ///     assert!(store.put_with_timestamp("apple", "steve jobs", older).await.is_err());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::TimestampRegression` if `created_at` is not newer than the
+    /// stored version, `Error::TimestampInFuture` if it is ahead of the clock, or
+    /// the errors of [`DataStore::put`]
+    pub async fn put_with_timestamp(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        val: impl AsRef<[u8]>,
+        created_at: CreatedAt,
+    ) -> Result<Bool, crate::err::Error> {
+        self.validate_size(key.as_ref(), Some(val.as_ref()))?;
+        if created_at > Utc::now() {
+            return Err(crate::err::Error::TimestampInFuture {
+                timestamp: created_at,
+            });
+        }
+        // timestamps not after the epoch are read as missing
+        let latest = self
+            .latest_created_at(key.as_ref())
+            .await?
+            .unwrap_or_else(util::default_datetime);
+        if created_at <= latest {
+            return Err(crate::err::Error::TimestampRegression {
+                timestamp: created_at,
+                latest,
+            });
+        }
+        self.put_at(key, val, created_at).await
+    }
+
+    /// Returns timestamp of the most recent version of `key`, including a deletion
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    async fn latest_created_at(&self, key: &[u8]) -> Result<Option<CreatedAt>, crate::err::Error> {
+        Ok(self.latest_version(key).await?.map(|version| version.created_at))
+    }
+
+    /// Returns most recent version of `key`, including a deletion
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    pub(crate) async fn latest_version(
+        &self,
+        key: &[u8],
+    ) -> Result<Option<SkipMapValue<ValOffset>>, crate::err::Error> {
+        if let Some(val) = self.active_memtable.get(key) {
+            return Ok(Some(val));
+        }
+        let mut latest: Option<SkipMapValue<ValOffset>> = None;
+        for table in self.read_only_memtables.iter() {
+            if let Some(val) = table.value().get(key) {
+                if latest.as_ref().is_none_or(|l| val.created_at > l.created_at) {
+                    latest = Some(val);
+                }
+            }
+        }
+        if latest.is_some() {
+            return Ok(latest);
+        }
+        for sst in self.key_range.filter_sstables_by_key_range(key).await?.iter() {
+            let index = Index::new(sst.index_file.path.to_owned(), sst.index_file.file.to_owned());
+            if let Some(block_handle) = index.get(key).await? {
+                if let Some((val_offset, created_at, is_tombstone)) = sst
+                    .get(
+                        block_handle,
+                        key,
+                        &self.config.block_cache,
+                        &self.config.io_retry,
+                        self.config.read_pool.as_ref(),
+                        true,
+                    )
+                    .await?
+                {
+                    if latest.as_ref().is_none_or(|l| created_at > l.created_at) {
+                        latest = Some(SkipMapValue::new(val_offset, created_at, is_tombstone));
+                    }
+                }
+            }
+        }
+        Ok(latest)
+    }
+
+    /// Same as [`DataStore::put`], with `created_at` as the timestamp of the entry
+    pub(crate) async fn put_at(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        val: impl AsRef<[u8]>,
+        created_at: CreatedAt,
     ) -> Result<Bool, crate::err::Error> {
         self.validate_size(key.as_ref(), Some(val.as_ref()))?;
+        let is_tombstone = val.as_ref() == TOMB_STONE_MARKER.as_bytes();
+        if !is_tombstone {
+            self.check_quota(key.as_ref().len() + val.as_ref().len(), [(key.as_ref(), false)])
+                .await?;
+        }
 
         if !self.gc_updated_entries.read().await.is_empty() {
             self.sync_gc_update_with_store().await?
         }
 
-        // This ensures sstables in key range whose filter is newly loaded(after crash) are mapped to the sstables
-        self.key_range.update_key_range().await;
-        let is_tombstone = std::str::from_utf8(val.as_ref()).unwrap() == TOMB_STONE_MARKER;
-        let created_at = Utc::now();
+        if self
+            .val_log
+            .wal
+            .as_ref()
+            .is_some_and(|wal| wal.accepts(val.as_ref()))
+        {
+            let entries = [(key.as_ref(), val.as_ref(), created_at, is_tombstone)];
+            let offsets = self.append_to_wal(&entries).await?;
+            let entry = Entry::new(key.as_ref().to_vec(), offsets[0], created_at, is_tombstone);
+            self.insert_to_active_memtable(entry).await;
+            return Ok(true);
+        }
+        self.seal_wal().await?;
         let v_offset = self
             .val_log
             .append(key.as_ref(), val.as_ref(), created_at, is_tombstone)
             .await?;
         let entry = Entry::new(key.as_ref().to_vec(), v_offset, created_at, is_tombstone);
+        self.insert_to_memtable(entry).await?;
+        Ok(true)
+    }
 
-        if self.active_memtable.is_full(HEAD_KEY_SIZE) {
-            self.migrate_memtable_to_read_only();
-        }
+    /// Inserts entry already written to value log into active memtable and GC table
+    ///
+    /// Active memtable is moved to read-only memtables first if it is full
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if write-ahead log entries could not be moved to the value log
+    pub(crate) async fn insert_to_memtable(
+        &mut self,
+        entry: Entry<Key, usize>,
+    ) -> Result<(), crate::err::Error> {
+        self.rotate_if_full().await?;
+        self.insert_to_active_memtable(entry).await;
+        Ok(())
+    }
+
+    /// Inserts entry into active memtable and GC table, without checking if the memtable is full
+    ///
+    /// Garbage collection moves of the key not synced yet hold an older version and are dropped
+    async fn insert_to_active_memtable(&mut self, entry: Entry<Key, usize>) {
         self.active_memtable.insert(&entry);
+        let mut gc_table = self.gc_table.write().await;
+        gc_table.insert(&entry);
+        self.gc.config.writes.record(&entry.key);
+        self.gc_updated_entries.write().await.remove(&entry.key);
+        self.expiry.forget(&entry.key, entry.created_at).await;
+        if !entry.is_tombstone {
+            self.stats.record_put();
+        }
+    }
+
+    /// Moves active memtable to read-only memtables if it is full
+    ///
+    /// Write-ahead log entries are moved to the value log first, so sstables
+    /// never point into the write-ahead log
+    async fn rotate_if_full(&mut self) -> Result<(), crate::err::Error> {
+        if self.active_memtable.is_full(HEAD_KEY_SIZE) || self.is_unflushed_vlog_full() {
+            self.seal_wal().await?;
+            self.migrate_memtable_to_read_only().await;
+        }
+        Ok(())
+    }
+
+    /// Appends `entries` to the write-ahead log with a single synced write
+    ///
+    /// Active memtable is rotated first if it is full, since rotation clears the
+    /// log. Entries must be inserted with [`DataStore::insert_to_active_memtable`].
+    /// Returns tagged offset of each entry, in order
+    async fn append_to_wal<T: AsRef<[u8]>>(
+        &mut self,
+        entries: &[(T, T, CreatedAt, bool)],
+    ) -> Result<Vec<usize>, crate::err::Error> {
+        self.rotate_if_full().await?;
+        self.val_log.append_batch_to_wal(entries).await
+    }
+
+    /// Moves write-ahead log entries still in the active memtable to the value log and clears the log
+    ///
+    /// Runs before anything else is appended to the value log, so the log only
+    /// holds writes newer than every value log entry and recovery can replay it last
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or an entry is missing from the log
+    pub(crate) async fn seal_wal(&mut self) -> Result<(), crate::err::Error> {
+        let wal = match &self.val_log.wal {
+            Some(wal) if !wal.is_empty() => wal,
+            _ => return Ok(()),
+        };
+        let mut logged: HashMap<usize, ValueLogEntry> = wal.entries().await?.into_iter().collect();
+        let mut pending = Vec::new();
+        for e in self.active_memtable.entries.iter() {
+            let offset = e.value().val_offset;
+            if !is_wal_offset(offset) {
+                continue;
+            }
+            let logged = logged
+                .remove(&offset)
+                .ok_or(crate::err::Error::WalEntryNotFound { offset })?;
+            pending.push((
+                offset,
+                e.key().to_owned(),
+                logged.value,
+                e.value().created_at,
+                e.value().is_tombstone,
+            ));
+        }
+        // keep write order, recovery replays the value log in order
+        pending.sort_by_key(|(offset, ..)| *offset);
+        let batch: Vec<_> = pending
+            .into_iter()
+            .map(|(_, key, value, created_at, is_tombstone)| (key, value, created_at, is_tombstone))
+            .collect();
+        let offsets = self.val_log.append_batch(&batch).await?;
+        self.val_log.sync_to_disk().await?;
         let gc_table = Arc::clone(&self.gc_table);
-        tokio::spawn(async move { gc_table.write().await.insert(&entry) });
-        Ok(true)
+        let mut gc_table = gc_table.write().await;
+        for ((key, _, created_at, is_tombstone), v_offset) in batch.into_iter().zip(offsets) {
+            let entry = Entry::new(key, v_offset, created_at, is_tombstone);
+            self.active_memtable.relocate(&entry);
+            gc_table.relocate(&entry);
+        }
+        drop(gc_table);
+        if let Some(wal) = self.val_log.wal.as_mut() {
+            wal.clear().await?;
+        }
+        Ok(())
+    }
+
+    /// Moves entries left in the write-ahead log by a crash to the value log and active memtable
+    ///
+    /// The log only holds writes newer than every value log entry, so its
+    /// entries are applied after the value log was replayed
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub(crate) async fn recover_wal(&mut self) -> Result<(), crate::err::Error> {
+        let logged = match &self.val_log.wal {
+            Some(wal) if !wal.is_empty() => wal.entries().await?,
+            _ => return Ok(()),
+        };
+        let batch: Vec<_> = logged
+            .into_iter()
+            .map(|(_, e)| (e.key, e.value, e.created_at, e.is_tombstone))
+            .collect();
+        let offsets = self.val_log.append_batch(&batch).await?;
+        self.val_log.sync_to_disk().await?;
+        if let Some(wal) = self.val_log.wal.as_mut() {
+            wal.clear().await?;
+        }
+        for ((key, _, created_at, is_tombstone), v_offset) in batch.into_iter().zip(offsets) {
+            self.insert_to_memtable(Entry::new(key, v_offset, created_at, is_tombstone))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Checks if the value log grew past `max_unflushed_vlog_size` since the last rotation
+    fn is_unflushed_vlog_full(&self) -> bool {
+        match self.config.max_unflushed_vlog_size {
+            // an empty memtable has no head offset to move to
+            Some(limit) if !self.active_memtable.entries.is_empty() => {
+                self.val_log.size.saturating_sub(self.val_log.head_offset) >= limit
+            }
+            _ => false,
+        }
     }
 
     /// Moves active memtable to read-only memtables
@@ -247,7 +718,7 @@ impl DataStore<'static, Key> {
     /// Marks the active memtable as read only,
     /// updates store metadata and moves the memtable
     /// to read-only memtables
-    pub(crate) fn migrate_memtable_to_read_only(&mut self) {
+    pub(crate) async fn migrate_memtable_to_read_only(&mut self) {
         let head_offset = self.active_memtable.get_most_recent_offset();
 
         self.val_log.set_head(head_offset);
@@ -265,7 +736,7 @@ impl DataStore<'static, Key> {
         self.update_meta_background();
 
         if self.read_only_memtables.is_empty() {
-            self.flush_stream.clear();
+            self.flush_stream.lock().unwrap().clear();
         }
         self.read_only_memtables.insert(
             MemTable::generate_table_id(),
@@ -275,7 +746,7 @@ impl DataStore<'static, Key> {
         if self.read_only_memtables.len() >= self.config.max_buffer_write_number {
             self.flush_read_only_memtables();
         }
-        self.reset_memtables();
+        self.reset_memtables().await;
     }
 
     /// Synchronize GC table with active memtable
@@ -289,7 +760,8 @@ impl DataStore<'static, Key> {
     /// Returns error, if an IO error occured.
     #[doc(hidden)]
     pub(crate) async fn sync_gc_update_with_store(&mut self) -> Result<(), crate::err::Error> {
-        let gc_entries_reader = self.gc_updated_entries.read().await;
+        // garbage collection can't add entries while they are moved over
+        let gc_entries_reader = self.gc_updated_entries.write().await;
         for e in gc_entries_reader.iter() {
             self.active_memtable.insert(&Entry::new(
                 e.key().to_vec(),
@@ -299,12 +771,15 @@ impl DataStore<'static, Key> {
             ));
         }
         gc_entries_reader.clear();
+        drop(gc_entries_reader);
         let (updated_head, updated_tail) = self.gc.free_unused_space().await?;
         self.meta.set_head(updated_head);
         self.meta.set_tail(updated_tail);
         self.meta.update_last_modified();
         self.val_log.set_head(updated_head);
         self.val_log.set_tail(updated_tail);
+        // garbage collection appended valid entries
+        self.val_log.refresh_size().await;
         Ok(())
     }
 
@@ -319,6 +794,94 @@ impl DataStore<'static, Key> {
         });
     }
 
+    /// Same as [`DataStore::put`], for string keys and values
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     store.put_str("apple", "tim cook").await.unwrap();
+    ///     assert_eq!(store.get_str("apple").await.unwrap().as_deref(), Some("tim cook"));
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or key/value size is invalid.
+    pub async fn put_str(&mut self, key: &str, val: &str) -> Result<Bool, crate::err::Error> {
+        self.put(key, val).await
+    }
+
+    /// Same as [`DataStore::get`], but returns the value as `String`
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the value is not valid UTF-8.
+    pub async fn get_str(&self, key: &str) -> Result<Option<String>, crate::err::Error> {
+        match self.get_entry(key).await? {
+            Some(entry) => String::from_utf8(entry.val)
+                .map(Some)
+                .map_err(crate::err::Error::InvalidUtf8),
+            None => Ok(None),
+        }
+    }
+
+    /// Same as [`DataStore::put`], but stores application `metadata` alongside the value
+    ///
+    /// Metadata such as a version tag or content type is kept in the value log
+    /// next to the value and returned by [`DataStore::get_with_metadata`]. It must
+    /// not exceed `255` bytes. A later write of the key without metadata replaces it.
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured, key, value or metadata size is invalid
+    /// or the write exceeds the keyspace quota.
+    pub async fn put_with_metadata(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        val: impl AsRef<[u8]>,
+        metadata: impl AsRef<[u8]>,
+    ) -> Result<Bool, crate::err::Error> {
+        self.validate_size(key.as_ref(), Some(val.as_ref()))?;
+        if metadata.as_ref().len() > MAX_METADATA_SIZE {
+            return Err(crate::err::Error::MetadataTooLarge {
+                size: metadata.as_ref().len(),
+                max: MAX_METADATA_SIZE,
+            });
+        }
+        if val.as_ref() == TOMB_STONE_MARKER.as_bytes() {
+            return self.put(key, val).await;
+        }
+        self.check_quota(
+            key.as_ref().len() + val.as_ref().len() + metadata.as_ref().len(),
+            [(key.as_ref(), false)],
+        )
+        .await?;
+
+        if !self.gc_updated_entries.read().await.is_empty() {
+            self.sync_gc_update_with_store().await?
+        }
+
+        // write-ahead log entries carry no metadata
+        self.seal_wal().await?;
+        let created_at = Utc::now();
+        let v_offset = self
+            .val_log
+            .append_with_metadata(key.as_ref(), val.as_ref(), Some(metadata.as_ref()), created_at)
+            .await?;
+        let entry = Entry::new(key.as_ref().to_vec(), v_offset, created_at, false);
+        self.insert_to_memtable(entry).await?;
+        Ok(true)
+    }
+
     /// Removes an entry from the store
     ///
     ///
@@ -350,17 +913,142 @@ impl DataStore<'static, Key> {
     /// ```
     pub async fn delete<T: AsRef<[u8]>>(&mut self, key: T) -> Result<bool, crate::err::Error> {
         self.validate_size(key.as_ref(), None::<T>)?;
-        self.get(key.as_ref()).await?;
+        self.get_entry(key.as_ref()).await?;
         let value = TOMB_STONE_MARKER;
         self.put(key.as_ref(), value).await
     }
 
+    /// Removes several entries from the store
+    ///
+    /// Tombstones of all live keys are written to the value log in a single
+    /// append, then inserted into the memtable in one pass.
+    ///
+    /// Returns result of each key, in order: `Ok(true)` if the key was
+    /// deleted, `Ok(false)` if it was not found, or the error that made the key invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use velarixdb::db::DataStore;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     store.put("apple", "tim cook").await.unwrap(); // handle error
+    ///     store.put("google", "sundar pichai").await.unwrap();
+    ///
+    ///     let results = store.delete_many(["apple", "google", "nvidia"]).await.unwrap();
+    ///     assert!(results[0].as_ref().is_ok_and(|deleted| *deleted));
+    ///     assert!(results[1].as_ref().is_ok_and(|deleted| *deleted));
+    ///     assert!(results[2].as_ref().is_ok_and(|deleted| !*deleted));
+    ///     assert!(store.get("apple").await.unwrap().is_none());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured, in which case no key was deleted.
+    pub async fn delete_many<T: AsRef<[u8]>>(
+        &mut self,
+        keys: impl IntoIterator<Item = T>,
+    ) -> Result<Vec<Result<bool, crate::err::Error>>, crate::err::Error> {
+        let mut results = Vec::new();
+        let mut tombstones = Vec::new();
+        for key in keys {
+            let res = match self.validate_size(key.as_ref(), None::<T>) {
+                Ok(()) => Ok(self.get_entry(key.as_ref()).await?.is_some()),
+                Err(err) => Err(err),
+            };
+            if let Ok(true) = res {
+                tombstones.push((
+                    key.as_ref().to_vec(),
+                    TOMB_STONE_MARKER.as_bytes().to_vec(),
+                    Utc::now(),
+                    true,
+                ));
+            }
+            results.push(res);
+        }
+        if tombstones.is_empty() {
+            return Ok(results);
+        }
+        self.append_entries(tombstones).await?;
+        Ok(results)
+    }
+
+    /// Writes `writes` to the value log in a single append, `None` deletes the key
+    ///
+    /// Entries are inserted into the memtable once all of them are written, with
+    /// the same timestamp. Size and quota of the writes are not checked.
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured, in which case no write was applied.
+    pub(crate) async fn write_batch(
+        &mut self,
+        writes: impl IntoIterator<Item = (Key, Option<Value>)>,
+    ) -> Result<(), crate::err::Error> {
+        let created_at = Utc::now();
+        let entries: Vec<_> = writes
+            .into_iter()
+            .map(|(key, val)| {
+                let val = val.unwrap_or_else(|| TOMB_STONE_MARKER.as_bytes().to_vec());
+                let is_tombstone = val == TOMB_STONE_MARKER.as_bytes();
+                (key, val, created_at, is_tombstone)
+            })
+            .collect();
+        if entries.is_empty() {
+            return Ok(());
+        }
+        self.append_entries(entries).await
+    }
+
+    /// Appends `entries` to the write-ahead log or value log in one write, then inserts them into the memtable
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured, in which case no entry was inserted.
+    async fn append_entries(
+        &mut self,
+        entries: Vec<(Key, Value, CreatedAt, bool)>,
+    ) -> Result<(), crate::err::Error> {
+        if !self.gc_updated_entries.read().await.is_empty() {
+            self.sync_gc_update_with_store().await?
+        }
+        if self
+            .val_log
+            .wal
+            .as_ref()
+            .is_some_and(|wal| entries.iter().all(|(_, val, _, _)| wal.accepts(val)))
+        {
+            let offsets = self.append_to_wal(&entries).await?;
+            for ((key, _, created_at, is_tombstone), v_offset) in entries.into_iter().zip(offsets) {
+                self.insert_to_active_memtable(Entry::new(key, v_offset, created_at, is_tombstone))
+                    .await;
+            }
+            return Ok(());
+        }
+        self.seal_wal().await?;
+        let offsets = self.val_log.append_batch(&entries).await?;
+        for ((key, _, created_at, is_tombstone), v_offset) in entries.into_iter().zip(offsets) {
+            self.insert_to_memtable(Entry::new(key, v_offset, created_at, is_tombstone))
+                .await?;
+        }
+        Ok(())
+    }
+
     /// Flushes read-only memtable to disk using a background tokio task
+    ///
+    /// Memtables whose flush failed are flushed again
     pub(crate) fn flush_read_only_memtables(&mut self) {
         for table in self.read_only_memtables.iter() {
             let key = table.key().to_owned();
             let value = table.value().to_owned();
-            if self.flush_stream.contains(&key) {
+            if self.flush_stream.lock().unwrap().get(&key) == Some(&false) {
                 continue;
             }
             let mut flusher = self.flusher.clone();
@@ -370,7 +1058,7 @@ impl DataStore<'static, Key> {
             // This is because tokio::spawn creates a new asynchronous task that is managed by the Tokio runtime.
             // The spawned task is executed concurrently and its lifecycle is not tied to the function that spawned it.
             // TODO: See if we can introduce semaphors to prevent overloading the system
-            self.flush_stream.insert(key.to_vec());
+            self.flush_stream.lock().unwrap().insert(key.to_vec(), false);
             tokio::spawn(async move {
                 flusher.flush_handler(key, value, tx);
             });
@@ -378,17 +1066,16 @@ impl DataStore<'static, Key> {
     }
 
     /// Resets both active memtable and GC table to new
-    pub(crate) fn reset_memtables(&mut self) {
+    ///
+    /// GC table is cleared in place, garbage collection shares it with the store
+    pub(crate) async fn reset_memtables(&mut self) {
         let capacity = self.active_memtable.capacity();
         let size_unit = self.active_memtable.size_unit();
         let false_positive_rate = self.active_memtable.false_positive_rate();
         self.active_memtable =
             MemTable::with_specified_capacity_and_rate(size_unit, capacity, false_positive_rate);
-        self.gc_table = Arc::new(RwLock::new(MemTable::with_specified_capacity_and_rate(
-            size_unit,
-            capacity,
-            false_positive_rate,
-        )));
+        *self.gc_table.write().await =
+            MemTable::with_specified_capacity_and_rate(size_unit, capacity, false_positive_rate);
     }
 
     /// Reteives an entry from the [`DataStore`]
@@ -439,9 +1126,93 @@ impl DataStore<'static, Key> {
     ///  assert!(entry7.is_none())
     /// }
     /// ```
-    pub async fn get<T: AsRef<[u8]>>(&self, key: T) -> Result<Option<UserEntry>, crate::err::Error> {
+    pub async fn get<T: AsRef<[u8]>>(&self, key: T) -> Result<Option<UserEntry<V>>, crate::err::Error> {
+        Ok(self.get_entry(key).await?.map(UserEntry::into_val))
+    }
+
+    /// Same as [`DataStore::get`], but also returns application metadata stored with the value
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use velarixdb::db::DataStore;
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     store.put_with_metadata("apple", "{\"ceo\":\"tim cook\"}", "application/json").await.unwrap();
+    ///     let (entry, metadata) = store.get_with_metadata("apple").await.unwrap().unwrap();
+    ///     assert_eq!(entry.val, b"{\"ceo\":\"tim cook\"}");
+    ///     assert_eq!(metadata.unwrap(), b"application/json");
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    pub async fn get_with_metadata<T: AsRef<[u8]>>(
+        &self,
+        key: T,
+    ) -> Result<Option<(UserEntry<V>, Option<Metadata>)>, crate::err::Error> {
+        Ok(self
+            .get_entry_with_metadata(key)
+            .await?
+            .map(|(entry, metadata)| (entry.into_val(), metadata)))
+    }
+
+    /// Same as [`DataStore::get`], but returns value as read from disk
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    pub(crate) async fn get_entry<T: AsRef<[u8]>>(
+        &self,
+        key: T,
+    ) -> Result<Option<UserEntry>, crate::err::Error> {
+        Ok(self.get_entry_with_metadata(key).await?.map(|(entry, _)| entry))
+    }
+
+    /// Same as [`DataStore::get_entry`], but also returns metadata stored with the value
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    pub(crate) async fn get_entry_with_metadata<T: AsRef<[u8]>>(
+        &self,
+        key: T,
+    ) -> Result<Option<(UserEntry, Option<Metadata>)>, crate::err::Error> {
+        self.get_entry_with_options(key, &ReadOptions::default()).await
+    }
+
+    /// Same as [`DataStore::get_entry_with_metadata`], but reads according to `opts`
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    pub(crate) async fn get_entry_with_options<T: AsRef<[u8]>>(
+        &self,
+        key: T,
+        opts: &ReadOptions,
+    ) -> Result<Option<(UserEntry, Option<Metadata>)>, crate::err::Error> {
         self.validate_size(key.as_ref(), None::<T>)?;
+        let found = self.find_entry_with_options(key.as_ref(), opts).await?;
+        // versions deleted by a range stay where they are until compaction merges them
+        Ok(found.filter(|(entry, _)| !self.range_tombstones.covers(key.as_ref(), entry.created_at)))
+    }
 
+    /// Returns most recent version of `key`, whether a range tombstone covers it or not
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    async fn find_entry_with_options(
+        &self,
+        key: &[u8],
+        opts: &ReadOptions,
+    ) -> Result<Option<(UserEntry, Option<Metadata>)>, crate::err::Error> {
         if let Some(val) = self.search_gc_entries(key.as_ref()).await? {
             return Ok(Some(val));
         }
@@ -453,7 +1224,8 @@ impl DataStore<'static, Key> {
             if val.is_tombstone {
                 return Ok(None);
             }
-            self.get_value_from_vlog(val.val_offset, val.created_at).await
+            self.get_value_from_vlog(key.as_ref(), val.val_offset, val.created_at)
+                .await
         } else {
             let mut is_deleted = false;
             for table in self.read_only_memtables.iter() {
@@ -465,17 +1237,29 @@ impl DataStore<'static, Key> {
                     }
                 }
             }
+            if !self.found_in_table(insert_time, lowest_insert_time) {
+                // flushed memtables, on equal times the later flush holds the current offset
+                for table in self.flusher.retained.read().unwrap().values() {
+                    if let Some(val) = table.get(key.as_ref()) {
+                        if val.created_at >= insert_time {
+                            offset = val.val_offset;
+                            insert_time = val.created_at;
+                            is_deleted = val.is_tombstone
+                        }
+                    }
+                }
+            }
             if self.found_in_table(insert_time, lowest_insert_time) {
                 if is_deleted {
                     return Ok(None);
                 }
-                self.get_value_from_vlog(offset, insert_time).await
+                self.get_value_from_vlog(key.as_ref(), offset, insert_time).await
             } else {
                 let ssts = &self.key_range.filter_sstables_by_key_range(key.as_ref()).await?;
                 if ssts.is_empty() {
                     return Ok(None);
                 }
-                self.search_key_in_sstables(key, ssts.to_vec()).await
+                self.search_key_in_sstables(key, ssts.to_vec(), opts).await
             }
         }
     }
@@ -486,7 +1270,10 @@ impl DataStore<'static, Key> {
     /// # Errors
     ///
     /// Returns error, if IO error occurs
-    async fn search_gc_entries(&self, key: impl AsRef<[u8]>) -> Result<Option<UserEntry>, crate::err::Error> {
+    async fn search_gc_entries(
+        &self,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Option<(UserEntry, Option<Metadata>)>, crate::err::Error> {
         let gc_entries = self.gc_updated_entries.read().await;
         if !gc_entries.is_empty() {
             if let Some(e) = gc_entries.get(key.as_ref()) {
@@ -494,13 +1281,18 @@ impl DataStore<'static, Key> {
                 if val.is_tombstone {
                     return Ok(None);
                 }
-                return self.get_value_from_vlog(val.val_offset, val.created_at).await;
+                return self
+                    .get_value_from_vlog(key.as_ref(), val.val_offset, val.created_at)
+                    .await;
             }
         }
         Ok(None)
     }
 
-    /// Removes an entry from the store
+    /// Replaces the value of an existing entry with the value `f` computes from the current one
+    ///
+    /// `f` receives the current value. A missing or deleted key is left as it
+    /// is, `f` is not called and `false` is returned.
     ///
     /// # Examples
     ///
@@ -514,32 +1306,157 @@ impl DataStore<'static, Key> {
     ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
     ///
     ///     store.put("apple", "tim cook").await.unwrap(); // handle error
+    ///     store.put("visits", "1").await.unwrap();
     ///
     ///     // Update entry
-    ///     let success = store.update("apple", "elon musk").await;
+    ///     let success = store.update_with("apple", |_| "elon musk").await;
     ///     assert!(success.is_ok());
     ///
-    ///     // Entry should now be updated
+    ///     // Derive new value from the current one
+    ///     store
+    ///         .update_with("visits", |old| {
+    ///             let visits: u32 = std::str::from_utf8(old).unwrap().parse().unwrap();
+    ///             (visits + 1).to_string()
+    ///         })
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     // Entries should now be updated
     ///     let entry = store.get("apple").await.unwrap(); // handle error
     ///     assert!(entry.is_some());
-    ///     assert_eq!(std::str::from_utf8(&entry.unwrap().val).unwrap(), "elon musk")
+    ///     assert_eq!(std::str::from_utf8(&entry.unwrap().val).unwrap(), "elon musk");
+    ///     let entry = store.get("visits").await.unwrap();
+    ///     assert_eq!(std::str::from_utf8(&entry.unwrap().val).unwrap(), "2");
     /// }
     /// ```
-    pub async fn update(
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the computed value is invalid.
+    pub async fn update_with<U: AsRef<[u8]>>(
         &mut self,
         key: impl AsRef<[u8]>,
-        value: impl AsRef<[u8]>,
+        f: impl FnOnce(&[u8]) -> U,
     ) -> Result<bool, crate::err::Error> {
+        self.validate_size(key.as_ref(), None::<U>)?;
+        let old = match self.get_entry(key.as_ref()).await? {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+        let value = f(&old.val);
         self.validate_size(key.as_ref(), Some(value.as_ref()))?;
-        self.get(key.as_ref()).await?;
         self.put(key, value).await
     }
 
+    /// Replaces the value of an existing entry with `value`
+    ///
+    /// Kept so existing callers passing the new value keep compiling, the
+    /// closure form is [`DataStore::update_with`]. Same as calling it with a
+    /// closure ignoring the current value.
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the value is invalid.
+    #[deprecated(
+        since = "0.0.17",
+        note = "kept for callers passing the new value, use `DataStore::update_with` to derive it from the current value"
+    )]
+    pub async fn update(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: impl AsRef<[u8]>,
+    ) -> Result<bool, crate::err::Error> {
+        self.update_with(key, |_| value).await
+    }
+
+    /// Inserts a new entry, unless `key` is already in the store
+    ///
+    /// Unlike [`DataStore::put`], a live value is never overwritten. Deleted
+    /// keys count as missing and can be inserted again.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use velarixdb::db::{DataStore, Error};
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     store.insert("apple", "tim cook").await.unwrap();
+    ///     let res = store.insert("apple", "steve jobs").await;
+    ///     assert!(matches!(res, Err(Error::KeyAlreadyExists)));
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyAlreadyExists`](crate::db::Error::KeyAlreadyExists) if `key` is live,
+    /// or error if an IO error occured.
+    pub async fn insert(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        val: impl AsRef<[u8]>,
+    ) -> Result<Bool, crate::err::Error> {
+        self.validate_size(key.as_ref(), Some(val.as_ref()))?;
+        if self.get_entry(key.as_ref()).await?.is_some() {
+            return Err(crate::err::Error::KeyAlreadyExists);
+        }
+        self.put(key, val).await
+    }
+
+    /// Returns the value of `key`, or inserts the value computed by `f` if `key` is not in the store
+    ///
+    /// `f` is only called when the key is missing or deleted, so a default that
+    /// is costly to build is not computed for keys that already have a value.
+    /// The returned entry is the stored one or the one just inserted.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use velarixdb::db::DataStore;
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     let entry = store.get_or_insert_with("apple", || "tim cook").await.unwrap();
+    ///     assert_eq!(entry.val, b"tim cook");
+    ///
+    ///     // Existing value is returned, closure is not called
+    ///     let entry = store.get_or_insert_with("apple", || "steve jobs").await.unwrap();
+    ///     assert_eq!(entry.val, b"tim cook");
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the computed value is invalid.
+    pub async fn get_or_insert_with<U: AsRef<[u8]>>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        f: impl FnOnce() -> U,
+    ) -> Result<UserEntry<V>, crate::err::Error> {
+        if let Some(entry) = self.get(key.as_ref()).await? {
+            return Ok(entry);
+        }
+        let value = f();
+        self.put(key.as_ref(), value.as_ref()).await?;
+        let created_at = self
+            .active_memtable
+            .get(key.as_ref())
+            .map(|e| e.created_at)
+            .unwrap_or_else(Utc::now);
+        Ok(UserEntry::new(value.as_ref().to_vec(), created_at).into_val())
+    }
+
     /// Validate key and value sizes.
     ///
-    /// Key size can be up to 65536 bytes in size, and value size can be
-    /// up to 2^32 bytes, key cannot be zero length and value(if provded)
-    /// cannot be zero length
+    /// Key size can be up to `max_key_size` bytes in size, and value size can be
+    /// up to `max_value_size` bytes (see [`Config`]), key cannot be zero length
+    /// and value(if provded) cannot be zero length
     ///
     /// # Errors
     ///
@@ -553,16 +1470,24 @@ impl DataStore<'static, Key> {
             return Err(crate::err::Error::KeySizeNone);
         }
 
-        if key.as_ref().len() > MAX_KEY_SIZE {
-            return Err(crate::err::Error::KeyMaxSizeExceeded);
+        if key.as_ref().len() > self.config.max_key_size {
+            return Err(crate::err::Error::KeyTooLarge {
+                size: key.as_ref().len(),
+                max: self.config.max_key_size,
+            });
         }
 
         if val.is_some() && val.as_ref().unwrap().as_ref().is_empty() {
             return Err(crate::err::Error::ValueSizeNone);
         }
 
-        if val.is_some() && val.as_ref().unwrap().as_ref().len() > MAX_VALUE_SIZE {
-            return Err(crate::err::Error::ValMaxSizeExceeded);
+        if let Some(val) = val {
+            if val.as_ref().len() > self.config.max_value_size {
+                return Err(crate::err::Error::ValueTooLarge {
+                    size: val.as_ref().len(),
+                    max: self.config.max_value_size,
+                });
+            }
         }
         Ok(())
     }
@@ -580,7 +1505,8 @@ impl DataStore<'static, Key> {
         &self,
         key: impl AsRef<[u8]>,
         ssts: Vec<Table>,
-    ) -> Result<Option<UserEntry>, crate::err::Error> {
+        opts: &ReadOptions,
+    ) -> Result<Option<(UserEntry, Option<Metadata>)>, crate::err::Error> {
         let mut insert_time = util::default_datetime();
         let lowest_insert_date = util::default_datetime();
         let mut offset = VLOG_START_OFFSET;
@@ -588,12 +1514,19 @@ impl DataStore<'static, Key> {
         for sst in ssts.iter() {
             let index = Index::new(sst.index_file.path.to_owned(), sst.index_file.file.to_owned());
             let block_handle = index.get(key.as_ref()).await?;
-            if block_handle.is_some() {
-                let sst_res = sst.get(block_handle.unwrap(), &key).await?;
-
-                if sst_res.as_ref().is_some() {
-                    let (val_offset, created_at, is_tombstone) = sst_res.unwrap();
-
+            if let Some(block_handle) = block_handle {
+                let sst_res = sst
+                    .get(
+                        block_handle,
+                        &key,
+                        &self.config.block_cache,
+                        &self.config.io_retry,
+                        self.config.read_pool.as_ref(),
+                        opts.admits_blocks(),
+                    )
+                    .await?;
+
+                if let Some((val_offset, created_at, is_tombstone)) = sst_res {
                     if created_at > insert_time {
                         offset = val_offset;
                         insert_time = created_at;
@@ -606,7 +1539,7 @@ impl DataStore<'static, Key> {
             if is_deleted {
                 return Ok(None);
             }
-            return self.get_value_from_vlog(offset, insert_time).await;
+            return self.get_value_from_vlog(key.as_ref(), offset, insert_time).await;
         }
         Ok(None)
     }
@@ -619,7 +1552,7 @@ impl DataStore<'static, Key> {
 
     /// Retrieves value from Value Log
     ///
-    /// Returns value from value log using the provided offset
+    /// Returns value and metadata from value log using the provided offset
     ///
     ///
     /// # Errors
@@ -627,17 +1560,81 @@ impl DataStore<'static, Key> {
     /// Returns error, if an IO error occurs or key was not found
     pub(crate) async fn get_value_from_vlog(
         &self,
+        key: &[u8],
         offset: usize,
         created_at: CreatedAt,
-    ) -> Result<Option<UserEntry>, crate::err::Error> {
-        let res = self.val_log.get(offset).await?;
-        if let Some((value, is_tombstone)) = res {
-            if is_tombstone {
-                return Ok(None);
+    ) -> Result<Option<(UserEntry, Option<Metadata>)>, crate::err::Error> {
+        let entry = match self.val_log.get_entry(offset).await? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        if self.config.verify_reads {
+            entry.verify(key, offset)?;
+        }
+        if entry.is_tombstone {
+            return Ok(None);
+        }
+        Ok(Some((UserEntry::new(entry.value, created_at), entry.metadata)))
+    }
+
+    /// Returns entry starting at `offset` in the value log and whether an SSTable still points to it
+    ///
+    /// Meant for debugging and garbage collection tooling. An entry is referenced if
+    /// any SSTable that may hold its key maps the key to `offset`, including SSTables
+    /// with a version overwritten since that compaction has not merged yet. Memtables
+    /// are not checked. Blob entries of deduplicated values are only referenced from the value
+    /// log, so they are never reported as referenced. `offset` should be the start
+    /// of an entry, as found in an SSTable or a previous entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use velarixdb::db::DataStore;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     // nothing was written past the start of the value log yet
+    ///     assert!(store.entry_at(1 << 20).await.unwrap().is_none());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occurs or the entry at `offset` is corrupted
+    pub async fn entry_at(&self, offset: usize) -> Result<Option<(ValueLogEntry, bool)>, crate::err::Error> {
+        let entry = match self.val_log.entry_at(offset).await? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let mut referenced = false;
+        if entry.kind != ValueKind::Blob {
+            let ssts = self.key_range.filter_sstables_by_key_range(&entry.key).await?;
+            for sst in ssts.iter() {
+                let index = Index::new(sst.index_file.path.to_owned(), sst.index_file.file.to_owned());
+                if let Some(block_handle) = index.get(&entry.key).await? {
+                    let sst_res = sst
+                        .get(
+                            block_handle,
+                            &entry.key,
+                            &self.config.block_cache,
+                            &self.config.io_retry,
+                            self.config.read_pool.as_ref(),
+                            true,
+                        )
+                        .await?;
+                    if matches!(sst_res, Some((val_offset, _, _)) if val_offset == offset) {
+                        referenced = true;
+                        break;
+                    }
+                }
             }
-            return Ok(Some(UserEntry::new(value, created_at)));
         }
-        Ok(None)
+        Ok(Some((entry, referenced)))
     }
 
     /// Flushes all memtable (active and read-only) to disk
@@ -651,6 +1648,7 @@ impl DataStore<'static, Key> {
     pub(crate) async fn force_flush(&mut self) -> Result<(), crate::err::Error> {
         use crossbeam_skiplist::SkipMap;
 
+        self.seal_wal().await?;
         self.active_memtable.mark_readonly();
 
         self.read_only_memtables.insert(
@@ -662,14 +1660,25 @@ impl DataStore<'static, Key> {
             Arc::clone(&self.read_only_memtables),
             Arc::clone(&self.buckets),
             Arc::clone(&self.key_range),
-        );
+            self.config.env.clone(),
+        )
+        .with_filter_memory_budget(self.config.filter_memory_budget)
+        .with_cpu_offload(self.config.offload_cpu_work)
+        .with_retained(self.flusher.retained.clone(), self.flusher.retained_limit);
         for table in immutable_tables.iter() {
-            if self.flush_stream.contains(table.key()) {
+            if self.flush_stream.lock().unwrap().get(table.key()) == Some(&false) {
                 continue;
             }
-            self.flush_stream.insert(table.key().to_vec());
-            flusher.flush(table.value().to_owned()).await?;
+            self.flush_stream
+                .lock()
+                .unwrap()
+                .insert(table.key().to_vec(), false);
+            if let FlushOutcome::Flushed { .. } = flusher.flush(table.value().to_owned()).await? {
+                flusher.retain(table.key().to_vec(), table.value().to_owned());
+            }
         }
+        // entries are shared with the flushed memtable, which may be retained
+        self.active_memtable.entries = Arc::new(SkipMap::new());
         self.active_memtable.clear();
         self.read_only_memtables = Arc::new(SkipMap::new());
         Ok(())
@@ -685,17 +1694,45 @@ impl DataStore<'static, Key> {
         size_unit: SizeUnit,
         config: Config,
     ) -> Result<DataStore<'static, Key>, crate::err::Error> {
+        let dir = match &config.vlog_dir {
+            Some(vlog_dir) => dir.with_val_log(vlog_dir),
+            None => dir,
+        };
+        let info = StoreInfo::read(&dir.root).await?;
+        if let Some(info) = &info {
+            info.check_supported(&dir.root)?;
+            if info.vlog_dir != config.vlog_dir {
+                log::warn!(
+                    "Store at {:?} was created with vlog_dir {:?}, opened with {:?}",
+                    dir.root,
+                    info.vlog_dir,
+                    config.vlog_dir
+                );
+            }
+        }
         let vlog_path = &dir.val_log.to_owned(); // value log file path
         let vlog_exist = vlog_path
             .try_exists()
-            .map_err(crate::err::Error::TryFilePathExist)?;
+            .map_err(|error| crate::err::Error::TryFilePathExist {
+                path: vlog_path.to_owned(),
+                error,
+            })?;
 
         let params = CreateOrRecoverStoreParams {
             buckets_path: &dir.buckets,
             meta: Meta::new(&dir.meta).await?,
             dir: &dir,
-            vlog: ValueLog::new(vlog_path).await?,
-            key_range: KeyRange::default(),
+            vlog: ValueLog::new(vlog_path)
+                .await?
+                .with_preallocation(config.vlog_preallocation_extent)
+                .with_checksums(config.verify_reads)
+                .with_dedup(config.dedup_min_value_size)
+                .with_wal(config.wal_max_value_size)
+                .await?
+                .with_retry(config.io_retry)
+                .with_read_pool(config.read_pool.clone()),
+            key_range: KeyRange::with_filter_cache(config.filter_cache.clone())
+                .with_block_cache(config.block_cache.clone()),
             config,
             size_unit,
         };
@@ -703,13 +1740,21 @@ impl DataStore<'static, Key> {
         if !vlog_exist
             || fs::metadata(vlog_path)
                 .await
-                .map_err(crate::err::Error::GetFileMetaData)?
+                .map_err(|error| crate::err::Error::GetFileMetaData {
+                    path: vlog_path.to_owned(),
+                    error,
+                })?
                 .len()
                 == 0
         {
+            if info.is_none() {
+                StoreInfo::new(&params.config).write(&dir.root).await?;
+            }
             return DataStore::handle_empty_vlog(params).await;
         }
-        DataStore::recover(params).await
+        let mut store = DataStore::recover(params).await?;
+        store.recover_wal().await?;
+        Ok(store)
     }
 
     /// Trigger compaction mannually
@@ -719,6 +1764,7 @@ impl DataStore<'static, Key> {
     /// Returns error, if trigger failed
     pub async fn run_compaction(&mut self) -> Result<(), crate::err::Error> {
         self.compactor.reason = CompactionReason::Manual;
+        let _permit = self.compactor.env.acquire(BackgroundJob::Compaction).await;
         Compactor::handle_compaction(
             Arc::clone(&self.buckets),
             Arc::clone(&self.key_range),
@@ -727,6 +1773,304 @@ impl DataStore<'static, Key> {
         .await
     }
 
+    /// Waits until no compaction is pending or running
+    ///
+    /// Flushes already in progress are awaited first since each of them can
+    /// make compaction necessary, memtables whose flush failed are flushed
+    /// again. A compaction still pending afterwards is run before returning,
+    /// so the sstables on disk are settled once this resolves.
+    /// Useful before benchmarks, backups and shutting down batch jobs.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     store.put("apple", "tim cook").await.unwrap();
+    ///     store.wait_for_compactions().await.unwrap();
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if a failed flush failed again or pending compaction failed
+    pub async fn wait_for_compactions(&self) -> Result<(), crate::err::Error> {
+        // flushed memtables are removed from read-only memtables by the flush task
+        loop {
+            let mut flushing = false;
+            let mut failed = Vec::new();
+            {
+                let flush_stream = self.flush_stream.lock().unwrap();
+                for table in self.read_only_memtables.iter() {
+                    match flush_stream.get(table.key()) {
+                        Some(false) => flushing = true,
+                        Some(true) => failed.push((table.key().to_owned(), table.value().to_owned())),
+                        None => {}
+                    }
+                }
+            }
+            if flushing {
+                sleep(BACKGROUND_JOB_POLL_INTERVAL).await;
+                continue;
+            }
+            if failed.is_empty() {
+                break;
+            }
+            for (table_id, table) in failed {
+                self.flush_stream
+                    .lock()
+                    .unwrap()
+                    .insert(table_id.to_owned(), false);
+                let permit = self.config.env.acquire(BackgroundJob::Flush).await;
+                let res = self.flusher.clone().flush(table.clone()).await;
+                drop(permit);
+                match res {
+                    Ok(outcome) => {
+                        if let FlushOutcome::Flushed { .. } = outcome {
+                            self.flusher.retain(table_id.to_owned(), table);
+                        }
+                        self.read_only_memtables.remove(&table_id);
+                    }
+                    Err(err) => {
+                        self.flush_stream.lock().unwrap().insert(table_id, true);
+                        return Err(crate::err::Error::FlushToDisk { error: Box::new(err) });
+                    }
+                }
+            }
+        }
+        self.compactor
+            .wait_for_idle(Arc::clone(&self.buckets), Arc::clone(&self.key_range))
+            .await
+    }
+
+    /// Picks up to `n` random keys, approximately uniform over the stored data
+    ///
+    /// Candidates are the block boundaries in sstable indexes, so every key
+    /// stands for about one block of data. Keys still in memtables are thinned
+    /// out to the same density. Returned keys are sorted, which makes them
+    /// usable as shard split points; they may include deleted keys.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///     store.put("apple", "tim cook").await.unwrap();
+    ///
+    ///     let keys = store.sample_keys(10).await.unwrap();
+    ///     assert!(keys.len() <= 10);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an sstable index could not be read
+    pub async fn sample_keys(&self, n: usize) -> Result<Vec<Key>, crate::err::Error> {
+        use rand::seq::SliceRandom;
+
+        let mut candidates = Vec::new();
+        let buckets = self.buckets.read().await;
+        for bucket in buckets.buckets.values() {
+            for sst in bucket.sstables.read().await.iter() {
+                let index = Index::new(sst.index_file.path.to_owned(), sst.index_file.file.to_owned());
+                candidates.extend(index.read_entries().await?.into_iter().map(|e| e.key));
+            }
+        }
+        drop(buckets);
+
+        let memtables = std::iter::once(self.active_memtable.entries.clone())
+            .chain(self.read_only_memtables.iter().map(|t| t.value().entries.clone()));
+        for entries in memtables {
+            if entries.is_empty() {
+                continue;
+            }
+            let key_bytes: usize = entries.iter().map(|e| e.key().len()).sum();
+            // a block entry holds key prefix, key, value offset, date and tombstone flag
+            let entry_size = key_bytes / entries.len() + SIZE_OF_U32 * 2 + SIZE_OF_U64 + SIZE_OF_U8;
+            let per_block = (BLOCK_SIZE / entry_size).max(1);
+            candidates.extend(entries.iter().step_by(per_block).map(|e| e.key().to_owned()));
+        }
+        candidates.retain(|key| key.as_slice() != HEAD_ENTRY_KEY && key.as_slice() != TAIL_ENTRY_KEY);
+        candidates.sort();
+        candidates.dedup();
+
+        let mut keys: Vec<Key> = candidates
+            .choose_multiple(&mut rand::thread_rng(), n)
+            .cloned()
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Lists files currently making up the store and pins them
+    ///
+    /// Sstables replaced by compaction while the returned [`LiveFiles`]
+    /// (or its [`FilePin`](crate::db::FilePin)) is held are deleted only after
+    /// it drops, so backup tools can copy a consistent set of files. Value log
+    /// is never deleted, space reclaimed by garbage collection is punched out
+    /// in place.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let backup = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///     store.put("apple", "tim cook").await.unwrap();
+    ///
+    ///     let files = store.live_files().await;
+    ///     for (i, path) in files.paths().iter().enumerate() {
+    ///         std::fs::copy(path, backup.path().join(i.to_string())).unwrap();
+    ///     }
+    ///     drop(files); // obsolete files can be deleted again
+    /// }
+    /// ```
+    pub async fn live_files(&self) -> LiveFiles {
+        // compaction needs write lock to delete files, holding read lock
+        // while pinning keeps listing and pin consistent
+        let buckets = self.buckets.read().await;
+        let mut files = LiveFiles::new(self.val_log.content.path.to_owned(), buckets.pins.pin());
+        files.wal = self.val_log.wal.as_ref().map(|wal| wal.content.path.to_owned());
+        for bucket in buckets.buckets.values() {
+            for sst in bucket.sstables.read().await.iter() {
+                files.sstables.push(sst.dir.to_owned());
+                files.data_files.push(sst.data_file.path.to_owned());
+                files.index_files.push(sst.index_file.path.to_owned());
+            }
+        }
+        files
+    }
+
+    /// Reports bytes on disk by bucket, sstable and value log
+    ///
+    /// Each sstable is broken down into data, index, filter and summary
+    /// files and carries its key range, see [`DiskUsage::in_range`]. Value
+    /// log is split into live bytes and bytes already punched out by
+    /// garbage collection.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///     store.put("apple", "tim cook").await.unwrap();
+    ///
+    ///     let usage = store.disk_usage().await.unwrap();
+    ///     for bucket in &usage.buckets {
+    ///         println!("bucket {}: {} bytes", bucket.id, bucket.total());
+    ///     }
+    ///     println!("vlog: {} live, {} punched", usage.vlog.live, usage.vlog.punched);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if file metadata could not be read
+    pub async fn disk_usage(&self) -> Result<DiskUsage, crate::err::Error> {
+        // read lock keeps compaction from deleting files while they are measured
+        let bucket_map = self.buckets.read().await;
+        let mut buckets = Vec::with_capacity(bucket_map.buckets.len());
+        for (id, bucket) in bucket_map.buckets.iter() {
+            let mut sstables = Vec::new();
+            for sst in bucket.sstables.read().await.iter() {
+                let (smallest_key, biggest_key) = sst
+                    .summary
+                    .as_ref()
+                    .map(|s| (s.smallest_key.to_owned(), s.biggest_key.to_owned()))
+                    .unwrap_or_default();
+                sstables.push(
+                    SSTableUsage::read(
+                        sst.dir.to_owned(),
+                        &sst.data_file.path,
+                        &sst.index_file.path,
+                        smallest_key,
+                        biggest_key,
+                    )
+                    .await?,
+                );
+            }
+            buckets.push(BucketUsage {
+                id: *id,
+                dir: bucket.dir.to_owned(),
+                sstables,
+            });
+        }
+        drop(bucket_map);
+        // garbage collector moves tail of its own handle first
+        let tail_offset = self.gc_log.read().await.tail_offset.max(self.val_log.tail_offset);
+        let vlog = VlogUsage::read(self.val_log.content.path.to_owned(), tail_offset).await?;
+        Ok(DiskUsage { buckets, vlog })
+    }
+
+    /// Subscribes to memtable flushes
+    ///
+    /// Every flush that completes after this call is delivered to the returned
+    /// [`FlushSubscription`] as a [`FlushEvent`]: the new SSTable path, the number
+    /// of entries and bytes written and how long it took, or the error of a failed
+    /// flush. Useful to invalidate application caches once data has reached disk.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::{DataStore, FlushEvent};
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     let mut flushes = store.subscribe_flush();
+    ///     tokio::spawn(async move {
+    ///         while let Some(event) = flushes.recv().await {
+    ///             match event {
+    ///                 FlushEvent::Flushed { sstable, entries, .. } => {
+    ///                     println!("{} entries flushed to {:?}", entries, sstable)
+    ///                 }
+    ///                 FlushEvent::Failed { error, .. } => eprintln!("flush failed: {}", error),
+    ///             }
+    ///         }
+    ///     });
+    /// }
+    /// ```
+    pub fn subscribe_flush(&self) -> FlushSubscription {
+        FlushSubscription::new(self.flush_signal_rx.new_receiver())
+    }
+
+    /// Returns mismatches between bucket directories and the bucket manifest found at open
+    ///
+    /// Files that belong to no sstable are ignored and sstables the manifest
+    /// does not list are reported but not loaded, nothing is deleted. Empty for a new store.
+    pub fn layout_issues(&self) -> &[LayoutIssue] {
+        &self.layout_issues
+    }
+
     /// Returns length of entries in active memtable
     pub fn len_of_entries_in_memtable(&self) -> usize {
         self.active_memtable.entries.len()
@@ -755,4 +2099,10 @@ impl DirPath {
             meta,
         }
     }
+
+    /// Returns `DirPath` with value log stored in `val_log` instead of under root
+    pub(crate) fn with_val_log(mut self, val_log: impl AsRef<Path>) -> Self {
+        self.val_log = val_log.as_ref().to_path_buf();
+        self
+    }
 }
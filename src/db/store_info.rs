@@ -0,0 +1,103 @@
+use crate::{
+    cfg::Config,
+    consts::{FORMAT_VERSION, LEGACY_FORMAT_VERSION, STORE_INFO_FILE_NAME},
+    err::Error,
+    fs::FileNode,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// Store-info file kept in the root directory of every store
+///
+/// Records which engine created the store and the on-disk format it uses,
+/// so a build never opens files laid out by a newer one. Stores created before
+/// this file existed have no store-info and are treated as format
+/// `LEGACY_FORMAT_VERSION` until [`DataStore::migrate`](crate::db::DataStore::migrate) runs.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoreInfo {
+    /// Version of the crate that created or last migrated the store
+    pub engine_version: String,
+
+    /// Version of the on-disk format
+    pub format_version: u32,
+
+    /// Creation time in milliseconds since the unix epoch
+    pub created_at: u64,
+
+    /// Value log directory, `None` if it is under the store root
+    pub vlog_dir: Option<PathBuf>,
+
+    /// Cold SSTable directory, `None` if cold storage is disabled
+    pub cold_storage_dir: Option<PathBuf>,
+
+    /// Whether value log entries carry a checksum
+    pub checksums: bool,
+}
+
+impl StoreInfo {
+    /// Creates `StoreInfo` for a new store opened with `config`
+    pub(crate) fn new(config: &Config) -> Self {
+        Self {
+            engine_version: env!("CARGO_PKG_VERSION").to_string(),
+            format_version: FORMAT_VERSION,
+            created_at: Utc::now().timestamp_millis() as u64,
+            vlog_dir: config.vlog_dir.to_owned(),
+            cold_storage_dir: config.cold_storage.as_ref().map(|cold| cold.dir.to_owned()),
+            checksums: config.verify_reads,
+        }
+    }
+
+    /// Returns `StoreInfo` assumed for a store without store-info file
+    pub(crate) fn legacy() -> Self {
+        Self {
+            engine_version: String::new(),
+            format_version: LEGACY_FORMAT_VERSION,
+            created_at: 0,
+            vlog_dir: None,
+            cold_storage_dir: None,
+            checksums: false,
+        }
+    }
+
+    /// Returns path of the store-info file of store at `root`
+    pub(crate) fn path(root: impl AsRef<Path>) -> PathBuf {
+        root.as_ref().join(STORE_INFO_FILE_NAME)
+    }
+
+    /// Reads store-info of store at `root`, `None` if it has none
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the file cannot be read or parsed
+    pub(crate) async fn read(root: impl AsRef<Path>) -> Result<Option<Self>, Error> {
+        let path = Self::path(root);
+        let buf = match fs::read(&path).await {
+            Ok(buf) => buf,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(Error::FileOpen { path, error: err }),
+        };
+        serde_json::from_slice(&buf)
+            .map(Some)
+            .map_err(|err| Error::StoreInfoCorrupt { path, error: err })
+    }
+
+    /// Writes store-info of store at `root`, replacing any previous one atomically
+    pub(crate) async fn write(&self, root: impl AsRef<Path>) -> Result<(), Error> {
+        let buf = serde_json::to_vec_pretty(self).map_err(|_| Error::Serialization("store info"))?;
+        FileNode::write_atomic(Self::path(root), &buf).await
+    }
+
+    /// Returns error if this build cannot read the store's format
+    pub(crate) fn check_supported(&self, root: impl AsRef<Path>) -> Result<(), Error> {
+        if self.format_version > FORMAT_VERSION {
+            return Err(Error::IncompatibleFormat {
+                path: root.as_ref().to_path_buf(),
+                found: self.format_version,
+                supported: FORMAT_VERSION,
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+use crate::cfg::Config;
+use crate::db::DataStore;
+use crate::err::Error;
+use crate::fs::P;
+use crate::types::{Bool, Key};
+
+/// [`DataStore`] wrapper for UTF-8 keys and values
+///
+/// Takes `&str` and returns `String`, values that are not valid UTF-8
+/// are reported as [`Error::InvalidUtf8`] instead of being returned as bytes.
+/// The wrapped store stays reachable through [`StringStore::inner`].
+///
+/// # Examples
+///
+/// ```rust
+/// use velarixdb::db::StringStore;
+/// # use tempfile::tempdir;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let root = tempdir().unwrap();
+///     let path = root.path().join("velarixdb");
+///     let mut store = StringStore::open("big_tech", path).await.unwrap(); // handle IO error
+///
+///     store.put("apple", "tim cook").await.unwrap();
+///     assert_eq!(store.get("apple").await.unwrap().as_deref(), Some("tim cook"));
+///
+///     store.update("apple", |ceo| ceo.to_uppercase()).await.unwrap();
+///     assert_eq!(store.get("apple").await.unwrap().as_deref(), Some("TIM COOK"));
+/// }
+/// ```
+pub struct StringStore {
+    store: DataStore<'static, Key>,
+}
+
+impl StringStore {
+    /// Opens a keyspace in the given directory, see [`DataStore::open`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    pub async fn open(keyspace: &'static str, dir: impl P) -> Result<Self, Error> {
+        Ok(Self::from(DataStore::open(keyspace, dir).await?))
+    }
+
+    /// Same as [`StringStore::open`], but uses the provided [`Config`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    pub async fn open_with_config(
+        keyspace: &'static str,
+        dir: impl P,
+        config: Config,
+    ) -> Result<Self, Error> {
+        Ok(Self::from(
+            DataStore::open_with_config(keyspace, dir, config).await?,
+        ))
+    }
+
+    /// Inserts a new entry, see [`DataStore::put`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or key/value size is invalid.
+    pub async fn put(&mut self, key: &str, val: &str) -> Result<Bool, Error> {
+        self.store.put_str(key, val).await
+    }
+
+    /// Retrieves value of `key`, see [`DataStore::get`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the value is not valid UTF-8.
+    pub async fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        self.store.get_str(key).await
+    }
+
+    /// Removes an entry, see [`DataStore::delete`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    pub async fn delete(&mut self, key: &str) -> Result<Bool, Error> {
+        self.store.delete(key).await
+    }
+
+    /// Replaces value of an existing `key` with the one computed by `f` from
+    /// the current value, returns false if the key does not exist
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the current value is not valid UTF-8.
+    pub async fn update<V: AsRef<str>>(
+        &mut self,
+        key: &str,
+        f: impl FnOnce(&str) -> V,
+    ) -> Result<Bool, Error> {
+        match self.get(key).await? {
+            Some(current) => self.put(key, f(&current).as_ref()).await,
+            None => Ok(false),
+        }
+    }
+
+    /// Returns the wrapped store
+    pub fn inner(&self) -> &DataStore<'static, Key> {
+        &self.store
+    }
+
+    /// Returns the wrapped store mutably
+    pub fn inner_mut(&mut self) -> &mut DataStore<'static, Key> {
+        &mut self.store
+    }
+
+    /// Unwraps the store
+    pub fn into_inner(self) -> DataStore<'static, Key> {
+        self.store
+    }
+}
+
+impl From<DataStore<'static, Key>> for StringStore {
+    fn from(store: DataStore<'static, Key>) -> Self {
+        Self { store }
+    }
+}
@@ -0,0 +1,335 @@
+use crate::{
+    consts::LOCK_TABLE_STRIPES,
+    db::DataStore,
+    err::Error,
+    memtable::Val,
+    types::{Key, Value},
+};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, HashMap},
+    hash::{Hash, Hasher},
+    pin::pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::{Notify, RwLock},
+    time::{timeout_at, Instant},
+};
+
+/// Identifies a transaction in the lock table
+pub(crate) type TxnId = u64;
+
+/// Keys locked by transactions of a store
+///
+/// Keys are spread by hash over `LOCK_TABLE_STRIPES` stripes, so transactions
+/// locking unrelated keys rarely contend on the same mutex. A transaction
+/// waiting for a key records which transaction holds it, a wait that would
+/// close a cycle is refused with [`Error::Deadlock`].
+#[derive(Debug, Clone)]
+pub(crate) struct LockTable {
+    inner: Arc<LockTableInner>,
+}
+
+#[derive(Debug)]
+struct LockTableInner {
+    stripes: Vec<Stripe>,
+
+    /// Transaction each waiting transaction waits for
+    waits_for: Mutex<HashMap<TxnId, TxnId>>,
+
+    next_id: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct Stripe {
+    /// Transaction holding each locked key
+    owners: Mutex<HashMap<Key, TxnId>>,
+
+    /// Wakes waiters once keys of the stripe are released
+    released: Notify,
+}
+
+impl Default for LockTable {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(LockTableInner {
+                stripes: (0..LOCK_TABLE_STRIPES).map(|_| Stripe::default()).collect(),
+                waits_for: Mutex::default(),
+                next_id: AtomicU64::new(1),
+            }),
+        }
+    }
+}
+
+impl LockTable {
+    /// Returns id for a new transaction
+    pub(crate) fn next_id(&self) -> TxnId {
+        self.inner.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn stripe_index(&self, key: &[u8]) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish() as usize % self.inner.stripes.len()
+    }
+
+    /// Locks `key` for transaction `id`, waiting up to `timeout` for the transaction holding it
+    ///
+    /// Returns true if the key was locked by this call, false if `id` already held it
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Deadlock`] if the holder of the key waits for `id`,
+    /// directly or through other transactions, and [`Error::LockTimeout`] if
+    /// the key was not released in time.
+    pub(crate) async fn acquire(&self, id: TxnId, key: &[u8], timeout: Duration) -> Result<bool, Error> {
+        let deadline = Instant::now() + timeout;
+        let stripe = &self.inner.stripes[self.stripe_index(key)];
+        loop {
+            // registered before the check, so a release in between is not missed
+            let mut released = pin!(stripe.released.notified());
+            released.as_mut().enable();
+            {
+                let mut owners = stripe.owners.lock().unwrap();
+                match owners.get(key) {
+                    None => {
+                        owners.insert(key.to_vec(), id);
+                        self.stop_waiting(id);
+                        return Ok(true);
+                    }
+                    Some(owner) if *owner == id => {
+                        self.stop_waiting(id);
+                        return Ok(false);
+                    }
+                    // recorded while the stripe is locked, the owner can't release in between
+                    Some(owner) => self.wait_for(id, *owner, key)?,
+                }
+            }
+            if timeout_at(deadline, released).await.is_err() {
+                self.stop_waiting(id);
+                return Err(Error::LockTimeout {
+                    key: key.to_vec(),
+                    timeout,
+                });
+            }
+        }
+    }
+
+    /// Records that `id` waits for `owner`, unless `owner` waits for `id`
+    fn wait_for(&self, id: TxnId, owner: TxnId, key: &[u8]) -> Result<(), Error> {
+        let mut waits_for = self.inner.waits_for.lock().unwrap();
+        let mut next = Some(owner);
+        // every transaction waits for one other, a longer chain is a cycle not involving `id`
+        for _ in 0..=waits_for.len() {
+            match next {
+                Some(txn) if txn == id => {
+                    waits_for.remove(&id);
+                    return Err(Error::Deadlock { key: key.to_vec() });
+                }
+                Some(txn) => next = waits_for.get(&txn).copied(),
+                None => break,
+            }
+        }
+        waits_for.insert(id, owner);
+        Ok(())
+    }
+
+    fn stop_waiting(&self, id: TxnId) {
+        self.inner.waits_for.lock().unwrap().remove(&id);
+    }
+
+    /// Releases `keys` held by `id` and wakes transactions waiting for them
+    pub(crate) fn release(&self, id: TxnId, keys: impl IntoIterator<Item = Key>) {
+        let mut released = BTreeSet::new();
+        for key in keys {
+            let index = self.stripe_index(&key);
+            let mut owners = self.inner.stripes[index].owners.lock().unwrap();
+            if owners.get(&key) == Some(&id) {
+                owners.remove(&key);
+                released.insert(index);
+            }
+        }
+        self.inner
+            .waits_for
+            .lock()
+            .unwrap()
+            .retain(|_, owner| *owner != id);
+        for index in released {
+            self.inner.stripes[index].released.notify_waiters();
+        }
+    }
+}
+
+/// Pessimistic transaction holding locks on the keys it reads for update and writes
+///
+/// Started by [`DataStore::begin_transaction`]. A key is locked the first time
+/// the transaction reads it with [`Transaction::get_for_update`] or writes it,
+/// and stays locked until the transaction commits, rolls back or is dropped,
+/// so a read-modify-write can't be interleaved with one of another transaction.
+/// Writes are buffered and applied to the store by [`Transaction::commit`].
+///
+/// Only transactions take locks, writes made directly on the store don't wait
+/// for them. Waiting for a lock fails with [`Error::Deadlock`] if the holder
+/// waits for this transaction, and with [`Error::LockTimeout`] once
+/// `Config::lock_timeout` passed. Both are retryable, the transaction should be
+/// rolled back to release its locks and run again.
+pub struct Transaction<V: Val = Value> {
+    id: TxnId,
+    store: Arc<RwLock<DataStore<'static, Key, V>>>,
+    locks: LockTable,
+    timeout: Duration,
+
+    /// Keys locked by the transaction
+    held: Vec<Key>,
+
+    /// Buffered writes, `None` deletes the key
+    writes: BTreeMap<Key, Option<Value>>,
+}
+
+impl<V: Val> Transaction<V> {
+    /// Locks `key` until the transaction ends, without reading it
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Deadlock`] or [`Error::LockTimeout`] if the lock was not acquired
+    pub async fn lock(&mut self, key: impl AsRef<[u8]>) -> Result<(), Error> {
+        let key = key.as_ref();
+        if self.locks.acquire(self.id, key, self.timeout).await? {
+            self.held.push(key.to_vec());
+        }
+        Ok(())
+    }
+
+    /// Locks `key` and returns its value, including writes of the transaction not yet committed
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the lock was not acquired or an IO error occured
+    pub async fn get_for_update(&mut self, key: impl AsRef<[u8]>) -> Result<Option<V>, Error> {
+        self.lock(key.as_ref()).await?;
+        if let Some(write) = self.writes.get(key.as_ref()) {
+            return Ok(write.clone().map(V::from));
+        }
+        let entry = self.store.read().await.get(key).await?;
+        Ok(entry.map(|entry| entry.val))
+    }
+
+    /// Locks `key` and buffers a write of `val` to it
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the lock was not acquired
+    pub async fn put(&mut self, key: impl AsRef<[u8]>, val: impl AsRef<[u8]>) -> Result<(), Error> {
+        self.lock(key.as_ref()).await?;
+        self.writes
+            .insert(key.as_ref().to_vec(), Some(val.as_ref().to_vec()));
+        Ok(())
+    }
+
+    /// Locks `key` and buffers its deletion
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the lock was not acquired
+    pub async fn delete(&mut self, key: impl AsRef<[u8]>) -> Result<(), Error> {
+        self.lock(key.as_ref()).await?;
+        self.writes.insert(key.as_ref().to_vec(), None);
+        Ok(())
+    }
+
+    /// Applies buffered writes to the store and releases the locks
+    ///
+    /// Writes are checked against the size limits and the keyspace quota of the
+    /// store, then written to the value log in a single append and inserted into
+    /// the memtable under one write lock of the store, so either all of them are
+    /// applied or none is.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if a write is invalid, exceeds the quota or an IO error
+    /// occured, in which case no write is applied. The locks are released either way.
+    pub async fn commit(mut self) -> Result<(), Error> {
+        let writes = std::mem::take(&mut self.writes);
+        if writes.is_empty() {
+            return Ok(());
+        }
+        let mut store = self.store.write().await;
+        for (key, val) in writes.iter() {
+            store.validate_size(key, val.as_ref())?;
+        }
+        let new_bytes = writes
+            .iter()
+            .filter_map(|(key, val)| val.as_ref().map(|val| key.len() + val.len()))
+            .sum();
+        store
+            .check_quota(
+                new_bytes,
+                writes.iter().map(|(key, val)| (key.as_slice(), val.is_none())),
+            )
+            .await?;
+        store.write_batch(writes).await
+    }
+
+    /// Discards buffered writes and releases the locks
+    pub fn rollback(self) {}
+
+    /// Returns number of keys the transaction holds locks on
+    pub fn locked_keys(&self) -> usize {
+        self.held.len()
+    }
+}
+
+impl<V: Val> Drop for Transaction<V> {
+    fn drop(&mut self) {
+        self.locks.release(self.id, std::mem::take(&mut self.held));
+    }
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Starts a pessimistic transaction on `store`, see [`Transaction`]
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use std::sync::Arc;
+    /// use tokio::sync::RwLock;
+    /// use velarixdb::db::DataStore;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///     let store = Arc::new(RwLock::new(store));
+    ///     store.write().await.put("balance", "10").await.unwrap();
+    ///
+    ///     let mut txn = DataStore::begin_transaction(&store).await;
+    ///     let balance = txn.get_for_update("balance").await.unwrap().unwrap();
+    ///     let balance: u32 = std::str::from_utf8(&balance).unwrap().parse().unwrap();
+    ///     txn.put("balance", (balance + 5).to_string()).await.unwrap();
+    ///     txn.commit().await.unwrap();
+    ///
+    ///     let entry = store.read().await.get("balance").await.unwrap().unwrap();
+    ///     assert_eq!(entry.val, b"15");
+    /// }
+    /// ```
+    pub async fn begin_transaction(store: &Arc<RwLock<Self>>) -> Transaction<V> {
+        let (locks, timeout) = {
+            let store = store.read().await;
+            (store.locks.clone(), store.config.lock_timeout)
+        };
+        Transaction {
+            id: locks.next_id(),
+            store: store.clone(),
+            locks,
+            timeout,
+            held: Vec::new(),
+            writes: BTreeMap::new(),
+        }
+    }
+}
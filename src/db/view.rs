@@ -0,0 +1,222 @@
+use crate::{
+    db::DataStore,
+    err::Error,
+    memtable::{UserEntry, Val},
+    range::{ContinuationToken, Page, RangeIterator},
+    types::{Key, Metadata, Value},
+};
+use std::ops::{Bound, RangeBounds};
+
+/// Read-only handle to the keys of a [`DataStore`] within a range, returned by [`DataStore::view`]
+///
+/// Point reads of keys outside the range fail with [`Error::KeyOutsideView`],
+/// scans are narrowed to the range, so a view can be handed to code that must
+/// not see the rest of the store.
+///
+/// # Examples
+///
+/// ```rust
+/// use velarixdb::db::{DataStore, Error};
+/// # use tempfile::tempdir;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let root = tempdir().unwrap();
+///     let mut store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+///     store.put("tenant1/apple", "tim cook").await.unwrap();
+///     store.put("tenant2/google", "sundar pichai").await.unwrap();
+///
+///     let view = store.prefix_view("tenant1/");
+///     assert!(view.get("tenant1/apple").await.unwrap().is_some());
+///     assert!(matches!(view.get("tenant2/google").await, Err(Error::KeyOutsideView)));
+///
+///     let mut iter = view.seek("a", "z").await.unwrap();
+///     assert_eq!(iter.next().await.unwrap().unwrap().key, b"tenant1/apple".to_vec());
+///     assert!(iter.next().await.unwrap().is_none());
+/// }
+/// ```
+pub struct StoreView<'a, V: Val = Value> {
+    store: &'a DataStore<'static, Key, V>,
+
+    /// Smallest key of the view
+    start: Key,
+
+    /// Key the view ends before, `None` if it has no end
+    end: Option<Key>,
+}
+
+/// Returns smallest key after every key starting with `prefix`, `None` if there is none
+fn prefix_end(prefix: &[u8]) -> Option<Key> {
+    let last = prefix.iter().rposition(|byte| *byte != u8::MAX)?;
+    let mut end = prefix[..=last].to_vec();
+    end[last] += 1;
+    Some(end)
+}
+
+/// Returns smallest key after `key`
+fn successor(key: &[u8]) -> Key {
+    let mut next = key.to_vec();
+    next.push(0);
+    next
+}
+
+impl<'a, V: Val> StoreView<'a, V> {
+    /// Returns true if `key` is within the view
+    pub fn contains<T: AsRef<[u8]>>(&self, key: T) -> bool {
+        let key = key.as_ref();
+        key >= self.start.as_slice() && self.end.as_ref().is_none_or(|end| key < end.as_slice())
+    }
+
+    /// Retrieves value of `key`, see [`DataStore::get`]
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::KeyOutsideView` if `key` is not within the view, or
+    /// error if an IO error occured.
+    pub async fn get<T: AsRef<[u8]>>(&self, key: T) -> Result<Option<UserEntry<V>>, Error> {
+        self.check(key.as_ref())?;
+        self.store.get(key).await
+    }
+
+    /// Retrieves value of `key` with its metadata, see [`DataStore::get_with_metadata`]
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::KeyOutsideView` if `key` is not within the view, or
+    /// error if an IO error occured.
+    pub async fn get_with_metadata<T: AsRef<[u8]>>(
+        &self,
+        key: T,
+    ) -> Result<Option<(UserEntry<V>, Option<Metadata>)>, Error> {
+        self.check(key.as_ref())?;
+        self.store.get_with_metadata(key).await
+    }
+
+    /// Returns iterator over live entries with keys from `start` up to, but excluding, `end`
+    /// that are within the view, see [`DataStore::seek`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an sstable could not be read
+    pub async fn seek<T: AsRef<[u8]>>(&self, start: T, end: T) -> Result<RangeIterator, Error> {
+        self.seek_after(start, end, None).await
+    }
+
+    /// Same as [`StoreView::seek`], but skips keys up to and including the position of `token`,
+    /// see [`DataStore::seek_after`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an sstable could not be read
+    pub async fn seek_after<T: AsRef<[u8]>>(
+        &self,
+        start: T,
+        end: T,
+        token: Option<&ContinuationToken>,
+    ) -> Result<RangeIterator, Error> {
+        let (start, end) = self.narrow(start.as_ref(), end.as_ref());
+        self.store.seek_after(start, end, token).await
+    }
+
+    /// Returns up to `limit` live entries with keys from `start` up to, but excluding, `end`
+    /// that are within the view, see [`DataStore::page`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an sstable or a value could not be read
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is zero
+    pub async fn page<T: AsRef<[u8]>>(
+        &self,
+        start: T,
+        end: T,
+        limit: usize,
+        token: Option<&ContinuationToken>,
+    ) -> Result<Page, Error> {
+        let (start, end) = self.narrow(start.as_ref(), end.as_ref());
+        self.store.page(start, end, limit, token).await
+    }
+
+    /// Returns number of live keys within the view, see [`DataStore::count_range`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an sstable could not be read
+    pub async fn count(&self) -> Result<usize, Error> {
+        let end = self.end.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+        self.store
+            .count_range::<&[u8]>((Bound::Included(self.start.as_slice()), end))
+            .await
+    }
+
+    /// Fails if `key` is not within the view
+    fn check(&self, key: &[u8]) -> Result<(), Error> {
+        if !self.contains(key) {
+            return Err(Error::KeyOutsideView);
+        }
+        Ok(())
+    }
+
+    /// Returns range from `start` up to `end` narrowed to the view, empty if they don't overlap
+    fn narrow<'b>(&'b self, start: &'b [u8], end: &'b [u8]) -> (&'b [u8], &'b [u8]) {
+        let start = start.max(self.start.as_slice());
+        let end = match &self.end {
+            Some(view_end) => end.min(view_end.as_slice()),
+            None => end,
+        };
+        (start, end.max(start))
+    }
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Returns read-only view of the keys within `range`
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let mut store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+    ///     store.put("apple", "tim cook").await.unwrap();
+    ///     store.put("google", "sundar pichai").await.unwrap();
+    ///     store.put("nvidia", "jensen huang").await.unwrap();
+    ///
+    ///     let view = store.view("b"..="nvidia");
+    ///     assert!(view.contains("google"));
+    ///     assert!(!view.contains("apple"));
+    ///     assert_eq!(view.count().await.unwrap(), 2);
+    /// }
+    /// ```
+    pub fn view<T: AsRef<[u8]>>(&self, range: impl RangeBounds<T>) -> StoreView<'_, V> {
+        let start = match range.start_bound() {
+            Bound::Included(start) => start.as_ref().to_vec(),
+            Bound::Excluded(start) => successor(start.as_ref()),
+            Bound::Unbounded => Key::new(),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => Some(successor(end.as_ref())),
+            Bound::Excluded(end) => Some(end.as_ref().to_vec()),
+            Bound::Unbounded => None,
+        };
+        StoreView {
+            store: self,
+            start,
+            end,
+        }
+    }
+
+    /// Returns read-only view of the keys starting with `prefix`, see [`DataStore::view`]
+    pub fn prefix_view<T: AsRef<[u8]>>(&self, prefix: T) -> StoreView<'_, V> {
+        StoreView {
+            store: self,
+            start: prefix.as_ref().to_vec(),
+            end: prefix_end(prefix.as_ref()),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{db::DataStore, err::Error, memtable::Val, types::Key};
+use std::ops::{Bound, RangeBounds};
+
+/// What [`DataStore::warm_cache`] loaded into caches
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheWarmup {
+    /// SSTables whose filter and index were loaded
+    pub sstables: usize,
+
+    /// Data blocks read into block cache
+    pub blocks: usize,
+
+    /// Bytes of data blocks read from disk
+    pub bytes: usize,
+}
+
+/// Returns true if keys from `smallest` to `biggest` overlap `range`
+fn overlaps<T: AsRef<[u8]>>(range: &impl RangeBounds<T>, smallest: &[u8], biggest: &[u8]) -> bool {
+    let after_start = match range.start_bound() {
+        Bound::Included(start) => biggest >= start.as_ref(),
+        Bound::Excluded(start) => biggest > start.as_ref(),
+        Bound::Unbounded => true,
+    };
+    let before_end = match range.end_bound() {
+        Bound::Included(end) => smallest <= end.as_ref(),
+        Bound::Excluded(end) => smallest < end.as_ref(),
+        Bound::Unbounded => true,
+    };
+    after_start && before_end
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Loads filters, indexes and data blocks of sstables holding keys in `range` into caches
+    ///
+    /// Meant to be called after startup so early reads of the range don't go to
+    /// disk. SSTables read most often are warmed first, data blocks stop being
+    /// loaded once they would fill the block cache. Memtables are always in memory
+    /// and values are not cached, so neither is read.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+    ///
+    ///     let warmup = store.warm_cache("a".."n").await.unwrap();
+    ///     assert_eq!(warmup.blocks, 0); // nothing was flushed yet
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if a file could not be read
+    pub async fn warm_cache<T: AsRef<[u8]>>(&self, range: impl RangeBounds<T>) -> Result<CacheWarmup, Error> {
+        let mut tables: Vec<_> = self
+            .key_range
+            .key_ranges
+            .read()
+            .await
+            .values()
+            .filter(|r| overlaps(&range, &r.smallest_key, &r.biggest_key))
+            .map(|r| r.sst.to_owned())
+            .collect();
+        tables.sort_by_key(|t| std::cmp::Reverse(t.get_hotness()));
+
+        let block_cache = &self.config.block_cache;
+        let mut warmup = CacheWarmup::default();
+        for table in tables {
+            self.key_range.load_filter(&table).await?;
+            let index = table.index_file.file.load().await?;
+            warmup.sstables += 1;
+
+            // an index entry holds the last key of its block
+            for entry in index.entries() {
+                let below_start = match range.start_bound() {
+                    Bound::Included(start) => entry.key.as_slice() < start.as_ref(),
+                    Bound::Excluded(start) => entry.key.as_slice() <= start.as_ref(),
+                    Bound::Unbounded => false,
+                };
+                if below_start {
+                    continue;
+                }
+                if warmup.bytes >= block_cache.capacity() {
+                    return Ok(warmup);
+                }
+                let (_, bytes_read) = table
+                    .cached_block(
+                        entry.block_handle,
+                        block_cache,
+                        &self.config.io_retry,
+                        self.config.read_pool.as_ref(),
+                    )
+                    .await?;
+                if bytes_read > 0 {
+                    warmup.blocks += 1;
+                    warmup.bytes += bytes_read;
+                }
+                let past_end = match range.end_bound() {
+                    Bound::Included(end) | Bound::Excluded(end) => entry.key.as_slice() >= end.as_ref(),
+                    Bound::Unbounded => false,
+                };
+                if past_end {
+                    break;
+                }
+            }
+        }
+        Ok(warmup)
+    }
+}
@@ -0,0 +1,9 @@
+mod priority;
+mod scheduler;
+mod supervisor;
+mod timer;
+pub use scheduler::BackgroundJob;
+pub use scheduler::Env;
+pub(crate) use supervisor::supervise;
+pub use supervisor::BackgroundError;
+pub(crate) use timer::Timer;
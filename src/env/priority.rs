@@ -0,0 +1,90 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{sync::Notify, time::timeout};
+
+/// High priority reads in flight, shared by clones
+///
+/// Background jobs wait for them to finish before they start IO, so
+/// user-facing reads don't queue behind compaction or garbage collection.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PriorityReads {
+    in_flight: Arc<AtomicUsize>,
+    finished: Arc<Notify>,
+}
+
+/// Marks a high priority read as in flight until dropped
+#[derive(Debug)]
+pub(crate) struct PriorityReadGuard {
+    reads: PriorityReads,
+}
+
+impl PriorityReads {
+    /// Marks a read as in flight until the returned guard is dropped
+    pub(crate) fn begin(&self) -> PriorityReadGuard {
+        self.in_flight.fetch_add(1, Ordering::AcqRel);
+        PriorityReadGuard { reads: self.clone() }
+    }
+
+    /// Returns number of reads in flight
+    pub(crate) fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+
+    /// Waits until no read is in flight, at most `max_wait` so a steady
+    /// stream of reads can't starve background jobs
+    pub(crate) async fn wait_idle(&self, max_wait: Duration) {
+        let _ = timeout(max_wait, async {
+            loop {
+                // registered before the count is checked, so no finish is missed
+                let finished = self.finished.notified();
+                if self.in_flight() == 0 {
+                    return;
+                }
+                finished.await;
+            }
+        })
+        .await;
+    }
+}
+
+impl Drop for PriorityReadGuard {
+    fn drop(&mut self) {
+        if self.reads.in_flight.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.reads.finished.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_idle_until_reads_finish() {
+        let reads = PriorityReads::default();
+        let guard = reads.begin();
+        assert_eq!(reads.in_flight(), 1);
+
+        let waiting = reads.clone();
+        let wait = tokio::spawn(async move { waiting.wait_idle(Duration::from_secs(3600)).await });
+        tokio::task::yield_now().await;
+        assert!(!wait.is_finished());
+
+        drop(guard);
+        assert_eq!(reads.in_flight(), 0);
+        assert!(timeout(Duration::from_secs(5), wait).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_wait_idle_gives_up_after_max_wait() {
+        let reads = PriorityReads::default();
+        let _guard = reads.begin();
+        let waited = timeout(Duration::from_secs(5), reads.wait_idle(Duration::from_millis(10))).await;
+        assert!(waited.is_ok());
+    }
+}
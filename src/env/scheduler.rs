@@ -0,0 +1,194 @@
+use super::priority::{PriorityReadGuard, PriorityReads};
+use super::supervisor::{BackgroundError, BackgroundErrors};
+use crate::consts::{
+    DEFAULT_MAX_BACKGROUND_COMPACTIONS, DEFAULT_MAX_BACKGROUND_FLUSHES, DEFAULT_MAX_BACKGROUND_GC,
+    MAX_PRIORITY_READ_WAIT,
+};
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Kinds of background work scheduled through [`Env`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackgroundJob {
+    /// Writing a read-only memtable to disk
+    Flush,
+
+    /// Merging sstables in buckets
+    Compaction,
+
+    /// Reclaiming space in value log
+    GarbageCollection,
+}
+
+/// Shared scheduler for background work
+///
+/// Every [`DataStore`](crate::db::DataStore) runs flush, compaction and garbage
+/// collection in background tasks. When many stores are opened in one process,
+/// cloning a single `Env` into the [`Config`](crate::db::Config) of each store caps how
+/// many of those jobs can run at the same time across all the stores.
+///
+/// Background tasks that panic are restarted, their panics are kept by
+/// the `Env` and returned by [`Env::background_errors`].
+///
+/// Background jobs yield to high priority reads (see
+/// [`ReadPriority::High`](crate::db::ReadPriority::High)) of any store sharing
+/// the `Env`: a job that got its slot waits for such reads in flight to finish,
+/// at most `MAX_PRIORITY_READ_WAIT`, before it starts.
+///
+/// Cloning an `Env` is cheap, clones share the same limits and errors.
+#[derive(Clone, Debug)]
+pub struct Env {
+    flushes: Arc<Semaphore>,
+    compactions: Arc<Semaphore>,
+    gc: Arc<Semaphore>,
+    pub(crate) errors: BackgroundErrors,
+    priority_reads: PriorityReads,
+}
+
+/// Permit returned by [`Env::acquire`], the job slot is released when dropped
+#[derive(Debug)]
+pub(crate) struct BackgroundPermit {
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Env::new(
+            DEFAULT_MAX_BACKGROUND_FLUSHES,
+            DEFAULT_MAX_BACKGROUND_COMPACTIONS,
+            DEFAULT_MAX_BACKGROUND_GC,
+        )
+    }
+}
+
+impl Env {
+    /// Creates new `Env` with limits for each kind of background job
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the limits is zero.
+    pub fn new(max_flushes: usize, max_compactions: usize, max_gc: usize) -> Self {
+        assert!(max_flushes > 0, "max_flushes should be greater than zero");
+        assert!(max_compactions > 0, "max_compactions should be greater than zero");
+        assert!(max_gc > 0, "max_gc should be greater than zero");
+        Self {
+            flushes: Arc::new(Semaphore::new(max_flushes)),
+            compactions: Arc::new(Semaphore::new(max_compactions)),
+            gc: Arc::new(Semaphore::new(max_gc)),
+            errors: BackgroundErrors::default(),
+            priority_reads: PriorityReads::default(),
+        }
+    }
+
+    /// Waits until a slot for `job` is free and reserves it
+    ///
+    /// Once reserved, also waits for high priority reads in flight to finish.
+    pub(crate) async fn acquire(&self, job: BackgroundJob) -> BackgroundPermit {
+        let permit = self
+            .semaphore(job)
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("background job semaphores are never closed");
+        self.priority_reads.wait_idle(MAX_PRIORITY_READ_WAIT).await;
+        BackgroundPermit { _permit: permit }
+    }
+
+    /// Marks a high priority read as in flight until the returned guard is dropped
+    pub(crate) fn begin_priority_read(&self) -> PriorityReadGuard {
+        self.priority_reads.begin()
+    }
+
+    /// Returns number of high priority reads in flight
+    pub fn priority_reads_in_flight(&self) -> usize {
+        self.priority_reads.in_flight()
+    }
+
+    /// Returns number of free slots for `job`
+    pub fn available_slots(&self, job: BackgroundJob) -> usize {
+        self.semaphore(job).available_permits()
+    }
+
+    /// Returns most recent panics of background tasks of stores using this `Env`, oldest first
+    pub fn background_errors(&self) -> Vec<BackgroundError> {
+        self.errors.list()
+    }
+
+    /// Returns true if both handles share the same limits
+    pub fn same_as(&self, other: &Env) -> bool {
+        Arc::ptr_eq(&self.flushes, &other.flushes)
+    }
+
+    fn semaphore(&self, job: BackgroundJob) -> &Arc<Semaphore> {
+        match job {
+            BackgroundJob::Flush => &self.flushes,
+            BackgroundJob::Compaction => &self.compactions,
+            BackgroundJob::GarbageCollection => &self.gc,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn test_acquire_respects_limit() {
+        let env = Env::new(1, 2, 1);
+        let first = env.acquire(BackgroundJob::Compaction).await;
+        let _second = env.acquire(BackgroundJob::Compaction).await;
+        assert_eq!(env.available_slots(BackgroundJob::Compaction), 0);
+
+        let blocked = timeout(Duration::from_millis(20), env.acquire(BackgroundJob::Compaction)).await;
+        assert!(blocked.is_err());
+
+        drop(first);
+        assert_eq!(env.available_slots(BackgroundJob::Compaction), 1);
+        let _third = env.acquire(BackgroundJob::Compaction).await;
+    }
+
+    #[tokio::test]
+    async fn test_jobs_have_separate_limits() {
+        let env = Env::new(1, 1, 1);
+        let _flush = env.acquire(BackgroundJob::Flush).await;
+        assert_eq!(env.available_slots(BackgroundJob::Flush), 0);
+        assert_eq!(env.available_slots(BackgroundJob::Compaction), 1);
+        assert_eq!(env.available_slots(BackgroundJob::GarbageCollection), 1);
+    }
+
+    #[tokio::test]
+    async fn test_clones_share_limits() {
+        let env = Env::new(2, 1, 1);
+        let shared = env.clone();
+        let _permit = shared.acquire(BackgroundJob::Flush).await;
+        assert_eq!(env.available_slots(BackgroundJob::Flush), 1);
+        assert!(env.same_as(&shared));
+        assert!(!env.same_as(&Env::default()));
+    }
+
+    #[tokio::test]
+    async fn test_acquire_waits_for_priority_reads() {
+        let env = Env::new(1, 1, 1);
+        let read = env.begin_priority_read();
+        assert_eq!(env.priority_reads_in_flight(), 1);
+
+        let blocked = timeout(
+            Duration::from_millis(20),
+            env.acquire(BackgroundJob::GarbageCollection),
+        )
+        .await;
+        assert!(blocked.is_err());
+
+        drop(read);
+        let _permit = env.acquire(BackgroundJob::GarbageCollection).await;
+        assert_eq!(env.priority_reads_in_flight(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "max_compactions should be greater than zero")]
+    fn test_new_invalid_limit() {
+        Env::new(1, 0, 1);
+    }
+}
@@ -0,0 +1,150 @@
+use super::BackgroundJob;
+use crate::consts::{MAX_BACKGROUND_ERRORS, SUPERVISOR_INITIAL_BACKOFF, SUPERVISOR_MAX_BACKOFF};
+use crate::types::CreatedAt;
+use chrono::Utc;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::task::JoinHandle;
+
+/// Panic of a background task, recorded before the task is restarted
+///
+/// Returned by [`Env::background_errors`](crate::db::Env::background_errors).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackgroundError {
+    /// Kind of job the task was running
+    pub job: BackgroundJob,
+
+    /// Panic message
+    pub message: String,
+
+    /// Time of the panic
+    pub occurred_at: CreatedAt,
+
+    /// Times the task was restarted before this panic
+    pub restarts: usize,
+}
+
+/// Most recent background errors, shared by clones
+#[derive(Clone, Debug, Default)]
+pub(crate) struct BackgroundErrors {
+    errors: Arc<Mutex<VecDeque<BackgroundError>>>,
+}
+
+impl BackgroundErrors {
+    /// Records `error`, the oldest error is dropped once `MAX_BACKGROUND_ERRORS` are kept
+    pub(crate) fn record(&self, error: BackgroundError) {
+        let mut errors = self.errors.lock().unwrap();
+        if errors.len() == MAX_BACKGROUND_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back(error);
+    }
+
+    /// Returns recorded errors, oldest first
+    pub(crate) fn list(&self) -> Vec<BackgroundError> {
+        self.errors.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Runs task created by `make_task` until it returns, restarting it if it panics
+///
+/// Each panic is logged and recorded in `errors`, then the task is created
+/// again after a backoff that doubles up to `SUPERVISOR_MAX_BACKOFF`. The
+/// backoff is reset once a task ran longer than that without panicking.
+pub(crate) fn supervise<F, Fut>(job: BackgroundJob, errors: BackgroundErrors, make_task: F) -> JoinHandle<()>
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut backoff = SUPERVISOR_INITIAL_BACKOFF;
+        let mut restarts = 0;
+        loop {
+            let started = Instant::now();
+            let err = match tokio::spawn(make_task()).await {
+                Ok(()) => return,
+                Err(err) if err.is_panic() => err,
+                // runtime is shutting down
+                Err(_) => return,
+            };
+            if started.elapsed() > SUPERVISOR_MAX_BACKOFF {
+                backoff = SUPERVISOR_INITIAL_BACKOFF;
+            }
+            let message = panic_message(err.into_panic());
+            log::error!(
+                "{:?} task panicked: {}, restarting in {:?}",
+                job,
+                message,
+                backoff
+            );
+            errors.record(BackgroundError {
+                job,
+                message,
+                occurred_at: Utc::now(),
+                restarts,
+            });
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(SUPERVISOR_MAX_BACKOFF);
+            restarts += 1;
+        }
+    })
+}
+
+/// Returns message a task panicked with
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return message.to_string();
+    }
+    if let Some(message) = payload.downcast_ref::<String>() {
+        return message.to_owned();
+    }
+    String::from("unknown panic")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_supervise_restarts_panicking_task() {
+        let errors = BackgroundErrors::default();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let task_runs = runs.clone();
+        let handle = supervise(BackgroundJob::Compaction, errors.clone(), move || {
+            let runs = task_runs.clone();
+            async move {
+                if runs.fetch_add(1, Ordering::SeqCst) < 2 {
+                    panic!("compaction bug");
+                }
+            }
+        });
+        handle.await.unwrap();
+
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        let recorded = errors.list();
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].job, BackgroundJob::Compaction);
+        assert_eq!(recorded[0].message, "compaction bug");
+        assert_eq!(recorded[1].restarts, 1);
+    }
+
+    #[test]
+    fn test_background_errors_are_bounded() {
+        let errors = BackgroundErrors::default();
+        for restarts in 0..MAX_BACKGROUND_ERRORS + 1 {
+            errors.record(BackgroundError {
+                job: BackgroundJob::Flush,
+                message: String::new(),
+                occurred_at: Utc::now(),
+                restarts,
+            });
+        }
+        let recorded = errors.list();
+        assert_eq!(recorded.len(), MAX_BACKGROUND_ERRORS);
+        assert_eq!(recorded[0].restarts, 1);
+    }
+}
@@ -0,0 +1,130 @@
+use rand::Rng;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::Notify,
+    time::{sleep_until, Instant},
+};
+
+/// Ticks of a background loop
+///
+/// Every delay is the interval moved by up to `jitter` times the interval
+/// either way, so loops of stores opened together drift apart instead of
+/// hitting the disk at the same moment. The interval can be changed while a
+/// loop waits, the wait is then measured against the new interval.
+///
+/// Clones share the interval.
+#[derive(Clone, Debug)]
+pub(crate) struct Timer {
+    /// Interval in nanoseconds
+    interval: Arc<AtomicU64>,
+
+    /// Wakes waiting loops when the interval changes
+    changed: Arc<Notify>,
+
+    /// Fraction of the interval a delay may differ by, between 0 and 1
+    jitter: f64,
+}
+
+impl Timer {
+    /// Creates `Timer` ticking every `interval`, without jitter
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self {
+            interval: Arc::new(AtomicU64::new(as_nanos(interval))),
+            changed: Arc::new(Notify::new()),
+            jitter: 0.0,
+        }
+    }
+
+    /// Moves every delay by up to `jitter` times the interval, clamped between 0 and 1
+    pub(crate) fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = if jitter.is_nan() {
+            0.0
+        } else {
+            jitter.clamp(0.0, 1.0)
+        };
+        self
+    }
+
+    /// Returns current interval
+    pub(crate) fn interval(&self) -> Duration {
+        Duration::from_nanos(self.interval.load(Ordering::Relaxed))
+    }
+
+    /// Changes interval of this timer and its clones, waking loops waiting on them
+    pub(crate) fn set_interval(&self, interval: Duration) {
+        self.interval.store(as_nanos(interval), Ordering::Relaxed);
+        self.changed.notify_waiters();
+    }
+
+    /// Returns delay before the next tick, with jitter applied
+    pub(crate) fn next_delay(&self) -> Duration {
+        let interval = self.interval();
+        if self.jitter == 0.0 || interval.is_zero() {
+            return interval;
+        }
+        let spread = interval.mul_f64(self.jitter);
+        let offset = spread.mul_f64(2.0 * rand::thread_rng().gen::<f64>());
+        (interval - spread).saturating_add(offset)
+    }
+
+    /// Waits for the next tick
+    pub(crate) async fn tick(&self) {
+        let start = Instant::now();
+        loop {
+            // registered before the deadline is computed, so no change is missed
+            let changed = self.changed.notified();
+            let deadline = start.checked_add(self.next_delay()).unwrap_or_else(far_future);
+            tokio::select! {
+                _ = sleep_until(deadline) => return,
+                _ = changed => {}
+            }
+        }
+    }
+}
+
+fn as_nanos(interval: Duration) -> u64 {
+    interval.as_nanos().try_into().unwrap_or(u64::MAX)
+}
+
+/// Returns an instant no timer reaches, about 30 years from now as tokio does
+fn far_future() -> Instant {
+    Instant::now() + Duration::from_secs(86400 * 365 * 30)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::timeout;
+
+    #[test]
+    fn test_next_delay_within_jitter() {
+        let timer = Timer::new(Duration::from_secs(10)).with_jitter(0.2);
+        for _ in 0..100 {
+            let delay = timer.next_delay();
+            assert!(delay >= Duration::from_secs(8) && delay <= Duration::from_secs(12));
+        }
+        let timer = Timer::new(Duration::from_secs(10)).with_jitter(5.0);
+        assert!(timer.next_delay() <= Duration::from_secs(20));
+        assert_eq!(
+            Timer::new(Duration::from_secs(10)).next_delay(),
+            Duration::from_secs(10)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_interval_wakes_waiting_tick() {
+        let timer = Timer::new(Duration::from_secs(3600));
+        let waiting = timer.clone();
+        let tick = tokio::spawn(async move { waiting.tick().await });
+        tokio::task::yield_now().await;
+        timer.set_interval(Duration::from_millis(1));
+        assert_eq!(timer.interval(), Duration::from_millis(1));
+        assert!(timeout(Duration::from_secs(5), tick).await.is_ok());
+    }
+}
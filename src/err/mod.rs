@@ -1,20 +1,53 @@
-use std::{io, path::PathBuf};
+use crate::types::{CreatedAt, Key};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
 use thiserror::Error;
 
+/// Category of an [`Error`], returned by [`Error::kind`]
+///
+/// Lets applications decide how to react to an error without matching every
+/// variant, e.g. alert on `Corruption` and back off on `Busy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// File system operation failed, [`Error::path`] returns the file it failed on
+    Io,
+
+    /// Data read from disk is damaged or inconsistent
+    Corruption,
+
+    /// Request was rejected because of its arguments, e.g. a key too large
+    InvalidArgument,
+
+    /// Key or file looked up does not exist
+    NotFound,
+
+    /// Store could not take the request now, e.g. a channel was full
+    Busy,
+
+    /// Operation is not supported on this platform or by this build
+    Unsupported,
+
+    /// Store reached a state it should never be in
+    Internal,
+}
+
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum Error {
-    #[error("Failed to sync writes to file")]
-    FileSync(#[source] io::Error),
+    #[error("Failed to sync writes to file `{path}`: {error}")]
+    FileSync { path: PathBuf, error: io::Error },
 
     #[error("Failed to create file: `{path}`: {error}")]
     FileCreation { path: PathBuf, error: io::Error },
 
-    #[error("File seek error")]
-    FileSeek(#[source] io::Error),
+    #[error("Failed to seek in file `{path}`: {error}")]
+    FileSeek { path: PathBuf, error: io::Error },
 
-    #[error("Directory deletion error")]
-    DirDelete(#[source] io::Error),
+    #[error("Failed to delete directory `{path}`: {error}")]
+    DirDelete { path: PathBuf, error: io::Error },
 
     #[error("Filter file path not provided")]
     FilterFilePathNotProvided,
@@ -22,19 +55,25 @@ pub enum Error {
     #[error("Filter file open error: path `{0}`")]
     FilterFileOpen(PathBuf),
 
-    #[error("File deletion error")]
-    FileDelete(#[source] io::Error),
+    #[error("Filter file `{0}` is corrupt")]
+    FilterFileCorrupt(PathBuf),
 
-    #[error("Failed to open file")]
+    #[error("Summary file `{0}` is corrupt")]
+    SummaryFileCorrupt(PathBuf),
+
+    #[error("Failed to delete file `{path}`: {error}")]
+    FileDelete { path: PathBuf, error: io::Error },
+
+    #[error("Failed to open file `{path}`: {error}")]
     FileOpen { path: PathBuf, error: io::Error },
 
-    #[error("Failed to get file metadata")]
-    GetFileMetaData(#[source] std::io::Error),
+    #[error("Failed to get metadata of file `{path}`: {error}")]
+    GetFileMetaData { path: PathBuf, error: io::Error },
 
-    #[error("Failed to check if file path exist")]
-    TryFilePathExist(#[source] std::io::Error),
+    #[error("Failed to check if file path `{path}` exist: {error}")]
+    TryFilePathExist { path: PathBuf, error: io::Error },
 
-    #[error("Failed to create directory")]
+    #[error("Failed to create directory `{path}`: {error}")]
     DirCreation { path: PathBuf, error: io::Error },
 
     #[error("Failed to clear file: `{path}`: {error}")]
@@ -46,9 +85,21 @@ pub enum Error {
     #[error("Failed to write to file `{path}`: {error}")]
     FileWrite { path: PathBuf, error: io::Error },
 
+    #[error("Failed to preallocate space for file `{path}`: {error}")]
+    FilePreallocate { path: PathBuf, error: io::Error },
+
+    #[error("Failed to read file system statistics of `{path}`: {error}")]
+    FileSystemStat { path: PathBuf, error: io::Error },
+
     #[error("Failed to open directory `{path}`: {error}")]
     DirOpen { path: PathBuf, error: io::Error },
 
+    #[error("Failed to sync directory `{path}`: {error}")]
+    DirSync { path: PathBuf, error: io::Error },
+
+    #[error("Failed to rename file to `{path}`: {error}")]
+    FileRename { path: PathBuf, error: io::Error },
+
     #[error("File read ended unexpectedly")]
     UnexpectedEOF(#[source] io::Error),
 
@@ -118,8 +169,8 @@ pub enum Error {
     #[error("Filter not provided, needed to flush table to disk")]
     FilterNotProvidedForFlush,
 
-    #[error("Key size too large, key must not exceed 65536 bytes")]
-    KeyMaxSizeExceeded,
+    #[error("Key too large, key is {size} bytes but must not exceed {max} bytes")]
+    KeyTooLarge { size: usize, max: usize },
 
     #[error("Key cannot be empty")]
     KeySizeNone,
@@ -127,8 +178,32 @@ pub enum Error {
     #[error("Value cannot be empty")]
     ValueSizeNone,
 
-    #[error("Value too large, value must not exceed 2^32 bytes")]
-    ValMaxSizeExceeded,
+    #[error("Value too large, value is {size} bytes but must not exceed {max} bytes")]
+    ValueTooLarge { size: usize, max: usize },
+
+    #[error("Metadata too large, metadata is {size} bytes but must not exceed {max} bytes")]
+    MetadataTooLarge { size: usize, max: usize },
+
+    #[error("Key already exists")]
+    KeyAlreadyExists,
+
+    #[error("Timestamp `{timestamp}` is not newer than `{latest}` of the stored version")]
+    TimestampRegression { timestamp: CreatedAt, latest: CreatedAt },
+
+    #[error("Timestamp `{timestamp}` is in the future")]
+    TimestampInFuture { timestamp: CreatedAt },
+
+    #[error("TTL `{0:?}` must be greater than zero and fit a timestamp")]
+    InvalidTtl(std::time::Duration),
+
+    #[error("Continuation token is invalid")]
+    InvalidContinuationToken,
+
+    #[error("Key is outside of the view")]
+    KeyOutsideView,
+
+    #[error("Value is not valid UTF-8")]
+    InvalidUtf8(#[source] std::string::FromUtf8Error),
 
     #[error("Filter not found")]
     FilterNotFound,
@@ -148,12 +223,27 @@ pub enum Error {
     #[error("Failed to insert to a bucket, reason `{0}`")]
     FailedToInsertToBucket(String),
 
-    #[error("Error punching hole in file, reason `{0}`")]
-    GCErrorFailedToPunchHoleInVlogFile(io::Error),
+    #[error("Error punching hole in file `{path}`, reason `{error}`")]
+    GCErrorFailedToPunchHoleInVlogFile { path: PathBuf, error: io::Error },
 
     #[error("Unsuported OS for garbage collection, err message `{0}`")]
     GCErrorUnsupportedPlatform(String),
 
+    #[error("Compaction journal `{path}` is corrupt: {error}")]
+    CompactionJournalCorrupt { path: PathBuf, error: serde_json::Error },
+
+    #[error("Compaction journal `{path}` of an unfinished compaction is resolved at next open, no compaction runs until then")]
+    CompactionJournalPending { path: PathBuf },
+
+    #[error("Garbage collection journal `{path}` is corrupt: {error}")]
+    GCErrorJournalCorrupt { path: PathBuf, error: serde_json::Error },
+
+    #[error("Garbage collection punch marker `{path}` is corrupt: {error}")]
+    GCErrorPunchMarkerCorrupt { path: PathBuf, error: serde_json::Error },
+
+    #[error("Bucket manifest `{path}` is corrupt: {error}")]
+    BucketManifestCorrupt { path: PathBuf, error: serde_json::Error },
+
     #[error("Range scan error `{0}`")]
     RangeScan(Box<Self>),
 
@@ -186,6 +276,343 @@ pub enum Error {
     #[error("Tokio join tasks error")]
     TokioJoin,
 
+    #[error("Failed to start read pool: {0}")]
+    ReadPoolStart(#[source] io::Error),
+
     #[error("Entries cannot be empty during flush")]
     EntriesCannotBeEmptyDuringFlush,
+
+    #[error("Value log entry at offset `{offset}` does not match its checksum")]
+    ChecksumMismatch { offset: usize },
+
+    #[error("Value log entry at offset `{offset}` belongs to a different key")]
+    EntryKeyMismatch { offset: usize },
+
+    #[error("Value log entry at offset `{offset}` references a value that is not stored")]
+    BlobNotFound { offset: usize },
+
+    #[error("Write-ahead log entry at offset `{offset}` is missing")]
+    WalEntryNotFound { offset: usize },
+
+    #[error("Store at `{path}` uses format version {found}, this build supports up to {supported}")]
+    IncompatibleFormat {
+        path: PathBuf,
+        found: u32,
+        supported: u32,
+    },
+
+    #[error("Store info file `{path}` is corrupt: {error}")]
+    StoreInfoCorrupt { path: PathBuf, error: serde_json::Error },
+
+    #[error("Write to keyspace `{keyspace}` exceeds its quota of {limit} {unit}")]
+    QuotaExceeded {
+        keyspace: String,
+        limit: usize,
+        unit: &'static str,
+    },
+
+    #[error("Waiting for the lock on key `{key:?}` would deadlock the transaction")]
+    Deadlock { key: Key },
+
+    #[error("Lock on key `{key:?}` not acquired within {timeout:?}")]
+    LockTimeout { key: Key, timeout: std::time::Duration },
+
+    #[error("Export directory `{0}` is not empty")]
+    ExportDirNotEmpty(PathBuf),
+
+    #[error("Cannot migrate store from format version {from} to {to}")]
+    UnsupportedMigration { from: u32, to: u32 },
+}
+
+impl Error {
+    /// Returns category of the error, errors wrapping another one take its category
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::{DataStore, ErrorKind};
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let mut store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+    ///
+    ///     let err = store.put("", "empty key").await.unwrap_err();
+    ///     assert_eq!(err.kind(), ErrorKind::InvalidArgument);
+    ///     assert!(!err.is_retryable());
+    /// }
+    /// ```
+    pub fn kind(&self) -> ErrorKind {
+        use Error::*;
+        match self {
+            FlushToDisk { error }
+            | MemTableRecovery(error)
+            | CompactionFailed(error)
+            | CompactionPartiallyFailed(error)
+            | KeyNotFound(error)
+            | RangeScan(error)
+            | CompactionCleanup(error) => error.kind(),
+
+            FileSync { .. }
+            | FileCreation { .. }
+            | FileSeek { .. }
+            | DirDelete { .. }
+            | FileDelete { .. }
+            | FileOpen { .. }
+            | GetFileMetaData { .. }
+            | TryFilePathExist { .. }
+            | DirCreation { .. }
+            | FileClear { .. }
+            | FileRead { .. }
+            | FileWrite { .. }
+            | FilePreallocate { .. }
+            | FileSystemStat { .. }
+            | DirOpen { .. }
+            | DirSync { .. }
+            | FileRename { .. }
+            | FilterFileOpen(_)
+            | GCErrorFailedToPunchHoleInVlogFile { .. }
+            | ReadPoolStart(_)
+            | CompactionCleanupPartial => ErrorKind::Io,
+
+            FilterFileCorrupt(_)
+            | SummaryFileCorrupt(_)
+            | UnexpectedEOF(_)
+            | InvaidUUIDParseString { .. }
+            | InvalidSSTableDirectory { .. }
+            | CompactionJournalCorrupt { .. }
+            | GCErrorJournalCorrupt { .. }
+            | GCErrorPunchMarkerCorrupt { .. }
+            | BucketManifestCorrupt { .. }
+            | ChecksumMismatch { .. }
+            | EntryKeyMismatch { .. }
+            | BlobNotFound { .. }
+            | WalEntryNotFound { .. }
+            | StoreInfoCorrupt { .. } => ErrorKind::Corruption,
+
+            KeyTooLarge { .. }
+            | KeySizeNone
+            | ValueSizeNone
+            | ValueTooLarge { .. }
+            | MetadataTooLarge { .. }
+            | KeyAlreadyExists
+            | TimestampRegression { .. }
+            | TimestampInFuture { .. }
+            | InvalidTtl(_)
+            | InvalidContinuationToken
+            | KeyOutsideView
+            | InvalidUtf8(_)
+            | QuotaExceeded { .. }
+            | ExportDirNotEmpty(_) => ErrorKind::InvalidArgument,
+
+            KeyNotFoundInAnySSTable
+            | KeyFoundAsTombstoneInSSTable
+            | KeyFoundAsTombstoneInMemtable
+            | KeyFoundAsTombstoneInValueLog
+            | KeyNotFoundInMemTable
+            | KeyNotFoundInValueLog
+            | NotFoundInDB
+            | KeyNotFoundByAnyBloomFilter
+            | FilterNotFound => ErrorKind::NotFound,
+
+            GCErrorAttemptToRemoveUnsyncedEntries
+            | FlushSignalChannelOverflow
+            | GCUpdateChannelOverflow
+            | Deadlock { .. }
+            | LockTimeout { .. } => ErrorKind::Busy,
+
+            GCErrorUnsupportedPlatform(_) | IncompatibleFormat { .. } | UnsupportedMigration { .. } => {
+                ErrorKind::Unsupported
+            }
+
+            FilterFilePathNotProvided
+            | ConditionsToInsertToBucketNotMet
+            | InsertToMemTableFailed { .. }
+            | TombStoneCheckFailed(_)
+            | BlockIsFull
+            | FilterNotProvidedForFlush
+            | BiggestKeyIndex
+            | LowestKeyIndex
+            | TableSummaryIsNone
+            | FailedToInsertToBucket(_)
+            | FlushSignalChannelClosed
+            | Serialization(_)
+            | CannotRemoveObsoleteSST
+            | CompactionJournalPending { .. }
+            | MergeSSTContainsZeroEntries
+            | TokioJoin
+            | EntriesCannotBeEmptyDuringFlush => ErrorKind::Internal,
+        }
+    }
+
+    /// Returns true if the operation that failed may succeed if it is run again unchanged
+    ///
+    /// `Busy` errors are retryable, and IO errors that were interrupted, would
+    /// have blocked or timed out. A failed sync is not, the writes it should
+    /// have made durable may be lost and a later sync can succeed without them.
+    pub fn is_retryable(&self) -> bool {
+        use Error::*;
+        match self {
+            FlushToDisk { error }
+            | MemTableRecovery(error)
+            | CompactionFailed(error)
+            | CompactionPartiallyFailed(error)
+            | KeyNotFound(error)
+            | RangeScan(error)
+            | CompactionCleanup(error) => error.is_retryable(),
+            FileSync { .. } | DirSync { .. } => false,
+            _ => match self.kind() {
+                ErrorKind::Busy => true,
+                ErrorKind::Io => self.io_error().is_some_and(|error| {
+                    matches!(
+                        error.kind(),
+                        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    )
+                }),
+                _ => false,
+            },
+        }
+    }
+
+    /// Returns file or directory the error occured on, if it concerns one
+    pub fn path(&self) -> Option<&Path> {
+        use Error::*;
+        match self {
+            FlushToDisk { error }
+            | MemTableRecovery(error)
+            | CompactionFailed(error)
+            | CompactionPartiallyFailed(error)
+            | KeyNotFound(error)
+            | RangeScan(error)
+            | CompactionCleanup(error) => error.path(),
+            FileSync { path, .. }
+            | FileCreation { path, .. }
+            | FileSeek { path, .. }
+            | DirDelete { path, .. }
+            | FileDelete { path, .. }
+            | FileOpen { path, .. }
+            | GetFileMetaData { path, .. }
+            | TryFilePathExist { path, .. }
+            | DirCreation { path, .. }
+            | FileClear { path, .. }
+            | FileRead { path, .. }
+            | FileWrite { path, .. }
+            | FilePreallocate { path, .. }
+            | FileSystemStat { path, .. }
+            | DirOpen { path, .. }
+            | DirSync { path, .. }
+            | FileRename { path, .. }
+            | GCErrorFailedToPunchHoleInVlogFile { path, .. }
+            | CompactionJournalCorrupt { path, .. }
+            | CompactionJournalPending { path }
+            | GCErrorJournalCorrupt { path, .. }
+            | GCErrorPunchMarkerCorrupt { path, .. }
+            | BucketManifestCorrupt { path, .. }
+            | IncompatibleFormat { path, .. }
+            | StoreInfoCorrupt { path, .. } => Some(path),
+            FilterFileOpen(path)
+            | FilterFileCorrupt(path)
+            | SummaryFileCorrupt(path)
+            | ExportDirNotEmpty(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Returns IO error the error was caused by, if any
+    pub fn io_error(&self) -> Option<&io::Error> {
+        use Error::*;
+        match self {
+            FlushToDisk { error }
+            | MemTableRecovery(error)
+            | CompactionFailed(error)
+            | CompactionPartiallyFailed(error)
+            | KeyNotFound(error)
+            | RangeScan(error)
+            | CompactionCleanup(error) => error.io_error(),
+            FileSync { error, .. }
+            | FileCreation { error, .. }
+            | FileSeek { error, .. }
+            | DirDelete { error, .. }
+            | FileDelete { error, .. }
+            | FileOpen { error, .. }
+            | GetFileMetaData { error, .. }
+            | TryFilePathExist { error, .. }
+            | DirCreation { error, .. }
+            | FileClear { error, .. }
+            | FileRead { error, .. }
+            | FileWrite { error, .. }
+            | FilePreallocate { error, .. }
+            | FileSystemStat { error, .. }
+            | DirOpen { error, .. }
+            | DirSync { error, .. }
+            | FileRename { error, .. }
+            | GCErrorFailedToPunchHoleInVlogFile { error, .. }
+            | ReadPoolStart(error)
+            | UnexpectedEOF(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn io_error(kind: io::ErrorKind) -> io::Error {
+        io::Error::new(kind, "test")
+    }
+
+    #[test]
+    fn test_kind_and_retryability() {
+        let path = PathBuf::from("v_log/val_log.bin");
+        let timed_out = Error::FileWrite {
+            path: path.to_owned(),
+            error: io_error(io::ErrorKind::TimedOut),
+        };
+        assert_eq!(timed_out.kind(), ErrorKind::Io);
+        assert!(timed_out.is_retryable());
+        assert_eq!(timed_out.path(), Some(path.as_path()));
+
+        let denied = Error::FileWrite {
+            path: path.to_owned(),
+            error: io_error(io::ErrorKind::PermissionDenied),
+        };
+        assert!(!denied.is_retryable());
+
+        // writes a failed sync should have made durable may be gone
+        let sync = Error::FileSync {
+            path: path.to_owned(),
+            error: io_error(io::ErrorKind::Interrupted),
+        };
+        assert_eq!(sync.kind(), ErrorKind::Io);
+        assert!(!sync.is_retryable());
+
+        assert_eq!(
+            Error::ChecksumMismatch { offset: 0 }.kind(),
+            ErrorKind::Corruption
+        );
+        assert_eq!(Error::KeySizeNone.kind(), ErrorKind::InvalidArgument);
+        assert!(Error::FlushSignalChannelOverflow.is_retryable());
+        assert_eq!(
+            Error::UnsupportedMigration { from: 2, to: 1 }.kind(),
+            ErrorKind::Unsupported
+        );
+        assert_eq!(Error::KeySizeNone.path(), None);
+    }
+
+    #[test]
+    fn test_wrapped_error_takes_kind_of_its_cause() {
+        let path = PathBuf::from("buckets/bucket_manifest.json");
+        let err = Error::CompactionFailed(Box::new(Error::FlushToDisk {
+            error: Box::new(Error::FileRead {
+                path: path.to_owned(),
+                error: io_error(io::ErrorKind::Interrupted),
+            }),
+        }));
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert!(err.is_retryable());
+        assert_eq!(err.path(), Some(path.as_path()));
+        assert_eq!(err.io_error().unwrap().kind(), io::ErrorKind::Interrupted);
+    }
 }
@@ -3,7 +3,7 @@ use crate::types::ByteSerializedEntry;
 use crate::types::Key;
 use crate::types::SkipMapEntries;
 use crate::{
-    consts::{FILTER_FILE_NAME, SIZE_OF_U32, SIZE_OF_U64},
+    consts::{FILTER_FILE_NAME, FILTER_META_SIZE, SIZE_OF_U32},
     err::Error,
     fs::{FileAsync, FilterFileNode, FilterFs},
     util,
@@ -103,19 +103,17 @@ impl BloomFilter {
         }
         true
     }
-    /// Writes filter metadata to disk
+    /// Writes filter to disk
     ///
-    /// Writes filter to disk, note, this does not include the
-    /// `bit_vec``, bit vec re-computed during crash recovery
+    /// Metadata is followed by `bit_vec`, so evicted filters can be
+    /// reloaded without reading sstable entries
     ///
     /// # Errors
     ///
     /// Returns IO error in case write fails
     pub async fn write(&mut self, dir: impl AsRef<Path> + Send + Sync) -> Result<(), Error> {
         let file_path = dir.as_ref().join(format!("{}.db", FILTER_FILE_NAME));
-        let file = FilterFileNode::new(file_path.to_owned(), crate::fs::FileType::Filter)
-            .await
-            .unwrap();
+        let file = FilterFileNode::new(file_path.to_owned(), crate::fs::FileType::Filter).await?;
         let serialized_data = self.serialize();
         file.node.write_all(&serialized_data).await?;
         self.file_path = Some(file_path.to_owned());
@@ -127,6 +125,24 @@ impl BloomFilter {
         entries.iter().for_each(|e| self.set(e.key()));
     }
 
+    /// Returns filter with `entries` inserted, built on the blocking
+    /// thread pool if `offload` is set
+    ///
+    /// # Errors
+    ///
+    /// Returns error if building the filter panicked
+    pub(crate) async fn build_from_entries(
+        mut self,
+        entries: SkipMapEntries<Key>,
+        offload: bool,
+    ) -> Result<Self, Error> {
+        util::run_cpu_bound(offload, move || {
+            self.build_filter_from_entries(&entries);
+            self
+        })
+        .await
+    }
+
     /// Retrieves filter meta data from disk
     ///
     /// # Errors
@@ -149,15 +165,46 @@ impl BloomFilter {
         Ok(())
     }
 
-    /// Serializes `BloomFilter` attributes
+    /// Loads filter from disk, including `bit_vec` if the filter file has it
+    ///
+    /// Returns false for filter files written by older versions, their
+    /// `bit_vec` has to be rebuilt from sstable entries
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case filter file is missing or corrupt
+    pub(crate) async fn load(&mut self) -> Result<bool, Error> {
+        self.recover_meta().await?;
+        match FilterFileNode::recover_bits(self.file_path.as_ref().unwrap()).await? {
+            Some(bits) => {
+                self.bit_vec = Arc::new(Mutex::new(bits));
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Returns copy of filter without `bit_vec`, `load` restores it from disk
+    pub(crate) fn without_bits(&self) -> Self {
+        Self {
+            sst_dir: None,
+            bit_vec: Arc::new(Mutex::new(BitVec::new())),
+            ..self.clone()
+        }
+    }
+
+    /// Serializes `BloomFilter`
     ///
     /// Converts `BloomFilter` atttributes such as no_of_hash_func, no_of_elements and
-    /// false positive floating point into byte vector
+    /// false positive floating point into byte vector, followed by their checksum,
+    /// then appends number of bits, `bit_vec` bytes and their checksum
     ///
     /// Returns the byte vector
-    fn serialize(&self) -> ByteSerializedEntry {
-        // No of Hash Function + No of Elements  + False Positive
-        let entry_len = SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64;
+    pub(crate) fn serialize(&self) -> ByteSerializedEntry {
+        let bits = self.bit_vec.lock().expect("Failed to lock file");
+        let bytes = bits.to_bytes();
+        // Metadata + No of Bits + Bits + Checksum
+        let entry_len = FILTER_META_SIZE + SIZE_OF_U32 + bytes.len() + SIZE_OF_U32;
 
         let mut serialized_data = Vec::with_capacity(entry_len);
 
@@ -168,6 +215,13 @@ impl BloomFilter {
 
         serialized_data.extend_from_slice(&util::float_to_le_bytes(self.false_positive_rate));
 
+        let checksum = util::crc32(&[&serialized_data]);
+        serialized_data.extend_from_slice(&checksum.to_le_bytes());
+
+        serialized_data.extend_from_slice(&(bits.len() as u32).to_le_bytes());
+        serialized_data.extend_from_slice(&bytes);
+        serialized_data.extend_from_slice(&util::crc32(&[&bytes]).to_le_bytes());
+
         serialized_data
     }
 
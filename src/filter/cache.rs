@@ -0,0 +1,78 @@
+use super::BloomFilter;
+use crate::{
+    cache::{CacheStats, LruCache},
+    consts::DEFAULT_FILTER_CACHE_CAPACITY,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Cache for bloom filters of SSTables
+///
+/// Filters are loaded from their filter files on demand, filters of tables
+/// that were not read recently are evicted once the cache exceeds its budget.
+///
+/// Cloning a `FilterCache` is cheap, clones share the same entries and
+/// memory budget, so one instance can be passed to the [`Config`](crate::db::Config)
+/// of several stores.
+#[derive(Clone, Debug)]
+pub struct FilterCache {
+    inner: Arc<LruCache<PathBuf, BloomFilter>>,
+}
+
+impl Default for FilterCache {
+    fn default() -> Self {
+        FilterCache::new(DEFAULT_FILTER_CACHE_CAPACITY)
+    }
+}
+
+impl FilterCache {
+    /// Creates new `FilterCache` that holds at most `capacity` bytes
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Returns cached filter of SSTable in `sst_dir`
+    pub(crate) fn get(&self, sst_dir: impl AsRef<Path>) -> Option<BloomFilter> {
+        self.inner.get(&sst_dir.as_ref().to_path_buf())
+    }
+
+    /// Caches filter of SSTable in `sst_dir`, charged by the size of its bit vector
+    pub(crate) fn insert(&self, sst_dir: impl AsRef<Path>, filter: BloomFilter) {
+        let charge = filter.num_bits().div_ceil(8);
+        self.inner.insert(sst_dir.as_ref().to_path_buf(), filter, charge);
+    }
+
+    /// Drops cached filter of SSTable in `sst_dir`
+    pub(crate) fn remove(&self, sst_dir: impl AsRef<Path>) {
+        self.inner.remove(&sst_dir.as_ref().to_path_buf());
+    }
+
+    /// Returns bytes currently held by the cache
+    pub fn usage(&self) -> usize {
+        self.inner.usage()
+    }
+
+    /// Returns maximum bytes the cache can hold
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    /// Changes maximum bytes the cache can hold, evicting least recently used filters that no longer fit
+    pub fn set_capacity(&self, capacity: usize) {
+        self.inner.set_capacity(capacity)
+    }
+
+    /// Returns hit, miss and eviction counts and size of the cache
+    pub fn stats(&self) -> CacheStats {
+        self.inner.stats()
+    }
+
+    /// Returns true if both handles share the same cache
+    pub fn same_as(&self, other: &FilterCache) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
@@ -1,5 +1,11 @@
 mod bf;
+mod cache;
+pub(crate) mod monkey;
+mod stats;
 pub use bf::BloomFilter;
 pub use bf::FalsePositive;
 pub use bf::NoHashFunc;
 pub use bf::NoOfElements;
+pub use cache::FilterCache;
+pub(crate) use stats::FilterCounters;
+pub use stats::FilterStats;
@@ -0,0 +1,83 @@
+//! # Monkey filter allocation
+//!
+//! With size tiered compaction a lookup may probe the filter of every sstable, so the
+//! expected number of wasted reads is the sum of their false positive rates. For a fixed
+//! number of filter bits, that sum is smallest when each table's rate is proportional to
+//! its number of entries (Dayan et al., "Monkey: Optimal Navigable Key-Value Store").
+//! Small, recently flushed tables therefore get more bits per key than large compacted ones.
+//!
+//! A filter with rate `p` costs `-ln(p) / ln(2)^2` bits per key. Solving for rates `p_i = c * n_i`
+//! whose filters exactly fill a budget of `m` bits gives
+//!
+//! ```text
+//! ln(c) = -(m * ln(2)^2 + sum(n_i * ln(n_i))) / sum(n_i)
+//! ```
+
+use crate::consts::{MAX_MONKEY_FALSE_POSITIVE_RATE, MIN_MONKEY_FALSE_POSITIVE_RATE};
+
+/// Returns false positive rate for a new sstable with `entries` entries
+///
+/// `tables` holds entry counts of the other sstables that share `budget_bytes`
+/// of filter memory. The rate is kept between `MIN_MONKEY_FALSE_POSITIVE_RATE`
+/// and `MAX_MONKEY_FALSE_POSITIVE_RATE`.
+pub(crate) fn false_positive_rate(tables: &[usize], entries: usize, budget_bytes: usize) -> f64 {
+    let entries = entries.max(1) as f64;
+    let (total, weighted_log) = tables
+        .iter()
+        .map(|n| (*n).max(1) as f64)
+        .chain(std::iter::once(entries))
+        .fold((0.0, 0.0), |(total, weighted_log), n| {
+            (total + n, weighted_log + n * n.ln())
+        });
+    let budget_bits = budget_bytes as f64 * 8.0;
+    let ln_c = -(budget_bits * 2_f64.ln().powi(2) + weighted_log) / total;
+    (ln_c + entries.ln())
+        .exp()
+        .clamp(MIN_MONKEY_FALSE_POSITIVE_RATE, MAX_MONKEY_FALSE_POSITIVE_RATE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(rate: f64, entries: usize) -> f64 {
+        -(entries as f64) * rate.ln() / 2_f64.ln().powi(2)
+    }
+
+    #[test]
+    fn test_rate_grows_with_table_size() {
+        let budget = 64 * 1024;
+        let small = false_positive_rate(&[10_000, 100_000], 1_000, budget);
+        let big = false_positive_rate(&[1_000, 10_000], 100_000, budget);
+        assert!(small < big);
+        assert!((big / small - 100.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_rates_fill_budget() {
+        let tables = [2_000, 20_000, 200_000];
+        let budget = 256 * 1024;
+        let used: f64 = tables
+            .iter()
+            .enumerate()
+            .map(|(i, n)| {
+                let mut others = tables.to_vec();
+                others.remove(i);
+                bits(false_positive_rate(&others, *n, budget), *n)
+            })
+            .sum();
+        assert!((used - budget as f64 * 8.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_rate_is_clamped() {
+        assert_eq!(
+            false_positive_rate(&[], 1_000_000, 1),
+            MAX_MONKEY_FALSE_POSITIVE_RATE
+        );
+        assert_eq!(
+            false_positive_rate(&[], 10, 1024 * 1024 * 1024),
+            MIN_MONKEY_FALSE_POSITIVE_RATE
+        );
+    }
+}
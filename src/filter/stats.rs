@@ -0,0 +1,66 @@
+use crate::{db::DataStore, memtable::Val, types::Key};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// Bloom filter checks made by point reads, returned by [`DataStore::filter_stats`]
+///
+/// Counters are cumulative since the store was opened.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FilterStats {
+    /// SSTables whose filter was checked because the key is within their key range
+    pub checks: u64,
+
+    /// Checks the filter answered negatively, each saved an index and a data block read
+    pub negatives: u64,
+}
+
+/// Counters behind [`FilterStats`], shared by clones
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FilterCounters {
+    checks: Arc<AtomicU64>,
+    negatives: Arc<AtomicU64>,
+}
+
+impl FilterCounters {
+    /// Records a filter check and whether the filter answered negatively
+    pub(crate) fn record(&self, negative: bool) {
+        self.checks.fetch_add(1, Ordering::Relaxed);
+        if negative {
+            self.negatives.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns current values of the counters
+    pub(crate) fn stats(&self) -> FilterStats {
+        FilterStats {
+            checks: self.checks.load(Ordering::Relaxed),
+            negatives: self.negatives.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Returns how often bloom filters spared reading an SSTable on point reads
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+    ///
+    ///     // no sstable yet, no filter was checked
+    ///     assert!(store.get("apple").await.unwrap().is_none());
+    ///     assert_eq!(store.filter_stats().checks, 0);
+    /// }
+    /// ```
+    pub fn filter_stats(&self) -> FilterStats {
+        self.key_range.filter_counters.stats()
+    }
+}
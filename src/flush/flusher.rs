@@ -1,20 +1,153 @@
-use crate::consts::FLUSH_SIGNAL;
+use crate::db::context;
+use crate::env::{supervise, BackgroundJob, Env};
+use crate::filter::{monkey, BloomFilter};
 use crate::flush::flusher::Error::FilterNotProvidedForFlush;
 use crate::flush::flusher::Error::TableSummaryIsNone;
-use crate::types::{self, BucketMapHandle, FlushSignal, ImmutableMemTables, KeyRangeHandle};
+use crate::types::{
+    self, BucketMapHandle, ImmutableMemTables, KeyRangeHandle, MemtableFlushStream, MemtableId,
+    RetainedMemTables,
+};
 use crate::{err::Error, memtable::MemTable};
+use async_broadcast::{Receiver, RecvError, TryRecvError};
+use crossbeam_skiplist::SkipMap;
 use std::fmt::Debug;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 type K = types::Key;
 pub type InActiveMemtable = Arc<MemTable<K>>;
 
+/// Sent to flush subscribers once a flush of a memtable completed or failed
+#[derive(Debug, Clone)]
+pub enum FlushEvent {
+    /// Memtable was written to disk
+    Flushed {
+        /// Id of the flushed memtable
+        memtable_id: MemtableId,
+
+        /// Directory of the SSTable the memtable was written to
+        sstable: PathBuf,
+
+        /// Number of entries written to the SSTable
+        entries: usize,
+
+        /// Size of the data file of the SSTable
+        bytes: usize,
+
+        /// Time the flush took, waiting for a flush slot excluded
+        duration: Duration,
+    },
+
+    /// Memtable could not be written, it stays read-only and keeps serving reads
+    Failed {
+        /// Id of the memtable
+        memtable_id: MemtableId,
+
+        /// Error the flush failed with
+        error: Arc<Error>,
+
+        /// Time until the flush failed
+        duration: Duration,
+    },
+}
+
+impl FlushEvent {
+    /// Returns id of the memtable the event is about
+    pub fn memtable_id(&self) -> &MemtableId {
+        match self {
+            FlushEvent::Flushed { memtable_id, .. } | FlushEvent::Failed { memtable_id, .. } => memtable_id,
+        }
+    }
+
+    /// Returns time the flush took
+    pub fn duration(&self) -> Duration {
+        match self {
+            FlushEvent::Flushed { duration, .. } | FlushEvent::Failed { duration, .. } => *duration,
+        }
+    }
+}
+
+/// Result of [`Flusher::flush`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FlushOutcome {
+    /// Memtable was written to an SSTable
+    Flushed {
+        /// Directory of the SSTable
+        sstable: PathBuf,
+
+        /// Size of the data file of the SSTable
+        bytes: usize,
+    },
+
+    /// Memtable had no entries, nothing was written
+    Skipped,
+}
+
+/// Receives a [`FlushEvent`] for every flush of a store
+///
+/// Returned by [`DataStore::subscribe_flush`](crate::db::DataStore::subscribe_flush).
+/// Events are buffered, a subscriber that falls too far behind skips
+/// the oldest ones.
+#[derive(Debug)]
+pub struct FlushSubscription {
+    rx: Receiver<FlushEvent>,
+}
+
+impl FlushSubscription {
+    pub(crate) fn new(rx: Receiver<FlushEvent>) -> Self {
+        Self { rx }
+    }
+
+    /// Waits for the next flush
+    ///
+    /// Returns `None` once the store has been dropped
+    pub async fn recv(&mut self) -> Option<FlushEvent> {
+        loop {
+            match self.rx.recv().await {
+                Ok(event) => return Some(event),
+                Err(RecvError::Overflowed(skipped)) => {
+                    log::warn!("Flush subscriber fell behind, skipped {} events", skipped)
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Returns next flush event if one is buffered, without waiting
+    pub fn try_recv(&mut self) -> Option<FlushEvent> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(event) => return Some(event),
+                Err(TryRecvError::Overflowed(_)) => continue,
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
 /// Responsible for flushing memtables to disk
 #[derive(Debug, Clone)]
 pub struct Flusher {
     pub(crate) read_only_memtable: ImmutableMemTables<K>,
     pub(crate) bucket_map: BucketMapHandle,
     pub(crate) key_range: KeyRangeHandle,
+    pub(crate) env: Env,
+
+    /// Filter memory shared by all sstables, memtable filter is kept if not set
+    pub(crate) filter_memory_budget: Option<usize>,
+
+    /// Build filters and encode blocks on the blocking thread pool
+    pub(crate) offload_cpu_work: bool,
+
+    /// Flushed memtables kept as a read cache, oldest first
+    pub(crate) retained: RetainedMemTables<K>,
+
+    /// Number of flushed memtables kept in `retained`
+    pub(crate) retained_limit: usize,
+
+    /// Memtables handed to `flush_handler`, shared with the store
+    pub(crate) flush_stream: MemtableFlushStream,
 }
 
 impl Flusher {
@@ -22,29 +155,99 @@ impl Flusher {
         read_only_memtable: ImmutableMemTables<K>,
         bucket_map: BucketMapHandle,
         key_range: KeyRangeHandle,
+        env: Env,
     ) -> Self {
         Self {
             read_only_memtable,
             bucket_map,
             key_range,
+            env,
+            filter_memory_budget: None,
+            offload_cpu_work: true,
+            retained: Arc::default(),
+            retained_limit: 0,
+            flush_stream: Arc::default(),
+        }
+    }
+
+    /// Sizes filters of flushed sstables within `filter_memory_budget`
+    pub(crate) fn with_filter_memory_budget(mut self, filter_memory_budget: Option<usize>) -> Self {
+        self.filter_memory_budget = filter_memory_budget;
+        self
+    }
+
+    /// Sets whether memtable filters are rebuilt on the blocking thread pool
+    pub(crate) fn with_cpu_offload(mut self, offload_cpu_work: bool) -> Self {
+        self.offload_cpu_work = offload_cpu_work;
+        self
+    }
+
+    /// Keeps up to `retained_limit` flushed memtables in `retained`
+    pub(crate) fn with_retained(mut self, retained: RetainedMemTables<K>, retained_limit: usize) -> Self {
+        self.retained = retained;
+        self.retained_limit = retained_limit;
+        self
+    }
+
+    /// Records failures of `flush_handler` in `flush_stream`
+    pub(crate) fn with_flush_stream(mut self, flush_stream: MemtableFlushStream) -> Self {
+        self.flush_stream = flush_stream;
+        self
+    }
+
+    /// Keeps flushed memtable `table` in memory, evicting the oldest ones beyond `retained_limit`
+    ///
+    /// Call only once the SSTable of `table` is durable and in `KeyRange`
+    pub(crate) fn retain(&self, table_id: MemtableId, table: InActiveMemtable) {
+        if self.retained_limit == 0 {
+            return;
+        }
+        let mut retained = self.retained.write().unwrap();
+        retained.insert(table_id, table);
+        while retained.len() > self.retained_limit {
+            retained.shift_remove_index(0);
         }
     }
 
     /// Handles a single flush operation
     ///
     /// This method writes memtable to the right bucket and update the
-    /// `KeyRange` with the new sstable
-    pub async fn flush(&mut self, table: InActiveMemtable) -> Result<(), Error> {
+    /// `KeyRange` with the new sstable. The sstable is listed in the bucket
+    /// manifest with its key range and filter first, a flush that fails
+    /// before that leaves no sstable behind.
+    ///
+    /// Returns directory of the new sstable, or [`FlushOutcome::Skipped`]
+    /// if the memtable is empty
+    pub async fn flush(&mut self, table: InActiveMemtable) -> Result<FlushOutcome, Error> {
         let flush_data = self;
         let table_reader = table;
         if table_reader.entries.is_empty() {
-            return Err(Error::FailedToInsertToBucket(
-                "Cannot flush an empty table".to_string(),
-            ));
+            return Ok(FlushOutcome::Skipped);
+        }
+        let mut memtable = table_reader.as_ref().to_owned();
+        if flush_data.retained_limit > 0 {
+            // entries of the sstable are cleared below, a retained memtable keeps its own
+            let entries = SkipMap::new();
+            for e in memtable.entries.iter() {
+                entries.insert(e.key().to_owned(), e.value().to_owned());
+            }
+            memtable.entries = Arc::new(entries);
+        }
+        if let Some(budget) = flush_data.filter_memory_budget {
+            // memtable filter was sized for its capacity before the rate was known
+            let entry_count = memtable.entries.len();
+            let other_tables = flush_data.key_range.entry_counts(&[]).await;
+            let filter = BloomFilter::new(
+                monkey::false_positive_rate(&other_tables, entry_count, budget),
+                entry_count,
+            );
+            memtable.bloom_filter = filter
+                .build_from_entries(memtable.entries.clone(), flush_data.offload_cpu_work)
+                .await?;
         }
         let mut bucket_lock = flush_data.bucket_map.write().await;
         let sst = bucket_lock
-            .insert_to_appropriate_bucket(Arc::new(Box::new(table_reader.as_ref().to_owned())))
+            .insert_to_appropriate_bucket(Arc::new(Box::new(memtable)))
             .await?;
         drop(table_reader);
         if sst.summary.is_none() {
@@ -56,11 +259,16 @@ impl Flusher {
         //IMPORTANT: Don't keep sst entries in memory
         sst.entries.clear();
         let summary = sst.summary.clone().unwrap();
+        let sst_dir = sst.dir.to_owned();
+        let bytes = sst.size;
         flush_data
             .key_range
-            .set(sst.dir.to_owned(), summary.smallest_key, summary.biggest_key, sst)
+            .set(sst_dir.to_owned(), summary.smallest_key, summary.biggest_key, sst)
             .await;
-        Ok(())
+        Ok(FlushOutcome::Flushed {
+            sstable: sst_dir,
+            bytes,
+        })
     }
 
     /// Flushes memtable to disk in background
@@ -68,33 +276,82 @@ impl Flusher {
     /// Handles flushing memtable to disk in background and
     /// removes it from the read only memtables
     ///
-    /// It also notifies flush listener
+    /// It also notifies flush listener. A failed flush is recorded in
+    /// `flush_stream`, the memtable is flushed again on the
+    /// next rotation or by `DataStore::wait_for_compactions`
     pub fn flush_handler(
         &mut self,
         table_id: impl 'static + AsRef<[u8]> + Send + Sync + Debug,
         table_to_flush: InActiveMemtable,
-        flush_tx: async_broadcast::Sender<FlushSignal>,
+        flush_tx: async_broadcast::Sender<FlushEvent>,
     ) {
         let tx = flush_tx.clone();
         let buckets = self.bucket_map.clone();
         let key_range = self.key_range.clone();
         let read_only_memtable = self.read_only_memtable.clone();
-        tokio::spawn(async move {
-            let mut flusher = Flusher::new(read_only_memtable.clone(), buckets, key_range);
-            match flusher.flush(table_to_flush).await {
-                Ok(_) => {
-                    read_only_memtable.remove(&table_id.as_ref().to_vec());
-                    if let Err(err) = tx.try_broadcast(FLUSH_SIGNAL) {
-                        match err {
-                            async_broadcast::TrySendError::Full(_) => {
-                                log::info!("{}", Error::FlushSignalChannelOverflow.to_string())
-                            }
-                            _ => log::error!("{}", err),
+        let env = self.env.clone();
+        let filter_memory_budget = self.filter_memory_budget;
+        let offload_cpu_work = self.offload_cpu_work;
+        let retained = self.retained.clone();
+        let retained_limit = self.retained_limit;
+        let flush_stream = self.flush_stream.clone();
+        let errors = self.env.errors.clone();
+        // a flush that panics is retried, the memtable stays read-only until it is written
+        supervise(BackgroundJob::Flush, errors, move || {
+            let tx = tx.clone();
+            let buckets = buckets.clone();
+            let key_range = key_range.clone();
+            let read_only_memtable = read_only_memtable.clone();
+            let env = env.clone();
+            let retained = retained.clone();
+            let flush_stream = flush_stream.clone();
+            let table_id = table_id.as_ref().to_vec();
+            let table_to_flush = table_to_flush.clone();
+            async move {
+                let permit = env.acquire(BackgroundJob::Flush).await;
+                let mut flusher = Flusher::new(read_only_memtable.clone(), buckets, key_range, env)
+                    .with_filter_memory_budget(filter_memory_budget)
+                    .with_cpu_offload(offload_cpu_work)
+                    .with_retained(retained, retained_limit)
+                    .with_flush_stream(flush_stream.clone());
+                let entries = table_to_flush.entries.len();
+                let contexts = table_to_flush.contexts.to_owned();
+                let started = Instant::now();
+                let res = flusher.flush(table_to_flush.clone()).await;
+                drop(permit);
+                let event = match res {
+                    Ok(FlushOutcome::Skipped) => {
+                        read_only_memtable.remove(&table_id);
+                        return;
+                    }
+                    Ok(FlushOutcome::Flushed { sstable, bytes }) => {
+                        flusher.retain(table_id.to_owned(), table_to_flush);
+                        read_only_memtable.remove(&table_id);
+                        FlushEvent::Flushed {
+                            memtable_id: table_id,
+                            sstable,
+                            entries,
+                            bytes,
+                            duration: started.elapsed(),
                         }
                     }
-                }
-                Err(err) => {
-                    log::error!("{}", err.to_string())
+                    Err(err) => {
+                        log::error!("{}{}", err, context::describe(&contexts));
+                        flush_stream.lock().unwrap().insert(table_id.to_owned(), true);
+                        FlushEvent::Failed {
+                            memtable_id: table_id,
+                            error: Arc::new(err),
+                            duration: started.elapsed(),
+                        }
+                    }
+                };
+                if let Err(err) = tx.try_broadcast(event) {
+                    match err {
+                        async_broadcast::TrySendError::Full(_) => {
+                            log::info!("{}", Error::FlushSignalChannelOverflow)
+                        }
+                        _ => log::error!("{}", err),
+                    }
                 }
             }
         });
@@ -1,2 +1,2 @@
 mod flusher;
-pub use crate::flush::flusher::Flusher;
+pub use crate::flush::flusher::{FlushEvent, FlushOutcome, FlushSubscription, Flusher};
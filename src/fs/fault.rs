@@ -0,0 +1,219 @@
+//! # Fault Injection
+//!
+//! Available with the `fault-injection` feature. Rules registered here make
+//! file operations issued by velarixdb fail (or write only part of their buffer)
+//! so recovery handling around velarixdb errors can be exercised in tests.
+//!
+//! Rules are process wide and match every file under their path, scope them to
+//! the directory of the store under test so tests running in parallel don't
+//! interfere with each other.
+//!
+//! ```rust
+//! use std::io::ErrorKind;
+//! use velarixdb::fault::{self, Fault, FaultRule, Operation};
+//! # let dir = tempfile::tempdir().unwrap();
+//!
+//! // Fail the second write to any file in `dir`, once
+//! fault::inject(
+//!     FaultRule::new(dir.path(), Operation::Write, Fault::Error(ErrorKind::Other))
+//!         .after(1)
+//!         .times(1),
+//! );
+//! // ... exercise the store ...
+//! fault::clear(dir.path());
+//! ```
+use crate::err::Error::{self, *};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Registered rules, checked in insertion order
+static RULES: Mutex<Vec<FaultRule>> = Mutex::new(Vec::new());
+
+/// File operations that can be intercepted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Creating or opening a file for appends
+    Create,
+
+    /// Opening a file for reads
+    Open,
+
+    /// Reading bytes, including value log entry reads
+    Read,
+
+    /// Writing bytes
+    Write,
+
+    /// Syncing or flushing a file
+    Sync,
+
+    /// Moving the file cursor
+    Seek,
+
+    /// Truncating a file
+    Clear,
+
+    /// Reserving disk space for a file
+    Preallocate,
+}
+
+/// What happens to an intercepted operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Operation fails with an I/O error of this kind
+    Error(io::ErrorKind),
+
+    /// Only the first `n` bytes are written before the write fails with
+    /// [`io::ErrorKind::WriteZero`], other operations fail without side effects
+    ShortWrite(usize),
+}
+
+/// Rule deciding which operations get a [`Fault`]
+#[derive(Debug, Clone)]
+pub struct FaultRule {
+    path: PathBuf,
+    op: Operation,
+    fault: Fault,
+    skip: usize,
+    remaining: Option<usize>,
+    triggered: usize,
+}
+
+impl FaultRule {
+    /// Creates rule that injects `fault` into every `op` on files under `path`
+    pub fn new(path: impl AsRef<Path>, op: Operation, fault: Fault) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            op,
+            fault,
+            skip: 0,
+            remaining: None,
+            triggered: 0,
+        }
+    }
+
+    /// Lets the first `n` matching operations succeed
+    pub fn after(mut self, n: usize) -> Self {
+        self.skip = n;
+        self
+    }
+
+    /// Injects the fault at most `n` times
+    pub fn times(mut self, n: usize) -> Self {
+        self.remaining = Some(n);
+        self
+    }
+
+    fn matches(&self, path: &Path, op: Operation) -> bool {
+        self.op == op && path.starts_with(&self.path)
+    }
+}
+
+/// Registers `rule`
+pub fn inject(rule: FaultRule) {
+    RULES.lock().expect("Failed to lock fault rules").push(rule);
+}
+
+/// Removes every rule registered for `path` or a path under it
+pub fn clear(path: impl AsRef<Path>) {
+    RULES
+        .lock()
+        .expect("Failed to lock fault rules")
+        .retain(|rule| !rule.path.starts_with(path.as_ref()));
+}
+
+/// Returns how many faults were injected by rules registered for `path` or a path under it
+pub fn triggered(path: impl AsRef<Path>) -> usize {
+    RULES
+        .lock()
+        .expect("Failed to lock fault rules")
+        .iter()
+        .filter(|rule| rule.path.starts_with(path.as_ref()))
+        .map(|rule| rule.triggered)
+        .sum()
+}
+
+/// Returns fault to inject into `op` on `path`, if any rule fires
+pub(crate) fn intercept(path: &Path, op: Operation) -> Option<Fault> {
+    let mut rules = RULES.lock().expect("Failed to lock fault rules");
+    for rule in rules.iter_mut().filter(|rule| rule.matches(path, op)) {
+        if rule.skip > 0 {
+            rule.skip -= 1;
+            continue;
+        }
+        match rule.remaining {
+            Some(0) => continue,
+            Some(ref mut n) => *n -= 1,
+            None => {}
+        }
+        rule.triggered += 1;
+        return Some(rule.fault);
+    }
+    None
+}
+
+impl Fault {
+    /// Returns the error `op` on `path` would have failed with
+    pub(crate) fn into_error(self, path: &Path, op: Operation) -> Error {
+        let error = match self {
+            Fault::Error(kind) => io::Error::new(kind, "injected fault"),
+            Fault::ShortWrite(_) => io::Error::new(io::ErrorKind::WriteZero, "injected short write"),
+        };
+        let path = path.to_path_buf();
+        match op {
+            Operation::Create => FileCreation { path, error },
+            Operation::Open => FileOpen { path, error },
+            Operation::Read => FileRead { path, error },
+            Operation::Write => FileWrite { path, error },
+            Operation::Sync => FileSync { path, error },
+            Operation::Seek => FileSeek { path, error },
+            Operation::Clear => FileClear { path, error },
+            Operation::Preallocate => FilePreallocate { path, error },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_after_and_times() {
+        let root = tempfile::tempdir().unwrap();
+        let file = root.path().join("data.bin");
+        inject(
+            FaultRule::new(root.path(), Operation::Sync, Fault::Error(io::ErrorKind::Other))
+                .after(1)
+                .times(2),
+        );
+        assert!(intercept(&file, Operation::Sync).is_none());
+        assert!(intercept(&file, Operation::Write).is_none());
+        assert_eq!(
+            intercept(&file, Operation::Sync),
+            Some(Fault::Error(io::ErrorKind::Other))
+        );
+        assert!(intercept(&file, Operation::Sync).is_some());
+        assert!(intercept(&file, Operation::Sync).is_none());
+        assert_eq!(triggered(root.path()), 2);
+        clear(root.path());
+        assert_eq!(triggered(root.path()), 0);
+    }
+
+    #[test]
+    fn test_rule_is_scoped_to_path() {
+        let root = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        inject(FaultRule::new(
+            root.path(),
+            Operation::Read,
+            Fault::Error(io::ErrorKind::Other),
+        ));
+        assert!(intercept(&other.path().join("data.bin"), Operation::Read).is_none());
+        assert!(intercept(&root.path().join("data.bin"), Operation::Read).is_some());
+        clear(root.path());
+        assert!(intercept(&root.path().join("data.bin"), Operation::Read).is_none());
+    }
+}
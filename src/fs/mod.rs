@@ -1,32 +1,59 @@
 use crate::{
-    consts::{EOF, SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8},
+    block::{Block, BlockEntry},
+    consts::{
+        BLOCK_SIZE, DATA_FILE_NAME, EOF, FILTER_META_SIZE, PREFIX_SKETCH_MAGIC, SIZE_OF_U32, SIZE_OF_U64,
+        SIZE_OF_U8, SUMMARY_STATS_MAGIC, VLOG_CHECKSUM_FLAG, VLOG_METADATA_FLAG, VLOG_TOMBSTONE_FLAG,
+    },
+    db::contains_key,
     err::Error::{self, *},
     filter::{FalsePositive, NoHashFunc, NoOfElements},
-    index::RangeOffset,
+    index::{IndexEntry, RangeOffset, SparseIndex},
     key_range::{BiggestKey, SmallestKey},
     load_buffer,
     memtable::{Entry, SkipMapValue},
+    sst::{PrefixSketch, RangeTombstone},
     types::{
-        CreatedAt, IsTombStone, Key, LastModified, NoBytesRead, SkipMapEntries, VLogHead, VLogTail,
-        ValOffset, Value,
+        CreatedAt, Key, LastModified, NoBytesRead, SkipMapEntries, VLogHead, VLogTail, ValOffset, Value,
     },
     util,
-    vlog::ValueLogEntry,
+    vlog::{ValueKind, ValueLogEntry},
 };
 use async_trait::async_trait;
+use bit_vec::BitVec;
 use crossbeam_skiplist::SkipMap;
 use std::{
     fmt::Debug,
     fs::Metadata,
     io::SeekFrom,
+    ops::Bound,
     path::{Path, PathBuf},
     sync::Arc,
 };
 use tokio::{
     fs::{self, File, OpenOptions},
     io::{self, AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
-    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    sync::{OnceCell, RwLock, RwLockReadGuard, RwLockWriteGuard},
 };
+
+#[cfg(feature = "fault-injection")]
+pub mod fault;
+mod read_pool;
+mod retry;
+pub use read_pool::ReadPool;
+pub use retry::RetryPolicy;
+
+/// Returns early with the fault injected into `$op` on `$path`, if any rule fires
+///
+/// Expands to nothing without the `fault-injection` feature
+macro_rules! intercept {
+    ($path:expr, $op:ident) => {
+        #[cfg(feature = "fault-injection")]
+        if let Some(fault) = fault::intercept($path, fault::Operation::$op) {
+            return Err(fault.into_error($path, fault::Operation::$op));
+        }
+    };
+}
+
 #[derive(Debug, Clone)]
 pub enum FileType {
     Index,
@@ -98,22 +125,21 @@ pub trait FileAsync: F {
 pub trait DataFs: F {
     async fn new(path: impl P, file_type: FileType) -> Result<Self, Error>;
     async fn load_entries(&self) -> Result<(SkipMapEntries<Key>, usize), Error>;
-    async fn find_entry(
-        &self,
-        offset: u32,
-        searched_key: &[u8],
-    ) -> Result<Option<(ValOffset, CreatedAt, IsTombStone)>, Error>;
 
     async fn load_entries_within_range(
         &self,
         range_offset: RangeOffset,
+        bounds: (Bound<Key>, Bound<Key>),
     ) -> Result<Vec<Entry<Key, usize>>, Error>;
+
+    async fn load_block(&self, offset: u32) -> Result<(Vec<BlockEntry>, NoBytesRead), Error>;
 }
 
 #[async_trait]
 pub trait VLogFs: F {
     async fn new(path: impl P, file_type: FileType) -> Result<Self, Error>;
     async fn get(&self, start_offset: usize) -> Result<Option<(Key, bool)>, Error>;
+    async fn get_entry(&self, start_offset: usize) -> Result<Option<ValueLogEntry>, Error>;
     async fn recover(&self, start_offset: usize) -> Result<Vec<ValueLogEntry>, Error>;
     async fn read_chunk_to_garbage_collect(
         &self,
@@ -126,6 +152,7 @@ pub trait VLogFs: F {
 pub trait FilterFs: F {
     async fn new(path: impl P, file_type: FileType) -> Result<Self, Error>;
     async fn recover(path: impl P) -> Result<(FalsePositive, NoHashFunc, NoOfElements), Error>;
+    async fn recover_bits(path: impl P) -> Result<Option<BitVec>, Error>;
 }
 
 #[async_trait]
@@ -133,6 +160,7 @@ pub trait FilterFs: F {
 pub trait IndexFs: F {
     async fn new(path: impl P, file_type: FileType) -> Result<Self, Error>;
     async fn get_from_index(&self, searched_key: &[u8]) -> Result<Option<u32>, Error>;
+    async fn read_entries(&self) -> Result<Vec<IndexEntry>, Error>;
     #[allow(dead_code)] // will be used for range queries(future)
     async fn get_block_range(&self, start_key: &[u8], end_key: &[u8]) -> Result<RangeOffset, Error>;
 }
@@ -140,9 +168,21 @@ pub trait IndexFs: F {
 #[async_trait]
 pub trait SummaryFs: F {
     async fn new(path: impl P, file_type: FileType) -> Result<Self, Error>;
-    async fn recover(path: impl P) -> Result<(SmallestKey, BiggestKey), Error>;
+    async fn recover(path: impl P) -> Result<RecoveredSummary, Error>;
 }
 
+/// Entry count and oldest/newest `created_at` of an sstable, absent in summaries of older versions
+pub type TableStats = (usize, CreatedAt, CreatedAt);
+
+/// Key range, stats and prefix sketch read from a summary file
+pub type RecoveredSummary = (
+    SmallestKey,
+    BiggestKey,
+    Option<TableStats>,
+    Option<PrefixSketch>,
+    Vec<RangeTombstone>,
+);
+
 #[async_trait]
 pub trait MetaFs: F {
     async fn new(path: impl P, file_type: FileType) -> Result<Self, Error>;
@@ -167,11 +207,244 @@ impl FileNode {
             file_path: path.as_ref().to_path_buf(),
         })
     }
+
+    /// Opens existing file at `path` for reads only, e.g. data file of a written SSTable
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the file does not exist or could not be opened
+    pub async fn open_read_only(path: impl P, file_type: FileType) -> Result<Self, Error> {
+        let file = FileNode::open(path.as_ref()).await?;
+        Ok(Self {
+            file_type,
+            file: Arc::new(RwLock::new(file)),
+            file_path: path.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Reads into `buf` from `offset` until `buf` is full or end of file is reached,
+    /// returns number of bytes read
+    ///
+    /// Uses pread(2) under the read lock, so concurrent reads on the same file
+    /// don't wait for each other and don't move the file cursor. Bytes written
+    /// through this node are seen once they were flushed or synced.
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case of IO error
+    pub async fn read_at(&self, offset: u64, buf: &mut Buf) -> Result<usize, Error> {
+        intercept!(&self.file_path, Read);
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+            let file = self.r_lock().await;
+            let mut total_bytes_read = 0;
+            while total_bytes_read < buf.len() {
+                let rest = &mut buf[total_bytes_read..];
+                let bytes_read = unsafe {
+                    libc::pread(
+                        file.as_raw_fd(),
+                        rest.as_mut_ptr() as *mut libc::c_void,
+                        rest.len(),
+                        (offset as usize + total_bytes_read) as libc::off_t,
+                    )
+                };
+                match bytes_read {
+                    0 => break,
+                    n if n > 0 => total_bytes_read += n as usize,
+                    _ => {
+                        let error = io::Error::last_os_error();
+                        if error.kind() == io::ErrorKind::Interrupted {
+                            continue;
+                        }
+                        return Err(FileRead {
+                            path: self.file_path.to_owned(),
+                            error,
+                        });
+                    }
+                }
+            }
+            Ok(total_bytes_read)
+        }
+        #[cfg(not(unix))]
+        {
+            // no positional reads, fall back to seeking under the write lock
+            let mut file = self.w_lock().await;
+            file.seek(SeekFrom::Start(offset))
+                .await
+                .map_err(|error| FileSeek {
+                    path: self.file_path.to_owned(),
+                    error,
+                })?;
+            let mut total_bytes_read = 0;
+            while total_bytes_read < buf.len() {
+                let bytes_read = load_buffer!(file, &mut buf[total_bytes_read..], self.file_path.to_owned())?;
+                if bytes_read == 0 {
+                    break;
+                }
+                total_bytes_read += bytes_read;
+            }
+            Ok(total_bytes_read)
+        }
+    }
+
+    /// Returns cursor reading the file sequentially from `offset` with [`FileNode::read_at`]
+    pub(crate) fn cursor(&self, offset: u64) -> ReadCursor<'_> {
+        ReadCursor {
+            node: self,
+            offset,
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Reserves disk space for `len` bytes starting at `offset` without changing
+    /// the file size, appends within that range don't need to extend the file
+    ///
+    /// Uses fallocate(2) with `FALLOC_FL_KEEP_SIZE`, does nothing on other platforms
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the file system rejected the allocation
+    #[allow(unused_variables)] // for non-linux environment
+    pub async fn preallocate(&self, offset: usize, len: usize) -> Result<(), Error> {
+        intercept!(&self.file_path, Preallocate);
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::io::AsRawFd;
+            let file = self.r_lock().await;
+            let result = unsafe {
+                libc::fallocate(
+                    file.as_raw_fd(),
+                    libc::FALLOC_FL_KEEP_SIZE,
+                    offset as libc::off_t,
+                    len as libc::off_t,
+                )
+            };
+            // 0 return means the allocation was successful
+            if result != 0 {
+                return Err(FilePreallocate {
+                    path: self.file_path.to_owned(),
+                    error: io::Error::last_os_error(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Cuts file down to `len` bytes, dropping anything written after
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case of IO error
+    pub async fn truncate(&self, len: usize) -> Result<(), Error> {
+        intercept!(&self.file_path, Clear);
+        let file = self.w_lock().await;
+        file.set_len(len as u64).await.map_err(|err| FileClear {
+            path: self.file_path.clone(),
+            error: err,
+        })
+    }
+
+    /// Flushes entries of directory `dir` to disk, so files created in, renamed
+    /// into or removed from it survive a power loss
+    ///
+    /// Does nothing on non-unix platforms, where directories can't be opened
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the directory could not be opened or synced
+    #[allow(unused_variables)] // for non-unix environment
+    pub async fn sync_dir(dir: impl P) -> Result<(), Error> {
+        intercept!(dir.as_ref(), Sync);
+        #[cfg(unix)]
+        {
+            let dir_file = File::open(dir.as_ref()).await.map_err(|err| DirOpen {
+                path: dir.as_ref().to_path_buf(),
+                error: err,
+            })?;
+            dir_file.sync_all().await.map_err(|err| DirSync {
+                path: dir.as_ref().to_path_buf(),
+                error: err,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Returns bytes available to unprivileged users on the file system holding `path`
+    ///
+    /// Uses statvfs(3), returns `None` on non-unix platforms
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the file system could not be queried
+    #[allow(unused_variables)] // for non-unix environment
+    pub async fn available_space(path: impl P) -> Result<Option<u64>, Error> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let path = path.as_ref();
+            let c_path =
+                std::ffi::CString::new(path.as_os_str().as_bytes()).map_err(|err| FileSystemStat {
+                    path: path.to_path_buf(),
+                    error: io::Error::new(io::ErrorKind::InvalidInput, err),
+                })?;
+            let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+            // 0 return means the statistics were read
+            if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+                return Err(FileSystemStat {
+                    path: path.to_path_buf(),
+                    error: io::Error::last_os_error(),
+                });
+            }
+            #[allow(clippy::unnecessary_cast)] // field types differ between platforms
+            return Ok(Some(stat.f_bavail as u64 * stat.f_frsize as u64));
+        }
+        #[cfg(not(unix))]
+        Ok(None)
+    }
+
+    /// Replaces contents of file at `path` with `buf`
+    ///
+    /// `buf` is written to a temporary file next to `path`, synced and renamed
+    /// over `path`, so after a crash the file has either the old or the new contents
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case of IO error
+    pub async fn write_atomic(path: impl P, buf: &Buf) -> Result<(), Error> {
+        let path = path.as_ref();
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        intercept!(&tmp_path, Write);
+        let mut file = File::create(&tmp_path).await.map_err(|err| FileCreation {
+            path: tmp_path.to_owned(),
+            error: err,
+        })?;
+        file.write_all(buf).await.map_err(|err| FileWrite {
+            path: tmp_path.to_owned(),
+            error: err,
+        })?;
+        file.sync_all().await.map_err(|error| FileSync {
+            path: tmp_path.to_owned(),
+            error,
+        })?;
+        fs::rename(&tmp_path, path).await.map_err(|err| FileRename {
+            path: path.to_path_buf(),
+            error: err,
+        })?;
+        match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => Self::sync_dir(dir).await,
+            _ => Ok(()),
+        }
+    }
 }
 
 #[async_trait]
 impl FileAsync for FileNode {
     async fn create(path: impl P) -> Result<File, Error> {
+        intercept!(path.as_ref(), Create);
         Ok(OpenOptions::new()
             .read(true)
             .append(true)
@@ -197,10 +470,14 @@ impl FileAsync for FileNode {
 
     async fn metadata(&self) -> Result<Metadata, Error> {
         let file = self.r_lock().await;
-        Ok(file.metadata().await.map_err(GetFileMetaData)?)
+        Ok(file.metadata().await.map_err(|error| GetFileMetaData {
+            path: self.file_path.to_owned(),
+            error,
+        })?)
     }
 
     async fn open(path: impl P) -> Result<File, Error> {
+        intercept!(path.as_ref(), Open);
         Ok(File::open(path.as_ref()).await.map_err(|err| FileOpen {
             path: path.as_ref().to_path_buf(),
             error: err,
@@ -208,6 +485,7 @@ impl FileAsync for FileNode {
     }
 
     async fn read_buf(&self, buf: &mut Buf) -> Result<usize, Error> {
+        intercept!(&self.file_path, Read);
         let mut file = self.w_lock().await;
         Ok(file.read(buf).await.map_err(|err| FileRead {
             path: self.file_path.clone(),
@@ -217,13 +495,27 @@ impl FileAsync for FileNode {
 
     async fn write_all(&self, buf: &Buf) -> Result<(), Error> {
         let mut file = self.w_lock().await;
-        Ok(file.write_all(buf).await.map_err(|err| FileWrite {
+        #[cfg(feature = "fault-injection")]
+        if let Some(injected) = fault::intercept(&self.file_path, fault::Operation::Write) {
+            if let fault::Fault::ShortWrite(n) = injected {
+                let _ = file.write_all(&buf[..n.min(buf.len())]).await;
+                let _ = file.flush().await;
+            }
+            return Err(injected.into_error(&self.file_path, fault::Operation::Write));
+        }
+        file.write_all(buf).await.map_err(|err| FileWrite {
+            path: self.file_path.clone(),
+            error: err,
+        })?;
+        // tokio may still be writing in the background, `read_at` bypasses it
+        Ok(file.flush().await.map_err(|err| FileWrite {
             path: self.file_path.clone(),
             error: err,
         })?)
     }
 
     async fn clear(&self) -> Result<(), Error> {
+        intercept!(&self.file_path, Clear);
         let file = self.w_lock().await;
         Ok(file.set_len(0).await.map_err(|err| FileClear {
             path: self.file_path.clone(),
@@ -232,22 +524,42 @@ impl FileAsync for FileNode {
     }
 
     async fn sync_all(&self) -> Result<(), Error> {
+        intercept!(&self.file_path, Sync);
         let file = self.w_lock().await;
-        Ok(file.sync_all().await.map_err(Error::FileSync)?)
+        Ok(file.sync_all().await.map_err(|error| FileSync {
+            path: self.file_path.to_owned(),
+            error,
+        })?)
     }
 
     async fn flush(&self) -> Result<(), Error> {
+        intercept!(&self.file_path, Sync);
         let mut file = self.w_lock().await;
-        Ok(file.flush().await.map_err(Error::FileSync)?)
+        Ok(file.flush().await.map_err(|error| FileSync {
+            path: self.file_path.to_owned(),
+            error,
+        })?)
     }
 
     async fn seek(&self, start_offset: u64) -> Result<u64, Error> {
+        intercept!(&self.file_path, Seek);
         let mut file = self.w_lock().await;
-        Ok(file.seek(SeekFrom::Start(start_offset)).await.map_err(FileSeek)?)
+        Ok(file
+            .seek(SeekFrom::Start(start_offset))
+            .await
+            .map_err(|error| FileSeek {
+                path: self.file_path.to_owned(),
+                error,
+            })?)
     }
 
     async fn remove_dir_all(&self) -> Result<(), Error> {
-        Ok(fs::remove_dir_all(&self.file_path).await.map_err(DirDelete)?)
+        Ok(fs::remove_dir_all(&self.file_path)
+            .await
+            .map_err(|error| DirDelete {
+                path: self.file_path.to_owned(),
+                error,
+            })?)
     }
 
     async fn w_lock(&self) -> WGuard<File> {
@@ -266,6 +578,18 @@ pub struct DataFileNode {
 
 impl ThreadSharable for DataFileNode {}
 
+impl DataFileNode {
+    /// Opens data file of a written SSTable for reads only
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the file does not exist or could not be opened
+    pub async fn open_read_only(path: impl P) -> Result<DataFileNode, Error> {
+        let node = FileNode::open_read_only(path, FileType::Data).await?;
+        Ok(DataFileNode { node })
+    }
+}
+
 #[async_trait]
 impl DataFs for DataFileNode {
     async fn new(path: impl P, file_type: FileType) -> Result<DataFileNode, Error> {
@@ -275,13 +599,11 @@ impl DataFs for DataFileNode {
     async fn load_entries(&self) -> Result<(SkipMapEntries<Key>, NoBytesRead), Error> {
         let entries = Arc::new(SkipMap::new());
         let mut total_bytes_read = 0;
-        let path = &self.node.file_path;
-        let mut file = self.node.file.write().await;
-        file.seek(std::io::SeekFrom::Start(0)).await.map_err(FileSeek)?;
+        let mut file = self.node.cursor(0);
 
         loop {
             let mut key_len_bytes = [0; SIZE_OF_U32];
-            let mut bytes_read = load_buffer!(file, &mut key_len_bytes, path.to_owned())?;
+            let mut bytes_read = file.read(&mut key_len_bytes).await?;
             total_bytes_read += bytes_read;
             if bytes_read == 0 {
                 break;
@@ -289,28 +611,28 @@ impl DataFs for DataFileNode {
 
             let key_len = u32::from_le_bytes(key_len_bytes);
             let mut key = vec![0; key_len as usize];
-            bytes_read = load_buffer!(file, &mut key, path.to_owned())?;
+            bytes_read = file.read(&mut key).await?;
             total_bytes_read += bytes_read;
             if bytes_read == 0 {
                 return Err(FileNode::unexpected_eof());
             }
 
             let mut val_offset_bytes = [0; SIZE_OF_U32];
-            bytes_read = load_buffer!(file, &mut val_offset_bytes, path.to_owned())?;
+            bytes_read = file.read(&mut val_offset_bytes).await?;
             total_bytes_read += bytes_read;
             if bytes_read == 0 {
                 return Err(FileNode::unexpected_eof());
             }
 
             let mut created_at_bytes = [0; SIZE_OF_U64];
-            bytes_read = load_buffer!(file, &mut created_at_bytes, path.to_owned())?;
+            bytes_read = file.read(&mut created_at_bytes).await?;
             total_bytes_read += bytes_read;
             if bytes_read == 0 {
                 return Err(FileNode::unexpected_eof());
             }
 
             let mut is_tombstone_byte = [0; SIZE_OF_U8];
-            bytes_read = load_buffer!(file, &mut is_tombstone_byte, path.to_owned())?;
+            bytes_read = file.read(&mut is_tombstone_byte).await?;
             total_bytes_read += bytes_read;
             if bytes_read == 0 {
                 return Err(FileNode::unexpected_eof());
@@ -331,78 +653,23 @@ impl DataFs for DataFileNode {
         return Ok((entries, total_bytes_read));
     }
 
-    async fn find_entry(
-        &self,
-        offset: u32,
-        searched_key: &[u8],
-    ) -> Result<Option<(ValOffset, CreatedAt, IsTombStone)>, Error> {
-        let path = &self.node.file_path;
-        let mut file = self.node.file.write().await;
-        file.seek(std::io::SeekFrom::Start(offset.into()))
-            .await
-            .map_err(FileSeek)?;
-
-        loop {
-            let mut key_len_bytes = [0; SIZE_OF_U32];
-            let mut bytes_read = load_buffer!(file, &mut key_len_bytes, path.to_owned())?;
-
-            if bytes_read == 0 {
-                return Ok(None);
-            }
-
-            let key_len = u32::from_le_bytes(key_len_bytes);
-            let mut key = vec![0; key_len as usize];
-            bytes_read = load_buffer!(file, &mut key, path.to_owned())?;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
-
-            let mut val_offset_bytes = [0; SIZE_OF_U32];
-            bytes_read = load_buffer!(file, &mut val_offset_bytes, path.to_owned())?;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
-
-            let mut created_at_bytes = [0; SIZE_OF_U64];
-            bytes_read = load_buffer!(file, &mut created_at_bytes, path.to_owned())?;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
-
-            let mut is_tombstone_byte = [0; SIZE_OF_U8];
-            bytes_read = load_buffer!(file, &mut is_tombstone_byte, path.to_owned())?;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
-
-            let created_at = u64::from_le_bytes(created_at_bytes);
-            let value_offset = u32::from_le_bytes(val_offset_bytes);
-            let is_tombstone = is_tombstone_byte[0] == 1;
-            if key == searched_key {
-                return Ok(Some((
-                    value_offset as usize,
-                    util::milliseconds_to_datetime(created_at),
-                    is_tombstone,
-                )));
-            }
-        }
-    }
-
+    /// Returns entries with keys within `bounds`, read from `range_offset.start_offset`
+    ///
+    /// Decoding stops at the first key past the upper bound, or once
+    /// `range_offset.end_offset` is reached.
     async fn load_entries_within_range(
         &self,
         range_offset: RangeOffset,
+        bounds: (Bound<Key>, Bound<Key>),
     ) -> Result<Vec<Entry<Key, ValOffset>>, Error> {
         let mut entries = Vec::new();
         let mut total_bytes_read = 0;
-        let path = &self.node.file_path;
-        let mut file = self.node.file.write().await;
-        file.seek(std::io::SeekFrom::Start((range_offset.start_offset) as u64))
-            .await
-            .map_err(FileSeek)?;
+        let upper = (Bound::Unbounded, bounds.1.clone());
+        let mut file = self.node.cursor(range_offset.start_offset as u64);
 
         loop {
             let mut key_len_bytes = [0; SIZE_OF_U32];
-            let mut bytes_read = load_buffer!(file, &mut key_len_bytes, path.to_owned())?;
+            let mut bytes_read = file.read(&mut key_len_bytes).await?;
             total_bytes_read += bytes_read;
             if bytes_read == 0 {
                 return Ok(entries);
@@ -410,48 +677,62 @@ impl DataFs for DataFileNode {
 
             let key_len = u32::from_le_bytes(key_len_bytes);
             let mut key = vec![0; key_len as usize];
-            bytes_read = load_buffer!(file, &mut key, path.to_owned())?;
+            bytes_read = file.read(&mut key).await?;
             total_bytes_read += bytes_read;
             if bytes_read == 0 {
                 return Err(FileNode::unexpected_eof());
             }
 
             let mut val_offset_bytes = [0; SIZE_OF_U32];
-            bytes_read = load_buffer!(file, &mut val_offset_bytes, path.to_owned())?;
+            bytes_read = file.read(&mut val_offset_bytes).await?;
             total_bytes_read += bytes_read;
             if bytes_read == 0 {
                 return Err(FileNode::unexpected_eof());
             }
 
             let mut created_at_bytes = [0; SIZE_OF_U64];
-            bytes_read = load_buffer!(file, &mut created_at_bytes, path.to_owned())?;
+            bytes_read = file.read(&mut created_at_bytes).await?;
             total_bytes_read += bytes_read;
             if bytes_read == 0 {
                 return Err(FileNode::unexpected_eof());
             }
 
             let mut is_tombstone_byte = [0; SIZE_OF_U8];
-            bytes_read = load_buffer!(file, &mut is_tombstone_byte, path.to_owned())?;
+            bytes_read = file.read(&mut is_tombstone_byte).await?;
             total_bytes_read += bytes_read;
             if bytes_read == 0 {
                 return Err(FileNode::unexpected_eof());
             }
 
-            let created_at = u64::from_le_bytes(created_at_bytes);
-            let value_offset = u32::from_le_bytes(val_offset_bytes) as usize;
-            let is_tombstone = is_tombstone_byte[0] == 1;
-            entries.push(Entry::new(
-                key,
-                value_offset,
-                util::milliseconds_to_datetime(created_at),
-                is_tombstone,
-            ));
+            if !contains_key(&upper, &key) {
+                return Ok(entries);
+            }
+            if contains_key(&bounds, &key) {
+                let created_at = u64::from_le_bytes(created_at_bytes);
+                let value_offset = u32::from_le_bytes(val_offset_bytes) as usize;
+                let is_tombstone = is_tombstone_byte[0] == 1;
+                entries.push(Entry::new(
+                    key,
+                    value_offset,
+                    util::milliseconds_to_datetime(created_at),
+                    is_tombstone,
+                ));
+            }
 
-            if total_bytes_read as u32 >= range_offset.end_offset {
+            if range_offset.start_offset as usize + total_bytes_read >= range_offset.end_offset as usize {
                 return Ok(entries);
             }
         }
     }
+
+    async fn load_block(&self, offset: u32) -> Result<(Vec<BlockEntry>, NoBytesRead), Error> {
+        // A block never exceeds `BLOCK_SIZE`, entries of the next block that
+        // are read along are kept as long as they are complete
+        let mut buf = vec![0; BLOCK_SIZE];
+        let total_bytes_read = self.node.read_at(offset.into(), &mut buf).await?;
+        buf.truncate(total_bytes_read);
+        Ok((Block::decode_entries(&buf), total_bytes_read))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -461,119 +742,144 @@ pub struct VLogFileNode {
 
 impl ThreadSharable for VLogFileNode {}
 
-#[async_trait]
-impl VLogFs for VLogFileNode {
-    async fn new(path: impl P, file_type: FileType) -> Result<VLogFileNode, Error> {
-        let node = FileNode::new(path, file_type).await?;
-        Ok(VLogFileNode { node })
-    }
-    async fn get(&self, start_offset: usize) -> Result<Option<(Value, bool)>, Error> {
-        let path = &self.node.file_path;
-
-        let mut file = self.node.file.write().await;
-        file.seek(std::io::SeekFrom::Start((start_offset) as u64))
-            .await
-            .map_err(FileSeek)?;
-
+impl VLogFileNode {
+    /// Reads entry at the current position of `file`
+    ///
+    /// Returns entry and number of bytes read, or `None` at the end of file
+    async fn read_entry(file: &mut File, path: &Path) -> Result<Option<(ValueLogEntry, NoBytesRead)>, Error> {
+        intercept!(path, Read);
+        let mut total_bytes_read = 0;
         let mut key_len_bytes = [0; SIZE_OF_U32];
         let mut bytes_read = load_buffer!(file, &mut key_len_bytes, path.to_owned())?;
+        total_bytes_read += bytes_read;
         if bytes_read == 0 {
             return Ok(None);
         }
-
         let key_len = u32::from_le_bytes(key_len_bytes);
+
         let mut val_len_bytes = [0; SIZE_OF_U32];
         bytes_read = load_buffer!(file, &mut val_len_bytes, path.to_owned())?;
+        total_bytes_read += bytes_read;
         if bytes_read == 0 {
             return Err(FileNode::unexpected_eof());
         }
-
         let val_len = u32::from_le_bytes(val_len_bytes);
+
         let mut creation_date_bytes = [0; SIZE_OF_U64];
         bytes_read = load_buffer!(file, &mut creation_date_bytes, path.to_owned())?;
+        total_bytes_read += bytes_read;
         if bytes_read == 0 {
             return Err(FileNode::unexpected_eof());
         }
+        let created_at = u64::from_le_bytes(creation_date_bytes);
 
-        let _ = u64::from_le_bytes(creation_date_bytes);
-        let mut istombstone_bytes = [0; SIZE_OF_U8];
-        let mut bytes_read = load_buffer!(file, &mut istombstone_bytes, path.to_owned())?;
+        let mut flags_bytes = [0; SIZE_OF_U8];
+        bytes_read = load_buffer!(file, &mut flags_bytes, path.to_owned())?;
+        total_bytes_read += bytes_read;
         if bytes_read == 0 {
             return Err(FileNode::unexpected_eof());
         }
+        let is_tombstone = flags_bytes[0] & VLOG_TOMBSTONE_FLAG != 0;
 
-        let is_tombstone = istombstone_bytes[0] == 1;
         let mut key = vec![0; key_len as usize];
         bytes_read = load_buffer!(file, &mut key, path.to_owned())?;
+        total_bytes_read += bytes_read;
         if bytes_read == 0 {
             return Err(FileNode::unexpected_eof());
         }
+
         let mut value = vec![0; val_len as usize];
         bytes_read = load_buffer!(file, &mut value, path.to_owned())?;
+        total_bytes_read += bytes_read;
         if bytes_read == 0 {
             return Err(FileNode::unexpected_eof());
         }
 
-        Ok(Some((value, is_tombstone)))
-    }
-
-    async fn recover(&self, start_offset: usize) -> Result<Vec<ValueLogEntry>, Error> {
-        let path = &self.node.file_path;
-        let mut entries = Vec::new();
-        let mut file = self.node.file.write().await;
-        file.seek(std::io::SeekFrom::Start((start_offset) as u64))
-            .await
-            .map_err(FileSeek)?;
-
-        loop {
-            let mut key_len_bytes = [0; SIZE_OF_U32];
-            let mut bytes_read = load_buffer!(file, &mut key_len_bytes, path.to_owned())?;
-            if bytes_read == 0 {
-                return Ok(entries);
-            }
-
-            let key_len = u32::from_le_bytes(key_len_bytes);
-            let mut val_len_bytes = [0; SIZE_OF_U32];
-            bytes_read = load_buffer!(file, &mut val_len_bytes, path.to_owned())?;
+        let mut metadata = None;
+        if flags_bytes[0] & VLOG_METADATA_FLAG != 0 {
+            let mut metadata_len_bytes = [0; SIZE_OF_U8];
+            bytes_read = load_buffer!(file, &mut metadata_len_bytes, path.to_owned())?;
+            total_bytes_read += bytes_read;
             if bytes_read == 0 {
                 return Err(FileNode::unexpected_eof());
             }
-
-            let val_len = u32::from_le_bytes(val_len_bytes);
-            let mut creation_date_bytes = [0; SIZE_OF_U64];
-            bytes_read = load_buffer!(file, &mut creation_date_bytes, path.to_owned())?;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
+            let mut metadata_bytes = vec![0; metadata_len_bytes[0] as usize];
+            if !metadata_bytes.is_empty() {
+                bytes_read = load_buffer!(file, &mut metadata_bytes, path.to_owned())?;
+                total_bytes_read += bytes_read;
+                if bytes_read == 0 {
+                    return Err(FileNode::unexpected_eof());
+                }
             }
+            metadata = Some(metadata_bytes);
+        }
 
-            let created_at = u64::from_le_bytes(creation_date_bytes);
-            let mut istombstone_bytes = [0; SIZE_OF_U8];
-            let mut bytes_read = load_buffer!(file, &mut istombstone_bytes, path.to_owned())?;
+        let mut checksum = None;
+        if flags_bytes[0] & VLOG_CHECKSUM_FLAG != 0 {
+            let mut checksum_bytes = [0; SIZE_OF_U32];
+            bytes_read = load_buffer!(file, &mut checksum_bytes, path.to_owned())?;
+            total_bytes_read += bytes_read;
             if bytes_read == 0 {
                 return Err(FileNode::unexpected_eof());
             }
+            checksum = Some(u32::from_le_bytes(checksum_bytes));
+        }
 
-            let is_tombstone = istombstone_bytes[0] == 1;
-            let mut key = vec![0; key_len as usize];
-            bytes_read = load_buffer!(file, &mut key, path.to_owned())?;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
+        let entry = ValueLogEntry {
+            ksize: key_len as usize,
+            vsize: val_len as usize,
+            key,
+            value,
+            created_at: util::milliseconds_to_datetime(created_at),
+            is_tombstone,
+            metadata,
+            checksum,
+            kind: ValueKind::from_flags(flags_bytes[0]),
+        };
+        Ok(Some((entry, total_bytes_read)))
+    }
+}
 
-            let mut value = vec![0; val_len as usize];
-            bytes_read = load_buffer!(file, &mut value, path.to_owned())?;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
-            entries.push(ValueLogEntry {
-                ksize: key_len as usize,
-                vsize: val_len as usize,
-                key,
-                value,
-                created_at: util::milliseconds_to_datetime(created_at),
-                is_tombstone,
-            })
+#[async_trait]
+impl VLogFs for VLogFileNode {
+    async fn new(path: impl P, file_type: FileType) -> Result<VLogFileNode, Error> {
+        let node = FileNode::new(path, file_type).await?;
+        Ok(VLogFileNode { node })
+    }
+
+    async fn get(&self, start_offset: usize) -> Result<Option<(Value, bool)>, Error> {
+        let entry = self.get_entry(start_offset).await?;
+        Ok(entry.map(|e| (e.value, e.is_tombstone)))
+    }
+
+    async fn get_entry(&self, start_offset: usize) -> Result<Option<ValueLogEntry>, Error> {
+        intercept!(&self.node.file_path, Seek);
+        let mut file = self.node.file.write().await;
+        file.seek(std::io::SeekFrom::Start((start_offset) as u64))
+            .await
+            .map_err(|error| FileSeek {
+                path: self.node.file_path.to_owned(),
+                error,
+            })?;
+        let entry = VLogFileNode::read_entry(&mut file, &self.node.file_path).await?;
+        Ok(entry.map(|(e, _)| e))
+    }
+
+    async fn recover(&self, start_offset: usize) -> Result<Vec<ValueLogEntry>, Error> {
+        let mut entries = Vec::new();
+        intercept!(&self.node.file_path, Seek);
+        let mut file = self.node.file.write().await;
+        file.seek(std::io::SeekFrom::Start((start_offset) as u64))
+            .await
+            .map_err(|error| FileSeek {
+                path: self.node.file_path.to_owned(),
+                error,
+            })?;
+
+        while let Some((entry, _)) = VLogFileNode::read_entry(&mut file, &self.node.file_path).await? {
+            entries.push(entry);
         }
+        Ok(entries)
     }
 
     async fn read_chunk_to_garbage_collect(
@@ -581,79 +887,41 @@ impl VLogFs for VLogFileNode {
         bytes_to_collect: usize,
         offset: u64,
     ) -> Result<(Vec<ValueLogEntry>, NoBytesRead), Error> {
-        let path = &self.node.file_path;
         let mut entries = Vec::new();
+        intercept!(&self.node.file_path, Seek);
         let mut file = self.node.file.write().await;
         file.seek(std::io::SeekFrom::Start(offset))
             .await
-            .map_err(FileSeek)?;
+            .map_err(|error| FileSeek {
+                path: self.node.file_path.to_owned(),
+                error,
+            })?;
         let mut total_bytes_read: usize = 0;
         loop {
-            let mut key_len_bytes = [0; SIZE_OF_U32];
-            let mut bytes_read = load_buffer!(file, &mut key_len_bytes, path.to_owned())?;
-            total_bytes_read += bytes_read;
-            if bytes_read == 0 {
-                return Ok((entries, total_bytes_read));
-            }
-            let key_len = u32::from_le_bytes(key_len_bytes);
-
-            let mut val_len_bytes = [0; SIZE_OF_U32];
-            bytes_read = load_buffer!(file, &mut val_len_bytes, path.to_owned())?;
-            total_bytes_read += bytes_read;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
-
-            let val_len = u32::from_le_bytes(val_len_bytes);
-            let mut creation_date_bytes = [0; SIZE_OF_U64];
-            bytes_read = load_buffer!(file, &mut creation_date_bytes, path.to_owned())?;
-            total_bytes_read += bytes_read;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
-            let created_at = u64::from_le_bytes(creation_date_bytes);
-
-            let mut istombstone_bytes = [0; SIZE_OF_U8];
-            let mut bytes_read = load_buffer!(file, &mut istombstone_bytes, path.to_owned())?;
-            total_bytes_read += bytes_read;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
-
-            let is_tombstone = istombstone_bytes[0] == 1;
-            let mut key = vec![0; key_len as usize];
-            bytes_read = load_buffer!(file, &mut key, path.to_owned())?;
-            total_bytes_read += bytes_read;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
-
-            let mut value = vec![0; val_len as usize];
-            bytes_read = load_buffer!(file, &mut value, path.to_owned())?;
+            let (entry, bytes_read) = match VLogFileNode::read_entry(&mut file, &self.node.file_path).await {
+                Ok(Some(read)) => read,
+                // entry at the end is still being appended by the store
+                Ok(None) | Err(UnexpectedEOF(_)) => break,
+                Err(err) => return Err(err),
+            };
             total_bytes_read += bytes_read;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
-            entries.push(ValueLogEntry {
-                ksize: key_len as usize,
-                vsize: val_len as usize,
-                key,
-                value,
-                created_at: util::milliseconds_to_datetime(created_at),
-                is_tombstone,
-            });
+            entries.push(entry);
 
             // Ensure the size read from value log is approximately bytes expected to be garbage collected
             if total_bytes_read >= bytes_to_collect {
-                return Ok((entries, total_bytes_read));
+                break;
             }
         }
+        Ok((entries, total_bytes_read))
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct IndexFileNode {
     pub node: FileNode,
+
+    /// Index loaded in memory, shared by clones
+    pub(crate) loaded: Arc<OnceCell<SparseIndex>>,
 }
 
 impl ThreadSharable for IndexFileNode {}
@@ -662,92 +930,66 @@ impl ThreadSharable for IndexFileNode {}
 impl IndexFs for IndexFileNode {
     async fn new(path: impl P, file_type: FileType) -> Result<IndexFileNode, Error> {
         let node = FileNode::new(path, file_type).await?;
-        Ok(IndexFileNode { node })
+        Ok(IndexFileNode {
+            node,
+            loaded: Arc::default(),
+        })
     }
     async fn get_from_index(&self, searched_key: &[u8]) -> Result<Option<u32>, Error> {
-        let path = &self.node.file_path;
-        let block_offset: i32 = -1;
-        let mut file = self.node.file.write().await;
-        file.seek(std::io::SeekFrom::Start(0_u64))
-            .await
-            .map_err(FileSeek)?;
-
-        loop {
-            let mut key_len_bytes = [0; SIZE_OF_U32];
-            let mut bytes_read = load_buffer!(file, &mut key_len_bytes, path.to_owned())?;
-            if bytes_read == 0 {
-                if block_offset == -1 {
-                    return Ok(None);
-                }
-                return Ok(Some(block_offset as u32));
-            }
-
-            let key_len = u32::from_le_bytes(key_len_bytes);
-            let mut key = vec![0; key_len as usize];
-            bytes_read = load_buffer!(file, &mut key, path.to_owned())?;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
+        Ok(self.load().await?.get(searched_key))
+    }
 
-            let mut key_offset_bytes = [0; SIZE_OF_U32];
-            bytes_read = load_buffer!(file, &mut key_offset_bytes, path.to_owned())?;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
-            let offset = u32::from_le_bytes(key_offset_bytes);
-            match key.cmp(&searched_key.to_vec()) {
-                std::cmp::Ordering::Less => {
-                    continue;
-                }
-                std::cmp::Ordering::Equal => {
-                    return Ok(Some(offset));
-                }
-                std::cmp::Ordering::Greater => {
-                    return Ok(Some(offset));
-                }
-            }
-        }
+    async fn read_entries(&self) -> Result<Vec<IndexEntry>, Error> {
+        Ok(self.load().await?.entries().to_vec())
     }
 
     async fn get_block_range(&self, start_key: &[u8], end_key: &[u8]) -> Result<RangeOffset, Error> {
-        let path = &self.node.file_path;
-        let mut range_offset = RangeOffset::new(0, 0);
-        let mut file = self.node.file.write().await;
-        file.seek(std::io::SeekFrom::Start(0_u64))
-            .await
-            .map_err(FileSeek)?;
-
-        loop {
-            let mut key_len_bytes = [0; SIZE_OF_U32];
-            let mut bytes_read = load_buffer!(file, &mut key_len_bytes, path.to_owned())?;
-            if bytes_read == 0 {
-                return Ok(range_offset);
-            }
+        Ok(self.load().await?.get_block_range(start_key, end_key))
+    }
+}
 
-            let key_len = u32::from_le_bytes(key_len_bytes);
-            let mut key = vec![0; key_len as usize];
-            bytes_read = load_buffer!(file, &mut key, path.to_owned())?;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
+impl IndexFileNode {
+    /// Returns index loaded in memory, the file is read on first call only
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if the index could neither be read nor rebuilt
+    pub async fn load(&self) -> Result<SparseIndex, Error> {
+        self.loaded
+            .get_or_try_init(|| self.read_or_rebuild())
+            .await
+            .cloned()
+    }
 
-            let mut key_offset_bytes = [0; SIZE_OF_U32];
-            bytes_read = load_buffer!(file, &mut key_offset_bytes, path.to_owned())?;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
-            let offset = u32::from_le_bytes(key_offset_bytes);
-            match key.cmp(&start_key.to_vec()) {
-                std::cmp::Ordering::Greater => match key.cmp(&end_key.to_vec()) {
-                    std::cmp::Ordering::Greater => {
-                        range_offset.end_offset = offset;
-                        return Ok(range_offset);
-                    }
-                    _ => range_offset.end_offset = offset,
-                },
-                _ => range_offset.start_offset = offset,
-            }
+    async fn read_or_rebuild(&self) -> Result<SparseIndex, Error> {
+        let path = &self.node.file_path;
+        let mut buf = vec![0; self.node.metadata().await?.len() as usize];
+        let bytes_read = self.node.read_at(0, &mut buf).await?;
+        buf.truncate(bytes_read);
+        if let Some(index) = SparseIndex::decode(&buf) {
+            return Ok(index);
         }
+
+        // index is corrupt, rebuild it from the data file of the same sstable
+        log::warn!("Index file {:?} is corrupt, rebuilding it from data file", path);
+        let data_file_path = path.with_file_name(format!("{}.db", DATA_FILE_NAME));
+        fs::metadata(&data_file_path).await.map_err(|err| FileOpen {
+            path: data_file_path.to_owned(),
+            error: err,
+        })?;
+        let data_file = DataFileNode::new(data_file_path, FileType::Data).await?;
+        let (data_entries, _) = data_file.load_entries().await?;
+        let index = SparseIndex::rebuild(&data_entries)?;
+        fs::write(
+            path,
+            SparseIndex::encode(index.entries(), index.tombstone_blocks()),
+        )
+        .await
+        .map_err(|err| FileWrite {
+            path: path.to_owned(),
+            error: err,
+        })?;
+        Ok(index)
     }
 }
 
@@ -792,7 +1034,57 @@ impl FilterFs for FilterFileNode {
         if false_positive_rate.is_none() {
             return Err(FileNode::unexpected_eof());
         }
-        return Ok((false_positive_rate.unwrap(), no_of_hash_func, no_of_elements));
+
+        // filter files written by older versions have no checksum
+        let mut checksum_bytes = [0; SIZE_OF_U32];
+        bytes_read = load_buffer!(file, &mut checksum_bytes, path.as_ref().to_path_buf())?;
+        if bytes_read != 0 {
+            let checksum = util::crc32(&[
+                &no_hash_func_bytes,
+                &no_of_elements_bytes,
+                &false_positive_rate_bytes,
+            ]);
+            if bytes_read != SIZE_OF_U32 || u32::from_le_bytes(checksum_bytes) != checksum {
+                return Err(FilterFileCorrupt(path.as_ref().to_owned()));
+            }
+        }
+        let false_positive_rate = false_positive_rate.unwrap();
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) || no_of_elements == 0 {
+            return Err(FilterFileCorrupt(path.as_ref().to_owned()));
+        }
+        return Ok((false_positive_rate, no_of_hash_func, no_of_elements));
+    }
+
+    async fn recover_bits(path: impl P) -> Result<Option<BitVec>, Error> {
+        intercept!(path.as_ref(), Read);
+        let buf = fs::read(path.as_ref()).await.map_err(|err| FileRead {
+            path: path.as_ref().to_owned(),
+            error: err,
+        })?;
+        // filter files written by older versions only store metadata
+        let bits_offset = FILTER_META_SIZE;
+        if buf.len() <= bits_offset {
+            return Ok(None);
+        }
+        let corrupt = || FilterFileCorrupt(path.as_ref().to_owned());
+        let bytes_offset = bits_offset + SIZE_OF_U32;
+        let no_of_bits = buf
+            .get(bits_offset..bytes_offset)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+            .filter(|no_of_bits| *no_of_bits > 0)
+            .ok_or_else(corrupt)?;
+        let checksum_offset = bytes_offset + no_of_bits.div_ceil(8);
+        if buf.len() != checksum_offset + SIZE_OF_U32 {
+            return Err(corrupt());
+        }
+        let bytes = &buf[bytes_offset..checksum_offset];
+        let checksum = u32::from_le_bytes(buf[checksum_offset..].try_into().unwrap());
+        if checksum != util::crc32(&[bytes]) {
+            return Err(corrupt());
+        }
+        let mut bits = BitVec::from_bytes(bytes);
+        bits.truncate(no_of_bits);
+        Ok(Some(bits))
     }
 }
 
@@ -861,7 +1153,7 @@ impl SummaryFs for SummaryFileNode {
         let node = FileNode::new(path, file_type).await?;
         Ok(SummaryFileNode { node })
     }
-    async fn recover(path: impl P) -> Result<(SmallestKey, BiggestKey), Error> {
+    async fn recover(path: impl P) -> Result<RecoveredSummary, Error> {
         let mut file = FileNode::open(path.as_ref())
             .await
             .map_err(|_| FilterFileOpen(path.as_ref().to_owned()))?;
@@ -889,7 +1181,106 @@ impl SummaryFs for SummaryFileNode {
         if bytes_read == 0 {
             return Err(FileNode::unexpected_eof());
         }
-        return Ok((smallest_key, biggest_key));
+
+        // summaries written by older versions have no stats after the keys
+        let mut magic_bytes = [0; SIZE_OF_U32];
+        bytes_read = load_buffer!(file, &mut magic_bytes, path.as_ref().to_owned())?;
+        if bytes_read < SIZE_OF_U32 || u32::from_le_bytes(magic_bytes) != SUMMARY_STATS_MAGIC {
+            return Ok((smallest_key, biggest_key, None, None, Vec::new()));
+        }
+        let mut entry_count_bytes = [0; SIZE_OF_U64];
+        bytes_read = load_buffer!(file, &mut entry_count_bytes, path.as_ref().to_owned())?;
+        if bytes_read == 0 {
+            return Err(FileNode::unexpected_eof());
+        }
+        let mut oldest_bytes = [0; SIZE_OF_U64];
+        bytes_read = load_buffer!(file, &mut oldest_bytes, path.as_ref().to_owned())?;
+        if bytes_read == 0 {
+            return Err(FileNode::unexpected_eof());
+        }
+        let mut newest_bytes = [0; SIZE_OF_U64];
+        bytes_read = load_buffer!(file, &mut newest_bytes, path.as_ref().to_owned())?;
+        if bytes_read == 0 {
+            return Err(FileNode::unexpected_eof());
+        }
+        let stats = (
+            u64::from_le_bytes(entry_count_bytes) as usize,
+            util::milliseconds_to_datetime(u64::from_le_bytes(oldest_bytes)),
+            util::milliseconds_to_datetime(u64::from_le_bytes(newest_bytes)),
+        );
+
+        // summaries written by older versions end after the stats or the sketch
+        let mut trailing_bytes = Vec::new();
+        file.read_to_end(&mut trailing_bytes)
+            .await
+            .map_err(|err| FileRead {
+                path: path.as_ref().to_owned(),
+                error: err,
+            })?;
+        let sketch_len = match trailing_bytes.starts_with(&PREFIX_SKETCH_MAGIC.to_le_bytes()) {
+            true => trailing_bytes.len().min(PrefixSketch::serialized_size()),
+            false => 0,
+        };
+        let (sketch_bytes, tombstone_bytes) = trailing_bytes.split_at(sketch_len);
+        let prefix_sketch = match sketch_bytes.is_empty() {
+            true => None,
+            false => Some(
+                PrefixSketch::deserialize(sketch_bytes)
+                    .ok_or_else(|| SummaryFileCorrupt(path.as_ref().to_owned()))?,
+            ),
+        };
+        let range_tombstones = match tombstone_bytes.is_empty() {
+            true => Vec::new(),
+            false => RangeTombstone::deserialize_all(tombstone_bytes)
+                .ok_or_else(|| SummaryFileCorrupt(path.as_ref().to_owned()))?,
+        };
+        return Ok((
+            smallest_key,
+            biggest_key,
+            Some(stats),
+            prefix_sketch,
+            range_tombstones,
+        ));
+    }
+}
+
+/// Sequential reader over [`FileNode::read_at`] with a cursor of its own
+///
+/// Reads a block at a time, so decoding small fields doesn't cost a syscall each
+#[derive(Debug)]
+pub(crate) struct ReadCursor<'a> {
+    node: &'a FileNode,
+    offset: u64,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl ReadCursor<'_> {
+    /// Reads into `out` until it is full or end of file is reached, returns
+    /// number of bytes read
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case of IO error
+    pub(crate) async fn read(&mut self, out: &mut Buf) -> Result<usize, Error> {
+        let mut total_bytes_read = 0;
+        while total_bytes_read < out.len() {
+            if self.pos == self.buf.len() {
+                self.buf.resize(BLOCK_SIZE.max(out.len() - total_bytes_read), 0);
+                let bytes_read = self.node.read_at(self.offset, &mut self.buf).await?;
+                self.buf.truncate(bytes_read);
+                self.offset += bytes_read as u64;
+                self.pos = 0;
+                if bytes_read == 0 {
+                    break;
+                }
+            }
+            let n = (out.len() - total_bytes_read).min(self.buf.len() - self.pos);
+            out[total_bytes_read..total_bytes_read + n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+            self.pos += n;
+            total_bytes_read += n;
+        }
+        Ok(total_bytes_read)
     }
 }
 
@@ -0,0 +1,69 @@
+use crate::err::Error;
+use std::{future::Future, sync::Arc};
+use tokio::runtime::{Builder, Handle, Runtime};
+
+/// Threads dedicated to value log and SSTable reads
+///
+/// Reads run on a runtime of their own, so a heavy flush or compaction keeping
+/// the threads of the store runtime and its blocking pool busy doesn't delay
+/// them. Clones share the threads, one pool can serve several stores.
+#[derive(Debug, Clone)]
+pub struct ReadPool {
+    runtime: Arc<PoolRuntime>,
+}
+
+/// Shuts the runtime down without waiting for reads still running, dropping a
+/// runtime from async context would panic
+#[derive(Debug)]
+struct PoolRuntime(Option<Runtime>);
+
+impl Drop for PoolRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl ReadPool {
+    /// Starts pool with `threads` threads for reads
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the threads could not be started
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threads` is zero
+    pub fn new(threads: usize) -> Result<Self, Error> {
+        assert!(threads > 0, "read pool should have at least one thread");
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(threads)
+            .max_blocking_threads(threads)
+            .thread_name("velarixdb-read")
+            .enable_all()
+            .build()
+            .map_err(Error::ReadPoolStart)?;
+        Ok(Self {
+            runtime: Arc::new(PoolRuntime(Some(runtime))),
+        })
+    }
+
+    /// Runs `read` on the pool and waits for it
+    ///
+    /// # Errors
+    ///
+    /// Returns error of `read`, or error if it panicked
+    pub(crate) async fn run<T, R>(&self, read: R) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        R: Future<Output = Result<T, Error>> + Send + 'static,
+    {
+        self.handle().spawn(read).await.map_err(|_| Error::TokioJoin)?
+    }
+
+    fn handle(&self) -> &Handle {
+        // only taken when the last clone is dropped
+        self.runtime.0.as_ref().unwrap().handle()
+    }
+}
@@ -0,0 +1,75 @@
+use crate::err::Error::{self, *};
+use std::{future::Future, time::Duration};
+
+/// Retries of file operations failing with transient IO errors
+///
+/// Interrupted, would-block and timed-out errors, as returned by network file
+/// systems under load, are retried with exponential backoff. Other errors and
+/// the error of the last attempt are returned as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts after the first one, zero disables retries
+    pub max_retries: usize,
+
+    /// Delay before the first retry, doubled after every retry
+    pub initial_backoff: Duration,
+
+    /// Longest delay between two attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    /// Returns delay before retry number `retry`, starting from zero
+    pub(crate) fn backoff(&self, retry: usize) -> Duration {
+        let factor = 1u32.checked_shl(retry as u32).unwrap_or(u32::MAX);
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+
+    /// Runs `op` until it succeeds, fails with an error that is not transient or retries are exhausted
+    ///
+    /// `op` is passed the number of the attempt, starting from zero, so it can
+    /// undo effects of a failed one first
+    pub(crate) async fn run<T, F, Fut>(&self, mut op: F) -> Result<T, Error>
+    where
+        F: FnMut(usize) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match op(attempt).await {
+                Err(err) if attempt < self.max_retries && is_transient(&err) => {
+                    log::warn!("Retrying transient IO error: {}", err);
+                    tokio::time::sleep(self.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Returns true if `err` is an IO error of an operation the policy may repeat that may not occur again
+fn is_transient(err: &Error) -> bool {
+    matches!(
+        err,
+        FileRead { .. } | FileWrite { .. } | FileClear { .. } | FileSeek { .. }
+    ) && err.is_retryable()
+}
@@ -3,25 +3,35 @@
 
 extern crate libc;
 extern crate nix;
-use crate::consts::{TAIL_ENTRY_KEY, TOMB_STONE_MARKER};
+use crate::block::BlockCache;
+use crate::bucket::FilePins;
+use crate::cfg::GcDiskPressure;
+use crate::consts::{DEFAULT_TOMBSTONE_TTL, GC_PUNCH_MARKER_FILE_NAME, TAIL_ENTRY_KEY, TOMB_STONE_MARKER};
+use crate::db::StatsRecorder;
+use crate::env::{supervise, BackgroundJob, Env, Timer};
 use crate::err::Error;
-use crate::fs::P;
+use crate::fs::{FileNode, RetryPolicy, P};
+use crate::gc::journal::{GcJournal, GcPhase};
+use crate::gc::{GcPass, GcTelemetry, WriteTracker};
 use crate::index::Index;
 use crate::memtable::{Entry, MemTable, SkipMapValue, K};
-use crate::sst::Table;
-use crate::types::{CreatedAt, ImmutableMemTables, Key, KeyRangeHandle, ValOffset, Value};
-use crate::vlog::{ValueLog, ValueLogEntry};
+use crate::sst::{RangeTombstones, Table};
+use crate::types::{CreatedAt, ImmutableMemTables, Key, KeyRangeHandle, Metadata, ValOffset, Value};
+use crate::vlog::{ValueKind, ValueLog, ValueLogEntry};
 use crate::{err, util};
 use chrono::Utc;
 use crossbeam_skiplist::SkipMap;
 use err::Error::*;
 use futures::future::join_all;
 use nix::libc::{c_int, off_t};
+use serde::{Deserialize, Serialize};
+use std::os::unix::fs::MetadataExt;
 use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 use tokio::sync::{Mutex, RwLock};
-use tokio::time::sleep;
 
 extern "C" {
     fn fallocate(fd: libc::c_int, mode: c_int, offset: off_t, len: off_t) -> c_int;
@@ -39,6 +49,9 @@ type GCLog = Arc<RwLock<ValueLog>>;
 /// Alias for thread-safe valid entries to re-insert
 type ValidEntries = Arc<RwLock<Vec<(Key, Value, ValOffset)>>>;
 
+/// Alias for thread-safe valid entries to rewrite to value log, with their metadata
+type EntriesToRewrite = Arc<RwLock<Vec<(Key, Value, Option<Metadata>)>>>;
+
 /// Alias thread-safe valid etries synced to disk
 type SyncedEntries = Arc<RwLock<Vec<(Key, Value, ValOffset)>>>;
 
@@ -46,6 +59,9 @@ type SyncedEntries = Arc<RwLock<Vec<(Key, Value, ValOffset)>>>;
 /// yet inserted to main store active memtable
 type GCUpdatedEntries<K> = Arc<RwLock<SkipMap<K, SkipMapValue<ValOffset>>>>;
 
+/// Alias for entries of a value log chunk with their offsets
+type ChunkEntries = Vec<(ValOffset, ValueLogEntry)>;
+
 // Alias for vlog head
 type Tail = usize;
 
@@ -73,24 +89,134 @@ pub struct GC {
 
     /// Keeps track of offsets to punch i.e remove
     pub(crate) punch_marker: Arc<Mutex<PunchMarker>>,
+
+    /// Pins of the store, no hole is punched while any is held
+    pub(crate) pins: FilePins,
 }
 
 /// GC Configuration
 #[derive(Clone, Debug)]
 pub(crate) struct Config {
-    pub online_gc_interval: std::time::Duration,
+    pub online_gc_interval: Timer,
     pub gc_chunk_size: usize,
+    pub env: Env,
+    pub block_cache: BlockCache,
+    pub io_retry: RetryPolicy,
+
+    /// How long soft deleted values are kept restorable
+    pub tombstone_ttl: std::time::Duration,
+
+    /// Counters and subscribers of passes
+    pub telemetry: GcTelemetry,
+
+    /// Bytes punched, counted for the stats of the store
+    pub reclaimed: Arc<AtomicU64>,
+
+    /// Keys written by the store while a pass runs
+    pub writes: WriteTracker,
+
+    /// Scaling of `gc_chunk_size` with free disk space, shared with running passes
+    pub disk_pressure: Arc<std::sync::RwLock<Option<GcDiskPressure>>>,
+
+    /// Range tombstones of the store, values of keys they cover are not moved
+    pub range_tombstones: RangeTombstones,
 }
 
 /// Marks area of value log file
 /// to be punched
-#[derive(Clone, Debug, Default)]
+///
+/// The marker is persisted next to the value log whenever it changes, so
+/// garbage collection resumes from the tail it reached after a restart and
+/// bytes carried over are punched once, with the range they are adjacent to.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PunchMarker {
     /// Offset in value log to start punching hole
     pub(crate) punch_hole_start_offset: usize,
 
     /// Length of holes to punch
     pub(crate) punch_hole_length: usize,
+
+    /// Offset of dead bytes left over from the previous punch because
+    /// they did not fill a whole file system block
+    pub(crate) carried_start_offset: usize,
+
+    /// Length of dead bytes left over from the previous punch
+    pub(crate) carried_length: usize,
+}
+
+impl PunchMarker {
+    /// Returns path of the marker in value log directory `dir`
+    pub(crate) fn path(dir: impl AsRef<Path>) -> PathBuf {
+        dir.as_ref().join(GC_PUNCH_MARKER_FILE_NAME)
+    }
+
+    /// Reads marker from value log directory `dir`, `None` if there is none
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the marker could not be read or decoded
+    pub(crate) async fn read(dir: impl AsRef<Path>) -> Result<Option<Self>, Error> {
+        let path = Self::path(dir);
+        let buf = match tokio::fs::read(&path).await {
+            Ok(buf) => buf,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(FileOpen { path, error: err }),
+        };
+        serde_json::from_slice(&buf)
+            .map(Some)
+            .map_err(|err| GCErrorPunchMarkerCorrupt { path, error: err })
+    }
+
+    /// Writes marker to value log directory `dir`, replacing the previous one
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case of IO error
+    pub(crate) async fn write(&self, dir: impl AsRef<Path>) -> Result<(), Error> {
+        let buf = serde_json::to_vec(self).map_err(|_| Serialization("garbage collection punch marker"))?;
+        FileNode::write_atomic(Self::path(dir), &buf).await
+    }
+
+    /// Returns tail garbage collection reached, all bytes before it are punched or carried over
+    pub(crate) fn reached_tail(&self) -> usize {
+        self.punch_hole_start_offset
+    }
+
+    /// Returns marker with nothing to punch from `tail` on, keeping bytes carried over before it
+    pub(crate) fn resume(self, tail: usize) -> Self {
+        let carried = self.carried_length > 0 && self.carried_start_offset + self.carried_length <= tail;
+        Self {
+            punch_hole_start_offset: tail,
+            punch_hole_length: 0,
+            carried_start_offset: if carried { self.carried_start_offset } else { tail },
+            carried_length: if carried { self.carried_length } else { 0 },
+        }
+    }
+
+    /// Returns range to punch, merged with the bytes left over from the previous
+    /// punch if both ranges are adjacent
+    pub(crate) fn range_to_punch(&self) -> (usize, usize) {
+        if self.carried_length > 0
+            && self.carried_start_offset + self.carried_length == self.punch_hole_start_offset
+        {
+            return (
+                self.carried_start_offset,
+                self.carried_length + self.punch_hole_length,
+            );
+        }
+        (self.punch_hole_start_offset, self.punch_hole_length)
+    }
+
+    /// Records the part of `range` that was not punched so that it can be merged
+    /// with the next range, and clears the range that was just handled
+    pub(crate) fn carry_over(&mut self, range: (usize, usize), punched: Option<(usize, usize)>) {
+        let (start, length) = range;
+        let punched_end = punched.map(|(offset, len)| offset + len).unwrap_or(start);
+        self.carried_start_offset = punched_end;
+        self.carried_length = start + length - punched_end;
+        self.punch_hole_start_offset = start + length;
+        self.punch_hole_length = 0;
+    }
 }
 
 impl GC {
@@ -101,55 +227,130 @@ impl GC {
         table: GCTable,
         vlog: GCLog,
         gc_updated_entries: GCUpdatedEntries<Key>,
+        env: Env,
+        block_cache: BlockCache,
     ) -> Self {
         Self {
             table,
             vlog,
             punch_marker: Arc::new(Mutex::new(PunchMarker::default())),
+            pins: FilePins::default(),
             gc_updated_entries,
             config: Config {
-                online_gc_interval,
+                online_gc_interval: Timer::new(online_gc_interval),
                 gc_chunk_size,
+                env,
+                block_cache,
+                io_retry: RetryPolicy::default(),
+                tombstone_ttl: DEFAULT_TOMBSTONE_TTL,
+                telemetry: GcTelemetry::default(),
+                reclaimed: Arc::default(),
+                writes: WriteTracker::default(),
+                disk_pressure: Arc::default(),
+                range_tombstones: RangeTombstones::default(),
             },
         }
     }
 
+    /// Moves delays between runs by up to `jitter` times the interval
+    pub(crate) fn with_jitter(mut self, jitter: f64) -> Self {
+        self.config.online_gc_interval = self.config.online_gc_interval.clone().with_jitter(jitter);
+        self
+    }
+
+    /// Retries sstable reads failing with transient IO errors according to `retry`
+    pub(crate) fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.config.io_retry = retry;
+        self
+    }
+
+    /// Resumes punching from `marker` read at open
+    pub(crate) fn with_punch_marker(mut self, marker: PunchMarker) -> Self {
+        self.punch_marker = Arc::new(Mutex::new(marker));
+        self
+    }
+
+    /// Scales chunks read by passes with free disk space according to `pressure`
+    pub(crate) fn with_disk_pressure(self, pressure: Option<GcDiskPressure>) -> Self {
+        self.set_disk_pressure(pressure);
+        self
+    }
+
+    /// Changes scaling of chunks with free disk space, also for a running garbage collector
+    pub(crate) fn set_disk_pressure(&self, pressure: Option<GcDiskPressure>) {
+        *self.config.disk_pressure.write().unwrap() = pressure;
+    }
+
+    /// Counts bytes punched in `stats`
+    pub(crate) fn with_stats(mut self, stats: &StatsRecorder) -> Self {
+        self.config.reclaimed = stats.gc_reclaimed();
+        self
+    }
+
+    /// Keeps soft deleted values restorable for `ttl`
+    pub(crate) fn with_tombstone_ttl(mut self, ttl: std::time::Duration) -> Self {
+        self.config.tombstone_ttl = ttl;
+        self
+    }
+
+    /// Treats values of keys deleted by `range_tombstones` of the store as invalid
+    pub(crate) fn with_range_tombstones(mut self, range_tombstones: RangeTombstones) -> Self {
+        self.config.range_tombstones = range_tombstones;
+        self
+    }
+
+    /// Shares pins with the buckets of the store
+    ///
+    /// Readers holding a [`FilePin`](crate::bucket::FilePin) may still read
+    /// value log entries that GC has moved, so their holes are punched later
+    pub(crate) fn with_pins(mut self, pins: FilePins) -> Self {
+        self.pins = pins;
+        self
+    }
+
     /// Continues to check if it's time to run GC (works in background)
     pub fn start_gc_worker(&self, key_range: KeyRangeHandle, read_only_memtables: ImmutableMemTables<Key>) {
         let cfg = self.config.to_owned();
         // NOTE: These are reference counter incrementation not deep clone
         let memtable = self.table.clone();
         let vlog = self.vlog.clone();
-        let table_ref = memtable.clone();
-        let vlog_ref = vlog.clone();
-        let key_range_ref = key_range.clone();
-        let read_only_memtables_ref = read_only_memtables.clone();
-        let gc_updated_entries_ref = self.gc_updated_entries.clone();
-        let punch_marker_ref = self.punch_marker.clone();
-        tokio::spawn(async move {
-            loop {
-                sleep_gc_task(cfg.online_gc_interval).await;
-                // if last valid entries is not synced with store memtable yet don't
-                // run another garbage collection
-                if !gc_updated_entries_ref.read().await.is_empty() {
-                    continue;
-                }
-                let res = GC::gc_handler(
-                    &cfg,
-                    table_ref.clone(),
-                    vlog_ref.clone(),
-                    key_range_ref.clone(),
-                    read_only_memtables_ref.clone(),
-                    gc_updated_entries_ref.clone(),
-                    punch_marker_ref.clone(),
-                )
-                .await;
-                match res {
-                    Ok(_) => {
-                        log::info!("GC successful, awaiting sync")
+        let gc_updated_entries = self.gc_updated_entries.clone();
+        let punch_marker = self.punch_marker.clone();
+        let errors = self.config.env.errors.clone();
+        supervise(BackgroundJob::GarbageCollection, errors, move || {
+            let cfg = cfg.to_owned();
+            let table_ref = memtable.clone();
+            let vlog_ref = vlog.clone();
+            let key_range_ref = key_range.clone();
+            let read_only_memtables_ref = read_only_memtables.clone();
+            let gc_updated_entries_ref = gc_updated_entries.clone();
+            let punch_marker_ref = punch_marker.clone();
+            async move {
+                loop {
+                    cfg.online_gc_interval.tick().await;
+                    // if last valid entries is not synced with store memtable yet don't
+                    // run another garbage collection
+                    if !gc_updated_entries_ref.read().await.is_empty() {
+                        continue;
                     }
-                    Err(err) => {
-                        log::error!("GC Error {}", err.to_string());
+                    let _permit = cfg.env.acquire(BackgroundJob::GarbageCollection).await;
+                    let res = GC::gc_handler(
+                        &cfg,
+                        table_ref.clone(),
+                        vlog_ref.clone(),
+                        key_range_ref.clone(),
+                        read_only_memtables_ref.clone(),
+                        gc_updated_entries_ref.clone(),
+                        punch_marker_ref.clone(),
+                    )
+                    .await;
+                    match res {
+                        Ok(_) => {
+                            log::info!("GC successful, awaiting sync")
+                        }
+                        Err(err) => {
+                            log::error!("GC Error {}", err);
+                        }
                     }
                 }
             }
@@ -174,40 +375,87 @@ impl GC {
         gc_updated_entries: GCUpdatedEntries<Key>,
         punch_marker: Arc<Mutex<PunchMarker>>,
     ) -> Result<(), Error> {
+        // chunk of the previous pass stays before the tail until the store synced it
+        if punch_marker.lock().await.punch_hole_length > 0 {
+            return Ok(());
+        }
+        // entries are checked against the store from here on, later writes win over their moves
+        let _tracking = cfg.writes.track();
         let invalid_entries = Arc::new(RwLock::new(Vec::new()));
         let valid_entries = Arc::new(RwLock::new(Vec::new()));
+        let soft_deleted_entries = Arc::new(RwLock::new(Vec::new()));
         let synced_entries = Arc::new(RwLock::new(Vec::new()));
+        let chunk_size = GC::chunk_size(cfg, &vlog).await;
         let vlog_reader = vlog.read().await;
-        let chunk_res = vlog_reader.read_chunk_to_garbage_collect(cfg.gc_chunk_size).await;
+        let chunk_res = vlog_reader.read_chunk_to_garbage_collect(chunk_size).await;
         drop(vlog_reader);
         match chunk_res {
             Ok((entries, total_bytes_read)) => {
-                let tasks = entries.into_iter().map(|entry| {
+                let tail_offset = vlog.read().await.tail_offset;
+                let (blobs, entries) = GC::split_blobs(entries, tail_offset);
+                let references: Vec<u64> = entries
+                    .iter()
+                    .map(|(_, e)| e)
+                    .filter(|e| e.kind == ValueKind::Reference)
+                    .filter_map(|e| GC::content_hash_of(&e.value))
+                    .collect();
+                let tasks = entries.into_iter().map(|(offset, entry)| {
                     // NOTE: These are reference counter incrementation not deep clone
                     let invalid_entries_ref = invalid_entries.clone();
                     let valid_entries_ref = valid_entries.clone();
+                    let soft_deleted_entries_ref = soft_deleted_entries.clone();
                     let table_ref = memtable.clone();
                     let vlog_ref = vlog.clone();
                     let key_range_ref = key_range.clone();
                     let read_only_memtables_ref = read_only_memtables.clone();
+                    let block_cache = cfg.block_cache.clone();
+                    let io_retry = cfg.io_retry;
+                    let tombstone_ttl = cfg.tombstone_ttl;
+                    let range_tombstones = cfg.range_tombstones.clone();
 
                     tokio::spawn(async move {
+                        if entry.is_soft_deleted() {
+                            // soft deleted value is kept while it is the current version and restorable
+                            let version = GC::latest_version(
+                                &entry.key,
+                                table_ref,
+                                key_range_ref,
+                                read_only_memtables_ref,
+                                &block_cache,
+                                &io_retry,
+                            )
+                            .await?;
+                            let current = version.is_some_and(|v| v.is_tombstone && v.val_offset == offset);
+                            if current && !entry.has_expired(tombstone_ttl) {
+                                soft_deleted_entries_ref.write().await.push(entry);
+                            } else {
+                                invalid_entries_ref.write().await.push(entry);
+                            }
+                            return Ok(());
+                        }
                         let most_recent_value = GC::get(
                             std::str::from_utf8(&entry.key).unwrap(),
                             table_ref.clone(),
                             key_range_ref.clone(),
                             vlog_ref.clone(),
                             read_only_memtables_ref.clone(),
+                            &block_cache,
+                            &io_retry,
                         )
                         .await;
                         match most_recent_value {
                             Ok((value, creation_time)) => {
-                                if entry.created_at < creation_time
+                                // value log keeps creation time in milliseconds
+                                if entry.created_at.timestamp_millis() < creation_time.timestamp_millis()
                                     || value == TOMB_STONE_MARKER.as_bytes().to_vec()
+                                    || range_tombstones.covers(&entry.key, creation_time)
                                 {
                                     invalid_entries_ref.write().await.push(entry);
                                 } else {
-                                    valid_entries_ref.write().await.push((entry.key, value));
+                                    valid_entries_ref
+                                        .write()
+                                        .await
+                                        .push((entry.key, value, entry.metadata));
                                 }
                                 Ok(())
                             }
@@ -224,11 +472,44 @@ impl GC {
                         }
                     }
                 }
+                let dedup = vlog.read().await.dedup.clone();
+                let mut live_blobs = Vec::new();
+                let mut dead_blobs = 0;
+                for (offset, blob) in blobs {
+                    let live = match (&dedup, GC::content_hash_of(&blob.key)) {
+                        (Some(dedup), Some(hash)) => {
+                            let moving = references.iter().filter(|h| **h == hash).count();
+                            dedup.retain(hash, offset, moving)
+                        }
+                        // blobs are kept while deduplication is disabled
+                        _ => true,
+                    };
+                    if live {
+                        live_blobs.push(blob);
+                    } else {
+                        dead_blobs += 1;
+                    }
+                }
+                let mut pass = GcPass {
+                    chunk_size: chunk_size as u64,
+                    bytes_scanned: total_bytes_read as u64,
+                    live_entries: (valid_entries.read().await.len()
+                        + soft_deleted_entries.read().await.len()
+                        + live_blobs.len()) as u64,
+                    dead_entries: (invalid_entries.read().await.len() + dead_blobs) as u64,
+                    ..Default::default()
+                };
                 // no entries to garbage collect, return early
-                if invalid_entries.read().await.is_empty() {
+                if pass.dead_entries == 0 {
+                    cfg.telemetry.record_pass(pass);
                     return Ok(());
                 }
+                // the store appended entries since this log was cloned
+                vlog.write().await.refresh_size().await;
                 let new_tail_offset = vlog.read().await.tail_offset + total_bytes_read;
+                let journal_dir = GC::journal_dir(&vlog).await;
+                let mut journal = GcJournal::planned(vlog.read().await.tail_offset, new_tail_offset);
+                journal.write(&journal_dir).await?;
                 let v_offset = GC::write_tail_to_disk(Arc::clone(&vlog), new_tail_offset).await?;
 
                 synced_entries.write().await.push((
@@ -237,30 +518,117 @@ impl GC {
                     v_offset,
                 ));
 
+                let size_before_rewrite = vlog.read().await.size;
+                for blob in live_blobs.iter() {
+                    vlog.write().await.relocate_blob(blob).await?;
+                }
+                // references of the chunk are moved or dropped, moved ones reference their blob again
+                if let Some(dedup) = &dedup {
+                    for hash in references {
+                        dedup.release(hash);
+                    }
+                }
                 GC::write_valid_entries_to_vlog(valid_entries, synced_entries.to_owned(), Arc::clone(&vlog))
                     .await?;
+                GC::write_soft_deleted_entries_to_vlog(
+                    soft_deleted_entries,
+                    synced_entries.to_owned(),
+                    Arc::clone(&vlog),
+                )
+                .await?;
+                pass.rewritten_bytes = (vlog.read().await.size - size_before_rewrite) as u64;
                 // call fsync on vlog to guarantee persistence to disk
                 vlog.write().await.sync_to_disk().await?;
+                journal.phase = GcPhase::Relocated;
+                journal.rewrites = synced_entries
+                    .read()
+                    .await
+                    .iter()
+                    .filter(|(key, _, _)| key != TAIL_ENTRY_KEY)
+                    .map(|(key, _, offset)| (key.to_owned(), *offset))
+                    .collect();
+                journal.write(&journal_dir).await?;
 
+                let head_before = vlog.read().await.head_offset;
                 GC::write_valid_entries_to_store(
                     synced_entries.to_owned(),
                     memtable.clone(),
                     gc_updated_entries,
                     vlog.clone(),
+                    &cfg.writes,
                 )
                 .await?;
+                pass.head_advanced = vlog.read().await.head_offset.saturating_sub(head_before) as u64;
+                pass.tail_advanced = total_bytes_read as u64;
+                cfg.telemetry.record_pass(pass);
 
                 // Don't free space or update tail immediatley until store active memtable is
                 // synced with gc table (handled seperately) but update punch hole marker
                 let mut marker_lock = punch_marker.lock().await;
                 marker_lock.punch_hole_start_offset = vlog.read().await.tail_offset;
                 marker_lock.punch_hole_length = total_bytes_read;
+                marker_lock.write(&journal_dir).await?;
             }
             Err(err) => return Err(err),
         };
         Ok(())
     }
 
+    /// Returns bytes of value log a pass reads, `gc_chunk_size` scaled by disk pressure
+    async fn chunk_size(cfg: &Config, vlog: &GCLog) -> usize {
+        let pressure = match cfg.disk_pressure.read().unwrap().clone() {
+            Some(pressure) => pressure,
+            None => return cfg.gc_chunk_size,
+        };
+        match FileNode::available_space(GC::journal_dir(vlog).await).await {
+            Ok(Some(available)) => {
+                let chunk_size = pressure.scale(cfg.gc_chunk_size, available);
+                if chunk_size > cfg.gc_chunk_size {
+                    log::info!("{} bytes free, GC chunk grown to {} bytes", available, chunk_size);
+                }
+                chunk_size
+            }
+            Ok(None) => cfg.gc_chunk_size,
+            Err(err) => {
+                log::warn!("{}, GC chunk not scaled", err);
+                cfg.gc_chunk_size
+            }
+        }
+    }
+
+    /// Returns directory of `vlog`, where the garbage collection journal is kept
+    pub(crate) async fn journal_dir(vlog: &GCLog) -> PathBuf {
+        let vlog_path = vlog.read().await.content.file.node.file_path.to_owned();
+        vlog_path
+            .parent()
+            .map(|dir| dir.to_path_buf())
+            .unwrap_or_default()
+    }
+
+    /// Separates blobs of a chunk read from `offset`, returning blobs and other entries with their offsets
+    pub(crate) fn split_blobs(
+        entries: Vec<ValueLogEntry>,
+        mut offset: usize,
+    ) -> (ChunkEntries, ChunkEntries) {
+        let mut blobs = Vec::new();
+        let mut others = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let entry_offset = offset;
+            offset += entry.encoded_len();
+            if entry.kind == ValueKind::Blob {
+                blobs.push((entry_offset, entry));
+            } else {
+                others.push((entry_offset, entry));
+            }
+        }
+        (blobs, others)
+    }
+
+    /// Decodes content hash stored in a blob key or reference value
+    fn content_hash_of(bytes: &[u8]) -> Option<u64> {
+        bytes.try_into().ok().map(u64::from_le_bytes)
+    }
+
     /// Inserts tail entry to value log
     pub(crate) async fn write_tail_to_disk(vlog: GCLog, new_tail_offset: usize) -> Result<ValOffset, Error> {
         vlog.write()
@@ -281,6 +649,7 @@ impl GC {
         table: GCTable,
         gc_updated_entries: GCUpdatedEntries<Key>,
         vlog: GCLog,
+        writes: &WriteTracker,
     ) -> Result<(), Error> {
         gc_updated_entries.write().await.clear();
         for (key, value, existing_v_offset) in valid_entries.to_owned().read().await.iter() {
@@ -290,6 +659,7 @@ impl GC {
                 *existing_v_offset,
                 table.clone(),
                 gc_updated_entries.clone(),
+                writes,
             )
             .await;
             // update  vlog head to the most recent entry offset
@@ -302,12 +672,17 @@ impl GC {
 
     /// Adds valid entries to value log
     pub(crate) async fn write_valid_entries_to_vlog(
-        valid_entries: Arc<RwLock<Vec<(Key, Value)>>>,
+        valid_entries: EntriesToRewrite,
         synced_entries: SyncedEntries,
         vlog: GCLog,
     ) -> Result<(), Error> {
-        for (key, value) in valid_entries.to_owned().read().await.iter() {
-            let v_offset = vlog.write().await.append(&key, &value, Utc::now(), false).await?;
+        for (key, value, metadata) in valid_entries.to_owned().read().await.iter() {
+            // metadata moves along with the value
+            let v_offset = vlog
+                .write()
+                .await
+                .append_with_metadata(key, value, metadata.as_deref(), Utc::now())
+                .await?;
             synced_entries
                 .write()
                 .await
@@ -316,6 +691,32 @@ impl GC {
         Ok(())
     }
 
+    /// Adds soft deleted entries to value log, keeping their value and deletion time
+    pub(crate) async fn write_soft_deleted_entries_to_vlog(
+        entries: Arc<RwLock<Vec<ValueLogEntry>>>,
+        synced_entries: SyncedEntries,
+        vlog: GCLog,
+    ) -> Result<(), Error> {
+        for entry in entries.read().await.iter() {
+            let v_offset = vlog
+                .write()
+                .await
+                .append_deleted(
+                    &entry.key,
+                    &entry.value,
+                    entry.metadata.as_deref(),
+                    entry.created_at,
+                )
+                .await?;
+            // empty value marks the entry deleted in the store
+            synced_entries
+                .write()
+                .await
+                .push((entry.key.to_owned(), Vec::new(), v_offset));
+        }
+        Ok(())
+    }
+
     #[allow(unused_variables)] // for non-linux environment
     /// Frees unused space on the disk
     ///
@@ -329,16 +730,32 @@ impl GC {
             return Err(GCErrorAttemptToRemoveUnsyncedEntries);
         }
         let vlog_path = self.vlog.read().await.content.file.node.file_path.to_owned();
-        let marker_lock = self.punch_marker.lock().await;
+        let marker_dir = GC::journal_dir(&self.vlog).await;
+        let mut marker_lock = self.punch_marker.lock().await;
+        if self.pins.is_pinned() {
+            // entries are already moved, only freeing their space is deferred; the range
+            // is merged with the next one and punched once nothing is pinned
+            let range = marker_lock.range_to_punch();
+            (self.vlog.write().await).tail_offset += marker_lock.punch_hole_length;
+            marker_lock.carry_over(range, None);
+            marker_lock.write(&marker_dir).await?;
+            let vlog_reader = self.vlog.read().await;
+            return Ok((vlog_reader.head_offset, vlog_reader.tail_offset));
+        }
         #[cfg(target_os = "linux")]
         {
-            GC::punch_holes(
-                vlog_path,
-                marker_lock.punch_hole_start_offset as i64,
-                marker_lock.punch_hole_length as i64,
-            )
-            .await?;
+            let range = marker_lock.range_to_punch();
+            let punched = GC::punch_holes(vlog_path, range.0 as i64, range.1 as i64).await?;
+            if let Some((_, len)) = punched {
+                self.config.telemetry.record_punch(len as u64);
+                self.config.reclaimed.fetch_add(len as u64, Ordering::Relaxed);
+            }
             (self.vlog.write().await).tail_offset += marker_lock.punch_hole_length;
+            marker_lock.carry_over(
+                range,
+                punched.map(|(offset, len)| (offset as usize, len as usize)),
+            );
+            marker_lock.write(&marker_dir).await?;
             let vlog_reader = self.vlog.read().await;
             Ok((vlog_reader.head_offset, vlog_reader.tail_offset))
         }
@@ -351,16 +768,42 @@ impl GC {
             // Even though punch wasn't successful due to OS incompatability, valid entires has been
             // synced to disk so we can update tail offset
             (self.vlog.write().await).tail_offset += marker_lock.punch_hole_length;
+            marker_lock.punch_hole_start_offset += marker_lock.punch_hole_length;
+            marker_lock.punch_hole_length = 0;
+            marker_lock.write(&marker_dir).await?;
             let vlog_reader = self.vlog.read().await;
             Ok((vlog_reader.head_offset, vlog_reader.tail_offset))
         }
     }
 
+    /// Shrinks `offset..offset + length` to whole blocks of `block_size` bytes
+    ///
+    /// Punching part of a block only zeroes it, the block is not released, so
+    /// such ranges are not worth punching
+    ///
+    /// Returns aligned offset and length, or `None` if the range does not cover a whole block
+    pub(crate) fn align_to_blocks(offset: off_t, length: off_t, block_size: off_t) -> Option<(off_t, off_t)> {
+        if block_size <= 0 || length <= 0 {
+            return None;
+        }
+        let start = (offset + block_size - 1) / block_size * block_size;
+        let end = (offset + length) / block_size * block_size;
+        if end <= start {
+            return None;
+        }
+        Some((start, end - start))
+    }
+
     /// Punch holes in value log file
     ///
     /// Deallocates space (i.e., creates a hole) in the byte range
-    /// starting at offset and continuing for len bytes
+    /// starting at offset and continuing for len bytes, the range is
+    /// first shrunk to whole file system blocks since only those are released
     /// <https://linux.die.net/man/2/fallocate>
+    ///
+    /// Returns the range that was punched, or `None` if the range
+    /// does not cover a whole block
+    ///
     /// # Errors
     ///
     /// Returns error in case punch failed
@@ -369,7 +812,7 @@ impl GC {
         file_path: impl 'static + P,
         offset: off_t,
         length: off_t,
-    ) -> std::result::Result<(), Error> {
+    ) -> std::result::Result<Option<(off_t, off_t)>, Error> {
         let punch_handle = tokio::task::spawn_blocking(move || {
             let file = std::fs::OpenOptions::new()
                 .read(true)
@@ -379,17 +822,29 @@ impl GC {
                     path: file_path.as_ref().to_path_buf(),
                     error: err,
                 })?;
+            let block_size = file
+                .metadata()
+                .map_err(|error| GetFileMetaData {
+                    path: file_path.as_ref().to_path_buf(),
+                    error,
+                })?
+                .blksize() as off_t;
+            let (offset, length) = match GC::align_to_blocks(offset, length, block_size) {
+                Some(range) => range,
+                None => return Ok(None),
+            };
 
             let fd = file.as_raw_fd();
             unsafe {
                 let result = fallocate(fd, FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE, offset, length);
                 // 0 return means the punch was successful
                 if result == 0 {
-                    Ok(())
+                    Ok(Some((offset, length)))
                 } else {
-                    Err(Error::GCErrorFailedToPunchHoleInVlogFile(
-                        std::io::Error::last_os_error(),
-                    ))
+                    Err(Error::GCErrorFailedToPunchHoleInVlogFile {
+                        path: file_path.as_ref().to_path_buf(),
+                        error: std::io::Error::last_os_error(),
+                    })
                 }
             }
         });
@@ -398,21 +853,26 @@ impl GC {
 
     /// Inserts valid entries to GC table
     ///
-    /// # Errors
-    ///
-    /// Returns error in case put fails
+    /// Entries of keys in `writes` are skipped, the store wrote a newer
+    /// version after the pass found them valid
     pub(crate) async fn put(
         key: impl AsRef<[u8]>,
         value: impl AsRef<[u8]>,
         val_offset: ValOffset,
         memtable: GCTable,
         gc_updated_entries: GCUpdatedEntries<Key>,
+        writes: &WriteTracker,
     ) {
         let is_tombstone = value.as_ref().is_empty();
         let created_at = Utc::now();
         let v_offset = val_offset;
         let entry = Entry::new(key.as_ref(), v_offset, created_at, is_tombstone);
-        memtable.write().await.insert(&entry);
+        // store records writes under the same lock
+        let mut table = memtable.write().await;
+        if writes.is_written(key.as_ref()) {
+            return;
+        }
+        table.insert(&entry);
         gc_updated_entries.write().await.insert(
             key.as_ref().to_vec(),
             SkipMapValue::new(v_offset, created_at, is_tombstone),
@@ -432,45 +892,67 @@ impl GC {
         key_range: KeyRangeHandle,
         vlog: Arc<RwLock<ValueLog>>,
         read_only_memtables: ImmutableMemTables<Key>,
+        block_cache: &BlockCache,
+        io_retry: &RetryPolicy,
     ) -> Result<(Value, CreatedAt), Error> {
+        let version = GC::latest_version(
+            key,
+            memtable,
+            key_range,
+            read_only_memtables,
+            block_cache,
+            io_retry,
+        )
+        .await?;
+        match version {
+            Some(version) if !version.is_tombstone => {
+                GC::get_value_from_vlog(&vlog, version.val_offset, version.created_at).await
+            }
+            _ => Err(NotFoundInDB),
+        }
+    }
+
+    /// Retrieves most recent version of key, including a deletion (searches GC Table first, then SSTables next)
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case search was not successful
+    pub(crate) async fn latest_version(
+        key: impl K,
+        memtable: GCTable,
+        key_range: KeyRangeHandle,
+        read_only_memtables: ImmutableMemTables<Key>,
+        block_cache: &BlockCache,
+        io_retry: &RetryPolicy,
+    ) -> Result<Option<SkipMapValue<ValOffset>>, Error> {
         let key = key.as_ref().to_vec();
         let mut offset = 0;
         let lowest_insert_date = util::default_datetime();
         let mut insert_time = util::default_datetime();
         // Step 1: Check the active memtable
         if let Some(value) = memtable.read().await.get(&key) {
-            if value.is_tombstone {
-                return Err(NotFoundInDB);
-            }
-            GC::get_value_from_vlog(&vlog, value.val_offset, value.created_at).await
-        } else {
-            // Step 2: Check the read-only memtables
-            let mut is_deleted = false;
-            for table in read_only_memtables.iter() {
-                if let Some(value) = table.value().get(&key) {
-                    if value.created_at > insert_time {
-                        offset = value.val_offset;
-                        insert_time = value.created_at;
-                        is_deleted = value.is_tombstone
-                    }
-                }
-            }
-            if GC::found_in_table(insert_time, lowest_insert_date) {
-                if is_deleted {
-                    return Err(NotFoundInDB);
+            return Ok(Some(value));
+        }
+        // Step 2: Check the read-only memtables
+        let mut is_deleted = false;
+        for table in read_only_memtables.iter() {
+            if let Some(value) = table.value().get(&key) {
+                if value.created_at > insert_time {
+                    offset = value.val_offset;
+                    insert_time = value.created_at;
+                    is_deleted = value.is_tombstone
                 }
-                GC::get_value_from_vlog(&vlog, offset, insert_time).await
-            } else {
-                // Step 3: Check sstables
-                let ssts = &key_range.filter_sstables_by_key_range(&key).await?;
-                GC::search_key_in_sstables(key, ssts.to_vec(), &vlog).await
             }
         }
+        if GC::found_in_table(insert_time, lowest_insert_date) {
+            return Ok(Some(SkipMapValue::new(offset, insert_time, is_deleted)));
+        }
+        // Step 3: Check sstables
+        let ssts = &key_range.filter_sstables_by_key_range(&key).await?;
+        GC::search_key_in_sstables(key, ssts.to_vec(), block_cache, io_retry).await
     }
 
-    /// Retrieves key from SSTable
-    ///
-    /// Returns tuple of value and date created
+    /// Retrieves most recent version of key from SSTables
     ///
     /// # Errors
     ///
@@ -478,8 +960,9 @@ impl GC {
     pub(crate) async fn search_key_in_sstables(
         key: impl AsRef<[u8]>,
         ssts: Vec<Table>,
-        val_log: &GCLog,
-    ) -> Result<(Value, CreatedAt), Error> {
+        block_cache: &BlockCache,
+        io_retry: &RetryPolicy,
+    ) -> Result<Option<SkipMapValue<ValOffset>>, Error> {
         let mut insert_time = util::default_datetime();
         let lowest_insert_date = util::default_datetime();
         let mut offset = 0;
@@ -488,11 +971,12 @@ impl GC {
             let index = Index::new(sst.index_file.path.to_owned(), sst.index_file.file.to_owned());
             let block_handle = index.get(&key).await?;
 
-            if block_handle.is_some() {
-                let sst_res = sst.get(block_handle.unwrap(), &key).await?;
+            if let Some(block_handle) = block_handle {
+                let sst_res = sst
+                    .get(block_handle, &key, block_cache, io_retry, None, true)
+                    .await?;
 
-                if sst_res.as_ref().is_some() {
-                    let (val_offset, created_at, is_tombstone) = sst_res.unwrap();
+                if let Some((val_offset, created_at, is_tombstone)) = sst_res {
                     if created_at > insert_time {
                         offset = val_offset;
                         insert_time = created_at;
@@ -502,12 +986,9 @@ impl GC {
             }
         }
         if GC::found_in_table(insert_time, lowest_insert_date) {
-            if is_deleted {
-                return Err(NotFoundInDB);
-            }
-            return GC::get_value_from_vlog(val_log, offset, insert_time).await;
+            return Ok(Some(SkipMapValue::new(offset, insert_time, is_deleted)));
         }
-        Err(NotFoundInDB)
+        Ok(None)
     }
 
     pub(crate) fn found_in_table(insert_time: CreatedAt, lowest_insert_date: CreatedAt) -> bool {
@@ -551,7 +1032,3 @@ impl GC {
         }
     }
 }
-
-async fn sleep_gc_task(duration: std::time::Duration) {
-    sleep(duration).await;
-}
@@ -0,0 +1,136 @@
+//! # Garbage Collection Journal
+//!
+//! Intent log of the last garbage collection cycle, kept next to the value log.
+//!
+//! A cycle is journaled as `Planned` before anything is written for it, with the
+//! tail it started from and the tail it moves to. Once relocated entries are
+//! synced to disk it is journaled again as `Relocated` with their new offsets.
+//! At next open the journal decides whether the cycle is rolled back, keeping the
+//! chunk, or rolled forward, punching the chunk and replaying the relocations.
+//! Both are idempotent, so the journal is kept until the next cycle replaces it.
+
+use crate::{
+    consts::GC_JOURNAL_FILE_NAME,
+    err::Error::{self, *},
+    fs::FileNode,
+    types::{Key, ValOffset},
+    vlog::ValueLog,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// Progress of a garbage collection cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum GcPhase {
+    /// Chunk was classified, nothing is relocated yet
+    Planned,
+
+    /// Valid entries of the chunk are relocated and synced to disk
+    Relocated,
+}
+
+/// Garbage collection cycle written before its effects
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct GcJournal {
+    pub(crate) phase: GcPhase,
+
+    /// Tail the cycle started from, start of the range to punch
+    pub(crate) tail_offset: usize,
+
+    /// Tail once the cycle completes, end of the range to punch
+    pub(crate) new_tail_offset: usize,
+
+    /// Keys of relocated entries with their new offsets
+    pub(crate) rewrites: Vec<(Key, ValOffset)>,
+}
+
+impl GcJournal {
+    /// Creates `Planned` journal of a cycle moving the tail from `tail_offset` to `new_tail_offset`
+    pub(crate) fn planned(tail_offset: usize, new_tail_offset: usize) -> Self {
+        Self {
+            phase: GcPhase::Planned,
+            tail_offset,
+            new_tail_offset,
+            rewrites: Vec::new(),
+        }
+    }
+
+    /// Returns path of the journal in value log directory `dir`
+    pub(crate) fn path(dir: impl AsRef<Path>) -> PathBuf {
+        dir.as_ref().join(GC_JOURNAL_FILE_NAME)
+    }
+
+    /// Reads journal from value log directory `dir`, `None` if there is none
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the journal could not be read or decoded
+    pub(crate) async fn read(dir: impl AsRef<Path>) -> Result<Option<Self>, Error> {
+        let path = Self::path(dir);
+        let buf = match fs::read(&path).await {
+            Ok(buf) => buf,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(FileOpen { path, error: err }),
+        };
+        serde_json::from_slice(&buf)
+            .map(Some)
+            .map_err(|err| GCErrorJournalCorrupt { path, error: err })
+    }
+
+    /// Writes journal to value log directory `dir`, replacing the previous one
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case of IO error
+    pub(crate) async fn write(&self, dir: impl AsRef<Path>) -> Result<(), Error> {
+        let buf = serde_json::to_vec(self).map_err(|_| Serialization("garbage collection journal"))?;
+        FileNode::write_atomic(Self::path(dir), &buf).await
+    }
+
+    /// Decides if the cycle can be rolled forward with the entries found in `vlog`
+    ///
+    /// A `Relocated` cycle is rolled forward only if every relocated entry reads
+    /// back with its key, otherwise it is rolled back like a `Planned` one
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case of IO error
+    pub(crate) async fn can_roll_forward(&self, vlog: &ValueLog) -> Result<bool, Error> {
+        if self.phase != GcPhase::Relocated {
+            return Ok(false);
+        }
+        for (key, offset) in self.rewrites.iter() {
+            match vlog.entry_at(*offset).await {
+                Ok(Some(entry)) if &entry.key == key => {}
+                Ok(_) | Err(ChecksumMismatch { .. } | UnexpectedEOF(_)) => return Ok(false),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Returns head and tail to recover from, given `head` and `tail` read from meta
+    ///
+    /// A `tail` past the cycle means meta was written after it completed and both
+    /// are kept. Rolling back keeps the chunk, only a tail behind the cycle start
+    /// is moved up to it since earlier cycles already punched that range. Rolling
+    /// forward skips the chunk and moves the head back to the first relocated
+    /// entry so relocations that never reached an SSTable are replayed.
+    pub(crate) fn recover(&self, roll_forward: bool, head: usize, tail: usize) -> (usize, usize) {
+        if tail >= self.new_tail_offset {
+            return (head, tail);
+        }
+        if !roll_forward {
+            let tail = tail.max(self.tail_offset);
+            return (head.max(tail), tail);
+        }
+        let tail = tail.max(self.new_tail_offset);
+        let head = self
+            .rewrites
+            .iter()
+            .map(|(_, offset)| *offset)
+            .fold(head.max(tail), usize::min);
+        (head, tail)
+    }
+}
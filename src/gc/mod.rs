@@ -1 +1,7 @@
 pub(crate) mod garbage_collector;
+pub(crate) mod journal;
+mod stats;
+mod writes;
+pub(crate) use stats::GcTelemetry;
+pub use stats::{GcPass, GcStats, GcSubscription};
+pub(crate) use writes::WriteTracker;
@@ -0,0 +1,233 @@
+use crate::{consts::DEFAULT_GC_EVENT_CHANNEL_SIZE, db::DataStore, memtable::Val, types::Key};
+use async_broadcast::{broadcast, InactiveReceiver, Receiver, RecvError, Sender, TryRecvError};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// Sent to garbage collection subscribers after each pass over a value log chunk
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcPass {
+    /// Bytes of value log the pass was allowed to read, grows under disk pressure
+    pub chunk_size: u64,
+
+    /// Bytes of the value log chunk read by the pass
+    pub bytes_scanned: u64,
+
+    /// Entries of the chunk still referenced, moved to the head of the value log
+    pub live_entries: u64,
+
+    /// Entries of the chunk overwritten or deleted, their space is freed
+    pub dead_entries: u64,
+
+    /// Bytes appended to the value log to move live entries
+    pub rewritten_bytes: u64,
+
+    /// Bytes the tail of the value log moves forward once the store picks up the moved entries
+    pub tail_advanced: u64,
+
+    /// Bytes the head of the value log moved forward
+    pub head_advanced: u64,
+}
+
+/// Garbage collection work of a store, returned by [`DataStore::gc_stats`]
+///
+/// Counters are cumulative since the store was opened. Few dead entries per
+/// pass mean chunks are read and rewritten for little gain, a larger
+/// `gc_chunk_size` or a longer `online_gc_interval` may suit the workload better.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcStats {
+    /// Passes that read a value log chunk
+    pub passes: u64,
+
+    /// Bytes of value log read by all passes
+    pub bytes_scanned: u64,
+
+    /// Entries found still referenced and moved
+    pub live_entries: u64,
+
+    /// Entries found overwritten or deleted
+    pub dead_entries: u64,
+
+    /// Bytes appended to the value log to move live entries
+    pub rewritten_bytes: u64,
+
+    /// Bytes of value log released to the file system by punching holes
+    pub bytes_punched: u64,
+
+    /// Bytes the tail of the value log moved forward
+    pub tail_advanced: u64,
+
+    /// Bytes the head of the value log moved forward
+    pub head_advanced: u64,
+}
+
+/// Counters behind [`GcStats`] and the channel of [`GcPass`] events, shared by clones
+#[derive(Debug, Clone)]
+pub(crate) struct GcTelemetry {
+    passes: Arc<AtomicU64>,
+    bytes_scanned: Arc<AtomicU64>,
+    live_entries: Arc<AtomicU64>,
+    dead_entries: Arc<AtomicU64>,
+    rewritten_bytes: Arc<AtomicU64>,
+    bytes_punched: Arc<AtomicU64>,
+    tail_advanced: Arc<AtomicU64>,
+    head_advanced: Arc<AtomicU64>,
+    tx: Sender<GcPass>,
+
+    /// Keeps the channel open while nobody is subscribed
+    rx: InactiveReceiver<GcPass>,
+}
+
+impl Default for GcTelemetry {
+    fn default() -> Self {
+        let (mut tx, rx) = broadcast(DEFAULT_GC_EVENT_CHANNEL_SIZE);
+        tx.set_overflow(true);
+        tx.set_await_active(false);
+        Self {
+            passes: Arc::default(),
+            bytes_scanned: Arc::default(),
+            live_entries: Arc::default(),
+            dead_entries: Arc::default(),
+            rewritten_bytes: Arc::default(),
+            bytes_punched: Arc::default(),
+            tail_advanced: Arc::default(),
+            head_advanced: Arc::default(),
+            tx,
+            rx: rx.deactivate(),
+        }
+    }
+}
+
+impl GcTelemetry {
+    /// Adds `pass` to the counters and sends it to subscribers
+    pub(crate) fn record_pass(&self, pass: GcPass) {
+        self.passes.fetch_add(1, Ordering::Relaxed);
+        self.bytes_scanned
+            .fetch_add(pass.bytes_scanned, Ordering::Relaxed);
+        self.live_entries.fetch_add(pass.live_entries, Ordering::Relaxed);
+        self.dead_entries.fetch_add(pass.dead_entries, Ordering::Relaxed);
+        self.rewritten_bytes
+            .fetch_add(pass.rewritten_bytes, Ordering::Relaxed);
+        self.tail_advanced
+            .fetch_add(pass.tail_advanced, Ordering::Relaxed);
+        self.head_advanced
+            .fetch_add(pass.head_advanced, Ordering::Relaxed);
+        // fails only without subscribers
+        let _ = self.tx.try_broadcast(pass);
+    }
+
+    /// Records `len` bytes of value log punched
+    pub(crate) fn record_punch(&self, len: u64) {
+        self.bytes_punched.fetch_add(len, Ordering::Relaxed);
+    }
+
+    /// Returns current values of the counters
+    pub(crate) fn stats(&self) -> GcStats {
+        GcStats {
+            passes: self.passes.load(Ordering::Relaxed),
+            bytes_scanned: self.bytes_scanned.load(Ordering::Relaxed),
+            live_entries: self.live_entries.load(Ordering::Relaxed),
+            dead_entries: self.dead_entries.load(Ordering::Relaxed),
+            rewritten_bytes: self.rewritten_bytes.load(Ordering::Relaxed),
+            bytes_punched: self.bytes_punched.load(Ordering::Relaxed),
+            tail_advanced: self.tail_advanced.load(Ordering::Relaxed),
+            head_advanced: self.head_advanced.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns subscription to passes made from now on
+    pub(crate) fn subscribe(&self) -> GcSubscription {
+        GcSubscription {
+            rx: self.rx.activate_cloned(),
+        }
+    }
+}
+
+/// Receives a [`GcPass`] for every garbage collection pass of a store
+///
+/// Returned by [`DataStore::subscribe_gc`]. Passes are buffered, a
+/// subscriber that falls too far behind skips the oldest ones.
+#[derive(Debug)]
+pub struct GcSubscription {
+    rx: Receiver<GcPass>,
+}
+
+impl GcSubscription {
+    /// Waits for the next pass
+    ///
+    /// Returns `None` once the store has been dropped
+    pub async fn recv(&mut self) -> Option<GcPass> {
+        loop {
+            match self.rx.recv().await {
+                Ok(pass) => return Some(pass),
+                Err(RecvError::Overflowed(skipped)) => {
+                    log::warn!("GC subscriber fell behind, skipped {} passes", skipped)
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Returns next pass if one is buffered, without waiting
+    pub fn try_recv(&mut self) -> Option<GcPass> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(pass) => return Some(pass),
+                Err(TryRecvError::Overflowed(_)) => continue,
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Returns how much garbage collection read, moved and freed
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+    ///
+    ///     // garbage collection has not run yet
+    ///     assert_eq!(store.gc_stats().passes, 0);
+    /// }
+    /// ```
+    pub fn gc_stats(&self) -> GcStats {
+        self.gc.config.telemetry.stats()
+    }
+
+    /// Subscribes to garbage collection passes
+    ///
+    /// Every pass that completes after this call is delivered to the returned
+    /// [`GcSubscription`] as a [`GcPass`], e.g. to export metrics per pass.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+    ///
+    ///     let mut passes = store.subscribe_gc();
+    ///     tokio::spawn(async move {
+    ///         while let Some(pass) = passes.recv().await {
+    ///             println!("scanned {} bytes, {} dead entries", pass.bytes_scanned, pass.dead_entries);
+    ///         }
+    ///     });
+    /// }
+    /// ```
+    pub fn subscribe_gc(&self) -> GcSubscription {
+        self.gc.config.telemetry.subscribe()
+    }
+}
@@ -0,0 +1,69 @@
+use crate::types::Key;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+/// Keys the store wrote while a garbage collection pass is running
+///
+/// A pass checks which entries of its chunk are still the latest version
+/// before moving them, a key written after that check holds a newer version
+/// the move must not replace. Keys and deleted ranges are recorded under the
+/// GC table lock, the same lock the pass holds while it moves an entry.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct WriteTracker {
+    /// Writes since the running pass started, `None` while no pass runs
+    written: Arc<Mutex<Option<Written>>>,
+}
+
+#[derive(Debug, Default)]
+struct Written {
+    keys: HashSet<Key>,
+
+    /// Start and end of ranges deleted by [`DataStore::delete_range`](crate::db::DataStore::delete_range)
+    ranges: Vec<(Key, Key)>,
+}
+
+/// Stops tracking writes once the pass that started it returns
+pub(crate) struct Tracking<'a> {
+    tracker: &'a WriteTracker,
+}
+
+impl WriteTracker {
+    /// Starts tracking writes for a pass, until the returned guard is dropped
+    pub(crate) fn track(&self) -> Tracking<'_> {
+        *self.written.lock().unwrap() = Some(Written::default());
+        Tracking { tracker: self }
+    }
+
+    /// Records write to `key` if a pass is running
+    pub(crate) fn record(&self, key: &[u8]) {
+        if let Some(written) = self.written.lock().unwrap().as_mut() {
+            written.keys.insert(key.to_vec());
+        }
+    }
+
+    /// Records deletion of keys from `start` up to, not including, `end` if a pass is running
+    pub(crate) fn record_range(&self, start: &[u8], end: &[u8]) {
+        if let Some(written) = self.written.lock().unwrap().as_mut() {
+            written.ranges.push((start.to_vec(), end.to_vec()));
+        }
+    }
+
+    /// Returns true if `key` was written or deleted since the running pass started
+    pub(crate) fn is_written(&self, key: &[u8]) -> bool {
+        self.written.lock().unwrap().as_ref().is_some_and(|written| {
+            written.keys.contains(key)
+                || written
+                    .ranges
+                    .iter()
+                    .any(|(start, end)| key >= start.as_slice() && key < end.as_slice())
+        })
+    }
+}
+
+impl Drop for Tracking<'_> {
+    fn drop(&mut self) {
+        *self.tracker.written.lock().unwrap() = None;
+    }
+}
@@ -48,11 +48,22 @@
 //! 2. Key: Variable-length key bytes, representing the last key in the block.
 //! 3. Block Handle: A 4-byte length prefix in little-endian format, indicating the start of the block in the data file
 //! - TODO: Block compresion size:  A 4-byte length prefix in little-endian format, indicating the compressed size of the block
-use crate::consts::SIZE_OF_U32;
+//!
+//! Entries are followed by a bitmap with a bit per block, set if the block holds only tombstones,
+//! the length of the bitmap, a CRC-32 checksum of entries and bitmap and `INDEX_TOMBSTONE_MAGIC`.
+//! Lengths, checksum and magic are 4 bytes in little-endian format. Index files written by older
+//! versions end with a checksum of the entries and `INDEX_CHECKSUM_MAGIC`, or after the last entry.
+//!
+//! The index is loaded once into a [`SparseIndex`] and searched with binary search. An index that
+//! fails its checksum is rebuilt from the data file.
+use crate::block::Block;
+use crate::consts::{INDEX_CHECKSUM_MAGIC, INDEX_TOMBSTONE_MAGIC, SIZE_OF_U32};
 use crate::err::Error;
 use crate::fs::{FileAsync, IndexFileNode, IndexFs};
-use crate::types::{ByteSerializedEntry, Key};
+use crate::types::{ByteSerializedEntry, Key, SkipMapEntries};
+use crate::util;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use Error::*;
 type Offset = u32;
@@ -76,7 +87,7 @@ impl<F: IndexFs> IndexFile<F> {
 }
 
 /// Represents each entry in the index
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct IndexEntry {
     /// Key length used to determine the length of key during
     /// retrieval from file
@@ -90,10 +101,69 @@ pub struct IndexEntry {
     // TODO: pub: compressed_size
 }
 
+impl IndexEntry {
+    /// Creates entry pointing at `block` written at `block_offset`
+    fn from_block(block: &Block, block_offset: usize) -> Self {
+        let last_entry = block.get_last_entry();
+        IndexEntry {
+            key_len: last_entry.key_prefix,
+            key: last_entry.key,
+            block_handle: block_offset as Offset,
+        }
+    }
+
+    /// Serializes entry to the byte layout used in index files
+    ///
+    /// The key length is written from `key` itself
+    #[doc(hidden)]
+    pub fn serialize(&self) -> ByteSerializedEntry {
+        let mut entry_vec = Vec::with_capacity(self.key.len() + SIZE_OF_U32 + SIZE_OF_U32);
+
+        // key len
+        entry_vec.extend_from_slice(&(self.key.len() as u32).to_le_bytes());
+
+        // key
+        entry_vec.extend_from_slice(&self.key);
+
+        // block offset
+        entry_vec.extend_from_slice(&self.block_handle.to_le_bytes());
+        entry_vec
+    }
+
+    /// Decodes index entries from bytes read from the index file
+    ///
+    /// Decoding stops at the first incomplete entry
+    #[doc(hidden)]
+    pub fn decode_entries(buf: &[u8]) -> Vec<IndexEntry> {
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        while offset + SIZE_OF_U32 <= buf.len() {
+            let key_len = u32::from_le_bytes(buf[offset..offset + SIZE_OF_U32].try_into().unwrap());
+            let entry_len = key_len as usize + SIZE_OF_U32 + SIZE_OF_U32;
+            if offset + entry_len > buf.len() {
+                break;
+            }
+            let pos = offset + SIZE_OF_U32;
+            let key = buf[pos..pos + key_len as usize].to_vec();
+            let handle_pos = pos + key_len as usize;
+            let block_handle =
+                u32::from_le_bytes(buf[handle_pos..handle_pos + SIZE_OF_U32].try_into().unwrap());
+            entries.push(IndexEntry {
+                key_len,
+                key,
+                block_handle,
+            });
+            offset += entry_len;
+        }
+        entries
+    }
+}
+
 /// Represents index
 #[derive(Debug, Clone)]
 pub struct Index {
     entries: Vec<IndexEntry>,
+    tombstone_blocks: Vec<bool>,
     file: IndexFile<IndexFileNode>,
 }
 
@@ -121,26 +191,30 @@ impl Index {
     pub fn new<P: AsRef<Path> + Send + Sync>(path: P, file: IndexFileNode) -> Self {
         Self {
             entries: Vec::new(),
+            tombstone_blocks: Vec::new(),
             file: IndexFile::new(path, file),
         }
     }
 
-    /// Inserts new entry
-    pub fn insert(&mut self, key_len: u32, key: Key, offset: Offset) {
+    /// Inserts new entry for a block, `only_tombstones` is set if every entry of the block is a tombstone
+    pub fn insert(&mut self, key_len: u32, key: Key, offset: Offset, only_tombstones: bool) {
         self.entries.push(IndexEntry {
             key_len,
             key,
             block_handle: offset,
-        })
+        });
+        self.tombstone_blocks.push(only_tombstones);
     }
 
-    /// Writes index to file,
+    /// Writes index to file, followed by its checksum
     /// Return IO error in case it happens
     pub async fn write_to_file(&self) -> Result<(), Error> {
+        let mut buf = Vec::new();
         for e in &self.entries {
-            let serialized_input = self.serialize_entry(e)?;
-            self.file.file.node.write_all(&serialized_input).await?;
+            buf.extend(self.serialize_entry(e)?);
         }
+        SparseIndex::append_trailer(&mut buf, &self.tombstone_blocks);
+        self.file.file.node.write_all(&buf).await?;
         Ok(())
     }
 
@@ -148,29 +222,293 @@ impl Index {
     ///
     /// Returns `ByteSerializedEntry`or Error if not
     fn serialize_entry(&self, e: &IndexEntry) -> Result<ByteSerializedEntry, Error> {
-        let entry_len = e.key.len() + SIZE_OF_U32 + SIZE_OF_U32;
-
-        let mut entry_vec = Vec::with_capacity(entry_len);
-
-        // key len
-        entry_vec.extend_from_slice(&(e.key_len).to_le_bytes());
-
-        // key
-        entry_vec.extend_from_slice(&e.key);
-
-        // block offset
-        entry_vec.extend_from_slice(&e.block_handle.to_le_bytes());
-        if entry_len != entry_vec.len() {
+        if e.key_len as usize != e.key.len() {
             return Err(Serialization("Invalid entry size"));
         }
-        Ok(entry_vec)
+        Ok(e.serialize())
     }
+
     /// Retrieves a Block Offset from index file
     pub(crate) async fn get(&self, searched_key: impl AsRef<[u8]>) -> Result<Option<BlockOffset>, Error> {
         self.file.file.get_from_index(searched_key.as_ref()).await
     }
 
+    /// Returns entries in index file, one per block of the sstable
+    pub(crate) async fn read_entries(&self) -> Result<Vec<IndexEntry>, Error> {
+        self.file.file.read_entries().await
+    }
+
     // pub(crate) async fn get_block_offset_range(&self, start_key: &[u8], end_key: &[u8]) -> Result<RangeOffset, Error> {
     //     self.file.file.get_block_range(start_key, end_key).await
     // }
 }
+
+/// Index entries of an sstable loaded in memory, sorted by key
+///
+/// Clones share the same entries.
+#[derive(Debug, Clone, Default)]
+pub struct SparseIndex {
+    entries: Arc<Vec<IndexEntry>>,
+
+    /// Per block, set if the block holds only tombstones. Empty for index files of older versions
+    tombstone_blocks: Arc<Vec<bool>>,
+}
+
+impl SparseIndex {
+    /// Creates `SparseIndex` from entries sorted by key
+    pub fn new(entries: Vec<IndexEntry>) -> Self {
+        Self {
+            entries: Arc::new(entries),
+            tombstone_blocks: Arc::default(),
+        }
+    }
+
+    /// Returns index with blocks holding only tombstones marked in `tombstone_blocks`
+    pub fn with_tombstone_blocks(mut self, tombstone_blocks: Vec<bool>) -> Self {
+        self.tombstone_blocks = Arc::new(tombstone_blocks);
+        self
+    }
+
+    /// Returns entries, one per block of the sstable
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// Returns per block flags, set if the block holds only tombstones
+    pub fn tombstone_blocks(&self) -> &[bool] {
+        &self.tombstone_blocks
+    }
+
+    /// Checks if block `idx` is known to hold only tombstones
+    pub fn is_tombstone_block(&self, idx: usize) -> bool {
+        self.tombstone_blocks.get(idx).copied().unwrap_or(false)
+    }
+
+    /// Returns start offset of the block `searched_key` can be in
+    ///
+    /// That is the first block whose last key is not less than `searched_key`
+    pub fn get(&self, searched_key: &[u8]) -> Option<BlockOffset> {
+        let idx = self.entries.partition_point(|e| e.key.as_slice() < searched_key);
+        self.entries.get(idx).map(|e| e.block_handle)
+    }
+
+    /// Returns offsets of blocks to read for keys between `start_key` and `end_key`
+    ///
+    /// Reading starts at the first block whose last key is not less than
+    /// `start_key` and stops before the block following the one `end_key` can be
+    /// in. End offset is `Offset::MAX` if that block is the last one.
+    pub fn get_block_range(&self, start_key: &[u8], end_key: &[u8]) -> RangeOffset {
+        let start = self.entries.partition_point(|e| e.key.as_slice() < start_key);
+        let end = self.entries.partition_point(|e| e.key.as_slice() < end_key) + 1;
+        RangeOffset::new(
+            self.entries.get(start).map_or(Offset::MAX, |e| e.block_handle),
+            self.entries.get(end).map_or(Offset::MAX, |e| e.block_handle),
+        )
+    }
+
+    /// Serializes entries followed by the tombstone bitmap and their checksum
+    pub(crate) fn encode(entries: &[IndexEntry], tombstone_blocks: &[bool]) -> ByteSerializedEntry {
+        let mut buf: ByteSerializedEntry = entries.iter().flat_map(IndexEntry::serialize).collect();
+        Self::append_trailer(&mut buf, tombstone_blocks);
+        buf
+    }
+
+    /// Appends the tombstone bitmap and checksum to serialized entries
+    fn append_trailer(buf: &mut ByteSerializedEntry, tombstone_blocks: &[bool]) {
+        let mut bitmap = vec![0u8; tombstone_blocks.len().div_ceil(8)];
+        for (idx, _) in tombstone_blocks.iter().enumerate().filter(|(_, only)| **only) {
+            bitmap[idx / 8] |= 1 << (idx % 8);
+        }
+        buf.extend_from_slice(&bitmap);
+        buf.extend_from_slice(&(bitmap.len() as u32).to_le_bytes());
+        let checksum = util::crc32(&[buf]);
+        buf.extend_from_slice(&checksum.to_le_bytes());
+        buf.extend_from_slice(&INDEX_TOMBSTONE_MAGIC.to_le_bytes());
+    }
+
+    /// Decodes content of an index file
+    ///
+    /// Returns `None` if the checksum does not match, entries are
+    /// truncated or not sorted. Files without checksum or tombstone
+    /// bitmap are accepted as long as their entries are well formed.
+    pub(crate) fn decode(buf: &[u8]) -> Option<SparseIndex> {
+        let trailer_len = SIZE_OF_U32 + SIZE_OF_U32;
+        let (body, bitmap) = match buf.len().checked_sub(trailer_len) {
+            Some(body_len) if buf[body_len + SIZE_OF_U32..] == INDEX_TOMBSTONE_MAGIC.to_le_bytes() => {
+                let checksum = u32::from_le_bytes(buf[body_len..body_len + SIZE_OF_U32].try_into().unwrap());
+                if util::crc32(&[&buf[..body_len]]) != checksum {
+                    return None;
+                }
+                let bitmap_end = body_len.checked_sub(SIZE_OF_U32)?;
+                let bitmap_len = u32::from_le_bytes(buf[bitmap_end..body_len].try_into().unwrap()) as usize;
+                let entries_len = bitmap_end.checked_sub(bitmap_len)?;
+                (&buf[..entries_len], &buf[entries_len..bitmap_end])
+            }
+            Some(body_len) if buf[body_len + SIZE_OF_U32..] == INDEX_CHECKSUM_MAGIC.to_le_bytes() => {
+                let checksum = u32::from_le_bytes(buf[body_len..body_len + SIZE_OF_U32].try_into().unwrap());
+                if util::crc32(&[&buf[..body_len]]) != checksum {
+                    return None;
+                }
+                (&buf[..body_len], &[][..])
+            }
+            _ => (buf, &[][..]),
+        };
+        let entries = IndexEntry::decode_entries(body);
+        let decoded_len: usize = entries
+            .iter()
+            .map(|e| e.key.len() + SIZE_OF_U32 + SIZE_OF_U32)
+            .sum();
+        if entries.is_empty() || decoded_len != body.len() || !entries.windows(2).all(|w| w[0].key < w[1].key)
+        {
+            return None;
+        }
+        let tombstone_blocks = if bitmap.is_empty() {
+            Vec::new()
+        } else {
+            (0..entries.len())
+                .map(|idx| {
+                    bitmap
+                        .get(idx / 8)
+                        .is_some_and(|byte| byte & (1 << (idx % 8)) != 0)
+                })
+                .collect()
+        };
+        Some(SparseIndex::new(entries).with_tombstone_blocks(tombstone_blocks))
+    }
+
+    /// Rebuilds index from entries of the data file
+    ///
+    /// Entries are split into blocks the same way they were when
+    /// the sstable was written, so block offsets match the data file.
+    pub(crate) fn rebuild(entries: &SkipMapEntries<Key>) -> Result<SparseIndex, Error> {
+        let mut index_entries = Vec::new();
+        let mut tombstone_blocks = Vec::new();
+        let mut block = Block::new();
+        let mut block_offset = 0;
+        for e in entries.iter() {
+            let entry_size = Block::entry_size(e.key());
+            if block.is_full(entry_size) {
+                index_entries.push(IndexEntry::from_block(&block, block_offset));
+                tombstone_blocks.push(block.has_only_tombstones());
+                block_offset += block.size;
+                block = Block::new();
+            }
+            block.set_entry(
+                e.key().len() as u32,
+                e.key(),
+                e.value().val_offset as u32,
+                e.value().created_at,
+                e.value().is_tombstone,
+            )?;
+        }
+        if !block.entries.is_empty() {
+            index_entries.push(IndexEntry::from_block(&block, block_offset));
+            tombstone_blocks.push(block.has_only_tombstones());
+        }
+        Ok(SparseIndex::new(index_entries).with_tombstone_blocks(tombstone_blocks))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memtable::SkipMapValue;
+    use crossbeam_skiplist::SkipMap;
+
+    fn entry(key: &[u8], block_handle: Offset) -> IndexEntry {
+        IndexEntry {
+            key_len: key.len() as u32,
+            key: key.to_vec(),
+            block_handle,
+        }
+    }
+
+    #[test]
+    fn test_sparse_index_get() {
+        let index = SparseIndex::new(vec![entry(b"c", 0), entry(b"f", 100), entry(b"k", 200)]);
+        assert_eq!(index.get(b"a"), Some(0));
+        assert_eq!(index.get(b"c"), Some(0));
+        assert_eq!(index.get(b"d"), Some(100));
+        assert_eq!(index.get(b"k"), Some(200));
+        assert_eq!(index.get(b"z"), None);
+    }
+
+    #[test]
+    fn test_sparse_index_get_block_range() {
+        let index = SparseIndex::new(vec![entry(b"c", 0), entry(b"f", 100), entry(b"k", 200)]);
+        let range = index.get_block_range(b"d", b"e");
+        assert_eq!((range.start_offset, range.end_offset), (100, 200));
+        let range = index.get_block_range(b"a", b"f");
+        assert_eq!((range.start_offset, range.end_offset), (0, 200));
+        let range = index.get_block_range(b"g", b"z");
+        assert_eq!((range.start_offset, range.end_offset), (200, Offset::MAX));
+        let range = index.get_block_range(b"x", b"z");
+        assert_eq!(range.start_offset, Offset::MAX);
+    }
+
+    #[test]
+    fn test_sparse_index_decode() {
+        let entries = vec![entry(b"apple", 0), entry(b"tesla", 4096)];
+        let mut buf = SparseIndex::encode(&entries, &[false, true]);
+        let index = SparseIndex::decode(&buf).unwrap();
+        assert_eq!(index.entries(), entries.as_slice());
+        assert_eq!(index.tombstone_blocks(), &[false, true]);
+        assert!(index.is_tombstone_block(1));
+
+        // index files of older versions have no tombstone bitmap or no checksum at all
+        let mut with_checksum: Vec<u8> = entries.iter().flat_map(IndexEntry::serialize).collect();
+        let without_checksum = with_checksum.clone();
+        let checksum = util::crc32(&[&with_checksum]);
+        with_checksum.extend_from_slice(&checksum.to_le_bytes());
+        with_checksum.extend_from_slice(&INDEX_CHECKSUM_MAGIC.to_le_bytes());
+        for old in [with_checksum, without_checksum] {
+            let index = SparseIndex::decode(&old).unwrap();
+            assert_eq!(index.entries(), entries.as_slice());
+            assert!(!index.is_tombstone_block(1));
+        }
+
+        buf[6] ^= 0xFF;
+        assert!(SparseIndex::decode(&buf).is_none());
+        assert!(SparseIndex::decode(&[]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_index_write_to_file() {
+        use crate::fs::{FileType, IndexFs};
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("index.db");
+        let file = IndexFileNode::new(&path, FileType::Index).await.unwrap();
+        let mut index = Index::new(&path, file);
+        index.insert(5, b"apple".to_vec(), 0, false);
+        index.insert(5, b"tesla".to_vec(), 4096, true);
+        index.write_to_file().await.unwrap();
+        let entries = vec![entry(b"apple", 0), entry(b"tesla", 4096)];
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            SparseIndex::encode(&entries, &[false, true])
+        );
+
+        index.insert(3, b"uber".to_vec(), 8192, false);
+        assert!(index.write_to_file().await.is_err());
+    }
+
+    #[test]
+    fn test_sparse_index_rebuild() {
+        let entries = Arc::new(SkipMap::new());
+        for i in 0..1000 {
+            entries.insert(
+                format!("key_{:04}", i).into_bytes(),
+                SkipMapValue::new(i, util::default_datetime(), false),
+            );
+        }
+        let index = SparseIndex::rebuild(&entries).unwrap();
+        let index_entries = index.entries();
+        assert!(index_entries.len() > 1);
+        assert_eq!(index_entries[0].block_handle, 0);
+        assert_eq!(index_entries.last().unwrap().key, b"key_0999".to_vec());
+        assert_eq!(index.tombstone_blocks(), vec![false; index_entries.len()]);
+
+        let data_size: usize = entries.iter().map(|e| Block::entry_size(e.key())).sum();
+        assert!((index.get(b"key_0999").unwrap() as usize) < data_size);
+    }
+}
@@ -1,4 +1,6 @@
 mod indexer;
 pub use indexer::Index;
+pub use indexer::IndexEntry;
 pub use indexer::IndexFile;
 pub use indexer::RangeOffset;
+pub use indexer::SparseIndex;
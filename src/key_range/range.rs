@@ -1,13 +1,17 @@
 use tokio::sync::RwLock;
 
 use crate::{
-    err::Error,
+    block::BlockCache,
+    consts::DATA_FILE_NAME,
+    db::overlaps,
+    err::Error::{self, FilterNotFound},
+    filter::{BloomFilter, FilterCache, FilterCounters},
     sst::Table,
     types::{self},
 };
 use std::{
-    cmp::Ordering,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    ops::{Bound, RangeBounds},
     path::{Path, PathBuf},
     sync::Arc,
 };
@@ -23,10 +27,15 @@ pub struct KeyRange {
     /// HashMap to map SSTable directory path to its key range
     pub key_ranges: Arc<RwLock<HashMap<PathBuf, Range>>>,
 
-    /// Maps SSTable path to its key range (for sstables
-    /// whose filters are just restored yet to be move to
-    /// `key_ranges`)
-    pub restored_ranges: Arc<RwLock<HashMap<PathBuf, Range>>>,
+    /// Filters of sstables in `key_ranges`, shared with other stores
+    /// using the same cache
+    pub filter_cache: FilterCache,
+
+    /// Blocks read from sstables in `key_ranges`, dropped with the sstable
+    pub(crate) block_cache: BlockCache,
+
+    /// Filter checks made by `filter_sstables_by_key_range`
+    pub(crate) filter_counters: FilterCounters,
 }
 
 /// Represents smallest and largest key in an sstable
@@ -46,6 +55,16 @@ impl Range {
         }
     }
 }
+impl Range {
+    /// Returns bounds of keys in the SSTable
+    fn span(&self) -> (Bound<&[u8]>, Bound<&[u8]>) {
+        (
+            Bound::Included(self.smallest_key.as_slice()),
+            Bound::Included(self.biggest_key.as_slice()),
+        )
+    }
+}
+
 impl Default for KeyRange {
     fn default() -> Self {
         Self::new()
@@ -54,19 +73,41 @@ impl Default for KeyRange {
 impl KeyRange {
     // Creates new `KeyRange``
     pub fn new() -> Self {
+        Self::with_filter_cache(FilterCache::default())
+    }
+
+    // Creates new `KeyRange` that keeps rebuilt filters in `filter_cache`
+    pub fn with_filter_cache(filter_cache: FilterCache) -> Self {
         Self {
             key_ranges: Arc::new(RwLock::new(HashMap::new())),
-            restored_ranges: Arc::new(RwLock::new(HashMap::new())),
+            filter_cache,
+            block_cache: BlockCache::default(),
+            filter_counters: FilterCounters::default(),
         }
     }
+
+    /// Sets cache that blocks of sstables in `key_ranges` are read into
+    pub(crate) fn with_block_cache(mut self, block_cache: BlockCache) -> Self {
+        self.block_cache = block_cache;
+        self
+    }
     /// Maps SSTable path to its key range
+    ///
+    /// Filter of `table` is moved to `filter_cache`, only its metadata
+    /// is kept in `key_ranges`
     pub async fn set<P: AsRef<Path> + Send + Sync, T: AsRef<[u8]>>(
         &self,
         sst_dir: P,
         smallest_key: T,
         biggest_key: T,
-        table: Table,
+        mut table: Table,
     ) -> bool {
+        if let Some(filter) = table.filter.as_mut() {
+            if filter.sst_dir.is_some() {
+                self.filter_cache.insert(&table.dir, filter.to_owned());
+            }
+            *filter = filter.without_bits();
+        }
         self.key_ranges
             .write()
             .await
@@ -79,12 +120,18 @@ impl KeyRange {
 
     /// Removes an entry from the `key_ranges` hash map
     pub async fn remove<P: AsRef<Path> + Send + Sync>(&self, sst_path: P) -> bool {
+        self.filter_cache.remove(sst_path.as_ref());
+        self.block_cache
+            .remove_sstable(sst_path.as_ref().join(format!("{}.db", DATA_FILE_NAME)));
         self.key_ranges.write().await.remove(sst_path.as_ref()).is_some()
     }
 
     /// Returns `Table`  vector whose last key is greater than the
     /// supplied key parameter
     ///
+    /// Only tables whose bloom filter may contain the key are returned,
+    /// so their index and data files are not read otherwise
+    ///
     /// # Errors
     ///
     /// Returns error in case failure occured
@@ -93,121 +140,107 @@ impl KeyRange {
         key: K,
     ) -> Result<Vec<Table>, Error> {
         let mut filtered_ssts: Vec<Table> = Vec::new();
-        let has_restored_ranges = !self.restored_ranges.read().await.is_empty();
-        if has_restored_ranges {
-            filtered_ssts = self.check_restored_key_ranges(key.as_ref()).await?;
-        }
-        let mut restored_range_map: HashMap<PathBuf, Range> = HashMap::new();
-        for (_, range) in self.key_ranges.read().await.iter() {
-            if has_restored_ranges
-                && self
-                    .restored_ranges
-                    .read()
-                    .await
-                    .contains_key(range.sst.dir.as_path())
-            {
-                continue;
-            }
-
-            let searched_key = key.as_ref().to_vec();
-            if searched_key >= range.smallest_key && searched_key <= range.biggest_key {
-                //  If an sstable does not have a bloom filter then
-                //  it means there has been a crash and we need to restore
-                //  filter from disk using filter metadata stored on sstable
-                if range.sst.filter.as_ref().unwrap().sst_dir.is_none() {
-                    let mut mut_range = range.to_owned();
-                    let mut filter = mut_range.sst.filter.as_ref().unwrap().to_owned();
-
-                    filter.recover_meta().await?;
-                    filter.sst_dir = Some(mut_range.sst.dir.to_owned());
-                    mut_range.sst.load_entries_from_file().await?;
-                    filter.build_filter_from_entries(&mut_range.sst.entries);
-                    // Don't keep sst entries in memory
-                    mut_range.sst.entries.clear();
-                    mut_range.sst.filter = Some(filter.to_owned());
-                    restored_range_map.insert(mut_range.sst.dir.to_owned(), mut_range.to_owned());
-
-                    if filter.contains(key.as_ref()) {
-                        filtered_ssts.push(mut_range.sst);
-                        continue;
-                    }
-                }
-
-                if range.sst.filter.as_ref().unwrap().contains(key.as_ref()) {
-                    filtered_ssts.push(range.sst.to_owned())
-                }
+        let searched_key = key.as_ref().to_vec();
+        let candidates: Vec<Table> = self
+            .key_ranges
+            .read()
+            .await
+            .values()
+            .filter(|range| searched_key >= range.smallest_key && searched_key <= range.biggest_key)
+            .map(|range| range.sst.to_owned())
+            .collect();
+        for mut sst in candidates {
+            let filter = self.load_filter(&sst).await?;
+            let contains = filter.contains(key.as_ref());
+            self.filter_counters.record(!contains);
+            if contains {
+                sst.filter = Some(filter);
+                filtered_ssts.push(sst);
             }
         }
-        if !restored_range_map.is_empty() {
-            // store the key ranges with sstables that contains
-            // bloom filters just restored to disk in the restored_ranges map. We are not
-            // updating key_ranges immediatlely to prevent write locks on key_ranges for
-            // get operations
-            let restored_ranges = self.restored_ranges.clone();
-
-            tokio::spawn(async move {
-                restored_range_map.clone_into(&mut (*restored_ranges.write().await));
-                drop(restored_ranges);
-            });
-        }
         Ok(filtered_ssts)
     }
 
-    /// Returns `Table` vector whose last key is greater than or equal to
-    /// the supplied key parameter
+    /// Returns filter of `sst` from `filter_cache`
     ///
-    /// NOTE: The search is carried out on sstables whose filters are just recoverd
+    /// Filters that are not cached (evicted or not read since a restart) are
+    /// loaded from the filter file, or rebuilt from sstable entries if the
+    /// file was written without `bit_vec`
     ///
     /// # Errors
     ///
     /// Returns error in case failure occured
-    pub async fn check_restored_key_ranges<K: AsRef<[u8]>>(&self, key: K) -> Result<Vec<Table>, Error> {
-        let mut filtered_ssts: Vec<Table> = Vec::new();
-        let key_ranges = self.restored_ranges.read().await;
-        for (_, range) in key_ranges.iter() {
-            let searched_key = key.as_ref().to_vec();
-            if searched_key >= range.smallest_key
-                && searched_key <= range.biggest_key
-                && range.sst.filter.as_ref().unwrap().contains(key.as_ref())
-            {
-                filtered_ssts.push(range.sst.to_owned())
-            }
+    pub(crate) async fn load_filter(&self, sst: &Table) -> Result<BloomFilter, Error> {
+        if let Some(filter) = self.filter_cache.get(&sst.dir) {
+            return Ok(filter);
         }
-        Ok(filtered_ssts)
+        let mut filter = sst.filter.as_ref().ok_or(FilterNotFound)?.to_owned();
+        if !filter.load().await? {
+            let mut table = sst.to_owned();
+            table.load_entries_from_file().await?;
+            filter.build_filter_from_entries(&table.entries);
+        }
+        filter.set_sstable_path(&sst.dir);
+        self.filter_cache.insert(&sst.dir, filter.to_owned());
+        Ok(filter)
     }
 
-    /// Moves entries in `restored_ranges` with sstables whose filters are just restored
-    /// to `key_ranges`
-    pub async fn update_key_range(&self) {
-        let restored_ranges = self.restored_ranges.read().await;
-        if !restored_ranges.is_empty() {
-            for (path, range) in restored_ranges.iter() {
-                self.key_ranges
-                    .write()
-                    .await
-                    .insert(path.to_owned(), range.to_owned());
-            }
-            drop(restored_ranges);
-            self.restored_ranges.write().await.clear();
+    /// Returns entry count of every sstable, except those in `excluded`
+    pub async fn entry_counts(&self, excluded: &[PathBuf]) -> Vec<usize> {
+        self.key_ranges
+            .read()
+            .await
+            .iter()
+            .filter(|(path, _)| !excluded.contains(path))
+            .map(|(_, range)| range.sst.entry_count())
+            .collect()
+    }
+
+    /// Returns estimated number of live keys starting with `prefix` over every sstable
+    ///
+    /// # Errors
+    ///
+    /// Returns IO error if the data file of a table without prefix sketch could not be read
+    pub(crate) async fn prefix_cardinality(&self, prefix: &[u8]) -> Result<usize, Error> {
+        let tables: Vec<Table> = self
+            .key_ranges
+            .read()
+            .await
+            .values()
+            .map(|range| range.sst.to_owned())
+            .collect();
+        let mut count = 0;
+        for table in tables {
+            count += table.prefix_cardinality(prefix).await?;
         }
+        Ok(count)
     }
 
-    /// Returns SSTables whose keys overlap with the key range supplied
+    /// Returns SSTables whose keys overlap with the key range supplied, both ends included
     pub async fn range_query_scan<T: AsRef<[u8]>>(&self, start_key: T, end_key: T) -> Vec<Range> {
+        let bounds = start_key.as_ref()..=end_key.as_ref();
+        self.key_ranges
+            .read()
+            .await
+            .values()
+            .filter(|range| overlaps(&bounds, range.span()))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns directories of SSTables holding no key within `bounds`
+    ///
+    /// SSTables missing from `key_ranges` are not returned, their keys are not known.
+    pub(crate) async fn disjoint_from<T: AsRef<[u8]>>(
+        &self,
+        bounds: &impl RangeBounds<T>,
+    ) -> HashSet<PathBuf> {
         self.key_ranges
             .read()
             .await
             .iter()
-            .filter(|(_, range)| {
-                // Check minimum range
-                (range.smallest_key.as_slice().cmp(start_key.as_ref()) == Ordering::Less
-                    || range.smallest_key.as_slice().cmp(start_key.as_ref()) == Ordering::Equal)
-
-                    // Check maximum range
-                    || (range.biggest_key.as_slice().cmp(end_key.as_ref()) == Ordering::Greater
-                        || range.biggest_key.as_slice().cmp(end_key.as_ref()) == Ordering::Equal)
-            })
-            .map(|(_, path)| path.to_owned())
+            .filter(|(_, range)| !overlaps(bounds, range.span()))
+            .map(|(dir, _)| dir.to_owned())
             .collect()
     }
 }
@@ -74,7 +74,7 @@
 //! - A relational database
 //!
 //! ### Constraint
-//! - Keys are limited to 65,536 bytes, and values are limited to 2^32 bytes. Larger keys and values have a bigger performance impact.
+//! - Keys are limited to 65,536 bytes and values to `u32::MAX` bytes by default, see `Config::max_key_size` and `Config::max_value_size`. Larger keys and values have a bigger performance impact.
 //!
 //! - Like any typical key-value store, keys are stored in lexicographic order.
 //!   If you are storing integer keys (e.g., timeseries data), use the big-endian form to adhere to locality.
@@ -121,7 +121,7 @@
 //!     store.delete("apple").await.unwrap();
 //!
 //!     // Update an entry
-//!     let success = store.update("microsoft", "elon musk").await;
+//!     let success = store.update_with("microsoft", |_| "elon musk").await;
 //!     assert!(success.is_ok());
 //! }
 //! ```
@@ -171,13 +171,19 @@
     html_favicon_url = "https://firebasestorage.googleapis.com/v0/b/generalsapi.appspot.com/o/Screenshot%202024-07-23%20at%2023.41.43.png?alt=media&token=109ab2a9-25d1-4a36-9d7e-7f8cfeb6ce6b"
 )]
 
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(all(test, not(feature = "bench")))]
+mod bench;
 mod block;
 mod bucket;
+mod cache;
 mod cfg;
 // contains compaction strategies
 pub mod compactors;
 mod consts;
 pub mod db;
+mod env;
 mod err;
 mod filter;
 mod flush;
@@ -191,6 +197,19 @@ mod meta;
 mod range;
 mod sst;
 mod tests;
+pub mod tools;
 mod types;
 mod util;
 mod vlog;
+
+// on-disk encoding of block, value log and index entries, for tooling built on the file formats
+#[doc(hidden)]
+pub mod codec {
+    pub use crate::block::{Block, BlockEntry};
+    pub use crate::index::IndexEntry;
+    pub use crate::vlog::{ValueKind, ValueLogEntry};
+}
+
+// makes file operations fail on command, for testing recovery handling
+#[cfg(feature = "fault-injection")]
+pub use fs::fault;
@@ -6,11 +6,13 @@
 //! Once the read-only memtable vector exceeds the `max_buffer_write_number` all memtable in the vector is flushed to to the disk concurrently
 
 use crate::bucket::InsertableToBucket;
-use crate::consts::{SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8};
-use crate::db::SizeUnit;
+use crate::consts::{MAX_MEMTABLE_CONTEXTS, SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8};
+use crate::db::{OpContext, SizeUnit};
 use crate::err::Error;
 use crate::filter::BloomFilter;
+use crate::sst::RangeTombstone;
 use crate::types::{CreatedAt, IsTombStone, Key, SkipMapEntries, ValOffset, Value};
+use crate::vlog::is_wal_offset;
 use chrono::Utc;
 use crossbeam_skiplist::SkipMap;
 use rand::distributions::Alphanumeric;
@@ -26,6 +28,14 @@ pub trait K: AsRef<[u8]> + Hash + Ord + Send + Sync + Clone + Debug {}
 
 impl<T> K for T where T: AsRef<[u8]> + Hash + Ord + Send + Sync + Clone + Debug {}
 
+/// Trait for values returned by `DataStore`
+///
+/// Implemented for every type that can be viewed as bytes and built from
+/// the bytes read back, e.g. `Vec<u8>`, `Box<[u8]>` or domain newtypes.
+pub trait Val: AsRef<[u8]> + From<Value> + Send + Sync {}
+
+impl<T> Val for T where T: AsRef<[u8]> + From<Value> + Send + Sync {}
+
 /// Each entry in `Memtable`
 #[derive(PartialOrd, PartialEq, Copy, Clone, Debug)]
 pub struct Entry<Key: K, V: Ord> {
@@ -37,18 +47,25 @@ pub struct Entry<Key: K, V: Ord> {
 
 /// Entry returned to user upon retreival
 #[derive(Debug)]
-pub struct UserEntry {
-    pub val: Value,
+pub struct UserEntry<V = Value> {
+    pub val: V,
     pub created_at: CreatedAt,
 }
 
-impl UserEntry {
+impl<V> UserEntry<V> {
     /// Creates new `UserEntry`
-    pub fn new(val: Value, created_at: CreatedAt) -> Self {
+    pub fn new(val: V, created_at: CreatedAt) -> Self {
         Self { val, created_at }
     }
 }
 
+impl UserEntry {
+    /// Converts value read from disk to value type of the store
+    pub(crate) fn into_val<V: Val>(self) -> UserEntry<V> {
+        UserEntry::new(V::from(self.val), self.created_at)
+    }
+}
+
 /// Value in SkipMap
 #[derive(Clone, Debug, PartialEq)]
 pub struct SkipMapValue<V: Ord> {
@@ -93,6 +110,12 @@ pub struct MemTable<Key: K> {
 
     /// Memtable configuration
     pub config: Config,
+
+    /// Contexts of the most recent writes made with one, logged if the flush fails
+    pub(crate) contexts: Vec<OpContext>,
+
+    /// Ranges deleted while the memtable was active, flushed to the summary of its sstable
+    pub(crate) range_tombstones: Vec<RangeTombstone>,
 }
 
 #[derive(Clone, Debug)]
@@ -166,6 +189,10 @@ impl InsertableToBucket for MemTable<Key> {
     fn get_filter(&self) -> BloomFilter {
         self.bloom_filter.to_owned()
     }
+
+    fn get_range_tombstones(&self) -> Vec<RangeTombstone> {
+        self.range_tombstones.clone()
+    }
 }
 
 impl MemTable<Key> {
@@ -200,7 +227,20 @@ impl MemTable<Key> {
             created_at: now,
             read_only: false,
             most_recent_entry: Entry::new(vec![], 0, Utc::now(), false),
+            contexts: Vec::new(),
+            range_tombstones: Vec::new(),
+        }
+    }
+
+    /// Remembers `context` of a write to the memtable, only the most recent ones are kept
+    pub(crate) fn record_context(&mut self, context: &OpContext) {
+        if self.contexts.last() == Some(context) {
+            return;
+        }
+        if self.contexts.len() == MAX_MEMTABLE_CONTEXTS {
+            self.contexts.remove(0);
         }
+        self.contexts.push(context.to_owned());
     }
 
     /// Inserts an entry to the `MemTable`
@@ -214,11 +254,23 @@ impl MemTable<Key> {
             entry.key.to_owned(),
             SkipMapValue::new(entry.val_offset, entry.created_at, entry.is_tombstone),
         );
-        if entry.val_offset > self.most_recent_entry.val_offset {
+        // write-ahead log entries are not in the value log yet, they can't be the head
+        if entry.val_offset > self.most_recent_entry.val_offset && !is_wal_offset(entry.val_offset) {
             entry.clone_into(&mut self.most_recent_entry);
         }
         self.size += entry_length_byte;
     }
+
+    /// Points key of `entry` to the value log offset its write-ahead log entry was moved to
+    pub(crate) fn relocate(&mut self, entry: &Entry<Key, ValOffset>) {
+        self.entries.insert(
+            entry.key.to_owned(),
+            SkipMapValue::new(entry.val_offset, entry.created_at, entry.is_tombstone),
+        );
+        if entry.val_offset > self.most_recent_entry.val_offset {
+            entry.clone_into(&mut self.most_recent_entry);
+        }
+    }
     /// Returns value for an entry or `None`
     pub fn get<EntryKey: K>(&self, key: EntryKey) -> Option<SkipMapValue<ValOffset>> {
         if self.bloom_filter.contains(&key.as_ref().to_vec()) {
@@ -328,6 +380,7 @@ impl MemTable<Key> {
         let max_no_of_entries = capacity_to_bytes / avg_entry_size as usize;
 
         self.entries.clear();
+        self.range_tombstones.clear();
         self.size = 0;
         self.bloom_filter = BloomFilter::new(self.config.false_pos_rate, max_no_of_entries);
     }
@@ -629,4 +682,20 @@ mod tests {
             .is_full(key.len() + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8 + memtable.capacity());
         assert!(is_full);
     }
+
+    #[test]
+    fn test_record_context_keeps_most_recent() {
+        let mut memtable = MemTable::new(51200, 0.01);
+        memtable.record_context(&OpContext::new("req-0"));
+        memtable.record_context(&OpContext::new("req-0"));
+        assert_eq!(memtable.contexts.len(), 1);
+
+        for i in 1..=MAX_MEMTABLE_CONTEXTS {
+            memtable.record_context(&OpContext::new(format!("req-{}", i)));
+        }
+        assert_eq!(memtable.contexts.len(), MAX_MEMTABLE_CONTEXTS);
+        assert_eq!(memtable.contexts[0].request_id(), "req-1");
+        // contexts move with the memtable when it becomes read-only
+        assert_eq!(memtable.to_owned().contexts, memtable.contexts);
+    }
 }
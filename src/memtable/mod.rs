@@ -3,4 +3,5 @@ pub use mem::Entry;
 pub use mem::MemTable;
 pub use mem::SkipMapValue;
 pub use mem::UserEntry;
+pub use mem::Val;
 pub use mem::K;
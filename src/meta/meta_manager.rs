@@ -55,10 +55,15 @@ impl Meta {
         })
     }
     /// Writes `Meta` to disk
+    ///
+    /// The meta file is replaced atomically, a crash during the
+    /// write leaves the previous meta file intact
     pub async fn write(&mut self) -> Result<(), Error> {
         let serialized_data = self.serialize();
-        self.file_handle.file.node.clear().await?;
-        self.file_handle.file.node.write_all(&serialized_data).await?;
+        FileNode::write_atomic(&self.file_handle.path, &serialized_data).await?;
+        // the open handle still refers to the replaced file
+        self.file_handle.file =
+            MetaFileNode::new(self.file_handle.path.to_owned(), crate::fs::FileType::Meta).await?;
         Ok(())
     }
     /// Sets `Meta` `v_log_head` field
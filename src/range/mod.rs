@@ -1,2 +1,4 @@
+mod page;
 mod range_iterator;
-pub use range_iterator::RangeIterator;
+pub use page::{ContinuationToken, Page};
+pub use range_iterator::{FetchedEntry, RangeIterator};
@@ -0,0 +1,267 @@
+use crate::consts::{HEAD_ENTRY_KEY, TAIL_ENTRY_KEY};
+use crate::db::{contains_key, overlaps, DataStore};
+use crate::err::Error;
+use crate::memtable::{SkipMapValue, Val};
+use crate::range::FetchedEntry;
+use crate::types::{Key, SkipMapEntries, ValOffset};
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+/// Version of the continuation token encoding
+const TOKEN_VERSION: u8 = 1;
+
+/// Opaque position after the last entry of a [`Page`]
+///
+/// Can be sent to clients with [`ContinuationToken::encode`] and passed back
+/// to [`DataStore::page`] after [`ContinuationToken::decode`], the store keeps no state between pages.
+/// Also returned by [`RangeIterator::checkpoint`](crate::db::RangeIterator::checkpoint)
+/// to resume a scan with [`DataStore::seek_after`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContinuationToken {
+    last_key: Key,
+}
+
+impl ContinuationToken {
+    /// Creates token positioned after `last_key`
+    pub(crate) fn after(last_key: Key) -> Self {
+        Self { last_key }
+    }
+
+    /// Returns lower bound of keys after the token, `start` if the token is before it
+    pub(crate) fn lower_bound(token: Option<&ContinuationToken>, start: &[u8]) -> Bound<Key> {
+        match token {
+            Some(token) if token.last_key.as_slice() >= start => Bound::Excluded(token.last_key.to_owned()),
+            _ => Bound::Included(start.to_vec()),
+        }
+    }
+
+    /// Returns the token as a hex string, safe to use in URLs
+    pub fn encode(&self) -> String {
+        std::iter::once(TOKEN_VERSION)
+            .chain(self.last_key.iter().copied())
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Reads token returned by [`ContinuationToken::encode`]
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidContinuationToken` if `token` was not returned by [`ContinuationToken::encode`]
+    pub fn decode(token: &str) -> Result<Self, Error> {
+        if !token.len().is_multiple_of(2) || !token.is_ascii() {
+            return Err(Error::InvalidContinuationToken);
+        }
+        let bytes = (0..token.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&token[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| Error::InvalidContinuationToken)?;
+        match bytes.split_first() {
+            Some((&TOKEN_VERSION, last_key)) => Ok(Self {
+                last_key: last_key.to_vec(),
+            }),
+            _ => Err(Error::InvalidContinuationToken),
+        }
+    }
+}
+
+/// Entries of a key range returned by [`DataStore::page`]
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub entries: Vec<FetchedEntry>,
+
+    /// Position to continue from, `None` once the range is exhausted
+    pub next: Option<ContinuationToken>,
+}
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Returns up to `limit` live entries with keys from `start` up to, but excluding, `end`
+    ///
+    /// Pass `next` of the returned page as `token` to get the following
+    /// entries. Each page only reads keys after the token, up to about `limit`
+    /// keys of every memtable and sstable. Pages are read independently,
+    /// writes made between two calls are seen by the later one if they fall
+    /// after its token.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::{ContinuationToken, DataStore};
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let mut store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+    ///     store.put("apple", "tim cook").await.unwrap();
+    ///     store.put("google", "sundar pichai").await.unwrap();
+    ///     store.put("nvidia", "jensen huang").await.unwrap();
+    ///
+    ///     let page = store.page("a", "z", 2, None).await.unwrap();
+    ///     assert_eq!(page.entries.len(), 2);
+    ///
+    ///     // token can be handed to a client and sent back with the next request
+    ///     let token = ContinuationToken::decode(&page.next.unwrap().encode()).unwrap();
+    ///     let page = store.page("a", "z", 2, Some(&token)).await.unwrap();
+    ///     assert_eq!(page.entries[0].key, b"nvidia".to_vec());
+    ///     assert!(page.next.is_none());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an sstable or a value could not be read
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is zero
+    pub async fn page<T: AsRef<[u8]>>(
+        &self,
+        start: T,
+        end: T,
+        limit: usize,
+        token: Option<&ContinuationToken>,
+    ) -> Result<Page, Error> {
+        assert!(limit > 0, "page limit should be greater than zero");
+        let mut lower = ContinuationToken::lower_bound(token, start.as_ref());
+        let upper = Bound::Excluded(end.as_ref().to_vec());
+        let mut keys = Vec::new();
+        // a window may hold fewer live keys than limit if most are deleted
+        let has_more = loop {
+            let (window, cutoff) = self
+                .collect_page_window((lower.clone(), upper.clone()), limit)
+                .await?;
+            keys.extend(window.into_iter().filter(|(key, value)| {
+                !value.is_tombstone && !self.range_tombstones.covers(key, value.created_at)
+            }));
+            match cutoff {
+                Some(cutoff) if keys.len() < limit => lower = Bound::Excluded(cutoff),
+                Some(_) => break true,
+                None => break keys.len() > limit,
+            }
+        };
+        keys.truncate(limit);
+
+        let mut entries = Vec::with_capacity(keys.len());
+        for (key, value) in keys.iter() {
+            if let Some(entry) = self.val_log.get_entry(value.val_offset).await? {
+                if self.config.verify_reads {
+                    entry.verify(key, value.val_offset)?;
+                }
+                entries.push(FetchedEntry {
+                    key: key.to_owned(),
+                    val: entry.value,
+                });
+            }
+        }
+        let next = match keys.last() {
+            Some((key, _)) if has_more => Some(ContinuationToken {
+                last_key: key.to_owned(),
+            }),
+            _ => None,
+        };
+        Ok(Page { entries, next })
+    }
+
+    /// Returns newest version of keys within `bounds` up to a cutoff key, and the cutoff
+    ///
+    /// Every source contributes at most `limit` keys. Keys past the smallest
+    /// last key of a source that had more are left for the next window, since
+    /// their versions in that source are not known yet. The cutoff is `None` if
+    /// every key up to the end of `bounds` was read.
+    async fn collect_page_window(
+        &self,
+        bounds: (Bound<Key>, Bound<Key>),
+        limit: usize,
+    ) -> Result<(BTreeMap<Key, SkipMapValue<ValOffset>>, Option<Key>), Error> {
+        let mut sources: Vec<Vec<(Key, SkipMapValue<ValOffset>)>> = Vec::new();
+        let from_memtable = |entries: &SkipMapEntries<Key>| {
+            entries
+                .range(bounds.clone())
+                .take(limit + 1)
+                .map(|e| (e.key().to_owned(), e.value().to_owned()))
+                .collect()
+        };
+        sources.push(from_memtable(&self.active_memtable.entries));
+        for table in self.read_only_memtables.iter() {
+            sources.push(from_memtable(&table.value().entries));
+        }
+        sources.push(
+            self.gc_updated_entries
+                .read()
+                .await
+                .range(bounds.clone())
+                .take(limit + 1)
+                .map(|e| (e.key().to_owned(), e.value().to_owned()))
+                .collect(),
+        );
+
+        let (_pin, tables) = {
+            let buckets = self.buckets.read().await;
+            let mut tables = Vec::new();
+            for bucket in buckets.buckets.values() {
+                tables.extend(bucket.sstables.read().await.iter().cloned());
+            }
+            (buckets.pins.pin(), tables)
+        };
+        let disjoint = self.key_range.disjoint_from(&bounds).await;
+        for table in tables.iter().filter(|t| !disjoint.contains(&t.dir)) {
+            let index = table.index_file.file.load().await?;
+            let mut source = Vec::new();
+            let mut block_lower = Bound::Unbounded;
+            for index_entry in index.entries().iter() {
+                let span = (block_lower, Bound::Included(index_entry.key.as_slice()));
+                block_lower = Bound::Excluded(index_entry.key.as_slice());
+                if !overlaps(&bounds, span) {
+                    continue;
+                }
+                let (block, _) = table
+                    .cached_block(
+                        index_entry.block_handle,
+                        &self.config.block_cache,
+                        &self.config.io_retry,
+                        self.config.read_pool.as_ref(),
+                    )
+                    .await?;
+                source.extend(block.iter().filter(|e| contains_key(&bounds, &e.key)).map(|e| {
+                    (
+                        e.key.to_owned(),
+                        SkipMapValue::new(e.value_offset as usize, e.creation_date, e.is_tombstone),
+                    )
+                }));
+                if source.len() > limit {
+                    break;
+                }
+            }
+            source.truncate(limit + 1);
+            sources.push(source);
+        }
+
+        // keys after the last one read from a source with more keys are not known yet
+        let cutoff = sources
+            .iter_mut()
+            .filter(|source| source.len() > limit)
+            .map(|source| {
+                source.truncate(limit);
+                source[limit - 1].0.to_owned()
+            })
+            .min();
+        let mut newest: BTreeMap<Key, SkipMapValue<ValOffset>> = BTreeMap::new();
+        for (key, value) in sources.into_iter().flatten() {
+            if cutoff.as_ref().is_some_and(|cutoff| &key > cutoff)
+                || key.as_slice() == HEAD_ENTRY_KEY
+                || key.as_slice() == TAIL_ENTRY_KEY
+            {
+                continue;
+            }
+            match newest.get(&key) {
+                Some(existing) if existing.created_at > value.created_at => {}
+                _ => {
+                    newest.insert(key, value);
+                }
+            }
+        }
+        Ok((newest, cutoff))
+    }
+}
@@ -1,68 +1,445 @@
-use crate::db::DataStore;
+use crate::block::BlockCache;
+use crate::bucket::FilePin;
+use crate::db::{contains_user_key, DataStore, RangeSnapshot};
 use crate::err::Error;
-use crate::memtable::Entry;
+use crate::fs::{ReadPool, RetryPolicy};
+use crate::memtable::{SkipMapValue, Val};
+use crate::range::ContinuationToken;
+use crate::sst::{RangeTombstone, Table};
 use crate::types::{Key, ValOffset, Value};
 use crate::vlog::ValueLog;
+use futures::future::{join_all, poll_fn, BoxFuture};
+use futures::{ready, FutureExt, Stream};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::ops::Bound;
+use std::pin::Pin;
+use std::sync::{Mutex, PoisonError};
+use std::task::{Context, Poll};
 
+/// Entry returned by [`RangeIterator`]
 #[derive(Debug, Clone)]
 pub struct FetchedEntry {
     pub key: Key,
     pub val: Value,
 }
 
-#[derive(Debug, Clone)]
-pub struct RangeIterator<'a> {
-    pub start: &'a [u8],
-    pub current: usize,
-    pub end: &'a [u8],
-    pub allow_prefetch: bool,
-    pub prefetch_entries_size: usize,
-    pub prefetch_entries: Vec<FetchedEntry>,
-    pub keys: Vec<Entry<Key, ValOffset>>,
-    pub v_log: ValueLog,
+/// Read in progress, hands the cursor back once it completes
+type PendingRead = BoxFuture<'static, (Box<Cursor>, Result<Option<FetchedEntry>, Error>)>;
+
+/// Iterates over live entries of a key range as they were when it was created
+///
+/// Memtable entries of the range and the SSTables holding it are captured when
+/// [`DataStore::seek`] is called, so memtable rotations, flushes and compactions
+/// happening during the scan don't change what is returned. SSTable blocks are
+/// read one at a time as the iterator advances and merged with the memtable
+/// entries, so memory use does not grow with the size of the range. Values are
+/// read from the value log as the iterator advances, GC does not free their space
+/// and compaction does not delete the SSTables until the iterator is dropped.
+///
+/// Entries are returned by [`RangeIterator::next`], or as `(key, value)` pairs
+/// by the [`Stream`] implementation.
+///
+/// # Examples
+///
+/// ```rust
+/// use futures::TryStreamExt;
+/// use velarixdb::db::DataStore;
+/// # use tempfile::tempdir;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let root = tempdir().unwrap();
+///     let mut store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+///     store.put("apple", "tim cook").await.unwrap();
+///     store.put("google", "sundar pichai").await.unwrap();
+///
+///     let iter = store.seek("a", "b").await.unwrap();
+///     let entries: Vec<(Vec<u8>, Vec<u8>)> = iter.try_collect().await.unwrap();
+///     assert_eq!(entries, vec![(b"apple".to_vec(), b"tim cook".to_vec())]);
+/// }
+/// ```
+pub struct RangeIterator {
+    pub start: Key,
+    pub end: Key,
+
+    /// Scan state, `None` while `pending` reads with it
+    cursor: Option<Box<Cursor>>,
+
+    /// Read started by polling the iterator, kept in a mutex only so the iterator is `Sync`
+    pending: Mutex<Option<PendingRead>>,
+    last_key: Option<Key>,
 }
 
-impl<'a> RangeIterator<'a> {
-    fn new(
-        start: &'a [u8],
-        end: &'a [u8],
-        allow_prefetch: bool,
-        prefetch_entries_size: usize,
-        keys: Vec<Entry<Key, ValOffset>>,
-        v_log: ValueLog,
+/// Position of a scan in the memtable entries and SSTable blocks of its range
+#[derive(Debug)]
+struct Cursor {
+    range: (Bound<Key>, Bound<Key>),
+
+    /// Newest memtable version of each key not yet merged, in key order
+    memtable_entries: VecDeque<(Key, SkipMapValue<ValOffset>)>,
+    tables: Vec<TableCursor>,
+    range_tombstones: Vec<RangeTombstone>,
+
+    /// Values of entries in the write-ahead log, read when the range was captured
+    wal_values: HashMap<ValOffset, Value>,
+
+    /// Entries whose values were read ahead
+    prefetched: VecDeque<FetchedEntry>,
+
+    /// Number of values read together, 1 without prefetch
+    batch_size: usize,
+    block_cache: BlockCache,
+    io_retry: RetryPolicy,
+    read_pool: Option<ReadPool>,
+    v_log: ValueLog,
+    verify_reads: bool,
+    _pin: FilePin,
+}
+
+/// Entries of an SSTable not yet merged, its blocks are read one at a time
+#[derive(Debug)]
+struct TableCursor {
+    table: Table,
+
+    /// Handles of blocks not yet read, in key order
+    blocks: VecDeque<u32>,
+
+    /// Entries of the last block read that are in the range and not yet merged
+    entries: VecDeque<(Key, SkipMapValue<ValOffset>)>,
+}
+
+impl TableCursor {
+    /// Reads blocks until one holds an entry of `range` or none are left
+    ///
+    /// # Errors
+    ///
+    /// Returns error if a block could not be read
+    async fn fill(
+        &mut self,
+        range: &(Bound<Key>, Bound<Key>),
+        block_cache: &BlockCache,
+        retry: &RetryPolicy,
+        read_pool: Option<&ReadPool>,
+    ) -> Result<(), Error> {
+        while self.entries.is_empty() {
+            let block_handle = match self.blocks.pop_front() {
+                Some(block_handle) => block_handle,
+                None => return Ok(()),
+            };
+            let (block, _) = self
+                .table
+                .cached_block(block_handle, block_cache, retry, read_pool)
+                .await?;
+            self.entries.extend(
+                block
+                    .iter()
+                    .filter(|e| contains_user_key(range, &e.key))
+                    .map(|e| {
+                        let value =
+                            SkipMapValue::new(e.value_offset as usize, e.creation_date, e.is_tombstone);
+                        (e.key.to_owned(), value)
+                    }),
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Cursor {
+    /// Creates cursor over `range` captured in `snapshot`
+    fn new<V: Val>(
+        store: &DataStore<'static, Key, V>,
+        range: (Bound<Key>, Bound<Key>),
+        snapshot: RangeSnapshot,
     ) -> Self {
+        let batch_size = if store.config.allow_prefetch {
+            store.config.prefetch_size.max(1)
+        } else {
+            1
+        };
         Self {
-            start,
-            current: 0,
-            end,
-            allow_prefetch,
-            prefetch_entries_size,
-            prefetch_entries: Vec::new(),
-            keys,
-            v_log,
+            range,
+            memtable_entries: snapshot.memtable_entries.into_iter().collect(),
+            tables: snapshot
+                .tables
+                .into_iter()
+                .map(|(table, blocks)| TableCursor {
+                    table,
+                    blocks: blocks.into(),
+                    entries: VecDeque::new(),
+                })
+                .collect(),
+            range_tombstones: snapshot.range_tombstones,
+            wal_values: snapshot.wal_values,
+            prefetched: VecDeque::new(),
+            batch_size,
+            block_cache: store.config.block_cache.clone(),
+            io_retry: store.config.io_retry,
+            read_pool: store.config.read_pool.clone(),
+            v_log: store.val_log.clone(),
+            verify_reads: store.config.verify_reads,
+            _pin: snapshot.pin,
+        }
+    }
+
+    /// Returns next entry in key order, or `None` once the range is exhausted
+    ///
+    /// # Errors
+    ///
+    /// Returns error if a block or a value could not be read
+    async fn next(&mut self) -> Result<Option<FetchedEntry>, Error> {
+        while self.prefetched.is_empty() {
+            let mut batch = Vec::with_capacity(self.batch_size);
+            while batch.len() < self.batch_size {
+                match self.next_live().await? {
+                    Some(entry) => batch.push(entry),
+                    None => break,
+                }
+            }
+            if batch.is_empty() {
+                return Ok(None);
+            }
+            let values = join_all(
+                batch
+                    .iter()
+                    .map(|(key, value)| self.read_value(key, value.val_offset)),
+            )
+            .await;
+            for ((key, _), value) in batch.into_iter().zip(values) {
+                if let Some(val) = value? {
+                    self.prefetched.push_back(FetchedEntry { key, val });
+                }
+            }
+        }
+        Ok(self.prefetched.pop_front())
+    }
+
+    /// Returns newest version of the next key that is not deleted
+    ///
+    /// # Errors
+    ///
+    /// Returns error if a block could not be read
+    async fn next_live(&mut self) -> Result<Option<(Key, SkipMapValue<ValOffset>)>, Error> {
+        loop {
+            for table in self.tables.iter_mut() {
+                table
+                    .fill(
+                        &self.range,
+                        &self.block_cache,
+                        &self.io_retry,
+                        self.read_pool.as_ref(),
+                    )
+                    .await?;
+            }
+            let key = match self
+                .tables
+                .iter()
+                .filter_map(|table| table.entries.front())
+                .chain(self.memtable_entries.front())
+                .map(|(key, _)| key)
+                .min()
+            {
+                Some(key) => key.to_owned(),
+                None => return Ok(None),
+            };
+            // versions of the same time are taken from memtables, they hold newer entries than sstables
+            let mut newest: Option<SkipMapValue<ValOffset>> = None;
+            let heads = self
+                .tables
+                .iter_mut()
+                .map(|table| &mut table.entries)
+                .chain(std::iter::once(&mut self.memtable_entries));
+            for entries in heads {
+                if entries.front().is_some_and(|(head, _)| *head == key) {
+                    let (_, value) = entries.pop_front().unwrap();
+                    if newest
+                        .as_ref()
+                        .is_none_or(|newest| value.created_at >= newest.created_at)
+                    {
+                        newest = Some(value);
+                    }
+                }
+            }
+            if let Some(value) = newest {
+                let covered = self
+                    .range_tombstones
+                    .iter()
+                    .any(|t| t.covers(&key, value.created_at));
+                if !value.is_tombstone && !covered {
+                    return Ok(Some((key, value)));
+                }
+            }
+        }
+    }
+
+    async fn read_value(&self, key: &[u8], val_offset: ValOffset) -> Result<Option<Value>, Error> {
+        if let Some(value) = self.wal_values.get(&val_offset) {
+            return Ok(Some(value.to_owned()));
+        }
+        match self.v_log.get_entry(val_offset).await? {
+            Some(v_entry) => {
+                if self.verify_reads {
+                    v_entry.verify(key, val_offset)?;
+                }
+                Ok(Some(v_entry.value))
+            }
+            None => Ok(None),
         }
     }
 }
 
-impl<'a> DataStore<'a, Key> {
-    // TODO: range query, add next and previous method
-    pub async fn seek(&self, _: &'a [u8], _: &'a [u8]) -> Result<RangeIterator, Error> {
-        let range_iterator = RangeIterator::<'a>::new(
-            &[1],
-            &[2],
-            self.config.allow_prefetch,
-            self.config.prefetch_size,
-            Merger::new().entries,
-            self.val_log.clone(),
-        );
-        Ok(range_iterator)
+impl RangeIterator {
+    /// Returns next entry in key order, or `None` once the range is exhausted
+    ///
+    /// With prefetch enabled, values of the next `prefetch_size` keys are
+    /// read together and served from memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if a block or a value could not be read
+    pub async fn next(&mut self) -> Result<Option<FetchedEntry>, Error> {
+        poll_fn(|cx| self.poll_entry(cx)).await
+    }
+
+    /// Returns position after the last entry returned, `None` if no entry was returned yet
+    ///
+    /// The token can be persisted and passed to [`DataStore::seek_after`] to
+    /// resume the scan, also after a restart. Snapshots are not kept across
+    /// restarts, the resumed scan sees the store as it is when it is resumed.
+    pub fn checkpoint(&self) -> Option<ContinuationToken> {
+        self.last_key.to_owned().map(ContinuationToken::after)
+    }
+
+    /// Polls the read of the next entry, starting it if none is in progress
+    fn poll_entry(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<FetchedEntry>, Error>> {
+        let pending = self.pending.get_mut().unwrap_or_else(PoisonError::into_inner);
+        let read = pending.get_or_insert_with(|| {
+            let mut cursor = self
+                .cursor
+                .take()
+                .expect("cursor is returned once a read completes");
+            async move {
+                let res = cursor.next().await;
+                (cursor, res)
+            }
+            .boxed()
+        });
+        let (cursor, res) = ready!(read.poll_unpin(cx));
+        *pending = None;
+        self.cursor = Some(cursor);
+        if let Ok(Some(entry)) = &res {
+            self.last_key = Some(entry.key.to_owned());
+        }
+        Poll::Ready(res)
+    }
+}
+
+impl Stream for RangeIterator {
+    type Item = Result<(Key, Value), Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let res = ready!(self.get_mut().poll_entry(cx));
+        Poll::Ready(res.transpose().map(|res| res.map(|entry| (entry.key, entry.val))))
     }
 }
-pub struct Merger {
-    entries: Vec<Entry<Key, ValOffset>>,
+
+impl fmt::Debug for RangeIterator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RangeIterator")
+            .field("start", &self.start)
+            .field("end", &self.end)
+            .field("last_key", &self.last_key)
+            .finish_non_exhaustive()
+    }
 }
-impl Merger {
-    fn new() -> Self {
-        Self { entries: Vec::new() }
+
+impl<V: Val> DataStore<'static, Key, V> {
+    /// Returns iterator over live entries with keys from `start` up to, but excluding, `end`
+    ///
+    /// The iterator sees the store as it was when `seek` returned, writes made
+    /// after that are not visible to it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::DataStore;
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let mut store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+    ///     store.put("apple", "tim cook").await.unwrap();
+    ///     store.put("google", "sundar pichai").await.unwrap();
+    ///     store.put("nvidia", "jensen huang").await.unwrap();
+    ///
+    ///     let mut iter = store.seek("a", "h").await.unwrap();
+    ///     store.put("amazon", "andy jassy").await.unwrap();
+    ///     let mut keys = Vec::new();
+    ///     while let Some(entry) = iter.next().await.unwrap() {
+    ///         keys.push(entry.key);
+    ///     }
+    ///     assert_eq!(keys, vec![b"apple".to_vec(), b"google".to_vec()]);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an sstable could not be read
+    pub async fn seek<T: AsRef<[u8]>>(&self, start: T, end: T) -> Result<RangeIterator, Error> {
+        self.seek_after(start, end, None).await
+    }
+
+    /// Same as [`DataStore::seek`], but skips keys up to and including the position of `token`
+    ///
+    /// Resumes a scan from [`RangeIterator::checkpoint`], the iterator sees the
+    /// store as it was when `seek_after` returned. Writes made since the
+    /// checkpoint to keys after it are returned, keys before it are not read again.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use velarixdb::db::{ContinuationToken, DataStore};
+    /// # use tempfile::tempdir;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let mut store = DataStore::open("big_tech", root.path().join("store")).await.unwrap();
+    ///     store.put("apple", "tim cook").await.unwrap();
+    ///     store.put("google", "sundar pichai").await.unwrap();
+    ///
+    ///     let mut iter = store.seek("a", "z").await.unwrap();
+    ///     iter.next().await.unwrap();
+    ///     // checkpoint can be persisted and read back after a restart
+    ///     let saved = iter.checkpoint().unwrap().encode();
+    ///
+    ///     let token = ContinuationToken::decode(&saved).unwrap();
+    ///     let mut iter = store.seek_after("a", "z", Some(&token)).await.unwrap();
+    ///     assert_eq!(iter.next().await.unwrap().unwrap().key, b"google".to_vec());
+    ///     assert!(iter.next().await.unwrap().is_none());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an sstable could not be read
+    pub async fn seek_after<T: AsRef<[u8]>>(
+        &self,
+        start: T,
+        end: T,
+        token: Option<&ContinuationToken>,
+    ) -> Result<RangeIterator, Error> {
+        let lower = ContinuationToken::lower_bound(token, start.as_ref());
+        let range = (lower, Bound::Excluded(end.as_ref().to_vec()));
+        let snapshot = self.snapshot_range(&range).await?;
+        Ok(RangeIterator {
+            start: start.as_ref().to_vec(),
+            end: end.as_ref().to_vec(),
+            cursor: Some(Box::new(Cursor::new(self, range, snapshot))),
+            pending: Mutex::new(None),
+            last_key: None,
+        })
     }
 }
@@ -1,4 +1,9 @@
+mod prefix_sketch;
+mod range_tombstone;
 mod table;
+pub(crate) use prefix_sketch::PrefixSketch;
+pub(crate) use range_tombstone::RangeTombstone;
+pub(crate) use range_tombstone::RangeTombstones;
 #[cfg(test)]
 pub use table::DataFile;
 pub(crate) use table::Summary;
@@ -0,0 +1,141 @@
+//! # Prefix Sketch
+//!
+//! Count-min sketch of the key prefixes of an SSTable, stored in its summary.
+//!
+//! Every prefix of a key up to `MAX_SKETCH_PREFIX_LEN` bytes, including the
+//! empty prefix, increments one counter in each of `PREFIX_SKETCH_DEPTH` rows.
+//! The estimate for a prefix is the smallest of its counters, so it is never
+//! below the number of keys with that prefix and only exceeds it when other
+//! prefixes hash to the same counters in every row.
+//!
+//! The serialized sketch is `PREFIX_SKETCH_MAGIC`, width and depth followed by
+//! the counters row after row, all 4 bytes in little-endian format.
+
+use crate::{
+    consts::{
+        MAX_SKETCH_PREFIX_LEN, PREFIX_SKETCH_DEPTH, PREFIX_SKETCH_MAGIC, PREFIX_SKETCH_WIDTH, SIZE_OF_U32,
+    },
+    types::ByteSerializedEntry,
+    util,
+};
+
+/// Approximate number of keys per prefix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixSketch {
+    counters: Vec<u32>,
+}
+
+impl Default for PrefixSketch {
+    fn default() -> Self {
+        PrefixSketch::new()
+    }
+}
+
+impl PrefixSketch {
+    /// Creates empty `PrefixSketch`
+    pub(crate) fn new() -> Self {
+        Self {
+            counters: vec![0; PREFIX_SKETCH_WIDTH * PREFIX_SKETCH_DEPTH],
+        }
+    }
+
+    /// Counts `key` once for each of its prefixes
+    pub(crate) fn insert(&mut self, key: &[u8]) {
+        let len = key.len().min(MAX_SKETCH_PREFIX_LEN);
+        for prefix_len in 0..=len {
+            for idx in Self::counter_indexes(&key[..prefix_len]) {
+                self.counters[idx] = self.counters[idx].saturating_add(1);
+            }
+        }
+    }
+
+    /// Returns estimated number of keys starting with `prefix`
+    ///
+    /// Prefixes longer than `MAX_SKETCH_PREFIX_LEN` are estimated from their
+    /// first `MAX_SKETCH_PREFIX_LEN` bytes
+    pub(crate) fn estimate(&self, prefix: &[u8]) -> usize {
+        let prefix = &prefix[..prefix.len().min(MAX_SKETCH_PREFIX_LEN)];
+        Self::counter_indexes(prefix)
+            .map(|idx| self.counters[idx])
+            .min()
+            .unwrap_or_default() as usize
+    }
+
+    /// Returns index of the counter of `prefix` in every row
+    fn counter_indexes(prefix: &[u8]) -> impl Iterator<Item = usize> {
+        // mixes bits of the hash so low and high halves are independent
+        let mut hash = util::content_hash(prefix);
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        hash ^= hash >> 31;
+        let (h1, h2) = (hash as u32 as usize, ((hash >> 32) as usize) | 1);
+        (0..PREFIX_SKETCH_DEPTH).map(move |row| {
+            row * PREFIX_SKETCH_WIDTH + h1.wrapping_add(row.wrapping_mul(h2)) % PREFIX_SKETCH_WIDTH
+        })
+    }
+
+    /// Returns size of serialized `PrefixSketch`
+    pub(crate) fn serialized_size() -> usize {
+        SIZE_OF_U32 * (3 + PREFIX_SKETCH_WIDTH * PREFIX_SKETCH_DEPTH)
+    }
+
+    /// Serializes `PrefixSketch` to byte vector
+    pub(crate) fn serialize(&self) -> ByteSerializedEntry {
+        let mut buf = Vec::with_capacity(Self::serialized_size());
+        buf.extend_from_slice(&PREFIX_SKETCH_MAGIC.to_le_bytes());
+        buf.extend_from_slice(&(PREFIX_SKETCH_WIDTH as u32).to_le_bytes());
+        buf.extend_from_slice(&(PREFIX_SKETCH_DEPTH as u32).to_le_bytes());
+        for counter in &self.counters {
+            buf.extend_from_slice(&counter.to_le_bytes());
+        }
+        buf
+    }
+
+    /// Decodes `PrefixSketch` from `buf`
+    ///
+    /// Returns `None` if `buf` does not hold a sketch with the dimensions of this build
+    pub(crate) fn deserialize(buf: &[u8]) -> Option<Self> {
+        if buf.len() != Self::serialized_size() {
+            return None;
+        }
+        let mut words = buf
+            .chunks_exact(SIZE_OF_U32)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()));
+        if words.next() != Some(PREFIX_SKETCH_MAGIC)
+            || words.next() != Some(PREFIX_SKETCH_WIDTH as u32)
+            || words.next() != Some(PREFIX_SKETCH_DEPTH as u32)
+        {
+            return None;
+        }
+        Some(Self {
+            counters: words.collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_estimates() {
+        let mut sketch = PrefixSketch::new();
+        for i in 0..100 {
+            sketch.insert(format!("user:{:03}", i).as_bytes());
+        }
+        for i in 0..20 {
+            sketch.insert(format!("order:{:03}", i).as_bytes());
+        }
+        assert_eq!(sketch.estimate(b""), 120);
+        assert!(sketch.estimate(b"user:") >= 100);
+        assert!(sketch.estimate(b"user:") < 110);
+        assert!(sketch.estimate(b"order:01") >= 10);
+        assert!(sketch.estimate(b"order:01") < 20);
+        // longer than the longest key
+        assert!(sketch.estimate(b"user:000:profile") < 5);
+
+        let decoded = PrefixSketch::deserialize(&sketch.serialize()).unwrap();
+        assert_eq!(decoded, sketch);
+        assert!(PrefixSketch::deserialize(&sketch.serialize()[4..]).is_none());
+    }
+}
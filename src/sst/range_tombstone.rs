@@ -0,0 +1,187 @@
+//! # Range Tombstones
+//!
+//! A range tombstone deletes every version of the keys from its start key up
+//! to, not including, its end key that was written before it. It is written to
+//! the value log by [`DataStore::delete_range`](crate::db::DataStore::delete_range),
+//! kept by the memtable it was inserted to and stored in the summary of the
+//! SSTable that memtable is flushed to. Compaction drops the keys it covers and
+//! carries it to the merged SSTable until the tombstone TTL passed.
+//!
+//! Timestamps are kept in nanoseconds in memtables but in milliseconds in the
+//! value log and SSTables. A tombstone is created at a whole millisecond that
+//! every earlier write is before and no later write is, so whether it covers
+//! a version is the same at either precision.
+//!
+//! The serialized tombstones are `RANGE_TOMBSTONES_MAGIC` and their count,
+//! followed by start key length, end key length, `created_at` in milliseconds,
+//! start key and end key of each, in little-endian format.
+
+use crate::{
+    consts::{RANGE_TOMBSTONES_MAGIC, SIZE_OF_U32, SIZE_OF_U64},
+    types::{ByteSerializedEntry, CreatedAt, Key},
+    util,
+};
+use chrono::Utc;
+use std::sync::{Arc, RwLock};
+
+/// Deletion of the keys from `start` up to, not including, `end`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeTombstone {
+    /// First key deleted
+    pub start: Key,
+
+    /// Key the deleted range ends before
+    pub end: Key,
+
+    /// Time the range was deleted, versions written before it are covered
+    pub created_at: CreatedAt,
+}
+
+impl RangeTombstone {
+    /// Creates new `RangeTombstone`
+    pub(crate) fn new(start: Key, end: Key, created_at: CreatedAt) -> Self {
+        Self {
+            start,
+            end,
+            created_at,
+        }
+    }
+
+    /// Whether the version of `key` created at `created_at` is deleted by the tombstone
+    pub(crate) fn covers(&self, key: &[u8], created_at: CreatedAt) -> bool {
+        key >= self.start.as_slice() && key < self.end.as_slice() && created_at < self.created_at
+    }
+
+    /// Whether more than `ttl` has passed since the range was deleted
+    pub(crate) fn has_expired(&self, ttl: std::time::Duration) -> bool {
+        let ttl = chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::MAX);
+        Utc::now().signed_duration_since(self.created_at) > ttl
+    }
+
+    /// Serializes `tombstones` to byte vector
+    pub(crate) fn serialize_all(tombstones: &[RangeTombstone]) -> ByteSerializedEntry {
+        let len = SIZE_OF_U32 * 2
+            + tombstones
+                .iter()
+                .map(|t| SIZE_OF_U32 * 2 + SIZE_OF_U64 + t.start.len() + t.end.len())
+                .sum::<usize>();
+        let mut buf = Vec::with_capacity(len);
+        buf.extend_from_slice(&RANGE_TOMBSTONES_MAGIC.to_le_bytes());
+        buf.extend_from_slice(&(tombstones.len() as u32).to_le_bytes());
+        for tombstone in tombstones {
+            buf.extend_from_slice(&(tombstone.start.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&(tombstone.end.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&(tombstone.created_at.timestamp_millis() as u64).to_le_bytes());
+            buf.extend_from_slice(&tombstone.start);
+            buf.extend_from_slice(&tombstone.end);
+        }
+        buf
+    }
+
+    /// Decodes tombstones serialized by [`RangeTombstone::serialize_all`] from `buf`
+    ///
+    /// Returns `None` if `buf` is not exactly the serialized tombstones
+    pub(crate) fn deserialize_all(buf: &[u8]) -> Option<Vec<RangeTombstone>> {
+        let mut rest = buf;
+        let mut take = |len: usize| -> Option<&[u8]> {
+            if rest.len() < len {
+                return None;
+            }
+            let (head, tail) = rest.split_at(len);
+            rest = tail;
+            Some(head)
+        };
+        let read_u32 = |bytes: &[u8]| u32::from_le_bytes(bytes.try_into().unwrap());
+        if read_u32(take(SIZE_OF_U32)?) != RANGE_TOMBSTONES_MAGIC {
+            return None;
+        }
+        let count = read_u32(take(SIZE_OF_U32)?) as usize;
+        let mut tombstones = Vec::with_capacity(count.min(buf.len()));
+        for _ in 0..count {
+            let start_len = read_u32(take(SIZE_OF_U32)?) as usize;
+            let end_len = read_u32(take(SIZE_OF_U32)?) as usize;
+            let millis = u64::from_le_bytes(take(SIZE_OF_U64)?.try_into().unwrap());
+            let start = take(start_len)?.to_vec();
+            let end = take(end_len)?.to_vec();
+            tombstones.push(RangeTombstone::new(
+                start,
+                end,
+                util::milliseconds_to_datetime(millis),
+            ));
+        }
+        rest.is_empty().then_some(tombstones)
+    }
+}
+
+/// Range tombstones of all memtables and SSTables of a store
+///
+/// Shared by reads, compaction and garbage collection, which would otherwise
+/// keep or move versions of keys deleted by a range that was not merged with
+/// them yet.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RangeTombstones {
+    inner: Arc<RwLock<Vec<RangeTombstone>>>,
+}
+
+impl RangeTombstones {
+    /// Adds `tombstone`
+    pub(crate) fn insert(&self, tombstone: RangeTombstone) {
+        self.inner.write().unwrap().push(tombstone);
+    }
+
+    /// Adds `tombstones` not yet known
+    pub(crate) fn extend(&self, tombstones: impl IntoIterator<Item = RangeTombstone>) {
+        let mut inner = self.inner.write().unwrap();
+        for tombstone in tombstones {
+            if !inner.contains(&tombstone) {
+                inner.push(tombstone);
+            }
+        }
+    }
+
+    /// Drops `tombstones`, once compaction stopped carrying them
+    pub(crate) fn remove(&self, tombstones: &[RangeTombstone]) {
+        if !tombstones.is_empty() {
+            self.inner.write().unwrap().retain(|t| !tombstones.contains(t));
+        }
+    }
+
+    /// Returns copy of the tombstones, not changed by later deletes and compactions
+    pub(crate) fn to_vec(&self) -> Vec<RangeTombstone> {
+        self.inner.read().unwrap().clone()
+    }
+
+    /// Whether the version of `key` created at `created_at` is deleted by any tombstone
+    pub(crate) fn covers(&self, key: &[u8], created_at: CreatedAt) -> bool {
+        self.inner
+            .read()
+            .unwrap()
+            .iter()
+            .any(|t| t.covers(key, created_at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_covers_and_serialize() {
+        let deleted_at = util::milliseconds_to_datetime(10_000);
+        let tombstone = RangeTombstone::new(b"b".to_vec(), b"d".to_vec(), deleted_at);
+        let before = util::milliseconds_to_datetime(9_999);
+        assert!(tombstone.covers(b"b", before));
+        assert!(tombstone.covers(b"c:1", before));
+        assert!(!tombstone.covers(b"a", before));
+        assert!(!tombstone.covers(b"d", before));
+        assert!(!tombstone.covers(b"c", deleted_at));
+
+        let tombstones = vec![
+            tombstone.clone(),
+            RangeTombstone::new(vec![], b"z".to_vec(), deleted_at),
+        ];
+        let buf = RangeTombstone::serialize_all(&tombstones);
+        assert_eq!(RangeTombstone::deserialize_all(&buf), Some(tombstones));
+        assert!(RangeTombstone::deserialize_all(&buf[..buf.len() - 1]).is_none());
+    }
+}
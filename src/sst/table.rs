@@ -35,30 +35,38 @@
 //! - TODO: In the future we will introduce Snappy Compression to reduce the size on the disk and also introduce checksum to ensure the data has not been corrupted
 
 use crate::{
-    block::Block,
-    bucket::InsertableToBucket,
+    block::{Block, BlockCache, BlockEntry, CachedBlock},
+    bucket::{manifest::FileNumber, InsertableToBucket},
     consts::{
-        DATA_FILE_NAME, INDEX_FILE_NAME, SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8, SIZE_OF_USIZE,
-        SUMMARY_FILE_NAME,
+        DATA_FILE_NAME, HEAD_ENTRY_KEY, INDEX_FILE_NAME, SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8, SIZE_OF_USIZE,
+        SUMMARY_FILE_NAME, SUMMARY_STATS_MAGIC, TAIL_ENTRY_KEY,
     },
     err::Error,
     filter::BloomFilter,
-    fs::{DataFileNode, DataFs, FileAsync, FileNode, IndexFileNode, IndexFs, SummaryFileNode, SummaryFs},
+    fs::{
+        DataFileNode, DataFs, FileAsync, FileNode, IndexFileNode, IndexFs, ReadPool, RetryPolicy,
+        SummaryFileNode, SummaryFs,
+    },
     index::{Index, IndexFile, RangeOffset},
     key_range::{BiggestKey, SmallestKey},
     memtable::{Entry, SkipMapValue},
+    sst::{PrefixSketch, RangeTombstone},
     types::{ByteSerializedEntry, CreatedAt, IsTombStone, Key, SkipMapEntries, ValOffset},
     util,
 };
 use chrono::Utc;
 use crossbeam_skiplist::SkipMap;
 use std::{
+    ops::{Bound, RangeBounds},
     path::{Path, PathBuf},
     sync::Arc,
     time::SystemTime,
 };
 use Error::*;
 
+/// Last entry, offset and whether it holds only tombstones, per block
+type EncodedBlocks = Vec<(BlockEntry, usize, bool)>;
+
 /// DataFile
 #[derive(Debug, Clone)]
 pub struct DataFile<F: DataFs> {
@@ -107,6 +115,12 @@ pub struct Table {
 
     /// Stores the summary including biggest and smallest key
     pub(crate) summary: Option<Summary>,
+
+    /// Range tombstones stored in the summary
+    pub(crate) range_tombstones: Vec<RangeTombstone>,
+
+    /// File number the sstable is listed with in the bucket manifest
+    pub(crate) file_number: FileNumber,
 }
 
 /// Defines trait to make `Table` insertable to bucket
@@ -122,18 +136,18 @@ impl InsertableToBucket for Table {
     fn get_filter(&self) -> BloomFilter {
         self.filter.as_ref().unwrap().to_owned()
     }
+
+    fn get_range_tombstones(&self) -> Vec<RangeTombstone> {
+        self.range_tombstones.clone()
+    }
 }
 
 impl Table {
     /// Creates a new `Table`
     pub async fn new<P: AsRef<Path> + Send + Sync>(dir: P) -> Result<Table, Error> {
         let (data_file_path, index_file_path, created_at) = Table::generate_file_path(dir.as_ref()).await?;
-        let data_file = DataFileNode::new(data_file_path.to_owned(), crate::fs::FileType::Data)
-            .await
-            .unwrap();
-        let index_file = IndexFileNode::new(index_file_path.to_owned(), crate::fs::FileType::Index)
-            .await
-            .unwrap();
+        let data_file = DataFileNode::new(data_file_path.to_owned(), crate::fs::FileType::Data).await?;
+        let index_file = IndexFileNode::new(index_file_path.to_owned(), crate::fs::FileType::Index).await?;
 
         Ok(Self {
             dir: dir.as_ref().to_path_buf(),
@@ -145,6 +159,8 @@ impl Table {
             size: Default::default(),
             filter: None,
             summary: None,
+            range_tombstones: Vec::new(),
+            file_number: Default::default(),
         })
     }
     pub fn increase_hotness(&mut self) {
@@ -155,6 +171,50 @@ impl Table {
         self.data_file.path.clone()
     }
 
+    /// Returns number of entries in `Table`
+    ///
+    /// Taken from the summary, or estimated from data file size
+    /// for summaries written by older versions
+    pub fn entry_count(&self) -> usize {
+        match &self.summary {
+            Some(summary) if summary.entry_count > 0 => summary.entry_count,
+            _ => self.size / Block::entry_size(&[]),
+        }
+    }
+
+    /// Returns estimated number of live keys starting with `prefix`
+    ///
+    /// Taken from the prefix sketch of the summary, tables with summaries
+    /// written by older versions are counted from their data file
+    ///
+    /// # Errors
+    ///
+    /// Returns IO error in case the data file could not be read
+    pub(crate) async fn prefix_cardinality(&self, prefix: &[u8]) -> Result<usize, Error> {
+        if let Some(summary) = &self.summary {
+            let before_prefix =
+                summary.biggest_key.as_slice() < prefix && !summary.biggest_key.starts_with(prefix);
+            let after_prefix =
+                summary.smallest_key.as_slice() > prefix && !summary.smallest_key.starts_with(prefix);
+            if before_prefix || after_prefix {
+                return Ok(0);
+            }
+            if let Some(prefix_sketch) = &summary.prefix_sketch {
+                return Ok(prefix_sketch.estimate(prefix));
+            }
+        }
+        let (entries, _) = self.data_file.file.load_entries().await?;
+        Ok(entries
+            .iter()
+            .filter(|e| {
+                !e.value().is_tombstone
+                    && e.key().starts_with(prefix)
+                    && e.key().as_slice() != HEAD_ENTRY_KEY
+                    && e.key().as_slice() != TAIL_ENTRY_KEY
+            })
+            .count())
+    }
+
     /// Returns `Table` `hotness`
     pub fn get_hotness(&self) -> u64 {
         self.hotness
@@ -178,6 +238,9 @@ impl Table {
 
     /// Returns a key from a block in sstable data file
     ///
+    /// The block is served from `block_cache` if present, otherwise it is
+    /// read from disk, on `read_pool` if provided, and cached if `admit` is true
+    ///
     /// # Errors
     ///
     /// Returns IO error in case it occurs
@@ -185,13 +248,77 @@ impl Table {
         &self,
         start_offset: u32,
         searched_key: K,
+        block_cache: &BlockCache,
+        retry: &RetryPolicy,
+        read_pool: Option<&ReadPool>,
+        admit: bool,
     ) -> Result<Option<(ValOffset, CreatedAt, IsTombStone)>, Error> {
-        self.data_file
-            .file
-            .find_entry(start_offset, searched_key.as_ref())
+        let (block, _) = self
+            .block(start_offset, block_cache, retry, read_pool, admit)
+            .await?;
+        Ok(block
+            .binary_search_by(|e| e.key.as_slice().cmp(searched_key.as_ref()))
+            .ok()
+            .map(|idx| {
+                let e = &block[idx];
+                (e.value_offset as usize, e.creation_date, e.is_tombstone)
+            }))
+    }
+
+    /// Returns block at `start_offset` in data file, read from disk and cached if absent
+    ///
+    /// Also returns number of bytes read from disk, `0` if the block was cached.
+    /// Reads failing with transient IO errors are retried according to `retry`,
+    /// they run on `read_pool` if provided
+    ///
+    /// # Errors
+    ///
+    /// Returns IO error in case it occurs
+    pub(crate) async fn cached_block(
+        &self,
+        start_offset: u32,
+        block_cache: &BlockCache,
+        retry: &RetryPolicy,
+        read_pool: Option<&ReadPool>,
+    ) -> Result<(CachedBlock, usize), Error> {
+        self.block(start_offset, block_cache, retry, read_pool, true)
             .await
     }
 
+    /// Same as [`Table::cached_block`], but a block read from disk is only cached if `admit` is true
+    ///
+    /// # Errors
+    ///
+    /// Returns IO error in case it occurs
+    pub(crate) async fn block(
+        &self,
+        start_offset: u32,
+        block_cache: &BlockCache,
+        retry: &RetryPolicy,
+        read_pool: Option<&ReadPool>,
+        admit: bool,
+    ) -> Result<(CachedBlock, usize), Error> {
+        if let Some(block) = block_cache.get(&self.data_file.path, start_offset) {
+            return Ok((block, 0));
+        }
+        let (entries, bytes_read) = retry
+            .run(|_| async {
+                match read_pool {
+                    Some(pool) => {
+                        let file = self.data_file.file.clone();
+                        pool.run(async move { file.load_block(start_offset).await }).await
+                    }
+                    None => self.data_file.file.load_block(start_offset).await,
+                }
+            })
+            .await?;
+        let block = Arc::new(entries);
+        if admit {
+            block_cache.insert(&self.data_file.path, start_offset, block.clone(), bytes_read);
+        }
+        Ok((block, bytes_read))
+    }
+
     /// Build  `entries` from sstable data file
     ///
     /// # Errors
@@ -205,31 +332,33 @@ impl Table {
     }
 
     /// Returns new `Table` using the supplied parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns IO error in case files could not be opened
     pub(crate) async fn build_from<P: AsRef<Path> + Send + Sync + Clone>(
         dir: P,
         data_file_path: P,
         index_file_path: P,
-    ) -> Table {
+    ) -> Result<Table, Error> {
         let mut table = Table {
             dir: dir.as_ref().to_path_buf(),
             hotness: 1,
             created_at: Utc::now(),
             data_file: DataFile {
-                file: DataFileNode::new(data_file_path.to_owned(), crate::fs::FileType::Data)
-                    .await
-                    .unwrap(),
+                file: DataFileNode::open_read_only(data_file_path.to_owned()).await?,
                 path: data_file_path.as_ref().to_path_buf(),
             },
             index_file: IndexFile {
-                file: IndexFileNode::new(index_file_path.to_owned(), crate::fs::FileType::Index)
-                    .await
-                    .unwrap(),
+                file: IndexFileNode::new(index_file_path.to_owned(), crate::fs::FileType::Index).await?,
                 path: index_file_path.as_ref().to_path_buf(),
             },
             size: Default::default(),
             entries: Arc::new(SkipMap::new()),
             filter: None,
             summary: None,
+            range_tombstones: Vec::new(),
+            file_number: Default::default(),
         };
         table.size = table.data_file.file.node.size().await;
         let modified_time = table
@@ -237,25 +366,28 @@ impl Table {
             .file
             .node
             .metadata()
-            .await
-            .unwrap()
+            .await?
             .modified()
-            .unwrap();
+            .map_err(|error| GetFileMetaData {
+                path: table.data_file.path.to_owned(),
+                error,
+            })?;
         let epoch = SystemTime::UNIX_EPOCH;
-        let elapsed_nanos = modified_time.duration_since(epoch).unwrap().as_nanos() as u64;
+        let elapsed_nanos = modified_time.duration_since(epoch).unwrap_or_default().as_nanos() as u64;
         table.created_at = util::milliseconds_to_datetime(elapsed_nanos / 1_000_000);
-        table
+        Ok(table)
     }
 
     /// Writes SSTable files to disk
     ///
     /// After successful write, the summary and bloom filter
-    /// for the table is set and stored in memory
+    /// for the table is set and stored in memory. Blocks are encoded
+    /// on the blocking thread pool if `offload_cpu_work` is set
     ///
     /// Errors
     ///
     /// Returns error in case of IO error
-    pub(crate) async fn write_to_file(&mut self) -> Result<(), Error> {
+    pub(crate) async fn write_to_file(&mut self, offload_cpu_work: bool) -> Result<(), Error> {
         if self.filter.is_none() {
             return Err(FilterNotProvidedForFlush);
         }
@@ -263,15 +395,11 @@ impl Table {
             return Err(EntriesCannotBeEmptyDuringFlush);
         }
         let index_file = &self.index_file;
-        let mut blocks: Vec<Block> = Vec::new();
         let mut index = Index::new(self.index_file.path.clone(), index_file.file.clone());
         let mut summary = Summary::new(self.dir.to_owned());
 
-        let smallest_entry = self.entries.front();
-        let biggest_entry = self.entries.back();
-
-        summary.smallest_key = smallest_entry.unwrap().key().to_vec();
-        summary.biggest_key = biggest_entry.unwrap().key().to_vec();
+        summary.set_from_entries(&self.entries);
+        summary.range_tombstones = self.range_tombstones.clone();
 
         // write summary to disk
         summary.write_to_file().await?;
@@ -284,13 +412,41 @@ impl Table {
             .unwrap()
             .set_sstable_path(&self.data_file.path);
 
-        // write data blocks
-        let mut current_block = Block::new();
-        if self.size > 0 {
-            self.reset_size();
+        // write data blocks, the whole data file is encoded up front
+        // and written with a single call
+        let entries = self.entries.clone();
+        let (block_offsets, data) =
+            util::run_cpu_bound(offload_cpu_work, move || Self::encode_blocks(&entries)).await??;
+        for (last_entry, offset, only_tombstones) in block_offsets {
+            index.insert(
+                last_entry.key_prefix,
+                last_entry.key,
+                offset as u32,
+                only_tombstones,
+            );
         }
+        self.data_file.file.node.write_all(&data).await?;
+        self.size = data.len();
+        index.write_to_file().await?;
 
-        for e in self.entries.iter() {
+        // sync once, after every block and the index were written
+        self.data_file.file.node.sync_all().await?;
+        self.index_file.file.node.sync_all().await?;
+        Ok(())
+    }
+
+    /// Packs `entries` into blocks and encodes them
+    ///
+    /// Returns last entry, offset and whether it holds only tombstones for every block, with the encoded blocks
+    /// concatenated in data file order
+    ///
+    /// Errors
+    ///
+    /// Returns error in case an entry could not be added to a block
+    fn encode_blocks(entries: &SkipMapEntries<Key>) -> Result<(EncodedBlocks, ByteSerializedEntry), Error> {
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut current_block = Block::new();
+        for e in entries.iter() {
             let entry = Entry::new(
                 e.key(),
                 e.value().val_offset,
@@ -298,8 +454,7 @@ impl Table {
                 e.value().is_tombstone,
             );
 
-            // key len(variable) +  key prefix + value offset length(4 bytes) + insertion time (8 bytes) + tombstone (1 byte)
-            let entry_size = entry.key.len() + SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8;
+            let entry_size = Block::entry_size(&entry.key);
             if current_block.is_full(entry_size) {
                 blocks.push(current_block);
                 current_block = Block::new();
@@ -313,44 +468,38 @@ impl Table {
             )?;
         }
 
-        for block in blocks.iter() {
-            self.write_block(block, &mut index).await?;
-        }
-
         // Incase we have some entries left in current block, write them to disk
         if !current_block.entries.is_empty() {
-            self.write_block(&current_block, &mut index).await?;
+            blocks.push(current_block);
         }
-        index.write_to_file().await?;
-        Ok(())
+        let mut block_offsets = Vec::with_capacity(blocks.len());
+        let mut data = Vec::with_capacity(blocks.iter().map(|block| block.size).sum());
+        for block in blocks.iter() {
+            block_offsets.push((block.get_last_entry(), data.len(), block.has_only_tombstones()));
+            data.extend(block.encode());
+        }
+        Ok((block_offsets, data))
     }
 
-    /// Write block to disk
+    /// Retreives entries with keys within `bounds` from a specific block range
     ///
-    /// Errors
-    ///
-    /// Returns error in case of IO error
-    async fn write_block(&mut self, block: &Block, table_index: &mut Index) -> Result<(), Error> {
-        let offset = self.size;
-        let last_entry = block.get_last_entry();
-        table_index.insert(last_entry.key_prefix, last_entry.key, offset as u32);
-        let bytes_written = block.write_to_file(self.data_file.file.node.clone()).await?;
-        self.size += bytes_written;
-        Ok(())
-    }
-
-    /// Retreives entries within a specific block range
+    /// Decoding stops at the first key past the upper bound of `bounds`
     ///
     /// Errors
     ///
     /// Returns error in case of IO error
     #[allow(dead_code)]
-    pub(crate) async fn range(&self, range_offset: RangeOffset) -> Result<Vec<Entry<Key, usize>>, Error> {
-        self.data_file.file.load_entries_within_range(range_offset).await
-    }
-
-    pub(crate) fn reset_size(&mut self) {
-        self.size = 0;
+    pub(crate) async fn range<T: AsRef<[u8]>>(
+        &self,
+        range_offset: RangeOffset,
+        bounds: &impl RangeBounds<T>,
+    ) -> Result<Vec<Entry<Key, usize>>, Error> {
+        let owned = |bound: Bound<&T>| bound.map(|key| key.as_ref().to_vec());
+        let bounds = (owned(bounds.start_bound()), owned(bounds.end_bound()));
+        self.data_file
+            .file
+            .load_entries_within_range(range_offset, bounds)
+            .await
     }
 
     /// Returns `size` of `Table`
@@ -385,6 +534,20 @@ pub struct Summary {
 
     /// Biggest key in `Table`
     pub biggest_key: BiggestKey,
+
+    /// Number of entries in `Table`, zero if recovered from an older summary
+    pub entry_count: usize,
+
+    /// Oldest and newest `created_at` of entries in `Table`, `None` if
+    /// recovered from an older summary
+    pub created_at_range: Option<(CreatedAt, CreatedAt)>,
+
+    /// Approximate key count per prefix of live entries in `Table`, `None` if
+    /// recovered from an older summary
+    pub prefix_sketch: Option<PrefixSketch>,
+
+    /// Range tombstones flushed or merged into `Table`
+    pub range_tombstones: Vec<RangeTombstone>,
 }
 
 impl Summary {
@@ -395,6 +558,10 @@ impl Summary {
             path: file_path,
             biggest_key: vec![],
             smallest_key: vec![],
+            entry_count: 0,
+            created_at_range: None,
+            prefix_sketch: None,
+            range_tombstones: Vec::new(),
         }
     }
 
@@ -404,9 +571,7 @@ impl Summary {
     ///
     /// Returns IO error in case it occurs
     pub async fn write_to_file(&mut self) -> Result<(), Error> {
-        let file = SummaryFileNode::new(self.path.to_owned(), crate::fs::FileType::Summary)
-            .await
-            .unwrap();
+        let file = SummaryFileNode::new(self.path.to_owned(), crate::fs::FileType::Summary).await?;
         let serialized_data = self.serialize();
         file.node.write_all(&serialized_data).await?;
         Ok(())
@@ -418,15 +583,57 @@ impl Summary {
     ///
     /// Returns IO error in case it occurs
     pub async fn recover(&mut self) -> Result<(), Error> {
-        let (smallest_key, biggest_key) = SummaryFileNode::recover(self.path.to_owned()).await?;
+        let (smallest_key, biggest_key, stats, prefix_sketch, range_tombstones) =
+            SummaryFileNode::recover(self.path.to_owned()).await?;
         self.smallest_key = smallest_key;
         self.biggest_key = biggest_key;
+        if let Some((entry_count, oldest, newest)) = stats {
+            self.entry_count = entry_count;
+            self.created_at_range = Some((oldest, newest));
+        }
+        self.prefix_sketch = prefix_sketch;
+        self.range_tombstones = range_tombstones;
         Ok(())
     }
 
+    /// Sets key range, entry count, `created_at` range and prefix sketch from `entries`
+    pub(crate) fn set_from_entries(&mut self, entries: &SkipMapEntries<Key>) {
+        if let (Some(smallest), Some(biggest)) = (entries.front(), entries.back()) {
+            self.smallest_key = smallest.key().to_vec();
+            self.biggest_key = biggest.key().to_vec();
+        }
+        self.entry_count = entries.len();
+        self.created_at_range = entries.iter().fold(None, |range, e| {
+            let created_at = e.value().created_at;
+            match range {
+                None => Some((created_at, created_at)),
+                Some((oldest, newest)) => Some((oldest.min(created_at), newest.max(created_at))),
+            }
+        });
+        let mut prefix_sketch = PrefixSketch::new();
+        entries
+            .iter()
+            .filter(|e| {
+                !e.value().is_tombstone
+                    && e.key().as_slice() != HEAD_ENTRY_KEY
+                    && e.key().as_slice() != TAIL_ENTRY_KEY
+            })
+            .for_each(|e| prefix_sketch.insert(e.key()));
+        self.prefix_sketch = Some(prefix_sketch);
+    }
+
     /// Serializes `Summary` to byte vector
+    ///
+    /// Entry count, `created_at` range, prefix sketch and range tombstones
+    /// follow the keys, so older versions that only read the keys can still
+    /// recover it. Range tombstones are only written if there are any.
     pub(crate) fn serialize(&self) -> ByteSerializedEntry {
-        let entry_len = SIZE_OF_U32 + SIZE_OF_U32 + self.biggest_key.len() + self.smallest_key.len();
+        let entry_len = SIZE_OF_U32
+            + SIZE_OF_U32
+            + self.biggest_key.len()
+            + self.smallest_key.len()
+            + SIZE_OF_U64 * 3
+            + PrefixSketch::serialized_size();
         let mut serialized_data = Vec::with_capacity(entry_len);
 
         serialized_data.extend_from_slice(&(self.smallest_key.len() as u32).to_le_bytes());
@@ -437,6 +644,24 @@ impl Summary {
 
         serialized_data.extend_from_slice(&self.biggest_key);
 
+        let (oldest, newest) = self.created_at_range.unwrap_or_default();
+
+        serialized_data.extend_from_slice(&SUMMARY_STATS_MAGIC.to_le_bytes());
+
+        serialized_data.extend_from_slice(&(self.entry_count as u64).to_le_bytes());
+
+        serialized_data.extend_from_slice(&(oldest.timestamp_millis() as u64).to_le_bytes());
+
+        serialized_data.extend_from_slice(&(newest.timestamp_millis() as u64).to_le_bytes());
+
+        if let Some(prefix_sketch) = &self.prefix_sketch {
+            serialized_data.extend_from_slice(&prefix_sketch.serialize());
+        }
+
+        if !self.range_tombstones.is_empty() {
+            serialized_data.extend_from_slice(&RangeTombstone::serialize_all(&self.range_tombstones));
+        }
+
         serialized_data
     }
 }
@@ -2,9 +2,13 @@
 mod tests {
     use crate::tests::workload::{FilterWorkload, SSTContructor};
     use crate::{
-        bucket::{Bucket, BucketMap},
+        bucket::{
+            manifest::{BucketManifest, SSTableRecord},
+            Bucket, BucketMap,
+        },
         consts::{BUCKET_HIGH, MIN_TRESHOLD},
-        err::Error,
+        db::SizeUnit,
+        sst::Table,
     };
     use std::sync::Arc;
     use tempfile::tempdir;
@@ -26,6 +30,24 @@ mod tests {
         assert!(new_bucket.sstables.read().await.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_table_new_io_error() {
+        let root = tempdir().unwrap();
+        let file_path = root.path().join("not_a_dir");
+        fs::write(&file_path, b"").await.unwrap();
+        // directories cannot be created below a file
+        assert!(Bucket::new(file_path.join("bucket")).await.is_err());
+        assert!(Table::new(file_path.join("sstable")).await.is_err());
+        let sst_dir = file_path.join("sstable");
+        assert!(Table::build_from(
+            sst_dir.to_owned(),
+            sst_dir.join("data.db"),
+            sst_dir.join("index.db")
+        )
+        .await
+        .is_err());
+    }
+
     #[tokio::test]
     async fn test_bucket_from_with_empty() {
         let root = tempdir().unwrap();
@@ -54,10 +76,7 @@ mod tests {
             .map(|s| tokio::spawn(fs::metadata(s.data_file.path.clone())));
         let mut all_sstable_size = 0;
         for meta_task in sst_meta {
-            let meta_data = meta_task
-                .await
-                .map_err(|err| Error::GetFileMetaData(err.into()))
-                .unwrap();
+            let meta_data = meta_task.await.unwrap();
             all_sstable_size += meta_data.unwrap().len() as usize;
         }
         let expected_avg = all_sstable_size / sst_count as usize;
@@ -79,10 +98,7 @@ mod tests {
             .map(|s| tokio::spawn(fs::metadata(s.data_file.path.clone())));
         let mut all_sstable_size = 0;
         for meta_task in sst_meta {
-            let meta_data = meta_task
-                .await
-                .map_err(|err| Error::GetFileMetaData(err.into()))
-                .unwrap();
+            let meta_data = meta_task.await.unwrap();
             all_sstable_size += meta_data.unwrap().len() as usize;
         }
         let expected_avg = all_sstable_size / sst_count as usize;
@@ -120,10 +136,7 @@ mod tests {
             .map(|s| tokio::spawn(fs::metadata(s.data_file.path.clone())));
         let mut all_sstable_size = 0;
         for meta_task in sst_meta {
-            let meta_data = meta_task
-                .await
-                .map_err(|err| Error::GetFileMetaData(err.into()))
-                .unwrap();
+            let meta_data = meta_task.await.unwrap();
             all_sstable_size += meta_data.unwrap().len() as usize;
         }
         for s in sst_samples {
@@ -331,6 +344,106 @@ mod tests {
         assert!(insert_res.is_ok());
         // SST size is not within first bucket size range so a new bucket should have be created
         assert_eq!(bucket_map.buckets.len(), 2);
+
+        // every sstable is listed with its key range and filter
+        let manifest = BucketManifest::read(&path).await.unwrap().unwrap();
+        let listed: Vec<_> = manifest.buckets.iter().flat_map(|b| b.sstables.iter()).collect();
+        assert_eq!(listed.len(), 3);
+        for sst in listed.iter() {
+            assert!(sst.key_range.is_some());
+            assert_eq!(sst.filter.as_deref(), Some("filter.db"));
+        }
+        // sstables are named by the file number listed with them
+        let mut numbers: Vec<_> = listed.iter().map(|sst| sst.number.unwrap()).collect();
+        numbers.sort();
+        assert_eq!(numbers, [1, 2, 3]);
+        assert!(listed
+            .iter()
+            .all(|sst| SSTableRecord::parse_number(&sst.name) == sst.number));
+        assert_eq!(manifest.next_file_number, 4);
+    }
+
+    #[tokio::test]
+    async fn table_insert_compacted_to_bucket_matching_its_size() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("bucket_map_compacted");
+        let mut bucket_map = BucketMap::new(path.to_owned()).await.unwrap();
+        let sst_samples = SSTContructor::generate_ssts(MIN_TRESHOLD as u32 + 1).await;
+        let mut merged = sst_samples[0].to_owned();
+        merged.load_entries_from_file().await.unwrap();
+        merged.filter = Some(FilterWorkload::from(0.1, merged.entries.to_owned()));
+        merged.size = SizeUnit::Kilobytes.as_bytes(120);
+
+        // sstables merged into `merged` still count towards the average of their bucket
+        let mut source = Bucket::new(path.to_owned()).await.unwrap();
+        source.avarage_size = merged.size;
+        for s in sst_samples[..MIN_TRESHOLD].iter() {
+            source.sstables.write().await.push(s.to_owned());
+        }
+        let mut target = Bucket::new(path.to_owned()).await.unwrap();
+        target.avarage_size = SizeUnit::Kilobytes.as_bytes(150);
+        target
+            .sstables
+            .write()
+            .await
+            .push(sst_samples[MIN_TRESHOLD].to_owned());
+        bucket_map.buckets.insert(source.id, source.to_owned());
+        bucket_map.buckets.insert(target.id, target.to_owned());
+
+        let replaced = vec![(source.id, sst_samples[..MIN_TRESHOLD].to_vec())];
+        let sst = bucket_map
+            .insert_to_appropriate_bucket_in(Arc::new(Box::new(merged)), None, &replaced)
+            .await
+            .unwrap();
+        assert_eq!(sst.dir.parent().unwrap(), target.dir);
+        assert_eq!(bucket_map.buckets[&target.id].sstables.read().await.len(), 2);
+        assert_eq!(
+            bucket_map.buckets[&source.id].sstables.read().await.len(),
+            MIN_TRESHOLD
+        );
+    }
+
+    #[test]
+    fn test_read_manifest_listing_names_only() {
+        let id = Uuid::new_v4();
+        let json = format!(
+            r#"{{"buckets":[{{"id":"{}","avarage_size":10,"sstables":["sstable_1"]}}]}}"#,
+            id
+        );
+        let manifest: BucketManifest = serde_json::from_str(&json).unwrap();
+        let record = manifest.bucket(&id).unwrap();
+        assert!(record.lists("sstable_1"));
+        assert_eq!(record.sstable("sstable_1").unwrap().key_range, None);
+        assert_eq!(record.sstable("sstable_1").unwrap().number, None);
+        assert_eq!(manifest.next_file_number, 0);
+    }
+
+    #[tokio::test]
+    async fn test_insert_unlisted_sstable_is_dropped() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("bucket_map_unlisted");
+        let mut bucket_map = BucketMap::new(path.to_owned()).await.unwrap();
+        let mut sst = SSTContructor::generate_ssts(1).await[0].to_owned();
+        sst.load_entries_from_file().await.unwrap();
+        sst.filter = Some(FilterWorkload::from(0.1, sst.entries.to_owned()));
+
+        // manifest can't be replaced while a non-empty directory is in its place
+        let manifest_path = BucketManifest::path(&path);
+        std::fs::create_dir_all(manifest_path.join("blocker")).unwrap();
+        let insert_res = bucket_map
+            .insert_to_appropriate_bucket(Arc::new(Box::new(sst.to_owned())))
+            .await;
+        assert!(insert_res.is_err());
+        assert_eq!(bucket_map.buckets.len(), 0);
+        assert_eq!(std::fs::read_dir(&path).unwrap().count(), 0);
+
+        std::fs::remove_dir_all(&manifest_path).unwrap();
+        let sst = bucket_map
+            .insert_to_appropriate_bucket(Arc::new(Box::new(sst)))
+            .await
+            .unwrap();
+        let manifest = BucketManifest::read(&path).await.unwrap().unwrap();
+        assert!(manifest.buckets[0].lists(&sst.dir.file_name().unwrap().to_string_lossy()));
     }
 
     #[tokio::test]
@@ -0,0 +1,125 @@
+#[cfg(test)]
+mod tests {
+    use crate::codec::{Block, BlockEntry, IndexEntry, ValueLogEntry};
+    use crate::util;
+    use proptest::{collection::vec, option, prelude::*, sample::Index};
+
+    // Every suite checks this many generated cases
+    const CASES: u32 = 256;
+
+    fn bytes(max_len: usize) -> impl Strategy<Value = Vec<u8>> {
+        vec(any::<u8>(), 0..=max_len)
+    }
+
+    fn date() -> impl Strategy<Value = chrono::DateTime<chrono::Utc>> {
+        // stored with millisecond precision, any date up to year 2100
+        (0..4_102_444_800_000u64).prop_map(util::milliseconds_to_datetime)
+    }
+
+    fn block_entry() -> impl Strategy<Value = BlockEntry> {
+        (bytes(64), any::<u32>(), date(), any::<bool>()).prop_map(
+            |(key, value_offset, creation_date, is_tombstone)| BlockEntry {
+                key_prefix: key.len() as u32,
+                key,
+                value_offset,
+                creation_date,
+                is_tombstone,
+            },
+        )
+    }
+
+    fn index_entry() -> impl Strategy<Value = IndexEntry> {
+        (bytes(64), any::<u32>()).prop_map(|(key, block_handle)| IndexEntry {
+            key_len: key.len() as u32,
+            key,
+            block_handle,
+        })
+    }
+
+    fn vlog_entry() -> impl Strategy<Value = ValueLogEntry> {
+        (
+            bytes(64),
+            bytes(256),
+            date(),
+            any::<bool>(),
+            option::of(bytes(32)),
+            any::<bool>(),
+        )
+            .prop_map(|(key, value, created_at, is_tombstone, metadata, checksum)| {
+                let mut entry =
+                    ValueLogEntry::new(key.len(), value.len(), key, value, created_at, is_tombstone);
+                if let Some(metadata) = metadata {
+                    entry = entry.with_metadata(metadata);
+                }
+                if checksum {
+                    entry.with_checksum()
+                } else {
+                    entry
+                }
+            })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(CASES))]
+
+        #[test]
+        fn block_entries_round_trip(entries in vec(block_entry(), 0..16), cut in any::<Index>()) {
+            let mut block = Block::new();
+            for e in &entries {
+                block
+                    .set_entry(
+                        e.key_prefix,
+                        &e.key,
+                        e.value_offset,
+                        e.creation_date,
+                        e.is_tombstone,
+                    )
+                    .unwrap();
+            }
+            let encoded = block.encode();
+            prop_assert_eq!(&Block::decode_entries(&encoded), &entries);
+
+            // a truncated buffer only yields the entries it fully contains
+            let cut = cut.index(encoded.len() + 1);
+            let decoded = Block::decode_entries(&encoded[..cut]);
+            let complete = entries
+                .iter()
+                .scan(0, |end, e| {
+                    *end += e.serialize().len();
+                    Some(*end)
+                })
+                .take_while(|end| *end <= cut)
+                .count();
+            prop_assert_eq!(decoded.as_slice(), &entries[..complete]);
+        }
+
+        #[test]
+        fn index_entries_round_trip(entries in vec(index_entry(), 0..16), cut in any::<Index>()) {
+            let encoded: Vec<u8> = entries.iter().flat_map(|e| e.serialize()).collect();
+            prop_assert_eq!(&IndexEntry::decode_entries(&encoded), &entries);
+
+            let cut = cut.index(encoded.len() + 1);
+            let decoded = IndexEntry::decode_entries(&encoded[..cut]);
+            prop_assert!(decoded.len() <= entries.len());
+            prop_assert_eq!(decoded.as_slice(), &entries[..decoded.len()]);
+        }
+
+        #[test]
+        fn vlog_entries_round_trip(entry in vlog_entry(), padding in bytes(8), cut in any::<Index>()) {
+            let encoded = entry.serialize();
+            prop_assert_eq!(encoded.len(), entry.encoded_len());
+
+            let (decoded, len) = ValueLogEntry::deserialize(&encoded).unwrap();
+            prop_assert_eq!(&decoded, &entry);
+            prop_assert_eq!(len, encoded.len());
+            prop_assert!(decoded.verify(&entry.key, 0).is_ok());
+
+            // trailing bytes of the next entry are left alone, missing bytes are reported
+            let mut padded = encoded.clone();
+            padded.extend(padding);
+            prop_assert_eq!(ValueLogEntry::deserialize(&padded).unwrap().1, encoded.len());
+            let cut = cut.index(encoded.len());
+            prop_assert!(ValueLogEntry::deserialize(&encoded[..cut]).is_none());
+        }
+    }
+}
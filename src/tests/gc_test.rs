@@ -1,10 +1,11 @@
 #[cfg(test)]
 mod tests {
     use crate::consts::{SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8};
-    use crate::db::{DataStore, SizeUnit};
+    use crate::db::{DataStore, GcDiskPressure, SizeUnit};
     use crate::err::Error;
-    use crate::gc::garbage_collector::GC;
+    use crate::gc::garbage_collector::{PunchMarker, GC};
     use crate::types::Key;
+    use crate::vlog::ValueLog;
     use std::sync::Arc;
     use tempfile::tempdir;
     use tokio::sync::RwLock;
@@ -310,35 +311,568 @@ mod tests {
         #[cfg(target_os = "linux")]
         {
             use std::io::{Read, Seek, SeekFrom, Write};
+            use std::os::unix::fs::MetadataExt;
             use tempfile::NamedTempFile;
 
-            const PUNCH_START: i64 = 0;
-            const PUNCH_LENGTH: usize = 7;
             let mut temp_file = NamedTempFile::new().unwrap();
             let file_path = temp_file.path().to_path_buf();
-            writeln!(temp_file, "Sample1Sample2Sample3Sample4Sample5Sample6").unwrap();
+            let block_size = temp_file.as_file().metadata().unwrap().blksize() as usize;
+            temp_file.write_all(&vec![1; 3 * block_size]).unwrap();
+            temp_file.flush().unwrap();
+
+            // range covers one whole block and parts of its neighbours
+            let punch_start = block_size / 2;
+            let punch_length = 2 * block_size;
+            let punch_res = GC::punch_holes(file_path, punch_start as i64, punch_length as i64).await;
+            assert_eq!(punch_res.unwrap(), Some((block_size as i64, block_size as i64)));
 
+            let inner_file = temp_file.as_file_mut();
+            inner_file.seek(SeekFrom::Start(0)).unwrap();
+            let mut buffer = vec![0; 3 * block_size];
+            inner_file.read_exact(&mut buffer).unwrap();
+            // After punch, only the whole block should be zero
+            assert!(buffer[..block_size].iter().all(|b| *b == 1));
+            assert!(buffer[block_size..2 * block_size].iter().all(|b| *b == 0));
+            assert!(buffer[2 * block_size..].iter().all(|b| *b == 1));
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_gc_test_punch_hole_smaller_than_block() {
+        #[cfg(target_os = "linux")]
+        {
+            use std::io::Write;
+            use tempfile::NamedTempFile;
+
+            let mut temp_file = NamedTempFile::new().unwrap();
+            let file_path = temp_file.path().to_path_buf();
+            writeln!(temp_file, "Sample1Sample2Sample3Sample4Sample5Sample6").unwrap();
             temp_file.flush().unwrap();
-            temp_file.as_file_mut().seek(SeekFrom::Start(0)).unwrap();
 
-            // Before punch, offsets within this range should be contain bytes
-            let mut buffer = [0; PUNCH_LENGTH];
-            let bytes_read = temp_file.read(&mut buffer).unwrap();
-            assert_eq!(bytes_read, PUNCH_LENGTH);
-            assert_eq!(&buffer, b"Sample1"); // bytes present in offset
+            let punch_res = GC::punch_holes(file_path, 0, 7).await;
+            assert_eq!(punch_res.unwrap(), None);
+        }
+    }
 
-            let punch_res = GC::punch_holes(file_path, PUNCH_START, PUNCH_LENGTH as i64).await;
+    #[test]
+    fn gc_test_align_to_blocks() {
+        assert_eq!(GC::align_to_blocks(0, 8192, 4096), Some((0, 8192)));
+        assert_eq!(GC::align_to_blocks(100, 8192, 4096), Some((4096, 4096)));
+        assert_eq!(GC::align_to_blocks(4096, 4095, 4096), None);
+        assert_eq!(GC::align_to_blocks(100, 50, 4096), None);
+        assert_eq!(GC::align_to_blocks(0, 0, 4096), None);
+    }
 
-            assert!(punch_res.is_ok());
+    #[test]
+    fn gc_test_punch_marker_resume_keeps_unpunched_bytes() {
+        let marker = PunchMarker {
+            punch_hole_start_offset: 8192,
+            punch_hole_length: 0,
+            carried_start_offset: 4096,
+            carried_length: 4096,
+        };
+        // tail recovered to where gc stopped, carried bytes are still to be punched
+        assert_eq!(marker.clone().resume(8192), marker);
 
-            let inner_file = temp_file.as_file_mut();
-            inner_file.seek(SeekFrom::Start(0)).unwrap();
+        // tail recovered behind the marker, its bytes may still be referenced
+        let resumed = marker.resume(4096);
+        assert_eq!(resumed.reached_tail(), 4096);
+        assert_eq!((resumed.carried_start_offset, resumed.carried_length), (4096, 0));
+        assert_eq!(resumed.punch_hole_length, 0);
+    }
+
+    #[test]
+    fn gc_test_punch_marker_merges_adjacent_ranges() {
+        let mut marker = PunchMarker {
+            punch_hole_start_offset: 100,
+            punch_hole_length: 5000,
+            ..Default::default()
+        };
+        let range = marker.range_to_punch();
+        assert_eq!(range, (100, 5000));
+        // range does not cover a whole block, so all of it is carried over
+        marker.carry_over(range, None);
+        assert_eq!((marker.carried_start_offset, marker.carried_length), (100, 5000));
+
+        // next range starts where the previous one ended
+        marker.punch_hole_start_offset = 5100;
+        marker.punch_hole_length = 3092;
+        let range = marker.range_to_punch();
+        assert_eq!(range, (100, 8092));
+        marker.carry_over(range, Some((4096, 4096)));
+        assert_eq!((marker.carried_start_offset, marker.carried_length), (8192, 0));
+        assert_eq!(marker.punch_hole_length, 0);
+
+        // ranges that are not adjacent are not merged
+        marker.carried_start_offset = 0;
+        marker.carried_length = 10;
+        marker.punch_hole_start_offset = 20;
+        marker.punch_hole_length = 30;
+        assert_eq!(marker.range_to_punch(), (20, 30));
+    }
+
+    #[tokio::test]
+    async fn gc_test_rewrite_keeps_metadata() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("gc_test_metadata");
+        let vlog = Arc::new(RwLock::new(ValueLog::new(path).await.unwrap()));
+        let valid_entries = Arc::new(RwLock::new(vec![
+            (b"key1".to_vec(), b"val1".to_vec(), Some(b"v2".to_vec())),
+            (b"key2".to_vec(), b"val2".to_vec(), None),
+        ]));
+        let synced_entries = Arc::new(RwLock::new(Vec::new()));
+        GC::write_valid_entries_to_vlog(valid_entries, synced_entries.clone(), vlog.clone())
+            .await
+            .unwrap();
+
+        let synced = synced_entries.read().await;
+        let entry = vlog.read().await.get_entry(synced[0].2).await.unwrap().unwrap();
+        assert_eq!(entry.metadata, Some(b"v2".to_vec()));
+        let entry = vlog.read().await.get_entry(synced[1].2).await.unwrap().unwrap();
+        assert_eq!(entry.metadata, None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn gc_test_punch_deferred_while_pinned() {
+        use std::os::unix::fs::MetadataExt;
+        let root = tempdir().unwrap();
+        let path = root.path().join("gc_test_pinned");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        for i in 0..64 {
+            store.put(format!("key{}", i), vec![b'v'; 1024]).await.unwrap();
+        }
+        store.val_log.sync_to_disk().await.unwrap();
+        let vlog = store.val_log.content.file.node.file_path.to_owned();
+        let block_size = std::fs::metadata(&vlog).unwrap().blksize() as usize;
+        let tail = store.gc_log.read().await.tail_offset;
+        {
+            let mut marker = store.gc.punch_marker.lock().await;
+            marker.punch_hole_start_offset = tail;
+            marker.punch_hole_length = 8 * block_size;
+        }
+
+        // space stays allocated while files are pinned, tail moves anyway
+        let live_files = store.live_files().await;
+        let blocks = std::fs::metadata(&vlog).unwrap().blocks();
+        store.sync_gc_update_with_store().await.unwrap();
+        assert_eq!(std::fs::metadata(&vlog).unwrap().blocks(), blocks);
+        assert_eq!(store.gc_log.read().await.tail_offset, tail + 8 * block_size);
+
+        // range is punched on the next sync once the pin is dropped
+        drop(live_files);
+        store.sync_gc_update_with_store().await.unwrap();
+        assert!(std::fs::metadata(&vlog).unwrap().blocks() < blocks);
+        assert_eq!(store.gc_log.read().await.tail_offset, tail + 8 * block_size);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn gc_test_keeps_referenced_blobs() {
+        use crate::cfg::Config;
+        let root = tempdir().unwrap();
+        let path = root.path().join("gc_test_dedup");
+        let config = Config {
+            dedup_min_value_size: Some(64),
+            gc_chunk_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let mut store = DataStore::open_with_config("test", path, config).await.unwrap();
+        let shared = vec![b's'; 256];
+        let dropped = vec![b'd'; 256];
+        store.put("key1", &shared).await.unwrap();
+        store.put("key2", &shared).await.unwrap();
+        store.put("key3", &dropped).await.unwrap();
+        store.put("key3", "small").await.unwrap();
+        store.delete("key1").await.unwrap();
+        // let writes reach the gc table
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        let dedup = store.val_log.dedup.clone().unwrap();
+        let shared_hash = crate::util::content_hash(&shared);
+        let dropped_hash = crate::util::content_hash(&dropped);
+        let shared_offset = dedup.locate(shared_hash).unwrap();
+        assert!(dedup.locate(dropped_hash).is_some());
+
+        let config = store.gc.config.clone();
+        GC::gc_handler(
+            &config,
+            Arc::clone(&store.gc_table),
+            Arc::clone(&store.gc_log),
+            Arc::clone(&store.key_range),
+            Arc::clone(&store.read_only_memtables),
+            Arc::clone(&store.gc_updated_entries),
+            Arc::clone(&store.gc.punch_marker),
+        )
+        .await
+        .unwrap();
+        store.sync_gc_update_with_store().await.unwrap();
+
+        // blob of the overwritten value is dropped, the shared one is moved
+        assert_eq!(dedup.locate(dropped_hash), None);
+        assert_ne!(dedup.locate(shared_hash).unwrap(), shared_offset);
+        assert_eq!(store.get("key2").await.unwrap().unwrap().val, shared);
+        assert!(store.get("key1").await.unwrap().is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn gc_test_keeps_restorable_soft_deletes() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("gc_test_soft_delete");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        store.put_with_metadata("key1", "val1", "v1").await.unwrap();
+        store.put("key2", "val2").await.unwrap();
+        store.soft_delete("key1").await.unwrap();
+        store.soft_delete("key2").await.unwrap();
+        store.put("key2", "new").await.unwrap();
+        // let writes reach the gc table
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        let config = store.gc.config.clone();
+        GC::gc_handler(
+            &config,
+            Arc::clone(&store.gc_table),
+            Arc::clone(&store.gc_log),
+            Arc::clone(&store.key_range),
+            Arc::clone(&store.read_only_memtables),
+            Arc::clone(&store.gc_updated_entries),
+            Arc::clone(&store.gc.punch_marker),
+        )
+        .await
+        .unwrap();
+        store.sync_gc_update_with_store().await.unwrap();
+
+        // soft deleted value moved along, the one written again since was dropped
+        assert!(store.get("key1").await.unwrap().is_none());
+        assert!(store.undelete("key1").await.unwrap());
+        let (entry, metadata) = store.get_with_metadata("key1").await.unwrap().unwrap();
+        assert_eq!(entry.val, b"val1");
+        assert_eq!(metadata, Some(b"v1".to_vec()));
+        assert!(!store.undelete("key2").await.unwrap());
+        assert_eq!(store.get("key2").await.unwrap().unwrap().val, b"new");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn gc_test_records_passes() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("gc_test_telemetry");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        for i in 0..100 {
+            store.put(format!("key{:03}", i), "old").await.unwrap();
+        }
+        for i in 0..50 {
+            store.put(format!("key{:03}", i), "new").await.unwrap();
+        }
+        // let writes reach the gc table
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        let mut passes = store.subscribe_gc();
+        assert!(passes.try_recv().is_none());
+
+        let config = store.gc.config.clone();
+        GC::gc_handler(
+            &config,
+            Arc::clone(&store.gc_table),
+            Arc::clone(&store.gc_log),
+            Arc::clone(&store.key_range),
+            Arc::clone(&store.read_only_memtables),
+            Arc::clone(&store.gc_updated_entries),
+            Arc::clone(&store.gc.punch_marker),
+        )
+        .await
+        .unwrap();
+        store.sync_gc_update_with_store().await.unwrap();
+
+        let pass = passes.try_recv().unwrap();
+        assert!(pass.bytes_scanned > 0);
+        assert!(pass.dead_entries > 0);
+        assert!(pass.live_entries > 0);
+        assert!(pass.rewritten_bytes > 0);
+        assert_eq!(pass.tail_advanced, pass.bytes_scanned);
+        assert!(pass.head_advanced > 0);
+        let stats = store.gc_stats();
+        assert_eq!(stats.passes, 1);
+        assert_eq!(stats.bytes_scanned, pass.bytes_scanned);
+        assert_eq!(stats.dead_entries, pass.dead_entries);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn gc_test_punch_marker_survives_restart() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("gc_test_punch_marker");
+        let mut store = DataStore::open_without_background("test", path.clone())
+            .await
+            .unwrap();
+        for i in 0..100 {
+            store.put(format!("key{:03}", i), "old").await.unwrap();
+        }
+        for i in 0..50 {
+            store.put(format!("key{:03}", i), "new").await.unwrap();
+        }
+        // let writes reach the gc table
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        let config = store.gc.config.clone();
+        GC::gc_handler(
+            &config,
+            Arc::clone(&store.gc_table),
+            Arc::clone(&store.gc_log),
+            Arc::clone(&store.key_range),
+            Arc::clone(&store.read_only_memtables),
+            Arc::clone(&store.gc_updated_entries),
+            Arc::clone(&store.gc.punch_marker),
+        )
+        .await
+        .unwrap();
+        store.sync_gc_update_with_store().await.unwrap();
+        let tail = store.val_log.tail_offset;
+        assert!(tail > 0);
+        let vlog_dir = GC::journal_dir(&store.gc_log).await;
+        let marker = PunchMarker::read(&vlog_dir).await.unwrap().unwrap();
+        assert_eq!(marker.reached_tail(), tail);
+        drop(store);
+
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        assert_eq!(store.val_log.tail_offset, tail);
+        assert_eq!(store.gc.punch_marker.lock().await.reached_tail(), tail);
+        for i in 0..100 {
+            let expected = if i < 50 { "new" } else { "old" };
+            let value = store.get(format!("key{:03}", i)).await.unwrap().unwrap();
+            assert_eq!(value.val, expected.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn gc_test_table_shared_after_rotation() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("gc_test_shared_table");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        store.put("key1", "val1").await.unwrap();
+        store.migrate_memtable_to_read_only().await;
+        store.put("key2", "val2").await.unwrap();
+
+        // garbage collection keeps the table it was created with
+        assert!(Arc::ptr_eq(&store.gc.table, &store.gc_table));
+        let table = store.gc.table.read().await;
+        assert!(table.get(b"key1").is_none());
+        assert!(table.get(b"key2").is_some());
+    }
+
+    #[tokio::test]
+    async fn gc_test_skips_moves_of_keys_written_during_pass() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("gc_test_written_during_pass");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        store.put("key1", "old").await.unwrap();
+        store.put("key2", "old").await.unwrap();
+
+        let writes = store.gc.config.writes.clone();
+        let tracking = writes.track();
+        store.put("key1", "new").await.unwrap();
+        // pass found both old versions valid before key1 was written
+        let moved = Arc::new(RwLock::new(vec![
+            (b"key1".to_vec(), b"old".to_vec(), 0),
+            (b"key2".to_vec(), b"old".to_vec(), 0),
+        ]));
+        GC::write_valid_entries_to_store(
+            moved,
+            Arc::clone(&store.gc.table),
+            Arc::clone(&store.gc_updated_entries),
+            Arc::clone(&store.gc_log),
+            &writes,
+        )
+        .await
+        .unwrap();
+        drop(tracking);
+
+        let updated = store.gc_updated_entries.read().await;
+        assert!(updated.get(b"key1".as_slice()).is_none());
+        assert!(updated.get(b"key2".as_slice()).is_some());
+        drop(updated);
+        assert_eq!(store.get("key1").await.unwrap().unwrap().val, b"new");
+        assert!(!writes.is_written(b"key1"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn gc_test_concurrent_writes_keep_latest_versions() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("gc_test_concurrent_writes");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        for i in 0..200 {
+            store.put(format!("key{:03}", i), "v0").await.unwrap();
+        }
+        // let writes reach the gc table
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        let config = store.gc.config.clone();
+        let gc_table = Arc::clone(&store.gc.table);
+        let gc_log = Arc::clone(&store.gc_log);
+        let key_range = Arc::clone(&store.key_range);
+        let read_only_memtables = Arc::clone(&store.read_only_memtables);
+        let gc_updated_entries = Arc::clone(&store.gc_updated_entries);
+        let punch_marker = Arc::clone(&store.gc.punch_marker);
+        let store = Arc::new(RwLock::new(store));
+        let writer = {
+            let store = Arc::clone(&store);
+            tokio::spawn(async move {
+                for round in 1..=5 {
+                    for i in 0..200 {
+                        store
+                            .write()
+                            .await
+                            .put(format!("key{:03}", i), format!("v{}", round))
+                            .await
+                            .unwrap();
+                    }
+                }
+            })
+        };
+        while !writer.is_finished() {
+            // same rule as the gc worker, moves are synced before the next pass
+            if gc_updated_entries.read().await.is_empty() {
+                GC::gc_handler(
+                    &config,
+                    Arc::clone(&gc_table),
+                    Arc::clone(&gc_log),
+                    Arc::clone(&key_range),
+                    Arc::clone(&read_only_memtables),
+                    Arc::clone(&gc_updated_entries),
+                    Arc::clone(&punch_marker),
+                )
+                .await
+                .unwrap();
+            }
+            tokio::task::yield_now().await;
+        }
+        writer.await.unwrap();
+
+        let mut store = store.write().await;
+        store.sync_gc_update_with_store().await.unwrap();
+        assert!(store.gc_stats().passes > 0);
+        for i in 0..200 {
+            let value = store.get(format!("key{:03}", i)).await.unwrap().unwrap();
+            assert_eq!(value.val, b"v5");
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn gc_test_chunk_grows_under_disk_pressure() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("gc_test_disk_pressure");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        for i in 0..100 {
+            store.put(format!("key{:03}", i), "old").await.unwrap();
+        }
+        for i in 0..100 {
+            store.put(format!("key{:03}", i), "new").await.unwrap();
+        }
+        // let writes reach the gc table
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        // any free space is below the thresholds
+        store.gc.set_disk_pressure(Some(GcDiskPressure {
+            low_free_space: u64::MAX,
+            critical_free_space: u64::MAX - 1,
+            max_chunk_size: SizeUnit::Megabytes.as_bytes(1),
+        }));
+        let mut passes = store.subscribe_gc();
+
+        let mut config = store.gc.config.clone();
+        config.gc_chunk_size = 100;
+        GC::gc_handler(
+            &config,
+            Arc::clone(&store.gc_table),
+            Arc::clone(&store.gc_log),
+            Arc::clone(&store.key_range),
+            Arc::clone(&store.read_only_memtables),
+            Arc::clone(&store.gc_updated_entries),
+            Arc::clone(&store.gc.punch_marker),
+        )
+        .await
+        .unwrap();
+        store.sync_gc_update_with_store().await.unwrap();
+
+        let pass = passes.try_recv().unwrap();
+        assert_eq!(pass.chunk_size, SizeUnit::Megabytes.as_bytes(1) as u64);
+        // whole value log fits the grown chunk
+        assert_eq!(pass.dead_entries, 100);
+        for i in 0..100 {
+            let value = store.get(format!("key{:03}", i)).await.unwrap().unwrap();
+            assert_eq!(value.val, b"new");
+        }
+    }
+
+    #[tokio::test]
+    async fn gc_test_journal_rolls_forward_relocated_cycle() {
+        use crate::gc::journal::{GcJournal, GcPhase};
+        let root = tempdir().unwrap();
+        let path = root.path().join("gc_test_journal_forward");
+        let mut store = DataStore::open_without_background("test", path.clone())
+            .await
+            .unwrap();
+        for i in 0..100 {
+            store.put(format!("key{:03}", i), "old").await.unwrap();
+        }
+        for i in 0..50 {
+            store.put(format!("key{:03}", i), "new").await.unwrap();
+        }
+        // let writes reach the gc table
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        // crash once valid entries are relocated but before the store picks them up
+        let mut config = store.gc.config.clone();
+        config.gc_chunk_size = 2048;
+        GC::gc_handler(
+            &config,
+            Arc::clone(&store.gc_table),
+            Arc::clone(&store.gc_log),
+            Arc::clone(&store.key_range),
+            Arc::clone(&store.read_only_memtables),
+            Arc::clone(&store.gc_updated_entries),
+            Arc::clone(&store.gc.punch_marker),
+        )
+        .await
+        .unwrap();
+        let vlog_dir = GC::journal_dir(&store.gc_log).await;
+        let journal = GcJournal::read(&vlog_dir).await.unwrap().unwrap();
+        assert_eq!(journal.phase, GcPhase::Relocated);
+        assert!(!journal.rewrites.is_empty());
+        drop(store);
+
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        assert_eq!(store.val_log.tail_offset, journal.new_tail_offset);
+        for i in 0..100 {
+            let expected = if i < 50 { "new" } else { "old" };
+            let value = store.get(format!("key{:03}", i)).await.unwrap().unwrap();
+            assert_eq!(value.val, expected.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn gc_test_journal_rolls_back_planned_cycle() {
+        use crate::gc::journal::GcJournal;
+        let root = tempdir().unwrap();
+        let path = root.path().join("gc_test_journal_back");
+        let mut store = DataStore::open_without_background("test", path.clone())
+            .await
+            .unwrap();
+        for i in 0..100 {
+            store.put(format!("key{:03}", i), "val").await.unwrap();
+        }
+        store.val_log.sync_to_disk().await.unwrap();
+        let (head, size) = (store.val_log.head_offset, store.val_log.size);
+        let vlog_dir = GC::journal_dir(&store.gc_log).await;
+        drop(store);
 
-            // After punch, offsets within this range should be zero
-            let mut buffer = [0; PUNCH_LENGTH];
-            let bytes_read = inner_file.read(&mut buffer).unwrap();
-            assert_eq!(bytes_read, PUNCH_LENGTH);
-            assert_eq!(&buffer, &[0; 7]); // all set to zero
+        // crash before anything of the cycle was written
+        GcJournal::planned(head, size).write(&vlog_dir).await.unwrap();
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        assert_eq!(store.val_log.tail_offset, head);
+        assert_eq!(store.val_log.head_offset, head);
+        for i in 0..100 {
+            assert!(store.get(format!("key{:03}", i)).await.unwrap().is_some());
         }
     }
 }
@@ -2,7 +2,6 @@
 mod tests {
     use crate::key_range::KeyRange;
     use crate::tests::*;
-    use std::time::Duration;
     use workload::SSTContructor;
 
     #[tokio::test]
@@ -23,14 +22,12 @@ mod tests {
     async fn test_default_keyrange() {
         let default_key_range = KeyRange::default();
         assert!(default_key_range.key_ranges.read().await.is_empty());
-        assert!(default_key_range.restored_ranges.read().await.is_empty());
     }
 
     #[tokio::test]
     async fn test_new_keyrange() {
         let new_key_range = KeyRange::new();
         assert!(new_key_range.key_ranges.read().await.is_empty());
-        assert!(new_key_range.restored_ranges.read().await.is_empty());
     }
 
     #[tokio::test]
@@ -175,6 +172,52 @@ mod tests {
         assert!(sst_found)
     }
 
+    #[tokio::test]
+    async fn test_key_range_recovered_filter_is_cached() {
+        let filter_cache = crate::filter::FilterCache::new(1024 * 1024);
+        let key_range = KeyRange::with_filter_cache(filter_cache.clone());
+        let mut fake_sstable = SSTContructor::generate_ssts(1).await[0].to_owned();
+        fake_sstable.load_entries_from_file().await.unwrap();
+        let entries = fake_sstable.entries.to_owned();
+        let smallest_key = entries.front().unwrap().key().to_owned();
+        let biggest_key = entries.back().unwrap().key().to_owned();
+        let fake_sst_dir = fake_sstable.dir.to_owned();
+        key_range
+            .set(fake_sst_dir.to_owned(), &smallest_key, &biggest_key, fake_sstable)
+            .await;
+        assert!(filter_cache.get(&fake_sst_dir).is_none());
+
+        let retrieved_sstables = key_range.filter_sstables_by_key_range(&smallest_key).await;
+        assert!(retrieved_sstables.is_ok());
+        let cached = filter_cache.get(&fake_sst_dir);
+        assert!(cached.is_some());
+        assert!(cached.unwrap().contains(&smallest_key));
+        assert!(filter_cache.usage() > 0);
+
+        // Another key range sharing the cache reuses the rebuilt filter
+        let other_key_range = KeyRange::with_filter_cache(filter_cache.clone());
+        let mut other_sstable = key_range
+            .key_ranges
+            .read()
+            .await
+            .get(&fake_sst_dir)
+            .unwrap()
+            .sst
+            .clone();
+        other_sstable.filter.as_mut().unwrap().file_path = None;
+        other_key_range
+            .set(
+                fake_sst_dir.to_owned(),
+                &smallest_key,
+                &biggest_key,
+                other_sstable,
+            )
+            .await;
+        let retrieved_sstables = other_key_range.filter_sstables_by_key_range(&smallest_key).await;
+        assert!(retrieved_sstables.is_ok());
+        assert_eq!(retrieved_sstables.unwrap().len(), 1);
+    }
+
     #[tokio::test]
     async fn test_key_range_not_found() {
         let key_range = KeyRange::new();
@@ -201,64 +244,79 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_restored_key_range() {
-        let key_range = KeyRange::new();
-        let ssts = SSTContructor::generate_ssts(2).await;
-
-        // Insert sstable
-        let mut fake_sstable = ssts[0].to_owned();
+    async fn test_key_range_set_moves_filter_to_cache() {
+        let filter_cache = crate::filter::FilterCache::new(1024 * 1024);
+        let key_range = KeyRange::with_filter_cache(filter_cache.clone());
+        let mut fake_sstable = SSTContructor::generate_ssts(1).await[0].to_owned();
         fake_sstable.load_entries_from_file().await.unwrap();
         let entries = fake_sstable.entries.to_owned();
-        let binding = entries.front().unwrap();
-        let smallest_key = binding.key();
-        let binding = entries.back().unwrap();
-        let biggest_key = binding.key();
+        let smallest_key = entries.front().unwrap().key().to_owned();
+        let biggest_key = entries.back().unwrap().key().to_owned();
         let fake_sst_dir = fake_sstable.dir.to_owned();
+
+        // Filter built in memory, as after a flush
+        let mut filter = crate::filter::BloomFilter::new(0.01, entries.len());
+        filter.build_filter_from_entries(&entries);
+        filter.set_sstable_path(&fake_sst_dir);
+        fake_sstable.filter.as_mut().unwrap().bit_vec = filter.bit_vec.clone();
+        fake_sstable.filter.as_mut().unwrap().no_of_hash_func = filter.no_of_hash_func;
+        fake_sstable.filter.as_mut().unwrap().sst_dir = Some(fake_sst_dir.to_owned());
         key_range
-            .set(fake_sst_dir.to_owned(), smallest_key, biggest_key, fake_sstable)
+            .set(fake_sst_dir.to_owned(), &smallest_key, &biggest_key, fake_sstable)
             .await;
-        assert_eq!(key_range.key_ranges.read().await.len(), 1);
 
-        // Bloom Filter should be empty
+        // Key range keeps filter metadata only
         let range = key_range.key_ranges.read().await;
-        let sst_with_empty_filter = range.get(&fake_sst_dir).unwrap();
+        let stored_filter = range.get(&fake_sst_dir).unwrap().sst.filter.clone().unwrap();
+        assert!(stored_filter.sst_dir.is_none());
+        assert_eq!(stored_filter.num_bits(), 0);
+        drop(range);
 
-        // Ensure Bloom Filter does not exist for this sstable
-        assert!(sst_with_empty_filter
-            .sst
-            .filter
-            .clone()
-            .unwrap()
-            .sst_dir
-            .is_none());
+        let cached = filter_cache.get(&fake_sst_dir);
+        assert!(cached.is_some());
+        assert_eq!(cached.unwrap().num_bits(), filter.num_bits());
 
-        // Ensure restored ranges is loaded
-        let retrieved_sstables = key_range.filter_sstables_by_key_range(smallest_key).await;
-        assert!(retrieved_sstables.is_ok());
-        assert!(!retrieved_sstables.as_ref().unwrap().is_empty());
+        // Removing the sstable drops its filter from the cache
+        key_range.remove(&fake_sst_dir).await;
+        assert!(filter_cache.get(&fake_sst_dir).is_none());
+        assert_eq!(filter_cache.usage(), 0);
+    }
 
-        // sleep for 3 seconds to ensure all background tasks completes
-        tokio::time::sleep(Duration::from_secs(3)).await;
+    #[tokio::test]
+    async fn test_key_range_evicted_filter_is_reloaded() {
+        let ssts = SSTContructor::generate_ssts(2).await;
+        let mut keys = Vec::new();
+        let mut filter_sizes = Vec::new();
+        for (i, sst) in ssts.iter().enumerate() {
+            let mut sst = sst.to_owned();
+            sst.load_entries_from_file().await.unwrap();
+            // A different key from every sstable, so each lookup loads one filter
+            keys.push(sst.entries.iter().nth(i).unwrap().key().to_owned());
+            let mut filter = sst.filter.clone().unwrap();
+            filter.recover_meta().await.unwrap();
+            filter_sizes.push(filter.num_bits().div_ceil(8));
+        }
+        // Room for one filter only
+        let filter_cache = crate::filter::FilterCache::new(*filter_sizes.iter().max().unwrap());
+        let key_range = KeyRange::with_filter_cache(filter_cache.clone());
+        for (sst, key) in ssts.iter().zip(keys.iter()) {
+            key_range.set(sst.dir.to_owned(), key, key, sst.to_owned()).await;
+        }
 
-        // Since we have not synced restored ssts with major key range
-        // we should find the sstable in the retored key range
-        let retrieved_sstables = key_range.check_restored_key_ranges(smallest_key).await;
-        assert!(retrieved_sstables.is_ok());
-        assert!(!retrieved_sstables.as_ref().unwrap().is_empty());
-        let mut sst_found = false;
-        for sst in retrieved_sstables.unwrap() {
-            if sst.dir == fake_sst_dir {
-                sst_found = true;
-                // Bloom Filter should have be restored
-                assert!(sst.filter.clone().unwrap().sst_dir.is_some());
+        for _ in 0..2 {
+            for (sst, key) in ssts.iter().zip(keys.iter()) {
+                let retrieved_sstables = key_range.filter_sstables_by_key_range(key).await.unwrap();
+                assert!(retrieved_sstables.iter().any(|s| s.dir == sst.dir));
+                assert!(filter_cache.get(&sst.dir).is_some());
+                assert!(filter_cache.usage() <= filter_cache.capacity());
             }
         }
-        // SSTable should have been found
-        assert!(sst_found)
+        // Filter of the least recently read sstable was evicted
+        assert!(filter_cache.get(&ssts[0].dir).is_none());
     }
 
     #[tokio::test]
-    async fn test_update_key_range() {
+    async fn test_key_range_range_query_scan() {
         let key_range = KeyRange::new();
         let ssts = SSTContructor::generate_ssts(2).await;
 
@@ -274,60 +332,43 @@ mod tests {
         key_range
             .set(fake_sst_dir.to_owned(), smallest_key, biggest_key, fake_sstable)
             .await;
-        assert_eq!(key_range.key_ranges.read().await.len(), 1);
-
-        // Bloom Filter should be empty
-        let range = key_range.key_ranges.read().await;
-        let sst_with_empty_filter = range.get(&fake_sst_dir).unwrap();
 
-        // Ensure Bloom Filter does not exist for this sstable
-        assert!(sst_with_empty_filter
-            .sst
-            .filter
-            .clone()
-            .unwrap()
-            .sst_dir
-            .is_none());
-
-        // Ensure restored ranges is loaded
-        let retrieved_sstables = key_range.filter_sstables_by_key_range(smallest_key).await;
-        assert!(retrieved_sstables.is_ok());
-        assert!(!retrieved_sstables.as_ref().unwrap().is_empty());
-
-        // Sleep for 3 seconds to ensure all background tasks completes
-        tokio::time::sleep(Duration::from_secs(3)).await;
-        // Drop read lock
-        drop(range);
-        // Call update on key ranges to sync restored key range with main key ranges hash map
-        key_range.update_key_range().await;
-
-        let retrieved_sstables = key_range.check_restored_key_ranges(smallest_key).await;
-        assert!(retrieved_sstables.is_ok());
-        // retrieved sstables should now be empty since we synced it
-        // with main key ranges hash map
-        assert!(retrieved_sstables.as_ref().unwrap().is_empty());
+        let range = key_range.range_query_scan(smallest_key, biggest_key).await;
+        assert_eq!(range.len(), 1);
+        assert_eq!(range.first().unwrap().sst.dir, fake_sst_dir);
     }
 
     #[tokio::test]
-    async fn test_key_range_range_query_scan() {
+    async fn test_key_range_prunes_disjoint_sstables() {
         let key_range = KeyRange::new();
-        let ssts = SSTContructor::generate_ssts(2).await;
+        let ssts = SSTContructor::generate_ssts(3).await;
+        let spans = [("apple", "fig"), ("grape", "mango"), ("nectarine", "pear")];
+        for (sst, (smallest, biggest)) in ssts.iter().zip(spans.iter()) {
+            key_range
+                .set(sst.dir.to_owned(), *smallest, *biggest, sst.to_owned())
+                .await;
+        }
 
-        // Insert sstable
-        let mut fake_sstable = ssts[0].to_owned();
-        fake_sstable.load_entries_from_file().await.unwrap();
-        let entries = fake_sstable.entries.to_owned();
-        let binding = entries.front().unwrap();
-        let smallest_key = binding.key();
-        let binding = entries.back().unwrap();
-        let biggest_key = binding.key();
-        let fake_sst_dir = fake_sstable.dir.to_owned();
-        key_range
-            .set(fake_sst_dir.to_owned(), smallest_key, biggest_key, fake_sstable)
+        let mut scanned: Vec<_> = key_range
+            .range_query_scan("banana", "kiwi")
+            .await
+            .into_iter()
+            .map(|r| r.sst.dir)
+            .collect();
+        scanned.sort();
+        let mut expected = vec![ssts[0].dir.to_owned(), ssts[1].dir.to_owned()];
+        expected.sort();
+        assert_eq!(scanned, expected);
+
+        let disjoint = key_range
+            .disjoint_from(&("fig".as_bytes().."grape".as_bytes()))
             .await;
+        assert!(!disjoint.contains(&ssts[0].dir));
+        assert!(disjoint.contains(&ssts[1].dir));
+        assert!(disjoint.contains(&ssts[2].dir));
 
-        let range = key_range.range_query_scan(smallest_key, biggest_key).await;
-        assert_eq!(range.len(), 1);
-        assert_eq!(range.first().unwrap().sst.dir, fake_sst_dir);
+        let disjoint = key_range.disjoint_from(&("pear".as_bytes()..)).await;
+        assert_eq!(disjoint.len(), 2);
+        assert!(!disjoint.contains(&ssts[2].dir));
     }
 }
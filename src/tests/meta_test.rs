@@ -88,4 +88,36 @@ mod tests {
 
         assert_eq!(serialized_entry.len(), expected_entry_len);
     }
+
+    #[tokio::test]
+    async fn test_meta_write_replaces_file() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("meta_replace");
+
+        let mut metadata = Meta::new(path.to_owned()).await.unwrap();
+        metadata.set_head(50);
+        metadata.write().await.unwrap();
+        metadata.set_head(75);
+        metadata.set_tail(10);
+        metadata.write().await.unwrap();
+
+        // every write replaces the whole file, no temporary file is left behind
+        let file_len = tokio::fs::metadata(&metadata.file_handle.path)
+            .await
+            .unwrap()
+            .len();
+        assert_eq!(file_len as usize, metadata.serialize().len());
+        let mut entries = tokio::fs::read_dir(&path).await.unwrap();
+        let mut file_count = 0;
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            assert!(!entry.file_name().to_string_lossy().ends_with(".tmp"));
+            file_count += 1;
+        }
+        assert_eq!(file_count, 1);
+
+        let mut recovered_meta = Meta::new(path).await.unwrap();
+        recovered_meta.recover().await.unwrap();
+        assert_eq!(recovered_meta.v_log_head, 75);
+        assert_eq!(recovered_meta.v_log_tail, 10);
+    }
 }
@@ -0,0 +1,147 @@
+#[cfg(test)]
+mod tests {
+    use crate::consts::{
+        FILTER_FILE_NAME, FILTER_META_SIZE, FORMAT_VERSION, INDEX_FILE_NAME, LEGACY_FORMAT_VERSION,
+        MIGRATION_FILE_NAME, SIZE_OF_U32, SIZE_OF_U64, SUMMARY_FILE_NAME,
+    };
+    use crate::db::{DataStore, StoreInfo};
+    use crate::fs::{FilterFileNode, FilterFs};
+    use crate::tools;
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+
+    fn sst_files(sst_dir: &Path) -> [PathBuf; 3] {
+        [
+            sst_dir.join(format!("{}.db", INDEX_FILE_NAME)),
+            sst_dir.join(format!("{}.db", SUMMARY_FILE_NAME)),
+            sst_dir.join(format!("{}.db", FILTER_FILE_NAME)),
+        ]
+    }
+
+    /// Creates a store with `tables` sstables and returns their directories
+    async fn create_store(path: &Path, tables: usize) -> Vec<PathBuf> {
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        for t in 0..tables {
+            for i in 0..200 {
+                store.put(format!("key_{}_{:04}", t, i), "value").await.unwrap();
+            }
+            store.force_flush().await.unwrap();
+            // sstable directories are named after the flush time
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+        let mut sst_dirs = store.live_files().await.sstables;
+        sst_dirs.sort();
+        sst_dirs
+    }
+
+    /// Rewrites files of `sst_dir` in the layout of format 1
+    async fn downgrade_sstable(sst_dir: &Path) {
+        let [index, summary, filter] = sst_files(sst_dir);
+        // no index checksum
+        let buf = tokio::fs::read(&index).await.unwrap();
+        tokio::fs::write(&index, &buf[..buf.len() - SIZE_OF_U32 * 2])
+            .await
+            .unwrap();
+        // no summary stats
+        let buf = tokio::fs::read(&summary).await.unwrap();
+        let stats_len = SIZE_OF_U32 + SIZE_OF_U64 * 3;
+        tokio::fs::write(&summary, &buf[..buf.len() - stats_len])
+            .await
+            .unwrap();
+        // filter metadata only, without checksum
+        let buf = tokio::fs::read(&filter).await.unwrap();
+        tokio::fs::write(&filter, &buf[..FILTER_META_SIZE - SIZE_OF_U32])
+            .await
+            .unwrap();
+    }
+
+    /// Returns index and summary of `sst_dir`, the filter is checked separately
+    /// since memtable filters are sized by capacity rather than entry count
+    async fn read_files(sst_dir: &Path) -> Vec<Vec<u8>> {
+        let mut bufs = Vec::new();
+        for path in &sst_files(sst_dir)[..2] {
+            bufs.push(tokio::fs::read(path).await.unwrap());
+        }
+        bufs
+    }
+
+    async fn has_filter_bits(sst_dir: &Path) -> bool {
+        let [_, _, filter] = sst_files(sst_dir);
+        FilterFileNode::recover_bits(filter).await.unwrap().is_some()
+    }
+
+    #[tokio::test]
+    async fn test_migrate_rewrites_legacy_sstables() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("migrate_test_1");
+        let sst_dirs = create_store(&path, 2).await;
+        let mut written = Vec::new();
+        for sst_dir in sst_dirs.iter() {
+            written.push(read_files(sst_dir).await);
+            downgrade_sstable(sst_dir).await;
+        }
+        tokio::fs::remove_file(StoreInfo::path(&path)).await.unwrap();
+
+        let info = tools::migrate(&path, FORMAT_VERSION).await.unwrap();
+        assert_eq!(info.format_version, FORMAT_VERSION);
+        assert_eq!(StoreInfo::read(&path).await.unwrap().unwrap(), info);
+        assert!(!path.join(MIGRATION_FILE_NAME).exists());
+        for (sst_dir, written) in sst_dirs.iter().zip(written) {
+            assert_eq!(read_files(sst_dir).await, written);
+            assert!(has_filter_bits(sst_dir).await);
+        }
+
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        let res = store.get("key_1_0100").await.unwrap();
+        assert_eq!(res.unwrap().val, b"value".to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_resumes_from_journal() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("migrate_test_2");
+        let sst_dirs = create_store(&path, 2).await;
+        let written = read_files(&sst_dirs[1]).await;
+        for sst_dir in sst_dirs.iter() {
+            downgrade_sstable(sst_dir).await;
+        }
+        tokio::fs::remove_file(StoreInfo::path(&path)).await.unwrap();
+        // first sstable was rewritten before the previous run stopped
+        let journal = serde_json::json!({
+            "target_version": FORMAT_VERSION,
+            "done": [sst_dirs[0]],
+        });
+        tokio::fs::write(path.join(MIGRATION_FILE_NAME), journal.to_string())
+            .await
+            .unwrap();
+        let skipped = read_files(&sst_dirs[0]).await;
+
+        tools::migrate(&path, FORMAT_VERSION).await.unwrap();
+        assert_eq!(read_files(&sst_dirs[0]).await, skipped);
+        assert!(!has_filter_bits(&sst_dirs[0]).await);
+        assert_eq!(read_files(&sst_dirs[1]).await, written);
+        assert!(has_filter_bits(&sst_dirs[1]).await);
+        assert!(!path.join(MIGRATION_FILE_NAME).exists());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_rejects_unsupported_target() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("migrate_test_3");
+        create_store(&path, 1).await;
+
+        let res = tools::migrate(&path, FORMAT_VERSION + 1).await;
+        assert!(matches!(
+            res,
+            Err(crate::err::Error::UnsupportedMigration { from, to })
+                if from == FORMAT_VERSION && to == FORMAT_VERSION + 1
+        ));
+        let res = tools::migrate(&path, LEGACY_FORMAT_VERSION).await;
+        assert!(matches!(res, Err(crate::err::Error::UnsupportedMigration { .. })));
+        // already current
+        let info = tools::migrate(&path, FORMAT_VERSION).await.unwrap();
+        assert_eq!(info.format_version, FORMAT_VERSION);
+    }
+}
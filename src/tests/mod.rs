@@ -1,7 +1,9 @@
 mod bucket_test;
+mod codec_test;
 mod gc_test;
 mod key_range_test;
 mod meta_test;
+mod migrate_test;
 mod sized_tier_test;
 mod store_test;
 mod summary_test;
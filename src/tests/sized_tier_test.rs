@@ -3,10 +3,12 @@ mod tests {
     use crate::bucket::{Bucket, BucketMap};
     use crate::compactors::{Config, IntervalParams, SizedTierRunner, Strategy, TtlParams};
     use crate::consts::MIN_TRESHOLD;
+    use crate::db::Expiry;
     use crate::key_range::KeyRange;
     use crate::memtable::Entry;
     use crate::tests::workload::SSTContructor;
     use chrono::Utc;
+    use crossbeam_skiplist::SkipMap;
     use std::sync::Arc;
     use std::time::Duration;
     use tempfile::tempdir;
@@ -87,17 +89,24 @@ mod tests {
         );
         assert_eq!(new_sized_tier_compaction_runner.config.entry_ttl, ttl.entry_ttl);
         assert_eq!(
-            new_sized_tier_compaction_runner.config.flush_listener_interval,
+            new_sized_tier_compaction_runner
+                .config
+                .flush_listener_interval
+                .interval(),
             intervals.flush_listener_interval
         );
         assert_eq!(
-            new_sized_tier_compaction_runner.config.background_interval,
+            new_sized_tier_compaction_runner
+                .config
+                .background_interval
+                .interval(),
             intervals.background_interval
         );
         assert_eq!(
             new_sized_tier_compaction_runner
                 .config
-                .tombstone_compaction_interval,
+                .tombstone_compaction_interval
+                .interval(),
             intervals.tombstone_compaction_interval
         );
         assert_eq!(
@@ -209,6 +218,41 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_merge_ssts_in_buckets_loads_entries_from_file() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("flushed_bucket");
+        let bucket = Bucket::new(path.to_owned()).await.unwrap();
+        let sst_count = 6;
+        let sst_samples = SSTContructor::generate_ssts(sst_count).await;
+        let mut keys = std::collections::HashSet::new();
+        for s in sst_samples.iter() {
+            let mut sst = s.to_owned();
+            sst.load_entries_from_file().await.unwrap();
+            keys.extend(sst.entries.iter().map(|e| e.key().to_owned()));
+            // flushed sstables only hold their entries on disk
+            sst.entries = Arc::new(SkipMap::new());
+            bucket.sstables.write().await.push(sst)
+        }
+
+        let root = tempdir().unwrap();
+        let path = root.path().join("bucket_map_new");
+        let mut bucket_map = BucketMap::new(path.to_owned()).await.unwrap();
+        bucket_map.buckets.insert(uuid::Uuid::new_v4(), bucket.to_owned());
+        let config = &generate_config();
+        let mut sized_tier_compaction_runner = SizedTierRunner::new(
+            Arc::new(RwLock::new(bucket_map)),
+            Arc::new(KeyRange::default()),
+            config,
+        );
+        let merge_ssts = sized_tier_compaction_runner
+            .merge_ssts_in_buckets(&[bucket])
+            .await
+            .unwrap();
+        assert_eq!(merge_ssts.len(), 1);
+        assert_eq!(merge_ssts[0].sstable.get_entries().len(), keys.len());
+    }
+
     #[tokio::test]
     async fn test_run_compaction() {
         let root = tempdir().unwrap();
@@ -647,4 +691,48 @@ mod tests {
         // length should increase since insertion is allowed
         assert_eq!(merged_entries.len(), 4);
     }
+
+    #[tokio::test]
+    async fn test_drop_expired_entries_after_grace_period() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("bucket_map_new");
+        let bucket_map = BucketMap::new(path.to_owned()).await.unwrap();
+        let mut config = generate_config();
+        config.use_ttl = true;
+        let mut sized_tier_compaction_runner = SizedTierRunner::new(
+            Arc::new(RwLock::new(bucket_map)),
+            Arc::new(KeyRange::default()),
+            &config,
+        );
+        let ago = |secs| Utc::now() - chrono::Duration::seconds(secs);
+        let written_with_ttl = ago(10);
+        sized_tier_compaction_runner.expired.insert(
+            b"session".to_vec(),
+            Expiry {
+                expires_at: ago(5).timestamp_millis() as u64,
+                key: b"session".to_vec(),
+                created_at: written_with_ttl.timestamp_nanos_opt().unwrap() as u64,
+            },
+        );
+
+        let mut merged_entries = Vec::new();
+        // expired 30 seconds ago, tombstone grace period of 120 seconds not passed yet
+        sized_tier_compaction_runner
+            .tombstone_check(&Entry::new("key1", 100, ago(90), false), &mut merged_entries);
+        assert_eq!(merged_entries.len(), 1);
+        // expired past the grace period
+        sized_tier_compaction_runner
+            .tombstone_check(&Entry::new("key2", 200, ago(200), false), &mut merged_entries);
+        assert_eq!(merged_entries.len(), 1);
+        // version written with a TTL that passed with its grace period
+        sized_tier_compaction_runner.tombstone_check(
+            &Entry::new("session", 300, written_with_ttl, false),
+            &mut merged_entries,
+        );
+        assert_eq!(merged_entries.len(), 1);
+        // other versions of the key are kept
+        sized_tier_compaction_runner
+            .tombstone_check(&Entry::new("session", 400, ago(1), false), &mut merged_entries);
+        assert_eq!(merged_entries.len(), 2);
+    }
 }
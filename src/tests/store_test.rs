@@ -1,8 +1,19 @@
 #[cfg(test)]
 mod tests {
-    use crate::db::DataStore;
+    use crate::compactors::{BucketID, BucketInfo, CompactionPolicy, SSTableInfo};
+    use crate::consts::{DEFAULT_FALSE_POSITIVE_RATE, FORMAT_VERSION, MAX_MONKEY_FALSE_POSITIVE_RATE};
+    use crate::db::{
+        BackgroundJob, BlockCache, CacheWarmup, ColdStorage, Config, ContinuationToken, DataStore, Env,
+        ExportManifest, FilterCache, FilterStats, FlushOutcome, Health, InconsistencyKind, KeyspaceQuota,
+        LayoutIssue, OpenPhase, ReadOptions, ReadPriority, StoreInfo, StringStore,
+    };
+    use crate::flush::{FlushEvent, Flusher};
+    use crate::fs::{FileAsync, FilterFileNode, FilterFs, IndexFs};
+    use crate::memtable::MemTable;
     use crate::tests::*;
     use futures::future::join_all;
+    use futures::{StreamExt, TryStreamExt};
+    use std::io::{Seek, SeekFrom, Write};
     use std::path::PathBuf;
     use std::sync::Arc;
     use tempfile::tempdir;
@@ -24,7 +35,9 @@ mod tests {
     #[tokio::test]
     async fn datastore_recover() {
         setup();
-        let path = PathBuf::new().join("src/tests/fixtures/data");
+        let root = tempdir().unwrap();
+        let path = root.path().join("fixture");
+        workload::copy_fixture_store(&path);
 
         let store = DataStore::open_without_background("test", path.clone())
             .await
@@ -122,6 +135,7 @@ mod tests {
     }
 
     #[tokio::test]
+    #[allow(clippy::useless_vec)]
     async fn datastore_test_put_and_get_concurrent() {
         setup();
         let root = tempdir().unwrap();
@@ -343,7 +357,11 @@ mod tests {
         assert!(res.is_ok());
         assert_eq!(res.unwrap().unwrap().val, write_workload[0].val);
 
-        let res = store_ref.write().await.update(key1, &updated_value).await;
+        let res = store_ref
+            .write()
+            .await
+            .update_with(key1, |_| &updated_value)
+            .await;
         assert!(res.is_ok());
         assert!(res.unwrap());
 
@@ -412,4 +430,3463 @@ mod tests {
         assert!(res.is_ok());
         assert!(res.unwrap().is_none());
     }
+
+    #[tokio::test]
+    async fn datastore_shared_env() {
+        setup();
+        let root = tempdir().unwrap();
+        let env = Env::new(1, 1, 1);
+        let config = Config {
+            env: env.clone(),
+            ..Default::default()
+        };
+        let store1 = DataStore::open_with_config("test", root.path().join("store_test_11"), config.clone())
+            .await
+            .unwrap();
+        let mut store2 = DataStore::open_with_config("test", root.path().join("store_test_12"), config)
+            .await
+            .unwrap();
+        assert!(store1.config.env.same_as(&env));
+        assert!(store2.compactor.env.same_as(&env));
+        assert!(store2.flusher.env.same_as(&env));
+        assert!(store2.gc.config.env.same_as(&store1.gc.config.env));
+
+        // A compaction slot held on behalf of one store blocks compactions in the other
+        let permit = env.acquire(BackgroundJob::Compaction).await;
+        let blocked =
+            tokio::time::timeout(std::time::Duration::from_millis(50), store2.run_compaction()).await;
+        assert!(blocked.is_err());
+        drop(permit);
+        assert!(store2.run_compaction().await.is_ok());
+        assert_eq!(env.available_slots(BackgroundJob::Compaction), 1);
+    }
+
+    #[tokio::test]
+    async fn datastore_shared_caches() {
+        setup();
+        let root = tempdir().unwrap();
+        let block_cache = BlockCache::new(1024 * 1024);
+        let filter_cache = FilterCache::new(1024 * 1024);
+        let config = Config {
+            block_cache: block_cache.clone(),
+            filter_cache: filter_cache.clone(),
+            ..Default::default()
+        };
+        let mut store1 =
+            DataStore::open_with_config("test", root.path().join("store_test_13"), config.clone())
+                .await
+                .unwrap();
+        let store2 = DataStore::open_with_config("test", root.path().join("store_test_14"), config)
+            .await
+            .unwrap();
+        assert!(store2.config.block_cache.same_as(&block_cache));
+        assert!(store2.key_range.filter_cache.same_as(&filter_cache));
+        assert!(store1.gc.config.block_cache.same_as(&store2.config.block_cache));
+
+        let workload = Workload::new(500, 5, 5, 1.0);
+        let (_, write_workload) = workload.generate_workload_data_as_vec();
+        for e in write_workload.iter() {
+            store1.put(e.key.to_owned(), e.val.to_owned()).await.unwrap();
+        }
+        store1.force_flush().await.unwrap();
+        assert_eq!(block_cache.usage(), 0);
+
+        // Reads served from sstables populate the shared cache
+        for e in write_workload.iter().take(10) {
+            let res = store1.get(&e.key).await.unwrap();
+            assert_eq!(res.unwrap().val, e.val);
+        }
+        let usage = block_cache.usage();
+        assert!(usage > 0);
+        assert!(usage <= block_cache.capacity());
+
+        // Cached blocks are reused
+        for e in write_workload.iter().take(10) {
+            let res = store1.get(&e.key).await.unwrap();
+            assert_eq!(res.unwrap().val, e.val);
+        }
+        assert_eq!(block_cache.usage(), usage);
+    }
+
+    #[tokio::test]
+    async fn datastore_block_cache_stats_and_residency() {
+        setup();
+        let root = tempdir().unwrap();
+        let block_cache = BlockCache::new(1024 * 1024);
+        let config = Config {
+            block_cache: block_cache.clone(),
+            ..Default::default()
+        };
+        let mut store =
+            DataStore::open_with_config("test", root.path().join("store_test_cache_stats"), config)
+                .await
+                .unwrap();
+        let workload = Workload::new(500, 5, 5, 1.0);
+        let (_, write_workload) = workload.generate_workload_data_as_vec();
+        for e in write_workload.iter() {
+            store.put(e.key.to_owned(), e.val.to_owned()).await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+
+        let key = &write_workload[0].key;
+        store.get(key).await.unwrap().unwrap();
+        let stats = block_cache.stats();
+        assert!(stats.misses > 0);
+        assert_eq!(stats.usage, block_cache.usage());
+        store.get(key).await.unwrap().unwrap();
+        assert_eq!(block_cache.stats().hits, stats.hits + 1);
+
+        let residency = block_cache.residency();
+        assert_eq!(residency.values().sum::<usize>(), block_cache.usage());
+        assert!(residency.keys().all(|path| path.exists()));
+
+        block_cache.set_capacity(0);
+        assert_eq!(block_cache.usage(), 0);
+        assert!(block_cache.stats().evictions > 0);
+    }
+
+    #[tokio::test]
+    async fn datastore_high_priority_get_bypasses_block_cache() {
+        setup();
+        let root = tempdir().unwrap();
+        let block_cache = BlockCache::new(1024 * 1024);
+        let config = Config {
+            block_cache: block_cache.clone(),
+            ..Default::default()
+        };
+        let mut store = DataStore::open_with_config("test", root.path().join("store_test_priority"), config)
+            .await
+            .unwrap();
+        let workload = Workload::new(500, 5, 5, 1.0);
+        let (_, write_workload) = workload.generate_workload_data_as_vec();
+        for e in write_workload.iter() {
+            store.put(e.key.to_owned(), e.val.to_owned()).await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+
+        let high = ReadOptions::new().priority(ReadPriority::High);
+        for e in write_workload.iter().take(10) {
+            let res = store.get_with_options(&e.key, &high).await.unwrap();
+            assert_eq!(res.unwrap().val, e.val);
+        }
+        assert_eq!(block_cache.usage(), 0);
+        assert_eq!(store.config.env.priority_reads_in_flight(), 0);
+
+        // Normal reads still populate the cache
+        let res = store
+            .get_with_options(&write_workload[0].key, &ReadOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(res.unwrap().val, write_workload[0].val);
+        assert!(block_cache.usage() > 0);
+    }
+
+    #[tokio::test]
+    async fn datastore_separate_vlog_dir() {
+        setup();
+        let root = tempdir().unwrap();
+        let vlog_root = tempdir().unwrap();
+        let path = root.path().join("store_test_15");
+        let vlog_dir = vlog_root.path().join("vlog");
+        let config = Config {
+            vlog_dir: Some(vlog_dir.to_owned()),
+            ..Default::default()
+        };
+        let mut store = DataStore::open_with_config("test", path.to_owned(), config.clone())
+            .await
+            .unwrap();
+        assert_eq!(store.get_dir().await.val_log, vlog_dir);
+        store.put("apple", "tim cook").await.unwrap();
+        drop(store);
+
+        assert!(vlog_dir.join(crate::consts::VLOG_FILE_NAME).exists());
+        assert!(!path.join(crate::consts::VALUE_LOG_DIRECTORY_NAME).exists());
+
+        let store = DataStore::open_with_config("test", path, config).await.unwrap();
+        let res = store.get("apple").await.unwrap();
+        assert_eq!(res.unwrap().val, b"tim cook".to_vec());
+    }
+
+    #[tokio::test]
+    async fn datastore_cold_storage() {
+        setup();
+        let root = tempdir().unwrap();
+        let cold_root = tempdir().unwrap();
+        let path = root.path().join("store_test_16");
+        let cold_dir = cold_root.path().join("cold");
+        let config = Config {
+            cold_storage: Some(ColdStorage {
+                dir: cold_dir.to_owned(),
+                max_hotness: u64::MAX,
+                min_age: std::time::Duration::ZERO,
+            }),
+            ..Default::default()
+        };
+        let mut store = DataStore::open_with_config("test", path.to_owned(), config.clone())
+            .await
+            .unwrap();
+        for i in 0..crate::consts::MIN_TRESHOLD {
+            store
+                .put(format!("key_{}", i), format!("val_{}", i))
+                .await
+                .unwrap();
+            store.force_flush().await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+        assert!(!cold_dir.exists());
+        store.run_compaction().await.unwrap();
+
+        let cold_ssts = store
+            .key_range
+            .key_ranges
+            .read()
+            .await
+            .keys()
+            .filter(|dir| dir.starts_with(&cold_dir))
+            .count();
+        assert_eq!(cold_ssts, 1);
+        for i in 0..crate::consts::MIN_TRESHOLD {
+            let res = store.get(format!("key_{}", i)).await.unwrap();
+            assert_eq!(res.unwrap().val, format!("val_{}", i).into_bytes());
+        }
+        drop(store);
+
+        let store = DataStore::open_with_config("test", path, config).await.unwrap();
+        let key_ranges = store.key_range.key_ranges.read().await;
+        assert!(key_ranges.keys().any(|dir| dir.starts_with(&cold_dir)));
+        drop(key_ranges);
+        let res = store.get("key_0").await.unwrap();
+        assert_eq!(res.unwrap().val, b"val_0".to_vec());
+    }
+
+    #[tokio::test]
+    async fn datastore_verify_reads() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_17");
+        let config = Config {
+            verify_reads: true,
+            ..Default::default()
+        };
+        let mut store = DataStore::open_with_config("test", path, config).await.unwrap();
+        store.put("apple", "tim cook").await.unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+        let res = store.get("apple").await.unwrap();
+        assert_eq!(res.unwrap().val, b"tim cook".to_vec());
+
+        // corrupt value of "google" in value log
+        let offset = store.active_memtable.get(b"google").unwrap().val_offset;
+        let entry = store.val_log.get_entry(offset).await.unwrap().unwrap();
+        let value_offset = offset + entry.encoded_len() - entry.vsize - crate::consts::SIZE_OF_U32;
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&store.val_log.content.path)
+            .unwrap();
+        file.seek(SeekFrom::Start(value_offset as u64)).unwrap();
+        file.write_all(b"X").unwrap();
+
+        let res = store.get("google").await;
+        assert!(matches!(res, Err(crate::err::Error::ChecksumMismatch { .. })));
+        assert!(store.get("apple").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn datastore_get_or_insert_with() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_18");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+
+        let mut calls = 0;
+        let entry = store
+            .get_or_insert_with("apple", || {
+                calls += 1;
+                "tim cook"
+            })
+            .await
+            .unwrap();
+        assert_eq!(entry.val, b"tim cook".to_vec());
+        let entry = store
+            .get_or_insert_with("apple", || {
+                calls += 1;
+                "steve jobs"
+            })
+            .await
+            .unwrap();
+        assert_eq!(entry.val, b"tim cook".to_vec());
+        assert_eq!(calls, 1);
+
+        // deleted keys are inserted again
+        store.delete("apple").await.unwrap();
+        let entry = store.get_or_insert_with("apple", || "steve jobs").await.unwrap();
+        assert_eq!(entry.val, b"steve jobs".to_vec());
+        assert_eq!(
+            store.get("apple").await.unwrap().unwrap().val,
+            b"steve jobs".to_vec()
+        );
+
+        // concurrent callers only insert once
+        let store = Arc::new(RwLock::new(store));
+        let tasks = (0..10).map(|i| {
+            let store = Arc::clone(&store);
+            tokio::spawn(async move {
+                let mut store = store.write().await;
+                store
+                    .get_or_insert_with("google", || format!("ceo_{}", i))
+                    .await
+                    .unwrap()
+                    .val
+            })
+        });
+        let values: Vec<_> = join_all(tasks).await.into_iter().map(|v| v.unwrap()).collect();
+        assert!(values.iter().all(|v| *v == values[0]));
+    }
+
+    #[tokio::test]
+    async fn datastore_update_with_closure() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_19");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+
+        // missing keys are not inserted
+        let res = store.update_with("counter", |_| "1").await.unwrap();
+        assert!(!res);
+        assert!(store.get("counter").await.unwrap().is_none());
+
+        store.put("counter", "5").await.unwrap();
+        // deprecated form replaces the value without reading it
+        #[allow(deprecated)]
+        let res = store.update("counter", "0").await.unwrap();
+        assert!(res);
+        let store = Arc::new(RwLock::new(store));
+        let tasks = (0..20).map(|_| {
+            let store = Arc::clone(&store);
+            tokio::spawn(async move {
+                store
+                    .write()
+                    .await
+                    .update_with("counter", |old| {
+                        let count: u32 = std::str::from_utf8(old).unwrap().parse().unwrap();
+                        (count + 1).to_string()
+                    })
+                    .await
+                    .unwrap()
+            })
+        });
+        let results = join_all(tasks).await;
+        assert!(results.into_iter().all(|r| r.unwrap()));
+        let entry = store.read().await.get("counter").await.unwrap();
+        assert_eq!(entry.unwrap().val, b"20".to_vec());
+    }
+
+    #[tokio::test]
+    async fn datastore_insert_if_absent() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_20");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+
+        assert!(store.insert("apple", "tim cook").await.unwrap());
+        let res = store.insert("apple", "steve jobs").await;
+        assert!(matches!(res, Err(crate::err::Error::KeyAlreadyExists)));
+        assert_eq!(
+            store.get("apple").await.unwrap().unwrap().val,
+            b"tim cook".to_vec()
+        );
+
+        // still rejected once the key is only found on disk
+        store.force_flush().await.unwrap();
+        let res = store.insert("apple", "steve jobs").await;
+        assert!(matches!(res, Err(crate::err::Error::KeyAlreadyExists)));
+
+        // tombstones don't count as live keys
+        store.delete("apple").await.unwrap();
+        assert!(store.insert("apple", "steve jobs").await.unwrap());
+        assert_eq!(
+            store.get("apple").await.unwrap().unwrap().val,
+            b"steve jobs".to_vec()
+        );
+
+        // only one of concurrent inserts of a key succeeds
+        let store = Arc::new(RwLock::new(store));
+        let tasks = (0..10).map(|i| {
+            let store = Arc::clone(&store);
+            tokio::spawn(async move { store.write().await.insert("id_1", format!("owner_{}", i)).await })
+        });
+        let inserted = join_all(tasks)
+            .await
+            .into_iter()
+            .filter(|res| res.as_ref().unwrap().is_ok())
+            .count();
+        assert_eq!(inserted, 1);
+    }
+
+    #[tokio::test]
+    async fn datastore_delete_many() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_21");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        store.put("apple", "tim cook").await.unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+        store.put("nvidia", "jensen huang").await.unwrap();
+        let vlog_size = store.val_log.size;
+
+        let results = store
+            .delete_many(vec![
+                "apple".to_owned(),
+                "".to_owned(),
+                "meta".to_owned(),
+                "nvidia".to_owned(),
+            ])
+            .await
+            .unwrap();
+        assert!(matches!(results[0], Ok(true)));
+        assert!(matches!(results[1], Err(crate::err::Error::KeySizeNone)));
+        assert!(matches!(results[2], Ok(false)));
+        assert!(matches!(results[3], Ok(true)));
+        assert!(store.val_log.size > vlog_size);
+        assert!(store.get("apple").await.unwrap().is_none());
+        assert!(store.get("nvidia").await.unwrap().is_none());
+        assert!(store.get("google").await.unwrap().is_some());
+
+        // nothing to delete, nothing written
+        let vlog_size = store.val_log.size;
+        let results = store.delete_many(["apple", "meta"]).await.unwrap();
+        assert!(results.iter().all(|res| matches!(res, Ok(false))));
+        assert_eq!(store.val_log.size, vlog_size);
+
+        // tombstones survive recovery
+        drop(store);
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        assert!(store.get("apple").await.unwrap().is_none());
+        assert!(store.get("google").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn datastore_soft_delete_and_undelete() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_soft_delete");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        store.put_with_metadata("apple", "tim cook", "ceo").await.unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+        store.put("nvidia", "jensen huang").await.unwrap();
+
+        assert!(store.soft_delete("apple").await.unwrap());
+        assert!(store.soft_delete("google").await.unwrap());
+        assert!(!store.soft_delete("meta").await.unwrap());
+        assert!(store.get("apple").await.unwrap().is_none());
+        assert!(!store.undelete("nvidia").await.unwrap());
+        assert!(!store.undelete("meta").await.unwrap());
+
+        // restorable from sstables and after recovery
+        store.force_flush().await.unwrap();
+        drop(store);
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        assert!(store.undelete("apple").await.unwrap());
+        let (entry, metadata) = store.get_with_metadata("apple").await.unwrap().unwrap();
+        assert_eq!(entry.val, b"tim cook");
+        assert_eq!(metadata, Some(b"ceo".to_vec()));
+        assert!(!store.undelete("apple").await.unwrap());
+
+        // a permanent deletion replaces the soft one
+        store.delete("google").await.unwrap();
+        assert!(!store.undelete("google").await.unwrap());
+
+        // not restorable once the tombstone ttl has passed
+        store.soft_delete("nvidia").await.unwrap();
+        store.config.tombstone_ttl = std::time::Duration::ZERO;
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        assert!(!store.undelete("nvidia").await.unwrap());
+        assert!(store.get("nvidia").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn datastore_view_enforces_bounds() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_view");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        for key in ["a/1", "a/2", "a/3", "b/1", "c"] {
+            store.put(key, "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        store.put("a/4", "value").await.unwrap();
+
+        let view = store.prefix_view("a/");
+        assert!(view.get("a/1").await.unwrap().is_some());
+        assert!(view.get("a/4").await.unwrap().is_some());
+        assert!(matches!(
+            view.get("b/1").await,
+            Err(crate::err::Error::KeyOutsideView)
+        ));
+        assert!(matches!(
+            view.get_with_metadata("a").await,
+            Err(crate::err::Error::KeyOutsideView)
+        ));
+        assert_eq!(view.count().await.unwrap(), 4);
+
+        let mut iter = view.seek("a/2", "z").await.unwrap();
+        let mut keys = Vec::new();
+        while let Some(entry) = iter.next().await.unwrap() {
+            keys.push(entry.key);
+        }
+        assert_eq!(keys, vec![b"a/2".to_vec(), b"a/3".to_vec(), b"a/4".to_vec()]);
+
+        // ranges and tokens outside the view return nothing
+        assert!(view.seek("b", "z").await.unwrap().next().await.unwrap().is_none());
+        let token = store.page("b", "z", 1, None).await.unwrap().next.unwrap();
+        assert!(view
+            .page("a", "z", 10, Some(&token))
+            .await
+            .unwrap()
+            .entries
+            .is_empty());
+        assert!(view
+            .seek_after("a", "z", Some(&token))
+            .await
+            .unwrap()
+            .next()
+            .await
+            .unwrap()
+            .is_none());
+
+        let page = view.page("", "z", 3, None).await.unwrap();
+        assert_eq!(page.entries.len(), 3);
+        let page = view.page("", "z", 3, page.next.as_ref()).await.unwrap();
+        assert_eq!(page.entries[0].key, b"a/4".to_vec());
+        assert!(page.next.is_none());
+
+        // inclusive end keeps the end key only
+        let view = store.view("a/3"..="b/1");
+        assert_eq!(view.count().await.unwrap(), 3);
+        assert!(view.contains("b/1"));
+        assert!(!view.contains("b/1\0"));
+    }
+
+    #[tokio::test]
+    async fn datastore_subscribe_flush() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_22");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        let mut flushes = store.subscribe_flush();
+        assert!(flushes.try_recv().is_none());
+
+        // fill enough memtables to trigger a flush
+        let mut count = 0;
+        while store.flush_stream.lock().unwrap().is_empty() {
+            store.put(format!("key_{}", count), "value").await.unwrap();
+            count += 1;
+        }
+
+        let event = tokio::time::timeout(std::time::Duration::from_secs(10), flushes.recv())
+            .await
+            .expect("flush event not received")
+            .unwrap();
+        assert!(!event.memtable_id().is_empty());
+        match event {
+            FlushEvent::Flushed {
+                sstable,
+                entries,
+                bytes,
+                ..
+            } => {
+                assert!(entries > 0);
+                assert!(bytes > 0);
+                assert!(sstable.exists());
+            }
+            FlushEvent::Failed { error, .. } => panic!("flush failed: {}", error),
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_wait_for_compactions() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_23");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        let mut flushes = store.subscribe_flush();
+
+        // flush enough sstables to make a bucket exceed compaction threshold
+        let mut flushed = 0;
+        let mut count = 0;
+        while flushed < crate::consts::MIN_TRESHOLD {
+            store.put(format!("key_{}", count), "value").await.unwrap();
+            count += 1;
+            while flushes.try_recv().is_some() {
+                flushed += 1;
+            }
+            tokio::task::yield_now().await;
+        }
+        store.wait_for_compactions().await.unwrap();
+        assert!(store.buckets.read().await.is_balanced().await);
+        assert_eq!(store.get("key_0").await.unwrap().unwrap().val, b"value");
+
+        // nothing pending, resolves right away
+        store.wait_for_compactions().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn datastore_live_files_pinned() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_24");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        let mut flushes = store.subscribe_flush();
+        let mut flushed = 0;
+        let mut count = 0;
+        while flushed < crate::consts::MIN_TRESHOLD {
+            store.put(format!("key_{}", count), "value").await.unwrap();
+            count += 1;
+            while flushes.try_recv().is_some() {
+                flushed += 1;
+            }
+            tokio::task::yield_now().await;
+        }
+
+        let files = store.live_files().await;
+        assert!(files.sstables.len() >= crate::consts::MIN_TRESHOLD);
+        assert_eq!(files.paths().len(), files.sstables.len() * 4 + 1);
+        assert!(files.paths().iter().all(|p| p.exists()));
+
+        // compacted sstables stay on disk until the pin drops
+        store.wait_for_compactions().await.unwrap();
+        assert!(files.paths().iter().all(|p| p.exists()));
+        let compacted: Vec<_> = {
+            let current = store.live_files().await;
+            files
+                .sstables
+                .iter()
+                .filter(|dir| !current.sstables.contains(dir))
+                .cloned()
+                .collect()
+        };
+        assert!(!compacted.is_empty());
+        let pin = files.into_pin();
+        assert!(compacted.iter().all(|dir| dir.exists()));
+        drop(pin);
+        // removal runs on the blocking pool
+        for _ in 0..100 {
+            if compacted.iter().all(|dir| !dir.exists()) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(compacted.iter().all(|dir| !dir.exists()));
+    }
+
+    #[tokio::test]
+    async fn datastore_disk_usage() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_25");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        let usage = store.disk_usage().await.unwrap();
+        assert!(usage.buckets.is_empty());
+        assert_eq!(usage.sstables(), 0);
+
+        store.put("apple", "tim cook").await.unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+        store.force_flush().await.unwrap();
+        store.put("nvidia", "jensen huang").await.unwrap();
+
+        let usage = store.disk_usage().await.unwrap();
+        assert_eq!(usage.buckets.len(), 1);
+        let sst = &usage.buckets[0].sstables[0];
+        assert!(sst.data > 0 && sst.index > 0 && sst.filter > 0 && sst.summary > 0);
+        // internal head and tail entries are flushed as well
+        assert!(sst.smallest_key.as_slice() <= b"apple".as_slice());
+        assert!(sst.biggest_key.as_slice() >= b"google".as_slice());
+        assert_eq!(usage.buckets[0].total(), sst.total());
+        assert_eq!(usage.vlog.live, store.val_log.size as u64);
+        assert_eq!(usage.vlog.punched, 0);
+        assert_eq!(usage.total(), usage.sstables() + usage.vlog.live);
+
+        assert_eq!(usage.in_range(b"b", b"c").count(), 1);
+        assert_eq!(usage.in_range(b"zz", b"zzz").count(), 0);
+    }
+
+    #[tokio::test]
+    async fn datastore_sample_keys() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_26");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        assert!(store.sample_keys(10).await.unwrap().is_empty());
+
+        for i in 0..3000 {
+            store.put(format!("key_{:05}", i), "value").await.unwrap();
+            if i == 1999 {
+                store.force_flush().await.unwrap();
+            }
+        }
+
+        let keys = store.sample_keys(5).await.unwrap();
+        assert_eq!(keys.len(), 5);
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+
+        // about one key per block, flushed and in-memory keys alike
+        let all = store.sample_keys(usize::MAX).await.unwrap();
+        assert!(all.len() < 3000 / 10);
+        assert!(all.iter().all(|k| k.starts_with(b"key_")));
+        let flushed = all
+            .iter()
+            .filter(|k| k.as_slice() < b"key_02000".as_slice())
+            .count();
+        assert!(flushed > 0 && flushed < all.len());
+        assert!(flushed > all.len() - flushed);
+    }
+
+    #[tokio::test]
+    async fn datastore_max_key_and_value_size() {
+        setup();
+        let root = tempdir().unwrap();
+        let config = Config {
+            max_key_size: 8,
+            max_value_size: 16,
+            ..Default::default()
+        };
+        let mut store = DataStore::open_with_config("test", root.path().join("store_test_27"), config)
+            .await
+            .unwrap();
+        assert!(store.put("apple", "tim cook").await.is_ok());
+        assert!(matches!(
+            store.put("pineapple", "x").await,
+            Err(crate::err::Error::KeyTooLarge { size: 9, max: 8 })
+        ));
+        assert!(matches!(
+            store.put("apple", vec![b'x'; 17]).await,
+            Err(crate::err::Error::ValueTooLarge { size: 17, max: 16 })
+        ));
+        assert!(matches!(
+            store.insert("pear", vec![b'x'; 17]).await,
+            Err(crate::err::Error::ValueTooLarge { .. })
+        ));
+        assert_eq!(store.get("apple").await.unwrap().unwrap().val, b"tim cook");
+
+        // limits apply to stores opened with defaults too
+        let mut store = DataStore::open_without_background("test", root.path().join("store_test_28"))
+            .await
+            .unwrap();
+        let key = vec![b'k'; crate::consts::DEFAULT_MAX_KEY_SIZE + 1];
+        assert!(matches!(
+            store.put(key, "v").await,
+            Err(crate::err::Error::KeyTooLarge { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn datastore_string_values() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_29");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        store.put_str("apple", "tim cook").await.unwrap();
+        assert_eq!(store.get_str("apple").await.unwrap().as_deref(), Some("tim cook"));
+        assert!(store.get_str("google").await.unwrap().is_none());
+
+        // binary values are stored, but not returned as strings
+        store.put("binary", [0xff, 0xfe]).await.unwrap();
+        assert!(matches!(
+            store.get_str("binary").await,
+            Err(crate::err::Error::InvalidUtf8(_))
+        ));
+
+        let mut store = StringStore::from(store);
+        assert!(store.update("apple", |ceo| format!("{}!", ceo)).await.unwrap());
+        assert!(!store.update("google", |ceo| ceo.to_owned()).await.unwrap());
+        assert_eq!(store.get("apple").await.unwrap().as_deref(), Some("tim cook!"));
+        assert!(store.delete("apple").await.unwrap());
+        assert!(store.get("apple").await.unwrap().is_none());
+        assert_eq!(
+            store.into_inner().get("binary").await.unwrap().unwrap().val,
+            [0xff, 0xfe]
+        );
+    }
+
+    #[tokio::test]
+    async fn datastore_value_type() {
+        #[derive(Debug, PartialEq)]
+        struct Ceo(Vec<u8>);
+
+        impl AsRef<[u8]> for Ceo {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl From<Vec<u8>> for Ceo {
+            fn from(bytes: Vec<u8>) -> Self {
+                Ceo(bytes)
+            }
+        }
+
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_30");
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        let mut store = store.with_value_type::<Ceo>();
+        store.put("apple", Ceo(b"tim cook".to_vec())).await.unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+        assert_eq!(
+            store.get("apple").await.unwrap().unwrap().val,
+            Ceo(b"tim cook".to_vec())
+        );
+        let entry = store
+            .get_or_insert_with("nvidia", || Ceo(b"jensen huang".to_vec()))
+            .await
+            .unwrap();
+        assert_eq!(entry.val, Ceo(b"jensen huang".to_vec()));
+        assert!(store
+            .update_with("google", |old| [old, b"!"].concat())
+            .await
+            .unwrap());
+
+        // switching value type keeps the data
+        let store = store.with_value_type::<Box<[u8]>>();
+        let val: Box<[u8]> = store.get("google").await.unwrap().unwrap().val;
+        assert_eq!(&*val, b"sundar pichai!");
+    }
+
+    #[tokio::test]
+    async fn datastore_recover_key_range_from_summary() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_31");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        store.put("apple", "tim cook").await.unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+        store.force_flush().await.unwrap();
+        drop(store);
+
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        let ranges = store.key_range.key_ranges.read().await;
+        assert_eq!(ranges.len(), 1);
+        let range = ranges.values().next().unwrap();
+        let summary = range.sst.summary.as_ref().unwrap();
+        assert_eq!(range.smallest_key, summary.smallest_key);
+        assert_eq!(range.biggest_key, summary.biggest_key);
+        // internal head and tail entries are flushed as well
+        assert!(summary.entry_count >= 2);
+        let (oldest, newest) = summary.created_at_range.unwrap();
+        assert!(oldest <= newest);
+        assert_eq!(range.sst.created_at, newest);
+        assert_eq!(
+            store.get_str("google").await.unwrap().as_deref(),
+            Some("sundar pichai")
+        );
+    }
+
+    #[tokio::test]
+    async fn datastore_filter_stats_count_negatives() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_filter_stats");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        for i in (0..500).step_by(2) {
+            store.put(format!("key_{:04}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        assert_eq!(store.filter_stats(), FilterStats::default());
+
+        store.get("key_0100").await.unwrap().unwrap();
+        assert_eq!(store.filter_stats().checks, 1);
+        assert_eq!(store.filter_stats().negatives, 0);
+
+        // missing keys within the key range of the sstable
+        for i in (1..500).step_by(2) {
+            assert!(store.get(format!("key_{:04}", i)).await.unwrap().is_none());
+        }
+        let stats = store.filter_stats();
+        assert_eq!(stats.checks, 251);
+        assert!(stats.negatives > 200);
+    }
+
+    #[tokio::test]
+    async fn datastore_sstable_range_stops_at_upper_bound() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_sst_range");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        for i in 0..500 {
+            store.put(format!("key_{:04}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        let sst = store
+            .key_range
+            .key_ranges
+            .read()
+            .await
+            .values()
+            .next()
+            .unwrap()
+            .sst
+            .to_owned();
+        let index = sst.index_file.file.load().await.unwrap();
+        assert!(index.entries().len() > 2);
+
+        let range_offset = index.get_block_range(b"key_0100", b"key_0150");
+        let entries = sst.range(range_offset, &("key_0100".."key_0150")).await.unwrap();
+        let keys: Vec<Vec<u8>> = entries.into_iter().map(|e| e.key).collect();
+        let expected: Vec<Vec<u8>> = (100..150).map(|i| format!("key_{:04}", i).into_bytes()).collect();
+        assert_eq!(keys, expected);
+
+        let range_offset = index.get_block_range(b"key_0490", b"key_0600");
+        let entries = sst.range(range_offset, &("key_0490"..="key_0600")).await.unwrap();
+        assert_eq!(entries.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn datastore_flush_empty_memtable_is_skipped() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_flush_empty");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        let mut flusher = Flusher::new(
+            store.read_only_memtables.clone(),
+            store.buckets.clone(),
+            store.key_range.clone(),
+            store.config.env.clone(),
+        );
+        let empty = MemTable::new(1024, DEFAULT_FALSE_POSITIVE_RATE);
+        let outcome = flusher.flush(Arc::new(empty)).await;
+        assert_eq!(outcome.unwrap(), FlushOutcome::Skipped);
+        assert!(store.key_range.key_ranges.read().await.is_empty());
+
+        store.put("apple", "tim cook").await.unwrap();
+        let outcome = flusher.flush(Arc::new(store.active_memtable.to_owned())).await;
+        assert!(
+            matches!(outcome.unwrap(), FlushOutcome::Flushed { sstable, bytes } if sstable.is_dir() && bytes > 0)
+        );
+        assert_eq!(store.key_range.key_ranges.read().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn datastore_reads_from_retained_memtables() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_retained_memtables");
+        let config = Config {
+            retained_memtables: 2,
+            ..Default::default()
+        };
+        let mut store = DataStore::open_with_config("test", path, config).await.unwrap();
+        for round in 0..3 {
+            store.put(format!("key_{}", round), "value").await.unwrap();
+            store.force_flush().await.unwrap();
+        }
+        // oldest flushed memtable was evicted
+        assert_eq!(store.flusher.retained.read().unwrap().len(), 2);
+
+        // sstables are out of reach, recent keys are still served from memory
+        let sst_dirs: Vec<PathBuf> = store.key_range.key_ranges.read().await.keys().cloned().collect();
+        for dir in sst_dirs {
+            store.key_range.remove(dir).await;
+        }
+        assert!(store.get("key_0").await.unwrap().is_none());
+        for key in ["key_1", "key_2"] {
+            assert_eq!(store.get(key).await.unwrap().unwrap().val, b"value".to_vec());
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_sstable_reads_share_file_handle() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_sst_read_only");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        for i in 0..500 {
+            store.put(format!("key_{:04}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        drop(store);
+
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        let sst = store
+            .key_range
+            .key_ranges
+            .read()
+            .await
+            .values()
+            .next()
+            .unwrap()
+            .sst
+            .to_owned();
+
+        // reads go through while another reader holds the data file
+        let held = sst.data_file.file.node.file.read().await;
+        let reads = (0..50).map(|i| store.get(format!("key_{:04}", i * 10)));
+        let entries = tokio::time::timeout(std::time::Duration::from_secs(5), join_all(reads))
+            .await
+            .expect("reads waited for the data file lock");
+        assert!(entries.into_iter().all(|e| e.unwrap().is_some()));
+        let index = sst.index_file.file.load().await.unwrap();
+        let range_offset = index.get_block_range(b"key_0100", b"key_0150");
+        let entries = sst.range(range_offset, &("key_0100".."key_0150")).await.unwrap();
+        assert_eq!(entries.len(), 50);
+        drop(held);
+
+        // data file of a recovered sstable is opened read-only, the write fails
+        // once it reaches the file
+        let node = &sst.data_file.file.node;
+        assert!(node.write_all(b"garbage").await.is_err() || node.flush().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn datastore_rebuild_corrupt_index() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_32");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        for i in 0..500 {
+            store.put(format!("key_{:04}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        let index_path = store.live_files().await.index_files[0].to_owned();
+        drop(store);
+
+        let mut index = tokio::fs::read(&index_path).await.unwrap();
+        let written_index = index.to_owned();
+        index[5] ^= 0xFF;
+        tokio::fs::write(&index_path, &index).await.unwrap();
+
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        let sst = store
+            .key_range
+            .key_ranges
+            .read()
+            .await
+            .values()
+            .next()
+            .unwrap()
+            .sst
+            .to_owned();
+        let rebuilt = sst.index_file.file.load().await.unwrap();
+        assert!(rebuilt.entries().len() > 1);
+        assert_eq!(tokio::fs::read(&index_path).await.unwrap(), written_index);
+        for i in [0, 250, 499] {
+            let key = format!("key_{:04}", i);
+            let block_handle = rebuilt.get(key.as_bytes()).unwrap();
+            let found = sst
+                .get(
+                    block_handle,
+                    &key,
+                    &store.config.block_cache,
+                    &store.config.io_retry,
+                    None,
+                    true,
+                )
+                .await
+                .unwrap();
+            assert!(found.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_regenerate_sstable_files() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_33");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        for i in 0..500 {
+            store.put(format!("key_{:04}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        let sst_dir = store.live_files().await.sstables[0].to_owned();
+        drop(store);
+
+        let index_path = sst_dir.join("index.db");
+        let filter_path = sst_dir.join("filter.db");
+        let summary_path = sst_dir.join("summary.db");
+        let written_index = tokio::fs::read(&index_path).await.unwrap();
+        let written_summary = tokio::fs::read(&summary_path).await.unwrap();
+        tokio::fs::remove_file(&index_path).await.unwrap();
+        tokio::fs::remove_file(&summary_path).await.unwrap();
+        let mut filter = tokio::fs::read(&filter_path).await.unwrap();
+        filter[0] ^= 0xFF;
+        tokio::fs::write(&filter_path, &filter).await.unwrap();
+
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        assert_eq!(tokio::fs::read(&index_path).await.unwrap(), written_index);
+        assert_eq!(tokio::fs::read(&summary_path).await.unwrap(), written_summary);
+        assert!(FilterFileNode::recover(&filter_path).await.is_ok());
+
+        let ssts = store
+            .key_range
+            .filter_sstables_by_key_range("key_0250")
+            .await
+            .unwrap();
+        assert_eq!(ssts.len(), 1);
+        let block_handle = ssts[0].index_file.file.get_from_index(b"key_0250").await.unwrap();
+        let found = ssts[0]
+            .get(
+                block_handle.unwrap(),
+                "key_0250",
+                &store.config.block_cache,
+                &store.config.io_retry,
+                None,
+                true,
+            )
+            .await
+            .unwrap();
+        assert!(found.is_some());
+    }
+
+    #[tokio::test]
+    async fn datastore_filter_memory_budget() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_34");
+        let config = Config {
+            filter_memory_budget: Some(1),
+            ..Default::default()
+        };
+        let mut store = DataStore::open_with_config("test", path.to_owned(), config)
+            .await
+            .unwrap();
+        store.put("apple", "tim cook").await.unwrap();
+        store.force_flush().await.unwrap();
+
+        let ranges = store.key_range.key_ranges.read().await;
+        let sst_dir = ranges.keys().next().unwrap().to_owned();
+        drop(ranges);
+        let filter = store.key_range.filter_cache.get(&sst_dir).unwrap();
+        // a single byte of filter memory can't keep the default rate
+        assert!(filter.false_positive_rate > DEFAULT_FALSE_POSITIVE_RATE);
+        assert!(filter.false_positive_rate <= MAX_MONKEY_FALSE_POSITIVE_RATE);
+        assert!(filter.contains(b"apple".as_slice()));
+        assert_eq!(store.get_str("apple").await.unwrap().as_deref(), Some("tim cook"));
+    }
+
+    #[tokio::test]
+    async fn datastore_keyspace_false_positive_rate() {
+        setup();
+        let root = tempdir().unwrap();
+        let mut config = Config {
+            false_positive_rate: 0.01,
+            ..Default::default()
+        };
+        config
+            .keyspace_false_positive_rates
+            .insert("logs".to_string(), 0.1);
+        let logs = DataStore::open_with_config("logs", root.path().join("store_test_35"), config.clone())
+            .await
+            .unwrap();
+        let users = DataStore::open_with_config("users", root.path().join("store_test_36"), config)
+            .await
+            .unwrap();
+        assert_eq!(logs.active_memtable.false_positive_rate(), 0.1);
+        assert_eq!(logs.compactor.config.filter_false_positive, 0.1);
+        assert_eq!(users.active_memtable.false_positive_rate(), 0.01);
+        assert_eq!(users.compactor.config.filter_false_positive, 0.01);
+    }
+
+    #[tokio::test]
+    async fn datastore_filter_cache_eviction() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_37");
+        // Room for two memtable sized filters
+        let filter_cache = FilterCache::new(3 * 1024);
+        let config = Config {
+            filter_cache: filter_cache.clone(),
+            ..Default::default()
+        };
+        let mut store = DataStore::open_with_config("test", path.to_owned(), config)
+            .await
+            .unwrap();
+        let keys: Vec<String> = (0..4).map(|i| format!("key_{}", i)).collect();
+        for key in keys.iter() {
+            store.put(key.as_str(), "value").await.unwrap();
+            store.force_flush().await.unwrap();
+            // sstable directories are named after their creation time
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+        assert!(filter_cache.usage() <= filter_cache.capacity());
+
+        // Filters evicted from the cache are reloaded from filter files
+        for _ in 0..2 {
+            for key in keys.iter() {
+                let sstables = store.key_range.filter_sstables_by_key_range(key).await.unwrap();
+                assert!(!sstables.is_empty());
+                assert!(sstables
+                    .iter()
+                    .all(|sst| sst.filter.as_ref().unwrap().contains(key.as_bytes())));
+                assert!(filter_cache.usage() <= filter_cache.capacity());
+            }
+        }
+
+        let ranges = store.key_range.key_ranges.read().await;
+        // Filter files carry the bit vector
+        for range in ranges.values() {
+            let mut filter = range.sst.filter.to_owned().unwrap();
+            assert!(filter.load().await.unwrap());
+            assert!(filter.num_bits() > 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_cpu_offload_writes_same_sstable() {
+        setup();
+        let root = tempdir().unwrap();
+        let mut data_files = Vec::new();
+        for (dir, offload_cpu_work) in [("store_test_38", true), ("store_test_39", false)] {
+            let config = Config {
+                offload_cpu_work,
+                ..Default::default()
+            };
+            let mut store = DataStore::open_with_config("test", root.path().join(dir), config)
+                .await
+                .unwrap();
+            assert_eq!(store.flusher.offload_cpu_work, offload_cpu_work);
+            assert_eq!(store.compactor.config.offload_cpu_work, offload_cpu_work);
+            for i in 0..500 {
+                store.put(format!("key_{:04}", i), "value").await.unwrap();
+            }
+            store.force_flush().await.unwrap();
+            assert_eq!(store.get_str("key_0042").await.unwrap().as_deref(), Some("value"));
+
+            let ranges = store.key_range.key_ranges.read().await;
+            assert_eq!(ranges.len(), 1);
+            let range = ranges.values().next().unwrap();
+            let index = range.sst.index_file.file.load().await.unwrap();
+            data_files.push((index.entries().len(), range.sst.size()));
+        }
+        assert_eq!(data_files[0], data_files[1]);
+        assert!(data_files[0].0 > 1);
+    }
+
+    #[tokio::test]
+    async fn datastore_flush_writes_blocks_and_index() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_40");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        let keys: Vec<String> = (0..2000).map(|i| format!("key_{:05}", i)).collect();
+        for key in keys.iter() {
+            store.put(key.as_str(), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+
+        let ranges = store.key_range.key_ranges.read().await;
+        let sst = ranges.values().next().unwrap().sst.to_owned();
+        drop(ranges);
+        let data_len = tokio::fs::metadata(&sst.data_file.path).await.unwrap().len() as usize;
+        assert_eq!(sst.size(), data_len);
+        let index = sst.index_file.file.load().await.unwrap();
+        assert!(index.entries().len() > 1);
+        assert!(index
+            .entries()
+            .iter()
+            .all(|e| (e.block_handle as usize) < data_len));
+        for key in keys.iter().step_by(97) {
+            assert_eq!(store.get_str(key).await.unwrap().as_deref(), Some("value"));
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_open_reports_progress() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_41");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        for i in 0..3 {
+            store.put(format!("key_{}", i), "value").await.unwrap();
+            store.force_flush().await.unwrap();
+            // sstable directories are named after their creation time
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+        for i in 3..200 {
+            store.put(format!("key_{}", i), "value").await.unwrap();
+        }
+        let table_count = store.key_range.key_ranges.read().await.len();
+        drop(store);
+
+        let progress = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reported = progress.clone();
+        let config = Config::default().on_progress(move |p| reported.lock().unwrap().push(p));
+        let store = DataStore::open_with_config("test", path, config).await.unwrap();
+        assert_eq!(store.get_str("key_150").await.unwrap().as_deref(), Some("value"));
+
+        let progress = progress.lock().unwrap();
+        for phase in [
+            OpenPhase::SSTableLoad,
+            OpenPhase::FilterBuild,
+            OpenPhase::VLogReplay,
+        ] {
+            let reports: Vec<_> = progress.iter().filter(|p| p.phase == phase).collect();
+            assert!(!reports.is_empty());
+            assert!(reports.windows(2).all(|w| w[0].completed < w[1].completed));
+            assert_eq!(reports.last().unwrap().percent(), 100.0);
+        }
+        let loaded = progress
+            .iter()
+            .filter(|p| p.phase == OpenPhase::SSTableLoad)
+            .count();
+        assert_eq!(loaded, table_count);
+        // phases are reported in order
+        let phases: Vec<_> = progress.iter().map(|p| p.phase).collect();
+        assert!(phases.windows(2).all(|w| w[0] == w[1]
+            || (w[0], w[1]) == (OpenPhase::SSTableLoad, OpenPhase::FilterBuild)
+            || (w[0], w[1]) == (OpenPhase::FilterBuild, OpenPhase::VLogReplay)));
+    }
+
+    #[tokio::test]
+    async fn datastore_open_time_budget_loads_lazily() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_42");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        for i in 0..500 {
+            store.put(format!("key_{:04}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        let index_path = store.live_files().await.index_files[0].to_owned();
+        drop(store);
+
+        let mut index = tokio::fs::read(&index_path).await.unwrap();
+        let written_index = index.to_owned();
+        index[5] ^= 0xFF;
+        tokio::fs::write(&index_path, &index).await.unwrap();
+
+        let config = Config {
+            open_time_budget: Some(std::time::Duration::ZERO),
+            ..Default::default()
+        };
+        let store = DataStore::open_with_config("test", path, config).await.unwrap();
+        let sst = store
+            .key_range
+            .key_ranges
+            .read()
+            .await
+            .values()
+            .next()
+            .unwrap()
+            .sst
+            .to_owned();
+        // index is neither loaded nor rebuilt while opening
+        assert!(sst.index_file.file.loaded.get().is_none());
+        assert_eq!(tokio::fs::read(&index_path).await.unwrap(), index);
+
+        // first lookup rebuilds it
+        let rebuilt = sst.index_file.file.load().await.unwrap();
+        assert_eq!(tokio::fs::read(&index_path).await.unwrap(), written_index);
+        let block_handle = rebuilt.get(b"key_0250").unwrap();
+        let found = sst
+            .get(
+                block_handle,
+                "key_0250",
+                &store.config.block_cache,
+                &store.config.io_retry,
+                None,
+                true,
+            )
+            .await
+            .unwrap();
+        assert!(found.is_some());
+    }
+
+    #[tokio::test]
+    async fn datastore_new_store_writes_store_info() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_43");
+        let config = Config {
+            verify_reads: true,
+            ..Default::default()
+        };
+        let store = DataStore::open_with_config("test", path.to_owned(), config)
+            .await
+            .unwrap();
+        drop(store);
+
+        let info = StoreInfo::read(&path).await.unwrap().unwrap();
+        assert_eq!(info.format_version, FORMAT_VERSION);
+        assert_eq!(info.engine_version, env!("CARGO_PKG_VERSION"));
+        assert!(info.checksums);
+        assert!(info.vlog_dir.is_none());
+        // reopening leaves it untouched
+        let store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        drop(store);
+        assert_eq!(StoreInfo::read(&path).await.unwrap().unwrap(), info);
+    }
+
+    #[tokio::test]
+    async fn datastore_refuses_newer_format() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_44");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        store.put("key", "value").await.unwrap();
+        drop(store);
+
+        let mut info = StoreInfo::read(&path).await.unwrap().unwrap();
+        info.format_version = FORMAT_VERSION + 1;
+        info.write(&path).await.unwrap();
+
+        let res = DataStore::open_without_background("test", path.to_owned()).await;
+        assert!(matches!(
+            res,
+            Err(crate::err::Error::IncompatibleFormat { found, supported, .. })
+                if found == FORMAT_VERSION + 1 && supported == FORMAT_VERSION
+        ));
+        assert!(matches!(
+            DataStore::migrate(path).await,
+            Err(crate::err::Error::IncompatibleFormat { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn datastore_migrate_legacy_store() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_45");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        store.put("key", "value").await.unwrap();
+        store.force_flush().await.unwrap();
+        store.meta.write().await.unwrap();
+        let created_at = store.meta.created_at.timestamp_millis() as u64;
+        drop(store);
+
+        // stores created before the store-info file existed have none
+        tokio::fs::remove_file(StoreInfo::path(&path)).await.unwrap();
+        let store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        drop(store);
+        assert!(StoreInfo::read(&path).await.unwrap().is_none());
+
+        let info = DataStore::migrate(path.to_owned()).await.unwrap();
+        assert_eq!(info.format_version, FORMAT_VERSION);
+        assert_eq!(info.created_at, created_at);
+        assert_eq!(StoreInfo::read(&path).await.unwrap().unwrap(), info);
+
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        assert_eq!(store.get("key").await.unwrap().unwrap().val, b"value".to_vec());
+    }
+
+    #[tokio::test]
+    async fn datastore_put_with_metadata() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_46");
+        let config = Config {
+            verify_reads: true,
+            ..Default::default()
+        };
+        let mut store = DataStore::open_with_config("test", path.to_owned(), config.clone())
+            .await
+            .unwrap();
+        store
+            .put_with_metadata("apple", "tim cook", "text/plain")
+            .await
+            .unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+
+        let (entry, metadata) = store.get_with_metadata("apple").await.unwrap().unwrap();
+        assert_eq!(entry.val, b"tim cook".to_vec());
+        assert_eq!(metadata, Some(b"text/plain".to_vec()));
+        // plain reads are unaffected
+        assert_eq!(
+            store.get("apple").await.unwrap().unwrap().val,
+            b"tim cook".to_vec()
+        );
+        let (_, metadata) = store.get_with_metadata("google").await.unwrap().unwrap();
+        assert!(metadata.is_none());
+        assert!(store.get_with_metadata("nvidia").await.unwrap().is_none());
+
+        let res = store.put_with_metadata("nvidia", "jensen huang", [0; 256]).await;
+        assert!(matches!(
+            res,
+            Err(crate::err::Error::MetadataTooLarge { size: 256, max: 255 })
+        ));
+
+        // metadata is read back from sstables and after reopening
+        store.force_flush().await.unwrap();
+        drop(store);
+        let mut store = DataStore::open_with_config("test", path, config).await.unwrap();
+        let (_, metadata) = store.get_with_metadata("apple").await.unwrap().unwrap();
+        assert_eq!(metadata, Some(b"text/plain".to_vec()));
+
+        // a later write without metadata replaces it
+        store.put("apple", "tim cook").await.unwrap();
+        let (_, metadata) = store.get_with_metadata("apple").await.unwrap().unwrap();
+        assert!(metadata.is_none());
+    }
+
+    #[tokio::test]
+    async fn datastore_export_range() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_47");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        store.put("apple", "tim cook").await.unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+        store.put("meta", "mark zuckerberg").await.unwrap();
+        store.put("nvidia", "jensen huang").await.unwrap();
+        store.force_flush().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+
+        // newer versions in the memtable replace flushed ones
+        store.put("google", "larry page").await.unwrap();
+        store.delete("meta").await.unwrap();
+
+        let export_dir = root.path().join("export");
+        let manifest = store.export("apple".."nvidia", &export_dir).await.unwrap();
+        assert_eq!(manifest.entry_count, 2);
+        assert_eq!(manifest.smallest_key, Some(b"apple".to_vec()));
+        assert_eq!(manifest.biggest_key, Some(b"google".to_vec()));
+        assert_eq!(manifest.format_version, FORMAT_VERSION);
+        assert_eq!(ExportManifest::read(&export_dir).await.unwrap(), manifest);
+
+        // exported tables point into the exported value log
+        let vlog = tokio::fs::read(export_dir.join(&manifest.vlog)).await.unwrap();
+        let mut exported = Vec::new();
+        for sst_dir in manifest.sstables.iter() {
+            let sst_dir = export_dir.join(sst_dir);
+            let mut table = crate::sst::Table::build_from(
+                sst_dir.to_owned(),
+                sst_dir.join("data.db"),
+                sst_dir.join("index.db"),
+            )
+            .await
+            .unwrap();
+            table.load_entries_from_file().await.unwrap();
+            for e in table.entries.iter() {
+                let (entry, _) =
+                    crate::vlog::ValueLogEntry::deserialize(&vlog[e.value().val_offset..]).unwrap();
+                exported.push((e.key().to_owned(), entry.value));
+            }
+        }
+        assert_eq!(
+            exported,
+            vec![
+                (b"apple".to_vec(), b"tim cook".to_vec()),
+                (b"google".to_vec(), b"larry page".to_vec())
+            ]
+        );
+
+        // an export never overwrites files
+        let res = store.export("a".."z", &export_dir).await;
+        assert!(matches!(res, Err(crate::err::Error::ExportDirNotEmpty(_))));
+    }
+
+    #[tokio::test]
+    async fn datastore_split_and_merge() {
+        setup();
+        let root = tempdir().unwrap();
+        let mut store = DataStore::open_without_background("test", root.path().join("store_test_48"))
+            .await
+            .unwrap();
+        store.put("apple", "tim cook").await.unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+        store.force_flush().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        store
+            .put_with_metadata("nvidia", "jensen huang", "text/plain")
+            .await
+            .unwrap();
+        store.put("openai", "sam altman").await.unwrap();
+
+        let export_dir = root.path().join("export");
+        let manifest = store.split_at("google", &export_dir).await.unwrap();
+        assert_eq!(manifest.entry_count, 3);
+        assert!(store.get("apple").await.unwrap().is_some());
+        for key in ["google", "nvidia", "openai"] {
+            assert!(store.get(key).await.unwrap().is_none());
+        }
+
+        // merged entries replace older versions in the other store
+        let mut other = DataStore::open_without_background("test", root.path().join("store_test_48_other"))
+            .await
+            .unwrap();
+        other.put("google", "larry page").await.unwrap();
+        other.put("xai", "elon musk").await.unwrap();
+        assert_eq!(other.merge_from(&export_dir).await.unwrap(), 3);
+        assert_eq!(
+            other.get("google").await.unwrap().unwrap().val,
+            b"sundar pichai".to_vec()
+        );
+        let (entry, metadata) = other.get_with_metadata("nvidia").await.unwrap().unwrap();
+        assert_eq!(entry.val, b"jensen huang".to_vec());
+        assert_eq!(metadata, Some(b"text/plain".to_vec()));
+        assert!(other.get("openai").await.unwrap().is_some());
+        assert!(other.get("xai").await.unwrap().is_some());
+        assert!(other.get("apple").await.unwrap().is_none());
+
+        // merged entries survive flush and reopen
+        other.force_flush().await.unwrap();
+        drop(other);
+        let other = DataStore::open_without_background("test", root.path().join("store_test_48_other"))
+            .await
+            .unwrap();
+        assert_eq!(
+            other.get("openai").await.unwrap().unwrap().val,
+            b"sam altman".to_vec()
+        );
+
+        // exports from newer formats are refused
+        let mut manifest = ExportManifest::read(&export_dir).await.unwrap();
+        manifest.format_version = FORMAT_VERSION + 1;
+        std::fs::write(
+            export_dir.join("MANIFEST"),
+            serde_json::to_vec(&manifest).unwrap(),
+        )
+        .unwrap();
+        let res = store.merge_from(&export_dir).await;
+        assert!(matches!(res, Err(crate::err::Error::IncompatibleFormat { .. })));
+    }
+
+    #[tokio::test]
+    async fn datastore_seek_unaffected_by_flush_during_scan() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_49");
+        let config = Config {
+            allow_prefetch: true,
+            prefetch_size: 4,
+            ..Default::default()
+        };
+        let mut store = DataStore::open_with_config("test", path, config).await.unwrap();
+        for i in 0..20 {
+            store
+                .put(format!("key_{:02}", i), format!("old_{}", i))
+                .await
+                .unwrap();
+        }
+        store.force_flush().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        for i in 10..30 {
+            store
+                .put(format!("key_{:02}", i), format!("new_{}", i))
+                .await
+                .unwrap();
+        }
+
+        let mut iter = store.seek("key_05", "key_25").await.unwrap();
+        let mut scanned = Vec::new();
+        for _ in 0..5 {
+            scanned.push(iter.next().await.unwrap().unwrap());
+        }
+
+        // memtable rotation, flush and compaction while the scan is in progress
+        for i in 0..30 {
+            store.put(format!("key_{:02}", i), "newest").await.unwrap();
+        }
+        store.delete("key_20").await.unwrap();
+        store.force_flush().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        store.run_compaction().await.unwrap();
+
+        while let Some(entry) = iter.next().await.unwrap() {
+            scanned.push(entry);
+        }
+        let expected: Vec<(Vec<u8>, Vec<u8>)> = (5..25)
+            .map(|i| {
+                let val = if i < 10 {
+                    format!("old_{}", i)
+                } else {
+                    format!("new_{}", i)
+                };
+                (format!("key_{:02}", i).into_bytes(), val.into_bytes())
+            })
+            .collect();
+        let scanned: Vec<(Vec<u8>, Vec<u8>)> = scanned.into_iter().map(|e| (e.key, e.val)).collect();
+        assert_eq!(scanned, expected);
+        assert!(iter.next().await.unwrap().is_none());
+
+        // a new iterator sees the latest writes
+        let mut iter = store.seek("key_19", "key_21").await.unwrap();
+        assert_eq!(iter.next().await.unwrap().unwrap().val, b"newest".to_vec());
+        assert!(iter.next().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn datastore_seek_resumes_from_checkpoint_after_reopen() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_checkpoint");
+        let config = Config {
+            allow_prefetch: true,
+            prefetch_size: 4,
+            ..Default::default()
+        };
+        let mut store = DataStore::open_with_config("test", path.to_owned(), config.clone())
+            .await
+            .unwrap();
+        for i in 0..20 {
+            store.put(format!("key_{:02}", i), "old").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+
+        let mut iter = store.seek("key_00", "key_20").await.unwrap();
+        assert!(iter.checkpoint().is_none());
+        for _ in 0..6 {
+            iter.next().await.unwrap().unwrap();
+        }
+        let saved = iter.checkpoint().unwrap().encode();
+        drop(iter);
+        drop(store);
+
+        let mut store = DataStore::open_with_config("test", path, config).await.unwrap();
+        store.put("key_03", "new").await.unwrap();
+        store.put("key_10", "new").await.unwrap();
+        let token = ContinuationToken::decode(&saved).unwrap();
+        let mut iter = store.seek_after("key_00", "key_20", Some(&token)).await.unwrap();
+        let mut scanned = Vec::new();
+        while let Some(entry) = iter.next().await.unwrap() {
+            scanned.push((entry.key, entry.val));
+        }
+        let expected: Vec<(Vec<u8>, Vec<u8>)> = (6..20)
+            .map(|i| {
+                let val = if i == 10 { "new" } else { "old" };
+                (format!("key_{:02}", i).into_bytes(), val.as_bytes().to_vec())
+            })
+            .collect();
+        assert_eq!(scanned, expected);
+        assert_eq!(
+            iter.checkpoint().unwrap(),
+            ContinuationToken::after(b"key_19".to_vec())
+        );
+
+        // without a token the whole range is scanned
+        let iter = store.seek_after("key_00", "key_20", None).await.unwrap();
+        assert_eq!(iter.count().await, 20);
+    }
+
+    #[tokio::test]
+    async fn datastore_warm_cache() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_50");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        for i in 0..2000 {
+            store.put(format!("key_{:04}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        drop(store);
+
+        // caches of a reopened store are empty
+        let block_cache = BlockCache::new(1024 * 1024);
+        let config = Config {
+            block_cache: block_cache.clone(),
+            ..Default::default()
+        };
+        let store = DataStore::open_with_config("test", path.to_owned(), config)
+            .await
+            .unwrap();
+        let warmup = store.warm_cache("key_0500".."key_1000").await.unwrap();
+        assert!(warmup.sstables > 0);
+        assert!(warmup.blocks > 0);
+        assert_eq!(block_cache.usage(), warmup.bytes);
+
+        // cached blocks are not read again, other ranges are
+        let again = store.warm_cache("key_0500".."key_1000").await.unwrap();
+        assert_eq!(again.blocks, 0);
+        let whole = store.warm_cache::<&str>(..).await.unwrap();
+        assert!(whole.blocks > 0);
+        assert_eq!(store.warm_cache("zzz"..).await.unwrap(), CacheWarmup::default());
+        drop(store);
+
+        // warming stops once the block cache is full
+        let block_cache = BlockCache::new(1);
+        let config = Config {
+            block_cache: block_cache.clone(),
+            ..Default::default()
+        };
+        let store = DataStore::open_with_config("test", path, config).await.unwrap();
+        let warmup = store.warm_cache::<&str>(..).await.unwrap();
+        assert_eq!(warmup.blocks, 1);
+    }
+
+    #[tokio::test]
+    async fn datastore_keyspace_quota() {
+        setup();
+        let root = tempdir().unwrap();
+        let mut config = Config::default();
+        config.keyspace_quotas.insert(
+            "tenant".to_string(),
+            KeyspaceQuota {
+                max_keys: Some(3),
+                ..Default::default()
+            },
+        );
+        let mut store =
+            DataStore::open_with_config("tenant", root.path().join("store_test_51"), config.clone())
+                .await
+                .unwrap();
+        store.put("apple", "tim cook").await.unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+        store.put("nvidia", "jensen huang").await.unwrap();
+        let stats = store.keyspace_stats().await.unwrap();
+        assert_eq!(stats.keyspace, "tenant");
+        assert_eq!(stats.keys, 3);
+        assert!(stats.bytes > 0);
+
+        let res = store.put("meta", "mark zuckerberg").await;
+        assert!(matches!(
+            res,
+            Err(crate::err::Error::QuotaExceeded {
+                limit: 3,
+                unit: "keys",
+                ..
+            })
+        ));
+        assert!(store.get("meta").await.unwrap().is_none());
+        // overwriting a live key adds no key
+        store.put("apple", "steve jobs").await.unwrap();
+        // deletes are accepted at the quota and free a key
+        assert!(store.delete("apple").await.unwrap());
+        assert_eq!(store.keyspace_stats().await.unwrap().keys, 2);
+        store.put("meta", "mark zuckerberg").await.unwrap();
+        assert_eq!(store.keyspace_stats().await.unwrap().keys, 3);
+
+        // other keyspaces sharing the config are unlimited
+        let mut other = DataStore::open_with_config("other", root.path().join("store_test_51_other"), config)
+            .await
+            .unwrap();
+        for i in 0..10 {
+            other.put(format!("key_{}", i), "value").await.unwrap();
+        }
+
+        let mut config = Config::default();
+        config.keyspace_quotas.insert(
+            "tenant".to_string(),
+            KeyspaceQuota {
+                max_bytes: Some(stats.bytes + 10),
+                ..Default::default()
+            },
+        );
+        drop(store);
+        let mut store = DataStore::open_with_config("tenant", root.path().join("store_test_51"), config)
+            .await
+            .unwrap();
+        let res = store
+            .put_with_metadata("meta", "mark zuckerberg", "text/plain")
+            .await;
+        assert!(matches!(
+            res,
+            Err(crate::err::Error::QuotaExceeded { unit: "bytes", .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn datastore_test_rotate_on_vlog_growth() {
+        let root = tempdir().unwrap();
+        let config = Config {
+            max_unflushed_vlog_size: Some(4096),
+            ..Default::default()
+        };
+        let mut store = DataStore::open_with_config("test", root.path().join("store_test_52"), config)
+            .await
+            .unwrap();
+        let initial_head = store.val_log.head_offset;
+        let value = "v".repeat(1000);
+        for i in 0..10 {
+            store.put(format!("key_{}", i), &value).await.unwrap();
+        }
+        // memtable is far from full, the value log growth rotated it
+        assert!(store.val_log.head_offset > initial_head);
+        assert!(store.val_log.size - store.val_log.head_offset < 4096 + 1100);
+        for i in 0..10 {
+            let entry = store.get(format!("key_{}", i)).await.unwrap().unwrap();
+            assert_eq!(entry.val, value.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_test_seek_skips_tombstone_blocks() {
+        let root = tempdir().unwrap();
+        let mut store = DataStore::open_without_background("test", root.path().join("store_test_53"))
+            .await
+            .unwrap();
+        for i in 0..600 {
+            store.put(format!("key_{:04}", i), "value").await.unwrap();
+        }
+        for i in 200..500 {
+            store.delete(format!("key_{:04}", i)).await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+
+        async fn seek_all(store: &DataStore<'static, Vec<u8>>) -> Vec<String> {
+            let mut iter = store.seek("key_", "key_~").await.unwrap();
+            let mut keys = Vec::new();
+            while let Some(entry) = iter.next().await.unwrap() {
+                keys.push(String::from_utf8(entry.key).unwrap());
+            }
+            keys
+        }
+        let keys = seek_all(&store).await;
+        assert_eq!(keys.len(), 300);
+        assert_eq!(keys[199], "key_0199");
+        assert_eq!(keys[200], "key_0500");
+
+        let table = store
+            .buckets
+            .read()
+            .await
+            .buckets
+            .values()
+            .next()
+            .unwrap()
+            .sstables
+            .read()
+            .await[0]
+            .clone();
+        let index = table.index_file.file.load().await.unwrap();
+        let skipped: Vec<_> = index
+            .entries()
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| index.is_tombstone_block(*idx))
+            .map(|(_, e)| e.block_handle)
+            .collect();
+        assert!(!skipped.is_empty());
+        for offset in skipped {
+            assert!(store
+                .config
+                .block_cache
+                .get(&table.data_file.path, offset)
+                .is_none());
+        }
+
+        // tombstones hiding entries of an older table are read
+        for i in 1000..1300 {
+            store.put(format!("key_{:04}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        for i in 1000..1300 {
+            store.delete(format!("key_{:04}", i)).await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        assert_eq!(seek_all(&store).await.len(), 300);
+    }
+
+    #[tokio::test]
+    async fn datastore_test_prefix_cardinality() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_54");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        for i in 0..100 {
+            store.put(format!("user:{:03}", i), "value").await.unwrap();
+        }
+        for i in 0..20 {
+            store.put(format!("order:{:03}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        for i in 100..110 {
+            store.put(format!("user:{:03}", i), "value").await.unwrap();
+        }
+        store.delete("order:000").await.unwrap();
+
+        let users = store.prefix_cardinality("user:").await.unwrap();
+        assert!((110..120).contains(&users));
+        assert!(store.prefix_cardinality("order:").await.unwrap() >= 20);
+        assert_eq!(store.prefix_cardinality("product:").await.unwrap(), 0);
+        drop(store);
+
+        // sketch is recovered from the summary
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        let ranges = store.key_range.key_ranges.read().await;
+        let summary = ranges.values().next().unwrap().sst.summary.as_ref().unwrap();
+        assert!(summary.prefix_sketch.as_ref().unwrap().estimate(b"user:") >= 100);
+        drop(ranges);
+        assert!(store.prefix_cardinality("user:").await.unwrap() >= users);
+    }
+
+    #[tokio::test]
+    async fn datastore_test_entry_at() {
+        let root = tempdir().unwrap();
+        let mut store = DataStore::open_without_background("test", root.path().join("store_test_55"))
+            .await
+            .unwrap();
+        store.put("apple", "tim cook").await.unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+        let offset = store.active_memtable.get("apple").unwrap().val_offset;
+        // not flushed yet
+        let (entry, referenced) = store.entry_at(offset).await.unwrap().unwrap();
+        assert_eq!(entry.key, b"apple");
+        assert_eq!(entry.value, b"tim cook");
+        assert!(!referenced);
+
+        store.force_flush().await.unwrap();
+        let (_, referenced) = store.entry_at(offset).await.unwrap().unwrap();
+        assert!(referenced);
+
+        store.put("apple", "steve jobs").await.unwrap();
+        let new_offset = store.active_memtable.get("apple").unwrap().val_offset;
+        store.force_flush().await.unwrap();
+        // older sstable keeps the overwritten version until compaction
+        let (_, referenced) = store.entry_at(offset).await.unwrap().unwrap();
+        assert!(referenced);
+        let (entry, referenced) = store.entry_at(new_offset).await.unwrap().unwrap();
+        assert_eq!(entry.value, b"steve jobs");
+        assert!(referenced);
+
+        assert!(store.entry_at(store.val_log.size).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn datastore_test_verify_consistency() {
+        let root = tempdir().unwrap();
+        let mut store = DataStore::open_without_background("test", root.path().join("store_test_56"))
+            .await
+            .unwrap();
+        for i in 0..50 {
+            store.put(format!("key_{:02}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        let report = store.verify_consistency(None).await.unwrap();
+        assert_eq!(report.sstables, 1);
+        assert_eq!(report.checked, 50);
+        assert!(report.is_consistent());
+        assert_eq!(store.verify_consistency(Some(10)).await.unwrap().checked, 10);
+
+        // tables pointing at values of another key, or past the value log
+        store.put("google", "sundar pichai").await.unwrap();
+        let google_offset = store.active_memtable.get("google").unwrap().val_offset;
+        let now = chrono::Utc::now();
+        store.active_memtable.insert(&crate::memtable::Entry::new(
+            b"apple".to_vec(),
+            google_offset,
+            now,
+            false,
+        ));
+        store.active_memtable.insert(&crate::memtable::Entry::new(
+            b"nvidia".to_vec(),
+            store.val_log.size + 100,
+            now,
+            false,
+        ));
+        store.force_flush().await.unwrap();
+
+        let report = store.verify_consistency(None).await.unwrap();
+        assert_eq!(report.checked, 53);
+        let mut kinds: Vec<_> = report
+            .inconsistencies
+            .iter()
+            .map(|i| (i.key.clone(), i.kind.clone()))
+            .collect();
+        kinds.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            kinds,
+            vec![
+                (
+                    b"apple".to_vec(),
+                    InconsistencyKind::KeyMismatch {
+                        found: b"google".to_vec()
+                    }
+                ),
+                (b"nvidia".to_vec(), InconsistencyKind::Missing),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn datastore_test_compaction_journal_recovery() {
+        use crate::compactors::journal::CompactionJournal;
+        fn copy_sstable(from: &std::path::Path, to: &std::path::Path) {
+            std::fs::create_dir_all(to).unwrap();
+            for file in std::fs::read_dir(from).unwrap() {
+                let file = file.unwrap();
+                std::fs::copy(file.path(), to.join(file.file_name())).unwrap();
+            }
+        }
+        let root = tempdir().unwrap();
+        let backup = tempdir().unwrap();
+        let path = root.path().join("store_test_57");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        for i in 0..crate::consts::MIN_TRESHOLD {
+            store.put(format!("key_{}", i), "value").await.unwrap();
+            store.force_flush().await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+        let inputs: Vec<PathBuf> = store.key_range.key_ranges.read().await.keys().cloned().collect();
+        for (i, dir) in inputs.iter().enumerate() {
+            copy_sstable(dir, &backup.path().join(i.to_string()));
+        }
+        let manifest_path = crate::bucket::manifest::BucketManifest::path(&store.dir.buckets);
+        let manifest = std::fs::read(&manifest_path).unwrap();
+        store.run_compaction().await.unwrap();
+        let outputs: Vec<PathBuf> = store.key_range.key_ranges.read().await.keys().cloned().collect();
+        assert!(!outputs.is_empty());
+        assert!(inputs.iter().all(|dir| !dir.exists()));
+        let buckets_dir = store.buckets.read().await.dir.to_owned();
+        assert!(CompactionJournal::read(&buckets_dir).await.unwrap().is_none());
+        drop(store);
+
+        // crash while inputs are deleted, the rest of them are deleted at open
+        for (i, dir) in inputs.iter().enumerate() {
+            copy_sstable(&backup.path().join(i.to_string()), dir);
+        }
+        let mut journal = CompactionJournal::new(inputs.to_owned());
+        journal.outputs = outputs.to_owned();
+        journal.complete = true;
+        journal.write(&buckets_dir).await.unwrap();
+        let store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        assert!(inputs.iter().all(|dir| !dir.exists()));
+        assert!(outputs.iter().all(|dir| dir.exists()));
+        assert_eq!(store.key_range.key_ranges.read().await.len(), outputs.len());
+        assert!(CompactionJournal::read(&buckets_dir).await.unwrap().is_none());
+        drop(store);
+
+        // crash before all outputs are written, the manifest still lists the inputs
+        // and outputs are deleted at open
+        for (i, dir) in inputs.iter().enumerate() {
+            copy_sstable(&backup.path().join(i.to_string()), dir);
+        }
+        std::fs::write(&manifest_path, manifest).unwrap();
+        journal.complete = false;
+        journal.write(&buckets_dir).await.unwrap();
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        assert!(inputs.iter().all(|dir| dir.exists()));
+        assert!(outputs.iter().all(|dir| !dir.exists()));
+        assert_eq!(store.key_range.key_ranges.read().await.len(), inputs.len());
+        for i in 0..crate::consts::MIN_TRESHOLD {
+            assert!(store.get(format!("key_{}", i)).await.unwrap().is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_test_wal() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_58");
+        let config = Config {
+            wal_max_value_size: Some(16),
+            ..Default::default()
+        };
+        let mut store = DataStore::open_with_config("test", path.to_owned(), config.clone())
+            .await
+            .unwrap();
+        let vlog_size = store.val_log.size;
+        store.put("apple", "tim cook").await.unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+        store.delete("apple").await.unwrap();
+        assert_eq!(store.val_log.size, vlog_size);
+        assert!(store.get("apple").await.unwrap().is_none());
+        assert_eq!(
+            store.get("google").await.unwrap().unwrap().val,
+            b"sundar pichai".to_vec()
+        );
+
+        // a larger value goes to the value log, logged entries are moved there first
+        store.put("nvidia", "jensen huang, nvidia").await.unwrap();
+        assert!(store.val_log.size > vlog_size);
+        assert!(store.val_log.wal.as_ref().unwrap().is_empty());
+        assert_eq!(
+            store.get("google").await.unwrap().unwrap().val,
+            b"sundar pichai".to_vec()
+        );
+        store.put("meta", "mark zuckerberg").await.unwrap();
+        assert!(!store.val_log.wal.as_ref().unwrap().is_empty());
+        drop(store);
+
+        // entries left in the log are recovered at open
+        let mut store = DataStore::open_with_config("test", path, config).await.unwrap();
+        assert!(store.val_log.wal.as_ref().unwrap().is_empty());
+        assert!(store.get("apple").await.unwrap().is_none());
+        assert_eq!(
+            store.get("meta").await.unwrap().unwrap().val,
+            b"mark zuckerberg".to_vec()
+        );
+        store.put("openai", "sam altman").await.unwrap();
+        store.force_flush().await.unwrap();
+        assert!(store.val_log.wal.as_ref().unwrap().is_empty());
+        for (key, val) in [
+            ("google", "sundar pichai"),
+            ("nvidia", "jensen huang, nvidia"),
+            ("meta", "mark zuckerberg"),
+            ("openai", "sam altman"),
+        ] {
+            assert_eq!(
+                store.get(key).await.unwrap().unwrap().val,
+                val.as_bytes().to_vec()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_test_put_with_timestamp() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_59");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        let created_at = chrono::Utc::now() - chrono::Duration::hours(1);
+        store
+            .put_with_timestamp("apple", "tim cook", created_at)
+            .await
+            .unwrap();
+        assert_eq!(store.get("apple").await.unwrap().unwrap().created_at, created_at);
+
+        let res = store.put_with_timestamp("apple", "steve jobs", created_at).await;
+        assert!(matches!(res, Err(crate::err::Error::TimestampRegression { .. })));
+        let res = store
+            .put_with_timestamp(
+                "apple",
+                "steve jobs",
+                chrono::Utc::now() + chrono::Duration::hours(1),
+            )
+            .await;
+        assert!(matches!(res, Err(crate::err::Error::TimestampInFuture { .. })));
+
+        // a deletion is a version too, also once flushed
+        store.delete("apple").await.unwrap();
+        store.force_flush().await.unwrap();
+        let res = store
+            .put_with_timestamp("apple", "steve jobs", created_at + chrono::Duration::minutes(1))
+            .await;
+        assert!(matches!(res, Err(crate::err::Error::TimestampRegression { .. })));
+        assert!(store.get("apple").await.unwrap().is_none());
+        store
+            .put_with_timestamp("apple", "steve jobs", chrono::Utc::now())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get("apple").await.unwrap().unwrap().val,
+            b"steve jobs".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn datastore_test_raw_versions() {
+        use crate::db::VersionSource;
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_60");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        store.put("apple", "tim cook").await.unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+        store.force_flush().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        store.put("apple", "steve jobs").await.unwrap();
+        store.delete("google").await.unwrap();
+        store.put("nvidia", "jensen huang").await.unwrap();
+
+        let mut iter = store.raw_versions("a", "h").await.unwrap();
+        assert_eq!(iter.remaining(), 4);
+        let mut versions = Vec::new();
+        while let Some(version) = iter.next().await.unwrap() {
+            let from_sstable = matches!(version.source, VersionSource::SSTable(_));
+            versions.push((version.key, version.val, version.is_tombstone, from_sstable));
+        }
+        assert_eq!(
+            versions,
+            vec![
+                (b"apple".to_vec(), Some(b"steve jobs".to_vec()), false, false),
+                (b"apple".to_vec(), Some(b"tim cook".to_vec()), false, true),
+                (b"google".to_vec(), None, true, false),
+                (b"google".to_vec(), Some(b"sundar pichai".to_vec()), false, true),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn datastore_test_count_range() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_61");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        for i in 0..2000 {
+            store.put(format!("key_{:05}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        for i in 0..100 {
+            store.delete(format!("key_{:05}", i)).await.unwrap();
+        }
+        store.put("key_03000", "value").await.unwrap();
+
+        let range = "key_00050".to_string().."key_01500".to_string();
+        assert_eq!(store.count_range(range.to_owned()).await.unwrap(), 1400);
+        assert_eq!(store.count_range("key_".to_string()..).await.unwrap(), 1901);
+        // tombstones still in the memtable are not subtracted from sstable blocks
+        let estimate = store.estimate_range_count(range).await.unwrap();
+        assert!((1400..=1450).contains(&estimate), "estimate {}", estimate);
+        let estimate = store
+            .estimate_range_count("key_01000".to_string()..)
+            .await
+            .unwrap();
+        assert!((950..=1050).contains(&estimate), "estimate {}", estimate);
+    }
+
+    #[tokio::test]
+    async fn datastore_test_page() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_62");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        for i in 0..500 {
+            store
+                .put(format!("key_{:05}", i), format!("value_{}", i))
+                .await
+                .unwrap();
+        }
+        store.force_flush().await.unwrap();
+        for i in 100..400 {
+            store.delete(format!("key_{:05}", i)).await.unwrap();
+        }
+        store.put("key_00010", "updated").await.unwrap();
+
+        let mut entries = Vec::new();
+        let mut token = None;
+        let mut pages = 0;
+        loop {
+            let page = store
+                .page("key_00005", "key_00450", 7, token.as_ref())
+                .await
+                .unwrap();
+            assert!(page.entries.len() <= 7);
+            entries.extend(page.entries);
+            pages += 1;
+            match page.next {
+                Some(next) => token = Some(ContinuationToken::decode(&next.encode()).unwrap()),
+                None => break,
+            }
+        }
+        let expected: Vec<Vec<u8>> = (5..100)
+            .chain(400..450)
+            .map(|i| format!("key_{:05}", i).into_bytes())
+            .collect();
+        let keys: Vec<Vec<u8>> = entries.iter().map(|e| e.key.to_owned()).collect();
+        assert_eq!(keys, expected);
+        assert_eq!(entries[5].val, b"updated".to_vec());
+        assert_eq!(entries[6].val, b"value_11".to_vec());
+        assert!(pages <= 22, "pages {}", pages);
+
+        assert!(matches!(
+            ContinuationToken::decode("zz"),
+            Err(crate::err::Error::InvalidContinuationToken)
+        ));
+        assert!(matches!(
+            ContinuationToken::decode("026b"),
+            Err(crate::err::Error::InvalidContinuationToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn datastore_test_health() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_63");
+        let mut config = Config {
+            min_free_disk_space: None,
+            ..Default::default()
+        };
+        config.keyspace_quotas.insert(
+            "tenant".to_string(),
+            KeyspaceQuota {
+                max_keys: Some(1),
+                ..Default::default()
+            },
+        );
+        let mut store = DataStore::open_with_config("tenant", path.to_owned(), config.clone())
+            .await
+            .unwrap();
+        let health = store.health().await.unwrap();
+        assert_eq!(health, Health::Ok);
+        assert!(health.is_ready());
+
+        config.env.errors.record(crate::env::BackgroundError {
+            job: BackgroundJob::Compaction,
+            message: "disk on fire".to_string(),
+            occurred_at: chrono::Utc::now(),
+            restarts: 0,
+        });
+        match store.health().await.unwrap() {
+            Health::Degraded(reason) => assert!(reason.contains("disk on fire"), "{}", reason),
+            health => panic!("unexpected health {:?}", health),
+        }
+
+        store.put("apple", "tim cook").await.unwrap();
+        let health = store.health().await.unwrap();
+        assert!(matches!(health, Health::ReadOnly(ref reason) if reason.contains("quota")));
+        assert!(!health.is_ready());
+
+        store.config.keyspace_quotas.clear();
+        store.config.min_free_disk_space = Some(u64::MAX);
+        assert!(matches!(store.health().await.unwrap(), Health::ReadOnly(_)));
+    }
+
+    #[tokio::test]
+    async fn datastore_test_layout_issues() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_64");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        assert!(store.layout_issues().is_empty());
+        store.put("apple", "tim cook").await.unwrap();
+        store.force_flush().await.unwrap();
+        let buckets_dir = store.dir.buckets.to_owned();
+        let sst_dir = store
+            .buckets
+            .read()
+            .await
+            .buckets
+            .values()
+            .next()
+            .unwrap()
+            .sstables
+            .read()
+            .await[0]
+            .dir
+            .to_owned();
+        drop(store);
+        assert!(path.join(crate::consts::BUCKET_MANIFEST_FILE_NAME).is_file());
+
+        let stray_file = sst_dir.join("notes.txt");
+        std::fs::write(&stray_file, b"not an sstable file").unwrap();
+        let stray_dir = buckets_dir.join("not_a_bucket");
+        std::fs::create_dir(&stray_dir).unwrap();
+        let stray_sst = sst_dir.with_file_name("sstable_0");
+        std::fs::create_dir(&stray_sst).unwrap();
+
+        let store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        let issues = store.layout_issues();
+        assert_eq!(issues.len(), 3, "{:?}", issues);
+        assert!(issues.contains(&LayoutIssue::UnknownFile(stray_file)));
+        assert!(issues.contains(&LayoutIssue::UnknownFile(stray_dir)));
+        assert!(issues.contains(&LayoutIssue::UnknownFile(stray_sst)));
+        assert_eq!(
+            store.get("apple").await.unwrap().unwrap().val,
+            b"tim cook".to_vec()
+        );
+
+        // sstable written after the manifest is not loaded, a removed one is reported
+        let copied_sst = sst_dir.with_file_name("sstable_1");
+        std::fs::create_dir(&copied_sst).unwrap();
+        for file in std::fs::read_dir(&sst_dir).unwrap() {
+            let file = file.unwrap();
+            std::fs::copy(file.path(), copied_sst.join(file.file_name())).unwrap();
+        }
+        drop(store);
+        let mut manifest = crate::bucket::manifest::BucketManifest::read(&buckets_dir)
+            .await
+            .unwrap()
+            .unwrap();
+        manifest.buckets[0]
+            .sstables
+            .push(crate::bucket::manifest::SSTableRecord::named("sstable_2"));
+        manifest.write(&buckets_dir).await.unwrap();
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        let issues = store.layout_issues();
+        assert!(issues.contains(&LayoutIssue::UnlistedSSTable(copied_sst.to_owned())));
+        assert!(issues.contains(&LayoutIssue::MissingSSTable(sst_dir.with_file_name("sstable_2"))));
+        assert!(copied_sst.is_dir());
+        let buckets = store.buckets.read().await;
+        for bucket in buckets.buckets.values() {
+            assert!(bucket
+                .sstables
+                .read()
+                .await
+                .iter()
+                .all(|sst| sst.dir != copied_sst));
+        }
+    }
+
+    #[derive(Debug)]
+    struct TenantPolicy;
+
+    fn tenant(key: &[u8]) -> &[u8] {
+        key.split(|&b| b == b':').next().unwrap_or_default()
+    }
+
+    fn is_shared(sst: &SSTableInfo) -> bool {
+        tenant(&sst.smallest_key) != tenant(&sst.biggest_key)
+    }
+
+    #[async_trait::async_trait]
+    impl CompactionPolicy for TenantPolicy {
+        async fn pick_buckets(&self, buckets: &[BucketInfo]) -> Vec<BucketID> {
+            buckets
+                .iter()
+                .filter(|b| b.sstables.iter().filter(|sst| is_shared(sst)).count() >= 2)
+                .map(|b| b.id)
+                .collect()
+        }
+
+        async fn choose_inputs(&self, bucket: &BucketInfo) -> Vec<PathBuf> {
+            bucket
+                .sstables
+                .iter()
+                .filter(|sst| is_shared(sst))
+                .map(|sst| sst.dir.to_owned())
+                .collect()
+        }
+
+        fn split_between(&self, previous: &[u8], key: &[u8]) -> bool {
+            tenant(previous) != tenant(key)
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_test_compaction_policy() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_65");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        store.compactor.config.policy = Some(Arc::new(TenantPolicy));
+        store.put("apple:ceo", "steve jobs").await.unwrap();
+        store.put("nvidia:ceo", "jensen huang").await.unwrap();
+        store.force_flush().await.unwrap();
+        store.put("apple:ceo", "tim cook").await.unwrap();
+        store.put("nvidia:founded", "1993").await.unwrap();
+        store.force_flush().await.unwrap();
+
+        store.run_compaction().await.unwrap();
+        let buckets = BucketInfo::from_map(&*store.buckets.read().await).await;
+        let ssts: Vec<_> = buckets.iter().flat_map(|b| b.sstables.iter()).collect();
+        // head and tail entries of the value log are keys of their own tenant
+        assert_eq!(ssts.len(), 4, "{:?}", ssts);
+        assert!(ssts.iter().all(|sst| !is_shared(sst)));
+        assert_eq!(
+            store.get("apple:ceo").await.unwrap().unwrap().val,
+            b"tim cook".to_vec()
+        );
+        assert_eq!(
+            store.get("nvidia:ceo").await.unwrap().unwrap().val,
+            b"jensen huang".to_vec()
+        );
+        assert_eq!(
+            store.get("nvidia:founded").await.unwrap().unwrap().val,
+            b"1993".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn datastore_test_plan_compaction() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_66");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        assert!(store.plan_compaction().await.unwrap().is_empty());
+        for i in 0..crate::consts::MIN_TRESHOLD {
+            store.put(format!("key_{}", i), "value").await.unwrap();
+            store.force_flush().await.unwrap();
+        }
+
+        let plan = store.plan_compaction().await.unwrap();
+        assert_eq!(plan.merges.len(), 1);
+        let merge = &plan.merges[0];
+        assert_eq!(merge.sstables.len(), crate::consts::MIN_TRESHOLD);
+        assert!(merge.sstables.iter().all(|sst| sst.dir.is_dir()));
+        assert!(plan.estimated_read_bytes() > 0);
+        assert!(plan.estimated_write_bytes() >= plan.estimated_read_bytes());
+        // planning leaves sstables in place
+        assert_eq!(store.plan_compaction().await.unwrap(), plan);
+
+        store.run_compaction().await.unwrap();
+        assert!(store.plan_compaction().await.unwrap().is_empty());
+        assert!(merge.sstables.iter().all(|sst| !sst.dir.exists()));
+    }
+
+    #[tokio::test]
+    async fn datastore_reads_on_read_pool() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_67");
+        let config = Config {
+            read_pool: Some(crate::fs::ReadPool::new(2).unwrap()),
+            block_cache: crate::block::BlockCache::new(0),
+            ..Default::default()
+        };
+        let mut store = DataStore::open_with_config("test", path, config).await.unwrap();
+        for i in 0..100 {
+            store
+                .put(format!("key_{:03}", i), format!("value_{}", i))
+                .await
+                .unwrap();
+        }
+        store.force_flush().await.unwrap();
+
+        // blocks and values are read on the pool while this runtime has a single thread
+        for i in [0, 50, 99] {
+            let entry = store.get(format!("key_{:03}", i)).await.unwrap().unwrap();
+            assert_eq!(entry.val, format!("value_{}", i).into_bytes());
+        }
+        let mut iter = store.seek("key_010", "key_013").await.unwrap();
+        let mut keys = Vec::new();
+        while let Some(entry) = iter.next().await.unwrap() {
+            keys.push(entry.key);
+        }
+        assert_eq!(
+            keys,
+            vec![b"key_010".to_vec(), b"key_011".to_vec(), b"key_012".to_vec()]
+        );
+        // the pool shuts down with the last store using it
+        drop(iter);
+        drop(store);
+    }
+
+    #[tokio::test]
+    async fn datastore_memtable_entries() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_68");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        store.put("apple", "tim cook").await.unwrap();
+        store.migrate_memtable_to_read_only().await;
+        store.put("apple", "steve jobs").await.unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+        store.delete("google").await.unwrap();
+
+        let mut entries = store.memtable_entries();
+        // memtables keep the latest version of a key, the delete replaced the put
+        assert_eq!(entries.remaining(), 3);
+        let newest = entries.next().unwrap();
+        assert_eq!(
+            (newest.key.as_slice(), newest.read_only),
+            (b"apple".as_slice(), false)
+        );
+        assert_eq!(
+            entries.value(&newest).await.unwrap(),
+            Some(b"steve jobs".to_vec())
+        );
+        let oldest = entries.next().unwrap();
+        assert_eq!(
+            (oldest.key.as_slice(), oldest.read_only),
+            (b"apple".as_slice(), true)
+        );
+        assert_eq!(entries.value(&oldest).await.unwrap(), Some(b"tim cook".to_vec()));
+        let deleted = entries.next().unwrap();
+        assert!(deleted.is_tombstone);
+        assert_eq!(entries.value(&deleted).await.unwrap(), None);
+        assert!(entries.next().is_none());
+
+        // flushed entries are no longer returned
+        store.force_flush().await.unwrap();
+        assert_eq!(store.memtable_entries().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn datastore_expired_keys_from_expiry_index() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_69");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        let ttl = std::time::Duration::from_millis(50);
+        store.put_with_ttl("apple", "tim cook", ttl).await.unwrap();
+        store.put_with_ttl("google", "sundar pichai", ttl).await.unwrap();
+        store.put_with_ttl("nvidia", "jensen huang", ttl).await.unwrap();
+        store
+            .put_with_ttl("openai", "sam altman", ttl * 100)
+            .await
+            .unwrap();
+        store.put("meta", "mark zuckerberg").await.unwrap();
+        assert!(matches!(
+            store
+                .put_with_ttl("meta", "mark", std::time::Duration::ZERO)
+                .await,
+            Err(crate::err::Error::InvalidTtl(_))
+        ));
+        // written again without TTL, deleted and flushed before the TTL passes
+        store.put("google", "larry page").await.unwrap();
+        store.delete("nvidia").await.unwrap();
+        store.force_flush().await.unwrap();
+        assert!(store.expired_keys(10).await.unwrap().is_empty());
+
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert_eq!(store.expired_keys(10).await.unwrap(), vec![b"apple".to_vec()]);
+        assert_eq!(store.get("apple").await.unwrap().unwrap().val, b"tim cook");
+
+        // stale records were dropped, the index survives reopening
+        drop(store);
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        assert_eq!(store.expiry.due(u64::MAX).await.len(), 2);
+        assert_eq!(store.expired_keys(10).await.unwrap(), vec![b"apple".to_vec()]);
+        assert!(store.expired_keys(0).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn datastore_expire_deletes_expired_keys_in_batches() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_70");
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        let mut store = store.with_expiry_batch_size(2);
+        let ttl = std::time::Duration::from_millis(20);
+        for key in ["apple", "google", "nvidia"] {
+            store.put_with_ttl(key, "ceo", ttl).await.unwrap();
+        }
+        store
+            .put_with_ttl("openai", "sam altman", ttl * 1000)
+            .await
+            .unwrap();
+        assert_eq!(store.expire().await.unwrap(), 0);
+
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(store.expire().await.unwrap(), 2);
+        assert_eq!(store.expire().await.unwrap(), 1);
+        assert_eq!(store.expire().await.unwrap(), 0);
+        for key in ["apple", "google", "nvidia"] {
+            assert!(store.get(key).await.unwrap().is_none());
+        }
+        assert!(store.get("openai").await.unwrap().is_some());
+        assert_eq!(store.expiry.due(u64::MAX).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn datastore_expiry_job_stops_with_store() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_71");
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        let store = Arc::new(RwLock::new(
+            store.with_expiry_interval(std::time::Duration::from_millis(5)),
+        ));
+        let job = DataStore::spawn_expiry_job(&store);
+        let ttl = std::time::Duration::from_millis(10);
+        store
+            .write()
+            .await
+            .put_with_ttl("apple", "tim cook", ttl)
+            .await
+            .unwrap();
+
+        let deleted = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while store.read().await.get("apple").await.unwrap().is_some() {
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+        })
+        .await;
+        assert!(deleted.is_ok());
+
+        drop(store);
+        assert!(tokio::time::timeout(std::time::Duration::from_secs(5), job)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn datastore_compaction_drops_versions_past_ttl() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_72");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        store.compactor.config.tombstone_ttl = std::time::Duration::ZERO;
+        let ttl = std::time::Duration::from_millis(20);
+        for i in 0..4 {
+            store
+                .put_with_ttl(format!("session_{}", i), "token", ttl)
+                .await
+                .unwrap();
+            store.put(format!("user_{}", i), "alice").await.unwrap();
+            store.force_flush().await.unwrap();
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        store.run_compaction().await.unwrap();
+
+        for i in 0..4 {
+            assert!(store
+                .latest_version(format!("session_{}", i).as_bytes())
+                .await
+                .unwrap()
+                .is_none());
+            assert!(store.get(format!("user_{}", i)).await.unwrap().is_some());
+        }
+        // records of the dropped versions are stale now
+        assert!(store.expired_keys(10).await.unwrap().is_empty());
+        assert!(store.expiry.due(u64::MAX).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn datastore_transaction_blocks_until_commit() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_73");
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        let store = Arc::new(RwLock::new(store));
+        store.write().await.put("balance", "10").await.unwrap();
+
+        let mut first = DataStore::begin_transaction(&store).await;
+        let balance = first.get_for_update("balance").await.unwrap().unwrap();
+        assert_eq!(balance, b"10");
+
+        let second = tokio::spawn({
+            let store = store.clone();
+            async move {
+                let mut txn = DataStore::begin_transaction(&store).await;
+                let balance = txn.get_for_update("balance").await.unwrap().unwrap();
+                let balance: u32 = std::str::from_utf8(&balance).unwrap().parse().unwrap();
+                txn.put("balance", (balance * 2).to_string()).await.unwrap();
+                txn.commit().await.unwrap();
+            }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!second.is_finished());
+
+        first.put("balance", "15").await.unwrap();
+        assert_eq!(first.get_for_update("balance").await.unwrap().unwrap(), b"15");
+        assert_eq!(first.locked_keys(), 1);
+        first.commit().await.unwrap();
+        second.await.unwrap();
+
+        let entry = store.read().await.get("balance").await.unwrap().unwrap();
+        assert_eq!(entry.val, b"30");
+    }
+
+    #[tokio::test]
+    async fn datastore_transaction_lock_timeout() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_74");
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        let timeout = std::time::Duration::from_millis(20);
+        let store = Arc::new(RwLock::new(store.with_lock_timeout(timeout)));
+
+        let mut first = DataStore::begin_transaction(&store).await;
+        first.lock("apple").await.unwrap();
+        let mut second = DataStore::begin_transaction(&store).await;
+        let err = second.put("apple", "tim cook").await.unwrap_err();
+        assert!(matches!(err, crate::err::Error::LockTimeout { ref key, .. } if key == b"apple"));
+        assert!(err.is_retryable());
+
+        first.rollback();
+        second.put("apple", "tim cook").await.unwrap();
+        second.commit().await.unwrap();
+        let entry = store.read().await.get("apple").await.unwrap().unwrap();
+        assert_eq!(entry.val, b"tim cook");
+    }
+
+    #[tokio::test]
+    async fn datastore_transaction_detects_deadlock() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_75");
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        let store = Arc::new(RwLock::new(store));
+
+        let mut first = DataStore::begin_transaction(&store).await;
+        let mut second = DataStore::begin_transaction(&store).await;
+        first.put("apple", "tim cook").await.unwrap();
+        second.put("google", "sundar pichai").await.unwrap();
+
+        let waiting = tokio::spawn(async move {
+            first.put("google", "larry page").await.unwrap();
+            first.commit().await.unwrap();
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let err = second.put("apple", "steve jobs").await.unwrap_err();
+        assert!(matches!(err, crate::err::Error::Deadlock { ref key } if key == b"apple"));
+        assert!(err.is_retryable());
+
+        second.rollback();
+        waiting.await.unwrap();
+        let store = store.read().await;
+        assert_eq!(store.get("apple").await.unwrap().unwrap().val, b"tim cook");
+        assert_eq!(store.get("google").await.unwrap().unwrap().val, b"larry page");
+    }
+
+    #[tokio::test]
+    async fn datastore_transaction_rollback_discards_writes() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_76");
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        let store = Arc::new(RwLock::new(store));
+        store.write().await.put("apple", "tim cook").await.unwrap();
+
+        let mut txn = DataStore::begin_transaction(&store).await;
+        txn.delete("apple").await.unwrap();
+        assert!(txn.get_for_update("apple").await.unwrap().is_none());
+        txn.put("google", "sundar pichai").await.unwrap();
+        drop(txn);
+
+        let mut txn = DataStore::begin_transaction(&store).await;
+        txn.delete("apple").await.unwrap();
+        txn.rollback();
+
+        let store = store.read().await;
+        assert!(store.get("apple").await.unwrap().is_some());
+        assert!(store.get("google").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn datastore_stats_persist_across_opens() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_77");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        for i in 0..4 {
+            store.put(format!("key_{}", i), "value").await.unwrap();
+            store.force_flush().await.unwrap();
+        }
+        store.delete("key_0").await.unwrap();
+        store.run_compaction().await.unwrap();
+        let stats = store.stats();
+        assert_eq!(stats.total_puts, 4);
+        assert_eq!(stats.open_count, 1);
+        assert!(stats.compaction_bytes > 0);
+        assert!(stats.total_uptime >= stats.uptime);
+        store.persist_stats().await.unwrap();
+        drop(store);
+
+        let store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        let reopened = store.stats();
+        assert_eq!(reopened.total_puts, 4);
+        assert_eq!(reopened.compaction_bytes, stats.compaction_bytes);
+        assert_eq!(reopened.open_count, 2);
+        assert!(reopened.total_uptime >= stats.total_uptime);
+        drop(store);
+
+        // a corrupt stats file is replaced rather than failing the open
+        std::fs::write(path.join(crate::consts::STATS_FILE_NAME), b"{").unwrap();
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        assert_eq!(store.stats().total_puts, 0);
+        assert_eq!(store.stats().open_count, 1);
+    }
+
+    #[tokio::test]
+    async fn datastore_describe_buckets_reports_compaction_threshold() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_78");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        assert!(store.describe_buckets().await.unwrap().is_empty());
+
+        for i in 0..3 {
+            store.put(format!("key_{}", i), "value").await.unwrap();
+            store.force_flush().await.unwrap();
+        }
+        let reports = store.describe_buckets().await.unwrap();
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.sstable_count, 3);
+        assert_eq!(report.threshold, 4);
+        assert!(report.average_size > 0);
+        assert!(report.dir.ends_with(format!("bucket{}", report.id)));
+        assert!(report.hotness.min <= report.hotness.median && report.hotness.median <= report.hotness.max);
+        assert!(!report.needs_compaction);
+        assert!(store.plan_compaction().await.unwrap().is_empty());
+
+        store.put("key_3", "value").await.unwrap();
+        store.force_flush().await.unwrap();
+        let reports = store.describe_buckets().await.unwrap();
+        assert_eq!(reports[0].sstable_count, 4);
+        assert!(reports[0].needs_compaction);
+        assert_eq!(
+            store.plan_compaction().await.unwrap().merges[0].bucket,
+            reports[0].id
+        );
+    }
+
+    #[tokio::test]
+    async fn datastore_delete_range_survives_flush_restart_and_compaction() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_79");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        for i in 0..10 {
+            store.put(format!("user:{}", i), "value").await.unwrap();
+        }
+        store.put("vendor:1", "nvidia").await.unwrap();
+        store.force_flush().await.unwrap();
+
+        store.delete_range("user:", "user;").await.unwrap();
+        store.put("user:3", "new").await.unwrap();
+        // an empty range deletes nothing
+        store.delete_range("vendor:2", "vendor:1").await.unwrap();
+        async fn assert_deleted(store: &DataStore<'static, Vec<u8>>) {
+            assert!(store.get("user:0").await.unwrap().is_none());
+            assert!(store.get("user:9").await.unwrap().is_none());
+            assert_eq!(store.get("user:3").await.unwrap().unwrap().val, b"new");
+            assert_eq!(store.get("vendor:1").await.unwrap().unwrap().val, b"nvidia");
+            let mut iter = store.seek("user:", "user;").await.unwrap();
+            assert_eq!(iter.next().await.unwrap().unwrap().key, b"user:3".to_vec());
+            assert!(iter.next().await.unwrap().is_none());
+        }
+        assert_deleted(&store).await;
+
+        // the tombstone moves to the summary of the flushed sstable
+        store.force_flush().await.unwrap();
+        assert_deleted(&store).await;
+        drop(store);
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        assert_deleted(&store).await;
+
+        for i in 0..2 {
+            store.put(format!("other:{}", i), "value").await.unwrap();
+            store.force_flush().await.unwrap();
+        }
+        store.run_compaction().await.unwrap();
+        let mut keys = Vec::new();
+        for bucket in store.buckets.read().await.buckets.values() {
+            for sst in bucket.sstables.read().await.iter() {
+                let mut table = sst.to_owned();
+                table.load_entries_from_file().await.unwrap();
+                keys.extend(table.entries.iter().map(|e| e.key().to_owned()));
+            }
+        }
+        assert!(!keys.contains(&b"user:0".to_vec()));
+        assert!(keys.contains(&b"user:3".to_vec()));
+        assert_deleted(&store).await;
+        drop(store);
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        assert_deleted(&store).await;
+    }
+
+    #[tokio::test]
+    async fn datastore_sstables_named_by_file_number() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_80");
+        let buckets_dir = path.join("buckets");
+        async fn sstables(store: &DataStore<'static, Vec<u8>>) -> Vec<(u64, PathBuf)> {
+            let mut sstables = Vec::new();
+            for bucket in store.buckets.read().await.buckets.values() {
+                for sst in bucket.sstables.read().await.iter() {
+                    sstables.push((sst.file_number, sst.dir.to_owned()));
+                }
+            }
+            sstables.sort();
+            sstables
+        }
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        for key in ["apple", "banana", "cherry"] {
+            store.put(key, "fruit").await.unwrap();
+            store.force_flush().await.unwrap();
+            // versions on disk keep milliseconds only
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+        let written = sstables(&store).await;
+        let names: Vec<_> = written.iter().map(|(_, dir)| dir.file_name().unwrap()).collect();
+        assert_eq!(names, ["sstable_000001", "sstable_000002", "sstable_000003"]);
+        drop(store);
+
+        // older versions named sstables by creation time and counted no file numbers
+        let mut manifest = crate::bucket::manifest::BucketManifest::read(&buckets_dir)
+            .await
+            .unwrap()
+            .unwrap();
+        manifest.next_file_number = 0;
+        let mut legacy = Vec::new();
+        for (i, (_, dir)) in written.iter().enumerate() {
+            let name = format!("sstable_{}", 1720785463919 - i as u64);
+            std::fs::rename(dir, dir.with_file_name(&name)).unwrap();
+            for bucket in manifest.buckets.iter_mut() {
+                for sst in bucket.sstables.iter_mut() {
+                    if dir.ends_with(&sst.name) {
+                        sst.name = name.to_owned();
+                        sst.number = None;
+                    }
+                }
+            }
+            legacy.push(dir.with_file_name(name));
+        }
+        manifest.write(&buckets_dir).await.unwrap();
+
+        // legacy sstables keep their names and are numbered in order of creation
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        assert!(store.layout_issues().is_empty());
+        let expected: Vec<_> = (1..).zip(legacy).collect();
+        assert_eq!(sstables(&store).await, expected);
+        store.put("durian", "fruit").await.unwrap();
+        store.force_flush().await.unwrap();
+        let (number, dir) = sstables(&store).await.pop().unwrap();
+        assert_eq!(number, 4);
+        assert_eq!(dir.file_name().unwrap(), "sstable_000004");
+        drop(store);
+
+        let manifest = crate::bucket::manifest::BucketManifest::read(&buckets_dir)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(manifest.next_file_number, 5);
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        assert_eq!(sstables(&store).await.len(), 4);
+        for key in ["apple", "banana", "cherry", "durian"] {
+            assert_eq!(store.get(key).await.unwrap().unwrap().val, b"fruit");
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_seek_streams_entries_across_sstables() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_81");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        let mut expected = std::collections::BTreeMap::new();
+        for round in 0..3 {
+            for i in (round..1500).step_by(2) {
+                let (key, val) = (format!("key_{:04}", i), format!("val_{}_{}", round, i));
+                store.put(&key, &val).await.unwrap();
+                expected.insert(key.into_bytes(), val.into_bytes());
+            }
+            store.force_flush().await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+        for i in (0..1500).step_by(7) {
+            let key = format!("key_{:04}", i);
+            store.delete(&key).await.unwrap();
+            expected.remove(key.as_bytes());
+        }
+        store.delete_range("key_0100", "key_0200").await.unwrap();
+        expected.retain(|key, _| !(b"key_0100".as_slice()..b"key_0200".as_slice()).contains(&key.as_slice()));
+        store.put("key_0150", "new").await.unwrap();
+        expected.insert(b"key_0150".to_vec(), b"new".to_vec());
+        store.put("key_9999", "outside").await.unwrap();
+
+        let mut iter = store.seek("key_0000", "key_1500").await.unwrap();
+        // stream and iterator share the position of the scan
+        let (key, _) = StreamExt::next(&mut iter).await.unwrap().unwrap();
+        assert_eq!(key, b"key_0001".to_vec());
+        assert_eq!(iter.next().await.unwrap().unwrap().key, b"key_0002".to_vec());
+        assert_eq!(
+            iter.checkpoint().unwrap(),
+            ContinuationToken::after(b"key_0002".to_vec())
+        );
+        // writes after seek are not visible
+        store.put("key_0003", "after seek").await.unwrap();
+        let rest: Vec<(Vec<u8>, Vec<u8>)> = iter.try_collect().await.unwrap();
+        let expected: Vec<_> = expected.into_iter().skip(2).collect();
+        assert_eq!(rest, expected);
+    }
+
+    #[tokio::test]
+    async fn datastore_seek_reads_write_ahead_log_entries_cleared_after_seek() {
+        setup();
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_82");
+        let config = Config {
+            wal_max_value_size: Some(16),
+            ..Default::default()
+        };
+        let mut store = DataStore::open_with_config("test", path, config).await.unwrap();
+        store.put("apple", "tim cook").await.unwrap();
+        let mut iter = store.seek("a", "b").await.unwrap();
+        let offset = store
+            .active_memtable
+            .entries
+            .get(b"apple".as_slice())
+            .unwrap()
+            .value()
+            .val_offset;
+
+        // metadata is kept in the value log only, the write-ahead log is cleared first
+        store
+            .put_with_metadata("google", "sundar pichai", "ceo")
+            .await
+            .unwrap();
+        store.put("tesla", "elon musk").await.unwrap();
+        let entry = iter.next().await.unwrap().unwrap();
+        assert_eq!((entry.key, entry.val), (b"apple".to_vec(), b"tim cook".to_vec()));
+        assert!(iter.next().await.unwrap().is_none());
+        // the offset of the cleared entry reads nothing, not the entry now at its position
+        assert!(store.val_log.get_entry(offset).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn datastore_transaction_checks_quota_before_writing() {
+        setup();
+        let root = tempdir().unwrap();
+        let mut config = Config::default();
+        config.keyspace_quotas.insert(
+            "tenant".to_string(),
+            KeyspaceQuota {
+                max_keys: Some(2),
+                ..Default::default()
+            },
+        );
+        let store = DataStore::open_with_config("tenant", root.path().join("store_test_83"), config)
+            .await
+            .unwrap();
+        let store = Arc::new(RwLock::new(store));
+        store.write().await.put("apple", "tim cook").await.unwrap();
+        store.write().await.put("google", "sundar pichai").await.unwrap();
+
+        let mut txn = DataStore::begin_transaction(&store).await;
+        txn.put("apple", "steve jobs").await.unwrap();
+        txn.put("nvidia", "jensen huang").await.unwrap();
+        assert!(matches!(
+            txn.commit().await,
+            Err(crate::err::Error::QuotaExceeded { unit: "keys", .. })
+        ));
+        let entry = store.read().await.get("apple").await.unwrap().unwrap();
+        assert_eq!(entry.val, b"tim cook");
+
+        // the deleted key makes room for the new one
+        let mut txn = DataStore::begin_transaction(&store).await;
+        txn.put("nvidia", "jensen huang").await.unwrap();
+        txn.delete("google").await.unwrap();
+        txn.commit().await.unwrap();
+        let store = store.read().await;
+        assert!(store.get("google").await.unwrap().is_none());
+        assert!(store.get("nvidia").await.unwrap().is_some());
+        assert_eq!(store.keyspace_stats().await.unwrap().keys, 2);
+    }
+
+    #[tokio::test]
+    async fn datastore_compaction_keeps_journal_of_unfinished_compaction() {
+        use crate::compactors::journal::CompactionJournal;
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_84");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        for i in 0..crate::consts::MIN_TRESHOLD {
+            store.put(format!("key_{}", i), "value").await.unwrap();
+            store.force_flush().await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+        let inputs: Vec<PathBuf> = store.key_range.key_ranges.read().await.keys().cloned().collect();
+        let buckets_dir = store.buckets.read().await.dir.to_owned();
+        let mut journal = CompactionJournal::new(inputs[..1].to_vec());
+        journal.outputs = vec![buckets_dir.join("bucket_lost").join("sstable_999999")];
+        journal.write(&buckets_dir).await.unwrap();
+
+        assert!(matches!(
+            store.run_compaction().await,
+            Err(crate::err::Error::CompactionJournalPending { .. })
+        ));
+        assert_eq!(
+            CompactionJournal::read(&buckets_dir).await.unwrap(),
+            Some(journal)
+        );
+        assert_eq!(store.key_range.key_ranges.read().await.len(), inputs.len());
+        drop(store);
+
+        // the journal is resolved at open, compaction runs again
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        store.run_compaction().await.unwrap();
+        assert_eq!(store.key_range.key_ranges.read().await.len(), 1);
+    }
+
+    #[cfg(feature = "fault-injection")]
+    #[tokio::test]
+    async fn datastore_failed_compaction_removes_unlisted_output() {
+        use crate::compactors::journal::CompactionJournal;
+        use crate::fault::{self, Fault, FaultRule, Operation};
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_85");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        for i in 0..crate::consts::MIN_TRESHOLD {
+            store.put(format!("key_{}", i), "value").await.unwrap();
+            store.force_flush().await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+        let inputs: Vec<PathBuf> = store.key_range.key_ranges.read().await.keys().cloned().collect();
+        let buckets_dir = store.buckets.read().await.dir.to_owned();
+        let sstable_dirs = |buckets_dir: &std::path::Path| {
+            let mut dirs = Vec::new();
+            for bucket in std::fs::read_dir(buckets_dir).unwrap() {
+                for sst in std::fs::read_dir(bucket.unwrap().path()).unwrap() {
+                    dirs.push(sst.unwrap().path());
+                }
+            }
+            dirs
+        };
+
+        // the merged sstable fails to be written
+        fault::inject(FaultRule::new(
+            &buckets_dir,
+            Operation::Write,
+            Fault::Error(std::io::ErrorKind::Other),
+        ));
+        assert!(store.run_compaction().await.is_err());
+        fault::clear(&buckets_dir);
+        assert!(CompactionJournal::read(&buckets_dir).await.unwrap().is_none());
+        assert_eq!(sstable_dirs(&buckets_dir).len(), inputs.len());
+        for i in 0..crate::consts::MIN_TRESHOLD {
+            assert!(store.get(format!("key_{}", i)).await.unwrap().is_some());
+        }
+
+        store.run_compaction().await.unwrap();
+        assert_eq!(sstable_dirs(&buckets_dir).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn datastore_skips_sstable_written_before_crash() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_86");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        store.put("apple", "tim cook").await.unwrap();
+        store.force_flush().await.unwrap();
+        store.put("banana", "split").await.unwrap();
+        let buckets_dir = store.dir.buckets.to_owned();
+        let manifest_path = crate::bucket::manifest::BucketManifest::path(&buckets_dir);
+        let meta_path = store.meta.file_handle.file.node.file_path.to_owned();
+        let manifest = std::fs::read(&manifest_path).unwrap();
+        let meta = std::fs::read(&meta_path).unwrap();
+        let sstable_dirs = |buckets_dir: &std::path::Path| {
+            let mut dirs = Vec::new();
+            for bucket in std::fs::read_dir(buckets_dir).unwrap() {
+                for sst in std::fs::read_dir(bucket.unwrap().path()).unwrap() {
+                    dirs.push(sst.unwrap().path());
+                }
+            }
+            dirs
+        };
+        let listed = sstable_dirs(&buckets_dir);
+        store.force_flush().await.unwrap();
+        let unlisted = sstable_dirs(&buckets_dir)
+            .into_iter()
+            .find(|dir| !listed.contains(dir))
+            .unwrap();
+        drop(store);
+
+        // crash after the sstable is written, before the manifest and meta are
+        std::fs::write(&manifest_path, manifest).unwrap();
+        std::fs::write(&meta_path, meta).unwrap();
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.layout_issues(),
+            &[LayoutIssue::UnlistedSSTable(unlisted.to_owned())]
+        );
+        assert!(unlisted.is_dir());
+        assert_eq!(store.get("banana").await.unwrap().unwrap().val, b"split".to_vec());
+
+        // the next sstable does not reuse the directory left by the crash
+        store.put("cherry", "pie").await.unwrap();
+        store.force_flush().await.unwrap();
+        assert_eq!(sstable_dirs(&buckets_dir).len(), 3);
+        drop(store);
+        let store = DataStore::open_without_background("test", path).await.unwrap();
+        for (key, val) in [("apple", "tim cook"), ("banana", "split"), ("cherry", "pie")] {
+            assert_eq!(
+                store.get(key).await.unwrap().unwrap().val,
+                val.as_bytes().to_vec()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_compaction_drops_cached_blocks_of_removed_sstables() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_87");
+        let mut store = DataStore::open_without_background("test", path.to_owned())
+            .await
+            .unwrap();
+        for i in 0..crate::consts::MIN_TRESHOLD {
+            store.put(format!("key_{}", i), "value").await.unwrap();
+            store.force_flush().await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+        for i in 0..crate::consts::MIN_TRESHOLD {
+            store.get(format!("key_{}", i)).await.unwrap().unwrap();
+        }
+        let block_cache = store.config.block_cache.clone();
+        let inputs: Vec<PathBuf> = store.key_range.key_ranges.read().await.keys().cloned().collect();
+        assert_eq!(block_cache.residency().len(), inputs.len());
+
+        store.run_compaction().await.unwrap();
+        assert!(inputs.iter().all(|dir| !dir.exists()));
+        assert!(block_cache.residency().is_empty());
+        assert_eq!(block_cache.usage(), 0);
+        for i in 0..crate::consts::MIN_TRESHOLD {
+            store.get(format!("key_{}", i)).await.unwrap().unwrap();
+        }
+        assert!(block_cache.residency().keys().all(|path| path.exists()));
+    }
+
+    async fn flush_keys(store: &mut DataStore<'static, crate::types::Key>, keys: std::ops::Range<usize>) {
+        for i in keys {
+            store.put(format!("key_{:08}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn datastore_wait_for_compactions_cascades_into_next_bucket() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_88");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        let sstable_counts = |buckets: &crate::bucket::BucketMap| {
+            let mut counts: Vec<usize> = buckets
+                .buckets
+                .values()
+                .map(|bucket| bucket.sstables.try_read().unwrap().len())
+                .collect();
+            counts.sort();
+            counts
+        };
+        // small sstables merge into one past the small sstable size, one bucket up
+        let mut keys = 0;
+        for _ in 0..crate::consts::MIN_TRESHOLD - 1 {
+            for _ in 0..crate::consts::MIN_TRESHOLD {
+                flush_keys(&mut store, keys..keys + 60).await;
+                keys += 60;
+            }
+            store.run_compaction().await.unwrap();
+        }
+        for _ in 0..crate::consts::MIN_TRESHOLD {
+            flush_keys(&mut store, keys..keys + 60).await;
+            keys += 60;
+        }
+        assert_eq!(
+            sstable_counts(&*store.buckets.read().await),
+            vec![crate::consts::MIN_TRESHOLD - 1, crate::consts::MIN_TRESHOLD]
+        );
+
+        // merging the small sstables fills the next bucket past the threshold
+        store.wait_for_compactions().await.unwrap();
+        assert_eq!(sstable_counts(&*store.buckets.read().await), vec![1]);
+        assert!(store.buckets.read().await.is_balanced().await);
+        for i in 0..keys {
+            assert!(store.get(format!("key_{:08}", i)).await.unwrap().is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_wait_for_compactions_dropped_mid_compaction() {
+        use crate::compactors::CompState;
+        let root = tempdir().unwrap();
+        let path = root.path().join("store_test_89");
+        let mut store = DataStore::open_without_background("test", path).await.unwrap();
+        for i in 0..crate::consts::MIN_TRESHOLD {
+            flush_keys(&mut store, i * 60..(i + 1) * 60).await;
+        }
+        let res =
+            tokio::time::timeout(std::time::Duration::from_nanos(1), store.wait_for_compactions()).await;
+        assert!(res.is_err());
+
+        // compactor is put back to sleep, so background workers compact again
+        let mut state = CompState::Active;
+        for _ in 0..100 {
+            state = store.compactor.is_active.lock().await.to_owned();
+            if state == CompState::Sleep {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(state, CompState::Sleep);
+    }
 }
@@ -1,8 +1,9 @@
 #[cfg(test)]
 mod tests {
-    use crate::consts::{SIZE_OF_U32, SUMMARY_FILE_NAME};
+    use crate::consts::{SIZE_OF_U32, SIZE_OF_U64, SUMMARY_FILE_NAME};
     use crate::sst::Summary;
     use crate::tests::workload::SSTContructor;
+    use crate::util;
     use tempfile::tempdir;
 
     #[tokio::test]
@@ -12,8 +13,8 @@ mod tests {
 
         let summary = Summary::new(path.to_owned());
 
-        assert_eq!(summary.smallest_key, vec![]);
-        assert_eq!(summary.biggest_key, vec![]);
+        assert_eq!(summary.smallest_key, Vec::<u8>::new());
+        assert_eq!(summary.biggest_key, Vec::<u8>::new());
         assert_eq!(summary.path, path.join(format!("{}.db", SUMMARY_FILE_NAME)));
     }
 
@@ -31,8 +32,14 @@ mod tests {
     #[tokio::test]
     async fn test_summary_write() {
         let sst = SSTContructor::generate_ssts(1).await[0].to_owned();
+        // the summary is rewritten in a copy, the fixture stays as checked in
+        let root = tempdir().unwrap();
+        let path = root.path().join("summary_rewrite");
+        std::fs::create_dir_all(&path).unwrap();
+        let file_name = format!("{}.db", SUMMARY_FILE_NAME);
+        std::fs::copy(sst.dir.join(&file_name), path.join(&file_name)).unwrap();
 
-        let mut recovered_summary = Summary::new(sst.dir.to_owned());
+        let mut recovered_summary = Summary::new(path);
         let res = recovered_summary.recover().await;
         assert!(res.is_ok());
         assert!(recovered_summary.write_to_file().await.is_ok())
@@ -47,10 +54,62 @@ mod tests {
         summary.biggest_key = vec![1, 2, 3];
         summary.smallest_key = vec![0, 2, 3];
 
-        let expected_entry_len =
-            SIZE_OF_U32 + SIZE_OF_U32 + summary.biggest_key.len() + summary.smallest_key.len();
+        let expected_entry_len = SIZE_OF_U32
+            + SIZE_OF_U32
+            + summary.biggest_key.len()
+            + summary.smallest_key.len()
+            + SIZE_OF_U32
+            + SIZE_OF_U64 * 3;
         let serialized_entry = summary.serialize();
 
         assert_eq!(serialized_entry.len(), expected_entry_len);
     }
+
+    #[tokio::test]
+    async fn test_summary_recover_stats() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("summary_stats");
+        tokio::fs::create_dir_all(&path).await.unwrap();
+
+        let mut summary = Summary::new(path.to_owned());
+        summary.smallest_key = b"apple".to_vec();
+        summary.biggest_key = b"tesla".to_vec();
+        summary.entry_count = 42;
+        summary.created_at_range = Some((
+            util::milliseconds_to_datetime(1_000),
+            util::milliseconds_to_datetime(2_000),
+        ));
+        summary.write_to_file().await.unwrap();
+
+        let mut recovered_summary = Summary::new(path);
+        recovered_summary.recover().await.unwrap();
+        assert_eq!(recovered_summary.smallest_key, b"apple".to_vec());
+        assert_eq!(recovered_summary.biggest_key, b"tesla".to_vec());
+        assert_eq!(recovered_summary.entry_count, 42);
+        assert_eq!(recovered_summary.created_at_range, summary.created_at_range);
+    }
+
+    #[tokio::test]
+    async fn test_summary_recover_without_stats() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("summary_old");
+        tokio::fs::create_dir_all(&path).await.unwrap();
+
+        // summary layout of older versions, keys only
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&5u32.to_le_bytes());
+        bytes.extend_from_slice(&5u32.to_le_bytes());
+        bytes.extend_from_slice(b"apple");
+        bytes.extend_from_slice(b"tesla");
+        tokio::fs::write(path.join(format!("{}.db", SUMMARY_FILE_NAME)), bytes)
+            .await
+            .unwrap();
+
+        let mut recovered_summary = Summary::new(path);
+        recovered_summary.recover().await.unwrap();
+        assert_eq!(recovered_summary.smallest_key, b"apple".to_vec());
+        assert_eq!(recovered_summary.biggest_key, b"tesla".to_vec());
+        assert_eq!(recovered_summary.entry_count, 0);
+        assert!(recovered_summary.created_at_range.is_none());
+    }
 }
@@ -1,8 +1,13 @@
 #[cfg(test)]
 mod tests {
-    use crate::consts::{SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8};
-    use crate::vlog::{ValueLog, ValueLogEntry};
+    use crate::consts::{
+        SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8, VLOG_CHECKSUM_FLAG, VLOG_METADATA_FLAG, VLOG_TOMBSTONE_FLAG,
+    };
+    use crate::err::Error;
+    use crate::fs::FileAsync;
+    use crate::vlog::{ValueKind, ValueLog, ValueLogEntry};
     use chrono::Utc;
+    use std::io::{Seek, SeekFrom, Write};
     use tempfile::tempdir;
 
     #[tokio::test]
@@ -37,6 +42,60 @@ mod tests {
         assert!(offset.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_append_batch() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("vlog_append_batch");
+
+        let mut vlog = ValueLog::new(path).await.unwrap();
+        let first = vlog.append("key0", "val0", Utc::now(), false).await.unwrap();
+        let entries = vec![
+            ("key1", "val1", Utc::now(), false),
+            ("key2", "val2", Utc::now(), true),
+        ];
+        let offsets = vlog.append_batch(&entries).await.unwrap();
+        assert_eq!(offsets.len(), 2);
+        assert!(offsets[0] > first);
+        assert_eq!(
+            vlog.get(offsets[0]).await.unwrap().unwrap(),
+            (b"val1".to_vec(), false)
+        );
+        assert_eq!(
+            vlog.get(offsets[1]).await.unwrap().unwrap(),
+            (b"val2".to_vec(), true)
+        );
+        assert_eq!(vlog.content.file.node.size().await, vlog.size);
+
+        let empty: Vec<(&str, &str, _, bool)> = Vec::new();
+        assert!(vlog.append_batch(&empty).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_append_with_preallocation() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("vlog_preallocate");
+        let extent = 64 * 1024;
+
+        let mut vlog = ValueLog::new(path)
+            .await
+            .unwrap()
+            .with_preallocation(Some(extent));
+        let offset1 = vlog.append("key1", "val1", Utc::now(), false).await.unwrap();
+        let offset2 = vlog.append("key2", "val2", Utc::now(), false).await.unwrap();
+        // preallocation must not change the logical size of the log
+        assert_eq!(vlog.content.file.node.size().await, vlog.size);
+        assert_eq!(vlog.get(offset1).await.unwrap().unwrap().0, b"val1".to_vec());
+        assert_eq!(vlog.get(offset2).await.unwrap().unwrap().0, b"val2".to_vec());
+
+        #[cfg(target_os = "linux")]
+        if vlog.preallocation_extent.is_some() {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(vlog.preallocated_to, extent);
+            let meta = vlog.content.file.node.metadata().await.unwrap();
+            assert!(meta.blocks() * 512 >= extent as u64);
+        }
+    }
+
     #[tokio::test]
     async fn test_get() {
         let root = tempdir().unwrap();
@@ -227,4 +286,152 @@ mod tests {
 
         assert_eq!(serialized_entry.len(), expected_entry_len);
     }
+
+    #[tokio::test]
+    async fn test_get_verified() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("vlog_verify");
+
+        let mut vlog = ValueLog::new(path).await.unwrap().with_checksums(true);
+        let time = Utc::now();
+        let offset1 = vlog.append("key1", "val1", time, false).await.unwrap();
+        let offset2 = vlog.append("key2", "val2", time, true).await.unwrap();
+
+        let entry = vlog.get_entry(offset2).await.unwrap().unwrap();
+        assert!(entry.is_tombstone);
+        assert!(entry.checksum.is_some());
+        assert_eq!(offset2 - offset1, entry.encoded_len());
+
+        let res = vlog.get_verified(b"key1", offset1).await.unwrap();
+        assert_eq!(res, Some((b"val1".to_vec(), false)));
+
+        let res = vlog.get_verified(b"key2", offset1).await;
+        assert!(matches!(res, Err(Error::EntryKeyMismatch { offset }) if offset == offset1));
+
+        // flip a byte of the first value
+        let value_offset = offset1 + SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8 + "key1".len();
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&vlog.content.path)
+            .unwrap();
+        file.seek(SeekFrom::Start(value_offset as u64)).unwrap();
+        file.write_all(b"X").unwrap();
+
+        let res = vlog.get_verified(b"key1", offset1).await;
+        assert!(matches!(res, Err(Error::ChecksumMismatch { offset }) if offset == offset1));
+        // unverified reads don't notice
+        assert_eq!(vlog.get(offset1).await.unwrap().unwrap().0, b"Xal1".to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_vlog_entry_with_checksum_serialize() {
+        let key = "test_key";
+        let val = "test_val";
+        let entry = ValueLogEntry::new(key.len(), val.len(), key, val, Utc::now(), true).with_checksum();
+
+        let serialized_entry = entry.serialize();
+
+        assert_eq!(serialized_entry.len(), entry.encoded_len());
+        assert_eq!(
+            serialized_entry[SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64],
+            VLOG_TOMBSTONE_FLAG | VLOG_CHECKSUM_FLAG
+        );
+        assert!(entry.verify(key.as_bytes(), 0).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_vlog_entry_with_metadata() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("vlog_metadata");
+
+        let mut vlog = ValueLog::new(path).await.unwrap().with_checksums(true);
+        let time = Utc::now();
+        let offset1 = vlog
+            .append_with_metadata("key1", "val1", Some(b"text/plain"), time)
+            .await
+            .unwrap();
+        let offset2 = vlog
+            .append_with_metadata("key2", "val2", Some(b""), time)
+            .await
+            .unwrap();
+        let offset3 = vlog.append("key3", "val3", time, false).await.unwrap();
+
+        let entry = vlog.get_entry(offset1).await.unwrap().unwrap();
+        assert_eq!(entry.metadata, Some(b"text/plain".to_vec()));
+        assert_eq!(offset2 - offset1, entry.encoded_len());
+        let serialized = entry.serialize();
+        assert_ne!(serialized[SIZE_OF_U32 * 2 + SIZE_OF_U64] & VLOG_METADATA_FLAG, 0);
+        assert_eq!(
+            ValueLogEntry::deserialize(&serialized),
+            Some((entry, serialized.len()))
+        );
+
+        let entry = vlog.get_entry(offset2).await.unwrap().unwrap();
+        assert_eq!(entry.metadata, Some(vec![]));
+        assert_eq!(offset3 - offset2, entry.encoded_len());
+        assert_eq!(vlog.get_entry(offset3).await.unwrap().unwrap().metadata, None);
+
+        // checksum covers metadata
+        let metadata_offset = offset1 + entry_header_len() + "key1".len() + "val1".len() + SIZE_OF_U8;
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&vlog.content.path)
+            .unwrap();
+        file.seek(SeekFrom::Start(metadata_offset as u64)).unwrap();
+        file.write_all(b"X").unwrap();
+        let res = vlog.get_verified(b"key1", offset1).await;
+        assert!(matches!(res, Err(Error::ChecksumMismatch { offset }) if offset == offset1));
+    }
+
+    #[tokio::test]
+    async fn test_append_with_dedup() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("vlog_dedup");
+        let value = vec![b'v'; 128];
+        let other = vec![b'o'; 128];
+
+        let mut vlog = ValueLog::new(&path)
+            .await
+            .unwrap()
+            .with_checksums(true)
+            .with_dedup(Some(64));
+        let time = Utc::now();
+        let offset1 = vlog.append(&b"key1"[..], &value, time, false).await.unwrap();
+        let size = vlog.size;
+        let offset2 = vlog.append(&b"key2"[..], &value, time, false).await.unwrap();
+        // second write only appends a reference
+        assert!(vlog.size - size < value.len());
+        // identical values of one batch share a blob too
+        let offsets = vlog
+            .append_batch(&[
+                (&b"key3"[..], &other[..], time, false),
+                (&b"key4"[..], &other[..], time, false),
+                (&b"key5"[..], &b"small"[..], time, false),
+            ])
+            .await
+            .unwrap();
+
+        let entry = vlog.get_entry(offset2).await.unwrap().unwrap();
+        assert_eq!(entry.kind, ValueKind::Reference);
+        assert_eq!(entry.value, value);
+        assert_eq!(vlog.get(offset1).await.unwrap(), Some((value.clone(), false)));
+        assert_eq!(vlog.get(offsets[1]).await.unwrap(), Some((other.clone(), false)));
+        assert_eq!(
+            vlog.get_entry(offsets[2]).await.unwrap().unwrap().kind,
+            ValueKind::Inline
+        );
+        let res = vlog.get_verified(b"key4", offsets[1]).await.unwrap();
+        assert_eq!(res, Some((other.clone(), false)));
+
+        // index is rebuilt from the value log
+        let reopened = ValueLog::new(&path).await.unwrap().with_dedup(Some(64));
+        reopened.rebuild_dedup_index().await.unwrap();
+        assert_eq!(reopened.get(offset2).await.unwrap(), Some((value, false)));
+        let res = ValueLog::new(&path).await.unwrap().get(offset2).await;
+        assert!(matches!(res, Err(Error::BlobNotFound { offset }) if offset == offset2));
+    }
+
+    fn entry_header_len() -> usize {
+        SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8
+    }
 }
@@ -0,0 +1,229 @@
+use crate::{
+    consts::{
+        BUCKETS_DIRECTORY_NAME, DATA_FILE_NAME, DEFAULT_FALSE_POSITIVE_RATE, FILTER_FILE_NAME,
+        FORMAT_VERSION, INDEX_FILE_NAME, META_DIRECTORY_NAME, META_FILE_NAME, MIGRATION_FILE_NAME,
+    },
+    db::StoreInfo,
+    err::Error,
+    filter::BloomFilter,
+    fs::{FileNode, FilterFileNode, FilterFs},
+    index::SparseIndex,
+    meta::Meta,
+    open_dir_stream,
+    sst::{Summary, Table},
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::fs::{self, read_dir};
+use Error::*;
+
+/// Progress of an interrupted migration, kept in the store root
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Journal {
+    target_version: u32,
+
+    /// SSTable directories already rewritten
+    done: Vec<PathBuf>,
+}
+
+impl Journal {
+    async fn read(path: &Path, target_version: u32) -> Result<Self, Error> {
+        let buf = match fs::read(path).await {
+            Ok(buf) => buf,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self {
+                    target_version,
+                    done: Vec::new(),
+                })
+            }
+            Err(err) => {
+                return Err(FileOpen {
+                    path: path.to_path_buf(),
+                    error: err,
+                })
+            }
+        };
+        let journal: Self = serde_json::from_slice(&buf).map_err(|err| StoreInfoCorrupt {
+            path: path.to_path_buf(),
+            error: err,
+        })?;
+        // rewritten tables are only known to be current for the same target
+        if journal.target_version != target_version {
+            return Ok(Self {
+                target_version,
+                done: Vec::new(),
+            });
+        }
+        Ok(journal)
+    }
+
+    async fn write(&self, path: &Path) -> Result<(), Error> {
+        let buf = serde_json::to_vec(self).map_err(|_| Serialization("migration journal"))?;
+        FileNode::write_atomic(path, &buf).await
+    }
+}
+
+/// Rewrites files of the store at `path` into the layout of format `target_version`
+///
+/// SSTables written by older versions may lack the index checksum, filter bits
+/// and checksum or summary stats and prefix sketch. Their index, summary and filter are rebuilt
+/// from the data file and replaced atomically. The value log layout has not
+/// changed since format 1, so it is left as is.
+///
+/// Finished SSTables are recorded in a journal in the store root, running the
+/// migration again after a crash resumes with the remaining ones. The store-info
+/// file only records `target_version` once every SSTable is rewritten.
+///
+/// The store must not be open while it is migrated.
+///
+/// # Errors
+///
+/// Returns `Error::IncompatibleFormat` if the store uses a newer format than
+/// this build, `Error::UnsupportedMigration` if `target_version` is older than
+/// the store's format or newer than this build's
+pub async fn migrate(path: impl AsRef<Path>, target_version: u32) -> Result<StoreInfo, Error> {
+    let root = path.as_ref();
+    let mut info = match StoreInfo::read(root).await? {
+        Some(info) => info,
+        None => legacy_info(root).await?,
+    };
+    info.check_supported(root)?;
+    if target_version < info.format_version || target_version > FORMAT_VERSION {
+        return Err(UnsupportedMigration {
+            from: info.format_version,
+            to: target_version,
+        });
+    }
+    let journal_path = root.join(MIGRATION_FILE_NAME);
+    if info.format_version == target_version {
+        if !StoreInfo::path(root).exists() {
+            info.write(root).await?;
+        }
+        return Ok(info);
+    }
+
+    log::info!(
+        "Migrating store at {:?} from format {} to {}",
+        root,
+        info.format_version,
+        target_version
+    );
+    let mut journal = Journal::read(&journal_path, target_version).await?;
+    let sst_dirs = list_sstables(root, info.cold_storage_dir.as_deref()).await?;
+    for sst_dir in sst_dirs {
+        if journal.done.contains(&sst_dir) {
+            continue;
+        }
+        rewrite_sstable(&sst_dir).await?;
+        journal.done.push(sst_dir);
+        journal.write(&journal_path).await?;
+    }
+
+    info.format_version = target_version;
+    info.engine_version = env!("CARGO_PKG_VERSION").to_string();
+    info.write(root).await?;
+    match fs::remove_file(&journal_path).await {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(FileDelete {
+            path: journal_path,
+            error,
+        }),
+        _ => Ok(info),
+    }
+}
+
+/// Returns `StoreInfo` of a store created before the store-info file existed
+async fn legacy_info(root: &Path) -> Result<StoreInfo, Error> {
+    let mut info = StoreInfo::legacy();
+    let meta_dir = root.join(META_DIRECTORY_NAME);
+    let meta_path = meta_dir.join(format!("{}.bin", META_FILE_NAME));
+    // meta is only written once value log head or tail moved
+    info.created_at = match fs::metadata(&meta_path).await {
+        Ok(m) if m.len() > 0 => {
+            let mut meta = Meta::new(&meta_dir).await?;
+            meta.recover().await?;
+            meta.created_at.timestamp_millis() as u64
+        }
+        _ => Utc::now().timestamp_millis() as u64,
+    };
+    Ok(info)
+}
+
+/// Returns directories of every SSTable, including those in cold storage
+async fn list_sstables(root: &Path, cold_storage_dir: Option<&Path>) -> Result<Vec<PathBuf>, Error> {
+    let mut buckets_roots = vec![root.join(BUCKETS_DIRECTORY_NAME)];
+    buckets_roots.extend(cold_storage_dir.map(Path::to_path_buf));
+    let mut sst_dirs = Vec::new();
+    for buckets_root in buckets_roots.into_iter().filter(|dir| dir.exists()) {
+        let mut buckets_stream = open_dir_stream!(buckets_root.to_owned());
+        while let Some(bucket_dir) = buckets_stream.next_entry().await.map_err(|err| DirOpen {
+            path: buckets_root.to_owned(),
+            error: err,
+        })? {
+            let mut sst_dir_stream = open_dir_stream!(bucket_dir.path());
+            while let Some(sst_dir) = sst_dir_stream.next_entry().await.map_err(|err| DirOpen {
+                path: bucket_dir.path(),
+                error: err,
+            })? {
+                if !sst_dir.path().join(format!("{}.db", DATA_FILE_NAME)).is_file() {
+                    return Err(InvalidSSTableDirectory {
+                        input_string: sst_dir.path().to_string_lossy().to_string(),
+                    });
+                }
+                sst_dirs.push(sst_dir.path());
+            }
+        }
+    }
+    // same order on every run
+    sst_dirs.sort();
+    Ok(sst_dirs)
+}
+
+/// Rebuilds index, summary and filter of SSTable at `sst_dir` from its data file
+///
+/// Each file is replaced atomically, so an interrupted rewrite can be repeated
+async fn rewrite_sstable(sst_dir: &Path) -> Result<(), Error> {
+    let index_path = sst_dir.join(format!("{}.db", INDEX_FILE_NAME));
+    let filter_path = sst_dir.join(format!("{}.db", FILTER_FILE_NAME));
+    let mut table = Table::build_from(
+        sst_dir.to_path_buf(),
+        sst_dir.join(format!("{}.db", DATA_FILE_NAME)),
+        index_path.to_owned(),
+    )
+    .await?;
+    table.load_entries_from_file().await?;
+    if table.entries.is_empty() {
+        return Err(InvalidSSTableDirectory {
+            input_string: sst_dir.to_string_lossy().to_string(),
+        });
+    }
+
+    let index = SparseIndex::rebuild(&table.entries)?;
+    FileNode::write_atomic(
+        &index_path,
+        &SparseIndex::encode(index.entries(), index.tombstone_blocks()),
+    )
+    .await?;
+
+    // range tombstones are only stored in the summary, kept if it is still readable
+    let mut summary = Summary::new(sst_dir);
+    if let Err(err) = summary.recover().await {
+        log::warn!(
+            "Summary of {:?} unreadable, range tombstones dropped: {}",
+            sst_dir,
+            err
+        );
+    }
+    summary.set_from_entries(&table.entries);
+    FileNode::write_atomic(&summary.path, &summary.serialize()).await?;
+
+    // keep the false positive rate the filter was built with
+    let false_positive_rate = FilterFileNode::recover(&filter_path)
+        .await
+        .map(|(false_positive_rate, _, _)| false_positive_rate)
+        .unwrap_or(DEFAULT_FALSE_POSITIVE_RATE);
+    let filter = BloomFilter::new(false_positive_rate, table.entries.len())
+        .build_from_entries(table.entries.clone(), true)
+        .await?;
+    FileNode::write_atomic(&filter_path, &filter.serialize()).await
+}
@@ -0,0 +1,3 @@
+//! Offline tools working on the files of a closed store
+mod migrate;
+pub use migrate::migrate;
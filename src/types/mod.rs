@@ -1,10 +1,12 @@
 use crate::{
     bucket::BucketMap,
+    flush::FlushEvent,
     key_range::KeyRange,
     memtable::{MemTable, SkipMapValue},
 };
 use chrono::{DateTime, Utc};
 use crossbeam_skiplist::SkipMap;
+use indexmap::IndexMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -15,6 +17,9 @@ pub type Key = Vec<u8>;
 /// Represents a value
 pub type Value = Vec<u8>;
 
+/// Represents application metadata stored alongside a value
+pub type Metadata = Vec<u8>;
+
 /// Alias for value offset in vlog file
 pub type ValOffset = usize;
 
@@ -27,9 +32,6 @@ pub type LastModified = DateTime<Utc>;
 /// Represents a tombstone marker (true if entry is deleted)
 pub type IsTombStone = bool;
 
-/// Represents singal sent after flush
-pub type FlushSignal = u8;
-
 /// Represents the number of bytes read
 pub type NoBytesRead = usize;
 
@@ -37,7 +39,7 @@ pub type NoBytesRead = usize;
 pub type SkipMapEntries<K> = Arc<SkipMap<K, SkipMapValue<ValOffset>>>;
 
 /// Represents a receiver for flush signal
-pub type FlushReceiver = async_broadcast::Receiver<FlushSignal>;
+pub type FlushReceiver = async_broadcast::Receiver<FlushEvent>;
 
 /// Thread-safe BucketMap
 pub type BucketMapHandle = Arc<RwLock<BucketMap>>;
@@ -51,14 +53,17 @@ pub type ImmutableMemTables<K> = Arc<SkipMap<K, Arc<MemTable<K>>>>;
 /// Represents read-only memtables without lock
 pub type ImmutableMemTablesLockFree<K> = SkipMap<MemtableId, Arc<MemTable<K>>>;
 
+/// Represents flushed memtables kept in memory, oldest first
+pub type RetainedMemTables<K> = Arc<std::sync::RwLock<IndexMap<MemtableId, Arc<MemTable<K>>>>>;
+
 /// Alias for a boolean value
 pub type Bool = bool;
 
 /// Represents an ID for a MemTable
 pub type MemtableId = Vec<u8>;
 
-/// Represents a HashSet that contains unique identifier of memtables currently being flushed
-pub type MemtableFlushStream = std::collections::HashSet<MemtableId>;
+/// Represents unique identifiers of memtables handed to a flush task, with whether the flush failed
+pub type MemtableFlushStream = Arc<std::sync::Mutex<std::collections::HashMap<MemtableId, Bool>>>;
 
 /// Represents updated entries in a SkipMap after garbage collection, with a generic key type
 pub type GCUpdatedEntries<K> = Arc<RwLock<SkipMap<K, SkipMapValue<ValOffset>>>>;
@@ -1,11 +1,12 @@
+use crate::err::Error;
 use chrono::{DateTime, TimeZone, Utc};
 
-#[cfg(test)]
+#[cfg(any(test, feature = "bench"))]
 use rand::{distributions::Alphanumeric, Rng};
 
 /// Gnerate random string id of `length`
-/// used during test
-#[cfg(test)]
+/// used during test and benchmarks
+#[cfg(any(test, feature = "bench"))]
 pub fn generate_random_id(length: usize) -> String {
     let rng = rand::thread_rng();
     let id: String = rng
@@ -53,6 +54,69 @@ pub fn float_from_le_bytes(bytes: &[u8]) -> Option<f64> {
     Some(float)
 }
 
+/// Lookup table for CRC-32 (IEEE 802.3 polynomial)
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Computes 64-bit FNV-1a hash of `value`, used to address deduplicated values
+///
+/// The hash is stored in the value log so it must not change between releases
+pub fn content_hash(value: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for byte in value {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Computes CRC-32 checksum over `parts` as if they were one contiguous slice
+pub fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for part in parts {
+        for byte in part.iter() {
+            crc = CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+        }
+    }
+    !crc
+}
+
+/// Runs CPU heavy `work` on the blocking thread pool if `offload` is set,
+/// so it doesn't stall other tasks on the async executor
+///
+/// # Errors
+///
+/// Returns error if `work` panicked
+pub(crate) async fn run_cpu_bound<T, W>(offload: bool, work: W) -> Result<T, Error>
+where
+    T: Send + 'static,
+    W: FnOnce() -> T + Send + 'static,
+{
+    if !offload {
+        return Ok(work());
+    }
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|_| Error::TokioJoin)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -101,4 +165,28 @@ mod tests {
         let result = float_from_le_bytes(&invalid_bytes);
         assert_eq!(result, None);
     }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(&[b"123456789"]), 0xCBF4_3926);
+        assert_eq!(crc32(&[b"1234", b"56789"]), 0xCBF4_3926);
+        assert_eq!(crc32(&[]), 0);
+    }
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(content_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(content_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[tokio::test]
+    async fn test_run_cpu_bound() {
+        let caller = std::thread::current().id();
+        let inline = run_cpu_bound(false, move || std::thread::current().id() == caller).await;
+        assert!(inline.unwrap());
+        let offloaded = run_cpu_bound(true, move || std::thread::current().id() == caller).await;
+        assert!(!offloaded.unwrap());
+        let panicked = run_cpu_bound(true, || panic!("work failed")).await;
+        assert!(matches!(panicked, Err(Error::TokioJoin)));
+    }
 }
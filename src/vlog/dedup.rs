@@ -0,0 +1,139 @@
+use super::{ValueKind, ValueLogEntry};
+use crate::types::ValOffset;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Deduplicated value stored once in the value log
+#[derive(Debug, Clone, Copy, Default)]
+struct Blob {
+    /// Offset of the blob entry, `None` while only references to it were seen
+    offset: Option<ValOffset>,
+
+    /// Reference entries between tail and end of the value log
+    refs: usize,
+}
+
+/// Blobs of the value log by content hash, shared by clones
+///
+/// Values of at least `min_size` bytes are written once as a blob entry and each
+/// write of such a value appends a reference entry holding its hash instead.
+/// Garbage collection releases the references it moves or drops and only keeps
+/// blobs that are still referenced. The index is rebuilt from the value log on open.
+#[derive(Debug, Clone)]
+pub(crate) struct DedupIndex {
+    /// Smallest value that is deduplicated
+    pub(crate) min_size: usize,
+
+    blobs: Arc<Mutex<HashMap<u64, Blob>>>,
+}
+
+impl DedupIndex {
+    /// Creates empty `DedupIndex`
+    pub(crate) fn new(min_size: usize) -> Self {
+        Self {
+            min_size,
+            blobs: Arc::default(),
+        }
+    }
+
+    /// Adds a reference to blob with `hash`
+    ///
+    /// Returns offset of the blob, or `None` without adding a reference if there is no such blob
+    pub(crate) fn acquire(&self, hash: u64) -> Option<ValOffset> {
+        let mut blobs = self.blobs.lock().unwrap();
+        let blob = blobs.get_mut(&hash)?;
+        let offset = blob.offset?;
+        blob.refs += 1;
+        Some(offset)
+    }
+
+    /// Registers blob with `hash` written at `offset`, with a reference to it
+    pub(crate) fn insert(&self, hash: u64, offset: ValOffset) {
+        let mut blobs = self.blobs.lock().unwrap();
+        let blob = blobs.entry(hash).or_default();
+        blob.offset = Some(offset);
+        blob.refs += 1;
+    }
+
+    /// Removes a reference to blob with `hash`
+    pub(crate) fn release(&self, hash: u64) {
+        if let Some(blob) = self.blobs.lock().unwrap().get_mut(&hash) {
+            blob.refs = blob.refs.saturating_sub(1);
+        }
+    }
+
+    /// Returns offset of blob with `hash`
+    pub(crate) fn locate(&self, hash: u64) -> Option<ValOffset> {
+        self.blobs.lock().unwrap().get(&hash).and_then(|blob| blob.offset)
+    }
+
+    /// Checks if blob with `hash` at `offset` is referenced by more than the `moving` references
+    /// about to be moved or dropped, a blob that is not is removed
+    ///
+    /// Copies of a blob other than the one in the index are never referenced
+    pub(crate) fn retain(&self, hash: u64, offset: ValOffset, moving: usize) -> bool {
+        let mut blobs = self.blobs.lock().unwrap();
+        match blobs.get(&hash) {
+            Some(blob) if blob.offset == Some(offset) => {
+                if blob.refs <= moving {
+                    blobs.remove(&hash);
+                    return false;
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Points blob with `hash` to its copy at `offset`
+    pub(crate) fn relocate(&self, hash: u64, offset: ValOffset) {
+        if let Some(blob) = self.blobs.lock().unwrap().get_mut(&hash) {
+            blob.offset = Some(offset);
+        }
+    }
+
+    /// Adds `entry` found at `offset` to the index while it is rebuilt
+    pub(crate) fn rebuild_with(&self, entry: &ValueLogEntry, offset: ValOffset) {
+        let hash = match entry.kind {
+            ValueKind::Blob => entry.key.as_slice(),
+            ValueKind::Reference => entry.value.as_slice(),
+            ValueKind::Inline | ValueKind::RangeTombstone => return,
+        };
+        let hash = match hash.try_into() {
+            Ok(hash) => u64::from_le_bytes(hash),
+            Err(_) => return,
+        };
+        let mut blobs = self.blobs.lock().unwrap();
+        let blob = blobs.entry(hash).or_default();
+        match entry.kind {
+            // the latest copy of a blob is the one kept by garbage collection
+            ValueKind::Blob => blob.offset = Some(offset),
+            _ => blob.refs += 1,
+        }
+    }
+
+    /// Removes all blobs
+    pub(crate) fn clear(&self) {
+        self.blobs.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blob_is_kept_while_referenced() {
+        let index = DedupIndex::new(64);
+        assert_eq!(index.acquire(7), None);
+        index.insert(7, 100);
+        assert_eq!(index.acquire(7), Some(100));
+        index.release(7);
+        assert!(index.retain(7, 100, 0));
+        // stale copy
+        assert!(!index.retain(7, 50, 0));
+        // only reference is moved
+        assert!(!index.retain(7, 100, 1));
+        assert_eq!(index.locate(7), None);
+    }
+}
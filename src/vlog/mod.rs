@@ -1,3 +1,7 @@
+mod dedup;
 mod v_log;
+mod wal;
+pub use v_log::ValueKind;
 pub use v_log::ValueLog;
 pub use v_log::ValueLogEntry;
+pub(crate) use wal::is_wal_offset;
@@ -76,17 +76,34 @@
 //! - **Key**: The actual key data, which can vary in size.
 //! - **Value**: The actual value data, which can vary in size.
 //! - **Created At**: A 8-byte field representing the time of insertion in bytes.
-//! - **Is Tombstone**: A 1 byte field representing a boolean of deleted or not deleted entry
+//! - **Is Tombstone**: A 1 byte field of flags, the lowest bit marks a deleted entry, the
+//!   second bit marks an entry followed by a checksum and the third bit an entry with metadata.
+//!   The fourth bit marks a blob, a deduplicated value keyed by its content hash, and the fifth
+//!   bit a reference whose value is the content hash of a blob
+//! - **Metadata**: Optional application metadata, a 1-byte length followed by up to
+//!   `MAX_METADATA_SIZE` bytes, written after the value
+//! - **Checksum**: An optional 4-byte CRC-32 of key, value and metadata, written last when
+//!   `Config::verify_reads` is enabled
 
 use chrono::{DateTime, Utc};
 
 use crate::{
-    consts::{SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8, VLOG_FILE_NAME},
+    consts::{
+        DEDUP_REBUILD_CHUNK_SIZE, MIN_DEDUP_VALUE_SIZE, SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8,
+        TOMB_STONE_MARKER, VLOG_BLOB_FLAG, VLOG_CHECKSUM_FLAG, VLOG_FILE_NAME, VLOG_METADATA_FLAG,
+        VLOG_RANGE_TOMBSTONE_FLAG, VLOG_REFERENCE_FLAG, VLOG_TOMBSTONE_FLAG, WAL_FILE_NAME,
+    },
     err::Error,
-    fs::{FileAsync, FileNode, VLogFileNode, VLogFs},
+    fs::{FileAsync, FileNode, ReadPool, RetryPolicy, VLogFileNode, VLogFs},
     types::{ByteSerializedEntry, CreatedAt, IsTombStone, ValOffset, Value},
+    util,
 };
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use super::dedup::DedupIndex;
+use super::wal::{is_wal_offset, Wal};
 type TotalBytesRead = usize;
 
 /// Value log file
@@ -123,6 +140,59 @@ pub struct ValueLog {
 
     /// Size of the Value log
     pub size: usize,
+
+    /// Size of extents reserved ahead of appends, `None` disables preallocation
+    pub(crate) preallocation_extent: Option<usize>,
+
+    /// Offset up to which disk space has been reserved
+    pub(crate) preallocated_to: usize,
+
+    /// Should appended entries carry a checksum?
+    pub(crate) checksum_entries: bool,
+
+    /// Blobs of deduplicated values, `None` disables deduplication
+    pub(crate) dedup: Option<DedupIndex>,
+
+    /// Log of small values not yet moved to the value log, `None` if there is none
+    pub(crate) wal: Option<Wal>,
+
+    /// Retries of appends failing with transient IO errors
+    pub(crate) retry: RetryPolicy,
+
+    /// Threads entries are read on, `None` reads on the runtime of the caller
+    pub(crate) read_pool: Option<ReadPool>,
+
+    /// End of the file reached by appends of all clones of the log
+    pub(crate) end: Arc<Mutex<usize>>,
+}
+
+/// How the value of a value log entry is stored
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum ValueKind {
+    /// Value is stored in the entry
+    #[default]
+    Inline,
+
+    /// Deduplicated value shared by references, the key of the entry is the content hash of the value
+    Blob,
+
+    /// Value is stored in the blob whose content hash is the value of the entry
+    Reference,
+
+    /// Deletes keys from the key of the entry up to, not including, the value of the entry
+    RangeTombstone,
+}
+
+/// Entries encoded for a single write
+#[derive(Default)]
+struct PendingWrite {
+    data: Vec<u8>,
+
+    /// Hashes of blobs referenced by the entries
+    references: Vec<u64>,
+
+    /// Hashes and offsets of blobs written with the entries
+    blobs: Vec<(u64, ValOffset)>,
 }
 
 /// Value log entry
@@ -145,6 +215,15 @@ pub struct ValueLogEntry {
 
     /// True means entry has been deleted
     pub is_tombstone: bool,
+
+    /// Application metadata stored alongside the value
+    pub metadata: Option<Vec<u8>>,
+
+    /// CRC-32 of key, value and metadata, only written in `verify_reads` mode
+    pub checksum: Option<u32>,
+
+    /// Whether the value is stored in the entry or deduplicated
+    pub kind: ValueKind,
 }
 
 impl ValueLog {
@@ -164,9 +243,102 @@ impl ValueLog {
             content: VFile::new(file_path, file),
             // IMPORTANT: cache vlog size in memory
             size,
+            preallocation_extent: None,
+            preallocated_to: size,
+            checksum_entries: false,
+            dedup: None,
+            wal: None,
+            retry: RetryPolicy::default(),
+            read_pool: None,
+            end: Arc::new(Mutex::new(size)),
         })
     }
 
+    /// Retries appends failing with transient IO errors according to `retry`
+    pub(crate) fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Reads entries on `read_pool` if provided
+    pub(crate) fn with_read_pool(mut self, read_pool: Option<ReadPool>) -> Self {
+        self.read_pool = read_pool;
+        self
+    }
+
+    /// Stores a checksum of key and value with every appended entry
+    pub(crate) fn with_checksums(mut self, checksum_entries: bool) -> Self {
+        self.checksum_entries = checksum_entries;
+        self
+    }
+
+    /// Stores values of at least `min_size` bytes once, `None` disables deduplication
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min_size` is less than `MIN_DEDUP_VALUE_SIZE`
+    pub(crate) fn with_dedup(mut self, min_size: Option<usize>) -> Self {
+        assert!(
+            min_size.is_none_or(|size| size >= MIN_DEDUP_VALUE_SIZE),
+            "dedup_min_value_size should not be less than 64 bytes"
+        );
+        self.dedup = min_size.map(DedupIndex::new);
+        self
+    }
+
+    /// Writes values of at most `max_value_size` bytes to a write-ahead log in the value log directory
+    ///
+    /// The log is opened even if `max_value_size` is `None` when a previous
+    /// open left one behind, so its entries are still recovered
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub(crate) async fn with_wal(mut self, max_value_size: Option<usize>) -> Result<Self, Error> {
+        let path = self.content.path.with_file_name(WAL_FILE_NAME);
+        if max_value_size.is_some() || path.exists() {
+            self.wal = Some(Wal::open(path, max_value_size).await?);
+        }
+        Ok(self)
+    }
+
+    /// Reserves disk space in extents of `extent` bytes ahead of appends
+    ///
+    /// # Panics
+    ///
+    /// Panics if `extent` is zero
+    pub(crate) fn with_preallocation(mut self, extent: Option<usize>) -> Self {
+        assert!(
+            extent != Some(0),
+            "preallocation extent should be greater than zero"
+        );
+        self.preallocation_extent = extent;
+        self
+    }
+
+    /// Reserves the next extents if `len` more bytes will not fit in the space reserved so far
+    ///
+    /// A failure is logged and disables preallocation, since appends
+    /// still work on file systems that don't support it
+    async fn preallocate(&mut self, len: usize) {
+        let extent = match self.preallocation_extent {
+            Some(extent) => extent,
+            None => return,
+        };
+        if self.size + len <= self.preallocated_to {
+            return;
+        }
+        let start = self.size.max(self.preallocated_to);
+        let alloc_len = (self.size + len - start).div_ceil(extent) * extent;
+        match self.content.file.node.preallocate(start, alloc_len).await {
+            Ok(_) => self.preallocated_to = start + alloc_len,
+            Err(err) => {
+                log::warn!("{}, value log preallocation disabled", err);
+                self.preallocation_extent = None;
+            }
+        }
+    }
+
     /// Appends new entry to value log
     ///
     /// Returns start offset of the newly inserted entry
@@ -177,7 +349,333 @@ impl ValueLog {
         created_at: CreatedAt,
         is_tombstone: bool,
     ) -> Result<ValOffset, Error> {
-        let v_log_entry = ValueLogEntry::new(
+        let mut end = self.claim_end().await;
+        let mut pending = PendingWrite::default();
+        // Get the current offset before writing(this will be the offset of the value stored in the memtable)
+        let offset = self
+            .encode_entry(
+                &mut pending,
+                key.as_ref(),
+                value.as_ref(),
+                None,
+                created_at,
+                is_tombstone,
+            )
+            .await?;
+        self.write_pending(pending, &mut end).await?;
+        Ok(offset)
+    }
+
+    /// Appends entries to value log with a single write
+    ///
+    /// Each entry is a tuple of key, value, creation time and tombstone flag.
+    /// Returns start offset of each entry, in order
+    pub async fn append_batch<T: AsRef<[u8]>>(
+        &mut self,
+        entries: &[(T, T, CreatedAt, bool)],
+    ) -> Result<Vec<ValOffset>, Error> {
+        let mut end = self.claim_end().await;
+        let mut offsets = Vec::with_capacity(entries.len());
+        let mut pending = PendingWrite::default();
+        for (key, value, created_at, is_tombstone) in entries {
+            let offset = self
+                .encode_entry(
+                    &mut pending,
+                    key.as_ref(),
+                    value.as_ref(),
+                    None,
+                    *created_at,
+                    *is_tombstone,
+                )
+                .await?;
+            offsets.push(offset);
+        }
+        self.write_pending(pending, &mut end).await?;
+        Ok(offsets)
+    }
+
+    /// Appends entries to the write-ahead log with a single synced write,
+    /// or to the value log if there is no write-ahead log
+    ///
+    /// Returns start offset of each entry, in order
+    pub(crate) async fn append_batch_to_wal<T: AsRef<[u8]>>(
+        &mut self,
+        entries: &[(T, T, CreatedAt, bool)],
+    ) -> Result<Vec<ValOffset>, Error> {
+        match self.wal.as_mut() {
+            Some(wal) => wal.append_batch(entries, &self.retry).await,
+            None => self.append_batch(entries).await,
+        }
+    }
+
+    /// Same as [`ValueLog::append_batch`], but each entry carries optional application metadata
+    ///
+    /// Each entry is a tuple of key, value, metadata and creation time.
+    /// Returns start offset of each entry, in order
+    pub async fn append_batch_with_metadata<T: AsRef<[u8]>>(
+        &mut self,
+        entries: &[(T, T, Option<T>, CreatedAt)],
+    ) -> Result<Vec<ValOffset>, Error> {
+        let mut end = self.claim_end().await;
+        let mut offsets = Vec::with_capacity(entries.len());
+        let mut pending = PendingWrite::default();
+        for (key, value, metadata, created_at) in entries {
+            let metadata = metadata.as_ref().map(|m| m.as_ref());
+            let offset = self
+                .encode_entry(
+                    &mut pending,
+                    key.as_ref(),
+                    value.as_ref(),
+                    metadata,
+                    *created_at,
+                    false,
+                )
+                .await?;
+            offsets.push(offset);
+        }
+        self.write_pending(pending, &mut end).await?;
+        Ok(offsets)
+    }
+
+    /// Same as [`ValueLog::append`], but stores application `metadata` with the value
+    ///
+    /// Returns start offset of the newly inserted entry
+    pub async fn append_with_metadata<T: AsRef<[u8]>>(
+        &mut self,
+        key: T,
+        value: T,
+        metadata: Option<&[u8]>,
+        created_at: CreatedAt,
+    ) -> Result<ValOffset, Error> {
+        let mut end = self.claim_end().await;
+        let mut pending = PendingWrite::default();
+        let offset = self
+            .encode_entry(
+                &mut pending,
+                key.as_ref(),
+                value.as_ref(),
+                metadata,
+                created_at,
+                false,
+            )
+            .await?;
+        self.write_pending(pending, &mut end).await?;
+        Ok(offset)
+    }
+
+    /// Same as [`ValueLog::append_with_metadata`], but marks the entry deleted while keeping its value
+    ///
+    /// Returns start offset of the newly inserted entry
+    pub(crate) async fn append_deleted<T: AsRef<[u8]>>(
+        &mut self,
+        key: T,
+        value: T,
+        metadata: Option<&[u8]>,
+        created_at: CreatedAt,
+    ) -> Result<ValOffset, Error> {
+        let mut end = self.claim_end().await;
+        let mut pending = PendingWrite::default();
+        let offset = self
+            .encode_entry(
+                &mut pending,
+                key.as_ref(),
+                value.as_ref(),
+                metadata,
+                created_at,
+                true,
+            )
+            .await?;
+        self.write_pending(pending, &mut end).await?;
+        Ok(offset)
+    }
+
+    /// Appends copy of `blob` moved by garbage collection and points its hash to the copy
+    ///
+    /// Returns start offset of the copy
+    pub(crate) async fn relocate_blob(&mut self, blob: &ValueLogEntry) -> Result<ValOffset, Error> {
+        let mut end = self.claim_end().await;
+        let offset = self.size;
+        let pending = PendingWrite {
+            data: blob.serialize(),
+            ..Default::default()
+        };
+        self.write_pending(pending, &mut end).await?;
+        if let (Some(dedup), Ok(hash)) = (&self.dedup, blob.key.as_slice().try_into()) {
+            dedup.relocate(u64::from_le_bytes(hash), offset);
+        }
+        Ok(offset)
+    }
+
+    /// Appends record deleting keys from `start` up to, not including, `end`
+    ///
+    /// Returns offset the record was written at
+    pub(crate) async fn append_range_tombstone(
+        &mut self,
+        start: &[u8],
+        end: &[u8],
+        created_at: CreatedAt,
+    ) -> Result<ValOffset, Error> {
+        let mut entry = self.new_entry(start, end, None, created_at, true);
+        entry.kind = ValueKind::RangeTombstone;
+        let mut file_end = self.claim_end().await;
+        let offset = self.size;
+        let pending = PendingWrite {
+            data: entry.serialize(),
+            ..Default::default()
+        };
+        self.write_pending(pending, &mut file_end).await?;
+        Ok(offset)
+    }
+
+    /// Encodes entry at the end of `pending`
+    ///
+    /// With deduplication enabled a value of at least `min_size` bytes is
+    /// written as a reference to the blob with the same content, the blob is
+    /// written first if there is none yet
+    ///
+    /// Returns offset the entry will be written at
+    async fn encode_entry(
+        &self,
+        pending: &mut PendingWrite,
+        key: &[u8],
+        value: &[u8],
+        metadata: Option<&[u8]>,
+        created_at: CreatedAt,
+        is_tombstone: bool,
+    ) -> Result<ValOffset, Error> {
+        if let Some(dedup) = self
+            .dedup
+            .as_ref()
+            .filter(|dedup| !is_tombstone && value.len() >= dedup.min_size)
+        {
+            let hash = util::content_hash(value);
+            let hash_bytes = hash.to_le_bytes();
+            match dedup.acquire(hash) {
+                Some(blob_offset) => {
+                    if self
+                        .blob_matches(pending, blob_offset, &hash_bytes, value)
+                        .await?
+                    {
+                        pending.references.push(hash);
+                        return Ok(self.push_reference(pending, key, &hash_bytes, metadata, created_at));
+                    }
+                    // hash collision, the value is stored inline
+                    dedup.release(hash);
+                }
+                None => {
+                    let blob_offset = self.size + pending.data.len();
+                    let mut blob = self.new_entry(&hash_bytes[..], value, None, created_at, false);
+                    blob.kind = ValueKind::Blob;
+                    pending.data.extend(blob.serialize());
+                    dedup.insert(hash, blob_offset);
+                    pending.references.push(hash);
+                    pending.blobs.push((hash, blob_offset));
+                    return Ok(self.push_reference(pending, key, &hash_bytes, metadata, created_at));
+                }
+            }
+        }
+        let offset = self.size + pending.data.len();
+        pending.data.extend(
+            self.new_entry(key, value, metadata, created_at, is_tombstone)
+                .serialize(),
+        );
+        Ok(offset)
+    }
+
+    /// Encodes reference to blob with `hash_bytes` at the end of `pending`
+    ///
+    /// Returns offset the reference will be written at
+    fn push_reference(
+        &self,
+        pending: &mut PendingWrite,
+        key: &[u8],
+        hash_bytes: &[u8],
+        metadata: Option<&[u8]>,
+        created_at: CreatedAt,
+    ) -> ValOffset {
+        let offset = self.size + pending.data.len();
+        let mut reference = self.new_entry(key, hash_bytes, metadata, created_at, false);
+        reference.kind = ValueKind::Reference;
+        pending.data.extend(reference.serialize());
+        offset
+    }
+
+    /// Checks that blob at `offset`, possibly not written yet, holds `value`
+    async fn blob_matches(
+        &self,
+        pending: &PendingWrite,
+        offset: ValOffset,
+        hash_bytes: &[u8],
+        value: &[u8],
+    ) -> Result<bool, Error> {
+        let blob = if offset >= self.size {
+            pending
+                .data
+                .get(offset - self.size..)
+                .and_then(ValueLogEntry::deserialize)
+                .map(|(blob, _)| blob)
+        } else {
+            self.content.file.get_entry(offset).await?
+        };
+        Ok(blob.is_some_and(|blob| {
+            blob.kind == ValueKind::Blob && blob.key == hash_bytes && blob.value == value
+        }))
+    }
+
+    /// Writes `pending` entries at the end of value log
+    ///
+    /// Blob references taken while encoding them are released if the write fails
+    async fn write_pending(&mut self, pending: PendingWrite, end: &mut usize) -> Result<(), Error> {
+        if pending.data.is_empty() {
+            return Ok(());
+        }
+        self.preallocate(pending.data.len()).await;
+        let (node, size, data) = (&self.content.file.node, self.size, &pending.data);
+        let written = self
+            .retry
+            .run(|attempt| async move {
+                // a failed attempt may have written part of the entries
+                if attempt > 0 {
+                    node.truncate(size).await?;
+                }
+                node.write_all(data).await
+            })
+            .await;
+        if let Err(err) = written {
+            if let Some(dedup) = &self.dedup {
+                for hash in pending.references {
+                    dedup.release(hash);
+                }
+                for (hash, offset) in pending.blobs {
+                    dedup.retain(hash, offset, 0);
+                }
+            }
+            return Err(err);
+        }
+        self.size += pending.data.len();
+        *end = self.size;
+        Ok(())
+    }
+
+    /// Waits for appends of clones of the log to finish and moves `size` to the end they reached
+    ///
+    /// Clones share the file, offsets of entries are assigned while the returned guard is held
+    async fn claim_end(&mut self) -> OwnedMutexGuard<usize> {
+        let end = Arc::clone(&self.end).lock_owned().await;
+        self.size = *end;
+        end
+    }
+
+    /// Builds entry to append, with checksum if enabled
+    fn new_entry<T: AsRef<[u8]>>(
+        &self,
+        key: T,
+        value: T,
+        metadata: Option<&[u8]>,
+        created_at: CreatedAt,
+        is_tombstone: bool,
+    ) -> ValueLogEntry {
+        let mut v_log_entry = ValueLogEntry::new(
             key.as_ref().len(),
             value.as_ref().len(),
             key.as_ref().to_vec(),
@@ -185,14 +683,13 @@ impl ValueLog {
             created_at,
             is_tombstone,
         );
-
-        let serialized_data = v_log_entry.serialize();
-        // Get the current offset before writing(this will be the offset of the value stored in the memtable)
-        let last_offset = self.size;
-        let data_file = &self.content;
-        data_file.file.node.write_all(&serialized_data).await?;
-        self.size += serialized_data.len();
-        Ok(last_offset)
+        if let Some(metadata) = metadata {
+            v_log_entry = v_log_entry.with_metadata(metadata);
+        }
+        if self.checksum_entries {
+            return v_log_entry.with_checksum();
+        }
+        v_log_entry
     }
 
     /// Fetches value from value log
@@ -203,7 +700,106 @@ impl ValueLog {
     ///
     /// Returns error in case there is an IO error
     pub async fn get(&self, start_offset: usize) -> Result<Option<(Value, IsTombStone)>, Error> {
-        self.content.file.get(start_offset).await
+        let entry = self.get_entry(start_offset).await?;
+        Ok(entry.map(|e| (e.value, e.is_tombstone)))
+    }
+
+    /// Fetches whole entry from value log
+    ///
+    /// A reference is returned with the value of its blob and without checksum,
+    /// checksums of both entries are checked while the blob is read. Offsets of
+    /// the write-ahead log are read from the log
+    ///
+    /// # Error
+    ///
+    /// Returns error in case there is an IO error or the blob of a reference is missing
+    pub async fn get_entry(&self, start_offset: usize) -> Result<Option<ValueLogEntry>, Error> {
+        if is_wal_offset(start_offset) {
+            return match &self.wal {
+                Some(wal) => wal.get_entry(start_offset).await,
+                None => Ok(None),
+            };
+        }
+        match self.read_entry(start_offset).await? {
+            Some(entry) if entry.kind == ValueKind::Reference => {
+                self.resolve(entry, start_offset).await.map(Some)
+            }
+            entry => Ok(entry),
+        }
+    }
+
+    /// Returns entry starting at `offset` as it is stored in the value log
+    ///
+    /// References are returned with the hash of their blob as value. The entry
+    /// is checked against its checksum, so an offset that is not the start of an
+    /// entry is reported, unless the entry there was written without checksum.
+    ///
+    /// # Error
+    ///
+    /// Returns error in case there is an IO error or the entry is corrupted
+    pub async fn entry_at(&self, offset: usize) -> Result<Option<ValueLogEntry>, Error> {
+        if offset >= self.size {
+            return Ok(None);
+        }
+        let entry = self.content.file.get_entry(offset).await?;
+        if let Some(entry) = &entry {
+            entry.verify(&entry.key, offset)?;
+        }
+        Ok(entry)
+    }
+
+    /// Reads entry starting at `offset` of the value log file, on the read pool if there is one
+    async fn read_entry(&self, offset: usize) -> Result<Option<ValueLogEntry>, Error> {
+        match &self.read_pool {
+            Some(pool) => {
+                let file = self.content.file.clone();
+                pool.run(async move { file.get_entry(offset).await }).await
+            }
+            None => self.content.file.get_entry(offset).await,
+        }
+    }
+
+    /// Returns reference `entry` found at `offset` with the value of its blob
+    async fn resolve(&self, mut entry: ValueLogEntry, offset: ValOffset) -> Result<ValueLogEntry, Error> {
+        entry.verify(&entry.key, offset)?;
+        let blob_offset = match (&self.dedup, entry.value.as_slice().try_into()) {
+            (Some(dedup), Ok(hash)) => dedup.locate(u64::from_le_bytes(hash)),
+            _ => None,
+        };
+        let blob = match blob_offset {
+            Some(blob_offset) => self.read_entry(blob_offset).await?,
+            None => None,
+        };
+        match (blob, blob_offset) {
+            (Some(blob), Some(blob_offset)) if blob.kind == ValueKind::Blob && blob.key == entry.value => {
+                blob.verify(&entry.value, blob_offset)?;
+                entry.vsize = blob.value.len();
+                entry.value = blob.value;
+                entry.checksum = None;
+                Ok(entry)
+            }
+            _ => Err(Error::BlobNotFound { offset }),
+        }
+    }
+
+    /// Same as [`ValueLog::get`], but checks that the entry belongs to `key` and
+    /// matches its checksum if it has one
+    ///
+    /// # Error
+    ///
+    /// Returns error in case there is an IO error or the entry is corrupted
+    pub async fn get_verified(
+        &self,
+        key: &[u8],
+        start_offset: usize,
+    ) -> Result<Option<(Value, IsTombStone)>, Error> {
+        match self.get_entry(start_offset).await? {
+            Some(entry) => {
+                entry.verify(key, start_offset)?;
+                Ok(Some((entry.value, entry.is_tombstone)))
+            }
+            None => Ok(None),
+        }
     }
 
     /// Ensures value log entries are persisted on the disk
@@ -244,6 +840,34 @@ impl ValueLog {
             .await
     }
 
+    /// Rebuilds deduplication index from entries between tail and end of value log
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub(crate) async fn rebuild_dedup_index(&self) -> Result<(), Error> {
+        let dedup = match &self.dedup {
+            Some(dedup) => dedup,
+            None => return Ok(()),
+        };
+        dedup.clear();
+        let mut offset = self.tail_offset;
+        loop {
+            let (entries, _) = self
+                .content
+                .file
+                .read_chunk_to_garbage_collect(DEDUP_REBUILD_CHUNK_SIZE, offset as u64)
+                .await?;
+            if entries.is_empty() {
+                return Ok(());
+            }
+            for entry in entries.iter() {
+                dedup.rebuild_with(entry, offset);
+                offset += entry.encoded_len();
+            }
+        }
+    }
+
     // CAUTION: This deletes the value log file
     pub async fn clear_all(&mut self) {
         if self.content.file.node.metadata().await.is_ok() {
@@ -252,8 +876,20 @@ impl ValueLog {
             }
         }
         self.size = 0;
+        *self.end.lock().await = 0;
+        self.preallocated_to = 0;
         self.tail_offset = 0;
         self.head_offset = 0;
+        if let Some(dedup) = &self.dedup {
+            dedup.clear();
+        }
+    }
+
+    /// Updates cached size to size of the file, which includes entries appended by clones
+    pub(crate) async fn refresh_size(&mut self) {
+        let mut end = self.end.lock().await;
+        self.size = self.content.file.node.size().await;
+        *end = self.size;
     }
 
     /// Sets `head_offset` of `ValueLog`
@@ -284,14 +920,84 @@ impl ValueLogEntry {
             value: value.as_ref().to_vec(),
             created_at,
             is_tombstone,
+            metadata: None,
+            checksum: None,
+            kind: ValueKind::Inline,
+        }
+    }
+
+    /// Returns entry with application `metadata` set
+    ///
+    /// `metadata` must not exceed `MAX_METADATA_SIZE` bytes
+    pub fn with_metadata(mut self, metadata: impl AsRef<[u8]>) -> Self {
+        self.metadata = Some(metadata.as_ref().to_vec());
+        self
+    }
+
+    /// Returns entry with checksum of its key, value and metadata set
+    pub(crate) fn with_checksum(mut self) -> Self {
+        self.checksum = Some(self.compute_checksum());
+        self
+    }
+
+    /// Returns CRC-32 of key, value and metadata
+    fn compute_checksum(&self) -> u32 {
+        match &self.metadata {
+            Some(metadata) => util::crc32(&[&self.key, &self.value, metadata]),
+            None => util::crc32(&[&self.key, &self.value]),
         }
     }
 
+    /// Checks that entry at `offset` belongs to `key` and matches its checksum
+    ///
+    /// Entries written without checksum only have their key checked
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the entry is corrupted
+    pub(crate) fn verify(&self, key: &[u8], offset: ValOffset) -> Result<(), Error> {
+        if self.key != key {
+            return Err(Error::EntryKeyMismatch { offset });
+        }
+        if let Some(checksum) = self.checksum {
+            if self.compute_checksum() != checksum {
+                return Err(Error::ChecksumMismatch { offset });
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the entry is a deletion that kept the deleted value, see [`DataStore::soft_delete`](crate::db::DataStore::soft_delete)
+    pub(crate) fn is_soft_deleted(&self) -> bool {
+        self.is_tombstone
+            && self.kind != ValueKind::RangeTombstone
+            && self.value != TOMB_STONE_MARKER.as_bytes()
+    }
+
+    /// Whether more than `ttl` has passed since the entry was created
+    pub(crate) fn has_expired(&self, ttl: std::time::Duration) -> bool {
+        let current_timestamp = Utc::now().timestamp_millis() as u64;
+        current_timestamp > (self.created_at.timestamp_millis() as u64 + ttl.as_millis() as u64)
+    }
+
+    /// Returns number of bytes the entry takes in value log
+    pub(crate) fn encoded_len(&self) -> usize {
+        let checksum_len = if self.checksum.is_some() { SIZE_OF_U32 } else { 0 };
+        let metadata_len = self.metadata.as_ref().map_or(0, |m| SIZE_OF_U8 + m.len());
+        SIZE_OF_U32
+            + SIZE_OF_U32
+            + SIZE_OF_U64
+            + self.key.len()
+            + self.value.len()
+            + SIZE_OF_U8
+            + metadata_len
+            + checksum_len
+    }
+
     /// Converts value log entry to a byte vector
-    pub(crate) fn serialize(&self) -> ByteSerializedEntry {
-        let entry_len =
-            SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + self.key.len() + self.value.len() + SIZE_OF_U8;
-        let mut serialized_data = Vec::with_capacity(entry_len);
+    #[doc(hidden)]
+    pub fn serialize(&self) -> ByteSerializedEntry {
+        let mut serialized_data = Vec::with_capacity(self.encoded_len());
 
         serialized_data.extend_from_slice(&(self.key.len() as u32).to_le_bytes());
 
@@ -299,12 +1005,105 @@ impl ValueLogEntry {
 
         serialized_data.extend_from_slice(&self.created_at.timestamp_millis().to_le_bytes());
 
-        serialized_data.push(self.is_tombstone as u8);
+        let mut flags = 0;
+        if self.is_tombstone {
+            flags |= VLOG_TOMBSTONE_FLAG;
+        }
+        if self.checksum.is_some() {
+            flags |= VLOG_CHECKSUM_FLAG;
+        }
+        if self.metadata.is_some() {
+            flags |= VLOG_METADATA_FLAG;
+        }
+        match self.kind {
+            ValueKind::Inline => {}
+            ValueKind::Blob => flags |= VLOG_BLOB_FLAG,
+            ValueKind::Reference => flags |= VLOG_REFERENCE_FLAG,
+            ValueKind::RangeTombstone => flags |= VLOG_RANGE_TOMBSTONE_FLAG,
+        }
+        serialized_data.push(flags);
 
         serialized_data.extend_from_slice(&self.key);
 
         serialized_data.extend_from_slice(&self.value);
 
+        if let Some(metadata) = &self.metadata {
+            serialized_data.push(metadata.len() as u8);
+            serialized_data.extend_from_slice(metadata);
+        }
+
+        if let Some(checksum) = self.checksum {
+            serialized_data.extend_from_slice(&checksum.to_le_bytes());
+        }
+
         serialized_data
     }
+
+    /// Decodes entry at the start of `buf`
+    ///
+    /// Returns entry and number of bytes it takes, or `None` if `buf`
+    /// ends before the entry does. Creation date has millisecond precision.
+    #[doc(hidden)]
+    pub fn deserialize(buf: &[u8]) -> Option<(Self, usize)> {
+        let header_len = SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8;
+        if buf.len() < header_len {
+            return None;
+        }
+        let ksize = u32::from_le_bytes(buf[..SIZE_OF_U32].try_into().unwrap()) as usize;
+        let vsize = u32::from_le_bytes(buf[SIZE_OF_U32..SIZE_OF_U32 * 2].try_into().unwrap()) as usize;
+        let created_at = u64::from_le_bytes(
+            buf[SIZE_OF_U32 * 2..SIZE_OF_U32 * 2 + SIZE_OF_U64]
+                .try_into()
+                .unwrap(),
+        );
+        let flags = buf[header_len - SIZE_OF_U8];
+        let checksum_len = if flags & VLOG_CHECKSUM_FLAG != 0 {
+            SIZE_OF_U32
+        } else {
+            0
+        };
+        let value_end = header_len + ksize + vsize;
+        let metadata_len = if flags & VLOG_METADATA_FLAG != 0 {
+            SIZE_OF_U8 + *buf.get(value_end)? as usize
+        } else {
+            0
+        };
+        let entry_len = value_end + metadata_len + checksum_len;
+        if buf.len() < entry_len {
+            return None;
+        }
+        let key = buf[header_len..header_len + ksize].to_vec();
+        let value = buf[header_len + ksize..value_end].to_vec();
+        let metadata =
+            (metadata_len > 0).then(|| buf[value_end + SIZE_OF_U8..value_end + metadata_len].to_vec());
+        let checksum = (checksum_len > 0)
+            .then(|| u32::from_le_bytes(buf[entry_len - SIZE_OF_U32..entry_len].try_into().unwrap()));
+        let entry = Self {
+            ksize,
+            vsize,
+            key,
+            value,
+            created_at: util::milliseconds_to_datetime(created_at),
+            is_tombstone: flags & VLOG_TOMBSTONE_FLAG != 0,
+            metadata,
+            checksum,
+            kind: ValueKind::from_flags(flags),
+        };
+        Some((entry, entry_len))
+    }
+}
+
+impl ValueKind {
+    /// Returns kind marked in the flags byte of an entry
+    pub(crate) fn from_flags(flags: u8) -> Self {
+        if flags & VLOG_BLOB_FLAG != 0 {
+            ValueKind::Blob
+        } else if flags & VLOG_REFERENCE_FLAG != 0 {
+            ValueKind::Reference
+        } else if flags & VLOG_RANGE_TOMBSTONE_FLAG != 0 {
+            ValueKind::RangeTombstone
+        } else {
+            ValueKind::Inline
+        }
+    }
 }
@@ -0,0 +1,184 @@
+//! # Write-Ahead Log
+//!
+//! Small log kept next to the value log for writes of small values.
+//!
+//! Each batch of entries is appended with a single write followed by a single
+//! sync, so a write is durable once it returns without growing the value log
+//! that garbage collection has to scan. Memtable entries point into it with
+//! offsets tagged by `WAL_OFFSET_FLAG` and the generation of the log. Before a
+//! memtable is made read-only its entries are moved to the value log and the log
+//! is cleared, so sstables only ever point into the value log. Clearing starts a
+//! new generation, an offset held since then no longer reads an entry written
+//! to the same position afterwards. Entries left in the log by a crash are moved
+//! at next open, entries are checksummed so a torn tail is ignored.
+
+use crate::{
+    consts::{WAL_GENERATION_SHIFT, WAL_OFFSET_FLAG},
+    err::Error,
+    fs::{FileAsync, RetryPolicy, VLogFileNode, VLogFs},
+    types::{CreatedAt, ValOffset},
+    vlog::{v_log::VFile, ValueLogEntry},
+};
+use std::path::Path;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use tokio::fs;
+
+/// Mask of the position in the log in a tagged offset
+const WAL_POSITION_MASK: usize = (1 << WAL_GENERATION_SHIFT) - 1;
+
+/// Mask of the generation of the log once shifted out of a tagged offset
+const WAL_GENERATION_MASK: usize = (WAL_OFFSET_FLAG >> WAL_GENERATION_SHIFT) - 1;
+
+/// Returns true if `offset` points into the write-ahead log
+pub(crate) fn is_wal_offset(offset: ValOffset) -> bool {
+    offset & WAL_OFFSET_FLAG != 0
+}
+
+/// Write-ahead log file
+#[derive(Debug, Clone)]
+pub(crate) struct Wal {
+    /// Write-ahead log file contents
+    pub(crate) content: VFile<VLogFileNode>,
+
+    /// Largest value written to the log, `None` keeps every value in the value log
+    pub(crate) max_value_size: Option<usize>,
+
+    /// Size of the log
+    pub(crate) size: usize,
+
+    /// Number of times the log was cleared, shared by clones reading the log
+    generation: Arc<AtomicUsize>,
+}
+
+impl Wal {
+    /// Opens write-ahead log at `path`, creating it if it does not exist
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub(crate) async fn open(path: impl AsRef<Path>, max_value_size: Option<usize>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let file = VLogFileNode::new(path.to_owned(), crate::fs::FileType::ValueLog).await?;
+        let size = file.node.size().await;
+        Ok(Self {
+            content: VFile::new(path, file),
+            max_value_size,
+            size,
+            generation: Arc::default(),
+        })
+    }
+
+    /// Returns offset of the entry at `position` in the current generation of the log
+    fn tag(&self, position: usize) -> ValOffset {
+        let generation = self.generation.load(Ordering::Acquire) & WAL_GENERATION_MASK;
+        WAL_OFFSET_FLAG | generation << WAL_GENERATION_SHIFT | position
+    }
+
+    /// Returns true if `value` should be written to the log
+    pub(crate) fn accepts(&self, value: &[u8]) -> bool {
+        self.max_value_size.is_some_and(|max| value.len() <= max)
+    }
+
+    /// Returns true if the log holds no entry
+    pub(crate) fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Appends entries with a single write and syncs them to disk
+    ///
+    /// Each entry is a tuple of key, value, creation time and tombstone flag.
+    /// The write is retried according to `retry`, the sync is not.
+    /// Returns tagged offset of each entry, in order
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub(crate) async fn append_batch<T: AsRef<[u8]>>(
+        &mut self,
+        entries: &[(T, T, CreatedAt, bool)],
+        retry: &RetryPolicy,
+    ) -> Result<Vec<ValOffset>, Error> {
+        let mut offsets = Vec::with_capacity(entries.len());
+        let mut buf = Vec::new();
+        for (key, value, created_at, is_tombstone) in entries {
+            let (key, value) = (key.as_ref(), value.as_ref());
+            let entry = ValueLogEntry::new(key.len(), value.len(), key, value, *created_at, *is_tombstone)
+                .with_checksum();
+            offsets.push(self.tag(self.size + buf.len()));
+            buf.extend_from_slice(&entry.serialize());
+        }
+        let (node, size, data) = (&self.content.file.node, self.size, &buf);
+        retry
+            .run(|attempt| async move {
+                // a failed attempt may have written part of the entries
+                if attempt > 0 {
+                    node.truncate(size).await?;
+                }
+                node.write_all(data).await
+            })
+            .await?;
+        node.sync_all().await?;
+        self.size += buf.len();
+        Ok(offsets)
+    }
+
+    /// Fetches entry at tagged `offset`, `None` if the log was cleared since it was written
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error or the entry is corrupted
+    pub(crate) async fn get_entry(&self, offset: ValOffset) -> Result<Option<ValueLogEntry>, Error> {
+        if self.tag(offset & WAL_POSITION_MASK) != offset {
+            return Ok(None);
+        }
+        let entry = self.content.file.get_entry(offset & WAL_POSITION_MASK).await?;
+        if let Some(entry) = &entry {
+            entry.verify(&entry.key, offset)?;
+        }
+        Ok(entry)
+    }
+
+    /// Returns every entry of the log with its tagged offset, in order
+    ///
+    /// Entries after one that is torn or does not match its checksum were
+    /// never acknowledged, so they are left out
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub(crate) async fn entries(&self) -> Result<Vec<(ValOffset, ValueLogEntry)>, Error> {
+        let buf = fs::read(&self.content.path)
+            .await
+            .map_err(|err| Error::FileRead {
+                path: self.content.path.to_owned(),
+                error: err,
+            })?;
+        let mut entries = Vec::new();
+        let mut position = 0;
+        while let Some((entry, len)) = ValueLogEntry::deserialize(&buf[position..]) {
+            if entry.verify(&entry.key, position).is_err() {
+                log::warn!("Write-ahead log {:?} is torn at {}", self.content.path, position);
+                break;
+            }
+            entries.push((self.tag(position), entry));
+            position += len;
+        }
+        Ok(entries)
+    }
+
+    /// Removes every entry of the log and starts a new generation
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub(crate) async fn clear(&mut self) -> Result<(), Error> {
+        self.content.file.node.clear().await?;
+        self.content.file.node.sync_all().await?;
+        self.size = 0;
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        Ok(())
+    }
+}
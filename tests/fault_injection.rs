@@ -0,0 +1,270 @@
+#![cfg(feature = "fault-injection")]
+
+use std::io::ErrorKind;
+use std::sync::Arc;
+use tempfile::tempdir;
+use tokio::sync::RwLock;
+use velarixdb::db::{self, BlockCache, Config, DataStore, FlushEvent, RetryPolicy};
+use velarixdb::fault::{self, Fault, FaultRule, Operation};
+
+#[tokio::test]
+async fn test_put_fails_on_injected_write_error() {
+    let root = tempdir().unwrap();
+    let path = root.path().join("velarix");
+    let mut store = DataStore::open("big_tech", path.to_owned()).await.unwrap();
+    store.put("apple", "tim cook").await.unwrap();
+
+    fault::inject(FaultRule::new(&path, Operation::Write, Fault::Error(ErrorKind::Other)).times(1));
+    let err = store.put("google", "sundar pichai").await.unwrap_err();
+    assert_eq!(err.kind(), db::ErrorKind::Io);
+    assert!(!err.is_retryable());
+    assert_eq!(fault::triggered(&path), 1);
+
+    // Rule is exhausted, writes go through again
+    store.put("nvidia", "jensen huang").await.unwrap();
+    let entry = store.get("apple").await.unwrap();
+    assert_eq!(std::str::from_utf8(&entry.unwrap().val).unwrap(), "tim cook");
+    fault::clear(&path);
+}
+
+#[tokio::test]
+async fn test_short_write_persists_prefix() {
+    let root = tempdir().unwrap();
+    let path = root.path().join("velarix");
+    let mut store = DataStore::open("big_tech", path.to_owned()).await.unwrap();
+    store.put("apple", "tim cook").await.unwrap();
+
+    let vlog_path = path.join("v_log").join("val_log.bin");
+    let size_before = std::fs::metadata(&vlog_path).unwrap().len();
+    fault::inject(FaultRule::new(&vlog_path, Operation::Write, Fault::ShortWrite(5)).times(1));
+    assert!(store.put("google", "sundar pichai").await.is_err());
+    assert_eq!(std::fs::metadata(&vlog_path).unwrap().len(), size_before + 5);
+    fault::clear(&path);
+}
+
+#[tokio::test]
+async fn test_get_fails_on_injected_read_error() {
+    let root = tempdir().unwrap();
+    let path = root.path().join("velarix");
+    let mut store = DataStore::open("big_tech", path.to_owned()).await.unwrap();
+    store.put("apple", "tim cook").await.unwrap();
+
+    fault::inject(FaultRule::new(
+        &path,
+        Operation::Read,
+        Fault::Error(ErrorKind::Interrupted),
+    ));
+    assert!(store.get("apple").await.is_err());
+    fault::clear(&path);
+    assert!(store.get("apple").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_put_retries_transient_write_error() {
+    let root = tempdir().unwrap();
+    let path = root.path().join("velarix");
+    let mut store = DataStore::open("big_tech", path.to_owned()).await.unwrap();
+
+    let vlog_path = path.join("v_log").join("val_log.bin");
+    fault::inject(
+        FaultRule::new(&vlog_path, Operation::Write, Fault::Error(ErrorKind::Interrupted)).times(2),
+    );
+    store.put("apple", "tim cook").await.unwrap();
+    assert_eq!(fault::triggered(&vlog_path), 2);
+    let entry = store.get("apple").await.unwrap();
+    assert_eq!(std::str::from_utf8(&entry.unwrap().val).unwrap(), "tim cook");
+    fault::clear(&vlog_path);
+}
+
+#[tokio::test]
+async fn test_put_fails_once_retries_are_exhausted() {
+    let root = tempdir().unwrap();
+    let path = root.path().join("velarix");
+    let config = Config {
+        io_retry: RetryPolicy {
+            max_retries: 1,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut store = DataStore::open_with_config("big_tech", path.to_owned(), config)
+        .await
+        .unwrap();
+
+    let vlog_path = path.join("v_log").join("val_log.bin");
+    fault::inject(
+        FaultRule::new(&vlog_path, Operation::Write, Fault::Error(ErrorKind::Interrupted)).times(2),
+    );
+    let err = store.put("apple", "tim cook").await.unwrap_err();
+    assert_eq!(err.kind(), db::ErrorKind::Io);
+    assert!(err.is_retryable());
+    assert_eq!(err.path(), Some(vlog_path.as_path()));
+    assert_eq!(fault::triggered(&vlog_path), 2);
+    fault::clear(&vlog_path);
+    store.put("apple", "tim cook").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_get_retries_transient_sstable_read_error() {
+    let root = tempdir().unwrap();
+    let path = root.path().join("velarix");
+    let config = || Config {
+        write_buffer_size: 4 * 1024,
+        block_cache: BlockCache::new(0),
+        ..Default::default()
+    };
+    let mut store = DataStore::open_with_config("big_tech", path.to_owned(), config())
+        .await
+        .unwrap();
+    let mut flushes = store.subscribe_flush();
+    let mut i = 0;
+    let sstable = loop {
+        store.put(format!("key_{:05}", i), "value").await.unwrap();
+        i += 1;
+        match flushes.try_recv() {
+            Some(FlushEvent::Flushed { sstable, .. }) => break sstable,
+            Some(FlushEvent::Failed { error, .. }) => panic!("flush failed: {}", error),
+            None => {}
+        }
+        tokio::task::yield_now().await;
+    };
+    // flushes still running would write the manifest of the reopened store
+    store.wait_for_compactions().await.unwrap();
+    drop(store);
+
+    let store = DataStore::open_with_config("big_tech", path.to_owned(), config())
+        .await
+        .unwrap();
+    let data_path = sstable.join("data.db");
+    fault::inject(FaultRule::new(&data_path, Operation::Read, Fault::Error(ErrorKind::TimedOut)).times(2));
+    let entry = store.get("key_00000").await.unwrap();
+    assert_eq!(std::str::from_utf8(&entry.unwrap().val).unwrap(), "value");
+    assert_eq!(fault::triggered(&data_path), 2);
+    fault::clear(&data_path);
+}
+
+#[tokio::test]
+async fn test_wait_for_compactions_returns_failed_flush() {
+    let root = tempdir().unwrap();
+    let path = root.path().join("velarix");
+    let config = Config {
+        write_buffer_size: 4 * 1024,
+        ..Default::default()
+    };
+    let mut store = DataStore::open_with_config("big_tech", path.to_owned(), config)
+        .await
+        .unwrap();
+    let mut flushes = store.subscribe_flush();
+
+    let buckets = path.join("buckets");
+    fault::inject(FaultRule::new(
+        &buckets,
+        Operation::Write,
+        Fault::Error(ErrorKind::Other),
+    ));
+    let mut i = 0;
+    loop {
+        store.put(format!("key_{:05}", i), "value").await.unwrap();
+        i += 1;
+        match flushes.try_recv() {
+            Some(FlushEvent::Failed { .. }) => break,
+            Some(FlushEvent::Flushed { .. }) => panic!("flush succeeded despite the injected error"),
+            None => {}
+        }
+        tokio::task::yield_now().await;
+    }
+    let wait = tokio::time::timeout(std::time::Duration::from_secs(30), store.wait_for_compactions());
+    let err = wait.await.expect("failed flush was waited for").unwrap_err();
+    assert_eq!(err.kind(), db::ErrorKind::Io);
+
+    // the memtable is flushed once the file system recovers
+    fault::clear(&buckets);
+    store.wait_for_compactions().await.unwrap();
+    let entry = store.get("key_00000").await.unwrap();
+    assert_eq!(std::str::from_utf8(&entry.unwrap().val).unwrap(), "value");
+}
+
+#[tokio::test]
+async fn test_wait_for_compactions_after_failed_compaction() {
+    let root = tempdir().unwrap();
+    let path = root.path().join("velarix");
+    let config = Config {
+        write_buffer_size: 4 * 1024,
+        compactor_flush_listener_interval: std::time::Duration::from_millis(10),
+        ..Default::default()
+    };
+    let mut store = DataStore::open_with_config("big_tech", path.to_owned(), config)
+        .await
+        .unwrap();
+    let mut flushes = store.subscribe_flush();
+
+    // flushes only write sstables, compactions fail reading them
+    let buckets = path.join("buckets");
+    fault::inject(FaultRule::new(
+        &buckets,
+        Operation::Read,
+        Fault::Error(ErrorKind::Other),
+    ));
+    let mut i = 0;
+    while fault::triggered(&buckets) == 0 {
+        store.put(format!("key_{:05}", i), "value").await.unwrap();
+        i += 1;
+        if let Some(FlushEvent::Failed { error, .. }) = flushes.try_recv() {
+            panic!("flush failed: {}", error);
+        }
+        tokio::task::yield_now().await;
+    }
+    fault::clear(&buckets);
+
+    let wait = tokio::time::timeout(std::time::Duration::from_secs(30), store.wait_for_compactions());
+    wait.await
+        .expect("failed compaction left the compactor active")
+        .unwrap();
+    let entry = store.get("key_00000").await.unwrap();
+    assert_eq!(std::str::from_utf8(&entry.unwrap().val).unwrap(), "value");
+}
+
+#[tokio::test]
+async fn test_transaction_commit_is_all_or_nothing() {
+    let root = tempdir().unwrap();
+    let path = root.path().join("velarix");
+    let store = DataStore::open("big_tech", path.to_owned()).await.unwrap();
+    let store = Arc::new(RwLock::new(store));
+    store.write().await.put("apple", "tim cook").await.unwrap();
+    let keys = ["apple", "google", "nvidia"];
+    let applied = |store: Arc<RwLock<DataStore<'static, Vec<u8>>>>| async move {
+        let store = store.read().await;
+        let mut applied = 0;
+        for key in keys {
+            if let Some(entry) = store.get(key).await.unwrap() {
+                applied += (entry.val == b"ceo") as usize;
+            }
+        }
+        applied
+    };
+
+    // the first write of the commit fails
+    let vlog_path = path.join("v_log").join("val_log.bin");
+    fault::inject(FaultRule::new(&vlog_path, Operation::Write, Fault::Error(ErrorKind::Other)).times(1));
+    let mut txn = DataStore::begin_transaction(&store).await;
+    for key in keys {
+        txn.put(key, "ceo").await.unwrap();
+    }
+    assert!(txn.commit().await.is_err());
+    assert_eq!(applied(store.clone()).await, 0);
+    fault::clear(&vlog_path);
+
+    // a failure after the first write can't leave part of the commit applied
+    fault::inject(
+        FaultRule::new(&vlog_path, Operation::Write, Fault::Error(ErrorKind::Other))
+            .after(1)
+            .times(1),
+    );
+    let mut txn = DataStore::begin_transaction(&store).await;
+    for key in keys {
+        txn.put(key, "ceo").await.unwrap();
+    }
+    txn.commit().await.unwrap();
+    assert_eq!(applied(store.clone()).await, keys.len());
+    fault::clear(&vlog_path);
+}
@@ -10,7 +10,7 @@ async fn test_update() {
     store.put("apple", "tim cook").await.unwrap(); // handle error
 
     // Update entry
-    let success = store.update("apple", "elon musk").await;
+    let success = store.update_with("apple", |_| "elon musk").await;
     assert!(success.is_ok());
 
     // Entry should now be updated